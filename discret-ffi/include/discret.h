@@ -0,0 +1,142 @@
+/*
+ * C ABI for the Discret library.
+ *
+ * Conventions:
+ * - every function returns a DiscretStatus, the error message of the last failed call
+ *   is available with discret_last_error(),
+ * - a panic of the library is reported with DISCRET_PANIC, its message is available
+ *   with discret_last_error(),
+ * - strings are UTF-8 and null terminated,
+ * - query parameters are passed as a JSON object, or NULL when there is no parameter,
+ * - strings returned by the library must be released with discret_string_free(),
+ * - events are delivered to a callback with a JSON payload.
+ *
+ * The values of the enums are part of the stable ABI and will never change.
+ */
+#ifndef DISCRET_H
+#define DISCRET_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum DiscretStatus {
+    DISCRET_OK = 0,
+    DISCRET_INVALID_ARGUMENT = 1,
+    DISCRET_ERROR = 2,
+    DISCRET_PANIC = 3,
+} DiscretStatus;
+
+typedef enum DiscretEventKind {
+    /* {"rooms": {"<room_id>": {"<entity>": [<mutation days>]}}} */
+    DISCRET_EVENT_DATA_CHANGED = 0,
+    /* {"room_id": "<room_id>"} */
+    DISCRET_EVENT_ROOM_MODIFIED = 1,
+    /* {"verifying_key": "<key>", "date": <date>, "connection_id": "<id>"} */
+    DISCRET_EVENT_PEER_CONNECTED = 2,
+    /* {"verifying_key": "<key>", "date": <date>, "connection_id": "<id>"} */
+    DISCRET_EVENT_PEER_DISCONNECTED = 3,
+    /* {"room_id": "<room_id>"} */
+    DISCRET_EVENT_ROOM_SYNCHRONIZED = 4,
+    /* {} */
+    DISCRET_EVENT_PENDING_PEER = 5,
+    /* {} */
+    DISCRET_EVENT_PENDING_HARDWARE = 6,
+    /* {"missed": <number of dropped events>} */
+    DISCRET_EVENT_LAGGED = 7,
+    /* {"verifying_key": "<key>", "offset": <offset in ms>} */
+    DISCRET_EVENT_CLOCK_SKEW = 8,
+    /* {"entity": "<entity name>", "conflicts": <number of conflicts>} */
+    DISCRET_EVENT_WRITE_CONTENTION = 9,
+    /* {"query_hash": "<query hash>", "duration": <duration in ms>, "rows": <number of rows>} */
+    DISCRET_EVENT_SLOW_QUERY = 10,
+    /* {"error": "<error message>"} */
+    DISCRET_EVENT_DATABASE_CORRUPTED = 11,
+    /* {"room_id": "<room_id>", "days": <number of entity days only held locally>} */
+    DISCRET_EVENT_LOCAL_ONLY_DATA = 12,
+    /* {"room_id": "<room_id>", "verifying_key": "<key>", "days": <number of entity days that differ>} */
+    DISCRET_EVENT_ROOM_DIVERGENCE = 13,
+    /* {"verifying_key": "<key>", "throttled": <number of throttled queries since startup>} */
+    DISCRET_EVENT_PEER_THROTTLED = 14,
+    /* {"room_id": "<room_id>", "verifying_key": "<key of the author>", "rejected": <number of rejected nodes and edges>} */
+    DISCRET_EVENT_FUTURE_DATED_DATA = 15,
+    /* {"room_id": "<room_id>", "by": "<key of the peer that added this device>"} */
+    DISCRET_EVENT_ROOM_JOINED = 16,
+    /* {"room_id": "<room_id>", "by": "<key of the peer that removed this device>"} */
+    DISCRET_EVENT_ROOM_LEFT = 17,
+    /* {"verifying_key": "<key>"}, answer with discret_answer_connection_request() */
+    DISCRET_EVENT_CONNECTION_REQUEST = 18,
+} DiscretEventKind;
+
+typedef struct DiscretEvent {
+    DiscretEventKind kind;
+    /* JSON object, only valid during the callback execution */
+    const char *payload;
+} DiscretEvent;
+
+typedef struct DiscretHandle DiscretHandle;
+typedef struct DiscretSubscription DiscretSubscription;
+
+/* called from a thread managed by the library, should return quickly */
+typedef void (*DiscretEventCallback)(const DiscretEvent *event, void *user_data);
+
+/* valid until the next call made by the current thread, or NULL. Identifiers are redacted when "error_privacy_mode" is enabled */
+const char *discret_last_error(void);
+
+void discret_string_free(char *value);
+
+/*
+ * key_material: 32 bytes master secret
+ * configuration: JSON object containing the values that differs from the default, or NULL
+ */
+DiscretStatus discret_new(const char *datamodel,
+                          const char *app_key,
+                          const uint8_t *key_material,
+                          const char *data_folder,
+                          const char *configuration,
+                          DiscretHandle **out);
+
+/* must not be called from an event callback */
+void discret_free(DiscretHandle *handle);
+
+DiscretStatus discret_query(const DiscretHandle *handle,
+                            const char *query,
+                            const char *parameters,
+                            char **out);
+
+DiscretStatus discret_mutate(const DiscretHandle *handle,
+                             const char *mutation,
+                             const char *parameters,
+                             char **out);
+
+DiscretStatus discret_delete(const DiscretHandle *handle,
+                             const char *deletion,
+                             const char *parameters);
+
+DiscretStatus discret_answer_connection_request(const DiscretHandle *handle,
+                                                const char *verifying_key,
+                                                bool accept);
+
+DiscretStatus discret_verifying_key(const DiscretHandle *handle, char **out);
+
+DiscretStatus discret_private_room(const DiscretHandle *handle, char **out);
+
+DiscretStatus discret_private_room_for(const DiscretHandle *handle,
+                                       const char *label,
+                                       char **out);
+
+DiscretStatus discret_subscribe_events(const DiscretHandle *handle,
+                                       DiscretEventCallback callback,
+                                       void *user_data,
+                                       DiscretSubscription **out);
+
+void discret_unsubscribe(DiscretSubscription *subscription);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* DISCRET_H */
@@ -0,0 +1,825 @@
+//! C ABI for the *Discret* library.
+//!
+//! This crate exposes the core *Discret* API over a stable C interface, allowing bindings to be written for languages like Dart (Flutter), Swift or Kotlin
+//! without re-wrapping the async Rust API in every project.
+//!
+//! The C declarations are available in `include/discret.h`.
+//!
+//! Conventions:
+//! - every function returns a *DiscretStatus*, the error message of the last failed call is available with *discret_last_error()*,
+//! - a panic never crosses the C ABI: it is reported with the *Panic* status and its message is available with *discret_last_error()*,
+//! - strings are UTF-8 and null terminated,
+//! - query parameters are passed as a JSON object, or NULL when there is no parameter,
+//! - strings returned by the library must be released with *discret_string_free()*,
+//! - events are delivered to a callback with a JSON payload.
+//!
+use std::{
+    any::Any,
+    cell::RefCell,
+    ffi::{c_char, c_void, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    ptr,
+};
+
+use discret::{base64_decode, base64_encode, Configuration, Discret, Event, Parameters};
+use serde_json::{json, Value};
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+///
+/// Result of every function call.
+///
+/// The values are part of the stable ABI and will never change.
+///
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DiscretStatus {
+    Ok = 0,
+    InvalidArgument = 1,
+    Error = 2,
+    Panic = 3,
+}
+
+///
+/// Kind of the events sent to the event callback.
+///
+/// The values are part of the stable ABI and will never change, new kinds will be appended.
+///
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DiscretEventKind {
+    DataChanged = 0,
+    RoomModified = 1,
+    PeerConnected = 2,
+    PeerDisconnected = 3,
+    RoomSynchronized = 4,
+    PendingPeer = 5,
+    PendingHardware = 6,
+    Lagged = 7,
+    ClockSkew = 8,
+    WriteContention = 9,
+    SlowQuery = 10,
+    DatabaseCorrupted = 11,
+    LocalOnlyData = 12,
+    RoomDivergence = 13,
+    PeerThrottled = 14,
+    FutureDatedData = 15,
+    RoomJoined = 16,
+    RoomLeft = 17,
+    ConnectionRequest = 18,
+}
+
+///
+/// Event sent to the event callback.
+///
+/// The payload is a JSON object that is only valid during the callback execution.
+///
+#[repr(C)]
+pub struct DiscretEvent {
+    pub kind: DiscretEventKind,
+    pub payload: *const c_char,
+}
+
+///
+/// Event callback.
+///
+/// It is called from a thread managed by the library and should return quickly.
+///
+pub type DiscretEventCallback = extern "C" fn(event: *const DiscretEvent, user_data: *mut c_void);
+
+///
+/// Opaque handle on a *Discret* instance and the runtime that drives it.
+///
+pub struct DiscretHandle {
+    runtime: Runtime,
+    discret: Discret,
+}
+
+///
+/// Opaque handle on an event subscription.
+///
+pub struct DiscretSubscription {
+    task: JoinHandle<()>,
+}
+
+struct UserData(*mut c_void);
+// the user data is owned by the caller that is responsible for its thread safety
+unsafe impl Send for UserData {}
+impl UserData {
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+fn set_last_error(message: impl ToString) {
+    let message = message.to_string().replace('\0', "");
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+}
+
+fn invalid_argument(message: impl ToString) -> DiscretStatus {
+    set_last_error(message);
+    DiscretStatus::InvalidArgument
+}
+
+fn error(message: impl ToString) -> DiscretStatus {
+    set_last_error(message);
+    DiscretStatus::Error
+}
+
+fn discret_error(e: discret::Error) -> DiscretStatus {
+    error(e.public_message())
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    };
+    format!("panic: {message}")
+}
+
+///
+/// Runs the body of an exported function, unwinding across the C ABI is undefined behaviour
+///
+fn status(body: impl FnOnce() -> Result<(), DiscretStatus>) -> DiscretStatus {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(_)) => DiscretStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(payload) => {
+            set_last_error(panic_message(payload));
+            DiscretStatus::Panic
+        }
+    }
+}
+
+///
+/// Runs the body of an exported function that has no status, the panic message is still available with *discret_last_error()*
+///
+fn no_status(body: impl FnOnce()) {
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(body)) {
+        set_last_error(panic_message(payload));
+    }
+}
+
+unsafe fn to_str<'a>(value: *const c_char, name: &str) -> Result<&'a str, DiscretStatus> {
+    if value.is_null() {
+        return Err(invalid_argument(format!("'{name}' cannot be NULL")));
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map_err(|_| invalid_argument(format!("'{name}' is not a valid UTF-8 string")))
+}
+
+unsafe fn to_handle<'a>(handle: *const DiscretHandle) -> Result<&'a DiscretHandle, DiscretStatus> {
+    handle
+        .as_ref()
+        .ok_or_else(|| invalid_argument("'handle' cannot be NULL"))
+}
+
+unsafe fn to_parameters(parameters: *const c_char) -> Result<Option<Parameters>, DiscretStatus> {
+    if parameters.is_null() {
+        return Ok(None);
+    }
+    let parameters = to_str(parameters, "parameters")?;
+    let parameters = Parameters::from_json(parameters).map_err(invalid_argument)?;
+    Ok(Some(parameters))
+}
+
+unsafe fn write_string(value: String, out: *mut *mut c_char) -> Result<(), DiscretStatus> {
+    if out.is_null() {
+        return Err(invalid_argument("'out' cannot be NULL"));
+    }
+    let value = CString::new(value).map_err(error)?;
+    *out = value.into_raw();
+    Ok(())
+}
+
+///
+/// The configuration JSON only needs to contain the values that differs from the default configuration.
+///
+fn to_configuration(configuration: Option<&str>) -> Result<Configuration, DiscretStatus> {
+    let mut default = serde_json::to_value(Configuration::default()).map_err(error)?;
+    if let Some(configuration) = configuration {
+        let configuration: Value = serde_json::from_str(configuration).map_err(invalid_argument)?;
+        let configuration = configuration
+            .as_object()
+            .ok_or_else(|| invalid_argument("'configuration' must be a JSON object"))?;
+        let default = default.as_object_mut().unwrap();
+        for (key, value) in configuration {
+            if !default.contains_key(key) {
+                return Err(invalid_argument(format!(
+                    "unknown configuration field '{key}'"
+                )));
+            }
+            default.insert(key.clone(), value.clone());
+        }
+    }
+    serde_json::from_value(default).map_err(invalid_argument)
+}
+
+fn event_payload(event: &Event) -> (DiscretEventKind, Value) {
+    match event {
+        Event::DataChanged(data_modification) => (
+            DiscretEventKind::DataChanged,
+            json!({ "rooms": data_modification.rooms }),
+        ),
+        Event::RoomModified(room) => (
+            DiscretEventKind::RoomModified,
+            json!({ "room_id": base64_encode(&room.id) }),
+        ),
+        Event::PeerConnected(verifying_key, date, connection_id) => (
+            DiscretEventKind::PeerConnected,
+            json!({
+                "verifying_key": base64_encode(verifying_key),
+                "date": date,
+                "connection_id": connection_id,
+            }),
+        ),
+        Event::PeerDisconnected(verifying_key, date, connection_id) => (
+            DiscretEventKind::PeerDisconnected,
+            json!({
+                "verifying_key": base64_encode(verifying_key),
+                "date": date,
+                "connection_id": connection_id,
+            }),
+        ),
+        Event::ClockSkew(verifying_key, offset) => (
+            DiscretEventKind::ClockSkew,
+            json!({
+                "verifying_key": base64_encode(verifying_key),
+                "offset": offset,
+            }),
+        ),
+        Event::WriteContention(entity, conflicts) => (
+            DiscretEventKind::WriteContention,
+            json!({ "entity": entity, "conflicts": conflicts }),
+        ),
+        Event::SlowQuery {
+            query_hash,
+            duration,
+            rows,
+        } => (
+            DiscretEventKind::SlowQuery,
+            json!({ "query_hash": query_hash, "duration": duration, "rows": rows }),
+        ),
+        Event::DatabaseCorrupted(error) => (
+            DiscretEventKind::DatabaseCorrupted,
+            json!({ "error": error }),
+        ),
+        Event::RoomSynchronized(room_id) => (
+            DiscretEventKind::RoomSynchronized,
+            json!({ "room_id": room_id }),
+        ),
+        Event::LocalOnlyData(room_id, days) => (
+            DiscretEventKind::LocalOnlyData,
+            json!({ "room_id": room_id, "days": days }),
+        ),
+        Event::RoomDivergence(room_id, verifying_key, days) => (
+            DiscretEventKind::RoomDivergence,
+            json!({ "room_id": room_id, "verifying_key": base64_encode(verifying_key), "days": days }),
+        ),
+        Event::PeerThrottled(verifying_key, throttled) => (
+            DiscretEventKind::PeerThrottled,
+            json!({ "verifying_key": base64_encode(verifying_key), "throttled": throttled }),
+        ),
+        Event::FutureDatedData(room_id, verifying_key, rejected) => (
+            DiscretEventKind::FutureDatedData,
+            json!({ "room_id": room_id, "verifying_key": base64_encode(verifying_key), "rejected": rejected }),
+        ),
+        Event::RoomJoined(room_id, by) => (
+            DiscretEventKind::RoomJoined,
+            json!({ "room_id": room_id, "by": base64_encode(by) }),
+        ),
+        Event::RoomLeft(room_id, by) => (
+            DiscretEventKind::RoomLeft,
+            json!({ "room_id": room_id, "by": base64_encode(by) }),
+        ),
+        Event::PendingPeer() => (DiscretEventKind::PendingPeer, json!({})),
+        Event::PendingHardware() => (DiscretEventKind::PendingHardware, json!({})),
+        Event::ConnectionRequest(verifying_key) => (
+            DiscretEventKind::ConnectionRequest,
+            json!({ "verifying_key": base64_encode(verifying_key) }),
+        ),
+        Event::Lagged(missed) => (DiscretEventKind::Lagged, json!({ "missed": missed })),
+    }
+}
+
+fn dispatch(event: &Event, callback: DiscretEventCallback, user_data: &UserData) {
+    let (kind, payload) = event_payload(event);
+    let payload = CString::new(payload.to_string()).unwrap_or_default();
+    let event = DiscretEvent {
+        kind,
+        payload: payload.as_ptr(),
+    };
+    callback(&event, user_data.get());
+}
+
+///
+/// Returns the error message of the last failed call made by the current thread, or NULL.
+///
+/// The returned string is owned by the library and is valid until the next call made by the current thread.
+///
+#[no_mangle]
+pub extern "C" fn discret_last_error() -> *const c_char {
+    panic::catch_unwind(|| {
+        LAST_ERROR.with(|e| match e.borrow().as_ref() {
+            Some(message) => message.as_ptr(),
+            None => ptr::null(),
+        })
+    })
+    .unwrap_or(ptr::null())
+}
+
+///
+/// Release a string returned by the library.
+///
+/// # Safety
+/// - value must be NULL or a string returned by the library that has not already been released.
+///
+#[no_mangle]
+pub unsafe extern "C" fn discret_string_free(value: *mut c_char) {
+    no_status(|| {
+        if !value.is_null() {
+            drop(CString::from_raw(value));
+        }
+    })
+}
+
+///
+/// Starts a *Discret* instance.
+/// - datamodel: the data model definition
+/// - app_key: a unique identifier for the application
+/// - key_material: a 32 bytes master secret
+/// - data_folder: the folder where the data will be stored
+/// - configuration: a JSON object containing the configuration values that differs from the default, or NULL
+/// - out: receives the handle that must be released with *discret_free()*
+///
+/// # Safety
+/// - strings must be NULL or valid null terminated strings,
+/// - key_material must point to 32 readable bytes,
+/// - out must be a valid pointer.
+///
+#[no_mangle]
+pub unsafe extern "C" fn discret_new(
+    datamodel: *const c_char,
+    app_key: *const c_char,
+    key_material: *const u8,
+    data_folder: *const c_char,
+    configuration: *const c_char,
+    out: *mut *mut DiscretHandle,
+) -> DiscretStatus {
+    status(|| {
+        let datamodel = to_str(datamodel, "datamodel")?;
+        let app_key = to_str(app_key, "app_key")?;
+        if key_material.is_null() {
+            return Err(invalid_argument("'key_material' cannot be NULL"));
+        }
+        let key_material: [u8; 32] = *(key_material as *const [u8; 32]);
+        let data_folder: PathBuf = to_str(data_folder, "data_folder")?.into();
+        let configuration = if configuration.is_null() {
+            to_configuration(None)?
+        } else {
+            to_configuration(Some(to_str(configuration, "configuration")?))?
+        };
+        if out.is_null() {
+            return Err(invalid_argument("'out' cannot be NULL"));
+        }
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(error)?;
+
+        let discret = runtime
+            .block_on(Discret::new(
+                datamodel,
+                app_key,
+                &key_material,
+                data_folder,
+                configuration,
+            ))
+            .map_err(discret_error)?;
+
+        *out = Box::into_raw(Box::new(DiscretHandle { runtime, discret }));
+        Ok(())
+    })
+}
+
+///
+/// Stops a *Discret* instance and release its resources.
+///
+/// # Safety
+/// - handle must be NULL or a handle returned by *discret_new()* that has not already been released,
+/// - it must not be called from an event callback.
+///
+#[no_mangle]
+pub unsafe extern "C" fn discret_free(handle: *mut DiscretHandle) {
+    no_status(|| {
+        if handle.is_null() {
+            return;
+        }
+        let handle = Box::from_raw(handle);
+        let DiscretHandle { runtime, discret } = *handle;
+        runtime.block_on(async move {
+            //flushes the pending writes and closes the connections
+            let _ = discret.shutdown().await;
+            drop(discret)
+        });
+        drop(runtime);
+    })
+}
+
+///
+/// Performs a query.
+/// - parameters: a JSON object containing the query parameters, or NULL
+/// - out: receives the JSON result that must be released with *discret_string_free()*
+///
+/// # Safety
+/// - handle must be a valid handle,
+/// - strings must be NULL or valid null terminated strings,
+/// - out must be a valid pointer.
+///
+#[no_mangle]
+pub unsafe extern "C" fn discret_query(
+    handle: *const DiscretHandle,
+    query: *const c_char,
+    parameters: *const c_char,
+    out: *mut *mut c_char,
+) -> DiscretStatus {
+    status(|| {
+        let handle = to_handle(handle)?;
+        let query = to_str(query, "query")?;
+        let parameters = to_parameters(parameters)?;
+        let result = handle
+            .runtime
+            .block_on(handle.discret.query(query, parameters))
+            .map_err(discret_error)?;
+        write_string(result, out)
+    })
+}
+
+///
+/// Performs a mutation.
+/// - parameters: a JSON object containing the mutation parameters, or NULL
+/// - out: receives the JSON result that must be released with *discret_string_free()*
+///
+/// # Safety
+/// - handle must be a valid handle,
+/// - strings must be NULL or valid null terminated strings,
+/// - out must be a valid pointer.
+///
+#[no_mangle]
+pub unsafe extern "C" fn discret_mutate(
+    handle: *const DiscretHandle,
+    mutation: *const c_char,
+    parameters: *const c_char,
+    out: *mut *mut c_char,
+) -> DiscretStatus {
+    status(|| {
+        let handle = to_handle(handle)?;
+        let mutation = to_str(mutation, "mutation")?;
+        let parameters = to_parameters(parameters)?;
+        let result = handle
+            .runtime
+            .block_on(handle.discret.mutate(mutation, parameters))
+            .map_err(discret_error)?;
+        write_string(result, out)
+    })
+}
+
+///
+/// Performs a deletion.
+/// - parameters: a JSON object containing the deletion parameters, or NULL
+///
+/// # Safety
+/// - handle must be a valid handle,
+/// - strings must be NULL or valid null terminated strings.
+///
+#[no_mangle]
+pub unsafe extern "C" fn discret_delete(
+    handle: *const DiscretHandle,
+    deletion: *const c_char,
+    parameters: *const c_char,
+) -> DiscretStatus {
+    status(|| {
+        let handle = to_handle(handle)?;
+        let deletion = to_str(deletion, "deletion")?;
+        let parameters = to_parameters(parameters)?;
+        handle
+            .runtime
+            .block_on(handle.discret.delete(deletion, parameters))
+            .map(|_| ())
+            .map_err(discret_error)
+    })
+}
+
+///
+/// Accepts or rejects the connections of a peer when the "connection_policy" configuration is "Prompt" or "Deny".
+/// - verifying_key: the key provided by the *DISCRET_EVENT_CONNECTION_REQUEST* event
+///
+/// # Safety
+/// - handle must be a valid handle,
+/// - verifying_key must be a valid null terminated string.
+///
+#[no_mangle]
+pub unsafe extern "C" fn discret_answer_connection_request(
+    handle: *const DiscretHandle,
+    verifying_key: *const c_char,
+    accept: bool,
+) -> DiscretStatus {
+    status(|| {
+        let handle = to_handle(handle)?;
+        let verifying_key = to_str(verifying_key, "verifying_key")?;
+        let verifying_key = base64_decode(verifying_key.as_bytes())
+            .map_err(|_| invalid_argument("'verifying_key' is not a valid key"))?;
+        handle
+            .runtime
+            .block_on(
+                handle
+                    .discret
+                    .answer_connection_request(verifying_key, accept),
+            )
+            .map_err(discret_error)
+    })
+}
+
+///
+/// Provides the public identity of the instance.
+/// - out: receives the verifying key that must be released with *discret_string_free()*
+///
+/// # Safety
+/// - handle must be a valid handle,
+/// - out must be a valid pointer.
+///
+#[no_mangle]
+pub unsafe extern "C" fn discret_verifying_key(
+    handle: *const DiscretHandle,
+    out: *mut *mut c_char,
+) -> DiscretStatus {
+    status(|| {
+        let handle = to_handle(handle)?;
+        write_string(handle.discret.verifying_key(), out)
+    })
+}
+
+///
+/// Provides the identifier of the private room.
+/// - out: receives the room identifier that must be released with *discret_string_free()*
+///
+/// # Safety
+/// - handle must be a valid handle,
+/// - out must be a valid pointer.
+///
+#[no_mangle]
+pub unsafe extern "C" fn discret_private_room(
+    handle: *const DiscretHandle,
+    out: *mut *mut c_char,
+) -> DiscretStatus {
+    status(|| {
+        let handle = to_handle(handle)?;
+        write_string(handle.discret.private_room(), out)
+    })
+}
+
+///
+/// Provides the identifier of a private room identified by a label, creating it if needed.
+/// - out: receives the room identifier that must be released with *discret_string_free()*
+///
+/// # Safety
+/// - handle must be a valid handle,
+/// - label must be a valid null terminated string,
+/// - out must be a valid pointer.
+///
+#[no_mangle]
+pub unsafe extern "C" fn discret_private_room_for(
+    handle: *const DiscretHandle,
+    label: *const c_char,
+    out: *mut *mut c_char,
+) -> DiscretStatus {
+    status(|| {
+        let handle = to_handle(handle)?;
+        let label = to_str(label, "label")?;
+        let room_id = handle
+            .runtime
+            .block_on(handle.discret.private_room_for(label))
+            .map_err(discret_error)?;
+        write_string(room_id, out)
+    })
+}
+
+///
+/// Subscribe for the event queue.
+/// - callback: called for every event
+/// - user_data: passed unmodified to the callback
+/// - out: receives the subscription that must be released with *discret_unsubscribe()*
+///
+/// # Safety
+/// - handle must be a valid handle,
+/// - user_data must remain valid until the subscription is released and must be usable from another thread,
+/// - out must be a valid pointer.
+///
+#[no_mangle]
+pub unsafe extern "C" fn discret_subscribe_events(
+    handle: *const DiscretHandle,
+    callback: Option<DiscretEventCallback>,
+    user_data: *mut c_void,
+    out: *mut *mut DiscretSubscription,
+) -> DiscretStatus {
+    status(|| {
+        let handle = to_handle(handle)?;
+        let callback = callback.ok_or_else(|| invalid_argument("'callback' cannot be NULL"))?;
+        if out.is_null() {
+            return Err(invalid_argument("'out' cannot be NULL"));
+        }
+        let mut receiver = handle.runtime.block_on(handle.discret.event_receiver());
+        let user_data = UserData(user_data);
+        let task = handle.runtime.spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                dispatch(&event, callback, &user_data);
+            }
+        });
+        *out = Box::into_raw(Box::new(DiscretSubscription { task }));
+        Ok(())
+    })
+}
+
+///
+/// Stops an event subscription.
+///
+/// # Safety
+/// - subscription must be NULL or a subscription returned by *discret_subscribe_events()* that has not already been released.
+///
+#[no_mangle]
+pub unsafe extern "C" fn discret_unsubscribe(subscription: *mut DiscretSubscription) {
+    no_status(|| {
+        if subscription.is_null() {
+            return;
+        }
+        let subscription = Box::from_raw(subscription);
+        subscription.task.abort();
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    const DATA_PATH: &str = "../test_data/ffi/";
+
+    fn c_string(value: &str) -> CString {
+        CString::new(value).unwrap()
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(discret_last_error()) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    static DATA_CHANGED: AtomicUsize = AtomicUsize::new(0);
+    extern "C" fn on_event(event: *const DiscretEvent, user_data: *mut c_void) {
+        let event = unsafe { &*event };
+        assert_eq!(user_data as usize, 42);
+        if event.kind == DiscretEventKind::DataChanged {
+            let payload = unsafe { CStr::from_ptr(event.payload) }.to_str().unwrap();
+            assert!(payload.starts_with("{\"rooms\":"));
+            DATA_CHANGED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn configuration() {
+        let configuration = to_configuration(Some(r#"{"parallelism": 2}"#)).unwrap();
+        assert_eq!(configuration.parallelism, 2);
+        assert_eq!(
+            configuration.event_buffer_size,
+            Configuration::default().event_buffer_size
+        );
+
+        let status = to_configuration(Some(r#"{"unknown": 2}"#)).unwrap_err();
+        assert_eq!(status, DiscretStatus::InvalidArgument);
+        assert_eq!(last_error(), "unknown configuration field 'unknown'");
+
+        let status = to_configuration(Some("[]")).unwrap_err();
+        assert_eq!(status, DiscretStatus::InvalidArgument);
+    }
+
+    #[test]
+    fn panic_does_not_unwind() {
+        let result = status(|| panic!("unexpected"));
+        assert_eq!(result, DiscretStatus::Panic);
+        assert_eq!(last_error(), "panic: unexpected");
+
+        no_status(|| panic!("unexpected {}", 2));
+        assert_eq!(last_error(), "panic: unexpected 2");
+    }
+
+    #[test]
+    fn api() {
+        fs::create_dir_all(DATA_PATH).unwrap();
+        let datamodel = c_string("{Person{name:String,}}");
+        let app_key = c_string("ffi");
+        let key_material = [7u8; 32];
+        let data_folder = c_string(DATA_PATH);
+        let configuration = c_string(r#"{"enable_multicast": false, "enable_beacons": false}"#);
+
+        let mut handle: *mut DiscretHandle = ptr::null_mut();
+        let status = unsafe {
+            discret_new(
+                datamodel.as_ptr(),
+                ptr::null(),
+                key_material.as_ptr(),
+                data_folder.as_ptr(),
+                configuration.as_ptr(),
+                &mut handle,
+            )
+        };
+        assert_eq!(status, DiscretStatus::InvalidArgument);
+        assert_eq!(last_error(), "'app_key' cannot be NULL");
+
+        let status = unsafe {
+            discret_new(
+                datamodel.as_ptr(),
+                app_key.as_ptr(),
+                key_material.as_ptr(),
+                data_folder.as_ptr(),
+                configuration.as_ptr(),
+                &mut handle,
+            )
+        };
+        assert_eq!(status, DiscretStatus::Ok);
+        assert!(!handle.is_null());
+
+        let mut subscription: *mut DiscretSubscription = ptr::null_mut();
+        let status = unsafe {
+            discret_subscribe_events(handle, Some(on_event), 42 as *mut c_void, &mut subscription)
+        };
+        assert_eq!(status, DiscretStatus::Ok);
+
+        let mutation = c_string(
+            r#"mutate {
+                result: Person{ name: $name }
+            }"#,
+        );
+        let parameters = c_string(r#"{"name": "John"}"#);
+        let mut result: *mut c_char = ptr::null_mut();
+        let status =
+            unsafe { discret_mutate(handle, mutation.as_ptr(), parameters.as_ptr(), &mut result) };
+        assert_eq!(status, DiscretStatus::Ok);
+        unsafe { discret_string_free(result) };
+
+        let query = c_string(
+            r#"query {
+                result: Person(name=$name){ name }
+            }"#,
+        );
+        let mut result: *mut c_char = ptr::null_mut();
+        let status =
+            unsafe { discret_query(handle, query.as_ptr(), parameters.as_ptr(), &mut result) };
+        assert_eq!(status, DiscretStatus::Ok);
+        let json = unsafe { CStr::from_ptr(result) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        unsafe { discret_string_free(result) };
+        assert!(json.contains("\"name\":\"John\""));
+
+        let invalid = c_string("query { result: Unknown{ name } }");
+        let status = unsafe { discret_query(handle, invalid.as_ptr(), ptr::null(), &mut result) };
+        assert_eq!(status, DiscretStatus::Error);
+        assert!(!last_error().is_empty());
+
+        let mut key: *mut c_char = ptr::null_mut();
+        let status = unsafe { discret_verifying_key(handle, &mut key) };
+        assert_eq!(status, DiscretStatus::Ok);
+        unsafe { discret_string_free(key) };
+
+        for _ in 0..20 {
+            if DATA_CHANGED.load(Ordering::Relaxed) > 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        assert!(DATA_CHANGED.load(Ordering::Relaxed) > 0);
+
+        unsafe {
+            discret_unsubscribe(subscription);
+            discret_free(handle);
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use discret::{
+    base64_decode, base64_encode, derive_pass_phrase, zero_uid, Configuration, DefaultRoom,
+    Discret, Event, Parameters, ResultParser,
+};
+use serde::Deserialize;
+use std::{env, io, path::PathBuf, sync::Arc};
+use tokio::sync::Mutex;
+
+//the application unique identifier
+const APPLICATION_KEY: &str = "github.com/discretlib/rust_example_chat";
+
+const DATA_MODEL: &str = "chat {
+    Message{
+        content:String
+    }
+}";
+
+//this struct is used to parse the query results
+#[derive(Deserialize)]
+struct Message {
+    id: String,
+    mdate: i64,
+    verifying_key: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct Room {
+    id: String,
+    authorisations: Vec<Authorisation>,
+}
+
+#[derive(Deserialize)]
+struct Authorisation {
+    id: String,
+    name: String,
+}
+
+///
+/// A chat room shared with other peers using invitations.
+///
+/// Start the first peer with: cargo run --example chat -- alice
+/// It prints an invitation that can be used to start a second peer on the local network:
+/// cargo run --example chat -- bob <invitation>
+///
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().collect();
+    let login = args.get(1).expect("usage: chat <login> [invitation]");
+    let invitation = args.get(2);
+
+    let path: PathBuf = format!("test_data/examples/chat/{}", login).into();
+    let key_material: [u8; 32] = derive_pass_phrase(login, "example password");
+    let app: Discret = Discret::new(
+        DATA_MODEL,
+        APPLICATION_KEY,
+        &key_material,
+        path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    let room_id = match invitation {
+        Some(invitation) => {
+            //the room is synchronized once the inviting peer is found
+            let mut events = app.event_receiver().await;
+            app.accept_invite(base64_decode(invitation.as_bytes()).unwrap())
+                .await
+                .unwrap();
+            println!("Waiting for the inviting peer...");
+            loop {
+                if let Some(Event::RoomSynchronized(room_id)) = events.recv().await {
+                    if !room_id.eq(&app.private_room()) {
+                        break room_id;
+                    }
+                }
+            }
+        }
+        None => {
+            let (room_id, authorisation) = chat_room(&app).await;
+            let invite = app
+                .invite(Some(DefaultRoom {
+                    room: room_id.clone(),
+                    authorisation,
+                }))
+                .await
+                .unwrap();
+            println!("Invitation: {}", base64_encode(&invite));
+            room_id
+        }
+    };
+
+    //print the new messages when data is modified
+    let event_app = app.clone();
+    let event_room = room_id.clone();
+    let last_message = Arc::new(Mutex::new((0, zero_uid())));
+    app.spawn_event_loop(move |event| {
+        let app = event_app.clone();
+        let room_id = event_room.clone();
+        let last_message = last_message.clone();
+        async move {
+            if let Event::DataChanged(_) = event {
+                let mut last_message = last_message.lock().await;
+                let params = Parameters::new()
+                    .with("mdate", last_message.0)
+                    .unwrap()
+                    .with("id", last_message.1.clone())
+                    .unwrap()
+                    .with("room_id", room_id)
+                    .unwrap();
+                let result = app
+                    .query(
+                        "query {
+                            res: chat.Message(
+                                order_by(mdate asc, id asc),
+                                after($mdate, $id),
+                                room_id = $room_id
+                            ) {
+                                id
+                                mdate
+                                verifying_key
+                                content
+                            }
+                        }",
+                        Some(params),
+                    )
+                    .await
+                    .unwrap();
+                let mut parser = ResultParser::new(&result).unwrap();
+                let messages: Vec<Message> = parser.take_array("res").unwrap();
+                for msg in messages {
+                    println!("{}: {}", &msg.verifying_key[0..8], msg.content);
+                    *last_message = (msg.mdate, msg.id);
+                }
+            }
+            true
+        }
+    })
+    .await;
+
+    println!("Write Something! (/q to quit)");
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        stdin.read_line(&mut line).unwrap();
+        let content = line.trim();
+        if content.starts_with("/q") {
+            break;
+        }
+        let params = Parameters::new()
+            .with("room_id", room_id.clone())
+            .unwrap()
+            .with("content", content)
+            .unwrap();
+        app.mutate(
+            "mutate {
+                chat.Message {
+                    room_id: $room_id
+                    content: $content
+                }
+            }",
+            Some(params),
+        )
+        .await
+        .unwrap();
+    }
+}
+
+///
+/// returns the chat room and its authorisation, creating them at the first start
+///
+async fn chat_room(app: &Discret) -> (String, String) {
+    let result = app
+        .query(
+            "query {
+                res: sys.Room(order_by(mdate asc)) {
+                    id
+                    authorisations {
+                        id
+                        name
+                    }
+                }
+            }",
+            None,
+        )
+        .await
+        .unwrap();
+    let mut parser = ResultParser::new(&result).unwrap();
+    let rooms: Vec<Room> = parser.take_array("res").unwrap();
+    for room in rooms {
+        if let Some(auth) = room.authorisations.into_iter().find(|a| a.name == "chat") {
+            return (room.id, auth.id);
+        }
+    }
+
+    let params = Parameters::new()
+        .with("user_id", app.verifying_key())
+        .unwrap();
+    let result = app
+        .mutate(
+            r#"mutate {
+                sys.Room {
+                    admin: [{
+                        verif_key: $user_id
+                    }]
+                    authorisations: [{
+                        name: "chat"
+                        rights: [{
+                            entity: "chat.Message"
+                            mutate_self: true
+                            mutate_all: false
+                        }]
+                        users: [{
+                            verif_key: $user_id
+                        }]
+                    }]
+                }
+            }"#,
+            Some(params),
+        )
+        .await
+        .unwrap();
+    let mut parser = ResultParser::new(&result).unwrap();
+    let mut room: Room = parser.take_object("sys.Room").unwrap();
+    let auth = room.authorisations.pop().unwrap();
+    (room.id, auth.id)
+}
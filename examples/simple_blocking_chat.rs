@@ -55,40 +55,37 @@ fn main() {
 
         let private_room: String = event_app.private_room();
         while let Ok(event) = events.blocking_recv() {
-            match event {
-                //triggered when data is modified
-                discret::Event::DataChanged(_) => {
-                    let mut param = Parameters::new();
-                    param.add("mdate", last_date).unwrap();
-                    param.add("id", last_id.clone()).unwrap();
-                    param.add("room_id", private_room.clone()).unwrap();
+            //triggered when data is modified
+            if let discret::Event::DataChanged(_) = event {
+                let mut param = Parameters::new();
+                param.add("mdate", last_date).unwrap();
+                param.add("id", last_id.clone()).unwrap();
+                param.add("room_id", private_room.clone()).unwrap();
 
-                    //get the latest data, the result is in the JSON format
-                    let result: String = event_app
-                        .query(
-                            "query {
-                                res: chat.Message(
-                                    order_by(mdate asc, id asc), 
-                                    after($mdate, $id),
-                                    room_id = $room_id
-                                ) {
-                                        id
-                                        mdate
-                                        content
-                                }
-                            }",
-                            Some(param),
-                        )
-                        .unwrap();
-                    let mut query_result = ResultParser::new(&result).unwrap();
-                    let res: Vec<Chat> = query_result.take_array("res").unwrap();
-                    for msg in res {
-                        last_date = msg.mdate;
-                        last_id = msg.id;
-                        println!("you said: {}", msg.content);
-                    }
+                //get the latest data, the result is in the JSON format
+                let result: String = event_app
+                    .query(
+                        "query {
+                            res: chat.Message(
+                                order_by(mdate asc, id asc), 
+                                after($mdate, $id),
+                                room_id = $room_id
+                            ) {
+                                    id
+                                    mdate
+                                    content
+                            }
+                        }",
+                        Some(param),
+                    )
+                    .unwrap();
+                let mut query_result = ResultParser::new(&result).unwrap();
+                let res: Vec<Chat> = query_result.take_array("res").unwrap();
+                for msg in res {
+                    last_date = msg.mdate;
+                    last_id = msg.id;
+                    println!("you said: {}", msg.content);
                 }
-                _ => {} //ignores other events
             }
         }
     });
@@ -97,7 +94,7 @@ fn main() {
     let private_room: String = app.private_room();
     let stdin = io::stdin();
     let mut line = String::new();
-    println!("{}", "Write Something!");
+    println!("Write Something!");
     loop {
         stdin.read_line(&mut line).unwrap();
         if line.starts_with("/q") {
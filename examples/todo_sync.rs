@@ -0,0 +1,150 @@
+use discret::{derive_pass_phrase, Configuration, Discret, Event, Parameters, ResultParser};
+use serde::Deserialize;
+use std::{env, io, path::PathBuf};
+
+//the application unique identifier
+const APPLICATION_KEY: &str = "github.com/discretlib/rust_example_todo_sync";
+
+const DATA_MODEL: &str = "todo {
+    Task{
+        title: String,
+        done: Boolean default false,
+    }
+}";
+
+//this struct is used to parse the query results
+#[derive(Deserialize)]
+struct Task {
+    id: String,
+    title: String,
+    done: bool,
+}
+
+///
+/// A todo list synchronized between the devices of the same user.
+///
+/// Every device using the same login and password shares the same private room.
+/// Start several devices on the local network with:
+/// cargo run --example todo_sync -- <device name>
+///
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().collect();
+    let device = args.get(1).expect("usage: todo_sync <device name>");
+
+    let path: PathBuf = format!("test_data/examples/todo_sync/{}", device).into();
+    let key_material: [u8; 32] = derive_pass_phrase("todo user", "example password");
+    let app: Discret = Discret::new(
+        DATA_MODEL,
+        APPLICATION_KEY,
+        &key_material,
+        path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    //the list is printed again when another device modifies it
+    let event_app = app.clone();
+    app.spawn_event_loop(move |event| {
+        let app = event_app.clone();
+        async move {
+            if let Event::RoomSynchronized(_) = event {
+                println!("-- synchronized --");
+                print_tasks(&list_tasks(&app).await);
+            }
+            true
+        }
+    })
+    .await;
+
+    println!("Commands: add <title>, done <number>, list, /q to quit");
+    print_tasks(&list_tasks(&app).await);
+
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        stdin.read_line(&mut line).unwrap();
+        let (command, argument) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        match command {
+            "/q" => break,
+            "add" => {
+                let params = Parameters::new()
+                    .with("room_id", app.private_room())
+                    .unwrap()
+                    .with("title", argument)
+                    .unwrap();
+                app.mutate(
+                    "mutate {
+                        todo.Task {
+                            room_id: $room_id
+                            title: $title
+                        }
+                    }",
+                    Some(params),
+                )
+                .await
+                .unwrap();
+            }
+            "done" => {
+                let tasks = list_tasks(&app).await;
+                let task = argument
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| tasks.get(n.wrapping_sub(1)));
+                match task {
+                    Some(task) => {
+                        let params = Parameters::new().with("id", task.id.clone()).unwrap();
+                        app.mutate(
+                            "mutate {
+                                todo.Task {
+                                    id: $id
+                                    done: true
+                                }
+                            }",
+                            Some(params),
+                        )
+                        .await
+                        .unwrap();
+                    }
+                    None => println!("unknown task: {}", argument),
+                }
+            }
+            "list" => {}
+            _ => {
+                println!("unknown command: {}", command);
+                continue;
+            }
+        }
+        print_tasks(&list_tasks(&app).await);
+    }
+}
+
+async fn list_tasks(app: &Discret) -> Vec<Task> {
+    let params = Parameters::new()
+        .with("room_id", app.private_room())
+        .unwrap();
+    let result = app
+        .query(
+            "query {
+                res: todo.Task(room_id = $room_id, order_by(cdate asc, id asc)) {
+                    id
+                    title
+                    done
+                }
+            }",
+            Some(params),
+        )
+        .await
+        .unwrap();
+    let mut parser = ResultParser::new(&result).unwrap();
+    parser.take_array("res").unwrap()
+}
+
+fn print_tasks(tasks: &[Task]) {
+    for (i, task) in tasks.iter().enumerate() {
+        let status = if task.done { "x" } else { " " };
+        println!("{}. [{}] {}", i + 1, status, task.title);
+    }
+}
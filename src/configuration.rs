@@ -71,6 +71,16 @@ pub struct Configuration {
     ///
     pub auto_allow_new_peers: bool,
 
+    ///
+    /// Default: ConnectionPolicy::AutoAccept
+    ///
+    /// Defines how the connections from the devices of the other users are accepted, see [ConnectionPolicy].
+    ///
+    /// Connections made with an invite and connections between your own devices are not affected:
+    /// creating or accepting an invite is an explicit consent, and your own devices are checked with the *auto_accept_local_device* rules.
+    ///
+    pub connection_policy: ConnectionPolicy,
+
     ///
     /// Default 256kb
     ///
@@ -158,6 +168,24 @@ pub struct Configuration {
     ///
     pub beacons: Vec<BeaconConfig>,
 
+    ///
+    /// Default: None
+    ///
+    /// Domain used to discover additional beacon servers at startup, allowing operators to change the beacons without updating the application.
+    /// The beacons are published with:
+    /// - SRV records named `_discret-beacon._udp.<domain>`, giving the beacon hostname and port,
+    /// - TXT records with the same name, containing `<hostname>=<certificate hash>` for each beacon.
+    ///
+    pub beacon_domain: Option<String>,
+
+    ///
+    /// Default: None
+    ///
+    /// DNS server used to resolve the *beacon_domain*, as an IP address with an optional port.
+    /// When not provided, the first nameserver of /etc/resolv.conf is used, which is not available on every platform.
+    ///
+    pub dns_server: Option<String>,
+
     ///
     /// Default: false (disabled)
     ///
@@ -170,6 +198,242 @@ pub struct Configuration {
     /// Should only be used if you're system requires a "paranoid" level of security.
     ///
     pub enable_database_memory_security: bool,
+
+    ///
+    /// Default: 16
+    ///
+    /// the number of events that are buffered for each subscriber.
+    /// When a subscriber is too slow to consume the events, the oldest ones are dropped.
+    /// Subscriptions created with *event_receiver()* are notified with an *Event::Lagged* when it happens.
+    /// A value of 0 is treated as 1.
+    ///
+    pub event_buffer_size: usize,
+
+    ///
+    /// Default: 60000 (one minute)
+    ///
+    /// The clock offset of a remote peer is estimated during the connection handshake.
+    /// When the absolute offset exceeds this value, an *Event::ClockSkew* is sent.
+    ///
+    /// Skewed clocks can create entries that appears to be in the future for the other peers.
+    ///
+    pub clock_skew_warning_in_ms: i64,
+
+    ///
+    /// Default: 600000 (ten minutes)
+    ///
+    /// Nodes and edges received during a synchronisation are rejected when their date exceeds the local clock by more than this value,
+    /// and an *Event::FutureDatedData* is sent.
+    /// Future dated data would win every conflict against the legitimate modifications.
+    ///
+    /// Rejected data is not lost, it is accepted by the synchronisations that happen once the local clock has reached its date.
+    ///
+    pub future_date_tolerance_in_ms: i64,
+
+    ///
+    /// Default: 10
+    ///
+    /// A write conflict occurs when a synchronisation overwrites a locally authored node with a version authored by another peer.
+    /// An *Event::WriteContention* is sent every time the number of conflicts of an entity reaches a multiple of this value.
+    ///
+    /// Set to 0 to disable the event.
+    ///
+    pub write_conflict_warning_threshold: u64,
+
+    ///
+    /// Default: 1000 (one second)
+    ///
+    /// An *Event::SlowQuery* is sent when a query takes longer than this value.
+    ///
+    /// Set to 0 to disable the event.
+    ///
+    pub slow_query_threshold_in_ms: u64,
+
+    ///
+    /// Default: 32
+    ///
+    /// The number of recent slow queries kept in memory and returned by *Discret::slow_queries()*.
+    ///
+    pub slow_query_log_size: usize,
+
+    ///
+    /// Default: 365 (one year)
+    ///
+    /// The deletion logs older than this number of days are removed every day, with the daily logs that are left empty.
+    /// A peer that did not synchronise a room for a longer period receives a snapshot of the room instead of the removed logs,
+    /// and deletes the older nodes and edges authored by the sender of the snapshot that are not part of it.
+    /// The horizon sent by a peer is never more recent than the one of the local retention.
+    ///
+    /// Peers that share rooms should use the same value. Set to 0 to keep the logs forever.
+    ///
+    pub log_retention_in_days: u32,
+
+    ///
+    /// Default: 168 (one week)
+    ///
+    /// Mutation idempotency keys older than this number of hours are removed at startup and every hour.
+    /// Retrying a mutation after that delay will perform it again. Set to 0 to keep the keys forever.
+    ///
+    pub idempotency_key_retention_in_hours: u32,
+
+    ///
+    /// Default: None
+    ///
+    /// A JSON object describing this device, for example: {"app_version":"1.2.0", "device":"Office laptop", "platform":"linux"}.
+    /// It is stored in the *metadata* field of the local sys.Peer, limited to 1024 bytes,
+    /// and sent to the other peers during the connection handshake.
+    ///
+    /// Devices sharing the same key material share the same sys.Peer: the most recently started device defines the metadata seen by the other peers.
+    ///
+    pub peer_metadata: Option<String>,
+
+    ///
+    /// Default: 1024 (1 Mb)
+    ///
+    /// Maximum size of the queries, mutations and deletions strings. Larger strings are rejected before being parsed.
+    /// Protects the application when query strings are provided by plugins or remote user interfaces.
+    ///
+    pub max_query_size_in_kb: usize,
+
+    ///
+    /// Default: 64
+    ///
+    /// Maximum number of nested '{', '(' and '[' in the queries, mutations and deletions strings.
+    /// Deeper strings are rejected before being parsed.
+    ///
+    pub max_query_depth: usize,
+
+    ///
+    /// Default: false
+    ///
+    /// When enabled, the data model template published by the admins of a *Room* is applied automatically after each synchronisation of the room.
+    /// It allows generic applications, started with an empty data model, to acquire the data model of the rooms they are invited in.
+    ///
+    /// The first applied template defines the template identifier and the signer allowed to update it:
+    /// templates with another identifier or signed by another peer are rejected.
+    ///
+    pub accept_data_model_templates: bool,
+
+    ///
+    /// Default: false
+    ///
+    /// When enabled, *Error::public_message()* replaces the identifiers embedded in the error messages (room ids, node ids, keys,...) with '<redacted>'.
+    /// Use it for applications that upload their logs for support. The identifiers remain available with *Error::identifiers()*.
+    ///
+    /// The mode applies to every Discret instance of the process once enabled.
+    ///
+    pub error_privacy_mode: bool,
+
+    ///
+    /// Default: 4096
+    ///
+    /// Maximum number of writes waiting to be committed before the synchronisation stops downloading nodes.
+    /// The download resumes once the database writer has committed enough of its backlog,
+    /// which bounds the memory used during the initial synchronisation of large rooms on slow storage.
+    ///
+    /// Set to 0 to disable the limit.
+    ///
+    pub write_backlog_limit: usize,
+
+    ///
+    /// Default: 500
+    ///
+    /// Maximum number of queries per second that a remote peer can send to this device.
+    /// Queries exceeding the rate are delayed instead of rejected, the peer is slowed down and an *Event::PeerThrottled* is sent.
+    ///
+    /// Set to 0 to disable the limit.
+    ///
+    pub remote_query_rate: usize,
+
+    ///
+    /// Default: 2000
+    ///
+    /// Number of queries that a remote peer can send in a burst before the *remote_query_rate* applies.
+    ///
+    pub remote_query_burst: usize,
+
+    ///
+    /// Default: 4
+    ///
+    /// Maximum number of queries of a remote peer that are processed at the same time, across all its connections.
+    ///
+    /// Set to 0 to disable the limit.
+    ///
+    pub remote_query_concurrency: usize,
+
+    ///
+    /// Default: 4
+    ///
+    /// Number of days of a room history that are synchronised at the same time with a remote peer.
+    /// The number of rooms synchronised at the same time is defined by *parallelism*.
+    ///
+    /// Set to 1 to synchronise one day after the other.
+    ///
+    pub sync_days_per_round: usize,
+
+    ///
+    /// Default: 2048
+    ///
+    /// Number of nodes requested to the remote peer in a single query during a room synchronisation.
+    ///
+    pub sync_batch_size: usize,
+
+    ///
+    /// Default: 100
+    ///
+    /// Round trip time above which a link is considered slow.
+    /// The round trip time is measured at the start of each room synchronisation, on slow links the *sync_days_per_round*
+    /// and *sync_batch_size* are multiplied by up to 4 to keep more data in flight.
+    ///
+    /// Set to 0 to disable the adaptation.
+    ///
+    pub sync_slow_link_rtt_in_ms: u64,
+
+    ///
+    /// Default: false
+    ///
+    /// Aggregates anonymous performance metrics: query and synchronisation durations, database size range.
+    /// The metrics are returned by *Discret::telemetry()*, nothing is ever sent by the library: submitting them is left to the application.
+    ///
+    pub enable_telemetry: bool,
+
+    ///
+    /// Default: None (disabled)
+    ///
+    /// Directory where the database backups are written. The backups are encrypted with the same secret as the database,
+    /// a backup file can replace the database file to restore it.
+    ///
+    /// *Discret::backup_status()* lists the backups and the last error.
+    ///
+    pub backup_directory: Option<String>,
+
+    ///
+    /// Default: 24
+    ///
+    /// Number of hours between two backups. The schedule is computed from the most recent backup file and survives restarts.
+    ///
+    pub backup_frequency_in_hours: u32,
+
+    ///
+    /// Default: 7
+    ///
+    /// The most recent backup of each of the last *backup_keep_daily* days is kept.
+    ///
+    pub backup_keep_daily: usize,
+
+    ///
+    /// Default: 4
+    ///
+    /// The most recent backup of each of the last *backup_keep_weekly* weeks is kept, the other backups are removed.
+    ///
+    pub backup_keep_weekly: usize,
+
+    ///
+    /// Default: Storage::File
+    ///
+    /// Defines where the database is stored. See [Storage] for the available options.
+    ///
+    pub storage: Storage,
 }
 impl Default for Configuration {
     fn default() -> Self {
@@ -177,6 +441,7 @@ impl Default for Configuration {
             parallelism: 4,
             auto_accept_local_device: true,
             auto_allow_new_peers: false,
+            connection_policy: ConnectionPolicy::AutoAccept,
             max_object_size_in_kb: 256,
             read_cache_size_in_kb: 2048,
             write_cache_size_in_kb: 2048,
@@ -187,11 +452,92 @@ impl Default for Configuration {
             multicast_ipv4_group: "224.0.0.224:22402".to_string(),
             enable_beacons: true,
             beacons: Vec::new(),
+            beacon_domain: None,
+            dns_server: None,
             enable_database_memory_security: false,
+            event_buffer_size: 16,
+            clock_skew_warning_in_ms: 60000,
+            future_date_tolerance_in_ms: 600000,
+            write_conflict_warning_threshold: 10,
+            slow_query_threshold_in_ms: 1000,
+            slow_query_log_size: 32,
+            log_retention_in_days: 365,
+            idempotency_key_retention_in_hours: 168,
+            peer_metadata: None,
+            max_query_size_in_kb: 1024,
+            max_query_depth: 64,
+            accept_data_model_templates: false,
+            error_privacy_mode: false,
+            write_backlog_limit: 4096,
+            remote_query_rate: 500,
+            remote_query_burst: 2000,
+            remote_query_concurrency: 4,
+            sync_days_per_round: 4,
+            sync_batch_size: 2048,
+            sync_slow_link_rtt_in_ms: 100,
+            enable_telemetry: false,
+            backup_directory: None,
+            backup_frequency_in_hours: 24,
+            backup_keep_daily: 7,
+            backup_keep_weekly: 4,
+            storage: Storage::File,
         }
     }
 }
 
+///
+/// Database storage
+///
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub enum Storage {
+    ///
+    /// The database is an encrypted file stored in the data folder.
+    ///
+    #[default]
+    File,
+
+    ///
+    /// The database lives in memory and is lost when the application stops.
+    /// Nothing is written in the data folder, which is usefull for tests or kiosks.
+    ///
+    /// Instances started with the same key material in the same process share the same database.
+    ///
+    Memory,
+
+    ///
+    /// The database file is stored in the data folder using a custom SQLite VFS (Virtual File System).
+    /// The VFS must be registered by the application with the given name before starting Discret.
+    ///
+    Vfs(String),
+}
+
+///
+/// Acceptance of the connections from the devices of the other users
+///
+/// The decisions are kept until Discret is stopped.
+///
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub enum ConnectionPolicy {
+    ///
+    /// Any allowed peer with a matching meeting token can connect.
+    ///
+    #[default]
+    AutoAccept,
+
+    ///
+    /// The application is asked to accept or reject the connection:
+    /// - an *Event::ConnectionRequest* is triggered with the verifying key of the peer,
+    /// - the connection waits for *Discret::answer_connection_request()*,
+    /// - the connection is closed if no answer is given within a minute, and the peer can ask again later.
+    ///
+    Prompt,
+
+    ///
+    /// Connections are rejected, unless the peer has been accepted with *Discret::answer_connection_request()*.
+    ///
+    Deny,
+}
+
 ///
 /// A beacon server
 ///
@@ -203,4 +549,9 @@ pub struct BeaconConfig {
     pub hostname: String,
     /// the hash of the Beacon config certificate
     pub cert_hash: String,
+    /// sign the announced meeting tokens with your identity,
+    /// required by the Beacons that only match the tokens of approved owners.
+    /// The Beacon learns your verifying key.
+    #[serde(default)]
+    pub register_tokens: bool,
 }
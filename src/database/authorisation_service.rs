@@ -1,5 +1,12 @@
+#[cfg(feature = "log")]
+use crate::log_level::{debug, LogSubsystem};
+#[cfg(feature = "log")]
+const LOG_SUBSYSTEM: LogSubsystem = LogSubsystem::Auth;
+
 use std::collections::{HashMap, HashSet};
 
+use serde::Serialize;
+
 use tokio::sync::{mpsc, oneshot::Sender};
 
 use crate::{
@@ -12,7 +19,7 @@ use crate::{
 
 use super::{
     daily_log::{DailyMutations, RoomChangelog},
-    deletion::DeletionQuery,
+    deletion::{DeletionQuery, NodeDelete},
     edge::{Edge, EdgeDeletionEntry},
     mutation_query::{InsertEntity, MutationQuery},
     node::{NodeDeletionEntry, NodeToInsert},
@@ -23,15 +30,21 @@ use super::{
         self, AUTH_RIGHTS_FIELD, AUTH_USER_ADMIN_FIELD, AUTH_USER_FIELD, ID_FIELD,
         MODIFICATION_DATE_FIELD, ROOM_ADMIN_FIELD, ROOM_AUTHORISATION_FIELD, ROOM_ENT,
     },
+    transaction::TransactionQuery,
     Error, Result,
 };
 
 pub enum AuthorisationMessage {
-    Sign(Vec<u8>, Sender<(Vec<u8>, Vec<u8>)>),
+    Sign(Vec<u8>, Sender<Result<(Vec<u8>, Vec<u8>)>>),
     Load(String, Sender<super::Result<()>>),
     Deletion(DeletionQuery, Sender<super::Result<DeletionQuery>>),
     Mutation(MutationQuery, Sender<super::Result<MutationQuery>>),
     MutationStream(MutationQuery, mpsc::Sender<super::Result<MutationQuery>>),
+    MutationBatch(Vec<MutationQuery>, Sender<super::Result<Vec<MutationQuery>>>),
+    Transaction(
+        Vec<TransactionQuery>,
+        Sender<super::Result<Vec<TransactionQuery>>>,
+    ),
     RoomMutationWrite(Result<()>, RoomMutationWriteQuery),
     RoomMutationStreamWrite(Result<()>, RoomMutationStreamWriteQuery),
     RoomNodeAdd(Option<RoomNode>, Box<RoomNode>, Sender<super::Result<()>>),
@@ -48,6 +61,11 @@ pub enum AuthorisationMessage {
         Sender<Result<()>>,
     ),
     UserForRoom(Uid, Sender<Result<HashSet<Vec<u8>>>>),
+    ContentionStats(Sender<Vec<EntityContention>>),
+    Can(Vec<u8>, Uid, String, RightType, Sender<bool>),
+    AddPrivateRoom(Uid, Sender<Result<()>>),
+    PreviewRoomChange(Uid, MutationQuery, Sender<Result<(Room, Room)>>),
+    GetRoom(Uid, Sender<Option<Room>>),
     // ValidatePeerNodesRequest(Uid, Vec<Vec<u8>>, Sender<Result<Vec<Vec<u8>>>>),
 }
 
@@ -59,6 +77,9 @@ pub struct RoomMutationWriteQuery {
 impl Writeable for RoomMutationWriteQuery {
     fn write(&mut self, conn: &rusqlite::Connection) -> std::result::Result<(), rusqlite::Error> {
         self.mutation_query.write(conn)?;
+        if self.mutation_query.key_mismatch {
+            return Ok(());
+        }
         for room_id in &self.room_list {
             RoomChangelog::log_room_definition(room_id, self.mutation_query.date, conn)?;
         }
@@ -66,6 +87,10 @@ impl Writeable for RoomMutationWriteQuery {
     }
 }
 impl RoomMutationWriteQuery {
+    pub fn write_error(&self) -> Option<Error> {
+        self.mutation_query.write_error()
+    }
+
     pub fn update_daily_logs(&self, daily_log: &mut DailyMutations) {
         for insert in &self.mutation_query.mutate_entities {
             if !self.room_list.contains(&insert.node_to_mutate.id) {
@@ -83,6 +108,9 @@ pub struct RoomMutationStreamWriteQuery {
 impl Writeable for RoomMutationStreamWriteQuery {
     fn write(&mut self, conn: &rusqlite::Connection) -> std::result::Result<(), rusqlite::Error> {
         self.mutation_query.write(conn)?;
+        if self.mutation_query.key_mismatch {
+            return Ok(());
+        }
         for room_id in &self.room_list {
             RoomChangelog::log_room_definition(room_id, self.mutation_query.date, conn)?;
         }
@@ -90,6 +118,10 @@ impl Writeable for RoomMutationStreamWriteQuery {
     }
 }
 impl RoomMutationStreamWriteQuery {
+    pub fn write_error(&self) -> Option<Error> {
+        self.mutation_query.write_error()
+    }
+
     pub fn update_daily_logs(&self, daily_log: &mut DailyMutations) {
         for insert in &self.mutation_query.mutate_entities {
             if !self.room_list.contains(&insert.node_to_mutate.id) {
@@ -171,9 +203,10 @@ impl AuthorisationService {
             }
 
             AuthorisationMessage::Sign(data, reply) => {
-                let verifying = auth.signing_key.export_verifying_key();
-                let signature = auth.signing_key.sign(&data);
-                let _ = reply.send((verifying, signature));
+                let res = auth
+                    .signing_key()
+                    .map(|signing_key| (auth.verifying_key.clone(), signing_key.sign(&data)));
+                let _ = reply.send(res);
             }
 
             AuthorisationMessage::Deletion(mut deletion_query, reply) => {
@@ -218,6 +251,71 @@ impl AuthorisationService {
                 };
             }
 
+            AuthorisationMessage::MutationBatch(mut mutation_queries, reply) => {
+                //the batch is written in a single transaction: one invalid mutation rejects the whole batch
+                let mut result = Ok(());
+                for mutation_query in mutation_queries
+                    .iter_mut()
+                    .filter(|q| q.replayed.is_none())
+                {
+                    match auth.validate_mutation(mutation_query) {
+                        Ok(rooms) if rooms.is_empty() => {}
+                        Ok(_) => {
+                            result = Err(Error::RoomBatchMutation());
+                            break;
+                        }
+                        Err(e) => {
+                            result = Err(e);
+                            break;
+                        }
+                    }
+                }
+                match result {
+                    Ok(()) => {
+                        let query = WriteMessage::MutationBatch(mutation_queries, reply);
+                        let _ = database_writer.send(query).await;
+                    }
+                    Err(e) => {
+                        let _ = reply.send(Err(e));
+                    }
+                }
+            }
+
+            AuthorisationMessage::Transaction(mut queries, reply) => {
+                //every operation must be valid for the transaction to be written
+                let mut result = Ok(());
+                for query in queries.iter_mut() {
+                    let res = match query {
+                        TransactionQuery::Mutation(mutation_query) => {
+                            if mutation_query.replayed.is_some() {
+                                continue;
+                            }
+                            match auth.validate_mutation(mutation_query) {
+                                Ok(rooms) if rooms.is_empty() => Ok(()),
+                                Ok(_) => Err(Error::RoomTransaction()),
+                                Err(e) => Err(e),
+                            }
+                        }
+                        TransactionQuery::Deletion(deletion_query) => {
+                            auth.validate_deletion(deletion_query)
+                        }
+                    };
+                    if let Err(e) = res {
+                        result = Err(e);
+                        break;
+                    }
+                }
+                match result {
+                    Ok(()) => {
+                        let query = WriteMessage::Transaction(queries, reply);
+                        let _ = database_writer.send(query).await;
+                    }
+                    Err(e) => {
+                        let _ = reply.send(Err(e));
+                    }
+                }
+            }
+
             AuthorisationMessage::MutationStream(mut mutation_query, reply) => {
                 match auth.validate_mutation(&mut mutation_query) {
                     Ok(rooms) => match rooms.is_empty() {
@@ -320,10 +418,31 @@ impl AuthorisationService {
                 Ok(_) => {
                     match query.room.parse() {
                         Ok(room) => {
+                            //detects when the local peer is added to or removed from the room
+                            let local_key = auth.verifying_key.clone();
+                            let date = now();
+                            let was_member = auth
+                                .rooms
+                                .get(&room.id)
+                                .map(|r| r.is_user_valid_at(&local_key, date))
+                                .unwrap_or(false);
+                            let is_member = room.is_user_valid_at(&local_key, date);
+                            let room_id = room.id;
+
                             auth.add_room(room.clone());
                             event_service
                                 .notify(EventServiceMessage::RoomModified(room))
                                 .await;
+
+                            if was_member != is_member {
+                                let by =
+                                    query.room.user_modified_by(&local_key).unwrap_or_default();
+                                let msg = match is_member {
+                                    true => EventServiceMessage::RoomJoined(room_id, by),
+                                    false => EventServiceMessage::RoomLeft(room_id, by),
+                                };
+                                event_service.notify(msg).await;
+                            }
                             let _ = query.reply.send(Ok(()));
                         }
                         Err(e) => {
@@ -342,13 +461,28 @@ impl AuthorisationService {
 
             AuthorisationMessage::AddNodes(valid_nodes, mut invalid_node, reply) => {
                 let mut write_nodes = Vec::new();
-
+                let mut contention_warnings = Vec::new();
+                let local_key = auth.verifying_key.clone();
                 for node in valid_nodes {
                     match auth.validate_node(&node) {
-                        true => write_nodes.push(node),
+                        true => {
+                            if let Some(warning) = auth.conflicts.track(&node, &local_key) {
+                                contention_warnings.push(warning);
+                            }
+                            write_nodes.push(node)
+                        }
                         false => invalid_node.push(node.id),
                     }
                 }
+                #[cfg(feature = "log")]
+                if !invalid_node.is_empty() {
+                    debug!("{} nodes rejected by the room rights", invalid_node.len());
+                }
+                for (entity, conflicts) in contention_warnings {
+                    event_service
+                        .notify(EventServiceMessage::WriteContention(entity, conflicts))
+                        .await;
+                }
                 let query = WriteMessage::Nodes(write_nodes, invalid_node, reply);
 
                 let _ = database_writer.send(query).await;
@@ -375,6 +509,14 @@ impl AuthorisationService {
                         invalid.push(edge.src);
                     }
                 }
+                #[cfg(feature = "log")]
+                if !invalid.is_empty() {
+                    debug!(
+                        "{} edges rejected by the rights of room {}",
+                        invalid.len(),
+                        base64_encode(&room_id)
+                    );
+                }
 
                 let query = WriteMessage::Edges(valid_edges, invalid, reply);
 
@@ -404,6 +546,31 @@ impl AuthorisationService {
 
             AuthorisationMessage::UserForRoom(room_id, reply) => {
                 let _ = reply.send(auth.user_for_room(room_id));
+            }
+            AuthorisationMessage::ContentionStats(reply) => {
+                let _ = reply.send(auth.conflicts.stats());
+            }
+            AuthorisationMessage::Can(verifying_key, room_id, entity, right, reply) => {
+                let _ = reply.send(auth.can(&verifying_key, &room_id, &entity, &right));
+            }
+            AuthorisationMessage::PreviewRoomChange(room_id, mut mutation_query, reply) => {
+                let _ = reply.send(auth.preview_room_change(&room_id, &mut mutation_query));
+            }
+            AuthorisationMessage::GetRoom(room_id, reply) => {
+                let _ = reply.send(auth.rooms.get(&room_id).cloned());
+            }
+            AuthorisationMessage::AddPrivateRoom(room_id, reply) => {
+                if auth.rooms.contains_key(&room_id) {
+                    let _ = reply.send(Ok(()));
+                    return;
+                }
+                let res = auth.create_system_room(room_id, database_writer).await;
+                if let Some(room) = auth.rooms.get(&room_id) {
+                    event_service
+                        .notify(EventServiceMessage::RoomModified(room.clone()))
+                        .await;
+                }
+                let _ = reply.send(res);
             } // AuthorisationMessage::ValidatePeerNodesRequest(room_id, keys, reply) => {
               //     let _ = reply.send(auth.validate_peer_nodes_request(room_id, keys));
               // }
@@ -430,63 +597,128 @@ impl AuthorisationService {
     }
 }
 
+///
+/// Write contention statistics of an entity, gathered since startup
+/// - overwrites: number of local nodes replaced by a more recent version received during synchronisation
+/// - conflicts: number of locally authored nodes replaced by a version authored by another peer
+///   while the local modification was not yet synchronised: the local change is lost
+///
+/// A high number of conflicts indicates an entity that is frequently modified concurrently,
+/// and might need a different schema design.
+///
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct EntityContention {
+    pub entity: String,
+    pub overwrites: u64,
+    pub conflicts: u64,
+}
+
+///
+/// Tracks the nodes overwritten during synchronisation
+///
+#[derive(Default)]
+pub struct WriteConflicts {
+    pub warning_threshold: u64,
+    entities: HashMap<String, EntityContention>,
+}
+impl WriteConflicts {
+    pub fn new(warning_threshold: u64) -> Self {
+        Self {
+            warning_threshold,
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// returns the entity name and its number of conflicts every time the number of conflicts reaches a multiple of the warning threshold
+    ///
+    pub fn track(
+        &mut self,
+        node_to_insert: &NodeToInsert,
+        local_key: &Vec<u8>,
+    ) -> Option<(String, u64)> {
+        let node = node_to_insert.node.as_ref()?;
+        let old_key = node_to_insert.old_verifying_key.as_ref()?;
+        let entity = node_to_insert.entity_name.as_ref()?;
+
+        let stats = self
+            .entities
+            .entry(entity.clone())
+            .or_insert_with(|| EntityContention {
+                entity: entity.clone(),
+                ..Default::default()
+            });
+        stats.overwrites += 1;
+
+        //the local modification is more recent than the last synchronisation of the room,
+        //it could not have been seen by the author of the incoming node
+        let unsynced = node_to_insert.old_mdate > node_to_insert.old_room_sync_date.unwrap_or(0);
+        let newer = node.mdate >= node_to_insert.old_mdate;
+
+        if old_key.eq(local_key) && !node.verifying_key.eq(local_key) && unsynced && newer {
+            stats.conflicts += 1;
+            if self.warning_threshold > 0 && stats.conflicts.is_multiple_of(self.warning_threshold)
+            {
+                return Some((entity.clone(), stats.conflicts));
+            }
+        }
+        None
+    }
+
+    ///
+    /// returns the statistics ordered by decreasing number of conflicts
+    ///
+    pub fn stats(&self) -> Vec<EntityContention> {
+        let mut stats: Vec<EntityContention> = self.entities.values().cloned().collect();
+        stats.sort_by(|a, b| {
+            b.conflicts
+                .cmp(&a.conflicts)
+                .then(b.overwrites.cmp(&a.overwrites))
+                .then(a.entity.cmp(&b.entity))
+        });
+        stats
+    }
+}
+
 pub struct RoomAuthorisations {
-    pub signing_key: Ed25519SigningKey,
+    //a read-only observer has no signing key
+    pub signing_key: Option<Ed25519SigningKey>,
+    pub verifying_key: Vec<u8>,
     pub rooms: HashMap<Uid, Room>,
     pub max_node_size: u64,
+    pub conflicts: WriteConflicts,
 }
 impl RoomAuthorisations {
     pub fn add_room(&mut self, room: Room) {
         self.rooms.insert(room.id, room);
     }
 
+    ///
+    /// every local mutation is signed, the database of a read-only observer cannot be mutated
+    ///
+    pub fn signing_key(&self) -> Result<&Ed25519SigningKey> {
+        self.signing_key.as_ref().ok_or(Error::ReadOnly())
+    }
+
     pub fn validate_deletion(&self, deletion_query: &mut DeletionQuery) -> Result<()> {
         let now = now();
-        let verifying_key = self.signing_key.export_verifying_key();
+        let signing_key = self.signing_key()?;
+        let verifying_key = self.verifying_key.clone();
         for node in &deletion_query.nodes {
-            match node.name.as_str() {
-                system_entities::ROOM_ENT
-                | system_entities::AUTHORISATION_ENT
-                | system_entities::ENTITY_RIGHT_ENT
-                | system_entities::USER_AUTH_ENT => return Err(Error::DeleteNotAllowed()),
-                _ => {
-                    if let Some(room_id) = &node.node.room_id {
-                        match self.rooms.get(room_id) {
-                            Some(room) => {
-                                let can = if node.node.verifying_key.eq(&verifying_key) {
-                                    room.can(
-                                        &verifying_key,
-                                        &node.name,
-                                        node.date,
-                                        &RightType::MutateSelf,
-                                    )
-                                } else {
-                                    room.can(&verifying_key, &node.name, now, &RightType::MutateAll)
-                                };
-                                if !can {
-                                    return Err(Error::AuthorisationRejected(
-                                        node.name.clone(),
-                                        base64_encode(room_id),
-                                    ));
-                                }
-                                let log_entry = NodeDeletionEntry::build(
-                                    room.id,
-                                    &node.node,
-                                    now,
-                                    &self.signing_key,
-                                );
+            if let Some(room) = self.validate_node_deletion(node, &verifying_key, now)? {
+                let log_entry = NodeDeletionEntry::build(room.id, &node.node, now, signing_key);
 
-                                deletion_query.node_log.push(log_entry);
-                            }
-                            None => return Err(Error::UnknownRoom(base64_encode(room_id))),
-                        }
-                    }
-                }
+                deletion_query.node_log.push(log_entry);
             }
         }
 
+        //soft deleted nodes are updated, the deletion is synchronised like any other update
+        for node in &deletion_query.soft_deleted {
+            self.validate_node_deletion(node, &verifying_key, now)?;
+        }
+
         for node in &mut deletion_query.updated_nodes {
-            node.sign(&self.signing_key)?;
+            node.sign(signing_key)?;
         }
 
         for edge in &deletion_query.edges {
@@ -520,12 +752,8 @@ impl RoomAuthorisations {
                                         base64_encode(room_id),
                                     ));
                                 }
-                                let log_entry = EdgeDeletionEntry::build(
-                                    room.id,
-                                    &edge.edge,
-                                    now,
-                                    &self.signing_key,
-                                );
+                                let log_entry =
+                                    EdgeDeletionEntry::build(room.id, &edge.edge, now, signing_key);
                                 deletion_query.edge_log.push(log_entry);
                             }
                             None => return Err(Error::UnknownRoom(base64_encode(room_id))),
@@ -538,10 +766,47 @@ impl RoomAuthorisations {
         Ok(())
     }
 
+    //
+    // returns the room of the node to delete, nodes without room can always be deleted
+    //
+    fn validate_node_deletion(
+        &self,
+        node: &NodeDelete,
+        verifying_key: &Vec<u8>,
+        now: i64,
+    ) -> Result<Option<&Room>> {
+        match node.name.as_str() {
+            system_entities::ROOM_ENT
+            | system_entities::AUTHORISATION_ENT
+            | system_entities::ENTITY_RIGHT_ENT
+            | system_entities::USER_AUTH_ENT => Err(Error::DeleteNotAllowed()),
+            _ => match &node.node.room_id {
+                Some(room_id) => match self.rooms.get(room_id) {
+                    Some(room) => {
+                        let can = if node.node.verifying_key.eq(verifying_key) {
+                            room.can(verifying_key, &node.name, node.date, &RightType::MutateSelf)
+                        } else {
+                            room.can(verifying_key, &node.name, now, &RightType::MutateAll)
+                        };
+                        if !can {
+                            return Err(Error::AuthorisationRejected(
+                                node.name.clone(),
+                                base64_encode(room_id),
+                            ));
+                        }
+                        Ok(Some(room))
+                    }
+                    None => Err(Error::UnknownRoom(base64_encode(room_id))),
+                },
+                None => Ok(None),
+            },
+        }
+    }
+
     pub fn validate_mutation(&mut self, mutation_query: &mut MutationQuery) -> Result<Vec<Room>> {
-        mutation_query.sign_all(&self.signing_key)?;
+        mutation_query.sign_all(self.signing_key()?)?;
 
-        let verifying_key = self.signing_key.export_verifying_key();
+        let verifying_key = self.verifying_key.clone();
         let mut rooms = Vec::new();
         for insert_entity in &mut mutation_query.mutate_entities {
             let mut rooms_ent = self.validate_entity_mutation(insert_entity, &verifying_key)?;
@@ -573,15 +838,30 @@ impl RoomAuthorisations {
                     to_insert.entity.clone(),
                 ))
             }
+            system_entities::ROOM_SETTINGS_ENT
+            | system_entities::TOMBSTONE_ENT
+            | system_entities::DATA_MODEL_PROPOSAL_ENT
+            | system_entities::DATA_MODEL_TEMPLATE_ENT
+            | system_entities::AUTOMATION_RULE_ENT
+                if to_insert.node.is_some() && to_insert.room_id.is_none() =>
+            {
+                return Err(Error::MissingRoomId(to_insert.entity.clone()))
+            }
+            //sync incidents are local to the device and must never be synchronised
+            system_entities::SYNC_INCIDENT_ENT if to_insert.room_id.is_some() => {
+                return Err(Error::ForbiddenRoomId(to_insert.entity.clone()))
+            }
             _ => {
-                if to_insert.node.is_none() {
-                    //this is a reference, not mutation occurs
-                    return Ok(rooms);
-                } else {
-                    let node = to_insert.node.as_ref().unwrap();
-                    let size = bincode::serialized_size(node)?;
-                    if size > self.max_node_size {
-                        return Err(Error::NodeTooBig(size, self.max_node_size));
+                match &to_insert.node {
+                    None => {
+                        //this is a reference, not mutation occurs
+                        return Ok(rooms);
+                    }
+                    Some(node) => {
+                        let size = bincode::serialized_size(node)?;
+                        if size > self.max_node_size {
+                            return Err(Error::NodeTooBig(size, self.max_node_size));
+                        }
                     }
                 }
                 match &to_insert.old_node {
@@ -645,7 +925,7 @@ impl RoomAuthorisations {
                                         room.id,
                                         edge_deletion,
                                         now,
-                                        &self.signing_key,
+                                        self.signing_key()?,
                                     );
                                     entity_to_mutate.edge_deletions_log.push(log);
                                 }
@@ -674,7 +954,7 @@ impl RoomAuthorisations {
                                         room.id,
                                         edge_deletion,
                                         now,
-                                        &self.signing_key,
+                                        self.signing_key()?,
                                     );
                                     entity_to_mutate.edge_deletions_log.push(log);
                                 }
@@ -720,7 +1000,7 @@ impl RoomAuthorisations {
             rights: HashMap::new(),
             user_admins: HashMap::new(),
         };
-        let vkey = self.signing_key.export_verifying_key();
+        let vkey = self.verifying_key.clone();
         auth.add_user(User {
             verifying_key: vkey,
             date: 0,
@@ -984,6 +1264,58 @@ impl RoomAuthorisations {
         result
     }
 
+    ///
+    /// check whether a user is currently allowed to mutate or delete an entity of a room
+    /// the room definition entities can only be modified by the room admins
+    ///
+    pub fn can(
+        &self,
+        verifying_key: &Vec<u8>,
+        room_id: &Uid,
+        entity: &str,
+        right: &RightType,
+    ) -> bool {
+        let now = now();
+        match self.rooms.get(room_id) {
+            Some(room) => match entity {
+                system_entities::ROOM_ENT
+                | system_entities::AUTHORISATION_ENT
+                | system_entities::ENTITY_RIGHT_ENT
+                | system_entities::USER_AUTH_ENT => room.is_admin(verifying_key, now),
+                _ => room.can(verifying_key, entity, now, right),
+            },
+            None => false,
+        }
+    }
+
+    ///
+    /// validates the mutation without applying it and returns the room definition before and after the mutation
+    /// the room is unchanged if the mutation does not modify it
+    ///
+    pub fn preview_room_change(
+        &self,
+        room_id: &Uid,
+        mutation_query: &mut MutationQuery,
+    ) -> Result<(Room, Room)> {
+        let old_room = self
+            .rooms
+            .get(room_id)
+            .ok_or(Error::UnknownRoom(uid_encode(room_id)))?
+            .clone();
+
+        mutation_query.sign_all(self.signing_key()?)?;
+        let verifying_key = self.verifying_key.clone();
+        let mut new_room = old_room.clone();
+        for insert_entity in &mut mutation_query.mutate_entities {
+            for room in self.validate_entity_mutation(insert_entity, &verifying_key)? {
+                if room.id.eq(room_id) {
+                    new_room = room;
+                }
+            }
+        }
+        Ok((old_room, new_room))
+    }
+
     pub fn user_for_room(&self, room_id: Uid) -> Result<HashSet<Vec<u8>>> {
         let room = self
             .rooms
@@ -8,12 +8,16 @@ mod tests {
     use crate::{
         configuration::Configuration,
         database::{
+            authorisation_service::WriteConflicts,
             graph_database::GraphDatabaseService,
+            node::{Node, NodeToInsert},
             query_language::parameter::{Parameters, ParametersAdd},
+            room::RightType,
+            system_entities::{AutomationRule, RoomSettings, Tombstone},
         },
         date_utils::now,
         event_service::EventService,
-        security::{base64_encode, random32, uid_decode},
+        security::{base64_encode, new_uid, random32, uid_decode},
         ResultParser,
     };
 
@@ -692,7 +696,7 @@ mod tests {
             &secret,
             &random32(),
             path,
-            &&Configuration::default(),
+            &Configuration::default(),
             EventService::new(),
         )
         .await
@@ -1460,4 +1464,541 @@ mod tests {
             .unwrap();
         println!("{}", res);
     }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn can() {
+        init_database_path();
+        let data_model = "{Person{ name:String }}";
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let (app, verifying_key, _) = GraphDatabaseService::start(
+            "authorisation app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        let other_key = random32().to_vec();
+        let mut param = Parameters::default();
+        param.add("admin", base64_encode(&verifying_key)).unwrap();
+        param.add("other", base64_encode(&other_key)).unwrap();
+        let room = app
+            .mutate_raw(
+                r#"mutate mut {
+                    sys.Room{
+                        admin: [{
+                            verif_key:$admin
+                        }]
+                        authorisations:[{
+                            name:"writers"
+                            rights:[{
+                                entity:"Person"
+                                mutate_self:true
+                                mutate_all:false
+                            }]
+                            users:[{
+                                verif_key:$other
+                            }]
+                        }]
+                    }
+                }"#,
+                Some(param),
+            )
+            .await
+            .unwrap();
+        let room_id = room.mutate_entities[0].node_to_mutate.id;
+
+        let can = |key: &Vec<u8>, entity: &str, right: RightType| {
+            app.can(key.clone(), room_id, entity.to_string(), right)
+        };
+
+        assert!(can(&other_key, "Person", RightType::MutateSelf)
+            .await
+            .unwrap());
+        assert!(!can(&other_key, "Person", RightType::MutateAll)
+            .await
+            .unwrap());
+        assert!(!can(&other_key, "sys.Room", RightType::MutateSelf)
+            .await
+            .unwrap());
+        assert!(!can(&other_key, "sys.RoomSettings", RightType::MutateSelf)
+            .await
+            .unwrap());
+
+        assert!(can(&verifying_key, "Person", RightType::MutateSelf)
+            .await
+            .unwrap());
+        assert!(can(&verifying_key, "sys.Room", RightType::MutateAll)
+            .await
+            .unwrap());
+        assert!(
+            can(&verifying_key, "sys.RoomSettings", RightType::MutateAll)
+                .await
+                .unwrap()
+        );
+
+        let unknown_key = random32().to_vec();
+        assert!(!can(&unknown_key, "Person", RightType::MutateSelf)
+            .await
+            .unwrap());
+
+        let unknown_room = app
+            .can(
+                verifying_key.clone(),
+                new_uid(),
+                "Person".to_string(),
+                RightType::MutateSelf,
+            )
+            .await
+            .unwrap();
+        assert!(!unknown_room);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn room_settings() {
+        init_database_path();
+        let data_model = "{Person{ name:String }}";
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let (app, verifying_key, _) = GraphDatabaseService::start(
+            "authorisation app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        let user_id = base64_encode(&verifying_key);
+
+        let mut param = Parameters::default();
+        param.add("user_id", user_id.clone()).unwrap();
+        let room = app
+            .mutate_raw(
+                r#"mutate mut {
+                    sys.Room{
+                        admin: [{
+                            verif_key:$user_id
+                        }]
+                    }
+                }"#,
+                Some(param),
+            )
+            .await
+            .unwrap();
+        let room_id = base64_encode(&room.mutate_entities[0].node_to_mutate.id);
+
+        RoomSettings::set(&room_id, "read_only", true.into(), &app)
+            .await
+            .unwrap();
+        RoomSettings::set(&room_id, "slow_mode", 30.into(), &app)
+            .await
+            .unwrap();
+        RoomSettings::set(&room_id, "slow_mode", 60.into(), &app)
+            .await
+            .unwrap();
+        RoomSettings::set(&room_id, "theme", "dark".into(), &app)
+            .await
+            .unwrap();
+
+        let settings = RoomSettings::load(&room_id, &app).await.unwrap();
+        assert_eq!(settings.get_bool("read_only"), Some(true));
+        assert_eq!(settings.get_i64("slow_mode"), Some(60));
+        assert_eq!(settings.get_str("theme"), Some("dark"));
+        assert_eq!(settings.get_bool("theme"), None);
+        assert_eq!(settings.get_bool("unknown"), None);
+        assert_eq!(settings.keys().count(), 3);
+
+        let result = app
+            .query(
+                "query q{
+                    sys.RoomSettings(order_by(key asc)){
+                        key
+                    }
+                }",
+                None,
+            )
+            .await
+            .unwrap();
+        let expected = "{\n\"sys.RoomSettings\":[{\"key\":\"read_only\"},{\"key\":\"slow_mode\"},{\"key\":\"theme\"}]\n}";
+        assert_eq!(result, expected);
+
+        app.mutate_raw(
+            r#"mutate {
+                sys.RoomSettings{
+                    key: "read_only"
+                    value: "false"
+                }
+            }"#,
+            None,
+        )
+        .await
+        .expect_err("settings must belong to a room");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn automation_rules() {
+        init_database_path();
+        let data_model = "{Task{ name:String, status:String default \"new\" } Log{ task:Base64 }}";
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let (app, verifying_key, _) = GraphDatabaseService::start(
+            "authorisation app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        let user_id = base64_encode(&verifying_key);
+        let mut param = Parameters::default();
+        param.add("user_id", user_id.clone()).unwrap();
+        let room = app
+            .mutate_raw(
+                r#"mutate mut {
+                    sys.Room{
+                        admin: [{
+                            verif_key:$user_id
+                        }]
+                        authorisations:[{
+                            name:"members"
+                            rights:[{
+                                entity:"Task"
+                                mutate_self:true
+                                mutate_all:true
+                            },{
+                                entity:"Log"
+                                mutate_self:true
+                                mutate_all:true
+                            }]
+                            users: [{
+                                verif_key:$user_id
+                            }]
+                        }]
+                    }
+                }"#,
+                Some(param),
+            )
+            .await
+            .unwrap();
+        let room_id = base64_encode(&room.mutate_entities[0].node_to_mutate.id);
+
+        //each task is logged, each log creates a task: the loop stops when a rule would trigger itself
+        let log_rule = AutomationRule::create(
+            &room_id,
+            "Task",
+            "mutate { Log{ room_id:$room_id task:$id } }",
+            &app,
+        )
+        .await
+        .unwrap();
+        AutomationRule::create(
+            &room_id,
+            "Log",
+            r#"mutate { Task{ room_id:$room_id name:"from log" } }"#,
+            &app,
+        )
+        .await
+        .unwrap();
+        AutomationRule::create(
+            &room_id,
+            "Task",
+            r#"mutate { Task{ id:$id room_id:$room_id status:"triggered" } }"#,
+            &app,
+        )
+        .await
+        .unwrap();
+
+        //rules only mutate the room of the inserted entity
+        for invalid in [
+            "mutate { Unknown{} }",
+            "mutate { Log{ task:$id } }",
+            r#"mutate { Log{ room_id:"jNHyqK0nSKKcOtbrFDRHdw" task:$id } }"#,
+            r#"mutate { Task{ id:"jNHyqK0nSKKcOtbrFDRHdw" room_id:$room_id status:"done" } }"#,
+            r#"mutate { Task{ room_id:$room_id name:"sub" } Log{ room_id:"jNHyqK0nSKKcOtbrFDRHdw" } }"#,
+            r#"mutate { sys.Tombstone{ room_id:$room_id target:$id reason:"rule" } }"#,
+        ] {
+            AutomationRule::create(&room_id, "Task", invalid, &app)
+                .await
+                .expect_err(invalid);
+        }
+
+        //an invalid rule received from a peer is not applied and does not prevent the other ones
+        let mut param = Parameters::default();
+        param.add("room_id", room_id.clone()).unwrap();
+        app.mutate_raw(
+            r#"mutate {
+                sys.AutomationRule{
+                    room_id: $room_id
+                    trigger: "Task"
+                    mutation: "mutate { Log{ task:$id } }"
+                }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+
+        let mut param = Parameters::default();
+        param.add("room_id", room_id.clone()).unwrap();
+        app.mutate_raw(
+            r#"mutate {
+                Task{
+                    room_id:$room_id
+                    name:"first"
+                }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+
+        let tasks = "query {
+            Task(order_by(name asc)){
+                name
+                status
+            }
+        }";
+        let result = app.query(tasks, None).await.unwrap();
+        let expected = "{\n\"Task\":[{\"name\":\"first\",\"status\":\"triggered\"},{\"name\":\"from log\",\"status\":\"triggered\"}]\n}";
+        assert_eq!(result, expected);
+
+        let logs = "query { Log{ task } }";
+        let mut parser = ResultParser::new(&app.query(logs, None).await.unwrap()).unwrap();
+        #[derive(Deserialize)]
+        struct Log {}
+        let log_list: Vec<Log> = parser.take_array("Log").unwrap();
+        assert_eq!(log_list.len(), 1);
+
+        AutomationRule::set_enabled(&log_rule, false, &app)
+            .await
+            .unwrap();
+        let rules = AutomationRule::load(&room_id, &app).await.unwrap();
+        assert_eq!(rules.len(), 4);
+        assert_eq!(rules.iter().filter(|r| r.enabled).count(), 3);
+
+        let mut param = Parameters::default();
+        param.add("room_id", room_id.clone()).unwrap();
+        app.mutate_raw(
+            r#"mutate {
+                Task{
+                    room_id:$room_id
+                    name:"second"
+                }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+        let mut parser = ResultParser::new(&app.query(logs, None).await.unwrap()).unwrap();
+        let log_list: Vec<Log> = parser.take_array("Log").unwrap();
+        assert_eq!(log_list.len(), 1);
+
+        //the rules cached for the room are reloaded when a rule changes
+        AutomationRule::set_enabled(&log_rule, true, &app)
+            .await
+            .unwrap();
+        let mut param = Parameters::default();
+        param.add("room_id", room_id.clone()).unwrap();
+        app.mutate_raw(
+            r#"mutate {
+                Task{
+                    room_id:$room_id
+                    name:"third"
+                }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+        let mut parser = ResultParser::new(&app.query(logs, None).await.unwrap()).unwrap();
+        let log_list: Vec<Log> = parser.take_array("Log").unwrap();
+        assert_eq!(log_list.len(), 2);
+
+        app.mutate_raw(
+            r#"mutate {
+                sys.AutomationRule{
+                    trigger: "Task"
+                    mutation: "mutate { Log{ task:$id } }"
+                }
+            }"#,
+            None,
+        )
+        .await
+        .expect_err("rules must belong to a room");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tombstone() {
+        init_database_path();
+        let data_model = "{Person{ name:String, parents:[Person] nullable }}";
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let (app, verifying_key, _) = GraphDatabaseService::start(
+            "authorisation app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        let mut param = Parameters::default();
+        param.add("user_id", base64_encode(&verifying_key)).unwrap();
+        let room = app
+            .mutate_raw(
+                r#"mutate mut {
+                    sys.Room{
+                        admin: [{
+                            verif_key:$user_id
+                        }]
+                        authorisations:[{
+                            name:"members"
+                            rights:[{
+                                entity:"Person"
+                                mutate_self:true
+                                mutate_all:true
+                            }]
+                        }]
+                    }
+                }"#,
+                Some(param),
+            )
+            .await
+            .unwrap();
+        let room_id = base64_encode(&room.mutate_entities[0].node_to_mutate.id);
+
+        let mut param = Parameters::default();
+        param.add("room_id", room_id.clone()).unwrap();
+        let res = app
+            .mutate(
+                r#"mutate mut {
+                    Person{
+                        room_id: $room_id
+                        name: "child"
+                        parents: [{
+                            room_id: $room_id
+                            name: "abusive"
+                        },{
+                            room_id: $room_id
+                            name: "polite"
+                        }]
+                    }
+                }"#,
+                Some(param),
+            )
+            .await
+            .unwrap();
+
+        #[derive(Deserialize)]
+        struct Id {
+            id: String,
+        }
+        #[derive(Deserialize)]
+        struct Child {
+            parents: Vec<Id>,
+        }
+        let mut parser = ResultParser::new(&res).unwrap();
+        let child: Child = parser.take_object("Person").unwrap();
+        let abusive_id = child.parents[0].id.clone();
+
+        let tombstone_id = Tombstone::create(&room_id, &abusive_id, Some("spam"), &app)
+            .await
+            .unwrap();
+
+        let query = "query q{
+                Person(order_by(name asc)){
+                    name
+                    parents{ name }
+                }
+            }";
+        let result = app.query(query, None).await.unwrap();
+        let expected = "{\n\"Person\":[{\"name\":\"child\",\"parents\":[{\"name\":\"polite\"}]},{\"name\":\"polite\",\"parents\":[]}]\n}";
+        assert_eq!(result, expected);
+
+        //the moderated node is retained and restored when the tombstone is deleted
+        let mut param = Parameters::default();
+        param.add("id", tombstone_id).unwrap();
+        app.delete(
+            "delete del {
+                sys.Tombstone{ $id }
+            }",
+            Some(param),
+        )
+        .await
+        .unwrap();
+
+        let result = app.query(query, None).await.unwrap();
+        assert!(result.contains("abusive"));
+
+        Tombstone::create("", &abusive_id, None, &app)
+            .await
+            .expect_err("a tombstone must belong to a room");
+    }
+
+    #[test]
+    fn write_conflicts() {
+        let local_key = random32().to_vec();
+        let remote_key = random32().to_vec();
+        let node_to_insert =
+            |entity: &str, old_key: Option<&Vec<u8>>, new_key: &Vec<u8>| NodeToInsert {
+                node: Some(Node {
+                    verifying_key: new_key.clone(),
+                    mdate: 300,
+                    ..Default::default()
+                }),
+                entity_name: Some(entity.to_string()),
+                old_verifying_key: old_key.cloned(),
+                old_mdate: 200,
+                old_room_sync_date: Some(100),
+                ..Default::default()
+            };
+
+        let mut conflicts = WriteConflicts::new(2);
+
+        //new nodes are not tracked
+        let new = node_to_insert("Person", None, &remote_key);
+        assert!(conflicts.track(&new, &local_key).is_none());
+        assert!(conflicts.stats().is_empty());
+
+        //overwriting a remote node is not a conflict
+        let overwrite = node_to_insert("Pet", Some(&remote_key), &remote_key);
+        assert!(conflicts.track(&overwrite, &local_key).is_none());
+
+        let conflict = node_to_insert("Person", Some(&local_key), &remote_key);
+        assert!(conflicts.track(&conflict, &local_key).is_none());
+        assert_eq!(
+            conflicts.track(&conflict, &local_key),
+            Some(("Person".to_string(), 2))
+        );
+        assert!(conflicts.track(&conflict, &local_key).is_none());
+
+        //the local node was synchronised before being overwritten: nothing is lost
+        let mut synced = node_to_insert("Person", Some(&local_key), &remote_key);
+        synced.old_room_sync_date = Some(250);
+        assert!(conflicts.track(&synced, &local_key).is_none());
+
+        let stats = conflicts.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].entity, "Person");
+        assert_eq!(stats[0].overwrites, 4);
+        assert_eq!(stats[0].conflicts, 3);
+        assert_eq!(stats[1].entity, "Pet");
+        assert_eq!(stats[1].overwrites, 1);
+        assert_eq!(stats[1].conflicts, 0);
+    }
 }
@@ -0,0 +1,364 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use rusqlite::backup::{Backup, StepResult};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    configuration::Configuration,
+    date_utils::{date, now},
+};
+
+use super::{sqlite_database::create_connection, Error, Result};
+
+const BACKUP_PREFIX: &str = "backup_";
+const BACKUP_EXTENSION: &str = ".db";
+const DAY_IN_MS: i64 = 24 * 60 * 60 * 1000;
+
+///
+/// A backup file
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BackupInfo {
+    ///
+    /// path of the backup file
+    ///
+    pub path: String,
+
+    ///
+    /// creation date of the backup
+    ///
+    pub date: i64,
+
+    ///
+    /// size of the file in bytes
+    ///
+    pub size: u64,
+}
+
+///
+/// State of the backup scheduler
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BackupStatus {
+    ///
+    /// false when the *backup_directory* configuration is not defined
+    ///
+    pub enabled: bool,
+
+    ///
+    /// date of the next scheduled backup
+    ///
+    pub next_backup: Option<i64>,
+
+    ///
+    /// error of the last backup attempt, None if it succeeded
+    ///
+    pub last_error: Option<String>,
+
+    ///
+    /// the backups kept in the directory, the most recent first
+    ///
+    pub backups: Vec<BackupInfo>,
+}
+
+///
+/// Periodic snapshots of the database with rotation.
+///
+/// Backups are encrypted with the database secret: a backup file replaces the database file to restore it.
+/// A backup is written in a temporary file that is renamed once complete, an interrupted backup is never listed.
+///
+/// The rotation keeps the most recent backup of the last *backup_keep_daily* days
+/// and the most recent backup of the last *backup_keep_weekly* weeks. The most recent backup is always kept.
+///
+#[derive(Clone)]
+pub struct BackupScheduler {
+    directory: Option<PathBuf>,
+    frequency: i64,
+    keep_daily: usize,
+    keep_weekly: usize,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+impl BackupScheduler {
+    pub fn new(configuration: &Configuration) -> Self {
+        Self {
+            directory: configuration.backup_directory.as_ref().map(PathBuf::from),
+            frequency: configuration.backup_frequency_in_hours.max(1) as i64 * 3600 * 1000,
+            keep_daily: configuration.backup_keep_daily,
+            keep_weekly: configuration.backup_keep_weekly,
+            last_error: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.directory.is_some()
+    }
+
+    ///
+    /// date of the next backup, computed from the most recent backup file so the schedule survives restarts
+    ///
+    pub fn next_backup(&self) -> Result<Option<i64>> {
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return Ok(None),
+        };
+        let backups = list_backups(directory)?;
+        Ok(Some(match backups.first() {
+            Some(backup) => backup.date + self.frequency,
+            None => now(),
+        }))
+    }
+
+    ///
+    /// copies the database in the backup directory and removes the backups that are not kept by the rotation
+    ///
+    pub fn backup(&self, path: &PathBuf, secret: &[u8; 32]) -> Result<BackupInfo> {
+        let res = self.backup_and_rotate(path, secret);
+        *self.last_error.lock().unwrap() = res.as_ref().err().map(|e| e.to_string());
+        res
+    }
+
+    fn backup_and_rotate(&self, path: &PathBuf, secret: &[u8; 32]) -> Result<BackupInfo> {
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => {
+                return Err(Error::Backup(
+                    "the backup_directory configuration is not defined".to_string(),
+                ))
+            }
+        };
+        fs::create_dir_all(directory)?;
+
+        let mut date = now();
+        //two backups in the same millisecond would share the same file
+        if let Some(last) = list_backups(directory)?.first() {
+            date = date.max(last.date + 1);
+        }
+        let target = directory.join(format!("{}{}{}", BACKUP_PREFIX, date, BACKUP_EXTENSION));
+        let mut temporary = target.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+
+        if let Err(e) = backup_database(path, secret, &temporary) {
+            let _ = fs::remove_file(&temporary);
+            return Err(e);
+        }
+        fs::rename(&temporary, &target)?;
+
+        let backups = list_backups(directory)?;
+        for backup in rotation(&backups, self.keep_daily, self.keep_weekly) {
+            fs::remove_file(&backup.path)?;
+        }
+
+        Ok(BackupInfo {
+            path: target.to_string_lossy().to_string(),
+            date,
+            size: fs::metadata(&target)?.len(),
+        })
+    }
+
+    pub fn status(&self) -> Result<BackupStatus> {
+        let backups = match &self.directory {
+            Some(directory) => list_backups(directory)?,
+            None => Vec::new(),
+        };
+        Ok(BackupStatus {
+            enabled: self.enabled(),
+            next_backup: self.next_backup()?,
+            last_error: self.last_error.lock().unwrap().clone(),
+            backups,
+        })
+    }
+}
+
+///
+/// copies the database in a new file encrypted with the same secret
+///
+/// the copy is done in a single step to get a consistent snapshot while the database is being modified
+///
+pub fn backup_database(path: &PathBuf, secret: &[u8; 32], target: &PathBuf) -> Result<()> {
+    if target.exists() {
+        fs::remove_file(target)?;
+    }
+    let source = create_connection(path, secret, 2048, false)?;
+    let mut destination = create_connection(target, secret, 2048, false)?;
+    let backup = Backup::new(&source, &mut destination)?;
+    loop {
+        match backup.step(-1)? {
+            StepResult::Done => break,
+            StepResult::More => continue,
+            StepResult::Busy | StepResult::Locked => {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            _ => continue,
+        }
+    }
+    Ok(())
+}
+
+///
+/// the backups of the directory, the most recent first
+///
+pub fn list_backups(directory: &Path) -> Result<Vec<BackupInfo>> {
+    let mut backups = Vec::new();
+    if !directory.exists() {
+        return Ok(backups);
+    }
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let date = name
+            .strip_prefix(BACKUP_PREFIX)
+            .and_then(|name| name.strip_suffix(BACKUP_EXTENSION))
+            .and_then(|date| date.parse::<i64>().ok());
+        if let Some(date) = date {
+            backups.push(BackupInfo {
+                path: entry.path().to_string_lossy().to_string(),
+                date,
+                size: entry.metadata()?.len(),
+            });
+        }
+    }
+    backups.sort_by_key(|b| std::cmp::Reverse(b.date));
+    Ok(backups)
+}
+
+///
+/// the backups that are not kept by the rotation
+///
+/// the backups must be sorted with the most recent first
+///
+pub fn rotation(backups: &[BackupInfo], keep_daily: usize, keep_weekly: usize) -> Vec<BackupInfo> {
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    let mut removed = Vec::new();
+    for (i, backup) in backups.iter().enumerate() {
+        let day = date(backup.date) / DAY_IN_MS;
+        //the epoch is a thursday, weeks start on monday
+        let week = (day + 3) / 7;
+
+        let mut keep = i == 0;
+        if !days.contains(&day) && days.len() < keep_daily {
+            days.insert(day);
+            keep = true;
+        }
+        if !weeks.contains(&week) && weeks.len() < keep_weekly {
+            weeks.insert(week);
+            keep = true;
+        }
+        if !keep {
+            removed.push(backup.clone());
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::random32;
+
+    const DATA_PATH: &str = "test_data/database/backup/";
+
+    fn backup_at(date: i64) -> BackupInfo {
+        BackupInfo {
+            path: date.to_string(),
+            date,
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn rotation_policy() {
+        //2024-01-01 is a monday
+        let monday = 1704067200000;
+        let mut backups = Vec::new();
+        for day in 0..28 {
+            backups.push(backup_at(monday + day * DAY_IN_MS));
+            backups.push(backup_at(monday + day * DAY_IN_MS + 3600 * 1000));
+        }
+        backups.sort_by_key(|b| std::cmp::Reverse(b.date));
+
+        let removed = rotation(&backups, 3, 2);
+        let kept: Vec<i64> = backups
+            .iter()
+            .filter(|b| !removed.iter().any(|r| r.date == b.date))
+            .map(|b| b.date)
+            .collect();
+
+        //the last backup of the three last days, the last week is covered by the daily backups
+        assert_eq!(
+            vec![
+                monday + 27 * DAY_IN_MS + 3600 * 1000,
+                monday + 26 * DAY_IN_MS + 3600 * 1000,
+                monday + 25 * DAY_IN_MS + 3600 * 1000,
+                monday + 20 * DAY_IN_MS + 3600 * 1000,
+            ],
+            kept
+        );
+
+        //the most recent backup is always kept
+        assert_eq!(backups.len() - 1, rotation(&backups, 0, 0).len());
+    }
+
+    #[test]
+    fn scheduled_backup() {
+        let directory: PathBuf = format!("{}{}", DATA_PATH, "scheduled").into();
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+
+        let secret = random32();
+        let path = directory.join("database");
+        let conn = create_connection(&path, &secret, 1024, false).unwrap();
+        conn.execute("CREATE TABLE test (value TEXT)", []).unwrap();
+        conn.execute("INSERT INTO test VALUES ('hello')", [])
+            .unwrap();
+
+        let configuration = Configuration {
+            backup_directory: Some(directory.join("backups").to_string_lossy().to_string()),
+            backup_keep_daily: 1,
+            backup_keep_weekly: 0,
+            ..Default::default()
+        };
+        let scheduler = BackupScheduler::new(&configuration);
+        let status = scheduler.status().unwrap();
+        assert!(status.enabled);
+        assert!(status.backups.is_empty());
+
+        let first = scheduler.backup(&path, &secret).unwrap();
+        assert!(first.size > 0);
+        let second = scheduler.backup(&path, &secret).unwrap();
+        assert!(second.date > first.date);
+
+        //both backups are on the same day, only the most recent one is kept
+        let status = scheduler.status().unwrap();
+        assert_eq!(1, status.backups.len());
+        assert_eq!(second.path, status.backups[0].path);
+        assert_eq!(Some(second.date + 24 * 3600 * 1000), status.next_backup);
+        assert!(status.last_error.is_none());
+
+        let backup = create_connection(&PathBuf::from(&second.path), &secret, 1024, false).unwrap();
+        let value: String = backup
+            .query_row("SELECT value FROM test", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!("hello", value);
+
+        //the backup is encrypted
+        let other = create_connection(&PathBuf::from(&second.path), &random32(), 1024, false);
+        assert!(
+            other.is_err()
+                || other
+                    .unwrap()
+                    .query_row("SELECT value FROM test", [], |row| row.get::<_, String>(0))
+                    .is_err()
+        );
+
+        let disabled = BackupScheduler::new(&Configuration::default());
+        assert!(disabled.backup(&path, &secret).is_err());
+        assert!(!disabled.status().unwrap().enabled);
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use rusqlite::{params_from_iter, Connection, OptionalExtension, ToSql};
+use tokio::sync::mpsc;
+
+use crate::security::{base64_encode, hash, Uid};
+
+use super::{node::Node, Result, VEC_OVERHEAD};
+
+///
+/// binaries smaller than this size are kept in the _node table
+///
+pub const BINARY_DEDUPLICATION_SIZE: usize = 1024;
+
+///
+/// SQL expression returning the binary of a node, whether it is stored inline or in the _binary_store table
+///
+/// the columns are not qualified and are resolved to the _node table in scope, it can be used with concat!()
+///
+macro_rules! binary_column {
+    () => {
+        "ifnull(_binary, (SELECT data FROM _binary_store WHERE hash = _binary_hash))"
+    };
+}
+pub(crate) use binary_column;
+
+//hash, data
+pub type StoredBinary = (Vec<u8>, Vec<u8>);
+
+//values of the _binary and _binary_hash columns
+type BinaryColumns<'a> = (Option<&'a Vec<u8>>, Option<Vec<u8>>);
+
+///
+/// same as binary_column!() for the queries that needs to qualify the _node table
+///
+pub fn binary_expression(table: &str) -> String {
+    format!(
+        "ifnull({0}._binary, (SELECT data FROM _binary_store WHERE hash = {0}._binary_hash))",
+        table
+    )
+}
+
+///
+/// Content addressed storage of the large binary payloads
+///
+/// Identical binaries stored by several nodes are stored once, indexed by their hash.
+/// The _binary_hash column of the _node table references the stored binary and the _binary column is left empty.
+///
+/// ref_count is maintained by triggers on the _node table and the binary is deleted when it is not referenced anymore.
+///
+/// The node signature is computed on the binary content, the storage is invisible outside of the local database.
+///
+pub struct BinaryStore {}
+impl BinaryStore {
+    ///
+    /// Creates the table, the triggers and the _binary_hash column of databases created before the store existed
+    ///
+    pub fn create_tables(conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        conn.execute(
+            "
+            CREATE TABLE IF NOT EXISTS _binary_store (
+                hash BLOB PRIMARY KEY,
+                data BLOB NOT NULL,
+                ref_count INTEGER NOT NULL
+            ) WITHOUT ROWID, STRICT",
+            [],
+        )?;
+
+        let has_column: Option<i64> = conn
+            .query_row(
+                "SELECT 1 FROM pragma_table_info('_node') WHERE name = '_binary_hash'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if has_column.is_none() {
+            conn.execute("ALTER TABLE _node ADD COLUMN _binary_hash BLOB", [])?;
+        }
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS _node_binary_hash ON _node (_binary_hash) WHERE _binary_hash IS NOT NULL",
+            [],
+        )?;
+
+        conn.execute(
+            "
+            CREATE TRIGGER IF NOT EXISTS \"_binary_store$insert\" AFTER INSERT ON _node
+            WHEN new._binary_hash IS NOT NULL
+            BEGIN
+                UPDATE _binary_store SET ref_count = ref_count + 1 WHERE hash = new._binary_hash;
+            END",
+            [],
+        )?;
+
+        conn.execute(
+            "
+            CREATE TRIGGER IF NOT EXISTS \"_binary_store$delete\" AFTER DELETE ON _node
+            WHEN old._binary_hash IS NOT NULL
+            BEGIN
+                UPDATE _binary_store SET ref_count = ref_count - 1 WHERE hash = old._binary_hash;
+                DELETE FROM _binary_store WHERE hash = old._binary_hash AND ref_count <= 0;
+            END",
+            [],
+        )?;
+
+        conn.execute(
+            "
+            CREATE TRIGGER IF NOT EXISTS \"_binary_store$update\" AFTER UPDATE OF _binary_hash ON _node
+            BEGIN
+                UPDATE _binary_store SET ref_count = ref_count + 1 WHERE hash = new._binary_hash;
+                UPDATE _binary_store SET ref_count = ref_count - 1 WHERE hash = old._binary_hash;
+                DELETE FROM _binary_store WHERE hash = old._binary_hash AND ref_count <= 0;
+            END",
+            [],
+        )?;
+
+        if has_column.is_none() {
+            Self::migrate(conn)?;
+        }
+        Ok(())
+    }
+
+    //
+    // moves the large binaries of an existing database to the store
+    //
+    fn migrate(conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        let mut stmt = conn.prepare(
+            "SELECT rowid, _binary FROM _node WHERE _binary IS NOT NULL AND length(_binary) > ?",
+        )?;
+        let rows = stmt
+            .query_map([BINARY_DEDUPLICATION_SIZE], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let mut update_stmt =
+            conn.prepare("UPDATE _node SET _binary = NULL, _binary_hash = ? WHERE rowid = ?")?;
+        for (rowid, binary) in rows {
+            let (_, binary_hash) = Self::store(&Some(binary), conn)?;
+            update_stmt.execute((binary_hash, rowid))?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// stores a large binary in the store, must be called before writing the node
+    ///
+    /// returns the values of the _binary and _binary_hash columns
+    ///
+    pub fn store<'a>(
+        binary: &'a Option<Vec<u8>>,
+        conn: &Connection,
+    ) -> std::result::Result<BinaryColumns<'a>, rusqlite::Error> {
+        match binary {
+            Some(data) if data.len() > BINARY_DEDUPLICATION_SIZE => {
+                let binary_hash = hash(data).to_vec();
+                let mut stmt = conn.prepare_cached(
+                    "INSERT INTO _binary_store (hash, data, ref_count) VALUES (?, ?, 0) ON CONFLICT DO NOTHING",
+                )?;
+                stmt.execute((&binary_hash, data))?;
+                Ok((None, Some(binary_hash)))
+            }
+            _ => Ok((binary.as_ref(), None)),
+        }
+    }
+
+    ///
+    /// rebuilds the reference counts from the _node table and removes the unused binaries
+    ///
+    pub fn recount(conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        conn.execute(
+            "UPDATE _binary_store SET ref_count = (SELECT count(1) FROM _node WHERE _node._binary_hash = _binary_store.hash)",
+            [],
+        )?;
+        conn.execute("DELETE FROM _binary_store WHERE ref_count <= 0", [])?;
+        Ok(())
+    }
+
+    ///
+    /// retrieve the locally stored binaries
+    ///
+    pub fn get(
+        hashes: &[Vec<u8>],
+        conn: &Connection,
+    ) -> std::result::Result<HashMap<Vec<u8>, Vec<u8>>, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached("SELECT data FROM _binary_store WHERE hash = ?")?;
+        let mut result = HashMap::new();
+        for binary_hash in hashes {
+            let data: Option<Vec<u8>> =
+                stmt.query_row([binary_hash], |row| row.get(0)).optional()?;
+            if let Some(data) = data {
+                result.insert(binary_hash.clone(), data);
+            }
+        }
+        Ok(result)
+    }
+
+    ///
+    /// retrieve the binaries requested by a peer
+    ///
+    /// only the binaries used by a node of the room that the reader can read are returned.
+    /// knowing the hash of a binary requires to be able to read one of the node that uses it
+    ///
+    pub fn filtered_by_room(
+        room_id: &Uid,
+        hashes: Vec<Vec<u8>>,
+        reader: &[u8],
+        batch_size: usize,
+        sender: &mpsc::Sender<Result<Vec<StoredBinary>>>,
+        conn: &Connection,
+    ) -> Result<()> {
+        let it = &mut hashes.iter().peekable();
+        let mut q = String::new();
+        while it.next().is_some() {
+            q.push('?');
+            if it.peek().is_some() {
+                q.push(',');
+            }
+        }
+        let query = format!(
+            "
+        SELECT hash, data
+        FROM _binary_store
+        WHERE
+            hash in ({}) AND
+            EXISTS (
+                SELECT 1 FROM _node
+                WHERE _node._binary_hash = _binary_store.hash AND room_id = ?{} AND {}
+            )
+        ",
+            q,
+            hashes.len() + 1,
+            Node::readable_by_condition("_node", hashes.len() + 2)
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let encoded_reader = base64_encode(reader);
+        let mut params: Vec<&dyn ToSql> = hashes.iter().map(|h| h as &dyn ToSql).collect();
+        params.push(room_id);
+        params.push(&reader);
+        params.push(&encoded_reader);
+        let mut rows = stmt.query(params_from_iter(params.iter()))?;
+
+        let mut len = 0;
+        let mut res = Vec::new();
+        while let Some(row) = rows.next()? {
+            let binary_hash: Vec<u8> = row.get(0)?;
+            let data: Vec<u8> = row.get(1)?;
+            let insert_len = len + (binary_hash.len() + data.len()) as u64 + 2 * VEC_OVERHEAD;
+            if insert_len > batch_size as u64 && !res.is_empty() {
+                let ready = res;
+                res = Vec::new();
+                len = 0;
+                if sender.blocking_send(Ok(ready)).is_err() {
+                    break;
+                }
+            } else {
+                len = insert_len;
+            }
+            res.push((binary_hash, data));
+        }
+        if !res.is_empty() {
+            let _ = sender.blocking_send(Ok(res));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{node::Node, sqlite_database::prepare_connection};
+
+    fn count(conn: &Connection) -> (i64, i64) {
+        conn.query_row(
+            "SELECT count(1), ifnull(sum(ref_count), 0) FROM _binary_store",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn deduplicate_binaries() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let large = vec![7u8; BINARY_DEDUPLICATION_SIZE + 1];
+        let mut first = Node {
+            _entity: "a".to_string(),
+            _binary: Some(large.clone()),
+            ..Default::default()
+        };
+        first.write(&conn, false, &None, &None).unwrap();
+        let mut second = Node {
+            _entity: "a".to_string(),
+            _binary: Some(large.clone()),
+            ..Default::default()
+        };
+        second.write(&conn, false, &None, &None).unwrap();
+        let mut small = Node {
+            _entity: "a".to_string(),
+            _binary: Some(vec![1, 2, 3]),
+            ..Default::default()
+        };
+        small.write(&conn, false, &None, &None).unwrap();
+
+        //stored once and referenced twice
+        assert_eq!((1, 2), count(&conn));
+        let inline: i64 = conn
+            .query_row(
+                "SELECT count(1) FROM _node WHERE _binary IS NOT NULL",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(1, inline);
+
+        let node = Node::get_with_entity(&second.id, "a", &conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(&large, node._binary.as_ref().unwrap());
+
+        let found = BinaryStore::get(&[hash(&large).to_vec(), vec![1]], &conn).unwrap();
+        assert_eq!(1, found.len());
+
+        //replacing the binary releases the reference
+        let mut second = *node;
+        second._binary = Some(vec![8u8; BINARY_DEDUPLICATION_SIZE + 1]);
+        second.write(&conn, false, &None, &None).unwrap();
+        assert_eq!((2, 2), count(&conn));
+
+        Node::delete(&first.id, &conn).unwrap();
+        assert_eq!((1, 1), count(&conn));
+        Node::delete(&second.id, &conn).unwrap();
+        assert_eq!((0, 0), count(&conn));
+
+        BinaryStore::recount(&conn).unwrap();
+        assert_eq!((0, 0), count(&conn));
+    }
+}
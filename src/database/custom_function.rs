@@ -0,0 +1,147 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use rusqlite::{
+    functions::{Context, FunctionFlags},
+    types::{ToSqlOutput, Value as SqlValue, ValueRef},
+    Connection,
+};
+use serde_json::{Number, Value};
+
+use crate::security::base64_encode;
+
+//prefix of the SQL function name, avoids any conflict with the SQLite built-in functions
+const SQL_PREFIX: &str = "_app_";
+
+//names used by the query language built-in functions
+const RESERVED: [&str; 7] = [
+    "avg",
+    "count",
+    "format_date",
+    "max",
+    "min",
+    "snippet",
+    "sum",
+];
+
+type CustomFunction = Arc<dyn Fn(&[Value]) -> Value + Send + Sync>;
+
+struct Registered {
+    arguments: usize,
+    function: CustomFunction,
+}
+
+lazy_static::lazy_static! {
+    static ref FUNCTIONS: RwLock<HashMap<String, Registered>> = RwLock::new(HashMap::new());
+}
+
+///
+/// Registers an application defined function that can be used in the query selections:
+///
+/// ```ignore
+/// register_function("distance", 4, |args| { ... })?;
+///
+/// query {
+///     geo.Place(dist < $max, order_by(dist asc)) {
+///         name
+///         dist: distance(lat, lon, $lat, $lon)
+///     }
+/// }
+/// ```
+/// The arguments are fields of the entity, variables or literal values, and the result can be used in the filters and the order_by like any other selected field.
+///
+/// The function must be pure and deterministic: the same arguments must always return the same result.
+///
+/// Functions are shared by every Discret instance of the process and must be registered before starting Discret,
+/// as they are added to the database connections when they are opened.
+///
+pub fn register_function<F>(name: &str, arguments: usize, function: F) -> Result<(), crate::Error>
+where
+    F: Fn(&[Value]) -> Value + Send + Sync + 'static,
+{
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(crate::Error::InvalidFunction(format!(
+            "'{}' is not a valid function name",
+            name
+        )));
+    }
+    if RESERVED.contains(&name.to_lowercase().as_str()) {
+        return Err(crate::Error::InvalidFunction(format!(
+            "'{}' is a reserved function name",
+            name
+        )));
+    }
+    FUNCTIONS.write().unwrap().insert(
+        name.to_string(),
+        Registered {
+            arguments,
+            function: Arc::new(function),
+        },
+    );
+    Ok(())
+}
+
+///
+/// number of arguments of a registered function
+///
+pub fn function_arguments(name: &str) -> Option<usize> {
+    FUNCTIONS.read().unwrap().get(name).map(|f| f.arguments)
+}
+
+///
+/// name of the function in the SQL queries
+///
+pub fn sql_name(name: &str) -> String {
+    format!("{}{}", SQL_PREFIX, name)
+}
+
+///
+/// adds the registered functions to the connection
+///
+pub fn add_custom_functions(conn: &Connection) -> rusqlite::Result<()> {
+    let functions = FUNCTIONS.read().unwrap();
+    for (name, registered) in functions.iter() {
+        let function = registered.function.clone();
+        conn.create_scalar_function(
+            &sql_name(name),
+            registered.arguments as i32,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            move |ctx| {
+                let args = arguments(ctx)?;
+                Ok(to_sql(function(&args)))
+            },
+        )?;
+    }
+    Ok(())
+}
+
+fn arguments(ctx: &Context) -> rusqlite::Result<Vec<Value>> {
+    let mut args = Vec::with_capacity(ctx.len());
+    for i in 0..ctx.len() {
+        let value = match ctx.get_raw(i) {
+            ValueRef::Null => Value::Null,
+            ValueRef::Integer(i) => Value::Number(i.into()),
+            ValueRef::Real(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+            ValueRef::Text(t) => Value::String(String::from_utf8_lossy(t).to_string()),
+            ValueRef::Blob(b) => Value::String(base64_encode(b)),
+        };
+        args.push(value);
+    }
+    Ok(args)
+}
+
+fn to_sql(value: Value) -> ToSqlOutput<'static> {
+    let value = match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => SqlValue::Text(s),
+        value => SqlValue::Text(value.to_string()),
+    };
+    ToSqlOutput::Owned(value)
+}
@@ -6,10 +6,13 @@ use tokio::sync::mpsc;
 
 use crate::{
     date_utils::{date, date_next_day},
-    security::Uid,
+    security::{base64_encode, Uid},
 };
 
-use super::{sqlite_database::Writeable, VEC_OVERHEAD};
+use super::{
+    log_retention::LogPruning, node::Node, sqlite_database::Writeable,
+    system_entities::READERS_FIELD, VEC_OVERHEAD,
+};
 
 ///
 /// Stores the modified dates for each rooms during the batch insert.
@@ -63,6 +66,73 @@ impl DailyMutations {
         }
         Ok(())
     }
+
+    ///
+    /// Finds the rows that are more recent than the last daily log of their room and entity
+    /// and flags the impacted days for recompute.
+    ///
+    /// Rows and daily log flags are written in the same transaction,
+    /// this catches the rows that were written without updating the daily log, like an interrupted write from an older version.
+    ///
+    pub fn reconcile(conn: &Connection) -> std::result::Result<Self, rusqlite::Error> {
+        let mut daily_mutations = Self::default();
+
+        let mut last_log_stmt = conn.prepare_cached(
+            "SELECT room_id, entity, max(date) FROM _daily_log GROUP BY room_id, entity",
+        )?;
+
+        let mut unlogged_stmt = conn.prepare_cached(
+            "
+                SELECT mdate 
+                FROM _node 
+                WHERE 
+                    _entity = ?2 AND
+                    room_id = ?1 AND 
+                    mdate >= ?3
+
+                UNION ALL
+                SELECT deletion_date
+                FROM _node_deletion_log 
+                WHERE 
+                    room_id = ?1 AND
+                    entity = ?2 AND
+                    deletion_date >= ?3
+
+                UNION ALL
+                SELECT deletion_date 
+                FROM _edge_deletion_log 
+                WHERE 
+                    room_id = ?1 AND 
+                    src_entity = ?2 AND
+                    deletion_date >= ?3
+            ",
+        )?;
+
+        let mut rows = last_log_stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let room_id: Uid = row.get(0)?;
+            let entity: String = row.get(1)?;
+            let last_date: i64 = row.get(2)?;
+
+            let mut unlogged =
+                unlogged_stmt.query((&room_id, &entity, date_next_day(last_date)))?;
+            while let Some(unlogged_row) = unlogged.next()? {
+                let mdate: i64 = unlogged_row.get(0)?;
+                daily_mutations.set_need_update(room_id, &entity, mdate);
+            }
+        }
+        Ok(daily_mutations)
+    }
+}
+
+///
+/// Startup pass that flags the daily logs that do not reflect the written rows
+///
+pub struct DailyLogReconciliation {}
+impl Writeable for DailyLogReconciliation {
+    fn write(&mut self, conn: &rusqlite::Connection) -> std::result::Result<(), rusqlite::Error> {
+        DailyMutations::reconcile(conn)?.write(conn)
+    }
 }
 
 #[derive(Default, Debug, Clone)]
@@ -286,6 +356,18 @@ pub struct DailyLog {
     pub need_recompute: bool,
 }
 impl DailyLog {
+    ///
+    /// coarsen the number of entries before sending the log to a low trust peer
+    ///
+    pub fn blur(&mut self, bucket: u32, key: &[u8; 32]) {
+        let noise = LogNoise {
+            room_id: &self.room_id,
+            entity: &self.entity,
+            date: self.date,
+        };
+        self.entry_number = blur_count(self.entry_number, bucket, key, noise);
+    }
+
     pub fn create_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
         conn.execute(
             "CREATE TABLE _daily_log (
@@ -318,10 +400,11 @@ impl DailyLog {
     }
 
     ///
-    /// Get the daily log for a room
+    /// Get the daily log for a room, as seen by the reader when provided
     ///
     pub fn get_room_log(
         room_id: &Uid,
+        reader: Option<&[u8]>,
         batch_size: usize,
         sender: &mpsc::Sender<Result<Vec<Self>, super::Error>>,
         conn: &Connection,
@@ -340,11 +423,15 @@ impl DailyLog {
             ORDER BY date, entity ASC
             ",
         )?;
+        let view = match reader {
+            Some(reader) => Some(ReaderView::build(room_id, reader, conn)?),
+            None => None,
+        };
         let mut rows = stmt.query([room_id])?;
         let mut res = Vec::new();
         let mut len = 0;
         while let Some(row) = rows.next()? {
-            let log = Self {
+            let mut log = Self {
                 room_id: row.get(0)?,
                 entity: row.get(1)?,
                 date: row.get(2)?,
@@ -353,6 +440,11 @@ impl DailyLog {
                 history_hash: row.get(5)?,
                 need_recompute: row.get(6)?,
             };
+            if let Some(view) = &view {
+                if !view.apply(&mut log) {
+                    continue;
+                }
+            }
             let size = bincode::serialized_size(&log)?;
             let insert_len = len + size + VEC_OVERHEAD;
             if insert_len > batch_size as u64 {
@@ -376,11 +468,12 @@ impl DailyLog {
     }
 
     ///
-    /// Get the daily log for a room at a specific date
+    /// Get the daily log for a room at a specific date, as seen by the reader when provided
     ///
     pub fn get_room_log_at(
         room_id: &Uid,
         date: i64,
+        reader: Option<&[u8]>,
         conn: &Connection,
     ) -> Result<Vec<Self>, rusqlite::Error> {
         let mut stmt = conn.prepare_cached(
@@ -399,10 +492,14 @@ impl DailyLog {
             ORDER BY date, entity ASC
             ",
         )?;
+        let view = match reader {
+            Some(reader) => Some(ReaderView::build(room_id, reader, conn)?),
+            None => None,
+        };
         let mut rows = stmt.query((room_id, date))?;
         let mut res = Vec::new();
         while let Some(row) = rows.next()? {
-            res.push(Self {
+            let mut log = Self {
                 room_id: row.get(0)?,
                 entity: row.get(1)?,
                 date: row.get(2)?,
@@ -410,7 +507,13 @@ impl DailyLog {
                 daily_hash: row.get(4)?,
                 history_hash: row.get(5)?,
                 need_recompute: row.get(6)?,
-            });
+            };
+            if let Some(view) = &view {
+                if !view.apply(&mut log) {
+                    continue;
+                }
+            }
+            res.push(log);
         }
         Ok(res)
     }
@@ -487,10 +590,7 @@ impl DailyLog {
             let mut rows = stmt.query(params_from_iter(ids.iter()))?;
             while let Some(row) = rows.next()? {
                 let date: Option<i64> = row.get(1)?;
-                let date = match date {
-                    Some(date) => date,
-                    None => i64::MAX, //null values will be push at the end of the list
-                };
+                let date = date.unwrap_or(i64::MAX);
                 id_date_list.push((row.get(0)?, date));
             }
         }
@@ -575,6 +675,138 @@ impl Writeable for RoomChangelog {
     }
 }
 
+//(entity, date) -> (entry_number, daily_hash)
+type ReaderDays = HashMap<(String, i64), (u32, Option<Vec<u8>>)>;
+
+///
+/// The daily log of a room as seen by a remote peer.
+///
+/// Nodes restricted with the sys_readers field are only sent to their readers:
+/// the days containing nodes that the peer cannot read are hashed without those nodes, allowing the logs of both peers to converge.
+/// The history hashes are computed over the complete log and are removed from the first modified day onward,
+/// forcing the comparison of the daily hashes.
+///
+pub struct ReaderView {
+    days: ReaderDays,
+    first_date: Option<i64>,
+}
+impl ReaderView {
+    pub fn build(room_id: &Uid, reader: &[u8], conn: &Connection) -> Result<Self, rusqlite::Error> {
+        let encoded_reader = base64_encode(reader);
+        let query = format!(
+            "SELECT _entity, mdate FROM _node
+            WHERE
+                room_id = ?1 AND
+                instr(_json, '{}') > 0 AND
+                NOT ({})",
+            READERS_FIELD,
+            Node::readable_by_condition("_node", 2)
+        );
+        let mut stmt = conn.prepare_cached(&query)?;
+        let mut rows = stmt.query((room_id, reader, &encoded_reader))?;
+        let mut restricted: HashSet<(String, i64)> = HashSet::new();
+        while let Some(row) = rows.next()? {
+            let entity: String = row.get(0)?;
+            let mdate: i64 = row.get(1)?;
+            restricted.insert((entity, date(mdate)));
+        }
+
+        //same as DailyLogsUpdate::compute(), without the nodes that the reader cannot read
+        let query = format!(
+            "
+                SELECT signature
+                FROM _node_deletion_log 
+                WHERE 
+                    room_id = ?1 AND
+                    entity = ?2 AND
+                    deletion_date >= ?3 AND deletion_date < ?4 
+                
+                UNION ALL
+                SELECT signature 
+                FROM _edge_deletion_log 
+                WHERE 
+                    room_id = ?1 AND 
+                    src_entity = ?2 AND
+                    deletion_date >= ?3 AND deletion_date < ?4 
+                
+                UNION ALL
+                SELECT _signature as signature
+                FROM _node 
+                WHERE
+                    room_id = ?1 AND
+                    _entity = ?2 AND 
+                    mdate >= ?3 AND mdate < ?4 AND
+                    {}
+                    
+                ORDER by signature
+            ",
+            Node::readable_by_condition("_node", 5)
+        );
+        let mut compute_stmt = conn.prepare_cached(&query)?;
+
+        let mut days = ReaderDays::with_capacity(restricted.len());
+        let mut first_date: Option<i64> = None;
+        for (entity, day) in restricted {
+            let mut rows = compute_stmt.query((
+                room_id,
+                &entity,
+                day,
+                date_next_day(day),
+                reader,
+                &encoded_reader,
+            ))?;
+            let mut entry_number: u32 = 0;
+            let mut hasher = blake3::Hasher::new();
+            while let Some(row) = rows.next()? {
+                let signature: Vec<u8> = row.get(0)?;
+                hasher.update(&signature);
+                entry_number += 1;
+            }
+            let daily_hash = if entry_number == 0 {
+                None
+            } else {
+                Some(hasher.finalize().as_bytes().to_vec())
+            };
+            first_date = Some(first_date.map_or(day, |first| first.min(day)));
+            days.insert((entity, day), (entry_number, daily_hash));
+        }
+        Ok(Self { days, first_date })
+    }
+
+    ///
+    /// returns false when the day does not contain any entry readable by the reader
+    ///
+    pub fn apply(&self, log: &mut DailyLog) -> bool {
+        if self.first_date.is_some_and(|first| log.date >= first) {
+            log.history_hash = None;
+        }
+        match self.days.get(&(log.entity.clone(), log.date)) {
+            Some((entry_number, daily_hash)) => {
+                log.entry_number = *entry_number;
+                log.daily_hash.clone_from(daily_hash);
+                *entry_number > 0
+            }
+            None => true,
+        }
+    }
+
+    ///
+    /// the room definition does not tell which entity its daily hash belongs to,
+    /// it is removed when the last day is modified
+    ///
+    pub fn apply_definition(&self, definition: &mut RoomDefinitionLog) {
+        if self.first_date.is_none() {
+            return;
+        }
+        definition.history_hash = None;
+        if let Some(last) = definition.last_data_date {
+            if self.days.keys().any(|(_, day)| *day == last) {
+                definition.daily_hash = None;
+            }
+        }
+    }
+}
+
 ///
 /// Used to transmit in one packet
 ///  - The room modification date to check whether the room defintion needs to be synchronized
@@ -588,10 +820,16 @@ pub struct RoomDefinitionLog {
     pub entry_number: Option<u32>,
     pub daily_hash: Option<Vec<u8>>,
     pub history_hash: Option<Vec<u8>>,
+    //the deletion logs older than this date have been pruned, 0 if never pruned
+    pub log_horizon: i64,
 }
 impl RoomDefinitionLog {
+    ///
+    /// the last daily log is the one seen by the reader when provided, see *ReaderView*
+    ///
     pub fn get(
         room_id: &Uid,
+        reader: Option<&[u8]>,
         conn: &Connection,
     ) -> Result<Option<RoomDefinitionLog>, rusqlite::Error> {
         let query = "
@@ -618,19 +856,73 @@ impl RoomDefinitionLog {
         let mut stmt = conn.prepare(query)?;
         let mut rows = stmt.query([&room_id])?;
         let res = if let Some(row) = rows.next()? {
-            Some(RoomDefinitionLog {
+            let mut definition = RoomDefinitionLog {
                 room_id: row.get(0)?,
                 room_def_date: row.get(1)?,
                 last_data_date: row.get(2)?,
                 entry_number: row.get(3)?,
                 daily_hash: row.get(4)?,
                 history_hash: row.get(5)?,
-            })
+                log_horizon: LogPruning::horizon(conn)?,
+            };
+            if let Some(reader) = reader {
+                ReaderView::build(room_id, reader, conn)?.apply_definition(&mut definition);
+            }
+            Some(definition)
         } else {
             None
         };
         Ok(res)
     }
+
+    ///
+    /// coarsen the number of entries before sending the definition to a low trust peer
+    ///
+    pub fn blur(&mut self, bucket: u32, key: &[u8; 32]) {
+        let noise = LogNoise {
+            room_id: &self.room_id,
+            entity: "",
+            date: self.last_data_date.unwrap_or(0),
+        };
+        self.entry_number = self.entry_number.map(|n| blur_count(n, bucket, key, noise));
+    }
+}
+
+///
+/// The log entry whose count is blurred, the noise is derived from it
+///
+#[derive(Clone, Copy)]
+pub struct LogNoise<'a> {
+    pub room_id: &'a Uid,
+    pub entity: &'a str,
+    pub date: i64,
+}
+
+///
+/// Hides the exact number of daily entries:
+/// a noise lower than the bucket size is added and the result is rounded up to a multiple of the bucket.
+///
+/// The noise is a keyed hash of the log entry and of its count, the key being a local secret.
+/// The same count is always blurred the same way: asking for a log many times and averaging the answers reveals nothing more.
+///
+/// A non empty day always stays non empty. The synchronisation only relies on the dates and hashes, which are left untouched.
+///
+pub fn blur_count(count: u32, bucket: u32, key: &[u8; 32], log: LogNoise) -> u32 {
+    if bucket <= 1 || count == 0 {
+        return count;
+    }
+    let mut hasher = blake3::Hasher::new_keyed(key);
+    hasher.update(log.room_id);
+    hasher.update(&log.date.to_le_bytes());
+    hasher.update(&count.to_le_bytes());
+    hasher.update(log.entity.as_bytes());
+    let hash = hasher.finalize();
+    let mut noise = [0; 8];
+    noise.copy_from_slice(&hash.as_bytes()[0..8]);
+    let noise = (u64::from_le_bytes(noise) % bucket as u64) as u32;
+
+    let noisy = count.saturating_add(noise);
+    noisy.div_ceil(bucket).saturating_mul(bucket)
 }
 #[cfg(test)]
 mod tests {
@@ -642,13 +934,15 @@ mod tests {
     use crate::{
         configuration::Configuration,
         database::{
+            edge::Edge,
             graph_database::GraphDatabaseService,
+            node::{Node, NodeDeletionEntry},
             query_language::parameter::{Parameters, ParametersAdd},
             Error,
         },
         date_utils::{date, now},
         event_service::EventService,
-        security::{base64_encode, default_uid, new_uid, random32},
+        security::{base64_encode, default_uid, new_uid, random32, Ed25519SigningKey},
     };
 
     use super::*;
@@ -681,7 +975,7 @@ mod tests {
 
         let (app, verifying_key, _) = GraphDatabaseService::start(
             "delete app",
-            &data_model,
+            data_model,
             &secret,
             &random32(),
             path,
@@ -692,12 +986,9 @@ mod tests {
         .unwrap();
         //receive daily_log event done during startup
         while let Ok(e) = events.recv().await {
-            match e {
-                crate::event_service::Event::DataChanged(log) => {
-                    assert_eq!(0, log.rooms.len());
-                    break;
-                }
-                _ => {}
+            if let crate::event_service::Event::DataChanged(log) = e {
+                assert_eq!(0, log.rooms.len());
+                break;
             }
         }
         let user_id = base64_encode(&verifying_key);
@@ -732,12 +1023,9 @@ mod tests {
 
         //receive daily_log event
         while let Ok(e) = events.recv().await {
-            match e {
-                crate::event_service::Event::DataChanged(log) => {
-                    assert_eq!(0, log.rooms.len());
-                    break;
-                }
-                _ => {}
+            if let crate::event_service::Event::DataChanged(log) = e {
+                assert_eq!(0, log.rooms.len());
+                break;
             }
         }
 
@@ -763,34 +1051,144 @@ mod tests {
 
         //receive daily_log event
         while let Ok(e) = events.recv().await {
-            match e {
-                crate::event_service::Event::DataChanged(log) => {
-                    let dates = log.rooms.get(&room_id).unwrap();
-                    assert_eq!(1, dates.len());
+            if let crate::event_service::Event::DataChanged(log) = e {
+                let dates = log.rooms.get(&room_id).unwrap();
+                assert_eq!(1, dates.len());
 
-                    break;
-                }
-
-                _ => {}
+                break;
             }
         }
 
-        let mut room_log_receiv = app.get_room_log(bin_room_id.clone()).await;
+        let mut room_log_receiv = app.get_room_log(*bin_room_id, None).await;
         let room_log = room_log_receiv.recv().await.unwrap().unwrap();
         assert_eq!(1, room_log.len());
         let rlog = &room_log[0];
         assert_eq!(date(now()), rlog.date);
         assert_eq!(4, rlog.entry_number);
+
+        //local writes computes the daily log in the same transaction,
+        //the log is up to date as soon as the write is done, even if the stream is still open
+        let (sender, mut receiver) = app.mutation_stream();
+        let mut param = Parameters::default();
+        param.add("room_id", room_id.clone()).unwrap();
+        sender
+            .send((
+                r#"mutate { Person {room_id:$room_id name:"Carol" } } "#.to_string(),
+                Some(param),
+            ))
+            .await
+            .unwrap();
+        receiver.recv().await.unwrap().unwrap();
+
+        let mut room_log_receiv = app.get_room_log(*bin_room_id, None).await;
+        let room_log = room_log_receiv.recv().await.unwrap().unwrap();
+        assert_eq!(5, room_log[0].entry_number);
+        drop(sender);
+    }
+
+    #[test]
+    fn reconcile_after_crash() {
+        let conn = Connection::open_in_memory().unwrap();
+        Node::create_tables(&conn).unwrap();
+        Edge::create_tables(&conn).unwrap();
+        DailyLog::create_tables(&conn).unwrap();
+
+        let signing_key = Ed25519SigningKey::new();
+        let room_id = new_uid();
+        let entity = "0.1".to_string();
+        let today = now();
+        let yesterday = date(today) - 1;
+
+        let write_node = |mdate: i64| {
+            let mut node = Node {
+                id: new_uid(),
+                room_id: Some(room_id),
+                cdate: mdate,
+                mdate,
+                _entity: entity.clone(),
+                ..Default::default()
+            };
+            node.sign(&signing_key).unwrap();
+            Writeable::write(&mut node, &conn).unwrap();
+            node
+        };
+        let flag = |mdate: i64| {
+            let mut daily_mutations = DailyMutations::new();
+            daily_mutations.set_need_update(room_id, &entity, mdate);
+            daily_mutations.write(&conn).unwrap();
+        };
+        let compute = || {
+            let mut update = DailyLogsUpdate::default();
+            update.compute(&conn).unwrap();
+        };
+        let entry_number = |day: i64| -> (u32, bool) {
+            conn.query_row(
+                "SELECT entry_number, need_recompute FROM _daily_log WHERE room_id = ? AND date = ?",
+                (&room_id, date(day)),
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap()
+        };
+
+        write_node(yesterday);
+        flag(yesterday);
+        compute();
+        assert_eq!((1, false), entry_number(yesterday));
+        assert!(DailyMutations::reconcile(&conn)
+            .unwrap()
+            .room_dates
+            .is_empty());
+
+        //crash after the rows are written, before the daily log is flagged
+        let node = write_node(today);
+        let mut deletion = NodeDeletionEntry::build(room_id, &node, today, &signing_key);
+        Writeable::write(&mut deletion, &conn).unwrap();
+
+        let reconciled = DailyMutations::reconcile(&conn).unwrap();
+        let dates = reconciled.room_dates.get(&room_id).unwrap();
+        assert_eq!(1, dates.get(&entity).unwrap().len());
+        reconciled.write(&conn).unwrap();
+        compute();
+        assert_eq!((2, false), entry_number(today));
+        assert_eq!((1, false), entry_number(yesterday));
+
+        //crash after the daily log is flagged, before it is computed
+        write_node(today);
+        flag(today);
+        assert_eq!((2, true), entry_number(today));
+        assert!(DailyMutations::reconcile(&conn)
+            .unwrap()
+            .room_dates
+            .is_empty());
+        compute();
+        assert_eq!((3, false), entry_number(today));
+
+        //crash during the computation, the transaction is rolled back
+        write_node(today);
+        flag(today);
+        conn.execute("BEGIN TRANSACTION", []).unwrap();
+        compute();
+        conn.execute("ROLLBACK", []).unwrap();
+        assert_eq!((3, true), entry_number(today));
+        assert!(DailyMutations::reconcile(&conn)
+            .unwrap()
+            .room_dates
+            .is_empty());
+        compute();
+        assert_eq!((4, false), entry_number(today));
     }
 
     #[tokio::test(flavor = "multi_thread")]
     async fn room_log() {
         let conn = Connection::open_in_memory().unwrap();
         DailyLog::create_tables(&conn).unwrap();
+        LogPruning::create_tables(&conn).unwrap();
         let room_id = new_uid();
         RoomChangelog::log_room_definition(&room_id, 100, &conn).unwrap();
 
-        let def = RoomDefinitionLog::get(&room_id, &conn).unwrap().unwrap();
+        let def = RoomDefinitionLog::get(&room_id, None, &conn)
+            .unwrap()
+            .unwrap();
         assert_eq!(def.room_id, room_id);
         assert_eq!(def.room_def_date, 100);
         assert_eq!(def.last_data_date, None);
@@ -799,7 +1197,7 @@ mod tests {
         assert_eq!(def.history_hash, None);
 
         let daily_log_1 = DailyLog {
-            room_id: room_id,
+            room_id,
             entity: "0.1".to_string(),
             date: 500,
             entry_number: 1,
@@ -810,7 +1208,7 @@ mod tests {
         daily_log_1.write(&conn).unwrap();
 
         let daily_log_0 = DailyLog {
-            room_id: room_id,
+            room_id,
             entity: "0.1".to_string(),
             date: 200,
             entry_number: 1,
@@ -820,7 +1218,9 @@ mod tests {
         };
         daily_log_0.write(&conn).unwrap();
 
-        let def = RoomDefinitionLog::get(&room_id, &conn).unwrap().unwrap();
+        let def = RoomDefinitionLog::get(&room_id, None, &conn)
+            .unwrap()
+            .unwrap();
         assert_eq!(def.room_id, room_id);
         assert_eq!(def.room_def_date, 100);
         assert_eq!(def.last_data_date.unwrap(), daily_log_1.date);
@@ -843,7 +1243,7 @@ mod tests {
             let date: i64 = OsRng.gen();
             RoomChangelog::log_room_definition(&room_id, 100, &conn).unwrap();
             let daily_log = DailyLog {
-                room_id: room_id,
+                room_id,
                 entity: "0.1".to_string(),
                 date,
                 entry_number: 1,
@@ -880,4 +1280,146 @@ mod tests {
 
         assert_eq!(empty_room_id, last_id);
     }
+
+    #[test]
+    fn blur() {
+        let key = random32();
+        let room_id = new_uid();
+        let noise = LogNoise {
+            room_id: &room_id,
+            entity: "Person",
+            date: date(now()),
+        };
+        assert_eq!(0, blur_count(0, 10, &key, noise));
+        assert_eq!(7, blur_count(7, 0, &key, noise));
+        assert_eq!(7, blur_count(7, 1, &key, noise));
+
+        //the same entry is always blurred the same way, the noise changes with the key and the entry
+        let blurred = blur_count(7, 10, &key, noise);
+        assert!(blurred == 10 || blurred == 20);
+        for _ in 0..100 {
+            assert_eq!(blurred, blur_count(7, 10, &key, noise));
+        }
+        let mut values = HashSet::new();
+        for _ in 0..100 {
+            let key = random32();
+            values.insert(blur_count(7, 10, &key, noise));
+        }
+        assert_eq!(values.len(), 2);
+        let mut values = HashSet::new();
+        for i in 0..100 {
+            let entity = format!("Entity{i}");
+            let noise = LogNoise {
+                entity: &entity,
+                ..noise
+            };
+            values.insert(blur_count(7, 10, &key, noise));
+        }
+        assert_eq!(values.len(), 2);
+
+        let mut log = DailyLog {
+            room_id: new_uid(),
+            date: date(now()),
+            entity: "Person".to_string(),
+            entry_number: 11,
+            daily_hash: Some(vec![1]),
+            history_hash: Some(vec![2]),
+            need_recompute: false,
+        };
+        log.blur(5, &key);
+        assert!(log.entry_number == 15 || log.entry_number == 20);
+        assert_eq!(Some(vec![1]), log.daily_hash);
+        assert_eq!(Some(vec![2]), log.history_hash);
+    }
+
+    #[test]
+    fn reader_view() {
+        use crate::database::sqlite_database::prepare_connection;
+        use crate::security::SigningKey;
+
+        let author = Ed25519SigningKey::new();
+        let reader = Ed25519SigningKey::new();
+        let other = Ed25519SigningKey::new();
+        let room_id = new_uid();
+        let entity = "0.1".to_string();
+        let mdate = now();
+
+        let mut shared = Node {
+            id: new_uid(),
+            room_id: Some(room_id),
+            cdate: mdate,
+            mdate,
+            _entity: entity.clone(),
+            ..Default::default()
+        };
+        shared.sign(&author).unwrap();
+        let mut restricted = Node {
+            _json: Some(format!(
+                r#"{{"{}":["{}"]}}"#,
+                READERS_FIELD,
+                base64_encode(&reader.export_verifying_key())
+            )),
+            id: new_uid(),
+            ..shared.clone()
+        };
+        restricted.sign(&author).unwrap();
+
+        let build_db = |nodes: Vec<Node>| {
+            let conn = Connection::open_in_memory().unwrap();
+            prepare_connection(&conn).unwrap();
+            let mut daily_mutations = DailyMutations::new();
+            for mut node in nodes {
+                node.write(&conn, false, &None, &None).unwrap();
+                daily_mutations.set_need_update(room_id, &entity, node.mdate);
+            }
+            daily_mutations.write(&conn).unwrap();
+            DailyLogsUpdate::default().compute(&conn).unwrap();
+            conn
+        };
+        let room_log = |reader: &[u8], conn: &Connection| {
+            let (sender, mut receiver) = mpsc::channel::<Result<Vec<DailyLog>, Error>>(10);
+            DailyLog::get_room_log(&room_id, Some(reader), 4096, &sender, conn).unwrap();
+            drop(sender);
+            let mut logs = Vec::new();
+            while let Some(log) = receiver.blocking_recv() {
+                logs.extend(log.unwrap());
+            }
+            logs
+        };
+
+        let author_db = build_db(vec![shared.clone(), restricted.clone()]);
+        let reader_db = build_db(vec![shared.clone(), restricted.clone()]);
+        let other_db = build_db(vec![shared.clone()]);
+
+        //the restricted node is not counted when comparing the logs with a peer that cannot read it
+        let author_log = room_log(&other.export_verifying_key(), &author_db);
+        let other_log = room_log(&author.export_verifying_key(), &other_db);
+        assert_eq!(1, author_log.len());
+        assert_eq!(1, author_log[0].entry_number);
+        assert_eq!(author_log[0].daily_hash, other_log[0].daily_hash);
+        assert_eq!(None, author_log[0].history_hash);
+
+        let stored = DailyLog::get_room_log_at(&room_id, date(mdate), None, &author_db).unwrap();
+        assert_eq!(2, stored[0].entry_number);
+        assert_ne!(stored[0].daily_hash, other_log[0].daily_hash);
+
+        //readers see the complete log
+        let author_log = room_log(&reader.export_verifying_key(), &author_db);
+        let reader_log = room_log(&author.export_verifying_key(), &reader_db);
+        assert_eq!(stored[0].daily_hash, author_log[0].daily_hash);
+        assert_eq!(stored[0].history_hash, author_log[0].history_hash);
+        assert_eq!(author_log[0].daily_hash, reader_log[0].daily_hash);
+
+        RoomChangelog::log_room_definition(&room_id, mdate, &author_db).unwrap();
+        let def = RoomDefinitionLog::get(&room_id, Some(&other.export_verifying_key()), &author_db)
+            .unwrap()
+            .unwrap();
+        assert_eq!(None, def.history_hash);
+        assert_eq!(None, def.daily_hash);
+        let def =
+            RoomDefinitionLog::get(&room_id, Some(&reader.export_verifying_key()), &author_db)
+                .unwrap()
+                .unwrap();
+        assert_eq!(stored[0].history_hash, def.history_hash);
+    }
 }
@@ -0,0 +1,192 @@
+use rusqlite::Connection;
+
+use super::{
+    binary_store::binary_column,
+    query_language::{
+        data_model_parser::{DataModel, Entity},
+        FieldType, ParamValue,
+    },
+    system_entities::{
+        BINARY_FIELD, CREATION_DATE_FIELD, ID_FIELD, MODIFICATION_DATE_FIELD, ROOM_ID_FIELD,
+        VERIFYING_KEY_FIELD,
+    },
+};
+
+///
+/// prefix of the views, the dots of the entity name are replaced by '$' like the index names
+///
+pub const VIEW_PREFIX: &str = "view$";
+
+///
+/// Developer helper enabled by the "debug_views" feature
+///
+/// Entities are stored in the _node table using short names for the entity and its fields.
+/// One view per entity maps those short names to the names defined in the data model,
+/// allowing to inspect the database file with a standard SQLite tool.
+///
+/// The views are recreated every time the data model is updated
+///
+pub fn create_views(conn: &Connection, data_model: &DataModel) -> Result<(), rusqlite::Error> {
+    let mut q = String::new();
+    {
+        let mut stmt = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type='view' AND name LIKE 'view$%'")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            q.push_str(&format!("DROP VIEW IF EXISTS \"{}\";\n", name));
+        }
+    }
+
+    for ns in data_model.namespaces() {
+        for entity in ns.1.values() {
+            if entity.deprecated {
+                continue;
+            }
+            q.push_str(&view_query(entity));
+            q.push_str(";\n");
+        }
+    }
+    conn.execute_batch(&q)
+}
+
+///
+/// Entity and Array fields are stored in the _edge table, they are displayed as a JSON array of hex encoded identifiers.
+/// Enum fields are stored using their position and are converted back to their value.
+///
+pub fn view_query(entity: &Entity) -> String {
+    let mut columns = vec![
+        ID_FIELD.to_string(),
+        ROOM_ID_FIELD.to_string(),
+        CREATION_DATE_FIELD.to_string(),
+        MODIFICATION_DATE_FIELD.to_string(),
+        VERIFYING_KEY_FIELD.to_string(),
+        format!("{} AS {}", binary_column!(), BINARY_FIELD),
+    ];
+
+    let mut fields: Vec<_> = entity
+        .fields
+        .values()
+        .filter(|f| !f.is_system && !f.deprecated)
+        .collect();
+    fields.sort_by_key(|f| f.short_name.parse::<usize>().unwrap_or(usize::MAX));
+
+    for field in fields {
+        let short = &field.short_name;
+        let column = match &field.field_type {
+            FieldType::Array(_) | FieldType::Entity(_) => format!(
+                "(SELECT json_group_array(hex(dest)) FROM _edge WHERE src=_node.id AND src_entity=_node._entity AND label='{}')",
+                short
+            ),
+            FieldType::Json | FieldType::Geo | FieldType::File => format!("_json->'$.{}'", short),
+            FieldType::Enum(values) => {
+                let mut case = format!("CASE _json->>'$.{}'", short);
+                for (i, value) in values.iter().enumerate() {
+                    case.push_str(&format!(" WHEN {} THEN '{}'", i, value.replace('\'', "''")));
+                }
+                case.push_str(" END");
+                case
+            }
+            FieldType::Boolean
+            | FieldType::Float
+            | FieldType::Base64
+            | FieldType::Integer
+            | FieldType::String
+            | FieldType::DateTime
+            | FieldType::Date => format!("_json->>'$.{}'", short),
+        };
+        //default values are not stored and are applied when reading
+        let column = match &field.default_value {
+            Some(default) => format!("ifnull({}, {})", column, literal(default)),
+            None => column,
+        };
+        columns.push(format!("{} AS \"{}\"", column, field.name));
+    }
+
+    format!(
+        "CREATE VIEW \"{}{}\" AS SELECT {} FROM _node WHERE _entity='{}'",
+        VIEW_PREFIX,
+        entity.name.replace('.', "$"),
+        columns.join(", "),
+        entity.short_name
+    )
+}
+
+fn literal(value: &ParamValue) -> String {
+    match value {
+        ParamValue::Boolean(b) => (*b as i64).to_string(),
+        ParamValue::Integer(i) => i.to_string(),
+        ParamValue::Float(f) => f.to_string(),
+        ParamValue::String(s) | ParamValue::Binary(s) => format!("'{}'", s.replace('\'', "''")),
+        ParamValue::Array(_) | ParamValue::Null => "null".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        mutation_query::MutationQuery,
+        query_language::{mutation_parser::MutationParser, parameter::Parameters},
+        sqlite_database::{prepare_connection, Writeable},
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn readable_views() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            ns {
+                Person {
+                    name : String,
+                    age : Integer,
+                    mood : Enum(\"happy\", \"sad\") default \"sad\",
+                    parents : [ns.Person],
+                }
+            }
+        ",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                ns.Person { name:"child" age:5 parents:[{name:"mother" age:30 mood:"happy"}] }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+        let mut param = Parameters::new();
+        let mut mutation_query =
+            MutationQuery::execute(&mut param, Arc::new(mutation), &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        create_views(&conn, &data_model).unwrap();
+        //views are replaced when the data model changes
+        create_views(&conn, &data_model).unwrap();
+
+        let rows: Vec<(String, i64, String, i64)> = conn
+            .prepare(
+                "SELECT name, age, mood, json_array_length(parents) FROM \"view$ns$Person\" ORDER BY age",
+            )
+            .unwrap()
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+
+        assert_eq!(
+            vec![
+                ("child".to_string(), 5, "sad".to_string(), 1),
+                ("mother".to_string(), 30, "happy".to_string(), 0)
+            ],
+            rows
+        );
+    }
+}
@@ -1,8 +1,9 @@
 use crate::{
     date_utils::now,
-    security::{uid_decode, Uid},
+    security::{uid_decode, uid_encode, Uid},
 };
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::Arc};
 
 use super::{
     daily_log::DailyMutations,
@@ -10,8 +11,20 @@ use super::{
     node::{Node, NodeDeletionEntry},
     query_language::{deletion_parser::DeletionParser, parameter::Parameters},
     sqlite_database::Writeable,
+    system_entities::DELETED_FIELD,
     Result,
 };
+
+///
+/// An entity removed by a deletion query
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeletedEntity {
+    pub id: String,
+    pub entity: String,
+    pub room_id: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct NodeDelete {
     pub node: Node,
@@ -29,6 +42,8 @@ pub struct EdgeDelete {
 #[derive(Debug)]
 pub struct DeletionQuery {
     pub nodes: Vec<NodeDelete>,
+    //nodes of the entities defining soft_delete, the updated version is in updated_nodes
+    pub soft_deleted: Vec<NodeDelete>,
     pub node_log: Vec<NodeDeletionEntry>,
     pub updated_nodes: Vec<Node>,
     pub edges: Vec<EdgeDelete>,
@@ -44,6 +59,7 @@ impl DeletionQuery {
         deletion.variables.validate_params(parameters)?;
         let mut deletion_query = Self {
             nodes: Vec::new(),
+            soft_deleted: Vec::new(),
             node_log: Vec::new(),
             updated_nodes: Vec::new(),
             edges: Vec::new(),
@@ -61,12 +77,7 @@ impl DeletionQuery {
             let node = Node::get_with_entity(&src, &del.short_name, conn)?;
             if let Some(node) = node {
                 if del.references.is_empty() {
-                    deletion_query.nodes.push(NodeDelete {
-                        node: *node,
-                        name: del.name.clone(),
-                        //  short_name: del.short_name.clone(),
-                        date,
-                    })
+                    deletion_query.remove_node(*node, del.name.clone(), date, del.soft_delete)?;
                 } else {
                     for edge_deletion in &del.references {
                         let dest = parameters
@@ -94,9 +105,156 @@ impl DeletionQuery {
                 }
             }
         }
+        if deletion.cascade {
+            deletion_query.cascade(&deletion, date, conn)?;
+        }
         Ok(deletion_query)
     }
 
+    //
+    // the nodes of the entities defining soft_delete are kept with a deletion date,
+    // deleting an allready soft deleted node removes it
+    //
+    fn remove_node(
+        &mut self,
+        node: Node,
+        name: String,
+        date: i64,
+        soft_delete: bool,
+    ) -> Result<()> {
+        if soft_delete && !is_soft_deleted(&node) {
+            let mut updated = node.clone();
+            let mut json: serde_json::Value = match &node._json {
+                Some(json) => serde_json::from_str(json)?,
+                None => serde_json::Value::Object(serde_json::Map::new()),
+            };
+            if let Some(map) = json.as_object_mut() {
+                map.insert(DELETED_FIELD.to_string(), serde_json::Value::from(date));
+            }
+            updated._json = Some(serde_json::to_string(&json)?);
+            updated.mdate = date;
+            self.updated_nodes.push(updated);
+            self.soft_deleted.push(NodeDelete { node, name, date });
+        } else {
+            self.nodes.push(NodeDelete { node, name, date });
+        }
+        Ok(())
+    }
+
+    //
+    // deletes the entities that are only referenced by the deleted entities and the removed references,
+    // until no entity becomes orphaned
+    //
+    fn cascade(
+        &mut self,
+        deletion: &DeletionParser,
+        date: i64,
+        conn: &rusqlite::Connection,
+    ) -> Result<()> {
+        let mut deleted: HashSet<Uid> = self
+            .nodes
+            .iter()
+            .chain(self.soft_deleted.iter())
+            .map(|n| n.node.id)
+            .collect();
+        let removed_edges: HashSet<(Uid, String, Uid)> = self
+            .edges
+            .iter()
+            .map(|e| (e.edge.src, e.edge.label.clone(), e.edge.dest))
+            .collect();
+
+        //(id, entity name, entity short name, soft delete) of the entities that might be orphaned
+        let mut candidates: Vec<(Uid, String, String, bool)> = Vec::new();
+        for edge in &self.edges {
+            if let Some(reference) = deletion
+                .cascade_references
+                .get(&edge.edge.src_entity)
+                .and_then(|refs| refs.iter().find(|r| r.label.eq(&edge.edge.label)))
+            {
+                candidates.push((
+                    edge.edge.dest,
+                    reference.dest_name.clone(),
+                    reference.dest_short_name.clone(),
+                    reference.dest_soft_delete,
+                ));
+            }
+        }
+
+        //(id, entity short name) of the entities deleted by the previous iteration
+        let mut new_nodes: Vec<(Uid, String)> = self
+            .nodes
+            .iter()
+            .chain(self.soft_deleted.iter())
+            .map(|n| (n.node.id, n.node._entity.clone()))
+            .collect();
+        loop {
+            for (node_id, entity) in &new_nodes {
+                if let Some(references) = deletion.cascade_references.get(entity) {
+                    for reference in references {
+                        for edge in Edge::get_edges(node_id, &reference.label, conn)? {
+                            candidates.push((
+                                edge.dest,
+                                reference.dest_name.clone(),
+                                reference.dest_short_name.clone(),
+                                reference.dest_soft_delete,
+                            ));
+                        }
+                    }
+                }
+            }
+            new_nodes.clear();
+
+            let mut remaining = Vec::new();
+            for (id, name, short_name, soft_delete) in candidates {
+                if deleted.contains(&id) {
+                    continue;
+                }
+                let mut stmt =
+                    conn.prepare_cached("SELECT src, label FROM _edge WHERE dest = ?")?;
+                let mut rows = stmt.query([&id])?;
+                let mut orphaned = true;
+                while let Some(row) = rows.next()? {
+                    let src: Uid = row.get(0)?;
+                    let label: String = row.get(1)?;
+                    if !deleted.contains(&src) && !removed_edges.contains(&(src, label, id)) {
+                        orphaned = false;
+                        break;
+                    }
+                }
+                if !orphaned {
+                    //might become orphaned when another referencing entity is deleted
+                    remaining.push((id, name, short_name, soft_delete));
+                    continue;
+                }
+                if let Some(node) = Node::get_with_entity(&id, &short_name, conn)? {
+                    deleted.insert(id);
+                    new_nodes.push((id, short_name));
+                    self.remove_node(*node, name, date, soft_delete)?;
+                }
+            }
+            candidates = remaining;
+            if new_nodes.is_empty() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// the entities removed by the deletion
+    ///
+    pub fn deleted(&self) -> Vec<DeletedEntity> {
+        self.nodes
+            .iter()
+            .chain(self.soft_deleted.iter())
+            .map(|n| DeletedEntity {
+                id: uid_encode(&n.node.id),
+                entity: n.name.clone(),
+                room_id: n.node.room_id.as_ref().map(uid_encode),
+            })
+            .collect()
+    }
+
     pub fn delete(
         &mut self,
         conn: &rusqlite::Connection,
@@ -129,9 +287,26 @@ impl DeletionQuery {
             daily_log.set_need_update(log.room_id, &log.entity, log.mdate);
             daily_log.set_need_update(log.room_id, &log.entity, log.deletion_date);
         }
+        for soft in &self.soft_deleted {
+            if let Some(room_id) = soft.node.room_id {
+                daily_log.set_need_update(room_id, &soft.node._entity, soft.node.mdate);
+                daily_log.set_need_update(room_id, &soft.node._entity, soft.date);
+            }
+        }
     }
 }
 
+///
+/// a node is soft deleted when its deletion date is defined
+///
+pub fn is_soft_deleted(node: &Node) -> bool {
+    node._json
+        .as_ref()
+        .and_then(|json| serde_json::from_str::<serde_json::Value>(json).ok())
+        .and_then(|json| json.get(DELETED_FIELD).map(|date| !date.is_null()))
+        .unwrap_or(false)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -206,8 +381,8 @@ mod tests {
         let persons = value.get("Person").unwrap().as_array().unwrap();
 
         let mut param = Parameters::new();
-        for i in 0..persons.len() {
-            let pers = persons[i].as_object().unwrap();
+        for (i, pers) in persons.iter().enumerate() {
+            let pers = pers.as_object().unwrap();
             let id = pers.get("id").unwrap().as_str().unwrap();
             param.add(&format!("id{}", i), id.to_string()).unwrap();
         }
@@ -319,8 +494,8 @@ mod tests {
 
         let parents = john.get("parents").unwrap().as_array().unwrap();
 
-        for i in 0..parents.len() {
-            let pers = parents[i].as_object().unwrap();
+        for (i, pers) in parents.iter().enumerate() {
+            let pers = pers.as_object().unwrap();
             let id = pers.get("id").unwrap().as_str().unwrap();
             param.add(&format!("p{}", i), id.to_string()).unwrap();
         }
@@ -364,4 +539,113 @@ mod tests {
         let expected = "{\n\"Person\":[]\n}";
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn delete_one_edge() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            {
+                Person {
+                    name : String,
+                    parents : [Person]
+                }
+            }",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                Person { name:"John" parents:[{name:"Alice"},{name:"Bob"}]  }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                Person(name="John") {
+                    id
+                    parents(order_by(name asc)){id name}
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+        let query_parser = Arc::new(query_parser);
+        let query = Arc::new(PreparedQueries::build(&query_parser).unwrap());
+
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: query_parser.clone(),
+            sql_queries: query.clone(),
+        };
+        let result = sql.read(&conn).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&result).unwrap();
+        let john = value["Person"][0].as_object().unwrap();
+        let src = john["id"].as_str().unwrap();
+        let alice = john["parents"][0]["id"].as_str().unwrap();
+
+        let mut param = Parameters::new();
+        param.add("src", src.to_string()).unwrap();
+        param.add("alice", alice.to_string()).unwrap();
+
+        let deletion = DeletionParser::parse(
+            "
+            delete delete_parent {
+                Person{$src}.parents{$alice}
+            }
+          ",
+            &data_model,
+        )
+        .unwrap();
+        let deletion = Arc::new(deletion);
+
+        let mut delete = DeletionQuery::build(&mut param, deletion, &conn).unwrap();
+        assert!(delete.nodes.is_empty());
+        assert_eq!(1, delete.edges.len());
+        delete.delete(&conn).unwrap();
+
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: query_parser,
+            sql_queries: query,
+        };
+        let result = sql.read(&conn).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&result).unwrap();
+        let parents = value["Person"][0]["parents"].as_array().unwrap();
+        assert_eq!(1, parents.len());
+        assert_eq!("Bob", parents[0]["name"].as_str().unwrap());
+
+        //the target node is not deleted
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                Person(name="Alice") { name }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"Person\":[{\"name\":\"Alice\"}]\n}";
+        assert_eq!(result, expected);
+    }
 }
@@ -1,11 +1,12 @@
 use super::{
     daily_log::DailyMutations,
+    node::Node,
     sqlite_database::{RowMappingFn, Writeable},
     Error, Result, VEC_OVERHEAD,
 };
 use crate::{
     date_utils::{date, date_next_day},
-    security::{import_verifying_key, SigningKey, Uid},
+    security::{base64_encode, import_verifying_key, SigningKey, Uid},
 };
 
 use rusqlite::{Connection, OptionalExtension};
@@ -109,7 +110,7 @@ impl Edge {
         let mut len = 0;
         len += &self.src.len();
         len += &self.src_entity.len();
-        len += &self.label.as_bytes().len();
+        len += &self.label.len();
         len += &self.dest.len();
         len += 8; //cdate
         len += &self.verifying_key.len();
@@ -312,26 +313,37 @@ impl Edge {
         Ok(rows)
     }
 
+    ///
+    /// the edges of the nodes that the reader cannot read are not returned
+    ///
     pub fn filtered_by_room(
         room_id: &Uid,
         node_ids: Vec<(Uid, i64)>,
+        reader: &[u8],
         batch_size: usize,
         sender: &mpsc::Sender<Result<Vec<Edge>>>,
         conn: &Connection,
     ) -> Result<()> {
-        let mut query = conn.prepare_cached(
+        let query = format!(
             "SELECT _edge.src, _edge.src_entity, _edge.label, _edge.dest, _edge.cdate, _edge.verifying_key, _edge.signature 
             FROM _edge JOIN _node ON  _edge.src = _node.id
             WHERE 
-                _edge.src = ? AND
-                _edge.cdate >= ? AND
-                _node.room_id = ?",
-        )?;
+                _edge.src = ?1 AND
+                _edge.cdate >= ?2 AND
+                _node.room_id = ?3 AND
+                {}",
+            Node::readable_by_condition("_node", 4)
+        );
+        let mut query = conn.prepare_cached(&query)?;
+        let encoded_reader = base64_encode(reader);
 
         let mut len = 0;
         let mut res = Vec::new();
         for (src, cdate) in &node_ids {
-            let edges = query.query_map((src, cdate, room_id), Self::EDGE_MAPPING)?;
+            let edges = query.query_map(
+                (src, cdate, room_id, reader, &encoded_reader),
+                Self::EDGE_MAPPING,
+            )?;
 
             for edge in edges {
                 let edge = *edge?;
@@ -602,10 +614,10 @@ mod tests {
         let from = new_uid();
         let to = new_uid();
         let mut e = Edge {
-            src: from.clone(),
+            src: from,
             src_entity: "0".to_string(),
             label: String::from("Test"),
-            dest: to.clone(),
+            dest: to,
             ..Default::default()
         };
 
@@ -616,7 +628,7 @@ mod tests {
         e.src = bad_id;
         e.verify()
             .expect_err("'from' has changed, verification must fail");
-        e.src = from.clone();
+        e.src = from;
         e.verify().unwrap();
 
         e.dest = bad_id;
@@ -625,7 +637,7 @@ mod tests {
         e.sign(&keypair).unwrap();
         e.verify().unwrap();
 
-        e.cdate = e.cdate + 1;
+        e.cdate += 1;
         e.verify()
             .expect_err("'cdate' has changed, verification must fail");
         e.sign(&keypair).unwrap();
@@ -651,10 +663,10 @@ mod tests {
         let from = new_uid();
         let to = new_uid();
         let mut e = Edge {
-            src: from.clone(),
+            src: from,
             src_entity: "0".to_string(),
             label: String::from(label),
-            dest: to.clone(),
+            dest: to,
             ..Default::default()
         };
 
@@ -691,10 +703,10 @@ mod tests {
         let from = new_uid();
         let to = new_uid();
         let mut e = Edge {
-            src: from.clone(),
+            src: from,
             src_entity: "0".to_string(),
             label: String::from(label),
-            dest: to.clone(),
+            dest: to,
             ..Default::default()
         };
 
@@ -738,10 +750,10 @@ mod tests {
         let from = new_uid();
         let to = new_uid();
         let mut e = Edge {
-            src: from.clone(),
+            src: from,
             src_entity: "0".to_string(),
             label: String::from(label),
-            dest: to.clone(),
+            dest: to,
             ..Default::default()
         };
         e.sign(&signing_key).unwrap();
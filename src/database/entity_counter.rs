@@ -0,0 +1,183 @@
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::security::Uid;
+
+///
+/// Number of nodes of an entity in a room and the modification date of the most recent one
+///
+/// The counters are maintained by triggers on the _node table, in the transaction that modifies the nodes.
+/// Reading a counter is a primary key lookup, whatever the size of the room.
+///
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntityCounter {
+    pub count: u64,
+    pub mdate: i64,
+}
+impl EntityCounter {
+    ///
+    /// Creates the table and the triggers that maintain it
+    ///
+    /// The table can be added to existing databases, hence the 'IF NOT EXISTS'.
+    /// The counters of an existing database are computed when the table is created.
+    ///
+    pub fn create_tables(conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        let exists: Option<i64> = conn
+            .query_row(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_entity_counter'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        conn.execute(
+            "
+            CREATE TABLE IF NOT EXISTS _entity_counter (
+                room_id BLOB NOT NULL,
+                entity TEXT NOT NULL,
+                count INTEGER NOT NULL,
+                mdate INTEGER NOT NULL,
+                PRIMARY KEY(room_id, entity)
+            ) WITHOUT ROWID, STRICT",
+            [],
+        )?;
+
+        conn.execute(
+            "
+            CREATE TRIGGER IF NOT EXISTS \"_entity_counter$insert\" AFTER INSERT ON _node
+            WHEN new.room_id IS NOT NULL
+            BEGIN
+                INSERT INTO _entity_counter (room_id, entity, count, mdate)
+                VALUES (new.room_id, new._entity, 1, new.mdate)
+                ON CONFLICT(room_id, entity) DO UPDATE SET count = count + 1, mdate = max(mdate, excluded.mdate);
+            END",
+            [],
+        )?;
+
+        //the mdate of the remaining nodes is read from the _node_entity index
+        conn.execute(
+            "
+            CREATE TRIGGER IF NOT EXISTS \"_entity_counter$delete\" AFTER DELETE ON _node
+            WHEN old.room_id IS NOT NULL
+            BEGIN
+                UPDATE _entity_counter SET
+                    count = count - 1,
+                    mdate = ifnull((SELECT max(mdate) FROM _node WHERE _entity = old._entity AND room_id = old.room_id), 0)
+                WHERE room_id = old.room_id AND entity = old._entity;
+                DELETE FROM _entity_counter WHERE room_id = old.room_id AND entity = old._entity AND count <= 0;
+            END",
+            [],
+        )?;
+
+        conn.execute(
+            "
+            CREATE TRIGGER IF NOT EXISTS \"_entity_counter$update\" AFTER UPDATE OF room_id, _entity, mdate ON _node
+            BEGIN
+                UPDATE _entity_counter SET
+                    count = count - 1,
+                    mdate = ifnull((SELECT max(mdate) FROM _node WHERE _entity = old._entity AND room_id = old.room_id), 0)
+                WHERE old.room_id IS NOT NULL AND room_id = old.room_id AND entity = old._entity;
+                DELETE FROM _entity_counter WHERE room_id = old.room_id AND entity = old._entity AND count <= 0;
+                INSERT INTO _entity_counter (room_id, entity, count, mdate)
+                SELECT new.room_id, new._entity, 1, new.mdate WHERE new.room_id IS NOT NULL
+                ON CONFLICT(room_id, entity) DO UPDATE SET count = count + 1, mdate = max(mdate, excluded.mdate);
+            END",
+            [],
+        )?;
+
+        if exists.is_none() {
+            conn.execute(
+                "
+                INSERT INTO _entity_counter (room_id, entity, count, mdate)
+                SELECT room_id, _entity, count(1), max(mdate) FROM _node
+                WHERE room_id IS NOT NULL
+                GROUP BY room_id, _entity",
+                [],
+            )?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// returns the counter of an entity in a room, a room without any node of the entity has an empty counter
+    ///
+    /// entity is the short name used in the _node table
+    ///
+    pub fn get(
+        room_id: &Uid,
+        entity: &str,
+        conn: &Connection,
+    ) -> std::result::Result<Self, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT count, mdate FROM _entity_counter WHERE room_id = ? AND entity = ?",
+        )?;
+        let counter = stmt
+            .query_row((room_id, entity), |row| {
+                Ok(Self {
+                    count: row.get(0)?,
+                    mdate: row.get(1)?,
+                })
+            })
+            .optional()?;
+        Ok(counter.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::node::Node,
+        security::{new_uid, Ed25519SigningKey},
+    };
+
+    #[test]
+    fn maintained_by_triggers() {
+        let conn = Connection::open_in_memory().unwrap();
+        Node::create_tables(&conn).unwrap();
+        EntityCounter::create_tables(&conn).unwrap();
+        let signing_key = Ed25519SigningKey::new();
+        let room_id = new_uid();
+
+        let mut nodes = Vec::new();
+        for mdate in 1..4 {
+            let mut node = Node {
+                room_id: Some(room_id),
+                _entity: "0.0".to_string(),
+                mdate,
+                ..Default::default()
+            };
+            node.sign(&signing_key).unwrap();
+            node.write(&conn, false, &None, &None).unwrap();
+            nodes.push(node);
+        }
+        let counter = EntityCounter::get(&room_id, "0.0", &conn).unwrap();
+        assert_eq!(3, counter.count);
+        assert_eq!(3, counter.mdate);
+        assert_eq!(
+            EntityCounter::default(),
+            EntityCounter::get(&room_id, "0.1", &conn).unwrap()
+        );
+
+        let mut node = nodes.pop().unwrap();
+        node.mdate = 10;
+        node.sign(&signing_key).unwrap();
+        node.write(&conn, false, &None, &None).unwrap();
+        let counter = EntityCounter::get(&room_id, "0.0", &conn).unwrap();
+        assert_eq!(3, counter.count);
+        assert_eq!(10, counter.mdate);
+
+        Node::delete(&node.id, &conn).unwrap();
+        let counter = EntityCounter::get(&room_id, "0.0", &conn).unwrap();
+        assert_eq!(2, counter.count);
+        assert_eq!(2, counter.mdate);
+
+        for node in nodes {
+            Node::delete(&node.id, &conn).unwrap();
+        }
+        assert_eq!(
+            EntityCounter::default(),
+            EntityCounter::get(&room_id, "0.0", &conn).unwrap()
+        );
+    }
+}
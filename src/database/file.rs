@@ -0,0 +1,361 @@
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::security::{base64_encode, hash, Uid};
+
+use super::{node::Node, query_language::FILE_ID_LENGTH, sqlite_database::Writeable, Result};
+
+///
+/// files are split in chunks that are stored and synchronised independently
+///
+pub const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+pub type FileId = [u8; FILE_ID_LENGTH];
+
+///
+/// Description of a file: its size and the hash of each chunk
+///
+/// The file identifier is the hash of this description, a file received from a peer is verified against the identifier
+/// stored in the signed node that references it.
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FileInfo {
+    pub size: u64,
+    pub chunks: Vec<[u8; 32]>,
+}
+impl FileInfo {
+    pub fn new(content: &[u8]) -> Self {
+        Self {
+            size: content.len() as u64,
+            chunks: content.chunks(FILE_CHUNK_SIZE).map(hash).collect(),
+        }
+    }
+
+    pub fn id(&self) -> FileId {
+        let mut description = Vec::with_capacity(8 + self.chunks.len() * 32);
+        description.extend(self.size.to_le_bytes());
+        for chunk in &self.chunks {
+            description.extend(chunk);
+        }
+        hash(&description)
+    }
+
+    ///
+    /// true if the data is the content of the chunk at the position
+    ///
+    pub fn is_valid_chunk(&self, chunk: &FileChunk) -> bool {
+        match self.chunks.get(chunk.position as usize) {
+            Some(chunk_hash) => {
+                chunk.data.len() <= FILE_CHUNK_SIZE && hash(&chunk.data).eq(chunk_hash)
+            }
+            None => false,
+        }
+    }
+
+    ///
+    /// Creates the tables used to store the files
+    ///
+    /// The tables can be added to existing databases, hence the 'IF NOT EXISTS'
+    ///
+    pub fn create_tables(conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        conn.execute(
+            "
+            CREATE TABLE IF NOT EXISTS _file (
+                room_id BLOB NOT NULL,
+                id BLOB NOT NULL,
+                info BLOB NOT NULL,
+                PRIMARY KEY(room_id, id)
+            ) WITHOUT ROWID, STRICT",
+            [],
+        )?;
+        conn.execute(
+            "
+            CREATE TABLE IF NOT EXISTS _file_chunk (
+                room_id BLOB NOT NULL,
+                file_id BLOB NOT NULL,
+                position INTEGER NOT NULL,
+                data BLOB NOT NULL,
+                PRIMARY KEY(room_id, file_id, position)
+            ) STRICT",
+            [],
+        )?;
+        Ok(())
+    }
+
+    pub fn get(room_id: &Uid, id: &FileId, conn: &Connection) -> Result<Option<Self>> {
+        let mut stmt =
+            conn.prepare_cached("SELECT info FROM _file WHERE room_id = ? AND id = ?")?;
+        let info: Option<Vec<u8>> = stmt.query_row((room_id, id), |row| row.get(0)).optional()?;
+        match info {
+            Some(info) => Ok(Some(bincode::deserialize(&info)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+///
+/// A part of a file
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileChunk {
+    pub position: u32,
+    pub data: Vec<u8>,
+}
+impl FileChunk {
+    ///
+    /// the positions of the chunks that are not stored yet, None when the file is unknown
+    ///
+    pub fn missing(room_id: &Uid, id: &FileId, conn: &Connection) -> Result<Option<Vec<u32>>> {
+        let info = match FileInfo::get(room_id, id, conn)? {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+        let mut stmt = conn.prepare_cached(
+            "SELECT position FROM _file_chunk WHERE room_id = ? AND file_id = ? ORDER BY position",
+        )?;
+        let stored = stmt
+            .query_map((room_id, id), |row| row.get::<_, u32>(0))?
+            .collect::<std::result::Result<Vec<u32>, rusqlite::Error>>()?;
+
+        let missing = (0..info.chunks.len() as u32)
+            .filter(|position| stored.binary_search(position).is_err())
+            .collect();
+        Ok(Some(missing))
+    }
+
+    pub fn read(
+        room_id: &Uid,
+        id: &FileId,
+        positions: &[u32],
+        conn: &Connection,
+    ) -> Result<Vec<Self>> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT data FROM _file_chunk WHERE room_id = ? AND file_id = ? AND position = ?",
+        )?;
+        let mut chunks = Vec::with_capacity(positions.len());
+        for position in positions {
+            let data: Option<Vec<u8>> = stmt
+                .query_row((room_id, id, position), |row| row.get(0))
+                .optional()?;
+            if let Some(data) = data {
+                chunks.push(Self {
+                    position: *position,
+                    data,
+                });
+            }
+        }
+        Ok(chunks)
+    }
+}
+
+///
+/// returns the content of a file, None if no room holds a complete copy of the file
+///
+pub fn read_file(id: &FileId, conn: &Connection) -> Result<Option<Vec<u8>>> {
+    let mut stmt = conn.prepare_cached("SELECT room_id, info FROM _file WHERE id = ?")?;
+    let files = stmt
+        .query_map([id], |row| {
+            Ok((row.get::<_, Uid>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })?
+        .collect::<std::result::Result<Vec<(Uid, Vec<u8>)>, rusqlite::Error>>()?;
+
+    let mut chunk_stmt = conn.prepare_cached(
+        "SELECT data FROM _file_chunk WHERE room_id = ? AND file_id = ? ORDER BY position",
+    )?;
+    for (room_id, info) in files {
+        let info: FileInfo = bincode::deserialize(&info)?;
+        let chunks = chunk_stmt
+            .query_map((&room_id, id), |row| row.get::<_, Vec<u8>>(0))?
+            .collect::<std::result::Result<Vec<Vec<u8>>, rusqlite::Error>>()?;
+        if chunks.len() == info.chunks.len() {
+            return Ok(Some(chunks.concat()));
+        }
+    }
+    Ok(None)
+}
+
+///
+/// the files referenced by the nodes of the room that are not completely stored in the room
+///
+/// fields contains the (entity short name, field short name) of the File fields
+///
+pub fn missing_files(
+    room_id: &Uid,
+    fields: &[(String, String)],
+    conn: &Connection,
+) -> Result<Vec<FileId>> {
+    let mut missing = Vec::new();
+    for (entity, field) in fields {
+        let query = format!(
+            "SELECT DISTINCT _json->>'$.{field}' FROM _node
+            WHERE room_id = ? AND _entity = ? AND _json->>'$.{field}' IS NOT NULL"
+        );
+        let mut stmt = conn.prepare_cached(&query)?;
+        let ids = stmt
+            .query_map((room_id, entity), |row| row.get::<_, String>(0))?
+            .collect::<std::result::Result<Vec<String>, rusqlite::Error>>()?;
+
+        for id in ids {
+            let id: FileId = match crate::security::base64_decode(id.as_bytes())
+                .ok()
+                .and_then(|id| id.try_into().ok())
+            {
+                Some(id) => id,
+                None => continue,
+            };
+            if missing.contains(&id) {
+                continue;
+            }
+            let complete = matches!(FileChunk::missing(room_id, &id, conn)?, Some(chunks) if chunks.is_empty());
+            if !complete {
+                missing.push(id);
+            }
+        }
+    }
+    Ok(missing)
+}
+
+///
+/// a file can be sent to a peer when it is referenced by a node of the room that the peer can read
+///
+pub fn readable_by(
+    room_id: &Uid,
+    id: &FileId,
+    fields: &[(String, String)],
+    reader: &[u8],
+    conn: &Connection,
+) -> Result<bool> {
+    let encoded_id = base64_encode(id);
+    let encoded_reader = base64_encode(reader);
+    for (entity, field) in fields {
+        let query = format!(
+            "SELECT 1 FROM _node
+            WHERE room_id = ?1 AND _entity = ?2 AND _json->>'$.{field}' = ?3 AND {}
+            LIMIT 1",
+            Node::readable_by_condition("_node", 4)
+        );
+        let mut stmt = conn.prepare_cached(&query)?;
+        let found = stmt
+            .query_row(
+                (room_id, entity, &encoded_id, reader, &encoded_reader),
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?;
+        if found.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+///
+/// stores the description of a file and some of its chunks
+///
+pub struct FileWrite {
+    pub room_id: Uid,
+    pub info: FileInfo,
+    pub chunks: Vec<FileChunk>,
+}
+impl Writeable for FileWrite {
+    fn write(&mut self, conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        let id = self.info.id();
+        //cannot fail for a struct containing only integers and byte arrays
+        let info = bincode::serialize(&self.info).unwrap();
+        let mut file_stmt = conn
+            .prepare_cached("INSERT OR IGNORE INTO _file (room_id, id, info) VALUES (?, ?, ?)")?;
+        file_stmt.execute((&self.room_id, &id, &info))?;
+
+        let mut chunk_stmt = conn.prepare_cached(
+            "INSERT OR IGNORE INTO _file_chunk (room_id, file_id, position, data) VALUES (?, ?, ?, ?)",
+        )?;
+        for chunk in &self.chunks {
+            chunk_stmt.execute((&self.room_id, &id, chunk.position, &chunk.data))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::sqlite_database::prepare_connection,
+        security::{base64_encode, new_uid},
+    };
+
+    use super::*;
+
+    #[test]
+    fn file_storage() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+        let room_id = new_uid();
+
+        let content: Vec<u8> = (0..FILE_CHUNK_SIZE * 2 + 10)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let info = FileInfo::new(&content);
+        assert_eq!(3, info.chunks.len());
+        let id = info.id();
+        assert_ne!(id, FileInfo::new(&content[1..]).id());
+
+        assert!(FileChunk::missing(&room_id, &id, &conn).unwrap().is_none());
+
+        let chunks: Vec<FileChunk> = content
+            .chunks(FILE_CHUNK_SIZE)
+            .enumerate()
+            .map(|(position, data)| FileChunk {
+                position: position as u32,
+                data: data.to_vec(),
+            })
+            .collect();
+        assert!(chunks.iter().all(|chunk| info.is_valid_chunk(chunk)));
+        let mut invalid = chunks[0].clone();
+        invalid.position = 1;
+        assert!(!info.is_valid_chunk(&invalid));
+
+        //the file is written in two steps, like an interrupted transfer
+        FileWrite {
+            room_id,
+            info: info.clone(),
+            chunks: vec![chunks[1].clone()],
+        }
+        .write(&conn)
+        .unwrap();
+        assert_eq!(
+            vec![0, 2],
+            FileChunk::missing(&room_id, &id, &conn).unwrap().unwrap()
+        );
+        assert!(read_file(&id, &conn).unwrap().is_none());
+
+        conn.execute(
+            "INSERT INTO _node (id, room_id, cdate, mdate, _entity, _json, verifying_key, _signature)
+            VALUES (?, ?, 0, 0, '32', ?, x'00', x'00')",
+            (
+                new_uid(),
+                room_id,
+                format!("{{\"33\":\"{}\"}}", base64_encode(&id)),
+            ),
+        )
+        .unwrap();
+        let fields = vec![("32".to_string(), "33".to_string())];
+        assert_eq!(vec![id], missing_files(&room_id, &fields, &conn).unwrap());
+
+        FileWrite {
+            room_id,
+            info: info.clone(),
+            chunks: vec![chunks[0].clone(), chunks[2].clone()],
+        }
+        .write(&conn)
+        .unwrap();
+        assert!(FileChunk::missing(&room_id, &id, &conn)
+            .unwrap()
+            .unwrap()
+            .is_empty());
+        assert!(missing_files(&room_id, &fields, &conn).unwrap().is_empty());
+        assert_eq!(content, read_file(&id, &conn).unwrap().unwrap());
+
+        let read = FileChunk::read(&room_id, &id, &[2, 5], &conn).unwrap();
+        assert_eq!(1, read.len());
+        assert_eq!(chunks[2].data, read[0].data);
+    }
+}
@@ -1,54 +1,100 @@
 #[cfg(feature = "log")]
-use log::error;
+use crate::log_level::{error, info, warning, LogSubsystem};
+#[cfg(feature = "log")]
+const LOG_SUBSYSTEM: LogSubsystem = LogSubsystem::Database;
 
 use lru::LruCache;
 use rusqlite::OptionalExtension;
 use std::collections::{HashSet, VecDeque};
-use std::{collections::HashMap, fs, num::NonZeroUsize, path::PathBuf, sync::Arc};
+use std::time::{Duration, Instant};
+use std::{
+    collections::HashMap,
+    fs,
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 use tokio::sync::{mpsc, oneshot, oneshot::Sender};
 
 use super::edge::Edge;
 use super::node::NodeToInsert;
 use super::query_language::data_model_parser::validate_json_for_entity;
 use super::sqlite_database::WriteStmt;
-use super::system_entities::{self, AllowedPeer, Peer, PeerNodes};
+use super::system_entities::{
+    self, AllowedPeer, AutomationRule, AutomationRuleCache, AutomationTrigger, Peer, PeerNodes,
+    PeerPage, PeerUpdate,
+};
 use super::{
-    authorisation_service::{AuthorisationMessage, AuthorisationService, RoomAuthorisations},
-    daily_log::DailyLogsUpdate,
+    authorisation_service::{
+        AuthorisationMessage, AuthorisationService, EntityContention, RoomAuthorisations,
+        WriteConflicts,
+    },
+    backup::{BackupInfo, BackupScheduler, BackupStatus},
+    binary_store::{BinaryStore, StoredBinary},
     daily_log::{DailyLog, RoomDefinitionLog},
+    daily_log::{DailyLogReconciliation, DailyLogsUpdate},
     deletion::DeletionQuery,
     edge::EdgeDeletionEntry,
-    mutation_query::MutationQuery,
-    node::{Node, NodeDeletionEntry, NodeIdentifier},
-    query::{PreparedQueries, Query},
+    entity_counter::EntityCounter,
+    file::{self, FileChunk, FileId, FileInfo, FileWrite, FILE_CHUNK_SIZE},
+    log_retention::{EdgeKey, LogPruning, RoomSnapshot, RoomSynchronisation},
+    mutation_query::{IdempotencyKeyPruning, MutationQuery},
+    node::{Node, NodeBatch, NodeDeletionEntry, NodeIdentifier},
+    query::{PreparedQueries, Query, SlowQueries, SlowQuery},
     query_language::{
-        data_model_parser::DataModel, deletion_parser::DeletionParser,
-        mutation_parser::MutationParser, parameter::Parameters, query_parser::QueryParser,
+        data_model_parser::{DataModel, TemplateSource},
+        deletion_parser::DeletionParser,
+        mutation_parser::MutationParser,
+        parameter::{Parameters, ParametersAdd},
+        query_parser::QueryParser,
+        ParsingLimits,
     },
+    replication::{DataAvailability, DayDivergence, PeerRoomLog, PeerSyncStats, PeerSyncUpdate},
+    room::{RightType, Room},
+    room_impact::RoomChangeImpact,
     room_node::RoomNode,
-    sqlite_database::{Database, WriteMessage, Writeable},
+    search::{self, SearchHit},
+    sqlite_database::{
+        is_corruption, recover_database, recovered_path, swap_recovered_database, Database,
+        DatabaseReader, WriteMessage, Writeable,
+    },
     system_entities::SYSTEM_DATA_MODEL,
+    telemetry::{self, Telemetry, TelemetryReport},
+    transaction::{TransactionOperation, TransactionQuery},
     Error, Result,
 };
-use super::{DataModification, MESSAGE_OVERHEAD};
+use super::{encode_result, DataModification, Encoding, MESSAGE_OVERHEAD};
 
 use crate::event_service::EventServiceMessage;
 use crate::security::{uid_encode, MeetingSecret, MeetingToken};
 use crate::{
-    configuration::Configuration,
+    configuration::{Configuration, Storage},
     date_utils::now,
     event_service::EventService,
-    security::{base64_encode, derive_key, derive_uid, Ed25519SigningKey, SigningKey, Uid},
+    security::{base64_encode, derive_key, derive_uid, hash, Ed25519SigningKey, SigningKey, Uid},
 };
 
 const LRU_SIZE: usize = 128;
 
+//delay before retrying a failed backup
+const BACKUP_RETRY_DELAY: i64 = 3600 * 1000;
+
 pub enum DbMessage {
     Query(String, Parameters, Sender<Result<String>>),
+    QueryEntities(String, Sender<Result<HashSet<String>>>),
+    Head(String, bool, Parameters, Sender<Result<i64>>),
     Mutate(String, Parameters, Sender<Result<MutationQuery>>),
+    MutateBatch(String, Vec<Parameters>, Sender<Result<Vec<MutationQuery>>>),
+    Transaction(Vec<TransactionOperation>, Sender<Result<Vec<TransactionQuery>>>),
+    Seed(String, String, Parameters, Sender<Result<bool>>),
     MutateStream(String, Parameters, mpsc::Sender<Result<MutationQuery>>),
     Delete(String, Parameters, Sender<Result<DeletionQuery>>),
+    ValidateAutomationRule(String, Sender<Result<()>>),
     DataModelUpdate(String, Sender<Result<String>>),
+    DataModelProposal(String, i64, Sender<Result<bool>>),
+    DataModelTemplate(String, TemplateSource, Sender<Result<bool>>),
+    DataModelTemplateSource(Sender<Option<TemplateSource>>),
+    DataModelPreflight(String, Sender<Result<String>>),
     DataModel(Sender<Result<String>>),
     AddNodes(Uid, Vec<NodeToInsert>, Sender<Result<Vec<Uid>>>),
     AddEdges(Uid, Vec<Edge>, Sender<Result<Vec<Uid>>>),
@@ -56,11 +102,36 @@ pub enum DbMessage {
     DeleteNodes(Vec<NodeDeletionEntry>, Sender<Result<()>>),
     ComputeDailyLog(),
     DailyLogComputed(Result<DailyLogsUpdate>),
+    SlowQueries(Sender<Vec<SlowQuery>>),
+    DatabaseCorrupted(String),
+    AttemptRecovery(Sender<Result<usize>>),
+    Backup(Sender<Result<BackupInfo>>),
+    BackupStatus(Sender<Result<BackupStatus>>),
+    Shutdown(Sender<Result<()>>),
+    Availability(Uid, Sender<Result<Vec<DataAvailability>>>),
+    RoomDivergence(Uid, Vec<u8>, Sender<Result<Vec<DayDivergence>>>),
+    PreviewRoomChange(Uid, String, Parameters, Sender<Result<RoomChangeImpact>>),
+    SearchAll(String, Vec<Uid>, Vec<String>, Sender<Result<Vec<SearchHit>>>),
+    SynchronisationDuration(u64),
+    Telemetry(bool, Sender<Result<Option<TelemetryReport>>>),
+    MissingFiles(Uid, Sender<Result<Vec<FileId>>>),
+    FileReadable(Uid, FileId, Vec<u8>, Sender<Result<bool>>),
+    EntityCounter(Uid, String, Sender<Result<EntityCounter>>),
+    Relocate(PathBuf, Sender<Result<PathBuf>>),
+    Relocated(PathBuf, PathBuf, Sender<Result<PathBuf>>),
 }
 
 pub type MutateReceiver =
     mpsc::Receiver<std::result::Result<MutationQuery, crate::database::Error>>;
 
+pub type OrderedMutateReceiver = mpsc::Receiver<(
+    u64,
+    std::result::Result<MutationQuery, crate::database::Error>,
+)>;
+
+//maximum number of mutations processed at the same time by an ordered mutation stream
+const ORDERED_STREAM_IN_FLIGHT: usize = 32;
+
 ///
 /// Entry Point for all databases interaction
 ///
@@ -73,6 +144,13 @@ pub struct GraphDatabaseService {
     pub auth: AuthorisationService,
     pub db: Database,
     pub buffer_size: usize,
+    pub accept_data_model_templates: bool,
+    pub write_backlog_limit: usize,
+    pub log_retention_in_days: u32,
+    pub verifying_key: Vec<u8>,
+    automation_rules: AutomationRuleCache,
+    //key of the noise added to the log entry numbers sent to low trust peers
+    log_noise_key: [u8; 32],
 }
 impl GraphDatabaseService {
     pub fn database_exists(
@@ -80,14 +158,20 @@ impl GraphDatabaseService {
         key_material: &[u8; 32],
         data_folder: &PathBuf,
     ) -> std::result::Result<bool, crate::Error> {
-        let signature_key = derive_key(&format!("{} SIGNING_KEY", app_key), key_material);
-        let database_secret = derive_key("DATABASE_SECRET", &signature_key);
-        let database_key = derive_key("DATABASE_NAME", &database_secret);
+        let keys = LocalKeys::derive(app_key, key_material);
+        let database_key = derive_key("DATABASE_NAME", &keys.database_secret);
         let database_path = build_path(data_folder, &base64_encode(&database_key))?;
         let exist = database_path.exists();
         Ok(exist)
     }
 
+    ///
+    /// the verifying key of the account defined by the key material
+    ///
+    pub fn derive_verifying_key(app_key: &str, key_material: &[u8; 32]) -> Vec<u8> {
+        LocalKeys::derive(app_key, key_material).verifying_key
+    }
+
     pub async fn start(
         app_key: &str,
         datamodel: &str,
@@ -96,29 +180,85 @@ impl GraphDatabaseService {
         data_folder: PathBuf,
         configuration: &Configuration,
         event_service: EventService,
+    ) -> Result<(Self, Vec<u8>, Uid)> {
+        let private_room_id = derive_uid(&format!("{}{}", app_key, "SYSTEM_ROOM"), key_material);
+        let keys = LocalKeys::derive(app_key, key_material);
+        Self::open(
+            private_room_id,
+            Some(public_key),
+            datamodel,
+            keys,
+            data_folder,
+            configuration,
+            event_service,
+        )
+        .await
+    }
+
+    ///
+    /// Starts the database of a read-only observer, identified by its verifying key.
+    ///
+    /// No signing key exists: every local mutation is rejected with *Error::ReadOnly*,
+    /// only the data signed by other peers can be inserted.
+    ///
+    pub async fn start_observer(
+        app_key: &str,
+        datamodel: &str,
+        verifying_key: &[u8],
+        storage_secret: &[u8; 32],
+        data_folder: PathBuf,
+        configuration: &Configuration,
+        event_service: EventService,
+    ) -> Result<Self> {
+        let private_room_id = derive_uid(&format!("{}{}", app_key, "OBSERVER_ROOM"), verifying_key);
+        let keys = LocalKeys::observer(app_key, verifying_key, storage_secret);
+        let (service, _, _) = Self::open(
+            private_room_id,
+            None,
+            datamodel,
+            keys,
+            data_folder,
+            configuration,
+            event_service,
+        )
+        .await?;
+        Ok(service)
+    }
+
+    async fn open(
+        private_room_id: Uid,
+        public_key: Option<&[u8; 32]>,
+        datamodel: &str,
+        keys: LocalKeys,
+        data_folder: PathBuf,
+        configuration: &Configuration,
+        event_service: EventService,
     ) -> Result<(Self, Vec<u8>, Uid)> {
         let (peer_sender, mut peer_receiver) =
             mpsc::channel::<DbMessage>(configuration.parallelism);
         //  let (interactive_sender, mut intereactive_receiver) = mpsc::channel::<Message>(128);
         let buffer_size = (configuration.write_buffer_length * 1024) - MESSAGE_OVERHEAD;
-        let private_room_id = derive_uid(&format!("{}{}", app_key, "SYSTEM_ROOM"), key_material);
 
         let mut db = GraphDatabase::new(
             private_room_id,
             public_key,
             datamodel,
-            app_key,
-            key_material,
+            keys,
             data_folder,
             configuration,
             event_service,
+            peer_sender.clone(),
         )
         .await?;
 
         let database = db.graph_database.clone();
+        let backup = db.backup.clone();
         let auth = db.auth_service.clone();
         let verifying_key = db.verifying_key.clone();
         let sender = peer_sender.clone();
+        let automation_rules = AutomationRuleCache::default();
+        let log_noise_key = derive_key("LOG_NOISE", &db.database_secret);
+        let rule_cache = automation_rules.clone();
         tokio::spawn(async move {
             while let Some(msg) = peer_receiver.recv().await {
                 match msg {
@@ -126,13 +266,31 @@ impl GraphDatabaseService {
                         let q = db.get_cached_query(&query);
                         match q {
                             Ok(cache) => {
-                                db.query(cache.0, cache.1, parameters, reply).await;
+                                let query_hash = base64_encode(&hash(query.as_bytes()));
+                                db.query(cache.0, cache.1, query_hash, parameters, reply)
+                                    .await;
+                            }
+                            Err(err) => {
+                                let _ = reply.send(Err(err));
+                            }
+                        }
+                    }
+                    DbMessage::Head(query, exists, parameters, reply) => {
+                        match db.get_cached_head_query(&query, exists) {
+                            Ok(cache) => {
+                                db.head(cache.0, cache.1, parameters, reply).await;
                             }
                             Err(err) => {
                                 let _ = reply.send(Err(err));
                             }
                         }
                     }
+                    DbMessage::QueryEntities(query, reply) => {
+                        let res = db
+                            .get_cached_query(&query)
+                            .map(|(parser, _)| parser.entities());
+                        let _ = reply.send(res);
+                    }
                     DbMessage::Mutate(mutation, parameters, reply) => {
                         let mutation = db.get_cached_mutation(&mutation);
                         match mutation {
@@ -145,6 +303,33 @@ impl GraphDatabaseService {
                         }
                     }
 
+                    DbMessage::MutateBatch(mutation, parameters, reply) => {
+                        let mutation = db.get_cached_mutation(&mutation);
+                        match mutation {
+                            Ok(cache) => {
+                                db.mutate_batch(cache, parameters, reply).await;
+                            }
+                            Err(err) => {
+                                let _ = reply.send(Err(err));
+                            }
+                        }
+                    }
+                    DbMessage::Transaction(operations, reply) => {
+                        db.transaction(operations, reply).await;
+                    }
+
+                    DbMessage::Seed(name, mutation, parameters, reply) => {
+                        let mutation = db.get_cached_mutation(&mutation);
+                        match mutation {
+                            Ok(cache) => {
+                                db.seed(name, cache, parameters, reply).await;
+                            }
+                            Err(err) => {
+                                let _ = reply.send(Err(err));
+                            }
+                        }
+                    }
+
                     DbMessage::MutateStream(mutation, parameters, reply) => {
                         let mutation = db.get_cached_mutation(&mutation);
                         match mutation {
@@ -169,6 +354,13 @@ impl GraphDatabaseService {
                         }
                     }
 
+                    DbMessage::ValidateAutomationRule(mutation, reply) => {
+                        let res = db
+                            .get_cached_mutation(&mutation)
+                            .and_then(|parser| AutomationRule::validate(&parser));
+                        let _ = reply.send(res);
+                    }
+
                     DbMessage::AddNodes(room_id, nodes, reply) => {
                         db.add_nodes(room_id, nodes, reply).await;
                     }
@@ -178,7 +370,7 @@ impl GraphDatabaseService {
                     }
 
                     DbMessage::DataModelUpdate(value, reply) => {
-                        match db.update_data_model(&value).await {
+                        match db.update_data_model(&value, None, None).await {
                             Ok(model) => {
                                 let _ = reply.send(Ok(model));
                             }
@@ -188,6 +380,41 @@ impl GraphDatabaseService {
                         }
                     }
 
+                    DbMessage::DataModelProposal(value, date, reply) => {
+                        if date <= db.data_model.proposal_date() {
+                            let _ = reply.send(Ok(false));
+                        } else {
+                            let res = db.update_data_model(&value, Some(date), None).await;
+                            let _ = reply.send(res.map(|_| true));
+                        }
+                    }
+
+                    DbMessage::DataModelTemplate(value, source, reply) => {
+                        let outdated = db
+                            .data_model
+                            .template()
+                            .is_some_and(|current| source.date <= current.date);
+                        if outdated {
+                            let _ = reply.send(Ok(false));
+                        } else {
+                            let res = db.update_data_model(&value, None, Some(source)).await;
+                            let _ = reply.send(res.map(|_| true));
+                        }
+                    }
+
+                    DbMessage::DataModelTemplateSource(reply) => {
+                        let _ = reply.send(db.data_model.template().cloned());
+                    }
+
+                    DbMessage::DataModelPreflight(value, reply) => {
+                        let mut data_model = db.data_model.clone();
+                        let res = match data_model.update(&value) {
+                            Ok(_) => serde_json::to_string_pretty(&data_model).map_err(Error::from),
+                            Err(err) => Err(err.into()),
+                        };
+                        let _ = reply.send(res);
+                    }
+
                     DbMessage::DataModel(reply) => {
                         match serde_json::to_string_pretty(&db.data_model) {
                             Ok(model) => {
@@ -225,6 +452,10 @@ impl GraphDatabaseService {
                                 for log in room_entry.1 {
                                     let entity = db.data_model.name_for(&log.entity);
                                     if let Some(entity) = entity {
+                                        //rules written locally or by a synchronisation
+                                        if entity.eq(system_entities::AUTOMATION_RULE_ENT) {
+                                            rule_cache.clear();
+                                        }
                                         let date = log.date;
                                         data_mod.add(room, entity, date);
                                     }
@@ -242,10 +473,109 @@ impl GraphDatabaseService {
                             error!("ComputedDailyLog {}", _e);
                         }
                     },
+
+                    DbMessage::SlowQueries(reply) => {
+                        let _ = reply.send(db.slow_queries.lock().unwrap().list());
+                    }
+
+                    DbMessage::DatabaseCorrupted(msg) => {
+                        #[cfg(feature = "log")]
+                        error!("Database corrupted: {msg}");
+                        let _ = db
+                            .event_service
+                            .sender
+                            .send(EventServiceMessage::DatabaseCorrupted(msg))
+                            .await;
+                    }
+
+                    DbMessage::AttemptRecovery(reply) => {
+                        db.attempt_recovery(reply);
+                    }
+
+                    DbMessage::Backup(reply) => {
+                        db.backup(reply);
+                    }
+
+                    DbMessage::BackupStatus(reply) => {
+                        let _ = reply.send(db.backup.status());
+                    }
+
+                    DbMessage::Relocate(data_folder, reply) => {
+                        db.relocate(data_folder, sender.clone(), reply);
+                    }
+
+                    DbMessage::Relocated(database_path, previous_folder, reply) => {
+                        db.database_path = database_path;
+                        let _ = reply.send(Ok(previous_folder));
+                    }
+
+                    DbMessage::Availability(room_id, reply) => {
+                        db.availability(room_id, reply).await;
+                    }
+
+                    DbMessage::RoomDivergence(room_id, verifying_key, reply) => {
+                        db.room_divergence(room_id, verifying_key, reply).await;
+                    }
+
+                    DbMessage::PreviewRoomChange(room_id, mutation, parameters, reply) => {
+                        let mutation = db.get_cached_mutation(&mutation);
+                        match mutation {
+                            Ok(cache) => {
+                                db.preview_room_change(room_id, cache, parameters, reply);
+                            }
+                            Err(err) => {
+                                let _ = reply.send(Err(err));
+                            }
+                        }
+                    }
+
+                    DbMessage::SynchronisationDuration(duration) => {
+                        db.telemetry.lock().unwrap().add_synchronisation(duration);
+                    }
+
+                    DbMessage::Telemetry(reset, reply) => {
+                        db.telemetry(reset, reply).await;
+                    }
+
+                    DbMessage::SearchAll(term, rooms, entities, reply) => {
+                        db.search_all(term, rooms, entities, reply).await;
+                    }
+                    DbMessage::MissingFiles(room_id, reply) => {
+                        db.missing_files(room_id, reply).await;
+                    }
+                    DbMessage::FileReadable(room_id, id, reader, reply) => {
+                        db.file_readable(room_id, id, reader, reply).await;
+                    }
+                    DbMessage::EntityCounter(room_id, entity, reply) => {
+                        db.entity_counter(room_id, entity, reply).await;
+                    }
+
+                    DbMessage::Shutdown(reply) => {
+                        //writes are processed in order: every pending write is done once this one is
+                        let res = db.graph_database.writer.write(Box::new(Flush {})).await;
+                        let _ = reply.send(res.map(|_| ()));
+                        break;
+                    }
                 }
             }
         });
 
+        //remove the old deletion logs before computing the daily logs
+        if configuration.log_retention_in_days > 0 {
+            database
+                .writer
+                .write(Box::new(LogPruning::for_retention(
+                    configuration.log_retention_in_days,
+                )))
+                .await?;
+        }
+
+        //flag the rows that are not reflected by the daily logs before computing them
+        database
+            .writer
+            .write(Box::new(DailyLogReconciliation {}))
+            .await?;
+
         //ensure that the logs are properly computed during startup  because the application can be closed during a synchronisation
         database
             .writer
@@ -255,12 +585,97 @@ impl GraphDatabaseService {
             ))
             .await?;
 
+        //prune the old deletion logs every day
+        if configuration.log_retention_in_days > 0 {
+            let retention = configuration.log_retention_in_days;
+            let writer = database.writer.clone();
+            let prune_sender = peer_sender.clone();
+            tokio::spawn(async move {
+                let period = Duration::from_secs(24 * 3600);
+                let mut interval =
+                    tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                loop {
+                    interval.tick().await;
+                    if let Err(_e) = writer
+                        .write(Box::new(LogPruning::for_retention(retention)))
+                        .await
+                    {
+                        #[cfg(feature = "log")]
+                        error!("LogPruning, Error: {_e}");
+                        continue;
+                    }
+                    if prune_sender
+                        .send(DbMessage::ComputeDailyLog())
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+
+        //remove the expired idempotency keys every hour, the first pruning is done at startup
+        if configuration.idempotency_key_retention_in_hours > 0 {
+            let retention = configuration.idempotency_key_retention_in_hours;
+            let writer = database.writer.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(3600));
+                loop {
+                    interval.tick().await;
+                    if let Err(_e) = writer
+                        .write(Box::new(IdempotencyKeyPruning::for_retention(retention)))
+                        .await
+                    {
+                        #[cfg(feature = "log")]
+                        error!("IdempotencyKeyPruning, Error: {_e}");
+                        break;
+                    }
+                }
+            });
+        }
+
+        //write the scheduled backups, the first one is written at startup when it is due
+        if backup.enabled() {
+            let backup_sender = peer_sender.clone();
+            tokio::spawn(async move {
+                loop {
+                    let delay = match backup.next_backup() {
+                        Ok(Some(next)) => (next - now()).max(0),
+                        _ => BACKUP_RETRY_DELAY,
+                    };
+                    tokio::time::sleep(Duration::from_millis(delay as u64)).await;
+
+                    let (reply, receive) = oneshot::channel::<Result<BackupInfo>>();
+                    if backup_sender.send(DbMessage::Backup(reply)).await.is_err() {
+                        break;
+                    }
+                    match receive.await {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(_e)) => {
+                            #[cfg(feature = "log")]
+                            error!("Backup, Error: {_e}");
+                            tokio::time::sleep(Duration::from_millis(BACKUP_RETRY_DELAY as u64))
+                                .await;
+                        }
+                        Err(_) => break,
+                    }
+                }
+            });
+        }
+
         Ok((
             GraphDatabaseService {
                 sender: peer_sender,
                 auth,
                 db: database,
                 buffer_size,
+                accept_data_model_templates: configuration.accept_data_model_templates,
+                write_backlog_limit: configuration.write_backlog_limit,
+                log_retention_in_days: configuration.log_retention_in_days,
+                verifying_key: verifying_key.clone(),
+                automation_rules,
+                log_noise_key,
             },
             verifying_key,
             private_room_id,
@@ -278,9 +693,34 @@ impl GraphDatabaseService {
         let (reply, receive) = oneshot::channel::<Result<DeletionQuery>>();
         let msg = DbMessage::Delete(delete.to_string(), param_opt.unwrap_or_default(), reply);
         let _ = self.sender.send(msg).await;
-        let result = receive.await?;
-        let _ = self.sender.send(DbMessage::ComputeDailyLog()).await;
-        result
+        let deletion = receive.await??;
+        self.automation_rules.deleted(&deletion);
+        Ok(deletion)
+    }
+
+    ///
+    /// coarsen the number of entries of the logs sent to a low trust peer, see [super::daily_log::blur_count]
+    ///
+    pub fn blur_logs(&self, logs: &mut [DailyLog], bucket: u32) {
+        logs.iter_mut()
+            .for_each(|log| log.blur(bucket, &self.log_noise_key));
+    }
+
+    ///
+    /// coarsen the number of entries of a room definition sent to a low trust peer
+    ///
+    pub fn blur_definition(&self, definition: &mut RoomDefinitionLog, bucket: u32) {
+        definition.blur(bucket, &self.log_noise_key);
+    }
+
+    ///
+    /// Checks that the mutation of an automation rule only mutates the room that triggered it, see [AutomationRule::validate]
+    ///
+    pub async fn validate_automation_rule(&self, mutation: &str) -> Result<()> {
+        let (reply, receive) = oneshot::channel::<Result<()>>();
+        let msg = DbMessage::ValidateAutomationRule(mutation.to_string(), reply);
+        let _ = self.sender.send(msg).await;
+        receive.await?
     }
 
     ///
@@ -288,21 +728,87 @@ impl GraphDatabaseService {
     /// returns and internal representation of the result
     /// should be only used by tests
     ///
+    /// the automation rules triggered by the inserted entities are applied once the mutation is done
+    ///
     pub async fn mutate_raw(
         &self,
         mutate: &str,
         param_opt: Option<Parameters>,
     ) -> Result<MutationQuery> {
+        let mutation = self
+            .send_mutation(mutate.to_string(), param_opt.unwrap_or_default())
+            .await?;
+        self.apply_automation_rules(&mutation).await;
+        Ok(mutation)
+    }
+
+    async fn send_mutation(&self, mutate: String, parameters: Parameters) -> Result<MutationQuery> {
         let (reply, receive) = oneshot::channel::<Result<MutationQuery>>();
 
-        let msg = DbMessage::Mutate(mutate.to_string(), param_opt.unwrap_or_default(), reply);
+        let msg = DbMessage::Mutate(mutate, parameters, reply);
         let _ = self.sender.send(msg).await;
 
-        let result = receive.await?;
+        receive.await?
+    }
 
-        let _ = self.sender.send(DbMessage::ComputeDailyLog()).await;
+    ///
+    /// applies the automation rules triggered by the entities inserted by the mutation
+    /// a failing rule does not stop the other ones
+    ///
+    /// rules are validated again before being applied, as the ones received from other peers were never checked
+    ///
+    async fn apply_automation_rules(&self, mutation: &MutationQuery) {
+        self.automation_rules.mutated(mutation);
+        let mut triggers = AutomationTrigger::collect(mutation, &[]);
+        while let Some(trigger) = triggers.pop() {
+            if trigger.chain.len() >= system_entities::MAX_AUTOMATION_DEPTH {
+                #[cfg(feature = "log")]
+                warning!(
+                    "AutomationRule chain {:?} reached the maximum depth",
+                    trigger.chain
+                );
+                continue;
+            }
+            let rules = match self
+                .automation_rules
+                .triggered_by(&trigger.room_id, &trigger.entity, self)
+                .await
+            {
+                Ok(rules) => rules,
+                Err(_e) => {
+                    #[cfg(feature = "log")]
+                    error!("AutomationRule, Error: {_e}");
+                    continue;
+                }
+            };
 
-        result
+            for rule in rules {
+                //a rule is never triggered by its own consequences
+                if trigger.chain.contains(&rule.id) {
+                    continue;
+                }
+                if let Err(_e) = self.validate_automation_rule(&rule.mutation).await {
+                    #[cfg(feature = "log")]
+                    error!("AutomationRule {}, Error: {_e}", rule.id);
+                    continue;
+                }
+                let mut parameters = Parameters::new();
+                let _ = parameters.add("id", uid_encode(&trigger.id));
+                let _ = parameters.add("room_id", uid_encode(&trigger.room_id));
+
+                match self.send_mutation(rule.mutation, parameters).await {
+                    Ok(result) => {
+                        let mut chain = trigger.chain.clone();
+                        chain.push(rule.id);
+                        triggers.extend(AutomationTrigger::collect(&result, &chain));
+                    }
+                    Err(_e) => {
+                        #[cfg(feature = "log")]
+                        error!("AutomationRule {}, Error: {_e}", rule.id);
+                    }
+                }
+            }
+        }
     }
 
     ///
@@ -317,6 +823,74 @@ impl GraphDatabaseService {
         }
     }
 
+    ///
+    /// Performs the same mutation for every parameter set in a single transaction
+    /// returns the mutations in the order of the parameters
+    ///
+    pub async fn mutate_batch(
+        &self,
+        mutate: &str,
+        parameters: Vec<Parameters>,
+    ) -> Result<Vec<MutationQuery>> {
+        if parameters.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (reply, receive) = oneshot::channel::<Result<Vec<MutationQuery>>>();
+        let msg = DbMessage::MutateBatch(mutate.to_string(), parameters, reply);
+        let _ = self.sender.send(msg).await;
+        let mutations = receive.await??;
+        for mutation in &mutations {
+            self.apply_automation_rules(mutation).await;
+        }
+        Ok(mutations)
+    }
+
+    ///
+    /// Writes the mutations and deletions in a single transaction, nothing is written if one of them is rejected
+    /// returns the queries in the order of the operations
+    ///
+    pub async fn transaction(
+        &self,
+        operations: Vec<TransactionOperation>,
+    ) -> Result<Vec<TransactionQuery>> {
+        if operations.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (reply, receive) = oneshot::channel::<Result<Vec<TransactionQuery>>>();
+        let msg = DbMessage::Transaction(operations, reply);
+        let _ = self.sender.send(msg).await;
+        let queries = receive.await??;
+        for query in &queries {
+            match query {
+                TransactionQuery::Mutation(mutation) => self.apply_automation_rules(mutation).await,
+                TransactionQuery::Deletion(deletion) => self.automation_rules.deleted(deletion),
+            }
+        }
+        Ok(queries)
+    }
+
+    ///
+    /// Performs the mutation only if no mutation was applied with the same seed name
+    /// returns false if the seed was allready applied
+    ///
+    pub async fn seed(
+        &self,
+        name: &str,
+        mutate: &str,
+        param_opt: Option<Parameters>,
+    ) -> Result<bool> {
+        let (reply, receive) = oneshot::channel::<Result<bool>>();
+        let msg = DbMessage::Seed(
+            name.to_string(),
+            mutate.to_string(),
+            param_opt.unwrap_or_default(),
+            reply,
+        );
+        let _ = self.sender.send(msg).await;
+
+        receive.await?
+    }
+
     ///
     /// Allow to send a stream of mutation. Usefull for batch insertion as you do have to wait for the mutation to finished before sending another.
     ///
@@ -336,7 +910,49 @@ impl GraphDatabaseService {
                 );
                 let _ = dbsender.send(msg).await;
             }
-            let _ = dbsender.send(DbMessage::ComputeDailyLog()).await;
+        });
+        (send, recv_res)
+    }
+
+    ///
+    /// Like *mutation_stream()*, but each mutation is sent with a sequence id that is returned with its result.
+    ///
+    /// Mutations are still processed concurrently, but the results are delivered in the order of the submissions.
+    ///
+    pub fn ordered_mutation_stream(
+        &self,
+    ) -> (
+        mpsc::Sender<(u64, String, Option<Parameters>)>,
+        OrderedMutateReceiver,
+    ) {
+        let (send, mut recv) = mpsc::channel::<(u64, String, Option<Parameters>)>(2);
+        let (send_res, recv_res) = mpsc::channel::<(u64, Result<MutationQuery>)>(2);
+        //the pending results in submission order, the channel size limits the mutations in flight
+        let (send_pending, mut recv_pending) =
+            mpsc::channel::<(u64, mpsc::Receiver<Result<MutationQuery>>)>(ORDERED_STREAM_IN_FLIGHT);
+        let dbsender = self.sender.clone();
+        tokio::spawn(async move {
+            while let Some((sequence, mutate, param_opt)) = recv.recv().await {
+                let (reply, receive) = mpsc::channel::<Result<MutationQuery>>(1);
+                let msg = DbMessage::MutateStream(mutate, param_opt.unwrap_or_default(), reply);
+                let _ = dbsender.send(msg).await;
+                if send_pending.send((sequence, receive)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        tokio::spawn(async move {
+            while let Some((sequence, mut receive)) = recv_pending.recv().await {
+                let result = match receive.recv().await {
+                    Some(result) => result,
+                    None => Err(Error::ChannelSend(
+                        "mutation dropped before being written".to_string(),
+                    )),
+                };
+                if send_res.send((sequence, result)).await.is_err() {
+                    break;
+                }
+            }
         });
         (send, recv_res)
     }
@@ -351,19 +967,84 @@ impl GraphDatabaseService {
         receive.await?
     }
 
-    //
-    // Perform a SQL Selection query on the database
-    // SQL mutation query are forbidden
-    //
-    // #[cfg(test)]
-    // pub async fn select<T: Send + Sized + 'static>(
-    //     &self,
-    //     query: String,
-    //     params: Vec<Box<dyn rusqlite::ToSql + Sync + Send>>,
-    //     mapping: super::sqlite_database::RowMappingFn<T>,
-    // ) -> Result<Vec<T>> {
-    //     let (reply, receive) = oneshot::channel::<Result<Vec<T>>>();
-
+    ///
+    /// Checks that at least one entity matches the filters without reading the entities
+    /// - entity: the entity name, e.g. "ns.Person"
+    /// - filters: the content of the entity parameters, e.g. "name=$name, room_id=$room_id"
+    ///
+    pub async fn exists(
+        &self,
+        entity: &str,
+        filters: &str,
+        param_opt: Option<Parameters>,
+    ) -> Result<bool> {
+        let res = self.head(entity, filters, true, param_opt).await?;
+        Ok(res > 0)
+    }
+
+    ///
+    /// Number of entities matching the filters, see *exists()*
+    ///
+    pub async fn count(
+        &self,
+        entity: &str,
+        filters: &str,
+        param_opt: Option<Parameters>,
+    ) -> Result<u64> {
+        let res = self.head(entity, filters, false, param_opt).await?;
+        Ok(res as u64)
+    }
+
+    async fn head(
+        &self,
+        entity: &str,
+        filters: &str,
+        exists: bool,
+        param_opt: Option<Parameters>,
+    ) -> Result<i64> {
+        let query = format!("query {{ {}({}) {{ id }} }}", entity, filters);
+        let (reply, receive) = oneshot::channel::<Result<i64>>();
+        let msg = DbMessage::Head(query, exists, param_opt.unwrap_or_default(), reply);
+        let _ = self.sender.send(msg).await;
+        receive.await?
+    }
+
+    ///
+    /// Name of the entities read by a query
+    ///
+    pub async fn query_entities(&self, query: &str) -> Result<HashSet<String>> {
+        let (reply, receive) = oneshot::channel::<Result<HashSet<String>>>();
+        let msg = DbMessage::QueryEntities(query.to_string(), reply);
+        let _ = self.sender.send(msg).await;
+        receive.await?
+    }
+
+    ///
+    /// Perform a query and returns the result with the requested encoding
+    ///
+    pub async fn query_bytes(
+        &self,
+        query: &str,
+        param_opt: Option<Parameters>,
+        encoding: Encoding,
+    ) -> Result<Vec<u8>> {
+        let result = self.query(query, param_opt).await?;
+        encode_result(result, encoding)
+    }
+
+    //
+    // Perform a SQL Selection query on the database
+    // SQL mutation query are forbidden
+    //
+    // #[cfg(test)]
+    // pub async fn select<T: Send + Sized + 'static>(
+    //     &self,
+    //     query: String,
+    //     params: Vec<Box<dyn rusqlite::ToSql + Sync + Send>>,
+    //     mapping: super::sqlite_database::RowMappingFn<T>,
+    // ) -> Result<Vec<T>> {
+    //     let (reply, receive) = oneshot::channel::<Result<Vec<T>>>();
+
     //     self.db
     //         .reader
     //         .send_async(Box::new(move |conn| {
@@ -388,6 +1069,53 @@ impl GraphDatabaseService {
         self.datamodel().await
     }
 
+    ///
+    /// Applies a confirmed data model proposal, unless a more recent proposal has already been applied.
+    /// returns true if the data model has been updated
+    ///
+    pub async fn apply_data_model_proposal(&self, datamodel: &str, date: i64) -> Result<bool> {
+        let (reply, receive) = oneshot::channel::<Result<bool>>();
+        let msg = DbMessage::DataModelProposal(datamodel.to_string(), date, reply);
+        let _ = self.sender.send(msg).await;
+        receive.await?
+    }
+
+    ///
+    /// Applies a data model template, unless a more recent version of the template has already been applied.
+    /// returns true if the data model has been updated
+    ///
+    pub async fn apply_data_model_template(
+        &self,
+        datamodel: &str,
+        source: TemplateSource,
+    ) -> Result<bool> {
+        let (reply, receive) = oneshot::channel::<Result<bool>>();
+        let msg = DbMessage::DataModelTemplate(datamodel.to_string(), source, reply);
+        let _ = self.sender.send(msg).await;
+        receive.await?
+    }
+
+    ///
+    /// The data model template applied to the data model, if any
+    ///
+    pub async fn data_model_template_source(&self) -> Result<Option<TemplateSource>> {
+        let (reply, receive) = oneshot::channel::<Option<TemplateSource>>();
+        let msg = DbMessage::DataModelTemplateSource(reply);
+        let _ = self.sender.send(msg).await;
+        Ok(receive.await?)
+    }
+
+    ///
+    /// Checks that a data model update is valid without applying it
+    /// returns the JSON representation of the resulting data model
+    ///
+    pub async fn preflight_data_model(&self, datamodel: &str) -> Result<String> {
+        let (reply, receive) = oneshot::channel::<Result<String>>();
+        let msg = DbMessage::DataModelPreflight(datamodel.to_string(), reply);
+        let _ = self.sender.send(msg).await;
+        receive.await?
+    }
+
     ///
     /// Update the existing data model definition with a new one  
     ///
@@ -398,6 +1126,72 @@ impl GraphDatabaseService {
         receive.await?
     }
 
+    ///
+    /// Write contention statistics gathered during synchronisation since startup
+    ///
+    pub async fn contention_stats(&self) -> Result<Vec<EntityContention>> {
+        let (reply, receive) = oneshot::channel::<Vec<EntityContention>>();
+        self.auth
+            .send(AuthorisationMessage::ContentionStats(reply))
+            .await?;
+        Ok(receive.await?)
+    }
+
+    ///
+    /// The most recent queries that took longer than the slow query threshold, the most recent first
+    ///
+    pub async fn slow_queries(&self) -> Result<Vec<SlowQuery>> {
+        let (reply, receive) = oneshot::channel::<Vec<SlowQuery>>();
+        let _ = self.sender.send(DbMessage::SlowQueries(reply)).await;
+        Ok(receive.await?)
+    }
+
+    ///
+    /// check whether a user is currently allowed to mutate or delete an entity of a room
+    ///
+    pub async fn can(
+        &self,
+        verifying_key: Vec<u8>,
+        room_id: Uid,
+        entity: String,
+        right: RightType,
+    ) -> Result<bool> {
+        let (reply, receive) = oneshot::channel::<bool>();
+        self.auth
+            .send(AuthorisationMessage::Can(
+                verifying_key,
+                room_id,
+                entity,
+                right,
+                reply,
+            ))
+            .await?;
+        Ok(receive.await?)
+    }
+
+    ///
+    /// the current definition of a room, None if the room is unknown
+    ///
+    pub async fn get_room(&self, room_id: Uid) -> Result<Option<Room>> {
+        let (reply, receive) = oneshot::channel::<Option<Room>>();
+        self.auth
+            .send(AuthorisationMessage::GetRoom(room_id, reply))
+            .await?;
+        Ok(receive.await?)
+    }
+
+    ///
+    /// register a room that is private to the user, like the system room
+    /// does nothing if the room already exists
+    ///
+    pub async fn add_private_room(&self, room_id: Uid) -> Result<()> {
+        let (reply, receive) = oneshot::channel::<Result<()>>();
+        self.auth
+            .send(AuthorisationMessage::AddPrivateRoom(room_id, reply))
+            .await?;
+        receive.await?
+    }
+
     ///
     /// insert the node list
     /// returns the list of ids that where not inserted for any reasons (parsing error, authorisations)
@@ -409,6 +1203,16 @@ impl GraphDatabaseService {
         receive.await?
     }
 
+    ///
+    /// waits for the writer to commit its backlog when it exceeds the *write_backlog_limit* configuration
+    ///
+    pub async fn wait_for_write_backlog(&self) {
+        self.db
+            .writer
+            .wait_for_backlog(self.write_backlog_limit)
+            .await;
+    }
+
     ///
     /// insert the edge list
     /// returns the list of ids that where not inserted for any reasons (parsing error, authorisations)
@@ -434,13 +1238,13 @@ impl GraphDatabaseService {
     /// sign a byte array
     /// returns  
     ///
-    pub async fn sign(&self, data: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
-        let (reply, receive) = oneshot::channel::<(Vec<u8>, Vec<u8>)>();
+    pub async fn sign(&self, data: Vec<u8>) -> Result<(Vec<u8>, Vec<u8>)> {
+        let (reply, receive) = oneshot::channel::<Result<(Vec<u8>, Vec<u8>)>>();
         let _ = self
             .auth
             .send(AuthorisationMessage::Sign(data, reply))
             .await;
-        receive.await.unwrap()
+        receive.await?
     }
 
     ///
@@ -519,8 +1323,7 @@ impl GraphDatabaseService {
                     .db
                     .reader
                     .send_async(Box::new(move |conn| {
-                        let error = DailyLog::sort_rooms(&room_ids, buffer_size, &creply, conn)
-                            .map_err(Error::from);
+                        let error = DailyLog::sort_rooms(&room_ids, buffer_size, &creply, conn);
                         if let Err(error) = error {
                             let _ = creply.blocking_send(Err(error));
                         }
@@ -541,12 +1344,19 @@ impl GraphDatabaseService {
     ///
     /// get the most recent log and the last definition modification date
     ///
-    pub async fn get_room_definition(&self, room_id: Uid) -> Result<Option<RoomDefinitionLog>> {
+    /// the log is the one seen by the reader when provided, see *ReaderView*
+    ///
+    pub async fn get_room_definition(
+        &self,
+        room_id: Uid,
+        reader: Option<Vec<u8>>,
+    ) -> Result<Option<RoomDefinitionLog>> {
         let (reply, receive) = oneshot::channel::<Result<Option<RoomDefinitionLog>>>();
         self.db
             .reader
             .send_async(Box::new(move |conn| {
-                let room_log = RoomDefinitionLog::get(&room_id, conn).map_err(Error::from);
+                let room_log =
+                    RoomDefinitionLog::get(&room_id, reader.as_deref(), conn).map_err(Error::from);
                 let _ = reply.send(room_log);
             }))
             .await?;
@@ -554,9 +1364,425 @@ impl GraphDatabaseService {
     }
 
     ///
-    /// get the complete dayly log for a specific room
+    /// copies the readable rows of the database in a new file that will be used at the next startup
+    ///
+    pub async fn attempt_recovery(&self) -> Result<usize> {
+        let (reply, receive) = oneshot::channel::<Result<usize>>();
+        let _ = self.sender.send(DbMessage::AttemptRecovery(reply)).await;
+        receive.await?
+    }
+
+    ///
+    /// writes a backup in the backup directory and applies the rotation
+    ///
+    pub async fn backup(&self) -> Result<BackupInfo> {
+        let (reply, receive) = oneshot::channel::<Result<BackupInfo>>();
+        let _ = self.sender.send(DbMessage::Backup(reply)).await;
+        receive.await?
+    }
+
+    ///
+    /// moves the database files in a new data folder
+    /// returns the previous data folder
+    ///
+    pub async fn relocate(&self, data_folder: PathBuf) -> Result<PathBuf> {
+        let (reply, receive) = oneshot::channel::<Result<PathBuf>>();
+        let _ = self
+            .sender
+            .send(DbMessage::Relocate(data_folder, reply))
+            .await;
+        receive.await?
+    }
+
+    pub async fn backup_status(&self) -> Result<BackupStatus> {
+        let (reply, receive) = oneshot::channel::<Result<BackupStatus>>();
+        let _ = self.sender.send(DbMessage::BackupStatus(reply)).await;
+        receive.await?
+    }
+
+    ///
+    /// flushes the pending writes and stops processing the database messages
+    /// the following calls will fail
+    ///
+    pub async fn shutdown(&self) -> Result<()> {
+        let (reply, receive) = oneshot::channel::<Result<()>>();
+        if self.sender.send(DbMessage::Shutdown(reply)).await.is_err() {
+            //allready stopped
+            return Ok(());
+        }
+        receive.await?
+    }
+
+    ///
+    /// get the last date the room was synchronised with a peer
+    ///
+    pub async fn get_room_sync_date(&self, room_id: Uid) -> Result<Option<i64>> {
+        let (reply, receive) = oneshot::channel::<Result<Option<i64>>>();
+        self.db
+            .reader
+            .send_async(Box::new(move |conn| {
+                let date = RoomSynchronisation::get(&room_id, conn).map_err(Error::from);
+                let _ = reply.send(date);
+            }))
+            .await?;
+        receive.await?
+    }
+
+    pub async fn set_room_sync_date(&self, room_id: Uid, date: i64) -> Result<()> {
+        self.db
+            .writer
+            .write(Box::new(RoomSynchronisation { room_id, date }))
+            .await?;
+        Ok(())
+    }
+
+    ///
+    /// stores the daily logs of a room as seen on a remote peer
+    ///
+    pub async fn set_peer_room_log(
+        &self,
+        room_id: Uid,
+        verifying_key: Vec<u8>,
+        logs: Vec<DailyLog>,
+    ) -> Result<()> {
+        self.db
+            .writer
+            .write(Box::new(PeerRoomLog {
+                room_id,
+                verifying_key,
+                logs,
+            }))
+            .await?;
+        Ok(())
+    }
+
+    ///
+    /// adds the statistics of a connection or a room synchronisation to the stored peer statistics
+    ///
+    pub async fn add_peer_sync_stats(&self, update: PeerSyncUpdate) -> Result<()> {
+        self.db.writer.write(Box::new(update)).await?;
+        Ok(())
+    }
+
+    pub async fn get_peer_sync_stats(
+        &self,
+        verifying_key: Vec<u8>,
+    ) -> Result<Option<PeerSyncStats>> {
+        let (reply, receive) = oneshot::channel::<Result<Option<PeerSyncStats>>>();
+        self.db
+            .reader
+            .send_async(Box::new(move |conn| {
+                let stats = PeerSyncStats::get(&verifying_key, conn).map_err(Error::from);
+                let _ = reply.send(stats);
+            }))
+            .await?;
+        receive.await?
+    }
+
+    ///
+    /// searches the term in the full text index of every application entity
+    ///
+    pub async fn search_all(
+        &self,
+        term: &str,
+        rooms: Vec<Uid>,
+        entities: Vec<String>,
+    ) -> Result<Vec<SearchHit>> {
+        let (reply, receive) = oneshot::channel::<Result<Vec<SearchHit>>>();
+        let _ = self
+            .sender
+            .send(DbMessage::SearchAll(
+                term.to_string(),
+                rooms,
+                entities,
+                reply,
+            ))
+            .await;
+        receive.await?
+    }
+
+    ///
+    /// stores a file in the room and returns its identifier
+    ///
+    /// the identifier is the value of the File fields that reference the file
+    ///
+    pub async fn put_file(&self, room_id: Uid, content: Vec<u8>) -> Result<FileId> {
+        let info = FileInfo::new(&content);
+        let id = info.id();
+        let chunks = content
+            .chunks(FILE_CHUNK_SIZE)
+            .enumerate()
+            .map(|(position, data)| FileChunk {
+                position: position as u32,
+                data: data.to_vec(),
+            })
+            .collect();
+        self.add_file_chunks(room_id, info, chunks).await?;
+        Ok(id)
+    }
+
+    ///
+    /// the content of a file, None if the file is unknown or not fully synchronised
+    ///
+    pub async fn get_file(&self, id: FileId) -> Result<Option<Vec<u8>>> {
+        let (reply, receive) = oneshot::channel::<Result<Option<Vec<u8>>>>();
+        self.db
+            .reader
+            .send_async(Box::new(move |conn| {
+                let _ = reply.send(file::read_file(&id, conn));
+            }))
+            .await?;
+        receive.await?
+    }
+
+    pub async fn get_file_info(&self, room_id: Uid, id: FileId) -> Result<Option<FileInfo>> {
+        let (reply, receive) = oneshot::channel::<Result<Option<FileInfo>>>();
+        self.db
+            .reader
+            .send_async(Box::new(move |conn| {
+                let _ = reply.send(FileInfo::get(&room_id, &id, conn));
+            }))
+            .await?;
+        receive.await?
+    }
+
+    pub async fn get_file_chunks(
+        &self,
+        room_id: Uid,
+        id: FileId,
+        positions: Vec<u32>,
+    ) -> Result<Vec<FileChunk>> {
+        let (reply, receive) = oneshot::channel::<Result<Vec<FileChunk>>>();
+        self.db
+            .reader
+            .send_async(Box::new(move |conn| {
+                let _ = reply.send(FileChunk::read(&room_id, &id, &positions, conn));
+            }))
+            .await?;
+        receive.await?
+    }
+
+    ///
+    /// the positions of the chunks that are not stored yet, None when the file is unknown
     ///
-    pub async fn get_room_log(&self, room_id: Uid) -> mpsc::Receiver<Result<Vec<DailyLog>>> {
+    pub async fn get_missing_chunks(&self, room_id: Uid, id: FileId) -> Result<Option<Vec<u32>>> {
+        let (reply, receive) = oneshot::channel::<Result<Option<Vec<u32>>>>();
+        self.db
+            .reader
+            .send_async(Box::new(move |conn| {
+                let _ = reply.send(FileChunk::missing(&room_id, &id, conn));
+            }))
+            .await?;
+        receive.await?
+    }
+
+    ///
+    /// stores the file description and some of its chunks, the chunks must have been verified
+    ///
+    pub async fn add_file_chunks(
+        &self,
+        room_id: Uid,
+        info: FileInfo,
+        chunks: Vec<FileChunk>,
+    ) -> Result<()> {
+        self.db
+            .writer
+            .write(Box::new(FileWrite {
+                room_id,
+                info,
+                chunks,
+            }))
+            .await?;
+        Ok(())
+    }
+
+    ///
+    /// the files referenced by the File fields of the room that are not completely stored
+    ///
+    pub async fn missing_files(&self, room_id: Uid) -> Result<Vec<FileId>> {
+        let (reply, receive) = oneshot::channel::<Result<Vec<FileId>>>();
+        let _ = self
+            .sender
+            .send(DbMessage::MissingFiles(room_id, reply))
+            .await;
+        receive.await?
+    }
+
+    ///
+    /// true when the file is referenced by a node of the room that the reader can read
+    ///
+    pub async fn is_file_readable(
+        &self,
+        room_id: Uid,
+        id: FileId,
+        reader: Vec<u8>,
+    ) -> Result<bool> {
+        let (reply, receive) = oneshot::channel::<Result<bool>>();
+        let _ = self
+            .sender
+            .send(DbMessage::FileReadable(room_id, id, reader, reply))
+            .await;
+        receive.await?
+    }
+
+    ///
+    /// the number of nodes of the entity in the room and the modification date of the most recent one
+    ///
+    pub async fn entity_counter(&self, room_id: Uid, entity: &str) -> Result<EntityCounter> {
+        let (reply, receive) = oneshot::channel::<Result<EntityCounter>>();
+        let _ = self
+            .sender
+            .send(DbMessage::EntityCounter(room_id, entity.to_string(), reply))
+            .await;
+        receive.await?
+    }
+
+    ///
+    /// adds the duration of a room synchronisation to the telemetry
+    ///
+    pub async fn add_synchronisation_duration(&self, duration_in_ms: u64) {
+        let _ = self
+            .sender
+            .send(DbMessage::SynchronisationDuration(duration_in_ms))
+            .await;
+    }
+
+    ///
+    /// the telemetry report, None when the telemetry is disabled
+    ///
+    pub async fn telemetry(&self, reset: bool) -> Result<Option<TelemetryReport>> {
+        let (reply, receive) = oneshot::channel::<Result<Option<TelemetryReport>>>();
+        let _ = self.sender.send(DbMessage::Telemetry(reset, reply)).await;
+        receive.await?
+    }
+
+    ///
+    /// estimates the number of peers holding a copy of each entity and day of the room
+    ///
+    pub async fn availability(&self, room_id: Uid) -> Result<Vec<DataAvailability>> {
+        let (reply, receive) = oneshot::channel::<Result<Vec<DataAvailability>>>();
+        let _ = self
+            .sender
+            .send(DbMessage::Availability(room_id, reply))
+            .await;
+        receive.await?
+    }
+
+    ///
+    /// lists the entity days of the room that differ from the content of a peer during its last synchronisation
+    ///
+    pub async fn room_divergence(
+        &self,
+        room_id: Uid,
+        verifying_key: Vec<u8>,
+    ) -> Result<Vec<DayDivergence>> {
+        let (reply, receive) = oneshot::channel::<Result<Vec<DayDivergence>>>();
+        let _ = self
+            .sender
+            .send(DbMessage::RoomDivergence(room_id, verifying_key, reply))
+            .await;
+        receive.await?
+    }
+
+    ///
+    /// validates a mutation modifying a room without applying it,
+    /// and returns the rights gained and lost by the room members
+    ///
+    pub async fn preview_room_change(
+        &self,
+        room_id: Uid,
+        mutation: &str,
+        param_opt: Option<Parameters>,
+    ) -> Result<RoomChangeImpact> {
+        let (reply, receive) = oneshot::channel::<Result<RoomChangeImpact>>();
+        let _ = self
+            .sender
+            .send(DbMessage::PreviewRoomChange(
+                room_id,
+                mutation.to_string(),
+                param_opt.unwrap_or_default(),
+                reply,
+            ))
+            .await;
+        receive.await?
+    }
+
+    ///
+    /// get the ids of the room nodes older than the horizon that the reader can read
+    ///
+    pub async fn get_snapshot_nodes(
+        &self,
+        room_id: Uid,
+        horizon: i64,
+        reader: Vec<u8>,
+    ) -> mpsc::Receiver<Result<Vec<Uid>>> {
+        let (reply, receive) = mpsc::channel::<Result<Vec<Uid>>>(1);
+        let creply = reply.clone();
+        let buffer_size = self.buffer_size;
+        let errors = self
+            .db
+            .reader
+            .send_async(Box::new(move |conn| {
+                let error =
+                    RoomSnapshot::get_nodes(&room_id, horizon, &reader, buffer_size, &creply, conn);
+                if let Err(error) = error {
+                    let _ = creply.blocking_send(Err(error));
+                }
+            }))
+            .await;
+
+        if let Err(error) = errors {
+            let _ = reply.send(Err(error)).await;
+        }
+        receive
+    }
+
+    ///
+    /// get the edges of the room older than the horizon whose source can be read by the reader
+    ///
+    pub async fn get_snapshot_edges(
+        &self,
+        room_id: Uid,
+        horizon: i64,
+        reader: Vec<u8>,
+    ) -> mpsc::Receiver<Result<Vec<EdgeKey>>> {
+        let (reply, receive) = mpsc::channel::<Result<Vec<EdgeKey>>>(1);
+        let creply = reply.clone();
+        let buffer_size = self.buffer_size;
+        let errors = self
+            .db
+            .reader
+            .send_async(Box::new(move |conn| {
+                let error =
+                    RoomSnapshot::get_edges(&room_id, horizon, &reader, buffer_size, &creply, conn);
+                if let Err(error) = error {
+                    let _ = creply.blocking_send(Err(error));
+                }
+            }))
+            .await;
+
+        if let Err(error) = errors {
+            let _ = reply.send(Err(error)).await;
+        }
+        receive
+    }
+
+    ///
+    /// removes the local nodes and edges that are older than the snapshot horizon and missing from the snapshot
+    ///
+    pub async fn apply_snapshot(&self, snapshot: RoomSnapshot) -> Result<()> {
+        self.db.writer.write(Box::new(snapshot)).await?;
+        Ok(())
+    }
+
+    ///
+    /// get the complete dayly log for a specific room, as seen by the reader when provided
+    ///
+    pub async fn get_room_log(
+        &self,
+        room_id: Uid,
+        reader: Option<Vec<u8>>,
+    ) -> mpsc::Receiver<Result<Vec<DailyLog>>> {
         let (reply, receive) = mpsc::channel::<Result<Vec<DailyLog>>>(1);
         let creply = reply.clone();
         let buffer_size = self.buffer_size;
@@ -564,8 +1790,8 @@ impl GraphDatabaseService {
             .db
             .reader
             .send_async(Box::new(move |conn| {
-                let error = DailyLog::get_room_log(&room_id, buffer_size, &creply, conn)
-                    .map_err(Error::from);
+                let error =
+                    DailyLog::get_room_log(&room_id, reader.as_deref(), buffer_size, &creply, conn);
                 if let Err(error) = error {
                     let _ = creply.blocking_send(Err(error));
                 }
@@ -579,14 +1805,20 @@ impl GraphDatabaseService {
     }
 
     ///
-    /// get the complete dayly log for a specific room
+    /// get the dayly log for a specific room at a specific date, as seen by the reader when provided
     ///
-    pub async fn get_room_log_at(&self, room_id: Uid, date: i64) -> Result<Vec<DailyLog>> {
+    pub async fn get_room_log_at(
+        &self,
+        room_id: Uid,
+        date: i64,
+        reader: Option<Vec<u8>>,
+    ) -> Result<Vec<DailyLog>> {
         let (reply, receive) = oneshot::channel::<Result<Vec<DailyLog>>>();
         self.db
             .reader
             .send_async(Box::new(move |conn| {
-                let room_log = DailyLog::get_room_log_at(&room_id, date, conn).map_err(Error::from);
+                let room_log = DailyLog::get_room_log_at(&room_id, date, reader.as_deref(), conn)
+                    .map_err(Error::from);
                 let _ = reply.send(room_log);
             }))
             .await?;
@@ -684,13 +1916,14 @@ impl GraphDatabaseService {
     }
 
     ///
-    /// get all node id for a room at a specific day
+    /// get all node id for a room at a specific day that the reader can read
     ///
     pub async fn get_room_daily_nodes(
         &self,
         room_id: Uid,
         entity: String,
         date: i64,
+        reader: Vec<u8>,
     ) -> mpsc::Receiver<Result<HashSet<NodeIdentifier>>> {
         let (reply, receive) = mpsc::channel::<Result<HashSet<NodeIdentifier>>>(1);
         let creply = reply.clone();
@@ -703,6 +1936,7 @@ impl GraphDatabaseService {
                     &room_id,
                     entity,
                     date,
+                    &reader,
                     buffer_size,
                     &creply,
                     conn,
@@ -729,7 +1963,7 @@ impl GraphDatabaseService {
         self.db
             .reader
             .send_async(Box::new(move |conn| {
-                match Node::filter_existing(&mut node_ids, conn).map_err(Error::from) {
+                match Node::filter_existing(&mut node_ids, conn) {
                     Ok(v) => {
                         let _ = reply.send(Ok(v));
                     }
@@ -749,8 +1983,8 @@ impl GraphDatabaseService {
         &self,
         room_id: Uid,
         node_ids: Vec<Uid>,
-    ) -> mpsc::Receiver<Result<Vec<Node>>> {
-        let (reply, receive) = mpsc::channel::<Result<Vec<Node>>>(1);
+    ) -> mpsc::Receiver<Result<NodeBatch>> {
+        let (reply, receive) = mpsc::channel::<Result<NodeBatch>>(1);
         let creply = reply.clone();
         let buffer_size = self.buffer_size;
 
@@ -772,12 +2006,64 @@ impl GraphDatabaseService {
     }
 
     ///
-    /// get full node definition
+    /// get the binaries of the *BinaryStore* that are already stored locally
+    ///
+    pub async fn get_binaries(&self, hashes: Vec<Vec<u8>>) -> Result<HashMap<Vec<u8>, Vec<u8>>> {
+        let (reply, receive) = oneshot::channel::<Result<HashMap<Vec<u8>, Vec<u8>>>>();
+        self.db
+            .reader
+            .send_async(Box::new(move |conn| {
+                let _ = reply.send(BinaryStore::get(&hashes, conn).map_err(Error::from));
+            }))
+            .await?;
+        receive.await?
+    }
+
+    ///
+    /// get the binaries requested by a peer, only those used by a node that the reader can read are returned
+    ///
+    pub async fn get_room_binaries(
+        &self,
+        room_id: Uid,
+        hashes: Vec<Vec<u8>>,
+        reader: Vec<u8>,
+    ) -> mpsc::Receiver<Result<Vec<StoredBinary>>> {
+        let (reply, receive) = mpsc::channel::<Result<Vec<StoredBinary>>>(1);
+        let creply = reply.clone();
+        let buffer_size = self.buffer_size;
+
+        let errors = self
+            .db
+            .reader
+            .send_async(Box::new(move |conn| {
+                let error = BinaryStore::filtered_by_room(
+                    &room_id,
+                    hashes,
+                    &reader,
+                    buffer_size,
+                    &creply,
+                    conn,
+                );
+
+                if let Err(error) = error {
+                    let _ = creply.blocking_send(Err(error));
+                }
+            }))
+            .await;
+        if let Err(error) = errors {
+            let _ = reply.send(Err(error)).await;
+        }
+        receive
+    }
+
+    ///
+    /// get the edges of the nodes, the edges of the nodes that the reader cannot read are not returned
     ///
     pub async fn get_edges(
         &self,
         room_id: Uid,
         node_ids: Vec<(Uid, i64)>,
+        reader: Vec<u8>,
     ) -> mpsc::Receiver<Result<Vec<Edge>>> {
         let (reply, receive) = mpsc::channel::<Result<Vec<Edge>>>(1);
         let creply = reply.clone();
@@ -787,7 +2073,8 @@ impl GraphDatabaseService {
             .db
             .reader
             .send_async(Box::new(move |conn| {
-                let error = Edge::filtered_by_room(&room_id, node_ids, buffer_size, &creply, conn);
+                let error =
+                    Edge::filtered_by_room(&room_id, node_ids, &reader, buffer_size, &creply, conn);
 
                 if let Err(error) = error {
                     let _ = creply.blocking_send(Err(error));
@@ -817,6 +2104,15 @@ impl GraphDatabaseService {
         }
     }
 
+    ///
+    /// replaces a sys.Peer node with the more recent version received during a connection handshake
+    ///
+    pub async fn update_peer_node(&self, node: Node) -> Result<()> {
+        let update = PeerUpdate::new(node)?;
+        self.db.writer.write(Box::new(update)).await?;
+        Ok(())
+    }
+
     ///
     /// get sys.Peer node
     ///
@@ -873,14 +2169,82 @@ impl GraphDatabaseService {
         receive
     }
 
-    ///
-    /// retrieve id of users defined in room users but not in the sys.Peer entity
-    ///
-    pub async fn get_allowed_peers(
-        &self,
-        room_id: Uid,
-    ) -> std::result::Result<Vec<AllowedPeer>, crate::Error> {
-        AllowedPeer::get(uid_encode(&room_id), system_entities::Status::Enabled, self).await
+    ///
+    /// retrieve one page of the peer nodes of a room, starting after the 'after' verifying key
+    ///
+    pub async fn peers_for_room_page(
+        &self,
+        room_id: Uid,
+        after: Option<Vec<u8>>,
+    ) -> Result<PeerPage> {
+        let (u_reply, u_receive) = oneshot::channel::<Result<HashSet<Vec<u8>>>>();
+        self.auth
+            .send(AuthorisationMessage::UserForRoom(room_id, u_reply))
+            .await?;
+        let keys = u_receive.await??;
+
+        let (reply, receive) = oneshot::channel::<Result<PeerPage>>();
+        let buffer_size = self.buffer_size;
+        self.db
+            .reader
+            .send_async(Box::new(move |conn| {
+                let _ = reply.send(Peer::get_peers_page(keys, after, buffer_size, conn));
+            }))
+            .await?;
+        receive.await?
+    }
+
+    ///
+    /// retrieve id of users defined in room users but not in the sys.Peer entity
+    ///
+    pub async fn get_allowed_peers(
+        &self,
+        room_id: Uid,
+    ) -> std::result::Result<Vec<AllowedPeer>, crate::Error> {
+        AllowedPeer::get(uid_encode(&room_id), system_entities::Status::Enabled, self).await
+    }
+}
+
+///
+/// empty write used to wait for the completion of the pending writes
+///
+struct Flush {}
+impl Writeable for Flush {
+    fn write(&mut self, _: &rusqlite::Connection) -> std::result::Result<(), rusqlite::Error> {
+        Ok(())
+    }
+}
+
+///
+/// The keys of the local peer
+///
+struct LocalKeys {
+    //None for a read-only observer
+    signing_key: Option<Ed25519SigningKey>,
+    verifying_key: Vec<u8>,
+    database_secret: [u8; 32],
+}
+impl LocalKeys {
+    fn derive(app_key: &str, key_material: &[u8; 32]) -> Self {
+        let signature_key = derive_key(&format!("{} SIGNING_KEY", app_key), key_material);
+        let signing_key = Ed25519SigningKey::create_from(&signature_key);
+        Self {
+            verifying_key: signing_key.export_verifying_key(),
+            signing_key: Some(signing_key),
+            database_secret: derive_key("DATABASE_SECRET", &signature_key),
+        }
+    }
+
+    //the observer cannot sign, its storage secret is only used to encrypt the database
+    fn observer(app_key: &str, verifying_key: &[u8], storage_secret: &[u8; 32]) -> Self {
+        Self {
+            signing_key: None,
+            verifying_key: verifying_key.to_vec(),
+            database_secret: derive_key(
+                &format!("{} OBSERVER_DATABASE_SECRET", app_key),
+                storage_secret,
+            ),
+        }
     }
 }
 
@@ -893,28 +2257,42 @@ struct GraphDatabase {
     query_cache: LruCache<String, QueryCacheEntry>,
     deletion_cache: LruCache<String, Arc<DeletionParser>>,
     verifying_key: Vec<u8>,
+    slow_queries: Arc<Mutex<SlowQueries>>,
+    telemetry: Arc<Mutex<Telemetry>>,
+    database_path: PathBuf,
+    database_secret: [u8; 32],
+    parsing_limits: ParsingLimits,
+    backup: BackupScheduler,
+    future_date_tolerance: i64,
 }
 impl GraphDatabase {
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
         private_room_id: Uid,
-        public_key: &[u8; 32],
+        public_key: Option<&[u8; 32]>,
         model: &str,
-        app_key: &str,
-        key_material: &[u8; 32],
+        keys: LocalKeys,
         data_folder: PathBuf,
         config: &Configuration,
         event_service: EventService,
+        daily_log_sender: mpsc::Sender<DbMessage>,
     ) -> Result<Self> {
-        let signature_key = derive_key(&format!("{} SIGNING_KEY", app_key), key_material);
-
-        let database_secret = derive_key("DATABASE_SECRET", &signature_key);
+        let LocalKeys {
+            signing_key,
+            verifying_key,
+            database_secret,
+        } = keys;
 
         let database_key = derive_key("DATABASE_NAME", &database_secret);
 
-        let signing_key = Ed25519SigningKey::create_from(&signature_key);
-        let verifying_key = signing_key.export_verifying_key();
-        let database_path = build_path(data_folder, &base64_encode(&database_key))?;
+        let database_path =
+            database_location(&config.storage, data_folder, &base64_encode(&database_key))?;
+
+        //a database recovered by attempt_recovery() replaces the corrupted one
+        if config.storage == Storage::File && swap_recovered_database(&database_path)? {
+            #[cfg(feature = "log")]
+            info!("The recovered database replaces the corrupted one");
+        }
 
         let graph_database = Database::start(
             &database_path,
@@ -924,6 +2302,7 @@ impl GraphDatabase {
             config.write_cache_size_in_kb,
             config.write_buffer_length,
             config.enable_database_memory_security,
+            Some(daily_log_sender),
         )?;
 
         let mutation_cache = LruCache::new(NonZeroUsize::new(LRU_SIZE).unwrap());
@@ -936,23 +2315,29 @@ impl GraphDatabase {
         let allowed_uid = derive_uid("ALLOWED_PEER_UID", &database_key);
         let token: MeetingToken = MeetingSecret::derive_token("MEETING_TOKEN", &database_key);
 
-        system_entities::init_allowed_peers(
-            &graph_database,
-            peer_uid,
-            public_key,
-            allowed_uid,
-            private_room_id,
-            token,
-            &signing_key,
-        )
-        .await?;
+        //the observer cannot sign its peer identity and never connects to other peers
+        if let (Some(signing_key), Some(public_key)) = (&signing_key, public_key) {
+            system_entities::init_allowed_peers(
+                &graph_database,
+                peer_uid,
+                public_key,
+                allowed_uid,
+                private_room_id,
+                token,
+                &config.peer_metadata,
+                signing_key,
+            )
+            .await?;
+        }
         // let allowed_peer_uid = derive_uid("ALLOWED_PEER_UID", &public_key);
         // let peer_node = Peer::create(peer_uid, meeting_pub_key);
 
         let mut auth = RoomAuthorisations {
             signing_key,
+            verifying_key: verifying_key.clone(),
             rooms: HashMap::new(),
             max_node_size: config.max_object_size_in_kb * 1024,
+            conflicts: WriteConflicts::new(config.write_conflict_warning_threshold),
         };
 
         // create the system room associated the user
@@ -971,15 +2356,33 @@ impl GraphDatabase {
             query_cache,
             deletion_cache,
             verifying_key,
+            slow_queries: Arc::new(Mutex::new(SlowQueries::new(
+                config.slow_query_threshold_in_ms,
+                config.slow_query_log_size,
+            ))),
+            telemetry: Arc::new(Mutex::new(Telemetry::new(config.enable_telemetry))),
+            database_path,
+            database_secret,
+            parsing_limits: ParsingLimits {
+                max_size: config.max_query_size_in_kb * 1024,
+                max_depth: config.max_query_depth,
+            },
+            backup: BackupScheduler::new(config),
+            future_date_tolerance: config.future_date_tolerance_in_ms,
         };
 
-        database.update_data_model(model).await?;
+        database.update_data_model(model, None, None).await?;
         database.initialise_authorisations().await?;
 
         Ok(database)
     }
 
-    pub async fn update_data_model(&mut self, model: &str) -> Result<String> {
+    pub async fn update_data_model(
+        &mut self,
+        model: &str,
+        proposal_date: Option<i64>,
+        template: Option<TemplateSource>,
+    ) -> Result<String> {
         let (send, recieve) = oneshot::channel::<Result<Option<String>>>();
 
         //load from database
@@ -1007,7 +2410,16 @@ impl GraphDatabase {
         }
 
         self.data_model.update_system(SYSTEM_DATA_MODEL)?;
-        self.data_model.update(model)?;
+        //an empty model keeps the stored one, allowing generic applications to rely on an acquired template
+        if !model.trim().is_empty() {
+            self.data_model.update(model)?;
+        }
+        if let Some(date) = proposal_date {
+            self.data_model.set_proposal_date(date);
+        }
+        if let Some(template) = template {
+            self.data_model.set_template(template);
+        }
 
         let str = serde_json::to_string(&self.data_model)?;
 
@@ -1021,8 +2433,9 @@ impl GraphDatabase {
                     "INSERT OR REPLACE INTO _configuration(key, value) VALUES ('Data Model', ?)";
                 conn.execute(query, [&self.0])?;
 
+                //Geo indexes are R*Tree tables
                 let mut index_exists_stmt = conn.prepare_cached(
-                    "SELECT 1 FROM sqlite_master WHERE type= 'index' AND name = ? ",
+                    "SELECT 1 FROM sqlite_master WHERE type IN ('index', 'table') AND name = ? ",
                 )?;
                 let datamodel = &self.1;
                 for ns in datamodel.namespaces() {
@@ -1033,7 +2446,7 @@ impl GraphDatabase {
                                 .query_row([name], |row| row.get(0))
                                 .optional()?;
                             if node.is_some() {
-                                conn.execute(&format!("DROP INDEX {}", name), [])?;
+                                conn.execute_batch(&to_delete.1.drop_query())?;
                             }
                         }
                         for to_insert in &entity.1.indexes {
@@ -1042,11 +2455,14 @@ impl GraphDatabase {
                                 .query_row([name], |row| row.get(0))
                                 .optional()?;
                             if node.is_none() {
-                                conn.execute(&to_insert.1.create_query(), [])?;
+                                conn.execute_batch(&to_insert.1.create_query())?;
                             }
                         }
                     }
                 }
+
+                #[cfg(feature = "debug_views")]
+                crate::database::debug_views::create_views(conn, datamodel)?;
                 Ok(())
             }
         }
@@ -1056,6 +2472,11 @@ impl GraphDatabase {
             .write(Box::new(Serialized(str.clone(), self.data_model.clone())))
             .await?;
 
+        //cached queries might not be valid anymore
+        self.mutation_cache.clear();
+        self.query_cache.clear();
+        self.deletion_cache.clear();
+
         Ok(str)
     }
 
@@ -1063,7 +2484,9 @@ impl GraphDatabase {
         let (send, recieve) = oneshot::channel::<Result<String>>();
         let cache = self.get_cached_query(RoomAuthorisations::LOAD_QUERY)?;
         let parameters = Parameters::default();
-        self.query(cache.0, cache.1, parameters, send).await;
+        let query_hash = base64_encode(&hash(RoomAuthorisations::LOAD_QUERY.as_bytes()));
+        self.query(cache.0, cache.1, query_hash, parameters, send)
+            .await;
         let result = recieve.await??;
 
         let (send, recieve) = oneshot::channel::<Result<()>>();
@@ -1078,7 +2501,11 @@ impl GraphDatabase {
         let muts = match self.mutation_cache.get(mutation) {
             Some(e) => e.clone(),
             None => {
-                let muts = Arc::new(MutationParser::parse(mutation, &self.data_model)?);
+                let muts = Arc::new(MutationParser::parse_with_limits(
+                    mutation,
+                    &self.data_model,
+                    &self.parsing_limits,
+                )?);
                 self.mutation_cache
                     .push(String::from(mutation), muts.clone());
                 muts
@@ -1102,6 +2529,9 @@ impl GraphDatabase {
                     MutationQuery::execute(&mut parameters, mutation.clone(), conn);
 
                 match mutation_query {
+                    Ok(muta) if muta.replayed.is_some() => {
+                        let _ = reply.send(Ok(muta));
+                    }
                     Ok(muta) => {
                         let msg = AuthorisationMessage::Mutation(muta, reply);
                         let _ = auth_service.send_blocking(msg);
@@ -1114,6 +2544,148 @@ impl GraphDatabase {
             .await;
     }
 
+    pub async fn mutate_batch(
+        &mut self,
+        mutation: Arc<MutationParser>,
+        parameters: Vec<Parameters>,
+        reply: Sender<Result<Vec<MutationQuery>>>,
+    ) {
+        let auth_service = self.auth_service.clone();
+        let _ = self
+            .graph_database
+            .reader
+            .send_async(Box::new(move |conn| {
+                let mut mutations = Vec::with_capacity(parameters.len());
+                for mut parameters in parameters {
+                    match MutationQuery::execute(&mut parameters, mutation.clone(), conn) {
+                        Ok(muta) => mutations.push(muta),
+                        Err(e) => {
+                            let _ = reply.send(Err(e));
+                            return;
+                        }
+                    }
+                }
+                let msg = AuthorisationMessage::MutationBatch(mutations, reply);
+                let _ = auth_service.send_blocking(msg);
+            }))
+            .await;
+    }
+
+    pub async fn transaction(
+        &mut self,
+        operations: Vec<TransactionOperation>,
+        reply: Sender<Result<Vec<TransactionQuery>>>,
+    ) {
+        enum Parsed {
+            Mutation(Arc<MutationParser>, Parameters),
+            Deletion(Arc<DeletionParser>, Parameters),
+        }
+        let mut parsed = Vec::with_capacity(operations.len());
+        for operation in operations {
+            let res = match operation {
+                TransactionOperation::Mutation(query, parameters) => self
+                    .get_cached_mutation(&query)
+                    .map(|mutation| Parsed::Mutation(mutation, parameters)),
+                TransactionOperation::Deletion(query, parameters) => self
+                    .get_cached_deletion(&query)
+                    .map(|deletion| Parsed::Deletion(deletion, parameters)),
+            };
+            match res {
+                Ok(p) => parsed.push(p),
+                Err(err) => {
+                    let _ = reply.send(Err(err));
+                    return;
+                }
+            }
+        }
+
+        let auth_service = self.auth_service.clone();
+        let _ = self
+            .graph_database
+            .reader
+            .send_async(Box::new(move |conn| {
+                let mut queries = Vec::with_capacity(parsed.len());
+                for operation in parsed {
+                    let res = match operation {
+                        Parsed::Mutation(mutation, mut parameters) => {
+                            MutationQuery::execute(&mut parameters, mutation, conn)
+                                .map(TransactionQuery::Mutation)
+                        }
+                        Parsed::Deletion(deletion, mut parameters) => {
+                            DeletionQuery::build(&mut parameters, deletion, conn)
+                                .map(TransactionQuery::Deletion)
+                        }
+                    };
+                    match res {
+                        Ok(query) => queries.push(query),
+                        Err(e) => {
+                            let _ = reply.send(Err(e));
+                            return;
+                        }
+                    }
+                }
+                let msg = AuthorisationMessage::Transaction(queries, reply);
+                let _ = auth_service.send_blocking(msg);
+            }))
+            .await;
+    }
+
+    pub async fn seed(
+        &mut self,
+        name: String,
+        mutation: Arc<MutationParser>,
+        mut parameters: Parameters,
+        reply: Sender<Result<bool>>,
+    ) {
+        let auth_service = self.auth_service.clone();
+        let (mutation_reply, mutation_receive) = oneshot::channel::<Result<MutationQuery>>();
+        let _ = self
+            .graph_database
+            .reader
+            .send_async(Box::new(move |conn| {
+                match MutationQuery::is_seeded(&name, conn) {
+                    Ok(true) => {
+                        let _ = mutation_reply.send(Ok(MutationQuery {
+                            mutate_entities: Vec::new(),
+                            mutation_parser: mutation,
+                            date: now(),
+                            idempotency_key: None,
+                            replayed: None,
+                            seed: Some(name),
+                            seeded: true,
+                            key_mismatch: false,
+                        }));
+                        return;
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        let _ = mutation_reply.send(Err(e.into()));
+                        return;
+                    }
+                }
+                match MutationQuery::execute(&mut parameters, mutation, conn) {
+                    Ok(mut muta) => {
+                        muta.seed = Some(name);
+                        let msg = AuthorisationMessage::Mutation(muta, mutation_reply);
+                        let _ = auth_service.send_blocking(msg);
+                    }
+                    Err(e) => {
+                        let _ = mutation_reply.send(Err(e));
+                    }
+                }
+            }))
+            .await;
+
+        tokio::spawn(async move {
+            let res = match mutation_receive.await {
+                Ok(Ok(muta)) => Ok(!muta.seeded),
+                Ok(Err(e)) => Err(e),
+                Err(e) => Err(e.into()),
+            };
+            let _ = reply.send(res);
+        });
+    }
+
     pub async fn mutate_stream(
         &mut self,
         mutation: Arc<MutationParser>,
@@ -1129,6 +2701,9 @@ impl GraphDatabase {
                     MutationQuery::execute(&mut parameters, mutation.clone(), conn);
 
                 match mutation_query {
+                    Ok(muta) if muta.replayed.is_some() => {
+                        let _ = reply.blocking_send(Ok(muta));
+                    }
                     Ok(muta) => {
                         let msg = AuthorisationMessage::MutationStream(muta, reply);
                         let _ = auth_service.send_blocking(msg);
@@ -1146,47 +2721,473 @@ impl GraphDatabase {
         query: &str,
     ) -> Result<(Arc<QueryParser>, Arc<PreparedQueries>)> {
         if self.query_cache.get(query).is_none() {
-            let parser = QueryParser::parse(query, &self.data_model)?;
-            let prepared_query = Arc::new(PreparedQueries::build(&parser)?);
+            let parser =
+                QueryParser::parse_with_limits(query, &self.data_model, &self.parsing_limits)?;
+            let prepared_query = Arc::new(PreparedQueries::build_for_reader(
+                &parser,
+                Some(&self.verifying_key),
+            )?);
+            let entry = QueryCacheEntry {
+                parser: Arc::new(parser),
+                prepared_query,
+            };
+
+            self.query_cache.push(String::from(query), entry);
+        }
+        let query = self.query_cache.get(query).unwrap();
+        Ok((query.parser.clone(), query.prepared_query.clone()))
+    }
+
+    //
+    // existence checks and counts are cached separately from the regular queries built from the same text
+    //
+    pub fn get_cached_head_query(
+        &mut self,
+        query: &str,
+        exists: bool,
+    ) -> Result<(Arc<QueryParser>, Arc<PreparedQueries>)> {
+        let key = format!("{}:{}", if exists { "exists" } else { "count" }, query);
+        if self.query_cache.get(&key).is_none() {
+            let parser =
+                QueryParser::parse_with_limits(query, &self.data_model, &self.parsing_limits)?;
+            let prepared_query = Arc::new(PreparedQueries::build_head(
+                &parser,
+                Some(&self.verifying_key),
+                exists,
+            )?);
             let entry = QueryCacheEntry {
                 parser: Arc::new(parser),
                 prepared_query,
             };
+            self.query_cache.push(key.clone(), entry);
+        }
+        let query = self.query_cache.get(&key).unwrap();
+        Ok((query.parser.clone(), query.prepared_query.clone()))
+    }
+
+    async fn head(
+        &self,
+        parser: Arc<QueryParser>,
+        sql_queries: Arc<PreparedQueries>,
+        parameters: Parameters,
+        reply: Sender<Result<i64>>,
+    ) {
+        let mut sql = Query {
+            parameters,
+            parser,
+            sql_queries,
+        };
+        let telemetry = self.telemetry.clone();
+        let event_sender = self.event_service.sender.clone();
+        let corrupted = self.graph_database.writer.corrupted.clone();
+        let _ = self
+            .graph_database
+            .reader
+            .send_async(Box::new(move |conn| {
+                let start = Instant::now();
+                let res = match sql.head(conn) {
+                    Err(Error::Database(e)) if is_corruption(&e) => {
+                        if !corrupted.swap(true, std::sync::atomic::Ordering::Relaxed) {
+                            let _ = event_sender.blocking_send(
+                                EventServiceMessage::DatabaseCorrupted(e.to_string()),
+                            );
+                        }
+                        Err(Error::DatabaseCorrupted(e.to_string()))
+                    }
+                    res => res,
+                };
+                telemetry
+                    .lock()
+                    .unwrap()
+                    .add_query(start.elapsed().as_millis() as u64);
+                let _ = reply.send(res);
+            }))
+            .await;
+    }
+
+    async fn query(
+        &mut self,
+        parser: Arc<QueryParser>,
+        sql_queries: Arc<PreparedQueries>,
+        query_hash: String,
+        parameters: Parameters,
+        reply: Sender<Result<String>>,
+    ) {
+        let mut sql = Query {
+            parameters,
+            parser,
+            sql_queries,
+        };
+        let slow_queries = self.slow_queries.clone();
+        let telemetry = self.telemetry.clone();
+        let event_sender = self.event_service.sender.clone();
+        let corrupted = self.graph_database.writer.corrupted.clone();
+        let _ = self
+            .graph_database
+            .reader
+            .send_async(Box::new(move |conn| {
+                let start = Instant::now();
+                let res = sql.read(conn);
+                let duration = start.elapsed().as_millis() as u64;
+
+                //corruptions are reported once and switch the database to read-only mode
+                let res = match res {
+                    Err(Error::Database(e)) if is_corruption(&e) => {
+                        if !corrupted.swap(true, std::sync::atomic::Ordering::Relaxed) {
+                            let _ = event_sender.blocking_send(
+                                EventServiceMessage::DatabaseCorrupted(e.to_string()),
+                            );
+                        }
+                        Err(Error::DatabaseCorrupted(e.to_string()))
+                    }
+                    res => res,
+                };
+
+                telemetry.lock().unwrap().add_query(duration);
+
+                let mut slow_query = None;
+                if let Ok(result) = &res {
+                    let mut slow_queries = slow_queries.lock().unwrap();
+                    if slow_queries.is_slow(duration) {
+                        let query = SlowQuery {
+                            query_hash,
+                            duration,
+                            rows: SlowQuery::count_rows(result),
+                            date: now(),
+                        };
+                        slow_queries.push(query.clone());
+                        slow_query = Some(query);
+                    }
+                }
+                let _ = reply.send(res);
+                if let Some(query) = slow_query {
+                    let _ = event_sender.blocking_send(EventServiceMessage::SlowQuery(query));
+                }
+            }))
+            .await;
+    }
+
+    async fn missing_files(&self, room_id: Uid, reply: Sender<Result<Vec<FileId>>>) {
+        let fields = self.data_model.file_fields();
+        if fields.is_empty() {
+            let _ = reply.send(Ok(Vec::new()));
+            return;
+        }
+        let _ = self
+            .graph_database
+            .reader
+            .send_async(Box::new(move |conn| {
+                let _ = reply.send(file::missing_files(&room_id, &fields, conn));
+            }))
+            .await;
+    }
+
+    async fn file_readable(
+        &self,
+        room_id: Uid,
+        id: FileId,
+        reader: Vec<u8>,
+        reply: Sender<Result<bool>>,
+    ) {
+        let fields = self.data_model.file_fields();
+        let _ = self
+            .graph_database
+            .reader
+            .send_async(Box::new(move |conn| {
+                let _ = reply.send(file::readable_by(&room_id, &id, &fields, &reader, conn));
+            }))
+            .await;
+    }
+
+    async fn entity_counter(
+        &self,
+        room_id: Uid,
+        entity: String,
+        reply: Sender<Result<EntityCounter>>,
+    ) {
+        let short_name = match self.data_model.get_entity(&entity) {
+            Ok(entity) => entity.short_name.clone(),
+            Err(err) => {
+                let _ = reply.send(Err(err.into()));
+                return;
+            }
+        };
+        let _ = self
+            .graph_database
+            .reader
+            .send_async(Box::new(move |conn| {
+                let counter = EntityCounter::get(&room_id, &short_name, conn).map_err(Error::from);
+                let _ = reply.send(counter);
+            }))
+            .await;
+    }
+
+    async fn search_all(
+        &self,
+        term: String,
+        rooms: Vec<Uid>,
+        entities: Vec<String>,
+        reply: Sender<Result<Vec<SearchHit>>>,
+    ) {
+        let mut searched = self.data_model.full_text_entities();
+        //an empty list searches every entity
+        if !entities.is_empty() {
+            let mut shorts = HashSet::with_capacity(entities.len());
+            for name in &entities {
+                match self.data_model.get_entity(name) {
+                    Ok(entity) if entity.enable_full_text => {
+                        shorts.insert(entity.short_name.clone());
+                    }
+                    Ok(_) => {
+                        let _ = reply.send(Err(Error::from(
+                            crate::database::query_language::Error::InvalidQuery(format!(
+                                "entity '{}' is not indexed for full text search",
+                                name
+                            )),
+                        )));
+                        return;
+                    }
+                    Err(e) => {
+                        let _ = reply.send(Err(Error::from(e)));
+                        return;
+                    }
+                }
+            }
+            searched.retain(|(short, _)| shorts.contains(short));
+        }
+        let entities = searched;
+        let soft_delete: Vec<String> = entities
+            .iter()
+            .filter(|(_, name)| {
+                self.data_model
+                    .get_entity(name)
+                    .is_ok_and(|entity| entity.soft_delete)
+            })
+            .map(|(short, _)| short.clone())
+            .collect();
+        let verifying_key = self.verifying_key.clone();
+        let _ = self
+            .graph_database
+            .reader
+            .send_async(Box::new(move |conn| {
+                let _ = reply.send(search::search_all(
+                    &term,
+                    &rooms,
+                    &entities,
+                    &soft_delete,
+                    &verifying_key,
+                    conn,
+                ));
+            }))
+            .await;
+    }
+
+    async fn telemetry(&self, reset: bool, reply: Sender<Result<Option<TelemetryReport>>>) {
+        if !self.telemetry.lock().unwrap().is_enabled() {
+            let _ = reply.send(Ok(None));
+            return;
+        }
+        let telemetry = self.telemetry.clone();
+        let _ = self
+            .graph_database
+            .reader
+            .send_async(Box::new(move |conn| {
+                let res = telemetry::database_size(conn)
+                    .map(|size| Some(telemetry.lock().unwrap().report(size, reset)));
+                let _ = reply.send(res);
+            }))
+            .await;
+    }
+
+    ///
+    /// the entity short names of the daily logs are replaced by the entity names
+    ///
+    async fn availability(&self, room_id: Uid, reply: Sender<Result<Vec<DataAvailability>>>) {
+        let data_model = self.data_model.clone();
+        let _ = self
+            .graph_database
+            .reader
+            .send_async(Box::new(move |conn| {
+                let res = DataAvailability::list(&room_id, conn).map(|mut list| {
+                    for availability in &mut list {
+                        if let Some(name) = data_model.name_for(&availability.entity) {
+                            availability.entity = name;
+                        }
+                    }
+                    list
+                });
+                let _ = reply.send(res.map_err(Error::from));
+            }))
+            .await;
+    }
+
+    ///
+    /// the impact is computed in a separate task to avoid blocking the database loop while waiting for the authorisation service
+    ///
+    fn preview_room_change(
+        &self,
+        room_id: Uid,
+        mutation: Arc<MutationParser>,
+        parameters: Parameters,
+        reply: Sender<Result<RoomChangeImpact>>,
+    ) {
+        let reader = self.graph_database.reader.clone();
+        let auth_service = self.auth_service.clone();
+        let data_model = self.data_model.clone();
+        tokio::spawn(async move {
+            let res = Self::room_change_impact(
+                room_id,
+                mutation,
+                parameters,
+                reader,
+                auth_service,
+                data_model,
+            )
+            .await;
+            let _ = reply.send(res);
+        });
+    }
 
-            self.query_cache.push(String::from(query), entry);
-        }
-        let query = self.query_cache.get(query).unwrap();
-        Ok((query.parser.clone(), query.prepared_query.clone()))
+    async fn room_change_impact(
+        room_id: Uid,
+        mutation: Arc<MutationParser>,
+        mut parameters: Parameters,
+        reader: DatabaseReader,
+        auth_service: AuthorisationService,
+        data_model: DataModel,
+    ) -> Result<RoomChangeImpact> {
+        let (send, receive) = oneshot::channel::<Result<MutationQuery>>();
+        reader
+            .send_async(Box::new(move |conn| {
+                let _ = send.send(MutationQuery::execute(&mut parameters, mutation, conn));
+            }))
+            .await?;
+        let mutation_query = receive.await??;
+        let date = mutation_query.date;
+
+        let (send, receive) = oneshot::channel::<Result<(Room, Room)>>();
+        auth_service
+            .send(AuthorisationMessage::PreviewRoomChange(
+                room_id,
+                mutation_query,
+                send,
+            ))
+            .await?;
+        let (old_room, new_room) = receive.await??;
+
+        let (send, receive) = oneshot::channel::<Result<RoomChangeImpact>>();
+        reader
+            .send_async(Box::new(move |conn| {
+                let res = RoomChangeImpact::build(&old_room, &new_room, date, &data_model, conn);
+                let _ = send.send(res.map_err(Error::from));
+            }))
+            .await?;
+        receive.await?
     }
 
-    async fn query(
-        &mut self,
-        parser: Arc<QueryParser>,
-        sql_queries: Arc<PreparedQueries>,
-        parameters: Parameters,
-        reply: Sender<Result<String>>,
+    ///
+    /// the entity short names of the daily logs are replaced by the entity names
+    ///
+    async fn room_divergence(
+        &self,
+        room_id: Uid,
+        verifying_key: Vec<u8>,
+        reply: Sender<Result<Vec<DayDivergence>>>,
     ) {
-        let mut sql = Query {
-            parameters,
-            parser,
-            sql_queries,
-        };
-
+        let data_model = self.data_model.clone();
         let _ = self
             .graph_database
             .reader
             .send_async(Box::new(move |conn| {
-                let res = sql.read(conn).map_err(Error::from);
-                let _ = reply.send(res);
+                let res = DayDivergence::list(&room_id, &verifying_key, conn).map(|mut list| {
+                    for divergence in &mut list {
+                        if let Some(name) = data_model.name_for(&divergence.entity) {
+                            divergence.entity = name;
+                        }
+                    }
+                    list
+                });
+                let _ = reply.send(res.map_err(Error::from));
             }))
             .await;
     }
 
+    ///
+    /// salvages the readable rows in a new database file that replaces the corrupted one at the next startup
+    ///
+    fn attempt_recovery(&self, reply: Sender<Result<usize>>) {
+        let path = self.database_path.clone();
+        let secret = self.database_secret;
+        std::thread::spawn(move || {
+            let target = recovered_path(&path);
+            let res = recover_database(&path, &secret, &target);
+            if res.is_err() {
+                let _ = fs::remove_file(&target);
+            }
+            let _ = reply.send(res);
+        });
+    }
+
+    ///
+    /// the snapshot is written by a dedicated thread to avoid blocking the database messages
+    ///
+    fn backup(&self, reply: Sender<Result<BackupInfo>>) {
+        let path = self.database_path.clone();
+        let secret = self.database_secret;
+        let backup = self.backup.clone();
+        std::thread::spawn(move || {
+            let _ = reply.send(backup.backup(&path, &secret));
+        });
+    }
+
+    ///
+    /// the files are moved by a dedicated thread, the database path is updated by the Relocated message
+    ///
+    fn relocate(
+        &self,
+        data_folder: PathBuf,
+        sender: mpsc::Sender<DbMessage>,
+        reply: Sender<Result<PathBuf>>,
+    ) {
+        let path = self.database_path.clone();
+        let secret = self.database_secret;
+        let database = self.graph_database.clone();
+        std::thread::spawn(move || {
+            let file_name = match path.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => {
+                    let _ = reply.send(Err(Error::Relocation("invalid database path".to_string())));
+                    return;
+                }
+            };
+            let subfolder = path.parent().map(PathBuf::from).unwrap_or_default();
+            let previous_folder = subfolder.parent().map(PathBuf::from).unwrap_or_default();
+
+            let res = build_path(data_folder, &file_name)
+                .and_then(|target| database.relocate(&path, &target, &secret).map(|_| target));
+            match res {
+                Ok(target) => {
+                    //only removed when empty, the folder can be shared with other databases
+                    let _ = fs::remove_dir(&subfolder);
+                    let _ =
+                        sender.blocking_send(DbMessage::Relocated(target, previous_folder, reply));
+                }
+                Err(e) => {
+                    let _ = reply.send(Err(e));
+                }
+            }
+        });
+    }
+
     pub fn get_cached_deletion(&mut self, deletion: &str) -> Result<Arc<DeletionParser>> {
         let deletion = match self.deletion_cache.get(deletion) {
             Some(e) => e.clone(),
             None => {
-                let dels = Arc::new(DeletionParser::parse(deletion, &self.data_model)?);
+                let dels = Arc::new(DeletionParser::parse_with_limits(
+                    deletion,
+                    &self.data_model,
+                    &self.parsing_limits,
+                )?);
                 self.deletion_cache
                     .push(String::from(deletion), dels.clone());
                 dels
@@ -1228,6 +3229,8 @@ impl GraphDatabase {
     ) {
         let mut invalid_nodes = Vec::new();
         let mut valid_nodes = Vec::new();
+        let max_date = now() + self.future_date_tolerance;
+        let mut future_dated: HashMap<Vec<u8>, usize> = HashMap::new();
 
         for mut node_to_insert in nodes {
             let node = match node_to_insert.node.as_ref() {
@@ -1238,6 +3241,12 @@ impl GraphDatabase {
                 }
             };
 
+            if node.mdate > max_date || node.cdate > max_date {
+                *future_dated.entry(node.verifying_key.clone()).or_default() += 1;
+                invalid_nodes.push(node_to_insert.id);
+                continue;
+            }
+
             match &node.room_id {
                 Some(r) => {
                     if !room_id.eq(r) {
@@ -1280,6 +3289,8 @@ impl GraphDatabase {
             }
         }
 
+        self.notify_future_dated(room_id, future_dated).await;
+
         let msg = AuthorisationMessage::AddNodes(valid_nodes, invalid_nodes, reply);
         let _ = self.auth_service.send(msg).await;
     }
@@ -1287,8 +3298,15 @@ impl GraphDatabase {
     pub async fn add_edges(&self, room_id: Uid, edges: Vec<Edge>, reply: Sender<Result<Vec<Uid>>>) {
         let mut invalid_edges = Vec::new();
         let mut valid_edges = Vec::new();
+        let max_date = now() + self.future_date_tolerance;
+        let mut future_dated: HashMap<Vec<u8>, usize> = HashMap::new();
 
         for edge in edges {
+            if edge.cdate > max_date {
+                *future_dated.entry(edge.verifying_key.clone()).or_default() += 1;
+                invalid_edges.push(edge.src);
+                continue;
+            }
             let name = match self.data_model.name_for(&edge.src_entity) {
                 Some(e) => e,
                 None => {
@@ -1299,10 +3317,34 @@ impl GraphDatabase {
             valid_edges.push((edge, name));
         }
 
+        self.notify_future_dated(room_id, future_dated).await;
+
         let msg = AuthorisationMessage::AddEdges(room_id, valid_edges, invalid_edges, reply);
         let _ = self.auth_service.send(msg).await;
     }
 
+    //
+    // data dated in the future wins every conflict, it is rejected until the local clock reaches its date
+    //
+    async fn notify_future_dated(&self, room_id: Uid, future_dated: HashMap<Vec<u8>, usize>) {
+        for (verifying_key, rejected) in future_dated {
+            #[cfg(feature = "log")]
+            warning!(
+                "{} future dated nodes or edges signed by {} rejected in room {}",
+                rejected,
+                base64_encode(&verifying_key),
+                uid_encode(&room_id)
+            );
+            self.event_service
+                .notify(EventServiceMessage::FutureDatedData(
+                    room_id,
+                    verifying_key,
+                    rejected,
+                ))
+                .await;
+        }
+    }
+
     pub async fn delete_edges(&self, mut edges: Vec<EdgeDeletionEntry>, reply: Sender<Result<()>>) {
         for edge in &mut edges {
             let entity_name = self.data_model.name_for(&edge.src_entity);
@@ -1313,8 +3355,7 @@ impl GraphDatabase {
             .graph_database
             .reader
             .send_async(Box::new(move |conn| {
-                let edges =
-                    EdgeDeletionEntry::with_source_authors(edges, conn).map_err(Error::from);
+                let edges = EdgeDeletionEntry::with_source_authors(edges, conn);
                 match edges {
                     Ok(edges) => {
                         let msg = AuthorisationMessage::DeleteEdges(edges, reply);
@@ -1338,8 +3379,7 @@ impl GraphDatabase {
             .graph_database
             .reader
             .send_async(Box::new(move |conn| {
-                let nodes =
-                    NodeDeletionEntry::with_previous_authors(nodes, conn).map_err(Error::from);
+                let nodes = NodeDeletionEntry::with_previous_authors(nodes, conn);
                 match nodes {
                     Ok(nodes) => {
                         let msg = AuthorisationMessage::DeleteNodes(nodes, reply);
@@ -1367,6 +3407,31 @@ fn build_path(data_folder: impl Into<PathBuf>, file_name: &String) -> Result<Pat
     path.push(file_name);
     Ok(path)
 }
+
+//
+// in memory databases and custom VFS uses the SQLite URI format
+// the memdb VFS shares the database among every connections of the process when the name starts with '/'
+//
+fn database_location(
+    storage: &Storage,
+    data_folder: impl Into<PathBuf>,
+    file_name: &String,
+) -> Result<PathBuf> {
+    match storage {
+        Storage::File => build_path(data_folder, file_name),
+        Storage::Memory => Ok(PathBuf::from(format!("file:/{}?vfs=memdb", file_name))),
+        Storage::Vfs(vfs) => {
+            let path = build_path(data_folder, file_name)?;
+            let path = path
+                .to_string_lossy()
+                .replace('%', "%25")
+                .replace('?', "%3f")
+                .replace('#', "%23");
+            Ok(PathBuf::from(format!("file:{}?vfs={}", path, vfs)))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -1380,7 +3445,8 @@ mod tests {
 
     use crate::{
         database::query_language::parameter::ParametersAdd,
-        security::{random32, uid_encode},
+        event_service::Event,
+        security::{new_uid, random32, uid_encode},
         ResultParser,
     };
 
@@ -1395,7 +3461,7 @@ mod tests {
         let path: PathBuf = DATA_PATH.into();
         let (app, _, _) = GraphDatabaseService::start(
             "selection app",
-            &data_model,
+            data_model,
             &secret,
             &random32(),
             path,
@@ -1432,6 +3498,96 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn in_memory_storage() {
+        let data_model = "{Person{ name:String }}";
+
+        let secret = random32();
+        let path: PathBuf = "test_data/database/in_memory/".into();
+        let configuration = Configuration {
+            storage: Storage::Memory,
+            ..Default::default()
+        };
+        let (app, _, _) = GraphDatabaseService::start(
+            "in memory app",
+            data_model,
+            &secret,
+            &random32(),
+            path.clone(),
+            &configuration,
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        app.mutate_raw(r#"mutate { Person { name:"Alice" } } "#, None)
+            .await
+            .unwrap();
+
+        let result = app.query("query { Person { name } }", None).await.unwrap();
+        assert_eq!(result, "{\n\"Person\":[{\"name\":\"Alice\"}]\n}");
+
+        //nothing is written in the data folder
+        assert!(!path.exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn observer_is_read_only() {
+        let data_model = "{Person{ name:String }}";
+
+        let configuration = Configuration {
+            storage: Storage::Memory,
+            ..Default::default()
+        };
+        let verifying_key = Ed25519SigningKey::new().export_verifying_key();
+        let app = GraphDatabaseService::start_observer(
+            "observer app",
+            data_model,
+            &verifying_key,
+            &random32(),
+            DATA_PATH.into(),
+            &configuration,
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        let error = app
+            .mutate_raw(r#"mutate { Person { name:"Alice" } } "#, None)
+            .await
+            .expect_err("the observer cannot mutate");
+        assert!(matches!(error, Error::ReadOnly()));
+
+        let mut params = Vec::new();
+        for name in ["Alice", "Bob"] {
+            let mut param = Parameters::new();
+            param.add("name", name).unwrap();
+            params.push(param);
+        }
+        let error = app
+            .mutate_batch("mutate { Person { name:$name } }", params)
+            .await
+            .expect_err("the observer cannot mutate");
+        assert!(matches!(error, Error::ReadOnly()));
+
+        let mut param = Parameters::new();
+        param.add("id", uid_encode(&new_uid())).unwrap();
+        let error = app
+            .delete("delete { Person { $id } }", Some(param))
+            .await
+            .expect_err("the observer cannot delete");
+        assert!(matches!(error, Error::ReadOnly()));
+
+        let error = app
+            .sign(random32().to_vec())
+            .await
+            .expect_err("the observer cannot sign");
+        assert!(matches!(error, Error::ReadOnly()));
+
+        let result = app.query("query { Person { name } }", None).await.unwrap();
+        assert_eq!(result, "{\n\"Person\":[]\n}");
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn delete() {
         init_database_path();
@@ -1442,7 +3598,7 @@ mod tests {
         let path: PathBuf = DATA_PATH.into();
         let (app, _, _) = GraphDatabaseService::start(
             "delete app",
-            &data_model,
+            data_model,
             &secret,
             &random32(),
             path,
@@ -1470,12 +3626,254 @@ mod tests {
         param.add("id", base64_encode(e)).unwrap();
         app.delete("delete {Person{$id}}", Some(param))
             .await
-            .unwrap();
+            .unwrap();
+
+        let result = app
+            .query(
+                "query q {
+            Person{
+                name
+            }
+        }",
+                None,
+            )
+            .await
+            .unwrap();
+        let expected = "{\n\"Person\":[{\"name\":\"Bob\"}]\n}";
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn idempotent_mutation() {
+        init_database_path();
+
+        let data_model = "{Person{ name:String }}";
+
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let (app, _, _) = GraphDatabaseService::start(
+            "idempotent app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        let mutation = r#"
+        mutate @id($key) {
+            Person { name:$name }
+        } "#;
+
+        let mut param = Parameters::new();
+        param.add("key", "client-uuid".to_string()).unwrap();
+        param.add("name", "Alice".to_string()).unwrap();
+        let first = app.mutate(mutation, Some(param)).await.unwrap();
+
+        //the retry is ignored and returns the original result
+        let mut param = Parameters::new();
+        param.add("key", "client-uuid".to_string()).unwrap();
+        param.add("name", "Alice".to_string()).unwrap();
+        let retry = app.mutate(mutation, Some(param)).await.unwrap();
+        assert_eq!(first, retry);
+
+        //reusing the key with different parameters is an error
+        let mut param = Parameters::new();
+        param.add("key", "client-uuid".to_string()).unwrap();
+        param.add("name", "Bob".to_string()).unwrap();
+        let error = app.mutate(mutation, Some(param)).await.unwrap_err();
+        assert!(matches!(error, Error::IdempotencyKeyMismatch(_)));
+
+        //reusing the key with a different mutation is an error
+        let mut param = Parameters::new();
+        param.add("key", "client-uuid".to_string()).unwrap();
+        param.add("name", "Alice".to_string()).unwrap();
+        let error = app
+            .mutate(
+                r#"mutate insert_person @id($key) { Person { name:$name } }"#,
+                Some(param),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::IdempotencyKeyMismatch(_)));
+
+        let mut param = Parameters::new();
+        param.add("key", "another-uuid".to_string()).unwrap();
+        param.add("name", "Bob".to_string()).unwrap();
+        let other = app.mutate(mutation, Some(param)).await.unwrap();
+        assert_ne!(first, other);
+
+        let result = app
+            .query(
+                "query q {
+            Person (order_by(name asc)){
+                name
+            }
+        }",
+                None,
+            )
+            .await
+            .unwrap();
+
+        let expected = "{\n\"Person\":[{\"name\":\"Alice\"},{\"name\":\"Bob\"}]\n}";
+        assert_eq!(result, expected);
+
+        //once the key has expired, the retry is performed again
+        app.db
+            .writer
+            .write(Box::new(IdempotencyKeyPruning { horizon: now() + 1 }))
+            .await
+            .unwrap();
+        let mut param = Parameters::new();
+        param.add("key", "client-uuid".to_string()).unwrap();
+        param.add("name", "Alice".to_string()).unwrap();
+        let retry = app.mutate(mutation, Some(param)).await.unwrap();
+        assert_ne!(first, retry);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn idempotency_key_mismatch_while_writing() {
+        init_database_path();
+
+        let data_model = "{Person{ name:String }}";
+
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let (app, _, _) = GraphDatabaseService::start(
+            "idempotent app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        let mutation = r#"
+        mutate @id($key) {
+            Person { name:$name }
+        } "#;
+
+        //the key is only detected while writing the second mutation: the whole batch is cancelled
+        let mut batch = Vec::new();
+        for name in ["Carol", "Dave"] {
+            let mut param = Parameters::new();
+            param.add("key", "batch-uuid".to_string()).unwrap();
+            param.add("name", name.to_string()).unwrap();
+            batch.push(param);
+        }
+        let error = app.mutate_batch(mutation, batch).await.unwrap_err();
+        assert!(matches!(error, Error::IdempotencyKeyMismatch(_)));
+
+        //concurrent mutations using the same key do not fail the other writes
+        let mut tasks = Vec::new();
+        for i in 0..16 {
+            let app = app.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut param = Parameters::new();
+                param.add("name", format!("{i}")).unwrap();
+                if i % 2 == 0 {
+                    param.add("key", "concurrent-uuid".to_string()).unwrap();
+                    app.mutate(mutation, Some(param)).await.map(|_| true)
+                } else {
+                    app.mutate("mutate { Person { name:$name } }", Some(param))
+                        .await
+                        .map(|_| false)
+                }
+            }));
+        }
+        let mut with_key = 0;
+        let mut without_key = 0;
+        for task in tasks {
+            match task.await.unwrap() {
+                Ok(true) => with_key += 1,
+                Ok(false) => without_key += 1,
+                Err(e) => assert!(matches!(e, Error::IdempotencyKeyMismatch(_))),
+            }
+        }
+        assert_eq!(with_key, 1);
+        assert_eq!(without_key, 8);
+
+        let result = app
+            .query(
+                "query q {
+            Person {
+                name
+            }
+        }",
+                None,
+            )
+            .await
+            .unwrap();
+        let mut parser = ResultParser::new(&result).unwrap();
+        #[derive(Deserialize)]
+        struct Person {
+            name: String,
+        }
+        let persons: Vec<Person> = parser.take_array("Person").unwrap();
+        assert_eq!(persons.len(), 9);
+        assert!(!persons
+            .iter()
+            .any(|p| p.name.eq("Carol") || p.name.eq("Dave")));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn seed_once() {
+        init_database_path();
+
+        let data_model = "{Person{ name:String }}";
+
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let (app, _, _) = GraphDatabaseService::start(
+            "seed app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        let mutation = r#"
+        mutate {
+            Person { name:$name }
+        } "#;
+
+        let mut param = Parameters::new();
+        param.add("name", "Alice".to_string()).unwrap();
+        assert!(app.seed("people", mutation, Some(param)).await.unwrap());
+
+        let mut param = Parameters::new();
+        param.add("name", "Bob".to_string()).unwrap();
+        assert!(!app.seed("people", mutation, Some(param)).await.unwrap());
+
+        let mut param = Parameters::new();
+        param.add("name", "Bob".to_string()).unwrap();
+        assert!(app
+            .seed("other people", mutation, Some(param))
+            .await
+            .unwrap());
+
+        //a failed mutation does not record the seed
+        app.seed("invalid", mutation, None)
+            .await
+            .expect_err("missing parameter");
+        let mut param = Parameters::new();
+        param.add("name", "Carol".to_string()).unwrap();
+        assert!(app.seed("invalid", mutation, Some(param)).await.unwrap());
 
         let result = app
             .query(
                 "query q {
-            Person{
+            Person (order_by(name asc)){
                 name
             }
         }",
@@ -1483,10 +3881,87 @@ mod tests {
             )
             .await
             .unwrap();
-        let expected = "{\n\"Person\":[{\"name\":\"Bob\"}]\n}";
+
+        let expected =
+            "{\n\"Person\":[{\"name\":\"Alice\"},{\"name\":\"Bob\"},{\"name\":\"Carol\"}]\n}";
         assert_eq!(result, expected);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn future_dated_data() {
+        init_database_path();
+
+        let data_model = "{Person{ name:String }}";
+        let events = EventService::new();
+        let mut receiver = events.subcribe().await;
+        let configuration = Configuration {
+            future_date_tolerance_in_ms: 60000,
+            ..Default::default()
+        };
+
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let (app, _, room_id) = GraphDatabaseService::start(
+            "future dated app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &configuration,
+            events,
+        )
+        .await
+        .unwrap();
+
+        let author = random32().to_vec();
+        let future = now() + 3600 * 1000;
+        let node = Node {
+            room_id: Some(room_id),
+            mdate: future,
+            _entity: "0".to_string(),
+            _json: Some(r#"{"32":"Mallory"}"#.to_string()),
+            verifying_key: author.clone(),
+            ..Default::default()
+        };
+        let id = node.id;
+        let rejected = app
+            .add_nodes(
+                room_id,
+                vec![NodeToInsert {
+                    id,
+                    node: Some(node),
+                    ..Default::default()
+                }],
+            )
+            .await
+            .unwrap();
+        assert_eq!(vec![id], rejected);
+
+        let edge = Edge {
+            src: id,
+            src_entity: "0".to_string(),
+            label: "32".to_string(),
+            dest: new_uid(),
+            cdate: future,
+            verifying_key: author.clone(),
+            signature: Vec::new(),
+        };
+        let rejected = app.add_edges(room_id, vec![edge]).await.unwrap();
+        assert_eq!(vec![id], rejected);
+
+        let mut notified = 0;
+        while notified < 2 {
+            if let Event::FutureDatedData(room, verifying_key, rejected) =
+                receiver.recv().await.unwrap()
+            {
+                assert_eq!(uid_encode(&room_id), room);
+                assert_eq!(author, verifying_key);
+                assert_eq!(1, rejected);
+                notified += 1;
+            }
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn load_data_model() {
         init_database_path();
@@ -1503,7 +3978,7 @@ mod tests {
             let path: PathBuf = DATA_PATH.into();
             GraphDatabaseService::start(
                 "load data_model app",
-                &data_model,
+                data_model,
                 &secret,
                 &random32(),
                 path,
@@ -1525,7 +4000,7 @@ mod tests {
             let path: PathBuf = DATA_PATH.into();
             let is_error = GraphDatabaseService::start(
                 "load data_model app",
-                &data_model,
+                data_model,
                 &secret,
                 &random32(),
                 path,
@@ -1548,7 +4023,7 @@ mod tests {
             let path: PathBuf = DATA_PATH.into();
             GraphDatabaseService::start(
                 "load data_model app",
-                &data_model,
+                data_model,
                 &secret,
                 &random32(),
                 path,
@@ -1576,7 +4051,7 @@ mod tests {
             let path: PathBuf = DATA_PATH.into();
             let (_, _, system_room_id) = GraphDatabaseService::start(
                 "app",
-                &data_model,
+                data_model,
                 &secret,
                 &random32(),
                 path,
@@ -1599,7 +4074,7 @@ mod tests {
             let path: PathBuf = DATA_PATH.into();
             let (_, _, system_room_id) = GraphDatabaseService::start(
                 "app",
-                &data_model,
+                data_model,
                 &secret,
                 &random32(),
                 path,
@@ -1623,7 +4098,7 @@ mod tests {
             let path: PathBuf = DATA_PATH.into();
             let (_, _, system_room_id) = GraphDatabaseService::start(
                 "another app",
-                &data_model,
+                data_model,
                 &secret,
                 &random32(),
                 path,
@@ -1648,7 +4123,7 @@ mod tests {
             let path: PathBuf = DATA_PATH.into();
             let (_, _, system_room_id) = GraphDatabaseService::start(
                 "another app",
-                &data_model,
+                data_model,
                 &random32(),
                 &random32(),
                 path,
@@ -1679,7 +4154,7 @@ mod tests {
         let path: PathBuf = DATA_PATH.into();
         let (app, _, system_room_id) = GraphDatabaseService::start(
             "app",
-            &data_model,
+            data_model,
             &secret,
             &random32(),
             path,
@@ -1705,6 +4180,78 @@ mod tests {
         .expect("wildcard auth");
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn geo_index() {
+        init_database_path();
+        let secret = random32();
+        let key_material = random32();
+
+        let mutate = r#"mutate{
+                geo.Place{
+                    name : "Paris"
+                    location : "48.8566,2.3522"
+                }
+            }"#;
+        let query = r#"query{
+                geo.Place(radius(location, 48.85, 2.35, 1000)){
+                    name
+                }
+            }"#;
+        {
+            let data_model = "
+                geo {
+                    Place{
+                        name:String,
+                        location: Geo,
+                        index(location)
+                    }
+                }";
+            let path: PathBuf = DATA_PATH.into();
+            let (app, _, _) = GraphDatabaseService::start(
+                "app",
+                data_model,
+                &secret,
+                &key_material,
+                path,
+                &Configuration::default(),
+                EventService::new(),
+            )
+            .await
+            .unwrap();
+            app.mutate(mutate, None).await.unwrap();
+            let result = app.query(query, None).await.unwrap();
+            assert_eq!("{\n\"geo.Place\":[{\"name\":\"Paris\"}]\n}", result);
+        }
+        {
+            //the R*Tree table and its triggers are removed with the index
+            let data_model = "
+                geo {
+                    Place{
+                        name:String,
+                        location: Geo,
+                    }
+                }";
+            let path: PathBuf = DATA_PATH.into();
+            let (app, _, _) = GraphDatabaseService::start(
+                "app",
+                data_model,
+                &secret,
+                &key_material,
+                path,
+                &Configuration::default(),
+                EventService::new(),
+            )
+            .await
+            .unwrap();
+            app.mutate(mutate, None).await.unwrap();
+            let result = app.query(query, None).await.unwrap();
+            assert_eq!(
+                "{\n\"geo.Place\":[{\"name\":\"Paris\"},{\"name\":\"Paris\"}]\n}",
+                result
+            );
+        }
+    }
+
     //
     // issue occured when updating entity sys.Peer to set a name.
     // this performs some deletion on the  _node_fts index that did not exits, causing an horrible:'database disk image is malformed'
@@ -1718,7 +4265,7 @@ mod tests {
         let path: PathBuf = DATA_PATH.into();
         let (app, verifying_key, system_room_id) = GraphDatabaseService::start(
             "app",
-            &data_model,
+            data_model,
             &secret,
             &random32(),
             path,
@@ -1791,7 +4338,7 @@ mod tests {
         let path: PathBuf = DATA_PATH.into();
         let (app, _, _) = GraphDatabaseService::start(
             "app",
-            &data_model,
+            data_model,
             &secret,
             &random32(),
             path,
@@ -1848,4 +4395,357 @@ mod tests {
         assert_eq!(all.a_int, a_int);
         assert_eq!(all.a_bool, a_bool);
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn search_all() {
+        init_database_path();
+
+        let data_model = "chat{
+            Message{ content:String }
+            Attachment(no_full_text_index){ data:String }
+            Note{ title:String }
+            Draft(soft_delete){ text:String }
+        }";
+
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let (app, _, private_room) = GraphDatabaseService::start(
+            "search app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        let mut param = Parameters::new();
+        param.add("room_id", uid_encode(&private_room)).unwrap();
+        app.mutate(
+            r#"
+        mutate {
+            chat.Message { room_id:$room_id content:"Meet at the Lighthouse tomorrow" }
+            chat.Attachment { room_id:$room_id data:"lighthouse.png" }
+        } "#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+        app.mutate(
+            r#"
+        mutate {
+            M1: chat.Message { content:"A lighthouse without room" }
+            M2: chat.Message { content:"Nothing to see" }
+        } "#,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let hits = app
+            .search_all("lighthouse", Vec::new(), Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(2, hits.len());
+        for hit in &hits {
+            assert_eq!("chat.Message", hit.entity);
+            assert!(hit.snippet.to_lowercase().contains("lighthouse"));
+        }
+
+        let hits = app
+            .search_all("lighthouse", vec![private_room], Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(1, hits.len());
+        assert_eq!(Some(uid_encode(&private_room)), hits[0].room_id);
+        assert_eq!("Meet at the Lighthouse tomorrow", hits[0].snippet);
+
+        let hits = app
+            .search_all("missing", Vec::new(), Vec::new())
+            .await
+            .unwrap();
+        assert!(hits.is_empty());
+
+        let mut param = Parameters::new();
+        param.add("room_id", uid_encode(&private_room)).unwrap();
+        app.mutate(
+            r#"
+        mutate {
+            N1: chat.Note { room_id:$room_id title:"Lighthouse, lighthouse keeper" }
+            N2: chat.Note { title:"The lighthouse of the other room" }
+        } "#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+
+        //the hits of the entities of the room are ranked together
+        let hits = app
+            .search_all(
+                "lighthouse",
+                vec![private_room],
+                vec!["chat.Message".to_string(), "chat.Note".to_string()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(2, hits.len());
+        assert_eq!("chat.Note", hits[0].entity);
+        assert_eq!("chat.Message", hits[1].entity);
+        assert!(hits[0].relevance > hits[1].relevance);
+        for hit in &hits {
+            assert_eq!(Some(uid_encode(&private_room)), hit.room_id);
+        }
+
+        let hits = app
+            .search_all("lighthouse", Vec::new(), vec!["chat.Note".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(2, hits.len());
+        assert!(hits.iter().all(|hit| hit.entity.eq("chat.Note")));
+
+        app.search_all(
+            "lighthouse",
+            Vec::new(),
+            vec!["chat.Attachment".to_string()],
+        )
+        .await
+        .expect_err("not indexed");
+        app.search_all("lighthouse", Vec::new(), vec!["chat.Unknown".to_string()])
+            .await
+            .expect_err("unknown entity");
+
+        //soft deleted nodes are not found
+        let draft = app
+            .mutate_raw(r#"mutate { chat.Draft { text:"Lighthouse draft" } }"#, None)
+            .await
+            .unwrap();
+        let draft_id = uid_encode(&draft.mutate_entities[0].node_to_mutate.id);
+        let drafts = vec!["chat.Draft".to_string()];
+        let hits = app
+            .search_all("lighthouse", Vec::new(), drafts.clone())
+            .await
+            .unwrap();
+        assert_eq!(1, hits.len());
+        let mut param = Parameters::new();
+        param.add("id", draft_id).unwrap();
+        app.delete("delete { chat.Draft { $id } }", Some(param))
+            .await
+            .unwrap();
+        let hits = app
+            .search_all("lighthouse", Vec::new(), drafts)
+            .await
+            .unwrap();
+        assert!(hits.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn search_entities_filters() {
+        init_database_path();
+
+        let data_model = "chat{
+            Message{ content:String }
+            Note{ title:String }
+        }";
+
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let (app, verifying_key, _) = GraphDatabaseService::start(
+            "search app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        let mut param = Parameters::new();
+        param.add("user_id", base64_encode(&verifying_key)).unwrap();
+        let room = app
+            .mutate_raw(
+                r#"mutate {
+                    sys.Room{
+                        admin: [{
+                            verif_key:$user_id
+                        }]
+                        authorisations:[{
+                            name:"members"
+                            rights:[{
+                                entity:"chat.Message"
+                                mutate_self:true
+                                mutate_all:true
+                            },{
+                                entity:"chat.Note"
+                                mutate_self:true
+                                mutate_all:true
+                            }]
+                        }]
+                    }
+                }"#,
+                Some(param),
+            )
+            .await
+            .unwrap();
+        let room_id = room.mutate_entities[0].node_to_mutate.id;
+
+        let mut param = Parameters::new();
+        param.add("room_id", uid_encode(&room_id)).unwrap();
+        let inserted = app
+            .mutate_raw(
+                r#"mutate {
+                    M1: chat.Message { room_id:$room_id content:"The lighthouse is closed" }
+                    M2: chat.Message { room_id:$room_id content:"Spam about a lighthouse" }
+                    N1: chat.Note { room_id:$room_id title:"Lighthouse notes" }
+                }"#,
+                Some(param),
+            )
+            .await
+            .unwrap();
+        let spam = uid_encode(&inserted.mutate_entities[1].node_to_mutate.id);
+
+        let messages = vec!["chat.Message".to_string()];
+        let hits = app
+            .search_all("lighthouse", vec![room_id], messages.clone())
+            .await
+            .unwrap();
+        assert_eq!(2, hits.len());
+
+        //the entities targeted by a tombstone are not found
+        system_entities::Tombstone::create(&uid_encode(&room_id), &spam, Some("spam"), &app)
+            .await
+            .unwrap();
+        let hits = app
+            .search_all("lighthouse", vec![room_id], messages)
+            .await
+            .unwrap();
+        assert_eq!(1, hits.len());
+        assert_eq!("The lighthouse is closed", hits[0].snippet);
+
+        let hits = app
+            .search_all("lighthouse", vec![room_id], Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(2, hits.len());
+        assert!(hits.iter().all(|hit| !hit.id.eq(&spam)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn query_bytes() {
+        init_database_path();
+
+        let data_model = "{Person{ name:String, age:Integer }}";
+
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let (app, _, _) = GraphDatabaseService::start(
+            "query bytes app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        app.mutate(
+            r#"
+        mutate {
+            P1: Person { name:"Alice" age:32 }
+            P2: Person { name:"Bob" age:27 }
+        } "#,
+            None,
+        )
+        .await
+        .unwrap();
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Person {
+            name: String,
+            age: i64,
+        }
+
+        let query = "query {
+            Person(order_by(name asc)){
+                name
+                age
+            }
+        }";
+        let json = app.query(query, None).await.unwrap();
+        let from_json: Vec<Person> = ResultParser::new(&json)
+            .unwrap()
+            .take_array("Person")
+            .unwrap();
+        assert_eq!(2, from_json.len());
+
+        for encoding in [Encoding::Json, Encoding::Cbor] {
+            let bytes = app.query_bytes(query, None, encoding).await.unwrap();
+            let persons: Vec<Person> = ResultParser::from_bytes(&bytes, encoding)
+                .unwrap()
+                .take_array("Person")
+                .unwrap();
+            assert_eq!(from_json, persons);
+            if encoding == Encoding::Cbor {
+                assert!(bytes.len() < json.len());
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn telemetry() {
+        init_database_path();
+
+        let data_model = "{Person{ name:String }}";
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let (app, _, _) = GraphDatabaseService::start(
+            "telemetry disabled app",
+            data_model,
+            &secret,
+            &random32(),
+            path.clone(),
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+        app.query("query { Person { name } }", None).await.unwrap();
+        assert!(app.telemetry(false).await.unwrap().is_none());
+
+        let configuration = Configuration {
+            enable_telemetry: true,
+            ..Default::default()
+        };
+        let (app, _, _) = GraphDatabaseService::start(
+            "telemetry app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &configuration,
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+        //ignores the queries performed during the startup
+        app.telemetry(true).await.unwrap();
+
+        app.query("query { Person { name } }", None).await.unwrap();
+        app.query("query { Person { name } }", None).await.unwrap();
+        app.add_synchronisation_duration(1500).await;
+
+        let report = app.telemetry(true).await.unwrap().unwrap();
+        assert_eq!(2, report.queries.count);
+        assert_eq!(1, report.synchronisations.under_10s);
+        assert_eq!("<1MB", report.database_size);
+
+        let report = app.telemetry(false).await.unwrap().unwrap();
+        assert_eq!(0, report.queries.count);
+        assert_eq!(0, report.synchronisations.count);
+    }
 }
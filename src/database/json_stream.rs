@@ -0,0 +1,283 @@
+use std::{
+    io::{BufRead, BufReader, Read},
+    marker::PhantomData,
+};
+
+use serde::de::DeserializeOwned;
+
+use super::Error;
+
+///
+/// Iterates over the elements of an array field of a JSON object read from a reader
+///
+/// Only one element is kept in memory: the bytes of an element are copied until its end is found and deserialized before reading the next one.
+/// The other fields of the object are skipped without being stored.
+///
+pub struct ArrayStream<R: Read, T: DeserializeOwned> {
+    reader: JsonReader<R>,
+    field: String,
+    first: bool,
+    done: bool,
+    buffer: Vec<u8>,
+    _entry: PhantomData<T>,
+}
+impl<R: Read, T: DeserializeOwned> ArrayStream<R, T> {
+    ///
+    /// reads the object until the start of the array of the field
+    ///
+    pub fn new(reader: R, field: &str) -> std::result::Result<Self, crate::Error> {
+        let mut reader = JsonReader::new(reader);
+        reader.skip_whitespace()?;
+        if !reader.next_is(b'{')? {
+            return Err(crate::Error::from(Error::InvalidJsonObject("".to_string())));
+        }
+        let mut buffer = Vec::new();
+        loop {
+            reader.skip_whitespace()?;
+            if reader.next_is(b'}')? {
+                return Err(crate::Error::from(Error::MissingJsonField(
+                    field.to_string(),
+                )));
+            }
+            buffer.clear();
+            reader.read_value(Some(&mut buffer))?;
+            let key: String = serde_json::from_slice(&buffer)?;
+
+            reader.skip_whitespace()?;
+            if !reader.next_is(b':')? {
+                return Err(crate::Error::from(Error::InvalidJsonObject(key)));
+            }
+            reader.skip_whitespace()?;
+            if key.eq(field) {
+                if !reader.next_is(b'[')? {
+                    return Err(crate::Error::from(Error::InvalidJSonArray(
+                        field.to_string(),
+                    )));
+                }
+                return Ok(Self {
+                    reader,
+                    field: field.to_string(),
+                    first: true,
+                    done: false,
+                    buffer,
+                    _entry: PhantomData,
+                });
+            }
+            reader.read_value(None)?;
+
+            reader.skip_whitespace()?;
+            if !reader.next_is(b',')? && !reader.next_is(b'}')? {
+                return Err(crate::Error::from(Error::InvalidJsonObject(key)));
+            }
+        }
+    }
+
+    fn next_entry(&mut self) -> std::result::Result<Option<T>, crate::Error> {
+        self.reader.skip_whitespace()?;
+        if self.reader.next_is(b']')? {
+            return Ok(None);
+        }
+        if !self.first && !self.reader.next_is(b',')? {
+            return Err(crate::Error::from(Error::InvalidJSonArray(
+                self.field.clone(),
+            )));
+        }
+        self.first = false;
+        self.reader.skip_whitespace()?;
+        self.buffer.clear();
+        self.reader.read_value(Some(&mut self.buffer))?;
+        let entry: T = serde_json::from_slice(&self.buffer)?;
+        Ok(Some(entry))
+    }
+}
+impl<R: Read, T: DeserializeOwned> Iterator for ArrayStream<R, T> {
+    type Item = std::result::Result<T, crate::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_entry() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+///
+/// finds the boundaries of the JSON values without parsing them
+///
+struct JsonReader<R: Read> {
+    inner: BufReader<R>,
+}
+impl<R: Read> JsonReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            inner: BufReader::new(reader),
+        }
+    }
+
+    fn peek(&mut self) -> std::io::Result<Option<u8>> {
+        Ok(self.inner.fill_buf()?.first().copied())
+    }
+
+    ///
+    /// consumes the next byte if it is the expected one
+    ///
+    fn next_is(&mut self, expected: u8) -> std::io::Result<bool> {
+        if self.peek()? == Some(expected) {
+            self.inner.consume(1);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> std::io::Result<()> {
+        while let Some(b) = self.peek()? {
+            if !b.is_ascii_whitespace() {
+                break;
+            }
+            self.inner.consume(1);
+        }
+        Ok(())
+    }
+
+    ///
+    /// reads a complete value, copying its bytes in the buffer when provided
+    /// a value that is not an object, an array or a string ends with the next separator
+    ///
+    fn read_value(&mut self, mut buffer: Option<&mut Vec<u8>>) -> std::io::Result<()> {
+        let mut depth: usize = 0;
+        let mut in_string = false;
+        let mut escape = false;
+        let mut started = false;
+        loop {
+            let b = match self.peek()? {
+                Some(b) => b,
+                None => {
+                    if started && depth == 0 && !in_string {
+                        return Ok(());
+                    }
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+                }
+            };
+            if !in_string
+                && depth == 0
+                && started
+                && (matches!(b, b',' | b']' | b'}' | b':') || b.is_ascii_whitespace())
+            {
+                return Ok(());
+            }
+            self.inner.consume(1);
+            if let Some(buffer) = buffer.as_mut() {
+                buffer.push(b);
+            }
+            started = true;
+
+            if in_string {
+                if escape {
+                    escape = false;
+                } else if b == b'\\' {
+                    escape = true;
+                } else if b == b'"' {
+                    in_string = false;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                continue;
+            }
+            match b {
+                b'"' => in_string = true,
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::database::ResultParser;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Person {
+        name: String,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn stream_array() {
+        let result = r#"{
+            "Skipped": [ { "name": "a \"quoted\" ] }", "list": [[1, 2], {}] }, 12.5e3, null ],
+            "Count" : 2,
+            "Person":[
+                {"name":"John","tags":["a,b", "]"]},
+                { "name" : "Ada", "tags" : [] }
+            ],
+            "Last": "value"
+        }"#;
+        let persons: Vec<Person> = ResultParser::stream_array(result.as_bytes(), "Person")
+            .unwrap()
+            .collect::<Result<Vec<Person>, crate::Error>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                Person {
+                    name: "John".to_string(),
+                    tags: vec!["a,b".to_string(), "]".to_string()],
+                },
+                Person {
+                    name: "Ada".to_string(),
+                    tags: vec![],
+                }
+            ],
+            persons
+        );
+
+        let skipped: Vec<serde_json::Value> =
+            ResultParser::stream_array(result.as_bytes(), "Skipped")
+                .unwrap()
+                .collect::<Result<Vec<serde_json::Value>, crate::Error>>()
+                .unwrap();
+        assert_eq!(3, skipped.len());
+        assert_eq!("a \"quoted\" ] }", skipped[0]["name"]);
+        assert_eq!(12500.0, skipped[1]);
+        assert!(skipped[2].is_null());
+
+        let empty: Vec<Person> =
+            ResultParser::stream_array(r#"{"Person":[ ]}"#.as_bytes(), "Person")
+                .unwrap()
+                .collect::<Result<Vec<Person>, crate::Error>>()
+                .unwrap();
+        assert!(empty.is_empty());
+
+        assert!(ResultParser::stream_array::<Person, _>(result.as_bytes(), "Unknown").is_err());
+        assert!(ResultParser::stream_array::<Person, _>(result.as_bytes(), "Count").is_err());
+        assert!(ResultParser::stream_array::<Person, _>("[]".as_bytes(), "Person").is_err());
+
+        //the elements read before an error are returned
+        let truncated = r#"{"Person":[{"name":"John","tags":[]}, {"name":"#;
+        let mut stream =
+            ResultParser::stream_array::<Person, _>(truncated.as_bytes(), "Person").unwrap();
+        assert!(stream.next().unwrap().is_ok());
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+    }
+}
@@ -0,0 +1,609 @@
+use std::collections::HashSet;
+
+use rusqlite::{Connection, OptionalExtension};
+use tokio::sync::mpsc;
+
+use crate::{
+    date_utils::{date, now},
+    security::{base64_encode, Uid},
+};
+
+use super::{
+    daily_log::DailyMutations, node::Node, sqlite_database::Writeable, Result, VEC_OVERHEAD,
+};
+
+const DAY_IN_MS: i64 = 24 * 60 * 60 * 1000;
+
+///
+/// Removes the deletion logs that are older than the horizon.
+///
+/// The days that lost deletion entries are flagged for recompute,
+/// and the daily logs that where left empty by a previous pruning are removed.
+///
+/// The horizon is stored and sent to the peers with the room definition:
+/// a peer that did not synchronise a room since the horizon could have missed some deletions and will perform a snapshot synchronisation.
+///
+pub struct LogPruning {
+    pub horizon: i64,
+}
+impl LogPruning {
+    ///
+    /// The logs older than the retention are removed, the horizon is aligned on the start of the day
+    ///
+    pub fn for_retention(retention_in_days: u32) -> Self {
+        Self {
+            horizon: date(now() - retention_in_days as i64 * DAY_IN_MS),
+        }
+    }
+
+    ///
+    /// The horizon sent by a remote peer cannot be more recent than the one of the local retention,
+    /// preventing a peer from forcing a snapshot synchronisation of recent data
+    ///
+    pub fn remote_horizon(remote_horizon: i64, retention_in_days: u32) -> i64 {
+        remote_horizon.min(Self::for_retention(retention_in_days).horizon)
+    }
+
+    ///
+    /// Creates the tables used by the log retention
+    ///
+    /// The tables can be added to existing databases, hence the 'IF NOT EXISTS'
+    ///
+    pub fn create_tables(conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        conn.execute(
+            "
+            CREATE TABLE IF NOT EXISTS _log_horizon (
+                id INTEGER NOT NULL,
+                horizon INTEGER NOT NULL,
+                PRIMARY KEY(id)
+            ) STRICT",
+            [],
+        )?;
+
+        conn.execute(
+            "
+            CREATE TABLE IF NOT EXISTS _room_synchronisation (
+                room_id BLOB NOT NULL,
+                sync_date INTEGER NOT NULL,
+                PRIMARY KEY(room_id)
+            ) WITHOUT ROWID, STRICT",
+            [],
+        )?;
+        Ok(())
+    }
+
+    ///
+    /// The date before which the deletion logs have been removed, 0 if the logs have never been pruned
+    ///
+    pub fn horizon(conn: &Connection) -> std::result::Result<i64, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached("SELECT horizon FROM _log_horizon WHERE id = 0")?;
+        let horizon: Option<i64> = stmt.query_row([], |row| row.get(0)).optional()?;
+        Ok(horizon.unwrap_or(0))
+    }
+}
+impl Writeable for LogPruning {
+    fn write(&mut self, conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        let mut daily_mutations = DailyMutations::default();
+        let mut pruned_stmt = conn.prepare_cached(
+            "
+            SELECT room_id, entity, deletion_date
+            FROM _node_deletion_log
+            WHERE deletion_date < ?1
+
+            UNION ALL
+            SELECT room_id, src_entity, deletion_date
+            FROM _edge_deletion_log
+            WHERE deletion_date < ?1
+            ",
+        )?;
+        let mut rows = pruned_stmt.query([self.horizon])?;
+        while let Some(row) = rows.next()? {
+            let room_id: Uid = row.get(0)?;
+            let entity: String = row.get(1)?;
+            let deletion_date: i64 = row.get(2)?;
+            daily_mutations.set_need_update(room_id, &entity, deletion_date);
+        }
+
+        conn.execute(
+            "DELETE FROM _daily_log WHERE date < ? AND entry_number = 0 AND need_recompute = 0",
+            [self.horizon],
+        )?;
+        conn.execute(
+            "DELETE FROM _node_deletion_log WHERE deletion_date < ?",
+            [self.horizon],
+        )?;
+        conn.execute(
+            "DELETE FROM _edge_deletion_log WHERE deletion_date < ?",
+            [self.horizon],
+        )?;
+        daily_mutations.write(conn)?;
+
+        conn.execute(
+            "INSERT INTO _log_horizon (id, horizon) VALUES (0, ?)
+            ON CONFLICT(id) DO UPDATE SET horizon = max(horizon, excluded.horizon)",
+            [self.horizon],
+        )?;
+        Ok(())
+    }
+}
+
+///
+/// Stores the last date a room was successfully synchronised with a peer
+///
+pub struct RoomSynchronisation {
+    pub room_id: Uid,
+    pub date: i64,
+}
+impl RoomSynchronisation {
+    pub fn get(
+        room_id: &Uid,
+        conn: &Connection,
+    ) -> std::result::Result<Option<i64>, rusqlite::Error> {
+        let mut stmt =
+            conn.prepare_cached("SELECT sync_date FROM _room_synchronisation WHERE room_id = ?")?;
+        stmt.query_row([room_id], |row| row.get(0)).optional()
+    }
+}
+impl Writeable for RoomSynchronisation {
+    fn write(&mut self, conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "INSERT OR REPLACE INTO _room_synchronisation (room_id, sync_date) VALUES (?, ?)",
+        )?;
+        stmt.execute((&self.room_id, self.date))?;
+        Ok(())
+    }
+}
+
+pub type EdgeKey = (Uid, String, Uid);
+
+///
+/// The content of a room that is older than a peer's log horizon.
+///
+/// A peer that missed the deletion logs removes the nodes and edges older than the horizon
+/// that were authored by the remote peer and are not part of its snapshot.
+/// The snapshot of a peer is not trusted for the data of other authors, which is always kept.
+/// Are never removed:
+/// - nodes and edges authored by the local peer, that the remote peer might not have received yet,
+/// - nodes restricted to a list of readers and their edges, that the remote peer might not be allowed to read.
+///
+/// The remote peer only lists the nodes and edges that the local peer is allowed to read.
+///
+pub struct RoomSnapshot {
+    pub room_id: Uid,
+    pub horizon: i64,
+    pub local_key: Vec<u8>,
+    pub remote_key: Vec<u8>,
+    pub nodes: HashSet<Uid>,
+    pub edges: HashSet<EdgeKey>,
+}
+impl RoomSnapshot {
+    ///
+    /// Get the ids of the room nodes older than the horizon that the reader can read
+    ///
+    pub fn get_nodes(
+        room_id: &Uid,
+        horizon: i64,
+        reader: &[u8],
+        batch_size: usize,
+        sender: &mpsc::Sender<Result<Vec<Uid>>>,
+        conn: &Connection,
+    ) -> Result<()> {
+        let query = format!(
+            "SELECT id FROM _node WHERE room_id = ?1 AND mdate < ?2 AND {}",
+            Node::readable_by_condition("_node", 3)
+        );
+        let mut stmt = conn.prepare_cached(&query)?;
+        let mut rows = stmt.query((room_id, horizon, reader, base64_encode(reader)))?;
+        let mut res = Vec::new();
+        let mut len = 0;
+        while let Some(row) = rows.next()? {
+            let id: Uid = row.get(0)?;
+            let insert_len = len + id.len() as u64 + VEC_OVERHEAD;
+            if insert_len > batch_size as u64 {
+                let ready = res;
+                res = Vec::new();
+                len = 0;
+                let s = sender.blocking_send(Ok(ready));
+                if s.is_err() {
+                    break;
+                }
+            } else {
+                len = insert_len;
+            }
+            res.push(id);
+        }
+        if !res.is_empty() {
+            let _ = sender.blocking_send(Ok(res));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Get the edges older than the horizon whose source is a room node that the reader can read
+    ///
+    pub fn get_edges(
+        room_id: &Uid,
+        horizon: i64,
+        reader: &[u8],
+        batch_size: usize,
+        sender: &mpsc::Sender<Result<Vec<EdgeKey>>>,
+        conn: &Connection,
+    ) -> Result<()> {
+        let query = format!(
+            "SELECT src, label, dest
+            FROM _edge
+            WHERE
+                src IN (SELECT id FROM _node WHERE room_id = ?1 AND {}) AND
+                cdate < ?2",
+            Node::readable_by_condition("_node", 3)
+        );
+        let mut stmt = conn.prepare_cached(&query)?;
+        let mut rows = stmt.query((room_id, horizon, reader, base64_encode(reader)))?;
+        let mut res = Vec::new();
+        let mut len = 0;
+        while let Some(row) = rows.next()? {
+            let edge: EdgeKey = (row.get(0)?, row.get(1)?, row.get(2)?);
+            let size = bincode::serialized_size(&edge)?;
+            let insert_len = len + size + VEC_OVERHEAD;
+            if insert_len > batch_size as u64 {
+                let ready = res;
+                res = Vec::new();
+                len = 0;
+                let s = sender.blocking_send(Ok(ready));
+                if s.is_err() {
+                    break;
+                }
+            } else {
+                len = insert_len;
+            }
+            res.push(edge);
+        }
+        if !res.is_empty() {
+            let _ = sender.blocking_send(Ok(res));
+        }
+        Ok(())
+    }
+}
+impl Writeable for RoomSnapshot {
+    fn write(&mut self, conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        let mut daily_mutations = DailyMutations::default();
+
+        let mut nodes_stmt = conn.prepare_cached(
+            "SELECT id, _entity, mdate
+            FROM _node
+            WHERE
+                room_id = ? AND
+                mdate < ? AND
+                verifying_key <> ? AND
+                verifying_key = ? AND
+                json_extract(_json, '$.sys_readers') IS NULL",
+        )?;
+        let mut removed_nodes = Vec::new();
+        let mut rows = nodes_stmt.query((
+            &self.room_id,
+            self.horizon,
+            &self.local_key,
+            &self.remote_key,
+        ))?;
+        while let Some(row) = rows.next()? {
+            let id: Uid = row.get(0)?;
+            if !self.nodes.contains(&id) {
+                let entity: String = row.get(1)?;
+                let mdate: i64 = row.get(2)?;
+                removed_nodes.push((id, entity, mdate));
+            }
+        }
+
+        let mut edges_stmt = conn.prepare_cached(
+            "SELECT src, label, dest, _edge.cdate, src_entity
+            FROM _edge
+            WHERE
+                src IN (
+                    SELECT id FROM _node
+                    WHERE room_id = ? AND json_extract(_json, '$.sys_readers') IS NULL
+                ) AND
+                _edge.cdate < ? AND
+                verifying_key <> ? AND
+                verifying_key = ?",
+        )?;
+        let mut removed_edges = Vec::new();
+        let mut rows = edges_stmt.query((
+            &self.room_id,
+            self.horizon,
+            &self.local_key,
+            &self.remote_key,
+        ))?;
+        while let Some(row) = rows.next()? {
+            let edge: EdgeKey = (row.get(0)?, row.get(1)?, row.get(2)?);
+            if !self.edges.contains(&edge) {
+                let cdate: i64 = row.get(3)?;
+                let src_entity: String = row.get(4)?;
+                removed_edges.push((edge, cdate, src_entity));
+            }
+        }
+
+        let mut delete_node_stmt = conn.prepare_cached("DELETE FROM _node WHERE id = ?")?;
+        let mut delete_src_stmt = conn.prepare_cached("DELETE FROM _edge WHERE src = ?")?;
+        for (id, entity, mdate) in removed_nodes {
+            delete_node_stmt.execute([&id])?;
+            delete_src_stmt.execute([&id])?;
+            daily_mutations.set_need_update(self.room_id, &entity, mdate);
+        }
+
+        let mut delete_edge_stmt =
+            conn.prepare_cached("DELETE FROM _edge WHERE src = ? AND label = ? AND dest = ?")?;
+        for ((src, label, dest), cdate, src_entity) in removed_edges {
+            delete_edge_stmt.execute((&src, &label, &dest))?;
+            daily_mutations.set_need_update(self.room_id, &src_entity, cdate);
+        }
+
+        daily_mutations.write(conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        daily_log::{DailyLog, DailyLogsUpdate},
+        edge::{Edge, EdgeDeletionEntry},
+        node::{Node, NodeDeletionEntry},
+        sqlite_database::prepare_connection,
+    };
+    use crate::security::{new_uid, Ed25519SigningKey, SigningKey};
+
+    #[test]
+    fn prune_deletion_logs() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let signing_key = Ed25519SigningKey::new();
+        let room_id = new_uid();
+        let entity = "Person".to_string();
+        let old_date = date(now()) - 400 * DAY_IN_MS;
+        let recent_date = date(now()) - 10 * DAY_IN_MS;
+
+        let mut daily_mutations = DailyMutations::default();
+        for deletion_date in [old_date, recent_date] {
+            let node = Node {
+                id: new_uid(),
+                _entity: entity.clone(),
+                mdate: deletion_date - 1,
+                ..Default::default()
+            };
+            let mut node_log =
+                NodeDeletionEntry::build(room_id, &node, deletion_date, &signing_key);
+            node_log.write(&conn).unwrap();
+
+            let edge = Edge {
+                src: new_uid(),
+                src_entity: entity.clone(),
+                label: "parents".to_string(),
+                dest: new_uid(),
+                cdate: deletion_date - 1,
+                ..Default::default()
+            };
+            let mut edge_log =
+                EdgeDeletionEntry::build(room_id, &edge, deletion_date, &signing_key);
+            edge_log.write(&conn).unwrap();
+            daily_mutations.set_need_update(room_id, &entity, deletion_date);
+        }
+        daily_mutations.write(&conn).unwrap();
+        DailyLogsUpdate::default().compute(&conn).unwrap();
+
+        assert_eq!(0, LogPruning::horizon(&conn).unwrap());
+        let mut pruning = LogPruning::for_retention(365);
+        pruning.write(&conn).unwrap();
+        assert_eq!(pruning.horizon, LogPruning::horizon(&conn).unwrap());
+
+        let count: i64 = conn
+            .query_row("SELECT count(1) FROM _node_deletion_log", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(1, count);
+        let count: i64 = conn
+            .query_row("SELECT count(1) FROM _edge_deletion_log", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(1, count);
+
+        //the old day is recomputed and becomes empty
+        DailyLogsUpdate::default().compute(&conn).unwrap();
+        let old_log = DailyLog::get_room_log_at(&room_id, old_date, None, &conn).unwrap();
+        assert_eq!(0, old_log[0].entry_number);
+
+        //empty daily logs are removed by the next pruning
+        LogPruning::for_retention(365).write(&conn).unwrap();
+        let old_log = DailyLog::get_room_log_at(&room_id, old_date, None, &conn).unwrap();
+        assert!(old_log.is_empty());
+        let recent_log = DailyLog::get_room_log_at(&room_id, recent_date, None, &conn).unwrap();
+        assert_eq!(2, recent_log[0].entry_number);
+
+        //the horizon never goes back
+        LogPruning::for_retention(1000).write(&conn).unwrap();
+        assert_eq!(pruning.horizon, LogPruning::horizon(&conn).unwrap());
+    }
+
+    #[test]
+    fn apply_snapshot() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let local_key = Ed25519SigningKey::new();
+        let remote_key = Ed25519SigningKey::new();
+        let other_key = Ed25519SigningKey::new();
+        let room_id = new_uid();
+        let old_date = date(now()) - 400 * DAY_IN_MS;
+        let horizon = date(now()) - 365 * DAY_IN_MS;
+
+        let mut nodes = Vec::new();
+        for (key, json, mdate) in [
+            (&remote_key, None, old_date), //kept by the remote
+            (&remote_key, None, old_date), //deleted by the remote
+            (&local_key, None, old_date),  //authored locally
+            (&remote_key, Some(r#"{"sys_readers":[]}"#), old_date), //restricted
+            (&remote_key, None, now()),    //more recent than the horizon
+            (&other_key, None, old_date),  //authored by another peer
+        ] {
+            let mut node = Node {
+                id: new_uid(),
+                room_id: Some(room_id),
+                cdate: mdate,
+                mdate,
+                _entity: "Person".to_string(),
+                _json: json.map(|j| j.to_string()),
+                ..Default::default()
+            };
+            node.sign(key).unwrap();
+            node.write(&conn, false, &None, &None).unwrap();
+            nodes.push(node);
+        }
+
+        let mut edges = Vec::new();
+        for key in [&remote_key, &remote_key, &local_key, &other_key] {
+            let mut edge = Edge {
+                src: nodes[0].id,
+                src_entity: "Person".to_string(),
+                label: "parents".to_string(),
+                dest: new_uid(),
+                cdate: old_date,
+                ..Default::default()
+            };
+            edge.sign(key).unwrap();
+            edge.write(&conn).unwrap();
+            edges.push(edge);
+        }
+
+        let (sender, mut receiver) = mpsc::channel::<Result<Vec<Uid>>>(10);
+        RoomSnapshot::get_nodes(
+            &room_id,
+            horizon,
+            &local_key.export_verifying_key(),
+            4096,
+            &sender,
+            &conn,
+        )
+        .unwrap();
+        drop(sender);
+        let mut remote_nodes = HashSet::new();
+        while let Some(ids) = receiver.blocking_recv() {
+            remote_nodes.extend(ids.unwrap());
+        }
+        //the restricted node is not listed for the local peer
+        assert_eq!(4, remote_nodes.len());
+        assert!(!remote_nodes.contains(&nodes[3].id));
+        remote_nodes.remove(&nodes[1].id);
+        remote_nodes.remove(&nodes[5].id);
+
+        let (sender, mut receiver) = mpsc::channel::<Result<Vec<EdgeKey>>>(10);
+        RoomSnapshot::get_edges(
+            &room_id,
+            horizon,
+            &local_key.export_verifying_key(),
+            4096,
+            &sender,
+            &conn,
+        )
+        .unwrap();
+        drop(sender);
+        let mut remote_edges = HashSet::new();
+        while let Some(keys) = receiver.blocking_recv() {
+            remote_edges.extend(keys.unwrap());
+        }
+        assert_eq!(4, remote_edges.len());
+        remote_edges.retain(|e| e.2 == edges[0].dest);
+
+        let mut snapshot = RoomSnapshot {
+            room_id,
+            horizon,
+            local_key: local_key.export_verifying_key(),
+            remote_key: remote_key.export_verifying_key(),
+            nodes: remote_nodes,
+            edges: remote_edges,
+        };
+        snapshot.write(&conn).unwrap();
+
+        let remaining: Vec<Uid> = conn
+            .prepare("SELECT id FROM _node")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|id| id.unwrap())
+            .collect();
+        //the data of other authors is kept even if it is missing from the snapshot
+        assert_eq!(5, remaining.len());
+        assert!(!remaining.contains(&nodes[1].id));
+        assert!(remaining.contains(&nodes[5].id));
+
+        let remaining: Vec<Uid> = conn
+            .prepare("SELECT dest FROM _edge")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|id| id.unwrap())
+            .collect();
+        assert_eq!(3, remaining.len());
+        assert!(!remaining.contains(&edges[1].dest));
+        assert!(remaining.contains(&edges[3].dest));
+    }
+
+    #[test]
+    fn forged_horizon() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let local_key = Ed25519SigningKey::new();
+        let remote_key = Ed25519SigningKey::new();
+        let other_key = Ed25519SigningKey::new();
+        let room_id = new_uid();
+        let recent_date = date(now()) - 10 * DAY_IN_MS;
+
+        let mut nodes = Vec::new();
+        for key in [&remote_key, &other_key] {
+            let mut node = Node {
+                id: new_uid(),
+                room_id: Some(room_id),
+                cdate: recent_date,
+                mdate: recent_date,
+                _entity: "Person".to_string(),
+                ..Default::default()
+            };
+            node.sign(key).unwrap();
+            node.write(&conn, false, &None, &None).unwrap();
+            nodes.push(node);
+        }
+
+        //the remote peer pretends that it pruned every log and sends an empty snapshot
+        let horizon = LogPruning::remote_horizon(now(), 365);
+        assert_eq!(LogPruning::for_retention(365).horizon, horizon);
+        assert_eq!(100, LogPruning::remote_horizon(100, 365));
+
+        let mut snapshot = RoomSnapshot {
+            room_id,
+            horizon,
+            local_key: local_key.export_verifying_key(),
+            remote_key: remote_key.export_verifying_key(),
+            nodes: HashSet::new(),
+            edges: HashSet::new(),
+        };
+        snapshot.write(&conn).unwrap();
+        let count: i64 = conn
+            .query_row("SELECT count(1) FROM _node", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(2, count);
+
+        //even without the clamp, only the data of the remote peer could be removed
+        snapshot.horizon = now();
+        snapshot.write(&conn).unwrap();
+        let remaining: Vec<Uid> = conn
+            .prepare("SELECT id FROM _node")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|id| id.unwrap())
+            .collect();
+        assert_eq!(vec![nodes[1].id], remaining);
+    }
+}
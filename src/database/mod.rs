@@ -1,21 +1,39 @@
 pub mod authorisation_service;
 pub mod authorisation_service_test;
+pub mod backup;
+pub mod binary_store;
+pub mod custom_function;
 pub mod daily_log;
+#[cfg(any(feature = "debug_views", test))]
+pub mod debug_views;
 pub mod deletion;
 pub mod edge;
+pub mod entity_counter;
+pub mod file;
 pub mod graph_database;
+pub mod json_stream;
+pub mod log_retention;
+pub mod mutation_batch;
 pub mod mutation_query;
 pub mod node;
 pub mod query;
 pub mod query_language;
+pub mod query_subscription;
 pub mod query_test;
+pub mod replication;
 pub mod room;
+pub mod room_builder;
+pub mod room_impact;
 pub mod room_node;
+pub mod search;
 
 pub mod sqlite_database;
 pub mod system_entities;
-use std::collections::HashMap;
+pub mod telemetry;
+pub mod transaction;
+use std::{collections::HashMap, io::Read};
 
+use json_stream::ArrayStream;
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 use thiserror::Error;
@@ -26,6 +44,33 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub const VEC_OVERHEAD: u64 = 4;
 pub const MESSAGE_OVERHEAD: usize = 16;
 
+///
+/// Encoding of the query results
+///
+/// Cbor is a compact binary encoding that is faster to parse than JSON for large result sets
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    Cbor,
+}
+
+///
+/// encodes a JSON query result
+///
+pub fn encode_result(result: String, encoding: Encoding) -> Result<Vec<u8>> {
+    match encoding {
+        Encoding::Json => Ok(result.into_bytes()),
+        Encoding::Cbor => {
+            let value: Value = serde_json::from_str(&result)?;
+            let mut bytes = Vec::new();
+            ciborium::into_writer(&value, &mut bytes)
+                .map_err(|e| Error::Encoding(e.to_string()))?;
+            Ok(bytes)
+        }
+    }
+}
+
 ///
 /// Helper structure to parse the JSON results
 ///
@@ -37,6 +82,22 @@ impl ResultParser {
         let parsed: Value = serde_json::from_str(result)?;
         Ok(Self { parsed })
     }
+
+    ///
+    /// parse the results of *Discret::query_bytes()*
+    ///
+    pub fn from_bytes(
+        result: &[u8],
+        encoding: Encoding,
+    ) -> std::result::Result<Self, crate::Error> {
+        let parsed: Value = match encoding {
+            Encoding::Json => serde_json::from_slice(result)?,
+            Encoding::Cbor => {
+                ciborium::from_reader(result).map_err(|e| Error::Encoding(e.to_string()))?
+            }
+        };
+        Ok(Self { parsed })
+    }
     ///
     /// Consumes the array found for the field and convert it to an array of the generic type T
     /// used to parse **query** results
@@ -73,6 +134,19 @@ impl ResultParser {
         Ok(re)
     }
 
+    ///
+    /// Reads the array found for the field from a JSON **query** result and converts its elements to the generic type T, one at a time
+    ///
+    /// Unlike *take_array()*, the result is not loaded in memory: it is suited to the very large results, like a result written to a file.
+    /// Fails when the field is missing or is not an array, the errors found in the array are returned by the iterator.
+    ///
+    pub fn stream_array<T: DeserializeOwned, R: Read>(
+        reader: R,
+        field: &str,
+    ) -> std::result::Result<ArrayStream<R, T>, crate::Error> {
+        ArrayStream::new(reader, field)
+    }
+
     ///
     /// Consumes the object found for the field and convert it to an object of the generic type T
     /// used to parse **Mutate** query results
@@ -165,6 +239,21 @@ pub enum Error {
     #[error("{0}")]
     DatabaseWrite(String),
 
+    #[error("The database is corrupted: {0}")]
+    DatabaseCorrupted(String),
+
+    #[error("Backup failed: {0}")]
+    Backup(String),
+
+    #[error("Cannot relocate the database: {0}")]
+    Relocation(String),
+
+    #[error("Result encoding failed: {0}")]
+    Encoding(String),
+
+    #[error("Invalid pagination cursor: {0}")]
+    InvalidCursor(String),
+
     #[error("{0}")]
     InvalidNode(String),
 
@@ -216,6 +305,12 @@ pub enum Error {
     #[error("system entity '{0}' cannot be mutated ouside a Room mutation")]
     InvalidAuthorisationMutation(String),
 
+    #[error("Room definitions cannot be modified by a batch mutation")]
+    RoomBatchMutation(),
+
+    #[error("Room definitions cannot be modified in a transaction")]
+    RoomTransaction(),
+
     #[error("not enough right to mutate entity '{0}' in room '{1}' ")]
     AuthorisationRejected(String, String),
 
@@ -225,15 +320,27 @@ pub enum Error {
     #[error("Unknown room id {0} ")]
     UnknownRoom(String),
 
+    #[error("Unknown authorisation {0} in room {1}")]
+    UnknownAuthorisation(String, String),
+
     #[error("{0} Entity cannot have a room_id defined")]
     ForbiddenRoomId(String),
 
+    #[error("{0} Entity must have a room_id defined")]
+    MissingRoomId(String),
+
     #[error("Updates not allowed, Only inserts can be performed for this entity")]
     UpdateNotAllowed(),
 
     #[error("Deletes not allowed, Only inserts can be performed for this entity")]
     DeleteNotAllowed(),
 
+    #[error("The database is opened by a read-only observer and cannot be mutated")]
+    ReadOnly(),
+
+    #[error("invalid automation rule: {0}")]
+    InvalidAutomationRule(String),
+
     #[error("Entity right is missing an entity name")]
     EntityRightMissingName(),
 
@@ -252,6 +359,9 @@ pub enum Error {
     #[error("{0}")]
     QueryParsing(String),
 
+    #[error("idempotency key '{0}' was allready used by a different mutation")]
+    IdempotencyKeyMismatch(String),
+
     #[error("An error occured while computing daily logs: {0}")]
     ComputeDailyLog(String),
 }
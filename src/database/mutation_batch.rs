@@ -0,0 +1,143 @@
+use tokio::{
+    sync::mpsc,
+    task::{JoinError, JoinSet},
+};
+
+use crate::Error;
+
+use super::{
+    graph_database::{DbMessage, GraphDatabaseService},
+    query_language::parameter::Parameters,
+};
+
+///
+/// Outcome of the mutations sent through a [MutationBatch].
+///
+#[derive(Debug, Clone, Default)]
+pub struct MutationBatchSummary {
+    ///
+    /// number of mutations sent to the batch
+    ///
+    pub sent: usize,
+
+    ///
+    /// number of mutations written in the database
+    ///
+    pub written: usize,
+
+    ///
+    /// the failed mutations: the position of the mutation in the batch, starting at 0, and the error message
+    ///
+    pub failures: Vec<(usize, String)>,
+}
+impl MutationBatchSummary {
+    ///
+    /// number of mutations that are still being processed
+    ///
+    pub fn pending(&self) -> usize {
+        self.sent - self.written - self.failures.len()
+    }
+}
+
+///
+/// Writes a large number of mutations without waiting for each mutation to be written.
+///
+/// Mutations are grouped in database transactions by the writer, like with *Discret::mutation_stream()*.
+/// At most *max_in_flight* mutations are processed at the same time: *send()* waits for a slot when the limit is reached.
+///
+/// *flush()* waits for every sent mutation to be written, which allows importers to checkpoint their progress.
+/// *close()* flushes the batch and returns the final summary.
+///
+pub struct MutationBatch {
+    sender: mpsc::Sender<DbMessage>,
+    max_in_flight: usize,
+    in_flight: JoinSet<(usize, super::Result<()>)>,
+    summary: MutationBatchSummary,
+}
+impl MutationBatch {
+    pub(crate) fn new(database: &GraphDatabaseService, max_in_flight: usize) -> Self {
+        Self {
+            sender: database.sender.clone(),
+            max_in_flight: max_in_flight.max(1),
+            in_flight: JoinSet::new(),
+            summary: MutationBatchSummary::default(),
+        }
+    }
+
+    ///
+    /// Sends a mutation, waiting for a slot if *max_in_flight* mutations are being processed.
+    ///
+    /// returns the position of the mutation in the batch.
+    /// The mutation errors are not returned here but reported in the summary.
+    ///
+    pub async fn send(
+        &mut self,
+        mutation: &str,
+        parameters: Option<Parameters>,
+    ) -> std::result::Result<usize, Error> {
+        while self.in_flight.len() >= self.max_in_flight {
+            if let Some(result) = self.in_flight.join_next().await {
+                self.process(result)?;
+            }
+        }
+
+        let (reply, mut receive) = mpsc::channel(1);
+        let msg =
+            DbMessage::MutateStream(mutation.to_string(), parameters.unwrap_or_default(), reply);
+        self.sender
+            .send(msg)
+            .await
+            .map_err(|e| Error::SendError(e.to_string()))?;
+
+        let position = self.summary.sent;
+        self.summary.sent += 1;
+        self.in_flight.spawn(async move {
+            let result = match receive.recv().await {
+                Some(result) => result.map(|_| ()),
+                None => Err(super::Error::ChannelSend(
+                    "mutation dropped before being written".to_string(),
+                )),
+            };
+            (position, result)
+        });
+        Ok(position)
+    }
+
+    ///
+    /// Waits until every sent mutation is written or failed.
+    ///
+    /// returns the summary of every mutation sent since the creation of the batch.
+    ///
+    pub async fn flush(&mut self) -> std::result::Result<MutationBatchSummary, Error> {
+        while let Some(result) = self.in_flight.join_next().await {
+            self.process(result)?;
+        }
+        Ok(self.summary.clone())
+    }
+
+    ///
+    /// Flushes the batch and returns the final summary.
+    ///
+    pub async fn close(mut self) -> std::result::Result<MutationBatchSummary, Error> {
+        self.flush().await
+    }
+
+    ///
+    /// The summary of the mutations processed so far, without waiting for the pending ones.
+    ///
+    pub fn summary(&self) -> &MutationBatchSummary {
+        &self.summary
+    }
+
+    fn process(
+        &mut self,
+        result: std::result::Result<(usize, super::Result<()>), JoinError>,
+    ) -> std::result::Result<(), Error> {
+        let (position, result) = result?;
+        match result {
+            Ok(_) => self.summary.written += 1,
+            Err(e) => self.summary.failures.push((position, e.to_string())),
+        }
+        Ok(())
+    }
+}
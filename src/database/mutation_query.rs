@@ -1,7 +1,8 @@
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
 
 use crate::{
     date_utils::now,
+    geo_utils::parse_location,
     security::{base64_decode, base64_encode, default_uid, uid_from, SigningKey, Uid},
 };
 
@@ -10,6 +11,7 @@ use super::{
     edge::{Edge, EdgeDeletionEntry},
     node::{extract_json, Node},
     query_language::{
+        self,
         mutation_parser::{EntityMutation, MutationField, MutationFieldValue, MutationParser},
         parameter::Parameters,
         FieldType,
@@ -68,21 +70,187 @@ impl Default for NodeToMutate {
     }
 }
 
+///
+/// Key provided by the caller to detect retried mutations
+///
+#[derive(Debug)]
+pub struct IdempotencyKey {
+    pub key: String,
+    //hash of the mutation text and of its parameters
+    pub request_hash: Vec<u8>,
+}
+impl IdempotencyKey {
+    pub fn request_hash(
+        mutation_parser: &MutationParser,
+        parameters: &Parameters,
+    ) -> Result<Vec<u8>> {
+        let mut params: Vec<_> = parameters.params.iter().collect();
+        params.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&mutation_parser.source_hash);
+        hasher.update(&serde_json::to_vec(&params)?);
+        Ok(hasher.finalize().as_bytes().to_vec())
+    }
+}
+
+///
+/// Removes the idempotency keys that are older than the retention
+///
+pub struct IdempotencyKeyPruning {
+    pub horizon: i64,
+}
+impl IdempotencyKeyPruning {
+    pub fn for_retention(retention_in_hours: u32) -> Self {
+        Self {
+            horizon: now() - retention_in_hours as i64 * 3600 * 1000,
+        }
+    }
+}
+impl Writeable for IdempotencyKeyPruning {
+    fn write(&mut self, conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        let mut stmt = conn.prepare_cached("DELETE FROM _mutation_key WHERE date < ?")?;
+        stmt.execute([self.horizon])?;
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct MutationQuery {
     pub mutate_entities: Vec<InsertEntity>,
     pub mutation_parser: Arc<MutationParser>,
     pub date: i64,
+    pub idempotency_key: Option<IdempotencyKey>,
+    //result of a previous mutation that used the same idempotency key
+    pub replayed: Option<String>,
+    //name of the seed applied by the mutation, recorded in the _configuration table
+    pub seed: Option<String>,
+    //the seed was allready applied, nothing has been written
+    pub seeded: bool,
+    //the idempotency key was used by a different mutation since execute() was called, nothing has been written
+    pub key_mismatch: bool,
 }
 impl Writeable for MutationQuery {
     fn write(&mut self, conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        if let Some(name) = &self.seed {
+            //the seed could have been applied by a concurrent call since execute() was called
+            if Self::is_seeded(name, conn)? {
+                self.mutate_entities.clear();
+                self.seeded = true;
+                return Ok(());
+            }
+        }
+
+        if let Some(key) = &self.idempotency_key {
+            //the same key could have been written by a concurrent mutation since execute() was called
+            //a mismatch does not fail the write, which would roll back every other query of the writer batch:
+            //it is reported by write_error() once the batch is committed
+            match Self::get_replayed_result(key, conn) {
+                Ok(Some(result)) => {
+                    self.mutate_entities.clear();
+                    self.replayed = Some(result);
+                    return Ok(());
+                }
+                Ok(None) => {}
+                Err(Error::IdempotencyKeyMismatch(_)) => {
+                    self.mutate_entities.clear();
+                    self.key_mismatch = true;
+                    return Ok(());
+                }
+                Err(e) => return Err(rusqlite::Error::ToSqlConversionFailure(Box::new(e))),
+            }
+        }
+
+        if let Some(name) = &self.seed {
+            let mut insert_stmt =
+                conn.prepare_cached("INSERT INTO _configuration (key, value) VALUES (?, ?)")?;
+            insert_stmt.execute((seed_key(name), self.date.to_string()))?;
+        }
+
         for insert in &mut self.mutate_entities {
             insert.write(conn)?;
         }
+
+        if let Some(key) = &self.idempotency_key {
+            let result = self
+                .result()
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+            let mut insert_stmt = conn.prepare_cached(
+                "INSERT INTO _mutation_key (key, request_hash, date, result) VALUES (?, ?, ?, ?)",
+            )?;
+            insert_stmt.execute((&key.key, &key.request_hash, self.date, result))?;
+        }
         Ok(())
     }
 }
 impl MutationQuery {
+    ///
+    /// Creates the table that stores the idempotency keys of the mutations
+    ///
+    /// The table can be added to existing databases, hence the 'IF NOT EXISTS'
+    ///
+    pub fn create_tables(conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        conn.execute(
+            "
+            CREATE TABLE IF NOT EXISTS _mutation_key (
+                key TEXT NOT NULL,
+                request_hash BLOB NOT NULL,
+                date INTEGER NOT NULL,
+                result TEXT NOT NULL,
+                PRIMARY KEY(key)
+            ) WITHOUT ROWID, STRICT",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS _mutation_key_date_idx ON _mutation_key(date)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    ///
+    /// retrieve the result of a mutation that was performed with the same idempotency key
+    ///
+    /// fails if the key was used by a different mutation or with different parameters
+    ///
+    pub fn get_replayed_result(key: &IdempotencyKey, conn: &Connection) -> Result<Option<String>> {
+        let mut stmt =
+            conn.prepare_cached("SELECT request_hash, result FROM _mutation_key WHERE key = ?")?;
+        let stored: Option<(Vec<u8>, String)> = stmt
+            .query_row([&key.key], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+        match stored {
+            Some((request_hash, result)) => {
+                if request_hash != key.request_hash {
+                    return Err(Error::IdempotencyKeyMismatch(key.key.clone()));
+                }
+                Ok(Some(result))
+            }
+            None => Ok(None),
+        }
+    }
+
+    ///
+    /// the error detected while writing the mutation, to be sent once the writer batch is committed
+    ///
+    pub fn write_error(&self) -> Option<Error> {
+        match &self.idempotency_key {
+            Some(key) if self.key_mismatch => Some(Error::IdempotencyKeyMismatch(key.key.clone())),
+            _ => None,
+        }
+    }
+
+    ///
+    /// true if a mutation was allready applied with the seed name
+    ///
+    pub fn is_seeded(name: &str, conn: &Connection) -> std::result::Result<bool, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached("SELECT 1 FROM _configuration WHERE key = ?")?;
+        let seeded: Option<i64> = stmt
+            .query_row([seed_key(name)], |row| row.get(0))
+            .optional()?;
+        Ok(seeded.is_some())
+    }
+
     pub fn update_daily_logs(&self, daily_log: &mut DailyMutations) {
         for insert in &self.mutate_entities {
             insert.update_daily_logs(daily_log);
@@ -95,10 +263,43 @@ impl MutationQuery {
         conn: &rusqlite::Connection,
     ) -> Result<MutationQuery> {
         mutation_parser.variables.validate_params(parameters)?;
-        let mut mutate_queries = vec![];
 
         //make sure that everything is mutated at the same exact date
         let date = now();
+
+        let key = match &mutation_parser.idempotency_key {
+            Some(MutationFieldValue::Variable(var)) => parameters
+                .params
+                .get(var)
+                .and_then(|v| v.as_string())
+                .map(|v| v.to_string()),
+            Some(MutationFieldValue::Value(value)) => value.as_string().map(|v| v.to_string()),
+            _ => None,
+        };
+        let idempotency_key = match key {
+            Some(key) => Some(IdempotencyKey {
+                key,
+                request_hash: IdempotencyKey::request_hash(&mutation_parser, parameters)?,
+            }),
+            None => None,
+        };
+
+        if let Some(key) = &idempotency_key {
+            if let Some(result) = Self::get_replayed_result(key, conn)? {
+                return Ok(MutationQuery {
+                    date,
+                    mutate_entities: Vec::new(),
+                    mutation_parser,
+                    idempotency_key,
+                    replayed: Some(result),
+                    seed: None,
+                    seeded: false,
+                    key_mismatch: false,
+                });
+            }
+        }
+
+        let mut mutate_queries = vec![];
         for entity in &mutation_parser.mutations {
             let query = Self::get_mutate_query(entity, parameters, conn, date)?;
             mutate_queries.push(query);
@@ -107,6 +308,11 @@ impl MutationQuery {
             date,
             mutate_entities: mutate_queries,
             mutation_parser,
+            idempotency_key,
+            replayed: None,
+            seed: None,
+            seeded: false,
+            key_mismatch: false,
         };
 
         Ok(query)
@@ -270,7 +476,9 @@ impl MutationQuery {
                         | FieldType::Float
                         | FieldType::Base64
                         | FieldType::Integer
-                        | FieldType::String => {
+                        | FieldType::String
+                        | FieldType::DateTime
+                        | FieldType::Date => {
                             let value = match &field.field_value {
                                 MutationFieldValue::Variable(v) => {
                                     let value = parameters.params.get(v).unwrap();
@@ -286,15 +494,81 @@ impl MutationQuery {
                         FieldType::Json => {
                             let value = match &field.field_value {
                                 MutationFieldValue::Variable(v) => {
-                                    let value = parameters.params.get(v).unwrap();
-
-                                    serde_json::from_str(value.as_string().unwrap())?
+                                    parameters.params.get(v).unwrap()
+                                }
+                                MutationFieldValue::Value(v) => v,
+                                _ => unreachable!(),
+                            };
+                            let mut value: serde_json::Value = match value.as_string() {
+                                Some(str) => serde_json::from_str(str)?,
+                                None => serde_json::Value::Null,
+                            };
+                            if let Some(schema) = &field.json_schema {
+                                if !value.is_null() {
+                                    schema.validate(&field.name, &value)?;
+                                }
+                            }
+                            //canonical form: semantically equal documents are stored with the same signature
+                            value.sort_all_objects();
+                            obj.insert(String::from(&field.short_name), value);
+                            field_updated = true;
+                        }
+                        FieldType::File => {
+                            let value = match &field.field_value {
+                                MutationFieldValue::Variable(v) => {
+                                    parameters.params.get(v).unwrap()
                                 }
-                                MutationFieldValue::Value(v) => {
-                                    serde_json::from_str(v.as_string().unwrap())?
+                                MutationFieldValue::Value(v) => v,
+                                _ => unreachable!(),
+                            };
+                            if let Some(id) = value.as_string() {
+                                query_language::validate_file_id(id, &field.name)?;
+                            }
+                            obj.insert(
+                                String::from(&field.short_name),
+                                value.as_serde_json_value()?,
+                            );
+                            field_updated = true;
+                        }
+                        FieldType::Geo => {
+                            let value = match &field.field_value {
+                                MutationFieldValue::Variable(v) => {
+                                    parameters.params.get(v).unwrap()
+                                }
+                                MutationFieldValue::Value(v) => v,
+                                _ => unreachable!(),
+                            };
+                            //locations are stored as a {"lat":..,"lon":..} object
+                            let value = match value.as_string().and_then(|s| parse_location(s)) {
+                                Some((lat, lon)) => serde_json::json!({"lat": lat, "lon": lon}),
+                                None => serde_json::Value::Null,
+                            };
+                            obj.insert(String::from(&field.short_name), value);
+                            field_updated = true;
+                        }
+                        FieldType::Enum(_) => {
+                            let value = match &field.field_value {
+                                MutationFieldValue::Variable(v) => {
+                                    parameters.params.get(v).unwrap()
                                 }
+                                MutationFieldValue::Value(v) => v,
                                 _ => unreachable!(),
                             };
+                            //enum values are stored using their position in the value list
+                            let value = match value.as_string() {
+                                Some(str) => match field.field_type.enum_position(str) {
+                                    Some(pos) => serde_json::Value::from(pos),
+                                    None => {
+                                        return Err(Error::Parsing(
+                                            query_language::Error::InvalidEnumValue(
+                                                str.to_string(),
+                                                field.name.clone(),
+                                            ),
+                                        ))
+                                    }
+                                },
+                                None => serde_json::Value::Null,
+                            };
                             obj.insert(String::from(&field.short_name), value);
                             field_updated = true;
                         }
@@ -374,6 +648,7 @@ impl MutationQuery {
             }
             None => {
                 let node = Node {
+                    id: entity.id_generation.new_uid(),
                     room_id,
                     _entity: String::from(entity_short),
                     ..Default::default()
@@ -394,6 +669,9 @@ impl MutationQuery {
     }
 
     pub fn to_json(&self) -> Result<serde_json::Value> {
+        if let Some(replayed) = &self.replayed {
+            return Ok(serde_json::from_str(replayed)?);
+        }
         let mutas = &self.mutation_parser.mutations;
         let inserts = &self.mutate_entities;
 
@@ -414,6 +692,9 @@ impl MutationQuery {
     }
 
     pub fn result(&self) -> Result<String> {
+        if let Some(replayed) = &self.replayed {
+            return Ok(replayed.clone());
+        }
         let mutas = &self.mutation_parser.mutations;
         let inserts = &self.mutate_entities;
 
@@ -551,7 +832,16 @@ impl InsertEntity {
                         if !field.is_default_filled {
                             let val_opt = obj.get(&field.short_name);
                             if let Some(val) = val_opt {
-                                json_map.insert(String::from(&field.name), val.clone());
+                                let val = match val.as_i64() {
+                                    Some(pos) if matches!(field.field_type, FieldType::Enum(_)) => {
+                                        match field.field_type.enum_value(pos) {
+                                            Some(v) => serde_json::Value::from(v.as_str()),
+                                            None => val.clone(),
+                                        }
+                                    }
+                                    _ => val.clone(),
+                                };
+                                json_map.insert(String::from(&field.name), val);
                             }
                         }
                     }
@@ -612,6 +902,11 @@ impl Default for InsertEntity {
     }
 }
 
+//key of a seed in the _configuration table
+fn seed_key(name: &str) -> String {
+    format!("Seed: {}", name)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -725,6 +1020,49 @@ mod tests {
         assert_eq!(2, mutation_query.mutate_entities.len());
     }
 
+    #[test]
+    fn time_ordered_ids() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            {
+                Message(time_ordered_id) {
+                    content : String ,
+                }
+            }",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                Message {
+                    content : $content
+                }
+                second : Message {
+                    content : $content
+                }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let mut param = Parameters::new();
+        param.add("content", String::from("Hello")).unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mutation = Arc::new(mutation);
+        let mutation_query = MutationQuery::execute(&mut param, mutation.clone(), &conn).unwrap();
+
+        let first = mutation_query.mutate_entities[0].node_to_mutate.id;
+        let second = mutation_query.mutate_entities[1].node_to_mutate.id;
+        assert_eq!(0x70, first[6] & 0xF0);
+        assert!(first < second);
+    }
+
     #[test]
     fn prepare_double_mutation_namespace() {
         let mut data_model = DataModel::new();
@@ -4,13 +4,16 @@ use std::{
 };
 
 use super::{
+    binary_store::{binary_column, BinaryStore},
     daily_log::DailyMutations,
+    log_retention::RoomSynchronisation,
     sqlite_database::{RowMappingFn, Writeable},
+    system_entities::READERS_FIELD,
     Error, Result, VEC_OVERHEAD,
 };
 use crate::{
     date_utils::{date, date_next_day, now},
-    security::{import_verifying_key, new_uid, SigningKey, Uid},
+    security::{base64_encode, import_verifying_key, new_uid, SigningKey, Uid},
 };
 
 use rusqlite::{params_from_iter, Connection, OptionalExtension};
@@ -75,7 +78,8 @@ impl Node {
             _json TEXT,
             _binary BLOB,
             verifying_key BLOB NOT NULL,
-            _signature BLOB NOT NULL
+            _signature BLOB NOT NULL,
+            _binary_hash BLOB
         ) STRICT",
             [],
         )?;
@@ -116,6 +120,8 @@ impl Node {
             [],
         )?;
 
+        BinaryStore::create_tables(conn)?;
+
         Ok(())
     }
 
@@ -148,6 +154,50 @@ impl Node {
             && self.verifying_key.eq(&node.verifying_key)
     }
 
+    ///
+    /// A node can restrict its visibility to a subset of the room members with the sys_readers field.
+    /// The author can always read its nodes.
+    /// A node whose readers cannot be parsed is only readable by its author
+    ///
+    pub fn is_readable_by(&self, verifying_key: &[u8]) -> bool {
+        if self.verifying_key.eq(verifying_key) {
+            return true;
+        }
+        let json: Value = match &self._json {
+            Some(json) => match serde_json::from_str(json) {
+                Ok(json) => json,
+                Err(_) => return false,
+            },
+            None => return true,
+        };
+        match json.get(READERS_FIELD) {
+            None | Some(Value::Null) => true,
+            Some(Value::Array(readers)) => {
+                let key = base64_encode(verifying_key);
+                readers.iter().any(|reader| reader.as_str() == Some(&key))
+            }
+            Some(_) => false,
+        }
+    }
+
+    ///
+    /// SQL equivalent of *is_readable_by()* for the node table alias,
+    /// the verifying key of the reader is bound to the parameter ?{key_param} and its base64 encoding to the next one
+    ///
+    pub fn readable_by_condition(table: &str, key_param: usize) -> String {
+        let encoded_param = key_param + 1;
+        format!(
+            "CASE
+                WHEN {table}.verifying_key = ?{key_param} OR {table}._json IS NULL THEN 1
+                WHEN NOT json_valid({table}._json) THEN 0
+                WHEN IFNULL(json_type({table}._json, '$.{READERS_FIELD}'), 'null') = 'null' THEN 1
+                WHEN json_type({table}._json, '$.{READERS_FIELD}') = 'array' THEN
+                    EXISTS (SELECT 1 FROM json_each({table}._json, '$.{READERS_FIELD}') WHERE value = ?{encoded_param})
+                ELSE 0
+            END"
+        )
+    }
+
     pub fn hash(&self) -> Result<blake3::Hash> {
         let mut hasher = blake3::Hasher::new();
         hasher.update(&self.id);
@@ -248,12 +298,16 @@ impl Node {
         entity: &str,
         conn: &Connection,
     ) -> std::result::Result<Option<Box<Node>>, rusqlite::Error> {
-        const QUERY: &str = "
-            SELECT id , room_id, cdate, mdate, _entity,_json, _binary, verifying_key, _signature, rowid  
+        const QUERY: &str = concat!(
+            "
+            SELECT id , room_id, cdate, mdate, _entity,_json, ",
+            binary_column!(),
+            ", verifying_key, _signature, rowid  
             FROM _node 
             WHERE 
             id = ? AND 
-            _entity = ?";
+            _entity = ?"
+        );
         let mut get_stmt = conn.prepare_cached(QUERY)?;
         let node = get_stmt
             .query_row((id, entity), Self::NODE_MAPPING)
@@ -261,13 +315,17 @@ impl Node {
         Ok(node)
     }
 
-    pub const NODE_ROOM_QUERY: &'static str = "
-    SELECT id , room_id, cdate, mdate, _entity,_json, _binary, verifying_key, _signature, rowid  
+    pub const NODE_ROOM_QUERY: &'static str = concat!(
+        "
+    SELECT id , room_id, cdate, mdate, _entity,_json, ",
+        binary_column!(),
+        ", verifying_key, _signature, rowid  
     FROM _node 
     WHERE 
         id = ? AND 
         room_id = ? AND  
-        _entity = ?";
+        _entity = ?"
+    );
     ///
     /// Retrieve a node using its primary key
     ///
@@ -327,6 +385,7 @@ impl Node {
         node_fts_str: &Option<String>,
     ) -> std::result::Result<(), rusqlite::Error> {
         static UPDATE_FTS_QUERY: &str = "INSERT INTO _node_fts (rowid, text) VALUES (?, ?)";
+        let (binary, binary_hash) = BinaryStore::store(&self._binary, conn)?;
         if let Some(id) = self._local_id {
             if index {
                 if let Some(previous) = old_fts_str {
@@ -353,7 +412,8 @@ impl Node {
                 _json = ?,
                 _binary = ?,
                 verifying_key = ?,
-                _signature = ?
+                _signature = ?,
+                _binary_hash = ?
             WHERE
                 rowid = ? ",
             )?;
@@ -365,9 +425,10 @@ impl Node {
                 &self.mdate,
                 &self._entity,
                 &self._json,
-                &self._binary,
+                binary,
                 &self.verifying_key,
                 &self._signature,
+                &binary_hash,
                 id,
             ))?;
         } else {
@@ -381,9 +442,10 @@ impl Node {
                     _json,
                     _binary,
                     verifying_key,
-                    _signature
+                    _signature,
+                    _binary_hash
                 ) VALUES (
-                    ?, ?, ?, ?, ?, ?, ?, ?, ?
+                    ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
                 )",
             )?;
             let rowid = insert_stmt.insert((
@@ -393,9 +455,10 @@ impl Node {
                 &self.mdate,
                 &self._entity,
                 &self._json,
-                &self._binary,
+                binary,
                 &self.verifying_key,
                 &self._signature,
+                &binary_hash,
             ))?;
             self._local_id = Some(rowid);
             if index {
@@ -412,24 +475,39 @@ impl Node {
     // retrieve all node id for a room at a specific date
     // used for synchonisation
     //
+    ///
+    /// the nodes that the reader cannot read are not listed
+    ///
     pub fn get_daily_nodes_for_room(
         room_id: &Uid,
         entity: String,
         day: i64,
+        reader: &[u8],
         batch_size: usize,
         sender: &mpsc::Sender<Result<HashSet<NodeIdentifier>>>,
         conn: &Connection,
     ) -> Result<()> {
-        let query = "SELECT id, mdate, _signature
+        let query = format!(
+            "SELECT id, mdate, _signature
             FROM _node 
             WHERE 
-                room_id = ? AND
-                _entity = ? AND
-                mdate >= ? AND mdate < ? 
-            ORDER BY mdate DESC";
-        let mut stmt = conn.prepare_cached(query)?;
-
-        let mut rows = stmt.query((room_id, entity, date(day), date_next_day(day)))?;
+                room_id = ?1 AND
+                _entity = ?2 AND
+                mdate >= ?3 AND mdate < ?4 AND
+                {}
+            ORDER BY mdate DESC",
+            Self::readable_by_condition("_node", 5)
+        );
+        let mut stmt = conn.prepare_cached(&query)?;
+
+        let mut rows = stmt.query((
+            room_id,
+            entity,
+            date(day),
+            date_next_day(day),
+            reader,
+            base64_encode(reader),
+        ))?;
         let mut res = HashSet::new();
         let mut len = 0;
         while let Some(row) = rows.next()? {
@@ -482,16 +560,17 @@ impl Node {
         }
 
         let query = format!("
-        SELECT id , room_id, cdate, mdate, _entity,_json, _binary, verifying_key, _signature, rowid  
+        SELECT id , room_id, cdate, mdate, _entity,_json, {}, verifying_key, _signature, rowid  
         FROM _node 
         WHERE 
          id in ({}) ",
-            q,);
+            binary_column!(), q,);
 
         let mut stmt = conn.prepare(&query)?;
         let mut rows = stmt.query(params_from_iter(ids.iter()))?;
 
         let mut result = Vec::new();
+        let mut room_sync_dates: HashMap<Uid, Option<i64>> = HashMap::new();
         while let Some(row) = rows.next()? {
             let node = Node {
                 id: row.get(0)?,
@@ -535,6 +614,18 @@ impl Node {
                         None
                     };
 
+                    let old_room_sync_date = match &node.room_id {
+                        Some(room_id) => match room_sync_dates.get(room_id) {
+                            Some(date) => *date,
+                            None => {
+                                let date = RoomSynchronisation::get(room_id, conn)?;
+                                room_sync_dates.insert(*room_id, date);
+                                date
+                            }
+                        },
+                        None => None,
+                    };
+
                     let node_to_insert = NodeToInsert {
                         id: node_id.id,
                         node: None,
@@ -544,6 +635,7 @@ impl Node {
                         old_room_id: node.room_id,
                         old_mdate: node.mdate,
                         old_verifying_key: Some(node.verifying_key),
+                        old_room_sync_date,
                         old_fts_str: old_fts,
                         node_fts_str: None,
                     };
@@ -563,6 +655,7 @@ impl Node {
                 old_room_id: None,
                 old_mdate: 0,
                 old_verifying_key: None,
+                old_room_sync_date: None,
                 old_fts_str: None,
                 node_fts_str: None,
             };
@@ -577,7 +670,7 @@ impl Node {
         room_id: &Uid,
         node_ids: Vec<Uid>,
         batch_size: usize,
-        sender: &mpsc::Sender<Result<Vec<Node>>>,
+        sender: &mpsc::Sender<Result<NodeBatch>>,
         conn: &Connection,
     ) -> Result<()> {
         let it = &mut node_ids.iter().peekable();
@@ -592,7 +685,7 @@ impl Node {
         let query = format!(
             "
         SELECT 
-            id, room_id, cdate, mdate, _entity, _json, _binary, verifying_key, _signature, rowid
+            id, room_id, cdate, mdate, _entity, _json, _binary, verifying_key, _signature, rowid, _binary_hash
         FROM _node
         WHERE 
             id in ({}) 
@@ -604,7 +697,7 @@ impl Node {
         let mut rows = stmt.query(params_from_iter(node_ids.iter()))?;
 
         let mut len = 0;
-        let mut res: Vec<Node> = Vec::new();
+        let mut res = NodeBatch::default();
         while let Some(row) = rows.next()? {
             let id: Uid = row.get(0)?;
             let db_room_id: Option<Uid> = row.get(1)?;
@@ -630,12 +723,16 @@ impl Node {
                 _signature: row.get(8)?,
                 _local_id: row.get(9)?,
             };
-            let size = bincode::serialized_size(&node)?;
+            let binary_hash: Option<Vec<u8>> = row.get(10)?;
+            let mut size = bincode::serialized_size(&node)?;
+            if let Some(binary_hash) = &binary_hash {
+                size += (binary_hash.len() + node.id.len()) as u64 + VEC_OVERHEAD;
+            }
             let insert_len = len + size + VEC_OVERHEAD;
 
             if insert_len > batch_size as u64 {
                 let ready = res;
-                res = Vec::new();
+                res = NodeBatch::default();
                 len = 0;
                 let s = sender.blocking_send(Ok(ready));
                 if s.is_err() {
@@ -644,9 +741,12 @@ impl Node {
             } else {
                 len = insert_len;
             }
-            res.push(node);
+            if let Some(binary_hash) = binary_hash {
+                res.binary_hashes.insert(node.id, binary_hash);
+            }
+            res.nodes.push(node);
         }
-        if !res.is_empty() {
+        if !res.nodes.is_empty() {
             let _ = sender.blocking_send(Ok(res));
         }
         Ok(())
@@ -654,6 +754,7 @@ impl Node {
 }
 impl Writeable for Node {
     fn write(&mut self, conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        let (binary, binary_hash) = BinaryStore::store(&self._binary, conn)?;
         if let Some(id) = self._local_id {
             let mut update_node_stmt = conn.prepare_cached(
                 "
@@ -666,7 +767,8 @@ impl Writeable for Node {
                 _json = ?,
                 _binary = ?,
                 verifying_key = ?,
-                _signature = ?
+                _signature = ?,
+                _binary_hash = ?
             WHERE
                 rowid = ? ",
             )?;
@@ -678,9 +780,10 @@ impl Writeable for Node {
                 &self.mdate,
                 &self._entity,
                 &self._json,
-                &self._binary,
+                binary,
                 &self.verifying_key,
                 &self._signature,
+                &binary_hash,
                 id,
             ))?;
         } else {
@@ -694,9 +797,10 @@ impl Writeable for Node {
                     _json,
                     _binary,
                     verifying_key,
-                    _signature
+                    _signature,
+                    _binary_hash
                 ) VALUES (
-                    ?, ?, ?, ?, ?, ?, ?, ?, ?
+                    ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
                 )",
             )?;
             let rowid = insert_stmt.insert((
@@ -706,15 +810,28 @@ impl Writeable for Node {
                 &self.mdate,
                 &self._entity,
                 &self._json,
-                &self._binary,
+                binary,
                 &self.verifying_key,
                 &self._signature,
+                &binary_hash,
             ))?;
             self._local_id = Some(rowid);
         }
         Ok(())
     }
 }
+///
+/// Nodes sent during synchronisation
+///
+/// The binaries stored in the *BinaryStore* are not sent with the node, only their hash is.
+/// The receiving peer only downloads the binaries it does not already have.
+///
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct NodeBatch {
+    pub nodes: Vec<Node>,
+    pub binary_hashes: HashMap<Uid, Vec<u8>>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NodeIdentifier {
     pub id: Uid,
@@ -746,6 +863,8 @@ pub struct NodeToInsert {
     pub old_room_id: Option<Uid>,
     pub old_mdate: i64,
     pub old_verifying_key: Option<Vec<u8>>,
+    //last successful synchronisation of the room of the existing node
+    pub old_room_sync_date: Option<i64>,
     pub old_local_id: Option<i64>,
     pub old_fts_str: Option<String>,
     pub node_fts_str: Option<String>,
@@ -1020,6 +1139,88 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn node_readers() {
+        let author = [1u8; 32];
+        let reader = [2u8; 32];
+        let other = [3u8; 32];
+        let mut node = Node {
+            _entity: "TEST".to_string(),
+            verifying_key: author.to_vec(),
+            ..Default::default()
+        };
+        assert!(node.is_readable_by(&other));
+
+        node._json = Some(format!(
+            r#"{{"{}":["{}"]}}"#,
+            READERS_FIELD,
+            base64_encode(&reader)
+        ));
+        assert!(node.is_readable_by(&author));
+        assert!(node.is_readable_by(&reader));
+        assert!(!node.is_readable_by(&other));
+
+        node._json = Some(format!(r#"{{"{}":null}}"#, READERS_FIELD));
+        assert!(node.is_readable_by(&other));
+
+        //unparsable readers fail closed
+        node._json = Some(format!(r#"{{"{}":["#, READERS_FIELD));
+        assert!(node.is_readable_by(&author));
+        assert!(!node.is_readable_by(&reader));
+        node._json = Some(format!(
+            r#"{{"{}":"{}"}}"#,
+            READERS_FIELD,
+            base64_encode(&reader)
+        ));
+        assert!(!node.is_readable_by(&reader));
+    }
+
+    #[test]
+    fn readable_by_condition() {
+        let conn = Connection::open_in_memory().unwrap();
+        Node::create_tables(&conn).unwrap();
+
+        let author = [1u8; 32];
+        let reader = [2u8; 32];
+        let other = [3u8; 32];
+        let encoded = base64_encode(&reader);
+        let jsons = [
+            None,
+            Some("{}".to_string()),
+            Some(format!(r#"{{"{}":null}}"#, READERS_FIELD)),
+            Some(format!(r#"{{"{}":["{}"]}}"#, READERS_FIELD, encoded)),
+            Some(format!(r#"{{"{}":[]}}"#, READERS_FIELD)),
+            Some(format!(r#"{{"{}":"{}"}}"#, READERS_FIELD, encoded)),
+            Some(format!(r#"{{"{}":["#, READERS_FIELD)),
+        ];
+        let query = format!(
+            "SELECT {} FROM _node WHERE id = ?1",
+            Node::readable_by_condition("_node", 2)
+        );
+        for json in jsons {
+            let node = Node {
+                id: new_uid(),
+                _entity: "TEST".to_string(),
+                verifying_key: author.to_vec(),
+                _json: json,
+                ..Default::default()
+            };
+            conn.execute(
+                "INSERT INTO _node (id, cdate, mdate, _entity, _json, verifying_key, _signature) VALUES (?, 0, 0, ?, ?, ?, ?)",
+                (&node.id, &node._entity, &node._json, &node.verifying_key, &node._signature),
+            )
+            .unwrap();
+            for key in [author, reader, other] {
+                let readable: bool = conn
+                    .query_row(&query, (&node.id, &key, base64_encode(&key)), |row| {
+                        row.get(0)
+                    })
+                    .unwrap();
+                assert_eq!(node.is_readable_by(&key), readable, "{:?}", node._json);
+            }
+        }
+    }
+
     #[test]
     fn node_signature() {
         let keypair = Ed25519SigningKey::new();
@@ -1034,13 +1235,13 @@ mod tests {
         node.sign(&keypair).unwrap();
         node.verify().unwrap();
 
-        node.cdate = node.cdate + 1;
+        node.cdate += 1;
         node.verify()
             .expect_err("cdate has changed, the verification fails");
         node.sign(&keypair).unwrap();
         node.verify().unwrap();
 
-        node.mdate = node.mdate + 1;
+        node.mdate += 1;
         node.verify()
             .expect_err("mdate has changed, the verification fails");
         node.sign(&keypair).unwrap();
@@ -1228,7 +1429,7 @@ mod tests {
         assert!(!node_exists);
 
         let mut exists_stmt = conn.prepare("SELECT count(1) FROM _node").unwrap();
-        let num_nodes: i64 = exists_stmt.query_row([], |row| Ok(row.get(0)?)).unwrap();
+        let num_nodes: i64 = exists_stmt.query_row([], |row| row.get(0)).unwrap();
         assert_eq!(0, num_nodes);
     }
 
@@ -1283,7 +1484,7 @@ mod tests {
         let room_id1 = new_uid();
         let date = 1000;
         let mut node1 = Node {
-            room_id: Some(room_id1.clone()),
+            room_id: Some(room_id1),
             _entity: String::from(entity),
             mdate: date,
             ..Default::default()
@@ -1292,7 +1493,7 @@ mod tests {
         node1.write(&conn, false, &None, &None).unwrap();
 
         let mut node2 = Node {
-            room_id: Some(room_id1.clone()),
+            room_id: Some(room_id1),
             _entity: String::from(entity),
             mdate: date,
             ..Default::default()
@@ -1301,7 +1502,7 @@ mod tests {
         node2.write(&conn, false, &None, &None).unwrap();
 
         let mut node3 = Node {
-            room_id: Some(room_id1.clone()),
+            room_id: Some(room_id1),
             _entity: String::from(entity),
             mdate: date,
             ..Default::default()
@@ -1314,6 +1515,7 @@ mod tests {
             &room_id1,
             entity.to_string(),
             date,
+            &[],
             1024 * 8,
             &reply,
             &conn,
@@ -1332,6 +1534,7 @@ mod tests {
             &room_id1,
             entity.to_string(),
             date,
+            &[],
             1024 * 8,
             &reply,
             &conn,
@@ -1382,7 +1585,7 @@ mod tests {
         assert_eq!(&node._entity, &entry.entity);
         assert_eq!(&node.mdate, &entry.mdate);
 
-        assert!(Node::get_with_entity(&node.id, &entity, &conn)
+        assert!(Node::get_with_entity(&node.id, entity, &conn)
             .unwrap()
             .is_some());
 
@@ -1394,7 +1597,7 @@ mod tests {
             log_with_author.into_iter().map(|e| e.1 .0).collect();
         NodeDeletionEntry::delete_all(&mut deletion, &mut DailyMutations::new(), &conn).unwrap();
 
-        assert!(Node::get_with_entity(&node.id, &entity, &conn)
+        assert!(Node::get_with_entity(&node.id, entity, &conn)
             .unwrap()
             .is_none());
     }
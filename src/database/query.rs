@@ -1,16 +1,29 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+};
 
 use rusqlite::{OptionalExtension, ToSql};
+use serde::Serialize;
 
-use crate::base64_decode;
+use crate::{base64_decode, base64_encode};
 
+use super::binary_store::{binary_column, binary_expression};
+use super::custom_function::sql_name;
 use super::query_language::query_parser::{
-    Direction, EntityParams, EntityQuery, Function, QueryField, QueryFieldType,
+    CustomFunction, Direction, EntityParams, EntityQuery, ExistsFilter, ExpressionToken,
+    FilterGroup, FilterParam, Function, FunctionArg, GeoArea, OrderBy, QueryField, QueryFieldType,
+    ResultShape,
+};
+use super::query_language::{self, FieldType, FieldValue, ParamValue};
+use super::query_language::{
+    data_model_parser::Field, parameter::Parameters, query_parser::QueryParser,
 };
-use super::query_language::{parameter::Parameters, query_parser::QueryParser};
-use super::query_language::{FieldType, FieldValue, ParamValue};
+use super::search::SNIPPET_FUNCTION;
 use super::system_entities::{
-    ID_FIELD, PEER_FIELD, ROOM_FIELD, ROOM_ID_FIELD, VERIFYING_KEY_FIELD,
+    BINARY_FIELD, DELETED_FIELD, ENTITY_FIELD, ID_FIELD, PEER_FIELD, READERS_FIELD, ROOM_FIELD,
+    ROOM_ID_FIELD, SYSTEM_NAMESPACE, TOMBSTONE_ENT_SHORT, TOMBSTONE_TARGET_SHORT,
+    VERIFYING_KEY_FIELD,
 };
 use super::Error;
 use super::Result;
@@ -21,11 +34,80 @@ pub struct Param {
     value: String,
 }
 
+//suffix of the result key containing the cursor of the next page
+pub const NEXT_CURSOR_SUFFIX: &str = "_next_cursor";
+
 #[derive(Debug, Default)]
 pub struct SingleQuery {
     pub name: String,
     pub var_order: Vec<Param>,
     pub sql_query: String,
+    pub shape: ResultShape,
+    //base64 verifying key of the local peer, used to hide nodes restricted to other readers
+    pub reader: Option<String>,
+    pub cursor: Option<CursorPaging>,
+}
+
+///
+/// pagination of a query using the cursor($c) parameter
+///
+#[derive(Debug)]
+pub struct CursorPaging {
+    //result key of the next cursor
+    pub key: String,
+    pub variable: String,
+    pub first: FieldValue,
+    //number of Order By fields
+    pub len: usize,
+}
+impl CursorPaging {
+    fn new(entity: &EntityQuery, shape: &ResultShape) -> Option<Self> {
+        entity.params.cursor.as_ref().map(|variable| Self {
+            key: shape.key(&format!("{}{}", entity.aliased_name(), NEXT_CURSOR_SUFFIX)),
+            variable: variable.clone(),
+            first: entity.params.first.clone(),
+            len: entity.params.order_by.len(),
+        })
+    }
+
+    ///
+    /// converts the opaque cursor parameter into the JSON array of Order By values used by the SQL query
+    ///
+    fn decode(&self, parameters: &mut Parameters) -> Result<()> {
+        let param = match parameters.params.get_mut(&self.variable) {
+            Some(param) => param,
+            None => return Ok(()),
+        };
+        if let ParamValue::String(cursor) = param {
+            let invalid = || Error::InvalidCursor(cursor.clone());
+            let json = base64_decode(cursor.as_bytes()).map_err(|_| invalid())?;
+            let json = String::from_utf8(json).map_err(|_| invalid())?;
+            let values: serde_json::Value = serde_json::from_str(&json).map_err(|_| invalid())?;
+            match values.as_array() {
+                Some(values) if values.len() == self.len => {}
+                _ => return Err(invalid()),
+            }
+            *param = ParamValue::String(json);
+        }
+        Ok(())
+    }
+
+    ///
+    /// the cursor of the next page, None when the result contains the last row
+    ///
+    /// last contains the JSON array of the Order By values of the last returned row
+    ///
+    fn next(&self, rows: i64, last: Option<String>, parameters: &Parameters) -> Option<String> {
+        let first = match &self.first {
+            FieldValue::Variable(var) => parameters.params.get(var).and_then(|v| v.as_i64()),
+            FieldValue::Value(val) => val.as_i64(),
+        }
+        .unwrap_or(0);
+        if first <= 0 || rows < first {
+            return None;
+        }
+        last.map(|last| base64_encode(last.as_bytes()))
+    }
 }
 
 impl SingleQuery {
@@ -45,16 +127,28 @@ impl SingleQuery {
         }
     }
 
-    pub fn build(entity: &EntityQuery) -> Result<Self> {
+    pub fn build(
+        entity: &EntityQuery,
+        shape: &ResultShape,
+        reader: Option<String>,
+    ) -> Result<Self> {
         let mut prepared_query = SingleQuery {
-            name: String::from(&entity.aliased_name()),
+            name: shape.key(&entity.aliased_name()),
+            shape: shape.clone(),
+            reader,
+            cursor: CursorPaging::new(entity, shape),
             ..Default::default()
         };
         let mut query = String::new();
         query.push_str("SELECT \n");
-        query.push_str("json_group_array(value->'$') \n");
+        query.push_str(&group_array(shape));
+        if prepared_query.cursor.is_some() {
+            //Order By values of the last row
+            query.push_str(", json_group_array(_cursor)->>'$[#-1]', count(1)");
+        }
+        query.push_str(" \n");
         query.push_str("FROM (\n");
-        let sub = get_entity_query(entity, &mut prepared_query, 1);
+        let sub = get_entity_query(entity, &mut prepared_query, 1)?;
         query.push_str(&sub);
         query.push_str("\n )");
 
@@ -62,6 +156,26 @@ impl SingleQuery {
         Ok(prepared_query)
     }
 
+    ///
+    /// checks the presence of the entities matching the filters without building the JSON result
+    /// - exists: returns 1 if a row matches, SQLite stops at the first matching row
+    /// - otherwise: returns the number of matching rows
+    ///
+    pub fn build_head(entity: &EntityQuery, reader: Option<String>, exists: bool) -> Result<Self> {
+        let mut prepared_query = SingleQuery {
+            name: entity.aliased_name(),
+            reader,
+            ..Default::default()
+        };
+        let source = get_entity_source(entity, &mut prepared_query, 2)?;
+        prepared_query.sql_query = if exists {
+            format!("SELECT EXISTS (\n\tSELECT 1\n{}\n)", source)
+        } else {
+            format!("SELECT count(1) FROM (\n\tSELECT 1\n{}\n)", source)
+        };
+        Ok(prepared_query)
+    }
+
     pub fn build_query_params(
         &self,
         params: &Parameters,
@@ -94,6 +208,12 @@ impl SingleQuery {
                         ParamValue::Binary(e) => {
                             v.push(Box::new(base64_decode(e.as_bytes())?));
                         }
+                        ParamValue::Array(_) => {
+                            //arrays are provided as a JSON array to be used with json_each()
+                            v.push(Box::new(serde_json::to_string(
+                                &val.as_serde_json_value()?,
+                            )?));
+                        }
                     }
                 } else {
                     return Err(Error::MissingParameter(String::from(&var.value)));
@@ -108,14 +228,34 @@ pub fn get_entity_query(
     entity: &EntityQuery,
     prepared_query: &mut SingleQuery,
     t: usize,
-) -> String {
+) -> Result<String> {
     let mut q = String::new();
     tab(&mut q, t);
     q.push_str("SELECT \n");
-    let selection = get_fields(entity, prepared_query, &entity.sql_aliased_name(), t);
+    let selection = get_fields(entity, prepared_query, &entity.sql_aliased_name(), t)?;
     tab(&mut q, t);
     q.push_str(&selection);
-    q.push_str(" as value\n");
+    q.push_str(" as value");
+    if entity.params.cursor.is_some() {
+        q.push_str(&format!(
+            ", {} as _cursor",
+            get_cursor_values(&entity.params)
+        ));
+    }
+    q.push('\n');
+    q.push_str(&get_entity_source(entity, prepared_query, t)?);
+    Ok(q)
+}
+
+//
+// FROM and WHERE clauses of an entity query, followed by the ordering and the limits
+//
+fn get_entity_source(
+    entity: &EntityQuery,
+    prepared_query: &mut SingleQuery,
+    t: usize,
+) -> Result<String> {
+    let mut q = String::new();
     tab(&mut q, t);
     q.push_str(&format!("FROM _node {}", entity.sql_aliased_name()));
     let search = get_search_join(&entity.params, &entity.sql_aliased_name(), t);
@@ -125,16 +265,41 @@ pub fn get_entity_query(
     tab(&mut q, t);
     q.push_str("WHERE \n");
     tab(&mut q, t);
-    q.push_str(&format!(
-        "{}._entity='{}' ",
-        entity.sql_aliased_name(),
-        &entity.short_name
+    if entity.is_wildcard() {
+        let shorts: Vec<String> = entity
+            .wildcard
+            .iter()
+            .map(|(short, _)| format!("'{}'", short))
+            .collect();
+        q.push_str(&format!(
+            "{}._entity IN ({}) ",
+            entity.sql_aliased_name(),
+            shorts.join(",")
+        ));
+    } else {
+        q.push_str(&format!(
+            "{}._entity='{}' ",
+            entity.sql_aliased_name(),
+            &entity.short_name
+        ));
+    }
+    q.push_str(&get_tombstone_filter(entity, &entity.sql_aliased_name()));
+    q.push_str(&get_soft_delete_filter(entity, &entity.sql_aliased_name()));
+    q.push_str(&get_readers_filter(
+        entity,
+        prepared_query,
+        &entity.sql_aliased_name(),
+    ));
+    q.push_str(&get_edit_chain_filter(
+        entity,
+        prepared_query,
+        &entity.sql_aliased_name(),
     ));
 
-    let exists = get_exists_query(entity, prepared_query, &entity.sql_aliased_name(), t);
+    let exists = get_exists_query(entity, prepared_query, &entity.sql_aliased_name(), t)?;
     q.push_str(&exists);
 
-    let end = get_end_select_query(entity, prepared_query, t);
+    let end = get_end_select_query(entity, prepared_query, &entity.sql_aliased_name(), t)?;
     q.push_str(&end);
 
     q.push('\n');
@@ -142,7 +307,7 @@ pub fn get_entity_query(
     let limit = get_limit(&entity.params, prepared_query);
     q.push_str(&limit);
 
-    q
+    Ok(q)
 }
 
 pub fn get_exists_query(
@@ -150,69 +315,154 @@ pub fn get_exists_query(
     prepared_query: &mut SingleQuery,
     parent_table: &str,
     t: usize,
-) -> String {
+) -> Result<String> {
     let mut q = String::new();
     for field in &entity.fields {
         let field_name = &field.name();
         let field_short = &field.field.short_name;
         match &field.field_type {
-            QueryFieldType::EntityArrayQuery(sub_entity, nullable) => {
-                if !nullable && !entity.params.nullable.contains(field_name) {
-                    tab(&mut q, t);
-                    q.push_str("AND EXISTS (\n");
-                    let sub = get_sub_entity_query(
+            QueryFieldType::EntityArrayQuery(sub_entity, nullable)
+                if !nullable && !entity.params.nullable.contains(field_name) =>
+            {
+                tab(&mut q, t);
+                q.push_str("AND EXISTS (\n");
+                let sub = get_sub_entity_query(
+                    sub_entity,
+                    prepared_query,
+                    parent_table,
+                    field_name,
+                    field_short,
+                    t + 1,
+                    false,
+                )?;
+                q.push_str(&sub);
+                q.push('\n');
+                tab(&mut q, t);
+                q.push(')');
+                q.push('\n');
+            }
+            QueryFieldType::EntityQuery(sub_entity, nullable)
+                if !nullable && !entity.params.nullable.contains(field_name) =>
+            {
+                tab(&mut q, t);
+                q.push_str("AND EXISTS (\n");
+                let sub = if field.field.is_system {
+                    get_sub_system_entity_query(
+                        sub_entity,
+                        prepared_query,
+                        parent_table,
+                        &field.name(),
+                        &field.field.name,
+                        t + 1,
+                        true,
+                    )?
+                } else {
+                    get_sub_entity_query(
                         sub_entity,
                         prepared_query,
                         parent_table,
                         field_name,
                         field_short,
                         t + 1,
-                        false,
-                    );
-                    q.push_str(&sub);
-                    q.push('\n');
-                    tab(&mut q, t);
-                    q.push(')');
-                    q.push('\n');
-                }
-            }
-            QueryFieldType::EntityQuery(sub_entity, nullable) => {
-                if !nullable && !entity.params.nullable.contains(field_name) {
-                    tab(&mut q, t);
-                    q.push_str("AND EXISTS (\n");
-                    let sub = if field.field.is_system {
-                        get_sub_system_entity_query(
-                            sub_entity,
-                            prepared_query,
-                            parent_table,
-                            &field.name(),
-                            &field.field.name,
-                            t + 1,
-                            true,
-                        )
-                    } else {
-                        get_sub_entity_query(
-                            sub_entity,
-                            prepared_query,
-                            parent_table,
-                            field_name,
-                            field_short,
-                            t + 1,
-                            true,
-                        )
-                    };
+                        true,
+                    )?
+                };
 
-                    q.push_str(&sub);
-                    q.push('\n');
-                    tab(&mut q, t);
-                    q.push(')');
-                    q.push('\n');
-                }
+                q.push_str(&sub);
+                q.push('\n');
+                tab(&mut q, t);
+                q.push(')');
+                q.push('\n');
             }
             _ => {}
         }
     }
-    q
+    for exists in &entity.params.exists_filters {
+        let filter = get_exists_filter(exists, prepared_query, parent_table, t)?;
+        q.push_str(&filter);
+    }
+    Ok(q)
+}
+
+///
+/// filters on the presence of related entities without selecting them
+/// the alias is suffixed by the indentation level to avoid conflicts between nested exists() filters
+///
+fn get_exists_filter(
+    exists: &ExistsFilter,
+    prepared_query: &mut SingleQuery,
+    parent_table: &str,
+    t: usize,
+) -> Result<String> {
+    let entity = &exists.entity;
+    let node_table = format!("_exists{}", t);
+    let mut q = String::new();
+    tab(&mut q, t);
+    if exists.negated {
+        q.push_str("AND NOT EXISTS (\n");
+    } else {
+        q.push_str("AND EXISTS (\n");
+    }
+    tab(&mut q, t + 1);
+    q.push_str(&format!("SELECT 1 FROM _node {}", node_table));
+    let search = get_search_join(&entity.params, &node_table, t + 1);
+    q.push_str(&search);
+
+    q.push('\n');
+    tab(&mut q, t + 1);
+    q.push_str("WHERE \n");
+    tab(&mut q, t + 1);
+    q.push_str(&format!(
+        "{}._entity='{}' AND \n",
+        node_table, &entity.short_name
+    ));
+    tab(&mut q, t + 1);
+    if exists.field.is_system {
+        match exists.field.name.as_str() {
+            PEER_FIELD => {
+                q.push_str(&format!(
+                    "{}.{}={}.{} ",
+                    node_table, VERIFYING_KEY_FIELD, parent_table, VERIFYING_KEY_FIELD
+                ));
+            }
+            ROOM_FIELD => {
+                q.push_str(&format!(
+                    "{}.{}={}.{} ",
+                    node_table, ID_FIELD, parent_table, ROOM_ID_FIELD
+                ));
+            }
+            _ => unreachable!(),
+        }
+    } else {
+        q.push_str(&format!(
+            "{0}.id IN (SELECT dest FROM _edge WHERE _edge.src={1}.id AND _edge.label='{2}') ",
+            node_table, parent_table, &exists.field.short_name
+        ));
+    }
+    q.push_str(&get_tombstone_filter(entity, &node_table));
+    q.push_str(&get_soft_delete_filter(entity, &node_table));
+    q.push_str(&get_readers_filter(entity, prepared_query, &node_table));
+
+    let nested = get_exists_query(entity, prepared_query, &node_table, t + 1)?;
+    q.push_str(&nested);
+
+    let search = get_search_filter(&entity.params, prepared_query, t + 1);
+    q.push_str(&search);
+
+    let filters = get_where_filters(&entity.params, prepared_query, t + 1)?;
+    q.push_str(&filters);
+
+    let geo = get_geo_filters(&entity.params, prepared_query, &node_table, t + 1);
+    q.push_str(&geo);
+
+    let dates = get_date_filters(&entity.params, prepared_query, &node_table, t + 1);
+    q.push_str(&dates);
+
+    q.push('\n');
+    tab(&mut q, t);
+    q.push(')');
+    q.push('\n');
+    Ok(q)
 }
 
 pub fn get_sub_group_array(
@@ -222,12 +472,13 @@ pub fn get_sub_group_array(
     field_name: &str,
     field_short: &str,
     t: usize,
-) -> String {
+) -> Result<String> {
     let mut q = String::new();
     tab(&mut q, t);
     q.push_str("SELECT \n");
     tab(&mut q, t);
-    q.push_str("json_group_array(value->'$') as value \n");
+    q.push_str(&group_array(&prepared_query.shape));
+    q.push_str(" as value \n");
     tab(&mut q, t);
     q.push_str("FROM (\n");
     let sub = get_sub_entity_query(
@@ -238,14 +489,26 @@ pub fn get_sub_group_array(
         field_short,
         t + 1,
         false,
-    );
+    )?;
     q.push_str(&sub);
     q.push('\n');
     tab(&mut q, t);
     q.push(')');
     q.push('\n');
     tab(&mut q, t);
-    q
+    Ok(q)
+}
+
+//
+// aggregates the selected rows into a JSON array
+// a single row is returned as is when the result is flattened
+//
+fn group_array(shape: &ResultShape) -> String {
+    if shape.flatten {
+        String::from("CASE WHEN count(1)=1 THEN json_group_array(value->'$')->'$[0]' ELSE json_group_array(value->'$') END")
+    } else {
+        String::from("json_group_array(value->'$')")
+    }
 }
 
 pub fn get_sub_entity_query(
@@ -256,11 +519,11 @@ pub fn get_sub_entity_query(
     field_short: &str,
     t: usize,
     is_unique_value: bool,
-) -> String {
+) -> Result<String> {
     let mut q = String::new();
     tab(&mut q, t);
     q.push_str("SELECT \n");
-    let selection = get_fields(entity, prepared_query, field_name, t);
+    let selection = get_fields(entity, prepared_query, field_name, t)?;
     tab(&mut q, t);
     q.push_str(&selection);
     q.push_str(" as value \n");
@@ -283,11 +546,14 @@ pub fn get_sub_entity_query(
     ));
     tab(&mut q, t);
     q.push_str(&format!("_edge.src={}.id ", &parent_table));
+    q.push_str(&get_tombstone_filter(entity, field_name));
+    q.push_str(&get_soft_delete_filter(entity, field_name));
+    q.push_str(&get_readers_filter(entity, prepared_query, field_name));
 
-    let exists = get_exists_query(entity, prepared_query, field_name, t);
+    let exists = get_exists_query(entity, prepared_query, field_name, t)?;
     q.push_str(&exists);
 
-    let end = get_end_select_query(entity, prepared_query, t);
+    let end = get_end_select_query(entity, prepared_query, field_name, t)?;
     q.push_str(&end);
 
     q.push('\n');
@@ -298,7 +564,7 @@ pub fn get_sub_entity_query(
     } else {
         q.push_str("LIMIT 1 ");
     }
-    q
+    Ok(q)
 }
 
 ///
@@ -314,11 +580,11 @@ pub fn get_sub_system_entity_query(
     field_system_name: &str,
     t: usize,
     is_unique_value: bool,
-) -> String {
+) -> Result<String> {
     let mut q = String::new();
     tab(&mut q, t);
     q.push_str("SELECT \n");
-    let selection = get_fields(entity, prepared_query, field_name, t);
+    let selection = get_fields(entity, prepared_query, field_name, t)?;
     tab(&mut q, t);
     q.push_str(&selection);
     q.push_str(" as value \n");
@@ -354,10 +620,10 @@ pub fn get_sub_system_entity_query(
         _ => unreachable!(),
     }
 
-    let exists = get_exists_query(entity, prepared_query, field_name, t);
+    let exists = get_exists_query(entity, prepared_query, field_name, t)?;
     q.push_str(&exists);
 
-    let end = get_end_select_query(entity, prepared_query, t);
+    let end = get_end_select_query(entity, prepared_query, field_name, t)?;
     q.push_str(&end);
 
     q.push('\n');
@@ -368,24 +634,139 @@ pub fn get_sub_system_entity_query(
     } else {
         q.push_str("LIMIT 1 ");
     }
-    q
+    Ok(q)
+}
+
+///
+/// excludes the nodes targeted by a sys.Tombstone of the same room
+/// system entities cannot be moderated
+///
+pub fn get_tombstone_filter(entity: &EntityQuery, node_table: &str) -> String {
+    if entity.name.starts_with(&format!("{}.", SYSTEM_NAMESPACE)) {
+        return String::new();
+    }
+    format!("AND {} ", tombstone_exclusion(node_table))
+}
+
+///
+/// SQL condition that is false for the nodes targeted by a sys.Tombstone of the same room
+///
+pub fn tombstone_exclusion(node_table: &str) -> String {
+    format!(
+        "NOT EXISTS (SELECT 1 FROM _node _tombstone WHERE _tombstone._entity='{1}' AND _tombstone.room_id={0}.room_id AND _tombstone._json->>'$.{2}'=base64_encode({0}.id))",
+        node_table, TOMBSTONE_ENT_SHORT, TOMBSTONE_TARGET_SHORT
+    )
+}
+
+///
+/// excludes the soft deleted nodes of the entities defining soft_delete, unless include_deleted is used
+///
+pub fn get_soft_delete_filter(entity: &EntityQuery, node_table: &str) -> String {
+    if entity.soft_delete.is_empty() || entity.params.include_deleted {
+        return String::new();
+    }
+    if entity.is_wildcard() {
+        let shorts: Vec<String> = entity
+            .soft_delete
+            .iter()
+            .map(|short| format!("'{}'", short))
+            .collect();
+        return format!(
+            "AND ({0}._json->>'$.{1}' IS NULL OR {0}._entity NOT IN ({2})) ",
+            node_table,
+            DELETED_FIELD,
+            shorts.join(",")
+        );
+    }
+    format!(
+        "AND {0}._json->>'$.{1}' IS NULL ",
+        node_table, DELETED_FIELD
+    )
+}
+
+///
+/// entities defining an edit_chain only return the latest version of each node.
+/// Versions reference the original node, and are ignored when their author is not the author of the original.
+/// A version received before its original is hidden until the original is received.
+///
+/// versions($id) returns the original node and all its versions instead
+///
+pub fn get_edit_chain_filter(
+    entity: &EntityQuery,
+    prepared_query: &mut SingleQuery,
+    node_table: &str,
+) -> String {
+    let chain = match &entity.edit_chain {
+        Some(chain) => chain,
+        None => return String::new(),
+    };
+    if let Some(versions) = &entity.params.versions {
+        let original = match versions {
+            FieldValue::Variable(var) => prepared_query.add_param(String::from(var), false),
+            FieldValue::Value(value) => {
+                let value = value.as_string().cloned().unwrap_or_default();
+                prepared_query.add_param(value, true)
+            }
+        };
+        return format!(
+            "AND ({0}.id=base64_decode({2}) OR {0}._json->>'$.{1}'={2}) AND {0}.verifying_key=(SELECT _original.verifying_key FROM _node _original WHERE _original.id=base64_decode({2}) AND _original._entity={0}._entity AND _original._json->>'$.{1}' IS NULL) ",
+            node_table, chain, original
+        );
+    }
+    format!(
+        "AND CASE WHEN {0}._json->>'$.{1}' IS NULL \
+            THEN NOT EXISTS (SELECT 1 FROM _node _version WHERE _version._entity={0}._entity AND _version.room_id IS {0}.room_id AND _version._json->>'$.{1}'=base64_encode({0}.id) AND _version.verifying_key={0}.verifying_key) \
+            ELSE EXISTS (SELECT 1 FROM _node _original WHERE _original.id=base64_decode({0}._json->>'$.{1}') AND _original._entity={0}._entity AND _original.room_id IS {0}.room_id AND _original.verifying_key={0}.verifying_key AND _original._json->>'$.{1}' IS NULL) \
+            AND NOT EXISTS (SELECT 1 FROM _node _version WHERE _version._entity={0}._entity AND _version.room_id IS {0}.room_id AND _version._json->>'$.{1}'={0}._json->>'$.{1}' AND _version.verifying_key={0}.verifying_key AND (_version.cdate>{0}.cdate OR (_version.cdate={0}.cdate AND _version.id>{0}.id))) \
+            END ",
+        node_table, chain
+    )
+}
+
+///
+/// hides the nodes that defines a list of readers that does not contains the local peer
+///
+pub fn get_readers_filter(
+    entity: &EntityQuery,
+    prepared_query: &mut SingleQuery,
+    node_table: &str,
+) -> String {
+    if entity.name.starts_with(&format!("{}.", SYSTEM_NAMESPACE)) {
+        return String::new();
+    }
+    let reader = match &prepared_query.reader {
+        Some(reader) => reader.clone(),
+        None => return String::new(),
+    };
+    let var = prepared_query.add_param(reader, true);
+    format!(
+        "AND ({0}._json->>'$.{1}' IS NULL OR base64_encode({0}.verifying_key)={2} OR EXISTS (SELECT 1 FROM json_each({0}._json, '$.{1}') WHERE value={2})) ",
+        node_table, READERS_FIELD, var
+    )
 }
 
 pub fn get_end_select_query(
     entity: &EntityQuery,
     prepared_query: &mut SingleQuery,
+    node_table: &str,
     t: usize,
-) -> String {
+) -> Result<String> {
     let mut q = String::new();
 
     let search = get_search_filter(&entity.params, prepared_query, t);
     q.push_str(&search);
 
-    let filters = get_where_filters(&entity.params, prepared_query, t);
+    let filters = get_where_filters(&entity.params, prepared_query, t)?;
     q.push_str(&filters);
 
+    let geo = get_geo_filters(&entity.params, prepared_query, node_table, t);
+    q.push_str(&geo);
+
+    let dates = get_date_filters(&entity.params, prepared_query, node_table, t);
+    q.push_str(&dates);
+
     if entity.is_aggregate {
-        let group_by = get_group_by(&entity.fields, t);
+        let group_by = get_group_by(entity, node_table, t);
         q.push_str(&group_by);
         if !entity.params.aggregate_filters.is_empty()
             | !entity.params.before.is_empty()
@@ -397,7 +778,7 @@ pub fn get_end_select_query(
             tab(&mut q, t);
         }
     }
-    let having = get_having_filters(&entity.params, prepared_query, t);
+    let having = get_having_filters(&entity.params, prepared_query, t)?;
     q.push_str(&having);
 
     if !entity.params.aggregate_filters.is_empty()
@@ -411,16 +792,23 @@ pub fn get_end_select_query(
         q.push_str(" AND \n");
         tab(&mut q, t);
     }
-    let paging = get_paging(&entity.params, prepared_query);
+    let paging = get_paging(&entity.params, prepared_query)?;
     q.push_str(&paging);
 
+    if entity.params.cursor.is_some() {
+        q.push_str(" AND \n");
+        tab(&mut q, t);
+        let cursor = get_cursor_paging(&entity.params, prepared_query);
+        q.push_str(&cursor);
+    }
+
     if !entity.params.order_by.is_empty() || entity.params.fulltext_search.is_some() {
         q.push('\n');
         tab(&mut q, t);
-        let order_by = get_order(&entity.params);
+        let order_by = get_order(&entity.params, &prepared_query.shape);
         q.push_str(&order_by);
     }
-    q
+    Ok(q)
 }
 
 pub fn tab(q: &mut String, t: usize) {
@@ -429,31 +817,103 @@ pub fn tab(q: &mut String, t: usize) {
     }
 }
 
+//
+// array values are only supported by the 'in' filter
+//
+fn invalid_array_value() -> Error {
+    Error::Parsing(query_language::Error::InvalidQuery(String::from(
+        "an array value can only be used with the 'in' operator",
+    )))
+}
+
 fn js_field(field: &str) -> String {
     format!("_json->'$.{}'", field)
 }
 
+//
+// Enum fields are stored using the position of the value.
+// converts the stored position into the value
+//
+fn enum_value(values: &[String], stored: &str, prepared_query: &mut SingleQuery) -> String {
+    let mut q = format!("CASE {}", stored);
+    for (i, value) in values.iter().enumerate() {
+        let param = prepared_query.add_param(String::from(value), true);
+        q.push_str(&format!(" WHEN {} THEN {}", i, param));
+    }
+    q.push_str(" END");
+    q
+}
+
+//
+// converts an Enum value into its stored position
+//
+fn enum_position(values: &[String], value: &str, prepared_query: &mut SingleQuery) -> String {
+    let mut q = format!("(CASE {}", value);
+    for (i, val) in values.iter().enumerate() {
+        let param = prepared_query.add_param(String::from(val), true);
+        q.push_str(&format!(" WHEN {} THEN {}", param, i));
+    }
+    q.push_str(" END)");
+    q
+}
+
+//
+// the '_all' query returns the entity name instead of its short name
+//
+fn wildcard_name(
+    entity: &EntityQuery,
+    node_table: &str,
+    prepared_query: &mut SingleQuery,
+) -> String {
+    let mut q = format!("CASE {}._entity", node_table);
+    for (short, name) in &entity.wildcard {
+        let param = prepared_query.add_param(String::from(name), true);
+        q.push_str(&format!(" WHEN '{}' THEN {}", short, param));
+    }
+    q.push_str(" END");
+    q
+}
+
 fn get_fields(
     entity: &EntityQuery,
     prepared_query: &mut SingleQuery,
     parent_table: &str,
     t: usize,
-) -> String {
+) -> Result<String> {
     let mut q = String::new();
     q.push_str("json_object(");
-    let it = &mut entity.fields.iter().peekable();
+    if prepared_query.shape.entity_names {
+        let name = if entity.is_wildcard() {
+            wildcard_name(entity, parent_table, prepared_query)
+        } else {
+            prepared_query.add_param(String::from(&entity.name), true)
+        };
+        q.push('\n');
+        tab(&mut q, t);
+        q.push_str(&format!("'_entity', {},", name));
+    }
+    let mut fields: Vec<&QueryField> = entity.fields.iter().collect();
+    if prepared_query.shape.ordered {
+        fields.sort_by_cached_key(|f| prepared_query.shape.key(&f.name()));
+    }
+    let it = &mut fields.into_iter().peekable();
     while let Some(field) = it.next() {
         q.push('\n');
         tab(&mut q, t);
+        let key = prepared_query.shape.key(&field.name());
 
         match &field.field_type {
             QueryFieldType::Binary => {
-                if field.field.is_system {
+                if field.field.is_system && field.field.short_name.eq(BINARY_FIELD) {
+                    q.push_str(&format!(
+                        "'{}', base64_encode({})",
+                        &key,
+                        binary_expression(parent_table),
+                    ));
+                } else if field.field.is_system {
                     q.push_str(&format!(
                         "'{}', base64_encode({}.{})",
-                        &field.name(),
-                        parent_table,
-                        &field.field.short_name,
+                        &key, parent_table, &field.field.short_name,
                     ));
                 } else if let Some(val) = &field.field.default_value {
                     let default = match val {
@@ -462,27 +922,45 @@ fn get_fields(
                     };
                     q.push_str(&format!(
                         "'{}',Ifnull({},{})",
-                        &field.name(),
+                        &key,
                         js_field(&field.field.short_name),
                         default
                     ))
                 } else {
-                    q.push_str(&format!(
-                        "'{}',{}",
-                        &field.name(),
-                        js_field(&field.field.short_name)
-                    ))
+                    q.push_str(&format!("'{}',{}", &key, js_field(&field.field.short_name)))
                 }
             }
 
             QueryFieldType::Scalar => {
-                if field.field.is_system {
+                if entity.is_wildcard() && field.field.name.eq(ENTITY_FIELD) {
+                    q.push_str(&format!(
+                        "'{}', {}",
+                        &key,
+                        wildcard_name(entity, parent_table, prepared_query)
+                    ));
+                } else if field.field.is_system {
                     q.push_str(&format!(
                         "'{}', {}.{}",
-                        &field.name(),
-                        parent_table,
-                        &field.field.short_name,
+                        &key, parent_table, &field.field.short_name,
                     ));
+                } else if let FieldType::Enum(values) = &field.field.field_type {
+                    let default = field
+                        .field
+                        .default_value
+                        .as_ref()
+                        .and_then(|v| v.as_string())
+                        .and_then(|v| field.field.field_type.enum_position(v));
+                    let stored = match default {
+                        Some(pos) => {
+                            format!("Ifnull(_json->>'$.{}',{})", &field.field.short_name, pos)
+                        }
+                        None => format!("_json->>'$.{}'", &field.field.short_name),
+                    };
+                    q.push_str(&format!(
+                        "'{}',{}",
+                        &key,
+                        enum_value(values, &stored, prepared_query)
+                    ))
                 } else if let Some(val) = &field.field.default_value {
                     let default = match val {
                         ParamValue::Boolean(b) => b.to_string(),
@@ -490,20 +968,17 @@ fn get_fields(
                         ParamValue::Float(f) => f.to_string(),
                         ParamValue::String(s) => prepared_query.add_param(String::from(s), true),
                         ParamValue::Binary(s) => prepared_query.add_param(String::from(s), true),
+                        ParamValue::Array(_) => return Err(invalid_array_value()),
                         ParamValue::Null => unreachable!(),
                     };
                     q.push_str(&format!(
                         "'{}',Ifnull({},{})",
-                        &field.name(),
+                        &key,
                         js_field(&field.field.short_name),
                         default
                     ))
                 } else {
-                    q.push_str(&format!(
-                        "'{}',{}",
-                        &field.name(),
-                        js_field(&field.field.short_name)
-                    ))
+                    q.push_str(&format!("'{}',{}", &key, js_field(&field.field.short_name)))
                 }
             }
 
@@ -522,21 +997,17 @@ fn get_fields(
                         ParamValue::Float(f) => f.to_string(),
                         ParamValue::String(s) => prepared_query.add_param(String::from(s), true),
                         ParamValue::Binary(s) => prepared_query.add_param(String::from(s), true),
+                        ParamValue::Array(_) => return Err(invalid_array_value()),
                         ParamValue::Null => unreachable!(),
                     };
-                    q.push_str(&format!(
-                        "'{}', Ifnull({},{}",
-                        &field.name(),
-                        select,
-                        default
-                    ))
+                    q.push_str(&format!("'{}', Ifnull({},{}", &key, select, default))
                 } else {
-                    q.push_str(&format!("'{}',{}", &field.name(), select))
+                    q.push_str(&format!("'{}',{}", &key, select))
                 }
             }
 
             QueryFieldType::EntityQuery(field_entity, _) => {
-                q.push_str(&format!("'{}', (\n", &field.name()));
+                q.push_str(&format!("'{}', (\n", &key));
 
                 let query = if field.field.is_system {
                     get_sub_system_entity_query(
@@ -547,7 +1018,7 @@ fn get_fields(
                         &field.field.name,
                         t + 1,
                         true,
-                    )
+                    )?
                 } else {
                     get_sub_entity_query(
                         field_entity,
@@ -557,7 +1028,7 @@ fn get_fields(
                         &field.field.short_name,
                         t + 1,
                         true,
-                    )
+                    )?
                 };
                 q.push_str(&query);
                 q.push('\n');
@@ -566,7 +1037,7 @@ fn get_fields(
             }
 
             QueryFieldType::EntityArrayQuery(field_entity, _) => {
-                q.push_str(&format!("'{}', (\n", &field.name()));
+                q.push_str(&format!("'{}', (\n", &key));
                 let query = get_sub_group_array(
                     field_entity,
                     prepared_query,
@@ -574,7 +1045,7 @@ fn get_fields(
                     &field.name(),
                     &field.field.short_name,
                     t + 1,
-                );
+                )?;
                 q.push_str(&query);
                 q.push('\n');
                 tab(&mut q, t);
@@ -589,16 +1060,16 @@ fn get_fields(
                         } else {
                             js_field(f)
                         };
-                        format!("'{}', avg({}) ", &field.name(), agg_field)
+                        format!("'{}', avg({}) ", &key, agg_field)
                     }
-                    Function::Count => format!("'{}',count(1) ", &field.name()),
+                    Function::Count => format!("'{}',count(1) ", &key),
                     Function::Max(f) => {
                         let agg_field = if field.field.is_system {
                             field.field.name.clone()
                         } else {
                             js_field(f)
                         };
-                        format!("'{}', max({}) ", &field.name(), agg_field)
+                        format!("'{}', max({}) ", &key, agg_field)
                     }
                     Function::Min(f) => {
                         let agg_field = if field.field.is_system {
@@ -606,7 +1077,7 @@ fn get_fields(
                         } else {
                             js_field(f)
                         };
-                        format!("'{}', min({}) ", &field.name(), agg_field)
+                        format!("'{}', min({}) ", &key, agg_field)
                     }
                     Function::Sum(f) => {
                         let agg_field = if field.field.is_system {
@@ -614,175 +1085,152 @@ fn get_fields(
                         } else {
                             js_field(f)
                         };
-                        format!("'{}', total({}) ", &field.name(), agg_field)
+                        format!("'{}', total({}) ", &key, agg_field)
                     }
                 };
                 q.push_str(&func);
             }
-        }
-
-        if it.peek().is_some() {
-            q.push(',');
-        }
-    }
-    q.push(')');
-    q
-}
 
-fn get_where_filters(params: &EntityParams, prepared_query: &mut SingleQuery, t: usize) -> String {
-    let mut q = String::new();
+            QueryFieldType::Custom(function) => {
+                let call = custom_function_call(function, prepared_query, parent_table)?;
+                q.push_str(&format!("'{}', {}", &key, call));
+            }
 
-    if !params.filters.is_empty() {
-        q.push_str("AND ");
-        q.push('\n');
-        tab(&mut q, t);
-        let it = &mut params.filters.iter().peekable();
-        while let Some(filter) = it.next() {
-            let mut operation = filter.operation.clone();
+            QueryFieldType::FormattedDate(date_only) => {
+                let column = numeric_column(&field.field, parent_table);
+                let format = if *date_only {
+                    format!("strftime('%Y-%m-%d', {} / 1000, 'unixepoch')", column)
+                } else {
+                    format!(
+                        "strftime('%Y-%m-%dT%H:%M:%fZ', {} / 1000.0, 'unixepoch')",
+                        column
+                    )
+                };
+                q.push_str(&format!("'{}', {}", &key, format));
+            }
 
-            let value = match &filter.value {
-                FieldValue::Variable(var) => prepared_query.add_param(String::from(var), false),
-                FieldValue::Value(val) => match val {
-                    ParamValue::Boolean(bool) => bool.to_string(),
-                    ParamValue::Integer(i) => i.to_string(),
-                    ParamValue::Float(f) => f.to_string(),
-                    ParamValue::String(s) => prepared_query.add_param(String::from(s), true),
-                    ParamValue::Binary(s) => prepared_query.add_param(String::from(s), true),
-                    ParamValue::Null => {
-                        match filter.operation.as_str() {
-                            "=" => operation = String::from("is"),
-                            "!=" => operation = String::from("is not"),
-                            _ => {}
-                        }
-                        String::from("null")
-                    }
-                },
-            };
+            QueryFieldType::Computed(tokens) => {
+                q.push_str(&format!(
+                    "'{}', {}",
+                    &key,
+                    computed_expression(tokens, parent_table)
+                ));
+            }
 
-            if filter.field.is_system {
-                q.push_str(&format!("{} {} {}", &filter.name, operation, &value));
-            } else {
-                match filter.field.field_type {
-                    FieldType::Array(_) => {
-                        q.push_str(&format!(
-                            "value->>'$.{}[0]' {} {}",
-                            &filter.name, operation, &value
-                        ));
-                    }
+            QueryFieldType::Snippet(length) => {
+                //the query parser ensures that the entity is searched
+                let term = match &entity.params.fulltext_search {
+                    Some(term) => search_term(term, prepared_query),
+                    None => String::from("''"),
+                };
+                q.push_str(&format!(
+                    "'{}', {}(_json->>'$.{}', {}, {})",
+                    &key, SNIPPET_FUNCTION, &field.field.short_name, term, length
+                ));
+            }
+        }
 
-                    FieldType::Entity(_) => {
-                        q.push_str(&format!(
-                            "value->>'$.{}' {} {}",
-                            &filter.name, operation, &value
-                        ));
+        if it.peek().is_some() {
+            q.push(',');
+        }
+    }
+    q.push(')');
+    Ok(q)
+}
+
+//
+// arithmetic expression of a computed field
+//
+fn computed_expression(tokens: &[ExpressionToken], parent_table: &str) -> String {
+    let mut q = String::new();
+    for token in tokens {
+        match token {
+            ExpressionToken::Field(field) => q.push_str(&numeric_column(field, parent_table)),
+            ExpressionToken::Number(number) => q.push_str(number),
+            ExpressionToken::Now => q.push_str(NOW_MILLISECONDS),
+            ExpressionToken::Operator(operator) => q.push_str(&format!(" {} ", operator)),
+            ExpressionToken::Open => q.push('('),
+            ExpressionToken::Close => q.push(')'),
+        }
+    }
+    q
+}
+
+//
+// calls a function registered by the application
+//
+fn custom_function_call(
+    function: &CustomFunction,
+    prepared_query: &mut SingleQuery,
+    parent_table: &str,
+) -> Result<String> {
+    let mut args = Vec::with_capacity(function.args.len());
+    for arg in &function.args {
+        let arg = match arg {
+            FunctionArg::Field(field) => {
+                if field.is_system {
+                    format!("{}.{}", parent_table, &field.short_name)
+                } else {
+                    let stored = format!("_json->>'$.{}'", &field.short_name);
+                    match &field.default_value {
+                        Some(ParamValue::String(s)) => format!(
+                            "Ifnull({},{})",
+                            stored,
+                            prepared_query.add_param(String::from(s), true)
+                        ),
+                        Some(ParamValue::Boolean(b)) => format!("Ifnull({},{})", stored, b),
+                        Some(ParamValue::Integer(i)) => format!("Ifnull({},{})", stored, i),
+                        Some(ParamValue::Float(f)) => format!("Ifnull({},{})", stored, f),
+                        _ => stored,
                     }
-                    _ => match &filter.field.default_value {
-                        Some(default) => {
-                            q.push_str("CASE\n");
-                            // tab(&mut q, t);
-                            // (
-                            //
-                            //     CASE ?1
-                            //         WHEN ?2 //default value
-                            //         THEN
-                            //             name = ?1 or name is null
-                            //         ELSE
-                            //             name = ?1
-                            //     END
-                            // )
-
-                            match default {
-                                ParamValue::Boolean(v) => {
-                                    tab(&mut q, t + 1);
-                                    q.push_str(&format!(
-                                        "WHEN {} {} {} THEN ",
-                                        v, operation, &value
-                                    ));
-                                }
-                                ParamValue::Integer(v) => {
-                                    tab(&mut q, t + 1);
-                                    q.push_str(&format!(
-                                        "WHEN {} {} {} THEN ",
-                                        v, operation, &value
-                                    ));
-                                }
-                                ParamValue::Float(v) => {
-                                    tab(&mut q, t + 1);
-                                    q.push_str(&format!(
-                                        "WHEN {} {} {} THEN ",
-                                        v, operation, &value
-                                    ));
-                                }
-                                ParamValue::String(v) => {
-                                    tab(&mut q, t + 1);
-                                    q.push_str(&format!(
-                                        "WHEN '{}' {} {} THEN ",
-                                        v, operation, &value
-                                    ));
-                                }
-                                ParamValue::Binary(v) => {
-                                    tab(&mut q, t + 1);
-                                    q.push_str(&format!(
-                                        "WHEN '{}' {} {} THEN ",
-                                        v, operation, &value
-                                    ));
-                                }
-                                _ => unreachable!(),
-                            }
-
-                            if filter.is_selected {
-                                q.push_str(&format!(
-                                    "value->>'$.{}' {} {} OR value->>'$.{}' is null \n",
-                                    &filter.name, operation, &value, &filter.name
-                                ));
-                            } else {
-                                q.push_str(&format!(
-                                    "_json->>'$.{}' {} {} OR _json->>'$.{}' is null \n",
-                                    &filter.field.short_name,
-                                    operation,
-                                    &value,
-                                    &filter.field.short_name,
-                                ));
-                            }
-                            tab(&mut q, t + 1);
-                            q.push_str("ELSE ");
-                            if filter.is_selected {
-                                q.push_str(&format!(
-                                    "value->>'$.{}' {} {} \n",
-                                    &filter.name, operation, &value
-                                ));
-                            } else {
-                                q.push_str(&format!(
-                                    "_json->>'$.{}' {} {} \n",
-                                    &filter.field.short_name, operation, &value
-                                ));
-                            }
-                            tab(&mut q, t);
-                            q.push_str("END");
-                        }
-                        None => {
-                            if filter.is_selected {
-                                q.push_str(&format!(
-                                    "value->>'$.{}' {} {}",
-                                    &filter.name, operation, &value
-                                ));
-                            } else {
-                                q.push_str(&format!(
-                                    "_json->>'$.{}' {} {}",
-                                    &filter.field.short_name, operation, &value
-                                ));
-                            }
-                        }
-                    },
                 }
             }
+            FunctionArg::Value(FieldValue::Variable(var)) => {
+                prepared_query.add_param(String::from(var), false)
+            }
+            FunctionArg::Value(FieldValue::Value(val)) => match val {
+                ParamValue::Boolean(bool) => bool.to_string(),
+                ParamValue::Integer(i) => i.to_string(),
+                ParamValue::Float(f) => f.to_string(),
+                ParamValue::String(s) => prepared_query.add_param(String::from(s), true),
+                ParamValue::Binary(s) => prepared_query.add_param(String::from(s), true),
+                ParamValue::Array(_) => return Err(invalid_array_value()),
+                ParamValue::Null => String::from("null"),
+            },
+        };
+        args.push(arg);
+    }
+    Ok(format!("{}({})", sql_name(&function.name), args.join(", ")))
+}
 
+fn get_where_filters(
+    params: &EntityParams,
+    prepared_query: &mut SingleQuery,
+    t: usize,
+) -> Result<String> {
+    let mut q = String::new();
+
+    if !params.filters.is_empty() {
+        q.push_str("AND ");
+        q.push('\n');
+        tab(&mut q, t);
+        let it = &mut params.filters.iter().peekable();
+        while let Some(filter) = it.next() {
+            q.push_str(&filter_condition(filter, prepared_query, t)?);
             if it.peek().is_some() {
                 q.push_str(" AND\n");
                 tab(&mut q, t);
             }
         }
     }
+    for group in &params.filter_groups {
+        q.push('\n');
+        tab(&mut q, t);
+        q.push_str("AND ");
+        q.push_str(&filter_group_condition(group, prepared_query, t)?);
+        q.push('\n');
+        tab(&mut q, t);
+    }
     if !params.json_filters.is_empty() {
         q.push_str("AND ");
         q.push('\n');
@@ -799,6 +1247,7 @@ fn get_where_filters(params: &EntityParams, prepared_query: &mut SingleQuery, t:
                     ParamValue::Float(f) => f.to_string(),
                     ParamValue::String(s) => prepared_query.add_param(String::from(s), true),
                     ParamValue::Binary(s) => prepared_query.add_param(String::from(s), true),
+                    ParamValue::Array(_) => return Err(invalid_array_value()),
                     ParamValue::Null => {
                         match filter.operation.as_str() {
                             "=" => operation = String::from("is"),
@@ -825,10 +1274,379 @@ fn get_where_filters(params: &EntityParams, prepared_query: &mut SingleQuery, t:
             }
         }
     }
+    Ok(q)
+}
+
+fn filter_condition(
+    filter: &FilterParam,
+    prepared_query: &mut SingleQuery,
+    t: usize,
+) -> Result<String> {
+    let mut q = String::new();
+    let mut operation = filter.operation.clone();
+
+    let mut value = match &filter.value {
+        FieldValue::Variable(var) => prepared_query.add_param(String::from(var), false),
+        FieldValue::Value(val) => match val {
+            ParamValue::Boolean(bool) => bool.to_string(),
+            ParamValue::Integer(i) => i.to_string(),
+            ParamValue::Float(f) => f.to_string(),
+            ParamValue::String(s) => prepared_query.add_param(String::from(s), true),
+            ParamValue::Binary(s) => prepared_query.add_param(String::from(s), true),
+            ParamValue::Array(_) => return Err(invalid_array_value()),
+            ParamValue::Null => {
+                match filter.operation.as_str() {
+                    "=" => operation = String::from("is"),
+                    "!=" => operation = String::from("is not"),
+                    _ => {}
+                }
+                String::from("null")
+            }
+        },
+    };
+
+    if operation.eq("in") {
+        //the array parameter is a JSON array of base64 encoded values
+        value = if filter.field.is_system
+            && !filter.is_selected
+            && filter.field.field_type == FieldType::Base64
+        {
+            format!("(SELECT base64_decode(value) FROM json_each({}))", value)
+        } else {
+            format!("(SELECT value FROM json_each({}))", value)
+        };
+    }
+
+    //LIKE patterns use '\' as escape character,
+    //starts_with and contains escape the wildcards of the value before adding their own
+    match operation.as_str() {
+        "like" => {
+            operation = String::from("LIKE");
+            value = format!("{} ESCAPE '\\'", value);
+        }
+        "starts_with" => {
+            operation = String::from("LIKE");
+            value = format!("{} || '%' ESCAPE '\\'", escape_like(&value));
+        }
+        "contains" => {
+            operation = String::from("LIKE");
+            value = format!("'%' || {} || '%' ESCAPE '\\'", escape_like(&value));
+        }
+        _ => {}
+    }
+
+    let mut default_value = filter.field.default_value.clone();
+    if let FieldType::Enum(values) = &filter.field.field_type {
+        if !filter.is_selected {
+            if !value.eq("null") {
+                value = enum_position(values, &value, prepared_query);
+            }
+            default_value = default_value
+                .as_ref()
+                .and_then(|v| v.as_string())
+                .and_then(|v| filter.field.field_type.enum_position(v))
+                .map(ParamValue::Integer);
+        }
+    }
+
+    let collate = filter.field.collation.sql();
+    if filter.field.is_system && filter.name.eq(BINARY_FIELD) {
+        q.push_str(&format!("{} {} {}", binary_column!(), operation, &value));
+    } else if filter.field.is_system {
+        q.push_str(&format!("{} {} {}", &filter.name, operation, &value));
+    } else {
+        match filter.field.field_type {
+            FieldType::Array(_) => {
+                q.push_str(&format!(
+                    "value->>'$.{}[0]' {} {}",
+                    prepared_query.shape.key(&filter.name),
+                    operation,
+                    &value
+                ));
+            }
+
+            FieldType::Entity(_) => {
+                q.push_str(&format!(
+                    "value->>'$.{}' {} {}",
+                    prepared_query.shape.key(&filter.name),
+                    operation,
+                    &value
+                ));
+            }
+            _ => match &default_value {
+                Some(default) => {
+                    q.push_str("CASE\n");
+                    // tab(&mut q, t);
+                    // (
+                    //
+                    //     CASE ?1
+                    //         WHEN ?2 //default value
+                    //         THEN
+                    //             name = ?1 or name is null
+                    //         ELSE
+                    //             name = ?1
+                    //     END
+                    // )
+
+                    match default {
+                        ParamValue::Boolean(v) => {
+                            tab(&mut q, t + 1);
+                            q.push_str(&format!("WHEN {} {} {} THEN ", v, operation, &value));
+                        }
+                        ParamValue::Integer(v) => {
+                            tab(&mut q, t + 1);
+                            q.push_str(&format!("WHEN {} {} {} THEN ", v, operation, &value));
+                        }
+                        ParamValue::Float(v) => {
+                            tab(&mut q, t + 1);
+                            q.push_str(&format!("WHEN {} {} {} THEN ", v, operation, &value));
+                        }
+                        ParamValue::String(v) => {
+                            tab(&mut q, t + 1);
+                            q.push_str(&format!(
+                                "WHEN '{}'{} {} {} THEN ",
+                                v, collate, operation, &value
+                            ));
+                        }
+                        ParamValue::Binary(v) => {
+                            tab(&mut q, t + 1);
+                            q.push_str(&format!("WHEN '{}' {} {} THEN ", v, operation, &value));
+                        }
+                        _ => unreachable!(),
+                    }
+
+                    if filter.is_selected {
+                        q.push_str(&format!(
+                            "value->>'$.{0}'{3} {1} {2} OR value->>'$.{0}' is null \n",
+                            prepared_query.shape.key(&filter.name),
+                            operation,
+                            &value,
+                            collate
+                        ));
+                    } else {
+                        q.push_str(&format!(
+                            "_json->>'$.{}'{} {} {} OR _json->>'$.{}' is null \n",
+                            &filter.field.short_name,
+                            collate,
+                            operation,
+                            &value,
+                            &filter.field.short_name,
+                        ));
+                    }
+                    tab(&mut q, t + 1);
+                    q.push_str("ELSE ");
+                    if filter.is_selected {
+                        q.push_str(&format!(
+                            "value->>'$.{}'{} {} {} \n",
+                            prepared_query.shape.key(&filter.name),
+                            collate,
+                            operation,
+                            &value
+                        ));
+                    } else {
+                        q.push_str(&format!(
+                            "_json->>'$.{}'{} {} {} \n",
+                            &filter.field.short_name, collate, operation, &value
+                        ));
+                    }
+                    tab(&mut q, t);
+                    q.push_str("END");
+                }
+                None => {
+                    if filter.is_selected {
+                        q.push_str(&format!(
+                            "value->>'$.{}'{} {} {}",
+                            prepared_query.shape.key(&filter.name),
+                            collate,
+                            operation,
+                            &value
+                        ));
+                    } else {
+                        q.push_str(&format!(
+                            "_json->>'$.{}'{} {} {}",
+                            &filter.field.short_name, collate, operation, &value
+                        ));
+                    }
+                }
+            },
+        }
+    }
+    Ok(q)
+}
+
+fn escape_like(value: &str) -> String {
+    format!(
+        "replace(replace(replace({}, '\\', '\\\\'), '%', '\\%'), '_', '\\_')",
+        value
+    )
+}
+
+//
+// or(), and() and not() groups are enclosed in parenthesis to be combined with the other filters
+//
+fn filter_group_condition(
+    group: &FilterGroup,
+    prepared_query: &mut SingleQuery,
+    t: usize,
+) -> Result<String> {
+    let (members, separator, negated) = match group {
+        FilterGroup::Filter(filter) => return filter_condition(filter, prepared_query, t),
+        FilterGroup::And(members) => (members, " AND\n", false),
+        FilterGroup::Or(members) => (members, " OR\n", false),
+        FilterGroup::Not(members) => (members, " AND\n", true),
+    };
+    let mut q = String::new();
+    if negated {
+        q.push_str("NOT ");
+    }
+    q.push_str("(\n");
+    let it = &mut members.iter().peekable();
+    while let Some(member) = it.next() {
+        tab(&mut q, t + 1);
+        q.push_str(&filter_group_condition(member, prepared_query, t + 1)?);
+        if it.peek().is_some() {
+            q.push_str(separator);
+        }
+    }
+    q.push('\n');
+    tab(&mut q, t);
+    q.push(')');
+    Ok(q)
+}
+
+//
+// the exact test is always performed,
+// the R*Tree index, when available, selects the candidates with a bounding box
+//
+fn get_geo_filters(
+    params: &EntityParams,
+    prepared_query: &mut SingleQuery,
+    node_table: &str,
+    t: usize,
+) -> String {
+    let mut q = String::new();
+    for filter in &params.geo_filters {
+        let values: Vec<String> = match &filter.area {
+            GeoArea::BoundingBox(values) | GeoArea::Radius(values) => values
+                .iter()
+                .map(|value| match value {
+                    FieldValue::Variable(var) => prepared_query.add_param(String::from(var), false),
+                    FieldValue::Value(ParamValue::Float(f)) => f.to_string(),
+                    FieldValue::Value(ParamValue::Integer(i)) => i.to_string(),
+                    _ => unreachable!(),
+                })
+                .collect(),
+        };
+        let lat = format!(
+            "{}._json->>'$.{}.lat'",
+            node_table, &filter.field.short_name
+        );
+        let lon = format!(
+            "{}._json->>'$.{}.lon'",
+            node_table, &filter.field.short_name
+        );
+
+        //min_lat, min_lon, max_lat, max_lon
+        let bbox = match &filter.area {
+            GeoArea::BoundingBox(_) => values.clone(),
+            GeoArea::Radius(_) => (0..4)
+                .map(|corner| {
+                    format!(
+                        "geo_box({}, {}, {}, {})",
+                        values[0], values[1], values[2], corner
+                    )
+                })
+                .collect(),
+        };
+
+        q.push('\n');
+        tab(&mut q, t);
+        if let Some(index) = &filter.index {
+            q.push_str(&format!(
+                "AND {0}.rowid IN (SELECT id FROM {1} WHERE max_lat >= {2} AND min_lat <= {4} AND (({3} <= {5} AND max_lon >= {3} AND min_lon <= {5}) OR ({3} > {5} AND (max_lon >= {3} OR min_lon <= {5})))) ",
+                node_table, index, bbox[0], bbox[1], bbox[2], bbox[3]
+            ));
+        }
+        match &filter.area {
+            GeoArea::BoundingBox(_) => {
+                //the box crosses the antimeridian when min_lon > max_lon
+                q.push_str(&format!(
+                    "AND {0} BETWEEN {2} AND {4} AND CASE WHEN {3} <= {5} THEN {1} BETWEEN {3} AND {5} ELSE ({1} >= {3} OR {1} <= {5}) END ",
+                    lat, lon, values[0], values[1], values[2], values[3]
+                ));
+            }
+            GeoArea::Radius(_) => {
+                q.push_str(&format!(
+                    "AND geo_distance({}, {}, {}, {}) <= {} ",
+                    lat, lon, values[0], values[1], values[2]
+                ));
+            }
+        }
+    }
     q
 }
 
-fn get_having_filters(params: &EntityParams, prepared_query: &mut SingleQuery, t: usize) -> String {
+//
+// the current time is evaluated once per statement
+//
+fn get_date_filters(
+    params: &EntityParams,
+    prepared_query: &mut SingleQuery,
+    node_table: &str,
+    t: usize,
+) -> String {
+    let mut q = String::new();
+    for filter in &params.date_filters {
+        let days = match &filter.days {
+            FieldValue::Variable(var) => prepared_query.add_param(String::from(var), false),
+            FieldValue::Value(ParamValue::Integer(i)) => i.to_string(),
+            _ => unreachable!(),
+        };
+        let now = if filter.field.field_type == FieldType::Date {
+            "CAST(round((julianday('now', 'start of day') - 2440587.5) * 86400000) AS INTEGER)"
+        } else {
+            NOW_MILLISECONDS
+        };
+        let limit = format!("{} - {} * 86400000", now, days);
+        q.push('\n');
+        tab(&mut q, t);
+        q.push_str(&format!(
+            "AND {} BETWEEN min({1}, {2}) AND max({1}, {2}) ",
+            numeric_column(&filter.field, node_table),
+            now,
+            limit
+        ));
+    }
+    q
+}
+
+//
+// current date in milliseconds since unix epoch
+//
+const NOW_MILLISECONDS: &str = "CAST(round((julianday('now') - 2440587.5) * 86400000) AS INTEGER)";
+
+//
+// value of an Integer, Float or date column with its default value
+// DateTime and Date fields are stored as milliseconds since unix epoch
+//
+fn numeric_column(field: &Field, node_table: &str) -> String {
+    let column = if field.is_system {
+        format!("{}.{}", node_table, &field.short_name)
+    } else {
+        format!("{}._json->>'$.{}'", node_table, &field.short_name)
+    };
+    match &field.default_value {
+        Some(ParamValue::Integer(default)) => format!("ifnull({}, {})", column, default),
+        Some(ParamValue::Float(default)) => format!("ifnull({}, {})", column, default),
+        _ => column,
+    }
+}
+
+fn get_having_filters(
+    params: &EntityParams,
+    prepared_query: &mut SingleQuery,
+    t: usize,
+) -> Result<String> {
     let mut q = String::new();
 
     let it = &mut params.aggregate_filters.iter().peekable();
@@ -843,6 +1661,7 @@ fn get_having_filters(params: &EntityParams, prepared_query: &mut SingleQuery, t
                 ParamValue::Float(f) => f.to_string(),
                 ParamValue::String(s) => prepared_query.add_param(String::from(s), true),
                 ParamValue::Binary(s) => prepared_query.add_param(String::from(s), true),
+                ParamValue::Array(_) => return Err(invalid_array_value()),
                 ParamValue::Null => {
                     match filter.operation.as_str() {
                         "=" => operation = String::from("is"),
@@ -856,7 +1675,9 @@ fn get_having_filters(params: &EntityParams, prepared_query: &mut SingleQuery, t
 
         q.push_str(&format!(
             "value->>'$.{}' {} {}",
-            &filter.name, operation, &value
+            prepared_query.shape.key(&filter.name),
+            operation,
+            &value
         ));
 
         if it.peek().is_some() {
@@ -864,32 +1685,43 @@ fn get_having_filters(params: &EntityParams, prepared_query: &mut SingleQuery, t
             tab(&mut q, t);
         }
     }
-    q
+    Ok(q)
 }
 
-pub fn get_order(params: &EntityParams) -> String {
+//
+// the value sorted by an Order By field: a selected field, a system field, a path of a Json field or a field of the entity
+//
+fn order_value(ord: &OrderBy, shape: &ResultShape) -> String {
+    let collate = ord.field.collation.sql();
+    if ord.is_selected {
+        format!("value->>'$.{}'{}", shape.key(&ord.name), collate)
+    } else if let Some(selector) = &ord.json_selector {
+        format!("{}->>{}", js_field(&ord.field.short_name), selector)
+    } else if ord.field.is_system {
+        ord.name.clone()
+    } else {
+        format!("_json->>'$.{}'{}", &ord.field.short_name, collate)
+    }
+}
+
+pub fn get_order(params: &EntityParams, shape: &ResultShape) -> String {
     let mut query = String::new();
     if params.fulltext_search.is_some() {
         query.push_str("ORDER BY rank");
+        if !params.order_by.is_empty() {
+            query.push_str(", ");
+        }
     } else if !params.order_by.is_empty() {
         query.push_str("ORDER BY ");
-
+    }
+    if !params.order_by.is_empty() {
         let it = &mut params.order_by.iter().peekable();
         while let Some(ord) = it.next() {
             let direction = match ord.direction {
                 Direction::Asc => String::from("asc"),
                 Direction::Desc => String::from("desc"),
             };
-            if ord.is_selected {
-                query.push_str(&format!("value->>'$.{}' {} ", &ord.name, direction));
-            } else if ord.field.is_system {
-                query.push_str(&format!("{} {} ", &ord.name, direction));
-            } else {
-                query.push_str(&format!(
-                    "_json->>'$.{}' {} ",
-                    &ord.field.short_name, direction
-                ));
-            }
+            query.push_str(&format!("{} {} ", order_value(ord, shape), direction));
 
             if it.peek().is_some() {
                 query.push_str(", ");
@@ -913,6 +1745,16 @@ pub fn get_search_join(params: &EntityParams, node_table: &str, t: usize) -> Str
     q
 }
 
+fn search_term(term: &FieldValue, prepared_query: &mut SingleQuery) -> String {
+    match term {
+        FieldValue::Variable(var) => prepared_query.add_param(String::from(var), false),
+        FieldValue::Value(val) => match val {
+            ParamValue::String(s) => prepared_query.add_param(String::from(s), true),
+            _ => unreachable!(),
+        },
+    }
+}
+
 pub fn get_search_filter(
     params: &EntityParams,
     prepared_query: &mut SingleQuery,
@@ -920,13 +1762,7 @@ pub fn get_search_filter(
 ) -> String {
     let mut q = String::new();
     if let Some(query) = &params.fulltext_search {
-        let value = match query {
-            FieldValue::Variable(var) => prepared_query.add_param(String::from(var), false),
-            FieldValue::Value(val) => match val {
-                ParamValue::String(s) => prepared_query.add_param(String::from(s), true),
-                _ => unreachable!(),
-            },
-        };
+        let value = search_term(query, prepared_query);
 
         q.push_str("AND \n");
         tab(&mut q, t);
@@ -935,7 +1771,7 @@ pub fn get_search_filter(
     q
 }
 
-pub fn get_paging(params: &EntityParams, prepared_query: &mut SingleQuery) -> String {
+pub fn get_paging(params: &EntityParams, prepared_query: &mut SingleQuery) -> Result<String> {
     let mut q = String::new();
 
     let mut before = true;
@@ -965,20 +1801,17 @@ pub fn get_paging(params: &EntityParams, prepared_query: &mut SingleQuery) -> St
                     ParamValue::Float(f) => f.to_string(),
                     ParamValue::String(s) => prepared_query.add_param(String::from(s), true),
                     ParamValue::Binary(s) => prepared_query.add_param(String::from(s), true),
+                    ParamValue::Array(_) => return Err(invalid_array_value()),
                     ParamValue::Null => String::from("null"),
                 },
             };
 
-            if ord.is_selected {
-                q.push_str(&format!("value->>'$.{}' = {}", &ord.name, value));
-            } else if ord.field.is_system {
-                q.push_str(&format!("{} = {}", &ord.name, value));
-            } else {
-                q.push_str(&format!(
-                    "_json->>'$.{}' = {}",
-                    &ord.field.short_name, value
-                ));
-            }
+            let value = enum_paging_value(ord, value, prepared_query);
+            q.push_str(&format!(
+                "{} = {}",
+                order_value(ord, &prepared_query.shape),
+                value
+            ));
 
             q.push_str(" AND ");
         }
@@ -993,6 +1826,7 @@ pub fn get_paging(params: &EntityParams, prepared_query: &mut SingleQuery) -> St
                 ParamValue::Float(f) => f.to_string(),
                 ParamValue::String(s) => prepared_query.add_param(String::from(s), true),
                 ParamValue::Binary(s) => prepared_query.add_param(String::from(s), true),
+                ParamValue::Array(_) => return Err(invalid_array_value()),
                 ParamValue::Null => String::from("null"),
             },
         };
@@ -1018,16 +1852,13 @@ pub fn get_paging(params: &EntityParams, prepared_query: &mut SingleQuery) -> St
             }
         };
 
-        if ord.is_selected {
-            q.push_str(&format!("value->>'$.{}' {} {}", &ord.name, ope, value));
-        } else if ord.field.is_system {
-            q.push_str(&format!("{} {} {}", &ord.name, ope, value));
-        } else {
-            q.push_str(&format!(
-                "_json->>'$.{}' {} {}",
-                &ord.field.short_name, ope, value
-            ));
-        }
+        let value = enum_paging_value(ord, value, prepared_query);
+        q.push_str(&format!(
+            "{} {} {}",
+            order_value(ord, &prepared_query.shape),
+            ope,
+            value
+        ));
 
         if paging.len() > 1 {
             q.push(')');
@@ -1041,9 +1872,93 @@ pub fn get_paging(params: &EntityParams, prepared_query: &mut SingleQuery) -> St
         q.push_str(") ");
     }
 
+    Ok(q)
+}
+
+//
+// the stored value of an Order By field, and true if it is a binary system field
+//
+fn cursor_field(ord: &OrderBy) -> (String, bool) {
+    if let Some(selector) = &ord.json_selector {
+        (
+            format!("{}->>{}", js_field(&ord.field.short_name), selector),
+            false,
+        )
+    } else if ord.field.is_system {
+        let binary = matches!(ord.field.field_type, FieldType::Base64);
+        (ord.name.clone(), binary)
+    } else {
+        (format!("_json->>'$.{}'", &ord.field.short_name), false)
+    }
+}
+
+//
+// JSON array of the Order By values of a row, binary values are base64 encoded
+//
+fn get_cursor_values(params: &EntityParams) -> String {
+    let values: Vec<String> = params
+        .order_by
+        .iter()
+        .map(|ord| match cursor_field(ord) {
+            (field, true) => format!("base64_encode({})", field),
+            (field, false) => field,
+        })
+        .collect();
+    format!("json_array({})", values.join(","))
+}
+
+//
+// resumes the query after the row encoded in the cursor, a null cursor returns the first page
+//
+pub fn get_cursor_paging(params: &EntityParams, prepared_query: &mut SingleQuery) -> String {
+    let cursor = match &params.cursor {
+        Some(cursor) => prepared_query.add_param(String::from(cursor), false),
+        None => return String::new(),
+    };
+    let fields: Vec<(String, String)> = params
+        .order_by
+        .iter()
+        .enumerate()
+        .map(|(i, ord)| {
+            let (field, binary) = cursor_field(ord);
+            let value = if binary {
+                format!("base64_decode(json_extract({}, '$[{}]'))", cursor, i)
+            } else {
+                format!("json_extract({}, '$[{}]')", cursor, i)
+            };
+            (format!("{}{}", field, ord.field.collation.sql()), value)
+        })
+        .collect();
+
+    let mut q = format!("({} IS NULL OR ", cursor);
+    for (i, ord) in params.order_by.iter().enumerate() {
+        q.push('(');
+        for (field, value) in fields.iter().take(i) {
+            q.push_str(&format!("{} = {} AND ", field, value));
+        }
+        let ope = match ord.direction {
+            Direction::Asc => '>',
+            Direction::Desc => '<',
+        };
+        let (field, value) = &fields[i];
+        q.push_str(&format!("{} {} {})", field, ope, value));
+        if i < params.order_by.len() - 1 {
+            q.push_str(" OR ");
+        }
+    }
+    q.push_str(") ");
     q
 }
 
+fn enum_paging_value(ord: &OrderBy, value: String, prepared_query: &mut SingleQuery) -> String {
+    match &ord.field.field_type {
+        FieldType::Enum(values) if !ord.is_selected && !value.eq("null") => {
+            enum_position(values, &value, prepared_query)
+        }
+        _ => value,
+    }
+}
+
 pub fn get_limit(params: &EntityParams, prepared_query: &mut SingleQuery) -> String {
     let mut query = String::new();
 
@@ -1077,28 +1992,49 @@ pub fn get_limit(params: &EntityParams, prepared_query: &mut SingleQuery) -> Str
     query
 }
 
-fn get_group_by(fields: &Vec<QueryField>, t: usize) -> String {
+//
+// groups by the fields of the group_by() parameter, or by the selected fields, and by the Json paths of the Order By
+//
+fn get_group_by(entity: &EntityQuery, node_table: &str, t: usize) -> String {
     let mut q = String::new();
 
-    let mut v = Vec::new();
+    let fields: Vec<&Field> = if entity.params.group_by.is_empty() {
+        entity
+            .fields
+            .iter()
+            .filter(|field| matches!(field.field_type, QueryFieldType::Scalar))
+            .map(|field| &field.field)
+            .collect()
+    } else {
+        entity.params.group_by.iter().collect()
+    };
 
-    for field in fields {
-        if let QueryFieldType::Scalar = &field.field_type {
-            v.push(field.field.short_name.clone())
+    let mut groups: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.is_system {
+                format!("{}.{}", node_table, field.short_name)
+            } else {
+                format!("_json->>'$.{}'{}", field.short_name, field.collation.sql())
+            }
+        })
+        .collect();
+
+    for ord in &entity.params.order_by {
+        if let Some(selector) = &ord.json_selector {
+            groups.push(format!(
+                "{}->>{}",
+                js_field(&ord.field.short_name),
+                selector
+            ));
         }
     }
-    if !v.is_empty() {
+
+    if !groups.is_empty() {
         q.push('\n');
         tab(&mut q, t);
-        q.push_str("GROUP BY ")
-    }
-
-    let it = &mut v.iter().peekable();
-    while let Some(field) = it.next() {
-        q.push_str(&format!("_json->>'$.{}'", field));
-        if it.peek().is_some() {
-            q.push(',');
-        }
+        q.push_str("GROUP BY ");
+        q.push_str(&groups.join(","));
     }
 
     q
@@ -1110,16 +2046,43 @@ pub struct PreparedQueries {
     pub sql_queries: Vec<SingleQuery>,
 }
 impl PreparedQueries {
+    #[cfg(test)]
     pub fn build(parser: &QueryParser) -> Result<Self> {
+        Self::build_for_reader(parser, None)
+    }
+
+    ///
+    /// build the queries for a local peer: nodes restricted to a list of readers are only returned to their author and to the listed readers
+    ///
+    pub fn build_for_reader(parser: &QueryParser, reader: Option<&[u8]>) -> Result<Self> {
+        let reader = reader.map(base64_encode);
         let mut sql_queries = Vec::new();
         for query in &parser.queries {
-            sql_queries.push(SingleQuery::build(query)?);
+            sql_queries.push(SingleQuery::build(query, &parser.shape, reader.clone())?);
+        }
+        if parser.shape.ordered {
+            sql_queries.sort_by(|a, b| a.name.cmp(&b.name));
         }
         Ok(Self {
             //   name: String::from(&parser.name),
             sql_queries,
         })
     }
+
+    ///
+    /// build an existence check or a count for a query made of a single entity, see *SingleQuery::build_head()*
+    ///
+    pub fn build_head(parser: &QueryParser, reader: Option<&[u8]>, exists: bool) -> Result<Self> {
+        if parser.queries.len() != 1 {
+            return Err(Error::Query(String::from(
+                "an existence check must query a single entity",
+            )));
+        }
+        let reader = reader.map(base64_encode);
+        Ok(Self {
+            sql_queries: vec![SingleQuery::build_head(&parser.queries[0], reader, exists)?],
+        })
+    }
 }
 
 pub struct Query {
@@ -1138,22 +2101,43 @@ impl Query {
             .validate_params(&mut self.parameters)?;
 
         let quer = &self.sql_queries.sql_queries;
+        let mut decoded = HashSet::new();
+        for cursor in quer.iter().filter_map(|query| query.cursor.as_ref()) {
+            if decoded.insert(&cursor.variable) {
+                cursor.decode(&mut self.parameters)?;
+            }
+        }
+
         for i in 0..quer.len() {
             let query = &quer[i];
             let params_vec = query.build_query_params(&self.parameters)?;
             let sql = &query.sql_query;
             let mut stmt = conn.prepare_cached(sql)?;
             let params = rusqlite::params_from_iter(&params_vec);
-            let query_res: Option<String> = stmt.query_row(params, |row| row.get(0)).optional()?;
-            let result = match query_res {
+            let query_res: Option<(String, Option<String>, i64)> = match &query.cursor {
+                Some(_) => stmt
+                    .query_row(params, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                    .optional()?,
+                None => stmt
+                    .query_row(params, |row| Ok((row.get(0)?, None, 0)))
+                    .optional()?,
+            };
+            let (result, last, rows) = match query_res {
                 Some(e) => e,
-                None => String::from("[]"),
+                None => (String::from("[]"), None, 0),
             };
             result_string.push('"');
             result_string.push_str(&query.name);
             result_string.push('"');
             result_string.push(':');
             result_string.push_str(&result);
+            if let Some(cursor) = &query.cursor {
+                let next = cursor.next(rows, last, &self.parameters);
+                result_string.push_str(",\n\"");
+                result_string.push_str(&cursor.key);
+                result_string.push_str("\":");
+                result_string.push_str(&serde_json::to_string(&next)?);
+            }
             if i < quer.len() - 1 {
                 result_string.push(',');
             }
@@ -1163,4 +2147,126 @@ impl Query {
         result_string.push('}');
         Ok(result_string)
     }
+
+    ///
+    /// runs a query built with *PreparedQueries::build_head()*
+    ///
+    pub fn head(&mut self, conn: &rusqlite::Connection) -> Result<i64> {
+        self.parser
+            .variables
+            .validate_params(&mut self.parameters)?;
+
+        let query = &self.sql_queries.sql_queries[0];
+        let params_vec = query.build_query_params(&self.parameters)?;
+        let mut stmt = conn.prepare_cached(&query.sql_query)?;
+        let result = stmt.query_row(rusqlite::params_from_iter(&params_vec), |row| row.get(0))?;
+        Ok(result)
+    }
+}
+
+///
+/// A query that took longer than the *slow_query_threshold_in_ms* configuration
+/// - query_hash: base64 encoded hash of the query text
+/// - duration: execution time in milliseconds
+/// - rows: number of rows returned
+/// - date: execution date
+///
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct SlowQuery {
+    pub query_hash: String,
+    pub duration: u64,
+    pub rows: usize,
+    pub date: i64,
+}
+impl SlowQuery {
+    ///
+    /// number of rows in a query result
+    ///
+    pub fn count_rows(result: &str) -> usize {
+        let result: serde_json::Value = match serde_json::from_str(result) {
+            Ok(result) => result,
+            Err(_) => return 0,
+        };
+        match result.as_object() {
+            Some(queries) => queries
+                .values()
+                .map(|value| match value {
+                    serde_json::Value::Array(rows) => rows.len(),
+                    serde_json::Value::Null | serde_json::Value::String(_) => 0,
+                    _ => 1,
+                })
+                .sum(),
+            None => 0,
+        }
+    }
+}
+
+///
+/// Keeps the most recent slow queries
+///
+#[derive(Default)]
+pub struct SlowQueries {
+    pub threshold: u64,
+    capacity: usize,
+    queries: VecDeque<SlowQuery>,
+}
+impl SlowQueries {
+    pub fn new(threshold: u64, capacity: usize) -> Self {
+        Self {
+            threshold,
+            capacity,
+            queries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn is_slow(&self, duration: u64) -> bool {
+        self.threshold > 0 && duration >= self.threshold
+    }
+
+    pub fn push(&mut self, query: SlowQuery) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.queries.len() == self.capacity {
+            self.queries.pop_front();
+        }
+        self.queries.push_back(query);
+    }
+
+    ///
+    /// returns the slow queries, the most recent first
+    ///
+    pub fn list(&self) -> Vec<SlowQuery> {
+        self.queries.iter().rev().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slow_queries_ring_buffer() {
+        let mut slow_queries = SlowQueries::new(10, 2);
+        assert!(!slow_queries.is_slow(9));
+        assert!(slow_queries.is_slow(10));
+
+        for i in 0..3 {
+            slow_queries.push(SlowQuery {
+                query_hash: i.to_string(),
+                duration: 10 + i,
+                ..Default::default()
+            });
+        }
+        let list = slow_queries.list();
+        assert_eq!(2, list.len());
+        assert_eq!("2", list[0].query_hash);
+        assert_eq!("1", list[1].query_hash);
+
+        let disabled = SlowQueries::new(0, 2);
+        assert!(!disabled.is_slow(u64::MAX));
+
+        let result = "{\n\"a\":[{\"x\":1},{\"x\":2}],\n\"b\":{\"x\":1},\n\"c\":[]\n}";
+        assert_eq!(3, SlowQuery::count_rows(result));
+    }
 }
@@ -1,18 +1,20 @@
 use crate::{
     database::system_entities::{
-        BINARY_FIELD, CREATION_DATE_FIELD, ENTITY_FIELD, ID_FIELD, JSON_FIELD,
-        MODIFICATION_DATE_FIELD, PEER_ENT, PEER_FIELD, ROOM_ENT, ROOM_FIELD, ROOM_ID_FIELD,
-        SIGNATURE_FIELD, SYSTEM_NAMESPACE, VERIFYING_KEY_FIELD,
+        BINARY_FIELD, CREATION_DATE_FIELD, DELETED_FIELD, ENTITY_FIELD, ID_FIELD, JSON_FIELD,
+        MODIFICATION_DATE_FIELD, PEER_ENT, PEER_FIELD, READERS_FIELD, ROOM_ENT, ROOM_FIELD,
+        ROOM_ID_FIELD, SIGNATURE_FIELD, SYSTEM_NAMESPACE, VERIFYING_KEY_FIELD,
     },
-    security::base64_decode,
+    security::{base64_decode, new_random_uid, new_time_ordered_uid, new_uid, Uid},
 };
 
-use super::{Error, FieldType, ParamValue, VariableType};
+use super::{
+    json_schema::JsonSchema, validate_file_id, Error, FieldType, ParamValue, VariableType,
+};
 use pest::iterators::Pair;
 use pest::Parser;
 use pest_derive::Parser;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Parser)]
 #[grammar = "database/query_language/data_model.pest"]
@@ -35,6 +37,9 @@ lazy_static::lazy_static! {
                 deprecated: false,
                 mutable: true,
                 is_system: true,
+                json_schema: None,
+                default_field: None,
+                collation: Collation::Binary,
             },
         );
 
@@ -49,6 +54,9 @@ lazy_static::lazy_static! {
                 deprecated: false,
                 mutable: true,
                 is_system: true,
+                json_schema: None,
+                default_field: None,
+                collation: Collation::Binary,
             },
         );
 
@@ -63,6 +71,9 @@ lazy_static::lazy_static! {
                 deprecated: false,
                 mutable: false,
                 is_system: true,
+                json_schema: None,
+                default_field: None,
+                collation: Collation::Binary,
             },
         );
 
@@ -77,6 +88,9 @@ lazy_static::lazy_static! {
                 deprecated: false,
                 mutable: false,
                 is_system: true,
+                json_schema: None,
+                default_field: None,
+                collation: Collation::Binary,
             },
         );
 
@@ -91,6 +105,9 @@ lazy_static::lazy_static! {
                 deprecated: false,
                 mutable: false,
                 is_system: true,
+                json_schema: None,
+                default_field: None,
+                collation: Collation::Binary,
             },
         );
 
@@ -105,6 +122,9 @@ lazy_static::lazy_static! {
                 deprecated: false,
                 mutable: false,
                 is_system: true,
+                json_schema: None,
+                default_field: None,
+                collation: Collation::Binary,
             },
         );
 
@@ -119,6 +139,9 @@ lazy_static::lazy_static! {
                 deprecated: false,
                 mutable: false,
                 is_system: true,
+                json_schema: None,
+                default_field: None,
+                collation: Collation::Binary,
             },
         );
 
@@ -133,6 +156,9 @@ lazy_static::lazy_static! {
                 deprecated: false,
                 mutable: true,
                 is_system: true,
+                json_schema: None,
+                default_field: None,
+                collation: Collation::Binary,
             },
         );
 
@@ -147,6 +173,9 @@ lazy_static::lazy_static! {
                 deprecated: false,
                 mutable: false,
                 is_system: true,
+                json_schema: None,
+                default_field: None,
+                collation: Collation::Binary,
             },
         );
 
@@ -161,6 +190,9 @@ lazy_static::lazy_static! {
                 deprecated: false,
                 mutable: false,
                 is_system: true,
+                json_schema: None,
+                default_field: None,
+                collation: Collation::Binary,
             },
         );
 
@@ -175,10 +207,58 @@ lazy_static::lazy_static! {
                 deprecated: false,
                 mutable: false,
                 is_system: true,
+                json_schema: None,
+                default_field: None,
+                collation: Collation::Binary,
             },
         );
 
+        //
+        // stored in the _json column like a regular field, but available on every entity
+        //
+        fields.insert(
+            READERS_FIELD.to_string(),
+            Field {
+                name: READERS_FIELD.to_string(),
+                short_name: READERS_FIELD.to_string(),
+                field_type: FieldType::Json,
+                default_value: None,
+                nullable: true,
+                deprecated: false,
+                mutable: true,
+                is_system: false,
+                json_schema: Some(
+                    JsonSchema::parse(
+                        READERS_FIELD,
+                        r#"{"type":"array","items":{"type":"string"}}"#,
+                    )
+                    .unwrap(),
+                ),
+                default_field: None,
+                collation: Collation::Binary,
+            },
+        );
 
+        //
+        // deletion date of the soft deleted nodes, stored in the _json column
+        // set to null to restore a node
+        //
+        fields.insert(
+            DELETED_FIELD.to_string(),
+            Field {
+                name: DELETED_FIELD.to_string(),
+                short_name: DELETED_FIELD.to_string(),
+                field_type: FieldType::Integer,
+                default_value: None,
+                nullable: true,
+                deprecated: false,
+                mutable: true,
+                is_system: false,
+                json_schema: None,
+                default_field: None,
+                collation: Collation::Binary,
+            },
+        );
 
         fields
 
@@ -190,12 +270,30 @@ lazy_static::lazy_static! {
 /// applies to entity and field
 pub const RESERVED_SHORT_NAMES: usize = 32;
 
+///
+/// Identifies the data model template acquired from a *Room*
+///
+/// Once a template is applied, only the updates of the same template signed by the same peer are accepted.
+///
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TemplateSource {
+    pub template_id: String,
+    pub signer: String,
+    pub date: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DataModel {
     model: String,
     namespace_ids: HashMap<String, usize>,
     namespaces: HashMap<String, HashMap<String, Entity>>,
     entities_short: HashMap<String, (String, String)>,
+    #[serde(default)]
+    deprecated_namespaces: HashSet<String>,
+    #[serde(default)]
+    proposal_date: i64,
+    #[serde(default)]
+    template: Option<TemplateSource>,
 }
 impl Default for DataModel {
     fn default() -> Self {
@@ -209,6 +307,9 @@ impl DataModel {
             namespace_ids: HashMap::new(),
             namespaces: HashMap::new(),
             entities_short: HashMap::new(),
+            deprecated_namespaces: HashSet::new(),
+            proposal_date: 0,
+            template: None,
         }
     }
 
@@ -236,6 +337,30 @@ impl DataModel {
         Ok(entity)
     }
 
+    ///
+    /// returns the entity if its namespace is not deprecated
+    ///
+    /// entities of a deprecated namespace can still be queried and synchronized, but cannot be mutated or deleted
+    ///
+    pub fn get_mutable_entity(&self, name: &str) -> Result<&Entity, Error> {
+        let entity = self.get_entity(name)?;
+        let split: Vec<&str> = name.split('.').collect();
+        let namespace = if split.len() == 2 {
+            split[0].to_lowercase()
+        } else {
+            "".to_string()
+        };
+        if self.deprecated_namespaces.contains(&namespace) {
+            return Err(Error::DeprecatedNamespace(namespace));
+        }
+        Ok(entity)
+    }
+
+    pub fn is_namespace_deprecated(&self, namespace: &str) -> bool {
+        self.deprecated_namespaces
+            .contains(&namespace.to_lowercase())
+    }
+
     fn insert(&mut self, name_space: &str, mut entity: Entity, decal: usize) -> Result<(), Error> {
         let namespace_short = match self.namespaces.contains_key(name_space) {
             true => *self.namespace_ids.get(name_space).unwrap(),
@@ -295,6 +420,28 @@ impl DataModel {
         Ok(())
     }
 
+    ///
+    /// modification date of the last applied data model proposal
+    ///
+    pub fn proposal_date(&self) -> i64 {
+        self.proposal_date
+    }
+
+    pub fn set_proposal_date(&mut self, date: i64) {
+        self.proposal_date = date;
+    }
+
+    ///
+    /// the data model template applied to this data model, if any
+    ///
+    pub fn template(&self) -> Option<&TemplateSource> {
+        self.template.as_ref()
+    }
+
+    pub fn set_template(&mut self, template: TemplateSource) {
+        self.template = Some(template);
+    }
+
     pub fn update(&mut self, model: &str) -> Result<(), Error> {
         let new_data_model = Self::parse_internal(model, 1)?; //decal namespace id by one to reserce the first id to the sys namespace
         self.update_with(new_data_model, false)?;
@@ -374,6 +521,10 @@ impl DataModel {
             }
             self.namespaces.insert(ns.0, ns.1);
         }
+        if !system {
+            //namespaces can be deprecated and 'undeprecated'
+            self.deprecated_namespaces = new_data_model.deprecated_namespaces;
+        }
         self.model = new_data_model.model;
         Ok(())
     }
@@ -382,6 +533,46 @@ impl DataModel {
         self.entities_short.get(short_name).map(|v| v.1.to_string())
     }
 
+    ///
+    /// the (short name, name) of the application entities that are indexed for full text search
+    ///
+    pub fn full_text_entities(&self) -> Vec<(String, String)> {
+        let mut entities = Vec::new();
+        for (namespace, namespace_entities) in &self.namespaces {
+            if namespace.eq(SYSTEM_NAMESPACE) {
+                continue;
+            }
+            for (name, entity) in namespace_entities {
+                if entity.enable_full_text {
+                    entities.push((entity.short_name.clone(), name.clone()));
+                }
+            }
+        }
+        entities.sort();
+        entities
+    }
+
+    ///
+    /// the (entity short name, field short name) of the File fields of the application entities
+    ///
+    pub fn file_fields(&self) -> Vec<(String, String)> {
+        let mut fields = Vec::new();
+        for (namespace, namespace_entities) in &self.namespaces {
+            if namespace.eq(SYSTEM_NAMESPACE) {
+                continue;
+            }
+            for entity in namespace_entities.values() {
+                for field in entity.fields.values() {
+                    if field.field_type == FieldType::File {
+                        fields.push((entity.short_name.clone(), field.short_name.clone()));
+                    }
+                }
+            }
+        }
+        fields.sort();
+        fields
+    }
+
     fn parse_internal(model: &str, decal: usize) -> Result<DataModel, Error> {
         let mut data_model = DataModel::new();
         data_model.model = String::from(model);
@@ -400,8 +591,10 @@ impl DataModel {
                 for pair in parse.into_inner() {
                     let namespace_pairs = pair.into_inner();
                     let mut name_space = String::from("");
+                    let mut deprecated = false;
                     for pair in namespace_pairs.into_iter() {
                         match pair.as_rule() {
+                            Rule::deprecated => deprecated = true,
                             Rule::identifier => {
                                 name_space = pair.as_str().to_lowercase();
                             }
@@ -429,6 +622,15 @@ impl DataModel {
                             _ => unreachable!(),
                         }
                     }
+                    if deprecated {
+                        if SYSTEM_NAMESPACE.eq(&name_space) {
+                            return Err(Error::NamespaceUpdate(format!(
+                                "{} namespace cannot be deprecated",
+                                SYSTEM_NAMESPACE
+                            )));
+                        }
+                        data_model.deprecated_namespaces.insert(name_space);
+                    }
                 }
             }
             _ => unreachable!(),
@@ -467,6 +669,19 @@ impl DataModel {
                                     _ => unreachable!(),
                                 }
                             }
+                            Rule::soft_delete => entity.soft_delete = true,
+                            Rule::edit_chain => {
+                                let field = pair.into_inner().next().unwrap();
+                                entity.edit_chain = Some(field.as_str().to_string());
+                            }
+                            Rule::id_generation => {
+                                let generation = pair.into_inner().next().unwrap();
+                                entity.id_generation = match generation.as_rule() {
+                                    Rule::time_ordered_id => IdGeneration::TimeOrdered,
+                                    Rule::random_id => IdGeneration::Random,
+                                    _ => unreachable!(),
+                                };
+                            }
                             Rule::comma => {}
                             _ => unreachable!(),
                         }
@@ -494,6 +709,8 @@ impl DataModel {
         }
 
         //     entity.check_consistency()?;
+        entity.check_default_fields()?;
+        entity.check_edit_chain()?;
         Ok((entity, parsed_index))
     }
 
@@ -511,9 +728,18 @@ impl DataModel {
     }
 
     fn is_reserved(value: &str) -> bool {
+        Self::is_reserved_field(value)
+            || matches!(
+                value.to_lowercase().as_str(),
+                "datetime" | "date" | "geo" | "file"
+            )
+    }
+
+    //the types added after the first release remain valid field names, data models with a 'date' field already exist
+    fn is_reserved_field(value: &str) -> bool {
         matches!(
             value.to_lowercase().as_str(),
-            "boolean" | "float" | "integer" | "string" | "base64" | "json"
+            "boolean" | "float" | "integer" | "string" | "base64" | "json" | "enum"
         )
     }
 
@@ -531,7 +757,7 @@ impl DataModel {
                     if name.starts_with('_') {
                         return Err(Error::InvalidName(name.to_string()));
                     }
-                    if Self::is_reserved(name) {
+                    if Self::is_reserved_field(name) {
                         return Err(Error::ReservedKeyword(name.to_string()));
                     }
                     field.name = name.to_string();
@@ -554,12 +780,31 @@ impl DataModel {
                     "string" => field.field_type = FieldType::String,
                     "base64" => field.field_type = FieldType::Base64,
                     "json" => field.field_type = FieldType::Json,
+                    "datetime" => field.field_type = FieldType::DateTime,
+                    "date" => field.field_type = FieldType::Date,
+                    "geo" => field.field_type = FieldType::Geo,
+                    "file" => field.field_type = FieldType::File,
                     _ => unreachable!(),
                 }
 
-                if let Some(pair) = scalar_field.next() {
+                for pair in scalar_field {
                     match pair.as_rule() {
                         Rule::nullable => field.nullable = true,
+                        Rule::collate => {
+                            if !matches!(field.field_type, FieldType::String) {
+                                return Err(Error::InvalidCollation(
+                                    field.name.clone(),
+                                    field.field_type.to_string(),
+                                ));
+                            }
+                            let collation = pair.into_inner().next().unwrap();
+                            field.collation = match collation.as_str().to_lowercase().as_str() {
+                                "binary" => Collation::Binary,
+                                "nocase" => Collation::NoCase,
+                                "rtrim" => Collation::RTrim,
+                                _ => unreachable!(),
+                            };
+                        }
                         Rule::default => {
                             let value_pair = pair
                                 .into_inner()
@@ -609,9 +854,12 @@ impl DataModel {
                                             field.default_value =
                                                 Some(ParamValue::Float(value.parse()?))
                                         }
-                                        FieldType::Integer => {
-                                            field.default_value =
-                                                Some(ParamValue::Integer(value.parse()?))
+                                        FieldType::Integer
+                                        | FieldType::DateTime
+                                        | FieldType::Date => {
+                                            field.default_value = Some(ParamValue::Integer(
+                                                field.field_type.date_number(value.parse()?),
+                                            ))
                                         }
                                         _ => {
                                             return Err(Error::InvalidDefaultValue(
@@ -649,6 +897,11 @@ impl DataModel {
                                             field.default_value =
                                                 Some(ParamValue::String(value.to_string()))
                                         }
+                                        FieldType::DateTime | FieldType::Date => {
+                                            field.default_value = Some(ParamValue::Integer(
+                                                field.field_type.parse_date(&value)?,
+                                            ))
+                                        }
                                         _ => {
                                             return Err(Error::InvalidDefaultValue(
                                                 field.name.clone(),
@@ -659,6 +912,10 @@ impl DataModel {
                                     }
                                 }
 
+                                Rule::default_field => {
+                                    field.default_field = Some(value_pair.as_str().to_string());
+                                }
+
                                 _ => unreachable!(),
                             }
                         }
@@ -667,6 +924,107 @@ impl DataModel {
                     }
                 }
             }
+            Rule::enum_field => {
+                let mut enum_field = field_type.into_inner();
+                let mut values: Vec<String> = Vec::new();
+                for value_pair in enum_field.next().unwrap().into_inner() {
+                    match value_pair.as_rule() {
+                        Rule::string => {
+                            let pair = value_pair.into_inner().next().unwrap();
+                            let value = pair.as_str().replace("\\\"", "\"");
+                            if values.contains(&value) {
+                                return Err(Error::DuplicatedEnumValue(field.name.clone(), value));
+                            }
+                            values.push(value);
+                        }
+                        Rule::comma => {}
+                        _ => unreachable!(),
+                    }
+                }
+                field.field_type = FieldType::Enum(values);
+
+                if let Some(pair) = enum_field.next() {
+                    match pair.as_rule() {
+                        Rule::nullable => field.nullable = true,
+                        Rule::default => {
+                            let value_pair = pair
+                                .into_inner()
+                                .next()
+                                .unwrap()
+                                .into_inner()
+                                .next()
+                                .unwrap();
+                            match value_pair.as_rule() {
+                                Rule::string => {
+                                    let pair = value_pair.into_inner().next().unwrap();
+                                    let value = pair.as_str().replace("\\\"", "\"");
+                                    if field.field_type.enum_position(&value).is_none() {
+                                        return Err(Error::InvalidEnumValue(
+                                            value,
+                                            field.name.clone(),
+                                        ));
+                                    }
+                                    field.default_value = Some(ParamValue::String(value))
+                                }
+                                _ => {
+                                    return Err(Error::InvalidDefaultValue(
+                                        field.name.clone(),
+                                        value_pair.as_str().to_string(),
+                                        field.field_type.to_string(),
+                                    ))
+                                }
+                            }
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            Rule::json_field => {
+                let mut json_field = field_type.into_inner();
+                let schema_pair = json_field.next().unwrap().into_inner().next().unwrap();
+                let schema = schema_pair
+                    .into_inner()
+                    .next()
+                    .unwrap()
+                    .as_str()
+                    .replace("\\\"", "\"");
+                let schema = JsonSchema::parse(&field.name, &schema)?;
+                field.field_type = FieldType::Json;
+
+                if let Some(pair) = json_field.next() {
+                    match pair.as_rule() {
+                        Rule::nullable => field.nullable = true,
+                        Rule::default => {
+                            let value_pair = pair
+                                .into_inner()
+                                .next()
+                                .unwrap()
+                                .into_inner()
+                                .next()
+                                .unwrap();
+                            match value_pair.as_rule() {
+                                Rule::string => {
+                                    let pair = value_pair.into_inner().next().unwrap();
+                                    let value = pair.as_str().replace("\\\"", "\"");
+                                    let json: serde_json::Value = serde_json::from_str(&value)
+                                        .map_err(|_| Error::InvalidJson(value.to_string()))?;
+                                    schema.validate(&field.name, &json)?;
+                                    field.default_value = Some(ParamValue::String(value))
+                                }
+                                _ => {
+                                    return Err(Error::InvalidDefaultValue(
+                                        field.name.clone(),
+                                        value_pair.as_str().to_string(),
+                                        field.field_type.to_string(),
+                                    ))
+                                }
+                            }
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+                field.json_schema = Some(schema);
+            }
             Rule::entity_field => {
                 let mut entity_field = field_type.into_inner();
 
@@ -701,21 +1059,17 @@ impl DataModel {
                 for field_entry in &entity.fields {
                     let field = field_entry.1;
                     match &field.field_type {
-                        FieldType::Array(e) => {
-                            if self.get_entity(e).is_err() {
-                                return Err(Error::Parser(format!(
+                        FieldType::Array(e) if self.get_entity(e).is_err() => {
+                            return Err(Error::Parser(format!(
                                     "entity {} does not exist. Please check the definition of the {}.{}:[{}] field",
                                     e, entity.name, field.name, e
                                 )));
-                            }
                         }
-                        FieldType::Entity(e) => {
-                            if self.get_entity(e).is_err() {
-                                return Err(Error::Parser(format!(
+                        FieldType::Entity(e) if self.get_entity(e).is_err() => {
+                            return Err(Error::Parser(format!(
                                     "entity {} does not exist. Please check the definition of the {}.{}:{} field",
                                     e, entity.name, field.name, e
                                 )));
-                            }
                         }
                         _ => {}
                     }
@@ -804,10 +1158,34 @@ pub fn validate_json_for_entity(
                             }
                         };
                     }
-                    FieldType::Integer => {
+                    FieldType::File => {
+                        match json.get(short_name) {
+                            Some(value) => match value.as_str() {
+                                Some(str) => validate_file_id(str, name)?,
+                                None => {
+                                    return Err(crate::database::Error::InvalidJsonFieldValue(
+                                        name.to_string(),
+                                        "File".to_string(),
+                                    ))
+                                }
+                            },
+                            None => {
+                                if !field.nullable {
+                                    return Err(crate::database::Error::MissingJsonField(
+                                        name.to_string(),
+                                    ));
+                                };
+                            }
+                        };
+                    }
+                    FieldType::Integer | FieldType::DateTime | FieldType::Date => {
                         match json.get(short_name) {
                             Some(value) => {
-                                if value.as_i64().is_none() {
+                                //Date values are stored at the start of the UTC day
+                                let valid = value
+                                    .as_i64()
+                                    .is_some_and(|v| field.field_type.date_number(v) == v);
+                                if !valid {
                                     return Err(crate::database::Error::InvalidJsonFieldValue(
                                         name.to_string(),
                                         "Integer".to_string(),
@@ -851,6 +1229,53 @@ pub fn validate_json_for_entity(
                                         "Json".to_string(),
                                     ));
                                 }
+                                if let Some(schema) = &field.json_schema {
+                                    schema.validate(name, value)?;
+                                }
+                            }
+                            None => {
+                                if !field.nullable && field.default_value.is_none() {
+                                    return Err(crate::database::Error::MissingJsonField(
+                                        name.to_string(),
+                                    ));
+                                }
+                            }
+                        };
+                    }
+                    FieldType::Geo => {
+                        match json.get(short_name) {
+                            Some(value) => {
+                                let valid = value.get("lat").and_then(|v| v.as_f64()).is_some()
+                                    && value.get("lon").and_then(|v| v.as_f64()).is_some();
+                                if !valid {
+                                    return Err(crate::database::Error::InvalidJsonFieldValue(
+                                        name.to_string(),
+                                        "Geo".to_string(),
+                                    ));
+                                }
+                            }
+                            None => {
+                                if !field.nullable && field.default_value.is_none() {
+                                    return Err(crate::database::Error::MissingJsonField(
+                                        name.to_string(),
+                                    ));
+                                }
+                            }
+                        };
+                    }
+                    FieldType::Enum(_) => {
+                        match json.get(short_name) {
+                            Some(value) => {
+                                let valid = value
+                                    .as_i64()
+                                    .and_then(|pos| field.field_type.enum_value(pos))
+                                    .is_some();
+                                if !valid {
+                                    return Err(crate::database::Error::InvalidJsonFieldValue(
+                                        name.to_string(),
+                                        field.field_type.to_string(),
+                                    ));
+                                }
                             }
                             None => {
                                 if !field.nullable && field.default_value.is_none() {
@@ -896,7 +1321,20 @@ impl Index {
             | FieldType::Float
             | FieldType::Base64
             | FieldType::Integer
-            | FieldType::String => {}
+            | FieldType::String
+            | FieldType::Enum(_)
+            | FieldType::DateTime
+            | FieldType::Date
+            | FieldType::Geo
+            | FieldType::File => {}
+        }
+
+        //Geo fields are indexed in a R*Tree that cannot contain other fields
+        if self.is_geo() || (field.field_type == FieldType::Geo && !self.fields.is_empty()) {
+            return Err(Error::InvalidQuery(format!(
+                "Geo field '{}' must be alone in its index",
+                &field.name
+            )));
         }
 
         if self.fields.iter().any(|f| f.name.eq(&field.name)) {
@@ -916,11 +1354,22 @@ impl Index {
         for i in &self.fields {
             name.push('$');
             name.push_str(&i.name);
+            name.push_str(i.collation.suffix());
         }
         name
     }
 
+    ///
+    /// a Geo index is a R*Tree table maintained by triggers on the _node table
+    ///
+    pub fn is_geo(&self) -> bool {
+        self.fields.len() == 1 && self.fields[0].field_type == FieldType::Geo
+    }
+
     pub fn create_query(&self) -> String {
+        if self.is_geo() {
+            return self.create_geo_query();
+        }
         let mut q = String::new();
         q.push_str(&format!("CREATE INDEX {} ON _node (", self.name()));
         let it = &mut self.fields.iter().peekable();
@@ -928,7 +1377,11 @@ impl Index {
             if field.is_system {
                 q.push_str(&field.name);
             } else {
-                q.push_str(&format!("_json->>'$.{}'", &field.name));
+                q.push_str(&format!(
+                    "_json->>'$.{}'{}",
+                    &field.name,
+                    field.collation.sql()
+                ));
             }
             if it.peek().is_some() {
                 q.push(',');
@@ -938,6 +1391,110 @@ impl Index {
         q.push_str(&format!(" WHERE _entity='{}' ", self.entity_short));
         q
     }
+
+    pub fn drop_query(&self) -> String {
+        let name = self.name();
+        if self.is_geo() {
+            format!(
+                "DROP TRIGGER IF EXISTS {0}$insert;
+                DROP TRIGGER IF EXISTS {0}$update;
+                DROP TRIGGER IF EXISTS {0}$delete;
+                DROP TABLE IF EXISTS {0};",
+                name
+            )
+        } else {
+            format!("DROP INDEX {}", name)
+        }
+    }
+
+    //
+    // the locations are stored as points: min and max coordinates are equals
+    // the table is filled with the existing nodes when created
+    //
+    fn create_geo_query(&self) -> String {
+        let field = &self.fields[0].short_name;
+        let lat = format!("_json->>'$.{}.lat'", field);
+        let lon = format!("_json->>'$.{}.lon'", field);
+        format!(
+            "CREATE VIRTUAL TABLE {0} USING rtree(id, min_lat, max_lat, min_lon, max_lon);
+            CREATE TRIGGER {0}$insert AFTER INSERT ON _node WHEN new._entity='{1}' AND new.{2} IS NOT NULL BEGIN
+                INSERT OR REPLACE INTO {0} VALUES (new.rowid, new.{2}, new.{2}, new.{3}, new.{3});
+            END;
+            CREATE TRIGGER {0}$update AFTER UPDATE ON _node WHEN old._entity='{1}' BEGIN
+                DELETE FROM {0} WHERE id=old.rowid;
+                INSERT INTO {0} SELECT new.rowid, new.{2}, new.{2}, new.{3}, new.{3} WHERE new.{2} IS NOT NULL;
+            END;
+            CREATE TRIGGER {0}$delete AFTER DELETE ON _node WHEN old._entity='{1}' BEGIN
+                DELETE FROM {0} WHERE id=old.rowid;
+            END;
+            INSERT INTO {0} SELECT rowid, {2}, {2}, {3}, {3} FROM _node WHERE _entity='{1}' AND {2} IS NOT NULL;",
+            self.name(),
+            self.entity_short,
+            lat,
+            lon
+        )
+    }
+}
+
+///
+/// How the identifiers of the new nodes of an entity are generated
+///
+/// - Default: 48 bits timestamp in milliseconds followed by random bits
+/// - TimeOrdered ('time_ordered_id'): UUIDv7 layout, strictly increasing ids, suited for high volume append only entities
+/// - Random ('random_id'): fully random ids that do not disclose the creation date
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum IdGeneration {
+    #[default]
+    Default,
+    TimeOrdered,
+    Random,
+}
+impl IdGeneration {
+    pub fn new_uid(&self) -> Uid {
+        match self {
+            Self::Default => new_uid(),
+            Self::TimeOrdered => new_time_ordered_uid(),
+            Self::Random => new_random_uid(),
+        }
+    }
+}
+
+///
+/// Collation used to compare and sort the values of a String field ('name: String collate nocase')
+///
+/// - Binary: default, compares the bytes of the strings
+/// - NoCase ('nocase'): ignores the case of the ASCII characters
+/// - RTrim ('rtrim'): ignores the trailing spaces
+///
+/// The collation applies to the filters, the order_by and paging parameters, the group by of aggregate queries, and to the indexes containing the field.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Collation {
+    #[default]
+    Binary,
+    NoCase,
+    RTrim,
+}
+impl Collation {
+    ///
+    /// the COLLATE clause appended to the field expressions
+    ///
+    pub fn sql(&self) -> &'static str {
+        match self {
+            Self::Binary => "",
+            Self::NoCase => " COLLATE NOCASE",
+            Self::RTrim => " COLLATE RTRIM",
+        }
+    }
+
+    fn suffix(&self) -> &'static str {
+        match self {
+            Self::Binary => "",
+            Self::NoCase => "$nocase",
+            Self::RTrim => "$rtrim",
+        }
+    }
 }
 
 ///
@@ -949,9 +1506,14 @@ impl Index {
 /// - fields cannot be removed
 /// - existing fields can be deprecated and 'undeprecated'
 /// - exiting field types cannot be changed
+/// - new values can be appended at the end of an Enum field value list
 /// - existing fields can be changed from not nullable to nullable
 /// - existing fields can be changed from nullable to not nullable only if a default value is provided
 /// - new fields must provide a default value if not nullable
+/// - the collation of String fields can be changed
+///
+/// A field filled from a sibling field ('display_name: String default name') is only filled when a node is created,
+/// it cannot be added to an existing entity because existing nodes would not have a value.
 ///
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Entity {
@@ -962,6 +1524,14 @@ pub struct Entity {
     pub indexes_to_remove: HashMap<String, Index>,
     pub deprecated: bool,
     pub enable_full_text: bool,
+    #[serde(default)]
+    pub id_generation: IdGeneration,
+    //name of the field referencing the original node of an edited version
+    #[serde(default)]
+    pub edit_chain: Option<String>,
+    //deletions only set the deletion date of the nodes
+    #[serde(default)]
+    pub soft_delete: bool,
 }
 impl Default for Entity {
     fn default() -> Self {
@@ -978,14 +1548,78 @@ impl Entity {
             indexes_to_remove: HashMap::new(),
             deprecated: false,
             enable_full_text: true,
+            id_generation: IdGeneration::default(),
+            edit_chain: None,
+            soft_delete: false,
+        }
+    }
+
+    ///
+    /// the edit chain field references the original node: it must be a nullable Base64 field
+    ///
+    pub fn check_edit_chain(&self) -> Result<(), Error> {
+        if let Some(name) = &self.edit_chain {
+            match self.fields.get(name) {
+                Some(field) if field.field_type == FieldType::Base64 && field.nullable => Ok(()),
+                _ => Err(Error::InvalidEditChain(name.clone(), self.name.clone())),
+            }
+        } else {
+            Ok(())
         }
     }
 
+    ///
+    /// a field can be filled at insert time with the value of a sibling field of the same type.
+    /// The sibling must be a not nullable scalar field, that is not itself filled from another field
+    ///
+    pub fn check_default_fields(&self) -> Result<(), Error> {
+        for field in self.fields.values() {
+            if let Some(source_name) = &field.default_field {
+                let source = match self.fields.get(source_name) {
+                    Some(source) => source,
+                    None => {
+                        return Err(Error::InvalidDefaultField(
+                            field.name.clone(),
+                            format!("field '{}' does not exist", source_name),
+                        ))
+                    }
+                };
+                if source.name.eq(&field.name) || source.default_field.is_some() {
+                    return Err(Error::InvalidDefaultField(
+                        field.name.clone(),
+                        format!("field '{}' is filled from another field", source_name),
+                    ));
+                }
+                if source.nullable {
+                    return Err(Error::InvalidDefaultField(
+                        field.name.clone(),
+                        format!("field '{}' is nullable", source_name),
+                    ));
+                }
+                if !source.field_type.eq(&field.field_type) {
+                    return Err(Error::InvalidDefaultField(
+                        field.name.clone(),
+                        format!(
+                            "field '{}' is a '{}' not a '{}'",
+                            source_name, source.field_type, field.field_type
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
     ///
     /// update an existing entity
     ///
     pub fn update(&mut self, mut new_entity: Entity) -> Result<(), Error> {
         self.deprecated = new_entity.deprecated;
+        //only impacts the nodes created from now on
+        self.id_generation = new_entity.id_generation;
+        self.edit_chain = new_entity.edit_chain.take();
+        //soft deleted nodes are visible again when the option is removed
+        self.soft_delete = new_entity.soft_delete;
         for field in &mut self.fields {
             let new_field_opt = new_entity.fields.remove(field.0);
             match new_field_opt {
@@ -1004,7 +1638,9 @@ impl Entity {
                             previous_pos,
                         ));
                     }
-                    if !field.field_type.eq(&new_field.field_type) {
+                    if !field.field_type.eq(&new_field.field_type)
+                        && !Self::is_enum_extension(&field.field_type, &new_field.field_type)
+                    {
                         return Err(Error::CannotUpdateFieldType(
                             String::from(&self.name),
                             String::from(&field.name),
@@ -1023,8 +1659,11 @@ impl Entity {
                             }
                         }
                     }
+                    field.field_type = new_field.field_type;
                     field.nullable = new_field.nullable;
                     field.default_value = new_field.default_value;
+                    field.default_field = new_field.default_field;
+                    field.collation = new_field.collation;
                     field.deprecated = new_field.deprecated;
                 }
                 None => {
@@ -1062,6 +1701,19 @@ impl Entity {
         Ok(())
     }
 
+    //
+    // Enum values are stored using their position,
+    // new values can only be added at the end of the list
+    //
+    fn is_enum_extension(old_type: &FieldType, new_type: &FieldType) -> bool {
+        match (old_type, new_type) {
+            (FieldType::Enum(old_values), FieldType::Enum(new_values)) => {
+                new_values.starts_with(old_values)
+            }
+            _ => false,
+        }
+    }
+
     pub fn add_field(&mut self, field: Field) -> Result<(), Error> {
         if self.fields.contains_key(&field.name) {
             return Err(Error::DuplicatedField(field.name.clone()));
@@ -1108,6 +1760,13 @@ pub struct Field {
     pub deprecated: bool,
     pub mutable: bool,
     pub is_system: bool,
+    #[serde(default)]
+    pub json_schema: Option<JsonSchema>,
+    //name of a sibling field whose value is copied when the field is not provided at insert time
+    #[serde(default)]
+    pub default_field: Option<String>,
+    #[serde(default)]
+    pub collation: Collation,
 }
 impl Default for Field {
     fn default() -> Self {
@@ -1125,6 +1784,9 @@ impl Field {
             deprecated: false,
             mutable: true,
             is_system: false,
+            json_schema: None,
+            default_field: None,
+            collation: Collation::Binary,
         }
     }
 
@@ -1138,10 +1800,16 @@ impl Field {
                     VariableType::Base64(self.nullable)
                 }
             }
+            FieldType::File => VariableType::Base64(self.nullable),
             FieldType::Boolean => VariableType::Boolean(self.nullable),
             FieldType::Integer => VariableType::Integer(self.nullable),
+            FieldType::DateTime => VariableType::DateTime(self.nullable),
+            FieldType::Date => VariableType::Date(self.nullable),
+            FieldType::Geo => VariableType::Geo(self.nullable),
             FieldType::Float => VariableType::Float(self.nullable),
-            FieldType::String | FieldType::Json => VariableType::String(self.nullable),
+            FieldType::String | FieldType::Json | FieldType::Enum(_) => {
+                VariableType::String(self.nullable)
+            }
         }
     }
 
@@ -1155,10 +1823,14 @@ impl Field {
                     VariableType::Base64(false)
                 }
             }
+            FieldType::File => VariableType::Base64(false),
             FieldType::Boolean => VariableType::Boolean(false),
             FieldType::Integer => VariableType::Integer(false),
+            FieldType::DateTime => VariableType::DateTime(false),
+            FieldType::Date => VariableType::Date(false),
+            FieldType::Geo => VariableType::Geo(false),
             FieldType::Float => VariableType::Float(false),
-            FieldType::String | FieldType::Json => VariableType::String(false),
+            FieldType::String | FieldType::Json | FieldType::Enum(_) => VariableType::String(false),
         }
     }
 }
@@ -45,25 +45,25 @@ mod tests {
 
         let age = pet.fields.get("age").unwrap();
         assert_eq!(FieldType::Float.type_id(), age.field_type.type_id());
-        assert_eq!(true, age.nullable);
+        assert!(age.nullable);
 
         let name = pet.fields.get("name").unwrap();
         assert_eq!(FieldType::String.type_id(), name.field_type.type_id());
-        assert_eq!(false, name.nullable);
+        assert!(!name.nullable);
         if let Some(ParamValue::String(e)) = &name.default_value {
             assert_eq!("John", e);
         }
 
         let surname = pet.fields.get("surname").unwrap();
         assert_eq!(FieldType::String.type_id(), surname.field_type.type_id());
-        assert_eq!(true, surname.nullable);
+        assert!(surname.nullable);
 
         let owner = pet.fields.get("owners").unwrap();
         match &owner.field_type {
             FieldType::Array(e) => assert_eq!("ns.Person", e),
             _ => unreachable!(),
         }
-        assert_eq!(false, owner.nullable);
+        assert!(!owner.nullable);
 
         let index = &pet.indexes;
         assert_eq!(1, index.len());
@@ -73,45 +73,45 @@ mod tests {
 
         let weight = pet.fields.get("weight").unwrap();
         assert_eq!(FieldType::Integer.type_id(), weight.field_type.type_id());
-        assert_eq!(true, weight.nullable);
+        assert!(weight.nullable);
 
         let is_vaccinated = pet.fields.get("is_vaccinated").unwrap();
         assert_eq!(
             FieldType::Boolean.type_id(),
             is_vaccinated.field_type.type_id()
         );
-        assert_eq!(true, is_vaccinated.nullable);
+        assert!(is_vaccinated.nullable);
 
         let person = datamodel.get_entity("NS.Person").unwrap();
         assert_eq!("ns.Person", person.name);
         let name = person.fields.get("name").unwrap();
         assert_eq!(FieldType::String.type_id(), name.field_type.type_id());
-        assert_eq!(false, name.nullable);
+        assert!(!name.nullable);
 
         let surname = person.fields.get("surname").unwrap();
         assert_eq!(FieldType::String.type_id(), surname.field_type.type_id());
-        assert_eq!(true, surname.nullable);
+        assert!(surname.nullable);
 
         let child = person.fields.get("child").unwrap();
         match &child.field_type {
             FieldType::Array(e) => assert_eq!("Ns.Person", e),
             _ => unreachable!(),
         }
-        assert_eq!(false, child.nullable);
+        assert!(!child.nullable);
 
         let mother = person.fields.get("mother").unwrap();
         match &mother.field_type {
             FieldType::Entity(e) => assert_eq!("ns.Person", e),
             _ => unreachable!(),
         }
-        assert_eq!(false, mother.nullable);
+        assert!(!mother.nullable);
 
         let father = person.fields.get("father").unwrap();
         match &father.field_type {
             FieldType::Entity(e) => assert_eq!("ns.Person", e),
             _ => unreachable!(),
         }
-        assert_eq!(false, father.nullable);
+        assert!(!father.nullable);
     }
 
     #[test]
@@ -356,6 +356,101 @@ mod tests {
             }",
             )
             .expect_err("scalar field names are reserved");
+
+        for keyword in ["DateTime", "Date", "Geo", "File"] {
+            let mut datamodel = DataModel::new();
+            datamodel
+                .update(&format!(
+                    "
+                {{
+                    {keyword} {{
+                        name : String,
+                    }}
+                }}"
+                ))
+                .expect_err("scalar type names are reserved");
+        }
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                "
+            {
+                Person {
+                    date : Date,
+                    file : File nullable,
+                }
+            }",
+            )
+            .expect("fields can be named like the recent types");
+    }
+
+    #[test]
+    fn entity_names_starting_with_keywords() {
+        let keywords = [
+            "Integer", "Float", "Boolean", "String", "Base64", "Json", "DateTime", "Date", "Geo",
+            "File",
+        ];
+        for keyword in keywords {
+            let mut datamodel = DataModel::new();
+            datamodel
+                .update(&format!(
+                    "
+                {{
+                    {keyword}s {{
+                        name : String,
+                    }}
+                    {keyword}_log {{
+                        name : String,
+                    }}
+                    Person {{
+                        single : {keyword}s,
+                        nullable_single : {keyword}_log nullable,
+                        many : [{keyword}s],
+                        value : {keyword} nullable,
+                    }}
+                }}"
+                ))
+                .expect("entity names can start with a scalar type");
+
+            let person = datamodel.get_entity("Person").unwrap();
+            let single = person.get_field("single").unwrap();
+            assert_eq!(single.field_type, FieldType::Entity(format!("{keyword}s")));
+            let nullable = person.get_field("nullable_single").unwrap();
+            assert_eq!(
+                nullable.field_type,
+                FieldType::Entity(format!("{keyword}_log"))
+            );
+            assert!(nullable.nullable);
+        }
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                "
+            {
+                Event {
+                    name : String,
+                }
+            }
+            date {
+                Event {
+                    name : String,
+                }
+            }
+            {
+                Person {
+                    event : date.Event,
+                    when : DateTime,
+                }
+            }",
+            )
+            .expect("namespaces can be named like a scalar type");
+        let person = datamodel.get_entity("Person").unwrap();
+        assert_eq!(
+            person.get_field("event").unwrap().field_type,
+            FieldType::Entity("date.Event".to_string())
+        );
     }
 
     #[test]
@@ -455,6 +550,113 @@ mod tests {
             .expect("@deprecated must be before the entity name");
     }
 
+    #[test]
+    fn default_from_field() {
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Person {
+                    name : String,
+                    display_name : String default name,
+                }
+            }"#,
+            )
+            .expect("display_name is filled from name");
+        let person = datamodel.get_entity("Person").unwrap();
+        let field = person.get_field("display_name").unwrap();
+        assert_eq!(Some("name".to_string()), field.default_field);
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Person {
+                    display_name : String default name,
+                }
+            }"#,
+            )
+            .expect_err("name does not exists");
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Person {
+                    age : Integer,
+                    display_name : String default age,
+                }
+            }"#,
+            )
+            .expect_err("age is not a String");
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Person {
+                    name : String nullable,
+                    display_name : String default name,
+                }
+            }"#,
+            )
+            .expect_err("name is nullable");
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Person {
+                    name : String,
+                    display_name : String default name,
+                    short_name : String default display_name,
+                }
+            }"#,
+            )
+            .expect_err("display_name is filled from another field");
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Person {
+                    is_vaccinated : Boolean default true,
+                    vaccinated : Boolean default truely,
+                }
+            }"#,
+            )
+            .expect_err("truely is not a boolean nor an existing field");
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Person {
+                    name : String,
+                }
+            }"#,
+            )
+            .unwrap();
+        datamodel
+            .update(
+                r#"
+            {
+                Person {
+                    name : String,
+                    display_name : String default name,
+                }
+            }"#,
+            )
+            .expect_err("existing nodes would not have a display_name");
+    }
+
     #[test]
     fn default_value() {
         let mut datamodel = DataModel::new();
@@ -1088,6 +1290,135 @@ mod tests {
             .expect("valid default value");
     }
 
+    #[test]
+    fn enum_field() {
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Article {
+                    status : Enum("draft", "published", "archived") default "draft",
+                    other : enum("a","b",) nullable,
+                }
+            }"#,
+            )
+            .expect("valid Enum");
+
+        let entity = datamodel.get_entity("Article").unwrap();
+        let field = entity.get_field("status").unwrap();
+        assert_eq!(
+            FieldType::Enum(vec![
+                "draft".to_string(),
+                "published".to_string(),
+                "archived".to_string()
+            ]),
+            field.field_type
+        );
+        assert_eq!(Some(1), field.field_type.enum_position("published"));
+        assert_eq!("archived", field.field_type.enum_value(2).unwrap());
+        assert!(field.field_type.enum_value(3).is_none());
+
+        validate_json_for_entity(entity, &Some(r#"{"32":1}"#.to_string())).expect("valid position");
+        validate_json_for_entity(entity, &Some(r#"{"32":3}"#.to_string()))
+            .expect_err("position out of range");
+        validate_json_for_entity(entity, &Some(r#"{"32":"draft"}"#.to_string()))
+            .expect_err("values are stored using their position");
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Article {
+                    status : Enum("draft", "published", "draft"),
+                }
+            }"#,
+            )
+            .expect_err("duplicated value");
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Article {
+                    status : Enum("draft", "published") default "archived",
+                }
+            }"#,
+            )
+            .expect_err("invalid default value");
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Article {
+                    status : Enum("draft", "published") default 1,
+                }
+            }"#,
+            )
+            .expect_err("invalid default value");
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Article {
+                    enum : String,
+                }
+            }"#,
+            )
+            .expect_err("reserved keyword");
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Article {
+                    status : Enum("draft", "published"),
+                }
+            }"#,
+            )
+            .unwrap();
+
+        datamodel
+            .update(
+                r#"
+            {
+                Article {
+                    status : Enum("published", "draft"),
+                }
+            }"#,
+            )
+            .expect_err("value position cannot change");
+
+        datamodel
+            .update(
+                r#"
+            {
+                Article {
+                    status : Enum("draft"),
+                }
+            }"#,
+            )
+            .expect_err("values cannot be removed");
+
+        datamodel
+            .update(
+                r#"
+            {
+                Article {
+                    status : Enum("draft", "published", "archived"),
+                }
+            }"#,
+            )
+            .expect("values can be appended");
+    }
+
     #[test]
     fn system() {
         let mut datamodel = DataModel::new();
@@ -1191,6 +1522,149 @@ mod tests {
         assert!(!person.enable_full_text);
     }
 
+    #[test]
+    fn id_generation() {
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                "
+            {
+                Person {
+                    name : String,
+                }
+                Message(time_ordered_id, no_full_text_index) {
+                    content : String,
+                }
+                Secret(random_id) {
+                    content : String,
+                }
+            }",
+            )
+            .unwrap();
+
+        let person = datamodel.get_entity("Person").unwrap();
+        assert_eq!(IdGeneration::Default, person.id_generation);
+
+        let message = datamodel.get_entity("Message").unwrap();
+        assert_eq!(IdGeneration::TimeOrdered, message.id_generation);
+        assert!(!message.enable_full_text);
+
+        let secret = datamodel.get_entity("Secret").unwrap();
+        assert_eq!(IdGeneration::Random, secret.id_generation);
+
+        let first = message.id_generation.new_uid();
+        let second = message.id_generation.new_uid();
+        assert!(first < second);
+
+        //the id generation can be changed, it only applies to new nodes
+        datamodel
+            .update(
+                "
+            {
+                Person(time_ordered_id) {
+                    name : String,
+                }
+                Message(no_full_text_index) {
+                    content : String,
+                }
+                Secret(random_id) {
+                    content : String,
+                }
+            }",
+            )
+            .unwrap();
+        let person = datamodel.get_entity("Person").unwrap();
+        assert_eq!(IdGeneration::TimeOrdered, person.id_generation);
+        let message = datamodel.get_entity("Message").unwrap();
+        assert_eq!(IdGeneration::Default, message.id_generation);
+
+        datamodel
+            .update(
+                "
+            {
+                Person(uuid_id) {
+                    name : String,
+                }
+            }",
+            )
+            .expect_err("unknown entity option");
+    }
+
+    #[test]
+    fn collation() {
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                r#"
+            {
+                Person {
+                    name : String collate nocase,
+                    nickname : String nullable collate rtrim,
+                    code : String default "a" COLLATE BINARY,
+                    surname : String,
+                    index(name, surname)
+                }
+            }"#,
+            )
+            .unwrap();
+
+        let person = datamodel.get_entity("Person").unwrap();
+        assert_eq!(
+            Collation::NoCase,
+            person.get_field("name").unwrap().collation
+        );
+        assert_eq!(
+            Collation::RTrim,
+            person.get_field("nickname").unwrap().collation
+        );
+        assert!(person.get_field("nickname").unwrap().nullable);
+        assert_eq!(
+            Collation::Binary,
+            person.get_field("code").unwrap().collation
+        );
+        assert_eq!(
+            Collation::Binary,
+            person.get_field("surname").unwrap().collation
+        );
+
+        let index = person.indexes.values().next().unwrap();
+        assert_eq!("idx$Person$name$nocase$surname", index.name());
+        assert!(index.create_query().contains("COLLATE NOCASE"));
+
+        //the collation can be changed
+        datamodel
+            .update(
+                r#"
+            {
+                Person {
+                    name : String,
+                    nickname : String nullable collate rtrim,
+                    code : String default "a" COLLATE BINARY,
+                    surname : String,
+                    index(name, surname)
+                }
+            }"#,
+            )
+            .unwrap();
+        let person = datamodel.get_entity("Person").unwrap();
+        assert_eq!(
+            Collation::Binary,
+            person.get_field("name").unwrap().collation
+        );
+
+        let mut datamodel = DataModel::new();
+        datamodel
+            .update(
+                "
+            {
+                Person {
+                    age : Integer collate nocase,
+                }
+            }",
+            )
+            .expect_err("only String fields can define a collation");
+    }
+
     #[test]
     fn namespace_update() {
         let mut datamodel = DataModel::new();
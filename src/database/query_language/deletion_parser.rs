@@ -1,8 +1,13 @@
-use super::{data_model_parser::DataModel, parameter::Variables, Error};
-use super::{FieldType, VariableType};
+use super::{
+    data_model_parser::{DataModel, Entity},
+    parameter::Variables,
+    Error,
+};
+use super::{FieldType, ParsingLimits, VariableType};
 use pest::iterators::Pair;
 use pest::Parser;
 use pest_derive::Parser;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Parser)]
 #[grammar = "database/query_language/deletion.pest"]
@@ -13,6 +18,20 @@ pub struct DeletionParser {
     pub name: String,
     pub variables: Variables,
     pub deletions: Vec<EntityDeletion>,
+    pub cascade: bool,
+    //array fields of the entities that can be deleted, indexed by the entity short name
+    pub cascade_references: HashMap<String, Vec<CascadeReference>>,
+}
+
+///
+/// array field followed when deleting by cascade
+///
+#[derive(Debug)]
+pub struct CascadeReference {
+    pub label: String,
+    pub dest_name: String,
+    pub dest_short_name: String,
+    pub dest_soft_delete: bool,
 }
 
 #[derive(Debug)]
@@ -22,6 +41,8 @@ pub struct EntityDeletion {
     pub alias: Option<String>,
     pub id_param: String,
     pub references: Vec<ReferenceDeletion>,
+    //the entity defines soft_delete
+    pub soft_delete: bool,
 }
 impl Default for EntityDeletion {
     fn default() -> Self {
@@ -36,6 +57,7 @@ impl EntityDeletion {
             alias: None,
             id_param: "".to_string(),
             references: Vec::new(),
+            soft_delete: false,
         }
     }
 }
@@ -57,10 +79,21 @@ impl DeletionParser {
             name: "".to_string(),
             variables: Variables::new(),
             deletions: Vec::new(),
+            cascade: false,
+            cascade_references: HashMap::new(),
         }
     }
 
     pub fn parse(query: &str, data_model: &DataModel) -> Result<DeletionParser, Error> {
+        Self::parse_with_limits(query, data_model, &ParsingLimits::default())
+    }
+
+    pub fn parse_with_limits(
+        query: &str,
+        data_model: &DataModel,
+        limits: &ParsingLimits,
+    ) -> Result<DeletionParser, Error> {
+        limits.check(query)?;
         let parse = match PestParser::parse(Rule::deletion, query) {
             Err(e) => {
                 let message = format!("{}", e);
@@ -78,8 +111,12 @@ impl DeletionParser {
                 let mut deletion_pairs = parse.into_inner();
 
                 let deletion_name = deletion_pairs.next().unwrap();
-                if let Some(name) = deletion_name.into_inner().next() {
-                    deletion.name = name.as_str().to_string();
+                for pair in deletion_name.into_inner() {
+                    match pair.as_rule() {
+                        Rule::identifier => deletion.name = pair.as_str().to_string(),
+                        Rule::cascade => deletion.cascade = true,
+                        _ => unreachable!(),
+                    }
                 }
 
                 for entity_pair in deletion_pairs {
@@ -99,10 +136,53 @@ impl DeletionParser {
             }
             _ => unreachable!(),
         }
+        if deletion.cascade {
+            deletion.cascade_references = Self::cascade_references(&deletion, data_model)?;
+        }
 
         Ok(deletion)
     }
 
+    //
+    // the array fields reachable from the deleted entities
+    // system entities are never deleted by cascade
+    //
+    fn cascade_references(
+        deletion: &DeletionParser,
+        data_model: &DataModel,
+    ) -> Result<HashMap<String, Vec<CascadeReference>>, Error> {
+        let mut references = HashMap::new();
+        let mut visited = HashSet::new();
+        let mut to_visit: Vec<String> = deletion.deletions.iter().map(|d| d.name.clone()).collect();
+        while let Some(name) = to_visit.pop() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            let entity = data_model.get_entity(&name)?;
+            let mut entity_references = Vec::new();
+            for field in entity.fields.values() {
+                if let FieldType::Array(dest_name) = &field.field_type {
+                    if dest_name.starts_with("sys.") {
+                        continue;
+                    }
+                    let dest = match data_model.get_mutable_entity(dest_name) {
+                        Ok(dest) => dest,
+                        Err(_) => continue,
+                    };
+                    entity_references.push(CascadeReference {
+                        label: field.short_name.clone(),
+                        dest_name: dest.name.clone(),
+                        dest_short_name: dest.short_name.clone(),
+                        dest_soft_delete: dest.soft_delete,
+                    });
+                    to_visit.push(dest.name.clone());
+                }
+            }
+            references.insert(entity.short_name.clone(), entity_references);
+        }
+        Ok(references)
+    }
+
     fn parse_entity(
         data_model: &DataModel,
         pair: Pair<'_, Rule>,
@@ -123,9 +203,10 @@ impl DeletionParser {
         } else {
             name_pair.next().unwrap().as_str()
         };
-        let model_entity = data_model.get_entity(entity_name)?;
+        let model_entity = data_model.get_mutable_entity(entity_name)?;
         entity.name = entity_name.to_string();
         entity.short_name = model_entity.short_name.clone();
+        entity.soft_delete = model_entity.soft_delete;
 
         for entity_pair in entity_pairs {
             match entity_pair.as_rule() {
@@ -134,38 +215,14 @@ impl DeletionParser {
                     variables.add(var, VariableType::Base64(false))?;
                     entity.id_param = var.to_string();
                 }
-                Rule::array_field => {
-                    let mut array_field_pairs = entity_pair.into_inner();
-                    let name = array_field_pairs.next().unwrap().as_str().to_string();
-                    let model_field = match model_entity.fields.get(&name) {
-                        None => {
-                            return Err(Error::InvalidQuery(format!(
-                                "Unknown field '{}' in entity '{}'",
-                                name, entity_name
-                            )))
-                        }
-                        Some(e) => match e.field_type {
-                            FieldType::Array(_) => e,
-                            _ => {
-                                return Err(Error::InvalidQuery(format!(
-                                "'{}' in entity '{}' is not defined as an array in the data model",
-                                name, entity_name
-                            )))
-                            }
-                        },
-                    };
-
-                    for param_pair in array_field_pairs {
-                        let id_param = param_pair.as_str()[1..].to_string(); //remove $
-
-                        variables.add(&id_param, VariableType::Base64(false))?;
-
-                        entity.references.push(ReferenceDeletion {
-                            label: model_field.short_name.clone(),
-                            dest_param: id_param,
-                            // entity_name: entity_name.to_string(),
-                        });
-                    }
+                Rule::array_field | Rule::edge_field => {
+                    Self::parse_references(
+                        &mut entity,
+                        entity_name,
+                        model_entity,
+                        entity_pair,
+                        variables,
+                    )?;
                 }
 
                 _ => unreachable!(),
@@ -174,12 +231,57 @@ impl DeletionParser {
 
         Ok(entity)
     }
+
+    //
+    // references are removed from the array field without deleting the referenced entities
+    //
+    fn parse_references(
+        entity: &mut EntityDeletion,
+        entity_name: &str,
+        model_entity: &Entity,
+        pair: Pair<'_, Rule>,
+        variables: &mut Variables,
+    ) -> Result<(), Error> {
+        let mut array_field_pairs = pair.into_inner();
+        let name = array_field_pairs.next().unwrap().as_str().to_string();
+        let model_field = match model_entity.fields.get(&name) {
+            None => {
+                return Err(Error::InvalidQuery(format!(
+                    "Unknown field '{}' in entity '{}'",
+                    name, entity_name
+                )))
+            }
+            Some(e) => match e.field_type {
+                FieldType::Array(_) => e,
+                _ => {
+                    return Err(Error::InvalidQuery(format!(
+                        "'{}' in entity '{}' is not defined as an array in the data model",
+                        name, entity_name
+                    )))
+                }
+            },
+        };
+
+        for param_pair in array_field_pairs {
+            let id_param = param_pair.as_str()[1..].to_string(); //remove $
+
+            variables.add(&id_param, VariableType::Base64(false))?;
+
+            entity.references.push(ReferenceDeletion {
+                label: model_field.short_name.clone(),
+                dest_param: id_param,
+                // entity_name: entity_name.to_string(),
+            });
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::database::query_language::query_parser::QueryParser;
     #[test]
     fn parse_valid_deletion() {
         let mut data_model = DataModel::new();
@@ -227,7 +329,7 @@ mod tests {
 
         assert_eq!(2, deletion.deletions.len());
 
-        let query = deletion.deletions.get(0).unwrap();
+        let query = deletion.deletions.first().unwrap();
         assert_eq!("Person", query.name);
         assert_eq!("0", query.short_name);
         let alias = query.alias.as_ref().unwrap();
@@ -235,7 +337,7 @@ mod tests {
         assert_eq!("id", query.id_param);
         assert_eq!(3, query.references.len());
 
-        let reference = query.references.get(0).unwrap();
+        let reference = query.references.first().unwrap();
         assert_eq!("34", reference.label);
         assert_eq!("id2".to_string(), reference.dest_param);
 
@@ -255,6 +357,171 @@ mod tests {
         assert_eq!(0, query.references.len());
     }
 
+    #[test]
+    fn parse_cascade() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            {
+                Person {
+                    name : String ,
+                    pets : [Pet],
+                }
+
+                Pet {
+                    name : String ,
+                    toys : [Toy],
+                }
+
+                Toy {
+                    name : String ,
+                }
+
+                Other {
+                    name : String ,
+                }
+            }",
+            )
+            .unwrap();
+
+        let deletion = DeletionParser::parse(
+            "delete delete_person @cascade { Person { $id } }",
+            &data_model,
+        )
+        .unwrap();
+        assert_eq!("delete_person", deletion.name);
+        assert!(deletion.cascade);
+        assert_eq!(3, deletion.cascade_references.len());
+
+        let references = deletion.cascade_references.get("0").unwrap();
+        assert_eq!(1, references.len());
+        assert_eq!("Pet", references[0].dest_name);
+        assert_eq!("1", references[0].dest_short_name);
+        assert!(deletion.cascade_references.get("2").unwrap().is_empty());
+        assert!(!deletion.cascade_references.contains_key("3"));
+
+        let deletion = DeletionParser::parse("delete { Person { $id } }", &data_model).unwrap();
+        assert!(!deletion.cascade);
+        assert!(deletion.cascade_references.is_empty());
+    }
+
+    #[test]
+    fn parse_edge_deletion() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            {
+                Person {
+                    name : String ,
+                    parent : [Person],
+                    pet : Pet
+                }
+
+                Pet {
+                    name : String ,
+                }
+            }",
+            )
+            .unwrap();
+
+        let deletion = DeletionParser::parse(
+            "
+            delete delete_parent {
+                del1: Person{$id}.parent{$id2, $id3}
+                Person{$id4}.parent{$id5}
+            }
+          ",
+            &data_model,
+        )
+        .unwrap();
+
+        assert_eq!(2, deletion.deletions.len());
+
+        let query = deletion.deletions.first().unwrap();
+        assert_eq!("Person", query.name);
+        assert_eq!("del1", query.alias.as_ref().unwrap());
+        assert_eq!("id", query.id_param);
+        assert_eq!(2, query.references.len());
+        let reference = query.references.get(1).unwrap();
+        assert_eq!("33", reference.label);
+        assert_eq!("id3".to_string(), reference.dest_param);
+
+        let query = deletion.deletions.get(1).unwrap();
+        assert_eq!("id4", query.id_param);
+        assert_eq!(1, query.references.len());
+
+        DeletionParser::parse(
+            "
+            delete delete_parent {
+                Person{$id}.unknown{$id2}
+            }
+          ",
+            &data_model,
+        )
+        .expect_err("unknown field");
+
+        DeletionParser::parse(
+            "
+            delete delete_parent {
+                Person{$id}.name{$id2}
+            }
+          ",
+            &data_model,
+        )
+        .expect_err("name is not an array");
+
+        DeletionParser::parse(
+            "
+            delete delete_parent {
+                Person{$id}.pet{$id2}
+            }
+          ",
+            &data_model,
+        )
+        .expect_err("pet is not an array");
+    }
+
+    #[test]
+    fn deprecated_namespace() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            @deprecated old {
+                Pet {
+                    name : String ,
+                }
+            }",
+            )
+            .unwrap();
+
+        DeletionParser::parse(
+            "
+            delete {
+                old.Pet {
+                    $id
+                }
+            }
+          ",
+            &data_model,
+        )
+        .expect_err("deprecated namespace cannot be deleted");
+
+        QueryParser::parse(
+            "
+            query {
+                old.Pet {
+                    name
+                }
+            }
+          ",
+            &data_model,
+        )
+        .expect("deprecated namespace can be queried");
+    }
+
     #[test]
     fn parse_namespace_datamodel() {
         let mut data_model = DataModel::new();
@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::Error;
+
+//
+// keywords of the supported JSON Schema subset
+//
+const KEYWORDS: [&str; 13] = [
+    "type",
+    "properties",
+    "required",
+    "additionalProperties",
+    "items",
+    "enum",
+    "minimum",
+    "maximum",
+    "minLength",
+    "maxLength",
+    "minItems",
+    "maxItems",
+    "description",
+];
+
+const TYPES: [&str; 7] = [
+    "object", "array", "string", "number", "integer", "boolean", "null",
+];
+
+///
+/// A minimal subset of JSON Schema used to validate the content of Json fields.
+///
+/// Supported keywords:
+/// - type: a type name or an array of type names (object, array, string, number, integer, boolean, null)
+/// - properties, required, additionalProperties (boolean only)
+/// - items
+/// - enum
+/// - minimum, maximum, minLength, maxLength, minItems, maxItems
+/// - description (ignored)
+///
+/// Any other keyword is rejected when the data model is parsed, to avoid silently accepting invalid data.
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonSchema(Value);
+impl JsonSchema {
+    ///
+    /// parse and check the schema definition of a field
+    ///
+    pub fn parse(field: &str, schema: &str) -> Result<Self, Error> {
+        let value: Value = serde_json::from_str(schema)
+            .map_err(|e| Error::InvalidJsonSchema(field.to_string(), e.to_string()))?;
+        Self::check(&value, "$").map_err(|e| Error::InvalidJsonSchema(field.to_string(), e))?;
+        Ok(Self(value))
+    }
+
+    fn check(schema: &Value, path: &str) -> Result<(), String> {
+        let schema = schema
+            .as_object()
+            .ok_or(format!("{}: a schema must be an object", path))?;
+
+        for (keyword, value) in schema {
+            let valid = match keyword.as_str() {
+                "type" => match value {
+                    Value::String(t) => TYPES.contains(&t.as_str()),
+                    Value::Array(types) => types
+                        .iter()
+                        .all(|t| t.as_str().is_some_and(|t| TYPES.contains(&t))),
+                    _ => false,
+                },
+                "properties" => match value.as_object() {
+                    Some(properties) => {
+                        for (name, property) in properties {
+                            Self::check(property, &format!("{}.{}", path, name))?;
+                        }
+                        true
+                    }
+                    None => false,
+                },
+                "required" => value
+                    .as_array()
+                    .is_some_and(|r| r.iter().all(|name| name.is_string())),
+                "additionalProperties" => value.is_boolean(),
+                "items" => {
+                    Self::check(value, &format!("{}[]", path))?;
+                    true
+                }
+                "enum" => value.is_array(),
+                "minimum" | "maximum" => value.is_number(),
+                "minLength" | "maxLength" | "minItems" | "maxItems" => value.is_u64(),
+                "description" => value.is_string(),
+                _ => {
+                    return Err(format!(
+                        "{}: unsupported keyword '{}', supported keywords are: {}",
+                        path,
+                        keyword,
+                        KEYWORDS.join(", ")
+                    ))
+                }
+            };
+            if !valid {
+                return Err(format!("{}: invalid value for keyword '{}'", path, keyword));
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// validate a value against the schema
+    ///
+    pub fn validate(&self, field: &str, value: &Value) -> Result<(), Error> {
+        Self::validate_value(&self.0, value, "$")
+            .map_err(|e| Error::JsonSchemaViolation(field.to_string(), e))
+    }
+
+    fn validate_value(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+        //the schema is checked during parsing
+        let schema = schema.as_object().unwrap();
+
+        if let Some(types) = schema.get("type") {
+            let valid = match types {
+                Value::Array(types) => types
+                    .iter()
+                    .any(|t| Self::has_type(t.as_str().unwrap(), value)),
+                t => Self::has_type(t.as_str().unwrap(), value),
+            };
+            if !valid {
+                return Err(format!("{}: expected type {}", path, types));
+            }
+        }
+
+        if let Some(values) = schema.get("enum").and_then(|v| v.as_array()) {
+            if !values.contains(value) {
+                return Err(format!("{}: value is not one of {}", path, schema["enum"]));
+            }
+        }
+
+        match value {
+            Value::Number(n) => {
+                let n = n.as_f64().unwrap_or_default();
+                if let Some(min) = schema.get("minimum").and_then(|v| v.as_f64()) {
+                    if n < min {
+                        return Err(format!("{}: {} is lower than {}", path, n, min));
+                    }
+                }
+                if let Some(max) = schema.get("maximum").and_then(|v| v.as_f64()) {
+                    if n > max {
+                        return Err(format!("{}: {} is greater than {}", path, n, max));
+                    }
+                }
+            }
+            Value::String(s) => {
+                let len = s.chars().count() as u64;
+                if let Some(min) = schema.get("minLength").and_then(|v| v.as_u64()) {
+                    if len < min {
+                        return Err(format!("{}: length is lower than {}", path, min));
+                    }
+                }
+                if let Some(max) = schema.get("maxLength").and_then(|v| v.as_u64()) {
+                    if len > max {
+                        return Err(format!("{}: length is greater than {}", path, max));
+                    }
+                }
+            }
+            Value::Array(items) => {
+                let len = items.len() as u64;
+                if let Some(min) = schema.get("minItems").and_then(|v| v.as_u64()) {
+                    if len < min {
+                        return Err(format!("{}: contains less than {} items", path, min));
+                    }
+                }
+                if let Some(max) = schema.get("maxItems").and_then(|v| v.as_u64()) {
+                    if len > max {
+                        return Err(format!("{}: contains more than {} items", path, max));
+                    }
+                }
+                if let Some(item_schema) = schema.get("items") {
+                    for (i, item) in items.iter().enumerate() {
+                        Self::validate_value(item_schema, item, &format!("{}[{}]", path, i))?;
+                    }
+                }
+            }
+            Value::Object(object) => {
+                if let Some(required) = schema.get("required").and_then(|v| v.as_array()) {
+                    for name in required {
+                        let name = name.as_str().unwrap();
+                        if !object.contains_key(name) {
+                            return Err(format!("{}: missing required property '{}'", path, name));
+                        }
+                    }
+                }
+                let properties = schema.get("properties").and_then(|v| v.as_object());
+                let additional = schema
+                    .get("additionalProperties")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(true);
+                for (name, property) in object {
+                    let property_path = format!("{}.{}", path, name);
+                    match properties.and_then(|p| p.get(name)) {
+                        Some(property_schema) => {
+                            Self::validate_value(property_schema, property, &property_path)?
+                        }
+                        None => {
+                            if !additional {
+                                return Err(format!("{}: property is not allowed", property_path));
+                            }
+                        }
+                    }
+                }
+            }
+            Value::Bool(_) | Value::Null => {}
+        }
+        Ok(())
+    }
+
+    fn has_type(json_type: &str, value: &Value) -> bool {
+        match json_type {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "number" => value.is_number(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_schema() {
+        let schema = JsonSchema::parse(
+            "address",
+            r#"{
+                "type": "object",
+                "required": ["city"],
+                "additionalProperties": false,
+                "properties": {
+                    "city": { "type": "string", "minLength": 1 },
+                    "zip": { "type": ["string", "null"], "maxLength": 5 },
+                    "tags": { "type": "array", "items": { "enum": ["home", "work"] }, "maxItems": 2 },
+                    "floor": { "type": "integer", "minimum": 0 }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let valid: Value =
+            serde_json::from_str(r#"{"city":"Paris", "zip":null, "tags":["home"], "floor":2}"#)
+                .unwrap();
+        schema.validate("address", &valid).unwrap();
+
+        for invalid in [
+            r#"{"zip":"75000"}"#,
+            r#"{"city":""}"#,
+            r#"{"city":"Paris", "zip":"750000"}"#,
+            r#"{"city":"Paris", "tags":["school"]}"#,
+            r#"{"city":"Paris", "tags":["home", "work", "home"]}"#,
+            r#"{"city":"Paris", "floor":1.5}"#,
+            r#"{"city":"Paris", "floor":-1}"#,
+            r#"{"city":"Paris", "country":"France"}"#,
+            r#"["Paris"]"#,
+        ] {
+            let value: Value = serde_json::from_str(invalid).unwrap();
+            schema
+                .validate("address", &value)
+                .expect_err(&format!("{} should be invalid", invalid));
+        }
+
+        JsonSchema::parse("a", r#"{"type": "date"}"#).expect_err("unknown type");
+        JsonSchema::parse("a", r#"{"pattern": "^a"}"#).expect_err("unsupported keyword");
+        JsonSchema::parse("a", r#"{"properties": {"b": {"minLength": "a"}}}"#)
+            .expect_err("invalid nested keyword value");
+        JsonSchema::parse("a", r#"["object"]"#).expect_err("not an object");
+    }
+}
@@ -1,6 +1,7 @@
 pub mod data_model_parser;
 pub mod data_model_parser_test;
 pub mod deletion_parser;
+pub mod json_schema;
 pub mod mutation_parser;
 pub mod parameter;
 pub mod query_parser;
@@ -11,6 +12,8 @@ use serde::{Deserialize, Serialize};
 use serde_json::Number;
 use thiserror::Error;
 
+use crate::date_utils::{parse_date, parse_datetime, truncate_datetime, TimeUnit};
+
 #[derive(Debug, Clone)]
 pub enum FieldValue {
     Variable(String),
@@ -24,6 +27,7 @@ pub enum ParamValue {
     Float(f64),
     String(String),
     Binary(String),
+    Array(Vec<ParamValue>),
     Null,
 }
 impl ParamValue {
@@ -72,6 +76,13 @@ impl ParamValue {
             }
             ParamValue::String(v) => Ok(serde_json::Value::String(String::from(v))),
             ParamValue::Binary(v) => Ok(serde_json::Value::String(String::from(v))),
+            ParamValue::Array(v) => {
+                let mut array = Vec::with_capacity(v.len());
+                for value in v {
+                    array.push(value.as_serde_json_value()?);
+                }
+                Ok(serde_json::Value::Array(array))
+            }
             ParamValue::Null => Ok(serde_json::Value::Null),
         }
     }
@@ -86,6 +97,10 @@ pub enum VariableType {
     Integer(bool),
     String(bool),
     Binary(bool),
+    DateTime(bool),
+    Date(bool),
+    Geo(bool),
+    Array(Box<VariableType>),
     Invalid,
 }
 impl fmt::Display for VariableType {
@@ -104,13 +119,135 @@ pub enum FieldType {
     Integer,
     String,
     Json,
+    Enum(Vec<String>),
+    DateTime,
+    Date,
+    Geo,
+    File,
+}
+impl FieldType {
+    ///
+    /// DateTime and Date strings are stored as milliseconds since unix epoch, Date values at the start of the UTC day
+    ///
+    pub fn parse_date(&self, value: &str) -> Result<i64, Error> {
+        match self {
+            Self::Date => parse_date(value).ok_or_else(|| Error::InvalidDate(value.to_string())),
+            _ => parse_datetime(value).ok_or_else(|| Error::InvalidDateTime(value.to_string())),
+        }
+    }
+
+    ///
+    /// Date numbers are truncated to the start of the UTC day
+    ///
+    pub fn date_number(&self, value: i64) -> i64 {
+        match self {
+            Self::Date => truncate_datetime(value, TimeUnit::Day),
+            _ => value,
+        }
+    }
+
+    ///
+    /// Enum values are stored using their position in the value list
+    ///
+    pub fn enum_position(&self, value: &str) -> Option<i64> {
+        match self {
+            Self::Enum(values) => values.iter().position(|v| v.eq(value)).map(|p| p as i64),
+            _ => None,
+        }
+    }
+
+    ///
+    /// retrieve the Enum value stored at the provided position
+    ///
+    pub fn enum_value(&self, position: i64) -> Option<&String> {
+        match self {
+            Self::Enum(values) => usize::try_from(position).ok().and_then(|p| values.get(p)),
+            _ => None,
+        }
+    }
 }
+///
+/// length of the identifiers stored in File fields, see *Discret::put_file()*
+///
+pub const FILE_ID_LENGTH: usize = 32;
+
+///
+/// File fields contain the base64 encoded identifier of a file
+///
+pub fn validate_file_id(value: &str, name: &str) -> Result<(), Error> {
+    match crate::security::base64_decode(value.as_bytes()) {
+        Ok(id) if id.len() == FILE_ID_LENGTH => Ok(()),
+        _ => Err(Error::InvalidFileId(name.to_string(), value.to_string())),
+    }
+}
+
 impl fmt::Display for FieldType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 
+///
+/// Size and nesting limits checked before parsing a query, a mutation or a deletion.
+/// Protects the parsers against pathological inputs provided by untrusted sources
+///
+#[derive(Debug, Clone)]
+pub struct ParsingLimits {
+    pub max_size: usize,
+    pub max_depth: usize,
+}
+impl Default for ParsingLimits {
+    fn default() -> Self {
+        Self {
+            max_size: 1024 * 1024,
+            max_depth: 64,
+        }
+    }
+}
+impl ParsingLimits {
+    ///
+    /// the depth is the number of nested '{', '(' and '[' found outside of the strings and comments
+    ///
+    pub fn check(&self, input: &str) -> Result<(), Error> {
+        if input.len() > self.max_size {
+            return Err(Error::InputTooLarge(input.len(), self.max_size));
+        }
+        let mut depth: usize = 0;
+        let mut in_string = false;
+        let mut in_comment = false;
+        let mut bytes = input.bytes().peekable();
+        while let Some(b) = bytes.next() {
+            if in_comment {
+                if b == b'\n' {
+                    in_comment = false;
+                }
+            } else if in_string {
+                match b {
+                    b'\\' => {
+                        bytes.next();
+                    }
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+            } else {
+                match b {
+                    b'"' => in_string = true,
+                    b'/' if bytes.peek() == Some(&b'/') => in_comment = true,
+                    b'{' | b'(' | b'[' => {
+                        depth += 1;
+                        if depth > self.max_depth {
+                            return Err(Error::InputTooDeep(self.max_depth));
+                        }
+                    }
+                    b'}' | b')' | b']' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error(transparent)]
@@ -131,9 +268,18 @@ pub enum Error {
     #[error("{0}")]
     InvalidQuery(String),
 
+    #[error("Function '{0}' is not registered")]
+    UnknownFunction(String),
+
+    #[error("Function '{0}' requires {1} arguments and is called with {2}")]
+    InvalidFunctionArguments(String, usize, usize),
+
     #[error("Namespace: '{0}' does not exists")]
     NamespaceNotFound(String),
 
+    #[error("namespace '{0}' is deprecated, its entities cannot be mutated")]
+    DeprecatedNamespace(String),
+
     #[error("{0}")]
     NamespaceUpdate(String),
 
@@ -203,12 +349,45 @@ pub enum Error {
     #[error("'{0}' is not valid JSON value")]
     InvalidJson(String),
 
+    #[error("field '{0}' has an invalid JSON schema: {1}")]
+    InvalidJsonSchema(String, String),
+
+    #[error("field '{0}' does not match its JSON schema: {1}")]
+    JsonSchemaViolation(String, String),
+
+    #[error("'{0}' is not a RFC3339 date or a number of milliseconds since the unix epoch")]
+    InvalidDateTime(String),
+
+    #[error("'{0}' is not a date formatted as 'YYYY-MM-DD', a RFC3339 date or a number of milliseconds since the unix epoch")]
+    InvalidDate(String),
+
+    #[error("'{0}' is not a location formatted as 'latitude,longitude'")]
+    InvalidLocation(String),
+
+    #[error("'{0}' is not a valid value for the Enum field '{1}'")]
+    InvalidEnumValue(String, String),
+
+    #[error("Enum field '{0}' defines the value '{1}' more than once")]
+    DuplicatedEnumValue(String, String),
+
     #[error("'{0}' is not a {1}. value:{2}")]
     ConflictingParameterType(String, String, String),
 
     #[error("field {0} default value is a '{1}' is not a {2}")]
     InvalidDefaultValue(String, String, String),
 
+    #[error("field {0} cannot be filled from another field: {1}")]
+    InvalidDefaultField(String, String),
+
+    #[error("edit_chain field {0} of entity {1} must be a nullable Base64 field")]
+    InvalidEditChain(String, String),
+
+    #[error("field '{0}' value '{1}' is not a file identifier")]
+    InvalidFileId(String, String),
+
+    #[error("field {0} is a {1}, only String fields can define a collation")]
+    InvalidCollation(String, String),
+
     #[error("float {0} is not a valid JSON float")]
     InvalidFloat(f64),
 
@@ -240,4 +419,16 @@ pub enum Error {
 
     #[error("the provided parameters '{0}' cannot be an object or an array ")]
     InvalidJsonParamField(String),
+
+    #[error("the '{0}' operation on field '{1}' requires an array variable")]
+    InvalidArrayFilter(String, String),
+
+    #[error("the '{0}' operation on field '{1}' requires a String field and a String value")]
+    InvalidStringFilter(String, String),
+
+    #[error("the input is {0} bytes long and exceeds the maximum size of {1} bytes")]
+    InputTooLarge(usize, usize),
+
+    #[error("the input exceeds the maximum nesting depth of {0}")]
+    InputTooDeep(usize),
 }
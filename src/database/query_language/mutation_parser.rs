@@ -2,13 +2,15 @@ use std::collections::HashMap;
 
 use crate::{
     database::system_entities::{ID_FIELD, ROOM_ID_FIELD},
-    security::base64_decode,
+    geo_utils::parse_location,
+    security::{base64_decode, hash},
 };
 
 use super::{
-    data_model_parser::{DataModel, Entity, Field},
+    data_model_parser::{DataModel, Entity, Field, IdGeneration},
+    json_schema::JsonSchema,
     parameter::Variables,
-    Error, FieldType, ParamValue,
+    validate_file_id, Error, FieldType, ParamValue, ParsingLimits, VariableType,
 };
 
 use pest::{
@@ -27,6 +29,7 @@ pub struct EntityMutation {
     pub alias: Option<String>,
     pub short_name: String,
     pub enable_full_text: bool,
+    pub id_generation: IdGeneration,
     pub depth: usize,
     pub fields: HashMap<String, MutationField>,
 }
@@ -42,6 +45,7 @@ impl EntityMutation {
             short_name: String::from(""),
             alias: None,
             enable_full_text: true,
+            id_generation: IdGeneration::default(),
             depth: 0,
             fields: HashMap::new(),
         }
@@ -78,6 +82,7 @@ pub struct MutationField {
     pub field_type: FieldType,
     pub field_value: MutationFieldValue,
     pub is_default_filled: bool,
+    pub json_schema: Option<JsonSchema>,
 }
 impl Default for MutationField {
     fn default() -> Self {
@@ -92,6 +97,7 @@ impl MutationField {
             field_type: FieldType::Boolean,
             field_value: MutationFieldValue::Value(ParamValue::Boolean(true)),
             is_default_filled: false,
+            json_schema: None,
         }
     }
 }
@@ -99,6 +105,9 @@ impl MutationField {
 #[derive(Debug)]
 pub struct MutationParser {
     pub name: String,
+    pub idempotency_key: Option<MutationFieldValue>,
+    //hash of the mutation text, used to detect an idempotency key reused by another mutation
+    pub source_hash: [u8; 32],
     pub variables: Variables,
     pub mutations: Vec<EntityMutation>,
 }
@@ -111,13 +120,25 @@ impl MutationParser {
     pub fn new() -> Self {
         Self {
             name: "".to_string(),
+            idempotency_key: None,
+            source_hash: [0; 32],
             variables: Variables::new(),
             mutations: Vec::new(),
         }
     }
 
     pub fn parse(p: &str, data_model: &DataModel) -> Result<Self, Error> {
+        Self::parse_with_limits(p, data_model, &ParsingLimits::default())
+    }
+
+    pub fn parse_with_limits(
+        p: &str,
+        data_model: &DataModel,
+        limits: &ParsingLimits,
+    ) -> Result<Self, Error> {
+        limits.check(p)?;
         let mut mutation = MutationParser::new();
+        mutation.source_hash = hash(p.as_bytes());
 
         let parse = match PestParser::parse(Rule::mutation, p) {
             Err(e) => {
@@ -133,8 +154,34 @@ impl MutationParser {
             let mut mutation_pairs = parse.into_inner();
 
             let mutation_name = mutation_pairs.next().unwrap();
-            if let Some(name) = mutation_name.into_inner().next() {
-                mutation.name = name.as_str().to_string();
+            for pair in mutation_name.into_inner() {
+                match pair.as_rule() {
+                    Rule::identifier => mutation.name = pair.as_str().to_string(),
+                    Rule::idempotency_key => {
+                        let key_pair = pair.into_inner().next().unwrap();
+                        let key = match key_pair.as_rule() {
+                            Rule::variable => {
+                                let var = &key_pair.as_str()[1..];
+                                mutation.variables.add(var, VariableType::String(false))?;
+                                MutationFieldValue::Variable(var.to_string())
+                            }
+                            Rule::string => {
+                                let value = key_pair.into_inner().next().unwrap().as_str();
+                                if value.is_empty() {
+                                    return Err(Error::InvalidQuery(
+                                        "idempotency key cannot be empty".to_string(),
+                                    ));
+                                }
+                                MutationFieldValue::Value(ParamValue::String(
+                                    value.replace("\\\"", "\""),
+                                ))
+                            }
+                            _ => unreachable!(),
+                        };
+                        mutation.idempotency_key = Some(key);
+                    }
+                    _ => unreachable!(),
+                }
             }
 
             for entity_pair in mutation_pairs {
@@ -190,10 +237,11 @@ impl MutationParser {
         entity.depth =
             Self::parse_entity_internals(&mut entity, data_model, entity_pairs, variables)?;
 
-        let entity_model = data_model.get_entity(&entity.name)?;
+        let entity_model = data_model.get_mutable_entity(&entity.name)?;
 
         entity.short_name = entity_model.short_name.clone();
         entity.enable_full_text = entity_model.enable_full_text;
+        entity.id_generation = entity_model.id_generation;
 
         Self::propagate_room(&mut entity)?;
         Self::fill_not_nullable(&mut entity, entity_model)?;
@@ -250,7 +298,7 @@ impl MutationParser {
         }
         for m_field_tuple in &entity_model.fields {
             let model_field = m_field_tuple.1;
-            if !model_field.nullable {
+            if !model_field.nullable && model_field.default_field.is_none() {
                 let field = entity_mutation.fields.get(&model_field.name);
                 if field.is_none() {
                     if let Some(default) = &model_field.default_value {
@@ -260,6 +308,7 @@ impl MutationParser {
                             field_type: model_field.field_type.clone(),
                             field_value: MutationFieldValue::Value(default.clone()),
                             is_default_filled: true,
+                            json_schema: model_field.json_schema.clone(),
                         };
                         entity_mutation
                             .fields
@@ -272,7 +321,12 @@ impl MutationParser {
                             | FieldType::Base64
                             | FieldType::Integer
                             | FieldType::String
-                            | FieldType::Json => {
+                            | FieldType::Json
+                            | FieldType::Enum(_)
+                            | FieldType::DateTime
+                            | FieldType::Date
+                            | FieldType::Geo
+                            | FieldType::File => {
                                 return Err(Error::MissingUpdateField(
                                     String::from(&entity_model.name),
                                     String::from(&model_field.name),
@@ -283,6 +337,37 @@ impl MutationParser {
                 }
             }
         }
+
+        //fields filled from a sibling field are processed once every sibling has its final value
+        for m_field_tuple in &entity_model.fields {
+            let model_field = m_field_tuple.1;
+            if let Some(source) = &model_field.default_field {
+                if entity_mutation.fields.contains_key(&model_field.name) {
+                    continue;
+                }
+                match entity_mutation.fields.get(source) {
+                    Some(source_field) => {
+                        let mutation_field = MutationField {
+                            name: model_field.name.clone(),
+                            short_name: model_field.short_name.clone(),
+                            field_type: model_field.field_type.clone(),
+                            field_value: source_field.field_value.clone(),
+                            is_default_filled: true,
+                            json_schema: model_field.json_schema.clone(),
+                        };
+                        entity_mutation
+                            .fields
+                            .insert(mutation_field.name.clone(), mutation_field);
+                    }
+                    None => {
+                        return Err(Error::MissingUpdateField(
+                            String::from(&entity_model.name),
+                            String::from(&model_field.name),
+                        ))
+                    }
+                }
+            }
+        }
         Ok(())
     }
 
@@ -311,6 +396,7 @@ impl MutationParser {
                     let mut mutation_field = MutationField::new();
                     mutation_field.name = name;
                     mutation_field.short_name = field_model.short_name.clone();
+                    mutation_field.json_schema = field_model.json_schema.clone();
 
                     let content_pair = field_pairs.next().unwrap().into_inner().next().unwrap();
                     match content_pair.as_rule() {
@@ -441,7 +527,7 @@ impl MutationParser {
                     if adepth > depth {
                         depth = adepth;
                     }
-                    let entity_model = data_model.get_entity(&entity.name)?;
+                    let entity_model = data_model.get_mutable_entity(&entity.name)?;
                     entity.short_name = entity_model.short_name.clone();
                     Self::fill_not_nullable(&mut entity, entity_model)?;
                     entities.push(entity)
@@ -479,7 +565,7 @@ impl MutationParser {
         let var_pair = content_pair.into_inner();
 
         let adepth = Self::parse_entity_internals(&mut entity, data_model, var_pair, variables)?;
-        let entity_model = data_model.get_entity(&entity.name)?;
+        let entity_model = data_model.get_mutable_entity(&entity.name)?;
         entity.short_name = entity_model.short_name.clone();
 
         Self::fill_not_nullable(&mut entity, entity_model)?;
@@ -544,10 +630,11 @@ impl MutationParser {
                 mutation_field.field_value =
                     MutationFieldValue::Value(ParamValue::Float(value.parse()?));
             }
-            FieldType::Integer => {
+            FieldType::Integer | FieldType::DateTime | FieldType::Date => {
                 let value = content_pair.as_str();
-                mutation_field.field_value =
-                    MutationFieldValue::Value(ParamValue::Integer(value.parse()?));
+                mutation_field.field_value = MutationFieldValue::Value(ParamValue::Integer(
+                    field.field_type.date_number(value.parse()?),
+                ));
             }
             _ => {
                 return Err(Error::InvalidFieldType(
@@ -577,15 +664,35 @@ impl MutationParser {
                 MutationParser::validate_base64(&value, &field.name)?;
                 mutation_field.field_value = MutationFieldValue::Value(ParamValue::String(value));
             }
+            FieldType::File => {
+                validate_file_id(&value, &field.name)?;
+                mutation_field.field_value = MutationFieldValue::Value(ParamValue::String(value));
+            }
             FieldType::Json => {
-                let v: std::result::Result<serde_json::Value, serde_json::Error> =
-                    serde_json::from_str(&value);
-                if v.is_err() {
-                    return Err(Error::InvalidJson(value));
+                let v: serde_json::Value =
+                    serde_json::from_str(&value).map_err(|_| Error::InvalidJson(value.clone()))?;
+                if let Some(schema) = &field.json_schema {
+                    schema.validate(&field.name, &v)?;
                 }
 
                 mutation_field.field_value = MutationFieldValue::Value(ParamValue::String(value));
             }
+            FieldType::Enum(_) => {
+                if field.field_type.enum_position(&value).is_none() {
+                    return Err(Error::InvalidEnumValue(value, field.name.clone()));
+                }
+                mutation_field.field_value = MutationFieldValue::Value(ParamValue::String(value));
+            }
+            FieldType::DateTime | FieldType::Date => {
+                let date = field.field_type.parse_date(&value)?;
+                mutation_field.field_value = MutationFieldValue::Value(ParamValue::Integer(date));
+            }
+            FieldType::Geo => {
+                if parse_location(&value).is_none() {
+                    return Err(Error::InvalidLocation(value));
+                }
+                mutation_field.field_value = MutationFieldValue::Value(ParamValue::String(value));
+            }
             _ => {
                 return Err(Error::InvalidFieldType(
                     mutation_field.name.to_string(),
@@ -612,7 +719,12 @@ impl MutationParser {
                 | FieldType::Base64
                 | FieldType::Integer
                 | FieldType::String
-                | FieldType::Json => return Err(Error::NotNullable(field.name.clone())),
+                | FieldType::Json
+                | FieldType::Enum(_)
+                | FieldType::DateTime
+                | FieldType::Date
+                | FieldType::Geo
+                | FieldType::File => return Err(Error::NotNullable(field.name.clone())),
             }
         }
         mutation_field.field_type = field.field_type.clone();
@@ -714,6 +826,85 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn deprecated_namespace() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            {
+                Person {
+                    name : String,
+                    pet : old.Pet nullable,
+                }
+            }
+            @deprecated old {
+                Pet {
+                    name : String,
+                }
+            }",
+            )
+            .unwrap();
+
+        let err = MutationParser::parse(
+            r#"
+            mutate {
+                old.Pet { name : "kiki" }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("deprecated namespace cannot be mutated");
+        assert!(matches!(err, Error::DeprecatedNamespace(ns) if ns.eq("old")));
+
+        MutationParser::parse(
+            r#"
+            mutate {
+                Person { name : "me" pet : { name : "kiki"} }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("deprecated namespace cannot be mutated from another entity");
+
+        MutationParser::parse(
+            r#"
+            mutate {
+                Person { name : "me" }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+
+        data_model
+            .update(
+                "
+            {
+                Person {
+                    name : String,
+                    pet : old.Pet nullable,
+                }
+            }
+            old {
+                Pet {
+                    name : String,
+                }
+            }",
+            )
+            .unwrap();
+
+        MutationParser::parse(
+            r#"
+            mutate {
+                old.Pet { name : "kiki" }
+            }
+        "#,
+            &data_model,
+        )
+        .expect("namespace is not deprecated anymore");
+    }
+
     #[test]
     fn scalar_field() {
         let mut data_model = DataModel::new();
@@ -889,6 +1080,82 @@ mod tests {
         .expect_err("namespace not found");
     }
 
+    #[test]
+    fn idempotency_key() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            {
+                Person {
+                    name : String ,
+                    age : Integer nullable,
+                }
+            }",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate insert_person @id("client-uuid") {
+                Person {
+                    name : $name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect("valid idempotency key");
+        assert_eq!("insert_person", mutation.name);
+        match mutation.idempotency_key {
+            Some(MutationFieldValue::Value(ParamValue::String(key))) => {
+                assert_eq!("client-uuid", key)
+            }
+            _ => unreachable!(),
+        }
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate @id($key) {
+                Person {
+                    name : $name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect("valid idempotency key");
+        assert!(matches!(
+            mutation.idempotency_key,
+            Some(MutationFieldValue::Variable(_))
+        ));
+
+        let _mutation = MutationParser::parse(
+            r#"
+            mutate @id("") {
+                Person {
+                    name : $name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("empty key");
+
+        let _mutation = MutationParser::parse(
+            r#"
+            mutate @id($age) {
+                Person {
+                    name : $name
+                    age : $age
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("the key variable conflicts with an Integer field");
+    }
+
     #[test]
     fn duplicated_field() {
         let mut data_model = DataModel::new();
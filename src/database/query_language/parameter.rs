@@ -1,6 +1,10 @@
 use std::collections::HashMap;
 
-use crate::security::base64_decode;
+use crate::{
+    date_utils::{parse_date, parse_datetime, truncate_datetime, TimeUnit},
+    geo_utils::parse_location,
+    security::base64_decode,
+};
 
 use super::{Error, ParamValue, VariableType};
 
@@ -111,6 +115,55 @@ impl Variables {
                         params.params.insert(var_name, p);
                     }
 
+                    VariableType::DateTime(nullable) | VariableType::Date(nullable) => {
+                        //RFC3339 dates are converted to milliseconds since unix epoch
+                        let date_param = match &p {
+                            ParamValue::Integer(i) => {
+                                ParamValue::Integer(date_number(&var.1.var_type, *i))
+                            }
+                            ParamValue::String(s) => {
+                                ParamValue::Integer(parse_date_param(&var.1.var_type, s)?)
+                            }
+                            ParamValue::Null => {
+                                if !nullable {
+                                    return Err(Error::NotNullable(var.0.to_string()));
+                                }
+                                p
+                            }
+                            _ => {
+                                return Err(Error::ConflictingParameterType(
+                                    var.0.to_string(),
+                                    var.1.var_type.to_string(),
+                                    format!("{:#?}", p),
+                                ));
+                            }
+                        };
+                        params.params.insert(var_name, date_param);
+                    }
+
+                    VariableType::Geo(nullable) => {
+                        match &p {
+                            ParamValue::String(s) => {
+                                if parse_location(s).is_none() {
+                                    return Err(Error::InvalidLocation(s.clone()));
+                                }
+                            }
+                            ParamValue::Null => {
+                                if !nullable {
+                                    return Err(Error::NotNullable(var.0.to_string()));
+                                }
+                            }
+                            _ => {
+                                return Err(Error::ConflictingParameterType(
+                                    var.0.to_string(),
+                                    "Geo".to_string(),
+                                    format!("{:#?}", p),
+                                ));
+                            }
+                        }
+                        params.params.insert(var_name, p);
+                    }
+
                     VariableType::Float(nullable) => {
                         match p {
                             ParamValue::Float(_) => {}
@@ -214,6 +267,28 @@ impl Variables {
                         params.params.insert(var_name, p);
                     }
 
+                    VariableType::Array(ref value_type) => {
+                        let array_param = match p {
+                            ParamValue::Array(values) => {
+                                let mut array = Vec::with_capacity(values.len());
+                                for value in values {
+                                    array.push(Self::validate_array_value(
+                                        &var_name, value_type, value,
+                                    )?);
+                                }
+                                ParamValue::Array(array)
+                            }
+                            _ => {
+                                return Err(Error::ConflictingParameterType(
+                                    var.0.to_string(),
+                                    "Array".to_string(),
+                                    format!("{:#?}", p),
+                                ));
+                            }
+                        };
+                        params.params.insert(var_name, array_param);
+                    }
+
                     VariableType::Invalid => {
                         params.params.insert(var_name, p);
                     }
@@ -224,6 +299,68 @@ impl Variables {
         }
         Ok(())
     }
+
+    //
+    // array values cannot be null
+    //
+    fn validate_array_value(
+        name: &str,
+        value_type: &VariableType,
+        value: ParamValue,
+    ) -> Result<ParamValue, Error> {
+        match (value_type, &value) {
+            (VariableType::DateTime(_) | VariableType::Date(_), ParamValue::String(s)) => {
+                return Ok(ParamValue::Integer(parse_date_param(value_type, s)?));
+            }
+            (VariableType::DateTime(_) | VariableType::Date(_), ParamValue::Integer(i)) => {
+                return Ok(ParamValue::Integer(date_number(value_type, *i)));
+            }
+            _ => {}
+        }
+        let valid = match (value_type, &value) {
+            (VariableType::Boolean(_), ParamValue::Boolean(_)) => true,
+            (VariableType::Integer(_), ParamValue::Integer(_)) => true,
+            (VariableType::Float(_), ParamValue::Float(_) | ParamValue::Integer(_)) => true,
+            (VariableType::String(_) | VariableType::Json(_), ParamValue::String(_)) => true,
+            (
+                VariableType::Base64(_) | VariableType::Binary(_),
+                ParamValue::String(e) | ParamValue::Binary(e),
+            ) => {
+                if base64_decode(e.as_bytes()).is_err() {
+                    return Err(Error::InvalidBase64(e.clone()));
+                }
+                true
+            }
+            _ => false,
+        };
+        if !valid {
+            return Err(Error::ConflictingParameterType(
+                name.to_string(),
+                value_type.to_string(),
+                format!("{:#?}", value),
+            ));
+        }
+        Ok(value)
+    }
+}
+
+//
+// Date parameters are stored at the start of the UTC day
+//
+fn date_number(value_type: &VariableType, value: i64) -> i64 {
+    match value_type {
+        VariableType::Date(_) => truncate_datetime(value, TimeUnit::Day),
+        _ => value,
+    }
+}
+
+fn parse_date_param(value_type: &VariableType, value: &str) -> Result<i64, Error> {
+    match value_type {
+        VariableType::Date(_) => {
+            parse_date(value).ok_or_else(|| Error::InvalidDate(value.to_string()))
+        }
+        _ => parse_datetime(value).ok_or_else(|| Error::InvalidDateTime(value.to_string())),
+    }
 }
 
 ///
@@ -245,7 +382,7 @@ impl Variables {
 /// let mut param = Parameters::new();
 /// param.add("name", "Alice")?;
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Parameters {
     pub params: HashMap<String, ParamValue>,
 }
@@ -349,6 +486,12 @@ impl ParametersAdd<String> for Parameters {
     }
 }
 
+impl ParametersAdd<&str> for Parameters {
+    fn add(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        self.add(key, value.to_string())
+    }
+}
+
 impl ParametersAdd<Option<String>> for Parameters {
     fn add(&mut self, key: &str, value: Option<String>) -> Result<(), Error> {
         self.exists_err(key)?;
@@ -366,6 +509,46 @@ impl ParametersAdd<Option<String>> for Parameters {
     }
 }
 
+impl ParametersAdd<Vec<String>> for Parameters {
+    fn add(&mut self, key: &str, value: Vec<String>) -> Result<(), Error> {
+        self.exists_err(key)?;
+        let array = value.into_iter().map(ParamValue::String).collect();
+        self.params
+            .insert(String::from(key), ParamValue::Array(array));
+        Ok(())
+    }
+}
+
+impl ParametersAdd<Vec<i64>> for Parameters {
+    fn add(&mut self, key: &str, value: Vec<i64>) -> Result<(), Error> {
+        self.exists_err(key)?;
+        let array = value.into_iter().map(ParamValue::Integer).collect();
+        self.params
+            .insert(String::from(key), ParamValue::Array(array));
+        Ok(())
+    }
+}
+
+impl ParametersAdd<Vec<f64>> for Parameters {
+    fn add(&mut self, key: &str, value: Vec<f64>) -> Result<(), Error> {
+        self.exists_err(key)?;
+        let array = value.into_iter().map(ParamValue::Float).collect();
+        self.params
+            .insert(String::from(key), ParamValue::Array(array));
+        Ok(())
+    }
+}
+
+impl ParametersAdd<Vec<bool>> for Parameters {
+    fn add(&mut self, key: &str, value: Vec<bool>) -> Result<(), Error> {
+        self.exists_err(key)?;
+        let array = value.into_iter().map(ParamValue::Boolean).collect();
+        self.params
+            .insert(String::from(key), ParamValue::Array(array));
+        Ok(())
+    }
+}
+
 impl Parameters {
     pub fn new() -> Self {
         Self {
@@ -383,6 +566,20 @@ impl Parameters {
         Ok(())
     }
 
+    ///
+    /// Chainable version of *add*
+    /// ```ignore
+    /// let params = Parameters::new().with("name", "John")?.with("age", 42)?;
+    /// ```
+    ///
+    pub fn with<T>(mut self, key: &str, value: T) -> Result<Self, Error>
+    where
+        Self: ParametersAdd<T>,
+    {
+        self.add(key, value)?;
+        Ok(self)
+    }
+
     pub fn add_null(&mut self, key: &str) -> Result<(), Error> {
         self.exists_err(key)?;
         self.params.insert(String::from(key), ParamValue::Null);
@@ -420,7 +617,30 @@ impl Parameters {
                 Value::String(str) => {
                     param.add(key, str.to_string())?;
                 }
-                Value::Array(_) => return Err(Error::InvalidJsonParamField(key.to_string())),
+                Value::Array(values) => {
+                    let mut array = Vec::with_capacity(values.len());
+                    for value in values {
+                        let value = match value {
+                            Value::Bool(bool) => ParamValue::Boolean(*bool),
+                            Value::Number(number) => {
+                                if let Some(num) = number.as_i64() {
+                                    ParamValue::Integer(num)
+                                } else if let Some(num) = number.as_f64() {
+                                    ParamValue::Float(num)
+                                } else {
+                                    return Err(Error::InvalidJsonParamField(key.to_string()));
+                                }
+                            }
+                            Value::String(str) => ParamValue::String(str.to_string()),
+                            _ => return Err(Error::InvalidJsonParamField(key.to_string())),
+                        };
+                        array.push(value);
+                    }
+                    param.exists_err(key)?;
+                    param
+                        .params
+                        .insert(key.to_string(), ParamValue::Array(array));
+                }
                 Value::Object(_) => return Err(Error::InvalidJsonParamField(key.to_string())),
             }
         }
@@ -471,6 +691,24 @@ mod tests {
             .expect_err("It is forbidden to insert duplicate names");
     }
 
+    #[test]
+    fn params_builder() {
+        let param = Parameters::new()
+            .with("name", "John")
+            .unwrap()
+            .with("age", 42)
+            .unwrap()
+            .with("nickname", None::<String>)
+            .unwrap();
+        assert_eq!(3, param.params.len());
+
+        Parameters::new()
+            .with("name", "John")
+            .unwrap()
+            .with("name", "Doe")
+            .expect_err("duplicated parameter");
+    }
+
     #[test]
     fn variables_validate_bool_type() {
         let name = "bool";
@@ -700,4 +938,54 @@ mod tests {
         param.add(name, "[0,1,2]".to_string()).unwrap();
         vars.validate_params(&mut param).expect("valid json");
     }
+
+    #[test]
+    fn variables_validate_array_type() {
+        let name = "array";
+
+        let mut vars = Variables::new();
+        vars.add(
+            name,
+            VariableType::Array(Box::new(VariableType::Binary(false))),
+        )
+        .unwrap();
+
+        let mut param = Parameters::new();
+        param
+            .add(name, "zSRIyMbf70V999wyC0KlhQ".to_string())
+            .unwrap();
+        vars.validate_params(&mut param)
+            .expect_err("param is not an array");
+
+        param = Parameters::new();
+        param.add_null(name).unwrap();
+        vars.validate_params(&mut param)
+            .expect_err("param cannot be null");
+
+        param = Parameters::new();
+        param
+            .add(
+                name,
+                vec!["zSRIyMbf70V999wyC0KlhQ".to_string(), "@@@".to_string()],
+            )
+            .unwrap();
+        vars.validate_params(&mut param)
+            .expect_err("not a valid base64");
+
+        param = Parameters::new();
+        param
+            .add(name, vec!["zSRIyMbf70V999wyC0KlhQ".to_string()])
+            .unwrap();
+        vars.validate_params(&mut param).expect("valid array");
+
+        param = Parameters::from_json(r#"{"array":["zSRIyMbf70V999wyC0KlhQ", 12]}"#).unwrap();
+        vars.validate_params(&mut param)
+            .expect_err("array contains an integer");
+
+        param = Parameters::from_json(r#"{"array":["zSRIyMbf70V999wyC0KlhQ"]}"#).unwrap();
+        vars.validate_params(&mut param).expect("valid array");
+
+        Parameters::from_json(r#"{"array":[["zSRIyMbf70V999wyC0KlhQ"]]}"#)
+            .expect_err("nested arrays are not supported");
+    }
 }
@@ -1,11 +1,11 @@
 use std::collections::HashSet;
 
-use crate::{security::base64_decode, database::query_language::VariableType};
+use crate::{security::base64_decode, database::{custom_function, query_language::VariableType, system_entities::{ALL_ENTITIES, CREATION_DATE_FIELD, MODIFICATION_DATE_FIELD, ROOM_ID_FIELD, SYSTEM_NAMESPACE}}};
 
 use super::{
     data_model_parser::{DataModel, Entity, Field},
     parameter::Variables,
-    Error, FieldType, FieldValue, ParamValue,
+    Error, FieldType, FieldValue, ParamValue, ParsingLimits,
 };
 
 use pest::{iterators::{Pair, Pairs}, Parser};
@@ -15,15 +15,50 @@ use pest_derive::Parser;
 #[grammar = "database/query_language/query.pest"]
 struct PestParser;
 
+lazy_static::lazy_static! {
+    //the '_all' entity only provides the system fields
+    static ref ALL_ENTITY: Entity = {
+        let mut entity = Entity::new();
+        entity.name = ALL_ENTITIES.to_string();
+        entity
+    };
+}
+
+//
+// entities that can be queried: the data model entities and the '_all' virtual entity
+// '_all' returns the changes of every entity of a room: _all(room_id=$room_id, after($date)) { id _entity mdate }
+//
+fn query_entity<'a>(data_model: &'a DataModel, name: &str) -> Result<&'a Entity, Error> {
+    if name.eq(ALL_ENTITIES) {
+        Ok(&ALL_ENTITY)
+    } else {
+        data_model.get_entity(name)
+    }
+}
+
+//
+// cdate and mdate are Integer system fields containing dates
+//
+fn is_date_system_field(field: &Field) -> bool {
+    field.is_system && (field.name.eq(CREATION_DATE_FIELD) || field.name.eq(MODIFICATION_DATE_FIELD))
+}
+
 
 #[derive(Debug)]
 pub enum QueryFieldType {
     Aggregate(Function),
+    Custom(CustomFunction),
     Binary,
     EntityArrayQuery(Box<EntityQuery>, bool), 
     EntityQuery(Box<EntityQuery>,bool),
     Scalar,
-    Json
+    Json,
+    //excerpt of a String field around the searched term, with the requested length
+    Snippet(usize),
+    //UTC formatted date, the flag is true for Date fields
+    FormattedDate(bool),
+    //arithmetic expression computed from the numeric fields
+    Computed(Vec<ExpressionToken>)
 }
 
 #[derive(Debug)]
@@ -51,15 +86,60 @@ pub enum Function {
     Sum(String),
 }
 
+///
+/// call to a function registered by the application with *register_function()*
+///
+#[derive(Debug)]
+pub struct CustomFunction {
+    pub name: String,
+    pub args: Vec<FunctionArg>,
+}
+
+///
+/// element of a computed field expression like 'total: price * quantity' or 'age_days: (now() - cdate) / 86400000'
+///
+/// the expression is copied in the SQL query, operator precedence and integer division follows the SQLite rules
+///
+#[derive(Debug)]
+pub enum ExpressionToken {
+    Field(Field),
+    Number(String),
+    //current date in milliseconds
+    Now,
+    Operator(String),
+    Open,
+    Close,
+}
+
+#[derive(Debug)]
+pub enum FunctionArg {
+    Field(Field),
+    Value(FieldValue),
+}
+
 #[derive(Debug)]
 pub struct EntityParams {
    pub filters: Vec<FilterParam>,
+   pub filter_groups: Vec<FilterGroup>,
    pub json_filters: Vec<JsonFilter>,
+   pub geo_filters: Vec<GeoFilter>,
+   pub date_filters: Vec<DateFilter>,
+   pub exists_filters: Vec<ExistsFilter>,
    pub aggregate_filters: Vec<FilterParam>,
    pub fulltext_search: Option<FieldValue>,
    pub before: Vec<FieldValue>,
    pub after: Vec<FieldValue>,
+   //variable containing the cursor returned by the previous page
+   pub cursor: Option<String>,
+   //identifier of the original node whose edit chain is queried
+   pub versions: Option<FieldValue>,
+   //include_deleted: the soft deleted nodes are not excluded from the results
+   pub include_deleted: bool,
    pub order_by: Vec<OrderBy>,
+   //group_by(): the aggregate functions are computed for each distinct value of these fields instead of the selected fields
+   pub group_by: Vec<Field>,
+   //order_by(rank): the search rank is the first sort criteria, followed by the order_by fields
+   pub order_by_rank: bool,
    pub first: FieldValue,
    pub skip: Option<FieldValue>,
    pub nullable : HashSet<String>
@@ -73,13 +153,22 @@ impl EntityParams {
     pub fn new() -> Self {
         Self {
             filters: Vec::new(),
+            filter_groups: Vec::new(),
             json_filters:Vec::new(),
+            geo_filters:Vec::new(),
+            date_filters:Vec::new(),
+            exists_filters:Vec::new(),
             aggregate_filters: Vec::new(),
             fulltext_search: None,
             before: Vec::new(),
             after: Vec::new(),
+            cursor: None,
+            versions: None,
+            include_deleted: false,
             first: FieldValue::Value(ParamValue::Integer(0)),
             order_by: Vec::new(),
+            group_by: Vec::new(),
+            order_by_rank: false,
             skip: None,
             nullable: HashSet::new()
         }
@@ -104,6 +193,46 @@ pub struct FilterParam {
     pub field: Field
 }
 
+///
+/// boolean combination of filters:
+///     Person(or(name = "a", name = "b"), age > 10)
+///     Person(not(name = "a", age > 10))
+///     Person(or(name = "a", and(name = "b", age > 10)))
+///
+/// the filters inside and() and not() are combined with AND, not() negates the combination
+///
+#[derive(Debug)]
+pub enum FilterGroup {
+    Filter(Box<FilterParam>),
+    And(Vec<FilterGroup>),
+    Or(Vec<FilterGroup>),
+    Not(Vec<FilterGroup>),
+}
+impl FilterGroup {
+    ///
+    /// the filters of the group and of its nested groups
+    ///
+    pub fn filters(&self) -> Vec<&FilterParam> {
+        match self {
+            FilterGroup::Filter(filter) => vec![filter],
+            FilterGroup::And(members) | FilterGroup::Or(members) | FilterGroup::Not(members) => {
+                members.iter().flat_map(|member| member.filters()).collect()
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ParsedFilterGroup {
+    Filter(ParsedFilter),
+    And(Vec<ParsedFilterGroup>),
+    Or(Vec<ParsedFilterGroup>),
+    Not(Vec<ParsedFilterGroup>),
+}
+
+//parameters of an entity with the filters and order_by that are built once the entity fields are known
+type ParsedParams = (EntityParams, Vec<ParsedFilter>, Vec<ParsedOrderBy>, Vec<ParsedFilterGroup>);
+
 #[derive(Debug)]
 pub struct JsonFilter {
     pub selector: String, 
@@ -112,11 +241,56 @@ pub struct JsonFilter {
     pub field: Field
 }
 
+///
+/// filters the locations of a Geo field:
+///     bbox(location, $min_lat, $min_lon, $max_lat, $max_lon)
+///     radius(location, $lat, $lon, $meters)
+///
+/// index is the name of the R*Tree table when the field is indexed
+///
+#[derive(Debug)]
+pub struct GeoFilter {
+    pub field: Field,
+    pub area: GeoArea,
+    pub index: Option<String>
+}
+
+#[derive(Debug)]
+pub enum GeoArea {
+    BoundingBox(Vec<FieldValue>),
+    Radius(Vec<FieldValue>),
+}
+
+///
+/// filters the dates relative to the current time:
+///     within_days(start, 7) the last 7 days
+///     within_days(start, -7) the next 7 days
+///
+/// Date fields are compared to the start of the current UTC day
+///
+#[derive(Debug)]
+pub struct DateFilter {
+    pub field: Field,
+    pub days: FieldValue,
+}
+
+///
+/// filters on the presence of related entities without selecting them:
+///     Person(exists(parents(name = "John")))
+///     Person(not exists(pet))
+///
+#[derive(Debug)]
+pub struct ExistsFilter {
+    pub negated: bool,
+    pub field: Field,
+    pub entity: EntityQuery
+}
 
 #[derive(Debug)]
 pub struct ParsedOrderBy{
     pub name: String, 
-    pub direction: Direction
+    pub direction: Direction,
+    pub json_selector: Option<String>
 }
 
 #[derive(Debug)]
@@ -125,7 +299,104 @@ pub struct OrderBy {
     pub direction: Direction, 
    // pub is_aggregate: bool,
     pub is_selected: bool,
-    pub field: Field
+    pub field: Field,
+    //order_by(data->$.priority desc): sorted by a path of the Json field
+    pub json_selector: Option<String>
+}
+
+///
+/// Key case conversion applied to the field names and aliases of a query result
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum KeyCase {
+    #[default]
+    Unchanged,
+    Camel,
+    Snake,
+}
+
+///
+/// Controls the shape of the JSON returned by a query.
+/// Enabled with directives placed after the query name:
+///     query sample @flatten @entity_names @camel_case @ordered { ... }
+///
+/// - @flatten: arrays containing a single element are replaced by the element
+/// - @entity_names: every object contains an '_entity' field with the entity name
+/// - @camel_case or @snake_case: converts the result keys
+/// - @ordered: result keys are sorted alphabetically
+///
+#[derive(Debug, Default, Clone)]
+pub struct ResultShape {
+    pub flatten: bool,
+    pub entity_names: bool,
+    pub key_case: KeyCase,
+    pub ordered: bool,
+}
+impl ResultShape {
+    ///
+    /// returns the result key for a field name or alias
+    ///
+    pub fn key(&self, name: &str) -> String {
+        match self.key_case {
+            KeyCase::Unchanged => name.to_string(),
+            KeyCase::Camel => {
+                let mut key = String::new();
+                let mut upper = false;
+                for c in name.chars() {
+                    if c == '_' {
+                        upper = !key.is_empty();
+                    } else if upper {
+                        key.extend(c.to_uppercase());
+                        upper = false;
+                    } else {
+                        key.push(c);
+                    }
+                }
+                key
+            }
+            KeyCase::Snake => {
+                let mut key = String::new();
+                for c in name.chars() {
+                    if c.is_uppercase() {
+                        if !key.is_empty() && !key.ends_with('_') {
+                            key.push('_');
+                        }
+                        key.extend(c.to_lowercase());
+                    } else {
+                        key.push(c);
+                    }
+                }
+                key
+            }
+        }
+    }
+
+    fn set_directive(&mut self, directive: &str) -> Result<(), Error> {
+        match directive {
+            "flatten" => self.flatten = true,
+            "entity_names" => self.entity_names = true,
+            "ordered" => self.ordered = true,
+            "camel_case" | "snake_case" => {
+                if self.key_case != KeyCase::Unchanged {
+                    return Err(Error::InvalidQuery(String::from(
+                        "@camel_case and @snake_case cannot be used together",
+                    )));
+                }
+                self.key_case = if directive.eq("camel_case") {
+                    KeyCase::Camel
+                } else {
+                    KeyCase::Snake
+                };
+            }
+            _ => {
+                return Err(Error::InvalidQuery(format!(
+                    "Unknown query directive '@{}'",
+                    directive
+                )))
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -144,6 +415,12 @@ pub struct EntityQuery {
     pub is_aggregate: bool,
     pub params: EntityParams,
     pub fields: Vec<QueryField>,
+    //short name and name of the entities queried by '_all'
+    pub wildcard: Vec<(String, String)>,
+    //short name of the edit chain field of top level entities
+    pub edit_chain: Option<String>,
+    //short name of the queried entities defining soft_delete
+    pub soft_delete: Vec<String>,
 }
 impl Default for EntityQuery{
     fn default() -> Self {
@@ -161,9 +438,16 @@ impl EntityQuery {
             is_aggregate:false,
             params: EntityParams::new(),
             fields: Vec::new(),
+            wildcard: Vec::new(),
+            edit_chain: None,
+            soft_delete: Vec::new(),
         }
     }
 
+    pub fn is_wildcard(&self) -> bool {
+        self.name.eq(ALL_ENTITIES)
+    }
+
     #[allow(clippy::map_entry)]
     pub fn add_field(&mut self, field:QueryField) -> Result<(),Error> {
         let key =field.name();
@@ -188,15 +472,60 @@ impl EntityQuery {
         self.alias.clone().unwrap_or(self.name.clone())
     }
 
+    ///
+    /// true if one of the sub entities defines a cursor
+    ///
+    pub fn has_sub_cursor(&self) -> bool{
+        self.fields.iter().any(|field| match &field.field_type {
+            QueryFieldType::EntityQuery(sub, _) | QueryFieldType::EntityArrayQuery(sub, _) => {
+                sub.params.cursor.is_some() || sub.has_sub_cursor()
+            }
+            _ => false,
+        })
+    }
+
+    ///
+    /// adds the name of the queried entity and of its sub entities
+    ///
+    pub fn collect_entities(&self, entities: &mut HashSet<String>){
+        if self.is_wildcard() {
+            entities.extend(self.wildcard.iter().map(|(_, name)| name.clone()));
+        } else {
+            entities.insert(self.name.clone());
+        }
+        for field in &self.fields {
+            if let QueryFieldType::EntityQuery(sub, _) | QueryFieldType::EntityArrayQuery(sub, _) = &field.field_type {
+                sub.collect_entities(entities);
+            }
+        }
+    }
+
     pub fn finalize(&self, variables: &mut Variables) -> Result<(), Error>{
         let par =&self.params;
 
-        if par.fulltext_search.is_some() & !par.order_by.is_empty(){
+        if par.fulltext_search.is_some() & !par.order_by.is_empty() & !par.order_by_rank{
             return Err(Error::InvalidQuery(String::from(
-                "Cannot add sort field when using search(). Results will be sorted by search rank '"
+                "Cannot add sort field when using search(). Results will be sorted by search rank, use order_by(rank, ...) to sort the results with the same rank"
+            )))
+        }
+
+        if par.fulltext_search.is_none() & par.order_by_rank{
+            return Err(Error::InvalidQuery(format!(
+                "order_by(rank) requires search() in query '{}'",
+                self.aliased_name()
             )))
         }
 
+        if par.fulltext_search.is_none(){
+            if let Some(field) = self.fields.iter().find(|f| matches!(f.field_type, QueryFieldType::Snippet(_))){
+                return Err(Error::InvalidQuery(format!(
+                    "snippet() requires search(), field '{}' in query '{}'",
+                    field.name(),
+                    self.aliased_name()
+                )))
+            }
+        }
+
         if !par.after.is_empty() && !par.before.is_empty(){
             return Err(Error::InvalidQuery(format!(
                 "'after' and 'before' filters cannot be used at the same time in query '{}'",
@@ -226,6 +555,11 @@ impl EntityQuery {
             for (i, val) in paging.iter().enumerate(){
                 let order_field = &par.order_by[i];
 
+                //the type of a json path is unknown, like the json filters any scalar value is accepted
+                if order_field.json_selector.is_some() {
+                    continue;
+                }
+
                 let field_type = &order_field.field.field_type;
                 match val{
                     FieldValue::Variable(var) => {
@@ -244,7 +578,7 @@ impl EntityQuery {
                         
                         ParamValue::Integer(_) => {
                             match field_type{
-                                FieldType::Integer => {},
+                                FieldType::Integer | FieldType::DateTime | FieldType::Date => {},
                                 FieldType::Float => {},
                                 _ => { return Err(Error::InvalidPagingValue(i, String::from("Integer")))},
                             }
@@ -258,9 +592,14 @@ impl EntityQuery {
                         ParamValue::String(s) => {
                             match field_type{
                                 FieldType::String => {},
-                                FieldType::Base64 => {
+                                FieldType::Base64 | FieldType::File => {
                                     validate_base64(s, &format!( "'after' or 'before' field position {} ",i))?;
                                 },
+                                FieldType::Enum(_) => {
+                                    if field_type.enum_position(s).is_none(){
+                                        return Err(Error::InvalidEnumValue(s.clone(), order_field.name.clone()));
+                                    }
+                                },
                                 _ => { return Err(Error::InvalidPagingValue(i, String::from("String")))},
                             }
                         }
@@ -269,6 +608,49 @@ impl EntityQuery {
                 }
             }
         }
+        if par.include_deleted && self.soft_delete.is_empty(){
+            return Err(Error::InvalidQuery(format!(
+                "'include_deleted' can only be used by entities defining soft_delete in query '{}'",
+                self.aliased_name()
+            )))
+        }
+        if par.versions.is_some() && self.edit_chain.is_none(){
+            return Err(Error::InvalidQuery(format!(
+                "'versions' can only be used by the top level entities defining an edit_chain in query '{}'",
+                self.aliased_name()
+            )))
+        }
+
+        if let Some(cursor) = &par.cursor {
+            if !paging.is_empty(){
+                return Err(Error::InvalidQuery(format!(
+                    "'cursor' cannot be used with 'after' or 'before' in query '{}'",
+                    self.aliased_name()
+                )))
+            }
+            if par.fulltext_search.is_some() || self.is_aggregate {
+                return Err(Error::InvalidQuery(format!(
+                    "'cursor' is not compatible with search() and aggregate functions in query '{}'",
+                    self.aliased_name()
+                )))
+            }
+            if par.order_by.is_empty(){
+                return Err(Error::InvalidQuery(format!(
+                    "'cursor' requires an Order By clause in query '{}'",
+                    self.aliased_name()
+                )))
+            }
+            //the cursor is built from the stored values of the last returned row
+            if let Some(ord) = par.order_by.iter().find(|ord| ord.is_selected){
+                return Err(Error::InvalidQuery(format!(
+                    "'cursor' cannot be used when ordering by the alias '{}' in query '{}'",
+                    ord.name,
+                    self.aliased_name()
+                )))
+            }
+            variables.add(cursor, VariableType::String(true))?;
+        }
+
         let mut has_entity_field = false;
         let mut has_aggregate_function = false;
 
@@ -281,7 +663,7 @@ impl EntityQuery {
                 QueryFieldType::Aggregate(_)=>{
                     has_aggregate_function = true;
                 }
-                QueryFieldType::Scalar| QueryFieldType::Binary | QueryFieldType::Json=>{}
+                QueryFieldType::Scalar| QueryFieldType::Binary | QueryFieldType::Json | QueryFieldType::Custom(_) | QueryFieldType::Snippet(_) | QueryFieldType::FormattedDate(_) | QueryFieldType::Computed(_)=>{}
             }
         }
         
@@ -300,6 +682,7 @@ pub struct QueryParser {
     pub name: String,
     pub variables: Variables,
     pub queries: Vec<EntityQuery>,
+    pub shape: ResultShape,
 }
 impl Default for QueryParser{
     fn default() -> Self {
@@ -312,10 +695,28 @@ impl QueryParser {
             name: "".to_string(),
             variables: Variables::new(),
             queries: Vec::new(),
+            shape: ResultShape::default(),
         }
     }
 
+    ///
+    /// name of the entities read by the query
+    ///
+    pub fn entities(&self) -> HashSet<String> {
+        let mut entities = HashSet::new();
+        for query in &self.queries {
+            query.collect_entities(&mut entities);
+        }
+        entities
+    }
+
+    #[cfg(test)]
     pub fn parse(p: &str, data_model: &DataModel) -> Result<Self, Error> {
+        Self::parse_with_limits(p, data_model, &ParsingLimits::default())
+    }
+
+    pub fn parse_with_limits(p: &str, data_model: &DataModel, limits: &ParsingLimits) -> Result<Self, Error> {
+        limits.check(p)?;
         let mut query = QueryParser::new();
 
         let parse = match PestParser::parse(Rule::query, p) {
@@ -332,8 +733,15 @@ impl QueryParser {
             let mut query_pairs = parse.into_inner();
 
             let query_name = query_pairs.next().unwrap();
-            if let Some(name) = query_name.into_inner().next(){
-                query.name = name.as_str().to_string();
+            for name_pair in query_name.into_inner(){
+                match name_pair.as_rule() {
+                    Rule::identifier => query.name = name_pair.as_str().to_string(),
+                    Rule::directive => {
+                        let directive = name_pair.into_inner().next().unwrap();
+                        query.shape.set_directive(directive.as_str())?;
+                    }
+                    _ => unreachable!(),
+                }
             }
           
             //query.name = query_pairs.next().unwrap().as_str().to_string();
@@ -353,6 +761,13 @@ impl QueryParser {
                             }
                         }
 
+                        if ent.has_sub_cursor(){
+                            return Err(Error::InvalidQuery(format!(
+                                "'cursor' can only be used by the top level entities of the query '{}'",
+                                ent.aliased_name()
+                            )))
+                        }
+
                         let alias = ent.aliased_name();
                         let exists = query.queries.iter().any(|x| x.aliased_name().eq(&alias)); 
                         if exists {
@@ -380,17 +795,29 @@ impl QueryParser {
         variables: &mut Variables,
     ) -> Result<(), Error> {
         let depth = entity.depth;
-        let entity_model = data_model.get_entity(&entity.name)?;
+        let entity_model = query_entity(data_model, &entity.name)?;
+        if depth == 0 {
+            entity.edit_chain = entity_model
+                .edit_chain
+                .as_ref()
+                .and_then(|name| entity_model.fields.get(name))
+                .map(|field| field.short_name.clone());
+        }
+        if entity_model.soft_delete {
+            entity.soft_delete.push(entity_model.short_name.clone());
+        }
         let mut parsed_filters = None;
+        let mut parsed_groups = None;
         let mut parsed_order_by = None;
         let mut parameters = EntityParams::new();
         for entity_pair in pairs {
             match entity_pair.as_rule() {
                 Rule::entity_param => {
-                    let params = Self::parse_params( entity_pair,entity_model, variables)?;
+                    let params = Self::parse_params( entity_pair, data_model, entity_model, variables)?;
                     parameters = params.0;
                     parsed_filters = Some(params.1);
-                    parsed_order_by = Some(params.2)
+                    parsed_order_by = Some(params.2);
+                    parsed_groups = Some(params.3);
                 }
 
                 Rule::field => {
@@ -428,7 +855,7 @@ impl QueryParser {
                                         &name
                                     )))
                                 }
-                                FieldType::Base64 => QueryFieldType::Binary,
+                                FieldType::Base64 | FieldType::File => QueryFieldType::Binary,
                                 
                                 _=>QueryFieldType::Scalar  
                             };
@@ -511,6 +938,26 @@ impl QueryParser {
                             let query_field =  Self::parse_functions(entity, data_model,field_pair)?;
                             entity.add_field(query_field)?;
                         }
+                        Rule::computed_field => {
+                            let mut computed_pairs = field_pair.into_inner();
+                            let name = computed_pairs.next().unwrap().as_str().to_string();
+                            let mut tokens = Vec::new();
+                            Self::parse_expression(&name, entity_model, computed_pairs.next().unwrap(), &mut tokens)?;
+                            //the result is filtered and ordered as a nullable Float
+                            let field = Field {
+                                name : name.clone(),
+                                is_system: false,
+                                field_type: FieldType::Float,
+                                nullable: true,
+                                ..Default::default()
+                            };
+                            entity.add_field(QueryField{
+                                field,
+                                alias:Some(name),
+                                json_selector: None,
+                                field_type: QueryFieldType::Computed(tokens)
+                            })?;
+                        }
                         Rule::json_field => {
                             let mut json_pair = field_pair.into_inner();
                             let alias = json_pair.next().unwrap().as_str().to_string();
@@ -562,6 +1009,13 @@ impl QueryParser {
                 
             }
         }
+
+        if let Some(groups) = parsed_groups{
+            for parse in groups{
+                let group = Self::build_filter_group(entity, entity_model, variables, parse)?;
+                parameters.filter_groups.push(group);
+            }
+        }
     
 
         if let Some(order_by) = parsed_order_by{
@@ -570,7 +1024,46 @@ impl QueryParser {
                 parameters.order_by.push(ord);
             }
         }
+
+        if entity.is_wildcard() {
+            if !parameters.filters.iter().any(|f| f.name.eq(ROOM_ID_FIELD) && f.operation.eq("=")){
+                return Err(Error::InvalidQuery(format!(
+                    "'{}' requires a '{}' filter",
+                    ALL_ENTITIES, ROOM_ID_FIELD
+                )))
+            }
+            //ordered by modification date, allowing after($date) to fetch the changes made since a date
+            if parameters.order_by.is_empty() && parameters.fulltext_search.is_none() {
+                let ord = Self::build_order_by(entity, entity_model, ParsedOrderBy{ 
+                    name: MODIFICATION_DATE_FIELD.to_string(), 
+                    direction: Direction::Asc,
+                    json_selector: None
+                })?;
+                parameters.order_by.push(ord);
+            }
+        }
         
+        if !parameters.group_by.is_empty() {
+            if !entity.is_aggregate {
+                return Err(Error::InvalidQuery(format!(
+                    "group_by() requires aggregate functions in the selection of '{}'",
+                    entity.aliased_name()
+                )))
+            }
+            //the selected fields must have a single value in each group
+            for field in &entity.fields {
+                if let QueryFieldType::Scalar = field.field_type {
+                    if !parameters.group_by.iter().any(|f| f.name.eq(&field.field.name)) {
+                        return Err(Error::InvalidQuery(format!(
+                            "selected field '{}' must be listed in the group_by() of '{}'",
+                            field.name(),
+                            entity.aliased_name()
+                        )))
+                    }
+                }
+            }
+        }
+
         for nullable_field in &parameters.nullable{
             match entity.fields.iter().find(|f| f.name().eq(nullable_field)){
                 Some(field) => {
@@ -585,6 +1078,10 @@ impl QueryParser {
             }
         }
 
+        for exists in &parameters.exists_filters{
+            entity.complexity += exists.entity.complexity + 1;
+        }
+
         entity.params = parameters;
         
         entity.finalize(variables)?;
@@ -604,7 +1101,7 @@ impl QueryParser {
         
         let function_pair =  function_pairs.next().unwrap().into_inner().next().unwrap();
 
-        let model_entity = data_model.get_entity(&entity.name)?;
+        let model_entity = query_entity(data_model, &entity.name)?;
         
         let query_field =  match function_pair.as_rule() {
             Rule::count_fn => {
@@ -652,7 +1149,7 @@ impl QueryParser {
                 let param = function_pair.into_inner().next().unwrap().as_str();
                 let model_field = model_entity.get_field(param)?;
                 match model_field.field_type{
-                    FieldType::Array(_) | FieldType::Entity(_) => {
+                    FieldType::Array(_) | FieldType::Entity(_) | FieldType::Enum(_) => {
                         return Err(Error::InvalidQuery(format!(
                             "max({}) requires a scalar field and '{}' is a '{}'",
                             &param, &param, model_field.field_type
@@ -678,7 +1175,7 @@ impl QueryParser {
                 let param = function_pair.into_inner().next().unwrap().as_str();
                 let model_field = model_entity.get_field(param)?;
                 match model_field.field_type{
-                    FieldType::Array(_) | FieldType::Entity(_) => {   
+                    FieldType::Array(_) | FieldType::Entity(_) | FieldType::Enum(_) => {   
                         return Err(Error::InvalidQuery(format!(
                         "min({}) requires a scalar field and '{}' is a '{}'",
                         &param, &param, model_field.field_type
@@ -726,12 +1223,161 @@ impl QueryParser {
 
             }
 
-           
+            Rule::snippet_fn => {
+                let mut snippet_pairs = function_pair.into_inner();
+                let param = snippet_pairs.next().unwrap().as_str();
+                let model_field = model_entity.get_field(param)?;
+                if model_field.field_type != FieldType::String {
+                    return Err(Error::InvalidQuery(format!(
+                        "snippet({}) requires a string field and '{}' is a '{}'",
+                        &param, &param, model_field.field_type
+                    )))
+                }
+                let length_pair = snippet_pairs.find(|p| p.as_rule() == Rule::unsigned_int).unwrap();
+                let length = length_pair.as_str().parse::<usize>().map_err(|_| Error::InvalidQuery(format!(
+                    "invalid snippet() length '{}'", length_pair.as_str()
+                )))?;
+                let field = Field {
+                    name : model_field.name.clone(),
+                    short_name: model_field.short_name.clone(),
+                    is_system: false,
+                    field_type: FieldType::String,
+                    nullable: true,
+                    ..Default::default()
+                };
+                QueryField{
+                    field,
+                    alias:Some(name),
+                    json_selector: None,
+                    field_type: QueryFieldType::Snippet(length)
+                }
+            }
+
+            Rule::format_date_fn => {
+                let param = function_pair.into_inner().next().unwrap().as_str();
+                let model_field = model_entity.get_field(param)?;
+                let date_only = match model_field.field_type {
+                    FieldType::Date => true,
+                    FieldType::DateTime => false,
+                    _ if is_date_system_field(model_field) => false,
+                    _ => return Err(Error::InvalidQuery(format!(
+                        "format_date({}) requires a DateTime or Date field and '{}' is a '{}'",
+                        &param, &param, model_field.field_type
+                    )))
+                };
+                let field = Field {
+                    name : model_field.name.clone(),
+                    short_name: model_field.short_name.clone(),
+                    is_system: model_field.is_system,
+                    field_type: FieldType::String,
+                    nullable: true,
+                    default_value: model_field.default_value.clone(),
+                    ..Default::default()
+                };
+                QueryField{
+                    field,
+                    alias:Some(name),
+                    json_selector: None,
+                    field_type: QueryFieldType::FormattedDate(date_only)
+                }
+            }
+
+            Rule::custom_fn => {
+                let mut custom_pairs = function_pair.into_inner();
+                let function = custom_pairs.next().unwrap().as_str().to_string();
+                let arguments = match custom_function::function_arguments(&function){
+                    Some(arguments) => arguments,
+                    None => return Err(Error::UnknownFunction(function)),
+                };
+                let mut args = Vec::new();
+                for arg_pair in custom_pairs {
+                    match arg_pair.as_rule() {
+                        Rule::function_arg => {
+                            let value_pair = arg_pair.into_inner().next().unwrap();
+                            if value_pair.as_rule() == Rule::identifier {
+                                let param = value_pair.as_str();
+                                let model_field = model_entity.get_field(param)?;
+                                match model_field.field_type{
+                                    FieldType::Boolean | FieldType::Float | FieldType::Integer | FieldType::String => {}
+                                    _=> {
+                                        return Err(Error::InvalidQuery(format!(
+                                        "{}() requires boolean, integer, float or string fields and '{}' is a '{}'",
+                                        &function, &param, model_field.field_type
+                                    ))) }
+                                }
+                                args.push(FunctionArg::Field(model_field.clone()));
+                            } else {
+                                args.push(FunctionArg::Value(Self::parse_field_value(value_pair)?));
+                            }
+                        }
+                        Rule::comma => {}
+                        _=> unreachable!()
+                    }
+                }
+                if args.len() != arguments {
+                    return Err(Error::InvalidFunctionArguments(function, arguments, args.len()));
+                }
+                //the result is filtered and ordered as a nullable Float
+                let field = Field {
+                    name : name.clone(),
+                    is_system: false,
+                    field_type: FieldType::Float,
+                    nullable: true,
+                    ..Default::default()
+                };
+                QueryField{
+                    field,
+                    alias:Some(name),
+                    json_selector: None,
+                    field_type: QueryFieldType::Custom(CustomFunction{ name: function, args })
+                }
+            }
             _=> unreachable!()
         };
         Ok(query_field)
     }
 
+    fn parse_expression(
+        name: &str,
+        entity_model: &Entity,
+        pair: Pair<'_, Rule>,
+        tokens: &mut Vec<ExpressionToken>,
+    ) -> Result<(), Error> {
+        for expression_pair in pair.into_inner() {
+            match expression_pair.as_rule() {
+                Rule::expression_group => {
+                    tokens.push(ExpressionToken::Open);
+                    Self::parse_expression(name, entity_model, expression_pair, tokens)?;
+                    tokens.push(ExpressionToken::Close);
+                }
+                Rule::expression_operand => {
+                    Self::parse_expression(name, entity_model, expression_pair, tokens)?;
+                }
+                Rule::now_fn => tokens.push(ExpressionToken::Now),
+                Rule::float | Rule::integer => {
+                    tokens.push(ExpressionToken::Number(expression_pair.as_str().to_string()));
+                }
+                Rule::identifier => {
+                    let param = expression_pair.as_str();
+                    let model_field = entity_model.get_field(param)?;
+                    match model_field.field_type {
+                        FieldType::Integer | FieldType::Float | FieldType::DateTime | FieldType::Date => {}
+                        _ => return Err(Error::InvalidQuery(format!(
+                            "computed field '{}' requires integer, float or date fields and '{}' is a '{}'",
+                            name, param, model_field.field_type
+                        )))
+                    }
+                    tokens.push(ExpressionToken::Field(model_field.clone()));
+                }
+                Rule::arithmetic_operator => {
+                    tokens.push(ExpressionToken::Operator(expression_pair.as_str().to_string()));
+                }
+                _ => unreachable!()
+            }
+        }
+        Ok(())
+    }
+
     fn parse_entity(
         data_model: &DataModel,
         pair: Pair<'_, Rule>,
@@ -755,22 +1401,231 @@ impl QueryParser {
         } else {
             name = name_pair.next().unwrap().as_str().to_string();
         }
-        let model_entity = data_model.get_entity(&name)?;
+        let model_entity = query_entity(data_model, &name)?;
         entity.name = name;
         entity.short_name = String::from(&model_entity.short_name);
+        if entity.is_wildcard() {
+            for (namespace, entities) in data_model.namespaces() {
+                if namespace.eq(SYSTEM_NAMESPACE) {
+                    continue;
+                }
+                for (name, model) in entities {
+                    entity.wildcard.push((model.short_name.clone(), name.clone()));
+                    if model.soft_delete {
+                        entity.soft_delete.push(model.short_name.clone());
+                    }
+                }
+            }
+            entity.wildcard.sort();
+            entity.soft_delete.sort();
+        }
 
         Self::parse_entity_internals(&mut entity,data_model, entity_pairs,variables)?;
 
         Ok(entity)
     }
 
+    fn parse_exists(
+        pair: Pair<'_, Rule>,
+        data_model: &DataModel,
+        entity_model: &Entity,
+        variables: &mut Variables,
+    ) -> Result<ExistsFilter, Error> {
+        let mut negated = false;
+        let mut exists_pairs = pair.into_inner();
+        let mut exists_pair = exists_pairs.next().unwrap();
+        if exists_pair.as_rule() == Rule::negation {
+            negated = true;
+            exists_pair = exists_pairs.next().unwrap();
+        }
+
+        let mut entity_pairs = exists_pair.into_inner();
+        let name = entity_pairs.next().unwrap().as_str();
+        let model_field = entity_model.get_field(name)?;
+        let target_entity_name = match &model_field.field_type {
+            FieldType::Array(e) | FieldType::Entity(e) => e,
+            _ => return Err(Error::InvalidQuery(format!(
+                "exists({}) requires an entity field and '{}' is a '{}'",
+                name, name, model_field.field_type
+            )))
+        };
+        let target_model = data_model.get_entity(target_entity_name)?;
+        let mut target_entity = EntityQuery::new();
+        target_entity.name = target_entity_name.clone();
+        target_entity.short_name = target_model.short_name.clone();
+
+        let mut parsed_filters = Vec::new();
+        if let Some(param_pair) = entity_pairs.next() {
+            for filter_pair in param_pair.into_inner() {
+                match filter_pair.as_rule() {
+                    Rule::exists_filter_param => {
+                        let pair = filter_pair.into_inner().next().unwrap();
+                        match pair.as_rule() {
+                            Rule::filter => parsed_filters.push(Self::parse_filter(pair)?),
+                            Rule::search => {
+                                target_entity.params.fulltext_search = Some(Self::parse_search(pair, variables)?);
+                            }
+                            Rule::json_filter => {
+                                let filter = Self::parse_json_filter(pair, target_model)?;
+                                target_entity.params.json_filters.push(filter);
+                            }
+                            Rule::exists_filter => {
+                                let exists = Self::parse_exists(pair, data_model, target_model, variables)?;
+                                target_entity.complexity += exists.entity.complexity + 1;
+                                target_entity.params.exists_filters.push(exists);
+                            }
+                            _ => unreachable!(),
+                        }
+                    }
+                    Rule::comma => {}
+                    _ => unreachable!()
+                }
+            }
+        }
+
+        for parsed in parsed_filters {
+            let group = Self::build_filter_group(&target_entity, target_model, variables, parsed)?;
+            for filter in group.filters() {
+                if let FieldType::Array(_) | FieldType::Entity(_) = filter.field.field_type {
+                    return Err(Error::InvalidQuery(format!(
+                        "'{}' references an Entity and cannot be filtered inside exists(), use a nested exists({}) instead",
+                        filter.name, filter.name
+                    )))
+                }
+            }
+            match group {
+                FilterGroup::Filter(filter) => target_entity.params.filters.push(*filter),
+                group => target_entity.params.filter_groups.push(group),
+            }
+        }
+
+        Ok(ExistsFilter{
+            negated,
+            field: model_field.clone(),
+            entity: target_entity
+        })
+    }
+
+    fn parse_search(pair: Pair<'_, Rule>, variables: &mut Variables) -> Result<FieldValue, Error> {
+        let val = pair.into_inner().next().unwrap().into_inner().next().unwrap();
+        let value = match val.as_rule(){
+            Rule::variable => {
+                let var = &val.as_str()[1..];
+                variables.add(var, VariableType::String(false))?;
+                FieldValue::Variable(var.to_string())
+            }
+            Rule::string => {
+                let pair = val.into_inner().next().unwrap();
+                let value = pair.as_str().replace("\\\"", "\"");
+                FieldValue::Value(ParamValue::String(value.to_string()))
+            }
+            _=> unreachable!()
+        };
+        Ok(value)
+    }
+
+    //
+    // the name of the Json field and the SQL selector of the path
+    //
+    fn parse_json_selector(pair: Pair<'_, Rule>) -> (String, String) {
+        let mut json_selector = pair.into_inner();
+        let name = json_selector.next().unwrap().as_str().to_string(); 
+        let selector_pair = json_selector.next().unwrap();
+        
+        let selector =  match selector_pair.as_rule(){
+            Rule::json_object_selector => format!("'{}'", selector_pair.as_str()),
+            Rule::json_array_selector =>  selector_pair.as_str().to_string(),
+            _=> unreachable!()
+        };
+        (name, selector)
+    }
+
+    fn json_field<'a>(name: &str, entity_model: &'a Entity) -> Result<&'a Field, Error> {
+        let field = entity_model.get_field(name)?;
+        if field.field_type != FieldType::Json{
+            return Err(Error::InvalidFieldType(name.to_string(), FieldType::Json.to_string(), field.field_type.to_string()));
+        }
+        Ok(field)
+    }
+
+    fn parse_json_filter(pair: Pair<'_, Rule>, entity_model: &Entity) -> Result<JsonFilter, Error> {
+        let mut values = pair.into_inner();
+        let (name, selector) = Self::parse_json_selector(values.next().unwrap());
+        let field = Self::json_field(&name, entity_model)?;
+  
+        let operation = values.next().unwrap().as_str().to_string();
+
+        let val_pair = values.next().unwrap().into_inner().next().unwrap();
+        
+        let value =Self::parse_field_value(val_pair)?;
+        Ok(JsonFilter{ selector, operation, value, field:field.clone() })
+    }
+
+    fn parse_date_filter(pair: Pair<'_, Rule>, entity_model: &Entity, variables: &mut Variables) -> Result<DateFilter, Error> {
+        let mut values = pair.into_inner();
+        let name = values.next().unwrap().as_str();
+        let field = entity_model.get_field(name)?;
+        match field.field_type {
+            FieldType::DateTime | FieldType::Date => {}
+            _ if is_date_system_field(field) => {}
+            _ => return Err(Error::InvalidFieldType(name.to_string(), FieldType::DateTime.to_string(), field.field_type.to_string()))
+        }
+
+        let count_pair = values.find(|p| p.as_rule() == Rule::day_count).unwrap().into_inner().next().unwrap();
+        if count_pair.as_rule() == Rule::variable {
+            variables.add(&count_pair.as_str()[1..], VariableType::Integer(false))?;
+        }
+        let days = Self::parse_field_value(count_pair)?;
+        Ok(DateFilter { field: field.clone(), days })
+    }
+
+    fn parse_geo_filter(pair: Pair<'_, Rule>, entity_model: &Entity, variables: &mut Variables) -> Result<GeoFilter, Error> {
+        let area_pair = pair.into_inner().next().unwrap();
+        let rule = area_pair.as_rule();
+        let mut values = area_pair.into_inner();
+        let name = values.next().unwrap().as_str();
+
+        let field = entity_model.get_field(name)?;
+        if field.field_type != FieldType::Geo{
+            return Err(Error::InvalidFieldType(name.to_string(), FieldType::Geo.to_string(), field.field_type.to_string()));
+        }
+
+        let mut coordinates = Vec::new();
+        for value in values {
+            match value.as_rule() {
+                Rule::geo_value => {
+                    let val_pair = value.into_inner().next().unwrap();
+                    if val_pair.as_rule() == Rule::variable {
+                        variables.add(&val_pair.as_str()[1..], VariableType::Float(false))?;
+                    }
+                    coordinates.push(Self::parse_field_value(val_pair)?);
+                }
+                Rule::comma => {}
+                _ => unreachable!()
+            }
+        }
+        let area = match rule {
+            Rule::bbox => GeoArea::BoundingBox(coordinates),
+            Rule::radius => GeoArea::Radius(coordinates),
+            _ => unreachable!()
+        };
+
+        let index = entity_model.indexes.values()
+            .find(|index| index.is_geo() && index.fields[0].name.eq(&field.name))
+            .map(|index| index.name());
+
+        Ok(GeoFilter{ field: field.clone(), area, index })
+    }
+
     fn parse_params(
         pair: Pair<'_, Rule>,
+        data_model: &DataModel,
         entity_model: &Entity,
         variables: &mut Variables,
-    ) -> Result<(EntityParams, Vec<ParsedFilter>, Vec<ParsedOrderBy>), Error> {
+    ) -> Result<ParsedParams, Error> {
         let mut parameters = EntityParams::new();
         let mut parsed_filter = Vec::new(); 
+        let mut parsed_groups = Vec::new();
         let mut parsed_order_by = Vec::new();
 
         let param_pairs = pair.into_inner();
@@ -780,8 +1635,21 @@ impl QueryParser {
                     let pair = param_pair.into_inner().next().unwrap();
                     match pair.as_rule() {
                         Rule::filter => {
-                            let filter = Self::parse_filter(pair)?;
-                            parsed_filter.push(filter);
+                            match Self::parse_filter(pair)? {
+                                ParsedFilterGroup::Filter(filter) => parsed_filter.push(filter),
+                                //the between() bounds are regular filters, which allows aggregates to use them
+                                ParsedFilterGroup::And(bounds) => {
+                                    for bound in bounds {
+                                        if let ParsedFilterGroup::Filter(filter) = bound {
+                                            parsed_filter.push(filter);
+                                        }
+                                    }
+                                }
+                                group => parsed_groups.push(group),
+                            }
+                        }
+                        Rule::filter_group => {
+                            parsed_groups.push(Self::parse_filter_group(pair)?);
                         }
                         Rule::order_by => {
                             let order_pairs = pair.into_inner();
@@ -790,7 +1658,22 @@ impl QueryParser {
                                 match order_pair.as_rule() {
                                     Rule::order_param => {  
                                         let mut order_p = order_pair.into_inner();
-                                        let name = order_p.next().unwrap().as_str().to_string();
+                                        let name_pair = order_p.next().unwrap();
+                                        if name_pair.as_rule() == Rule::rank_order {
+                                            if !parsed_order_by.is_empty() || parameters.order_by_rank {
+                                                return Err(Error::InvalidQuery(String::from(
+                                                    "rank must be the first parameter of order_by()"
+                                                )))
+                                            }
+                                            parameters.order_by_rank = true;
+                                            continue;
+                                        }
+                                        let (name, json_selector) = if name_pair.as_rule() == Rule::json_selector {
+                                            let (name, selector) = Self::parse_json_selector(name_pair);
+                                            (name, Some(selector))
+                                        } else {
+                                            (name_pair.as_str().to_string(), None)
+                                        };
         
                                         let direction_str = order_p.next().unwrap().as_str().to_lowercase();
                                         let direction = match direction_str.as_str() {
@@ -798,7 +1681,7 @@ impl QueryParser {
                                             "desc" => Direction::Desc,
                                             _=> unreachable!()
                                         };
-                                        parsed_order_by.push(ParsedOrderBy{ name, direction })}
+                                        parsed_order_by.push(ParsedOrderBy{ name, direction, json_selector })}
                                     Rule::comma => {}
                                     _=> unreachable!()
                                 }
@@ -840,21 +1723,7 @@ impl QueryParser {
 
 
                         Rule::search => {
-                            let val = pair.into_inner().next().unwrap().into_inner().next().unwrap();
-                            match val.as_rule(){
-                                Rule::variable => {
-                                    let var = &val.as_str()[1..];
-                                    variables.add(var, VariableType::String(false))?;
-                                    parameters.fulltext_search = Some(FieldValue::Variable(var.to_string()));
-
-                                }
-                                Rule::string => {
-                                    let pair = val.into_inner().next().unwrap();
-                                    let value = pair.as_str().replace("\\\"", "\"");
-                                    parameters.fulltext_search = Some(FieldValue::Value(ParamValue::String(value.to_string())));
-                                }
-                                _=> unreachable!()
-                            }
+                            parameters.fulltext_search = Some(Self::parse_search(pair, variables)?);
                         }
 
                         Rule::before => {
@@ -869,33 +1738,73 @@ impl QueryParser {
                             parameters.after = after;
                         }
 
-                        Rule::json_filter => {
-                            
-                            let mut values = pair.into_inner();
-                            let mut json_selector = values.next().unwrap().into_inner();
-                            let name = json_selector.next().unwrap().as_str(); 
-                            
-                            let field = entity_model.get_field(name)?;
-                            if field.field_type != FieldType::Json{
-                                return Err(Error::InvalidFieldType(name.to_string(), FieldType::Json.to_string(), field.field_type.to_string()));
-                            }
+                        Rule::cursor => {
+                            let var = &pair.into_inner().next().unwrap().as_str()[1..];
+                            parameters.cursor = Some(var.to_string());
+                        }
 
-                            let selector_pair = json_selector.next().unwrap();
-                            
-                            let selector =  match selector_pair.as_rule(){
-                                Rule::json_object_selector => format!("'{}'", selector_pair.as_str()),
-                                Rule::json_array_selector =>  selector_pair.as_str().to_string(),
+                        Rule::include_deleted => {
+                            parameters.include_deleted = true;
+                        }
+
+                        Rule::versions => {
+                            let val = pair.into_inner().next().unwrap().into_inner().next().unwrap();
+                            let value = match val.as_rule(){
+                                Rule::variable => {
+                                    let var = &val.as_str()[1..];
+                                    variables.add(var, VariableType::Base64(false))?;
+                                    FieldValue::Variable(var.to_string())
+                                }
+                                Rule::string => {
+                                    let value = val.into_inner().next().unwrap().as_str();
+                                    validate_base64(value, "versions")?;
+                                    FieldValue::Value(ParamValue::String(value.to_string()))
+                                }
                                 _=> unreachable!()
                             };
-                      
-                            let operation = values.next().unwrap().as_str().to_string();
+                            parameters.versions = Some(value);
+                        }
 
-                            let val_pair = values.next().unwrap().into_inner().next().unwrap();
-                            
-                            let value =Self::parse_field_value(val_pair)?;
-                            let filter = JsonFilter{ selector, operation, value, field:field.clone() };
+                        Rule::json_filter => {
+                            let filter = Self::parse_json_filter(pair, entity_model)?;
                             parameters.json_filters.push(filter);
-
+                        }
+                        Rule::geo_filter => {
+                            let filter = Self::parse_geo_filter(pair, entity_model, variables)?;
+                            parameters.geo_filters.push(filter);
+                        }
+                        Rule::within_days => {
+                            let filter = Self::parse_date_filter(pair, entity_model, variables)?;
+                            parameters.date_filters.push(filter);
+                        }
+                        Rule::exists_filter => {
+                            let exists = Self::parse_exists(pair, data_model, entity_model, variables)?;
+                            parameters.exists_filters.push(exists);
+                        }
+                        Rule::group_by => {
+                            for name_pair in pair.into_inner() {
+                                if name_pair.as_rule() != Rule::identifier {
+                                    continue;
+                                }
+                                let name = name_pair.as_str();
+                                let field = entity_model.get_field(name)?;
+                                match field.field_type {
+                                    FieldType::Array(_) | FieldType::Entity(_) | FieldType::Json | FieldType::Geo | FieldType::File => {
+                                        return Err(Error::InvalidQuery(format!(
+                                            "field '{}' of type '{}' cannot be used in group_by()",
+                                            name, field.field_type
+                                        )))
+                                    }
+                                    _ => {}
+                                }
+                                if parameters.group_by.iter().any(|f| f.name.eq(&field.name)) {
+                                    return Err(Error::InvalidQuery(format!(
+                                        "field '{}' is defined several times in group_by()",
+                                        name
+                                    )))
+                                }
+                                parameters.group_by.push(field.clone());
+                            }
                         }
                         Rule::nullable => {
                             let values = pair.into_inner();
@@ -913,24 +1822,105 @@ impl QueryParser {
             
         }
 
-        Ok((parameters, parsed_filter, parsed_order_by))
+        Ok((parameters, parsed_filter, parsed_order_by, parsed_groups))
+    }
+
+    fn parse_filter_group(
+        pair: Pair<'_, Rule>,
+    ) -> Result<ParsedFilterGroup, Error> {
+        let group_pair = pair.into_inner().next().unwrap();
+        let rule = group_pair.as_rule();
+        let mut members = Vec::new();
+        for param_pair in group_pair.into_inner() {
+            match param_pair.as_rule() {
+                Rule::group_param => {
+                    let pair = param_pair.into_inner().next().unwrap();
+                    match pair.as_rule() {
+                        Rule::filter => members.push(Self::parse_filter(pair)?),
+                        Rule::filter_group => members.push(Self::parse_filter_group(pair)?),
+                        _ => unreachable!(),
+                    }
+                }
+                Rule::comma => {}
+                _ => unreachable!(),
+            }
+        }
+        Ok(match rule {
+            Rule::or_group => ParsedFilterGroup::Or(members),
+            Rule::and_group => ParsedFilterGroup::And(members),
+            Rule::not_group => ParsedFilterGroup::Not(members),
+            _ => unreachable!(),
+        })
+    }
+
+    fn build_filter_group(
+        entity: &EntityQuery,
+        entity_model: &Entity,
+        variables: &mut Variables,
+        parsed: ParsedFilterGroup
+    ) -> Result<FilterGroup, Error> {
+        let build_members = |members: Vec<ParsedFilterGroup>, variables: &mut Variables| {
+            members
+                .into_iter()
+                .map(|member| Self::build_filter_group(entity, entity_model, variables, member))
+                .collect::<Result<Vec<FilterGroup>, Error>>()
+        };
+        Ok(match parsed {
+            ParsedFilterGroup::Filter(parsed) => {
+                let filter = Self::build_filter(entity, entity_model, variables, parsed)?;
+                if filter.is_aggregate {
+                    return Err(Error::InvalidQuery(format!(
+                        "aggregate '{}' cannot be filtered inside or(), and() or not()",
+                        filter.name
+                    )))
+                }
+                FilterGroup::Filter(Box::new(filter))
+            }
+            ParsedFilterGroup::And(members) => FilterGroup::And(build_members(members, variables)?),
+            ParsedFilterGroup::Or(members) => FilterGroup::Or(build_members(members, variables)?),
+            ParsedFilterGroup::Not(members) => FilterGroup::Not(build_members(members, variables)?),
+        })
     }
 
 
+    //
+    // 'between($low, $high)' and 'in [$a, $b]' are expressed with the filter groups:
+    //      and(field >= $low, field <= $high)
+    //      or(field = $a, field = $b)
+    //
     fn parse_filter (
         pair: Pair<'_, Rule>,
-    ) -> Result<ParsedFilter, Error> {
+    ) -> Result<ParsedFilterGroup, Error> {
         let mut filter_pairs = pair.into_inner();
 
         let name = filter_pairs.next().unwrap().as_str().to_string();
 
         let operation_pair =  filter_pairs.next().unwrap();
-        let operation = operation_pair.as_str().to_string();
-    
-        let value_pair = filter_pairs.next().unwrap().into_inner().next().unwrap();
-        let value = Self::parse_field_value(value_pair)?;
-        Ok(ParsedFilter{ name, operation, value })
-
+        let operation = operation_pair.as_str().to_lowercase();
+
+        match operation_pair.as_rule() {
+            Rule::between => {
+                let mut bounds = Vec::with_capacity(2);
+                for (value_pair, operation) in filter_pairs.filter(|p| p.as_rule() == Rule::filter_value).zip([">=", "<="]) {
+                    let value = Self::parse_field_value(value_pair.into_inner().next().unwrap())?;
+                    bounds.push(ParsedFilterGroup::Filter(ParsedFilter{ name: name.clone(), operation: operation.to_string(), value }));
+                }
+                Ok(ParsedFilterGroup::And(bounds))
+            }
+            _ => {
+                let value_pair = filter_pairs.next().unwrap();
+                if value_pair.as_rule() == Rule::value_list {
+                    let mut values = Vec::new();
+                    for value_pair in value_pair.into_inner().filter(|p| p.as_rule() == Rule::filter_value) {
+                        let value = Self::parse_field_value(value_pair.into_inner().next().unwrap())?;
+                        values.push(ParsedFilterGroup::Filter(ParsedFilter{ name: name.clone(), operation: "=".to_string(), value }));
+                    }
+                    return Ok(ParsedFilterGroup::Or(values));
+                }
+                let value = Self::parse_field_value(value_pair.into_inner().next().unwrap())?;
+                Ok(ParsedFilterGroup::Filter(ParsedFilter{ name, operation, value }))
+            }
+        }
     }
 
     fn build_filter(
@@ -961,7 +1951,7 @@ impl QueryParser {
                             match e.field_type {
                                 QueryFieldType::EntityQuery(_, _) | QueryFieldType::EntityArrayQuery(_, _)=> is_entity_field = true,
                                 QueryFieldType::Aggregate(_) => is_aggregate = true,
-                                QueryFieldType::Scalar | QueryFieldType::Binary | QueryFieldType::Json=> {},
+                                QueryFieldType::Scalar | QueryFieldType::Binary | QueryFieldType::Json | QueryFieldType::Custom(_) | QueryFieldType::Snippet(_) | QueryFieldType::FormattedDate(_) | QueryFieldType::Computed(_)=> {},
                             }
                             &e.field
                         },
@@ -982,7 +1972,60 @@ impl QueryParser {
        
        
         let name = parsed_filters.name;
+
+        if field.field_type == FieldType::Geo {
+            return Err(Error::InvalidQuery(format!(
+                "Geo field '{}' can only be filtered with bbox() or radius()",
+                name
+            )))
+        }
+
+        if parsed_filters.operation.eq("in") {
+            //'in' filters are used to match a list of values provided by an array variable
+            let var = match &parsed_filters.value {
+                FieldValue::Variable(var) if !is_aggregate => var,
+                _ => return Err(Error::InvalidArrayFilter(parsed_filters.operation, name)),
+            };
+            match field.field_type {
+                FieldType::Base64 | FieldType::String | FieldType::Integer | FieldType::Float | FieldType::DateTime | FieldType::Date => {}
+                _ => return Err(Error::InvalidFieldType(
+                    name,
+                    field.field_type.to_string(),
+                    "Array".to_string(),
+                ))
+            }
+            let var_type = VariableType::Array(Box::new(field.get_variable_type_non_nullable()));
+            variables.add(var, var_type)?;
+            return Ok(FilterParam {
+                name,
+                operation: parsed_filters.operation,
+                value: parsed_filters.value,
+                is_aggregate,
+                is_selected,
+                field:field.clone()
+            })
+        }
         
+        if matches!(parsed_filters.operation.as_str(), "like" | "starts_with" | "contains") {
+            //string matching operations are only available on String fields
+            if is_entity_field || is_aggregate || field.field_type != FieldType::String {
+                return Err(Error::InvalidStringFilter(parsed_filters.operation, name));
+            }
+            match &parsed_filters.value {
+                FieldValue::Variable(var) => variables.add(var, field.get_variable_type_non_nullable())?,
+                FieldValue::Value(ParamValue::String(_)) => {}
+                _ => return Err(Error::InvalidStringFilter(parsed_filters.operation, name)),
+            }
+            return Ok(FilterParam {
+                name,
+                operation: parsed_filters.operation,
+                value: parsed_filters.value,
+                is_aggregate,
+                is_selected,
+                field:field.clone()
+            })
+        }
+
         let value = match &parsed_filters.value {
             FieldValue::Variable(var) => {
                 if is_entity_field{
@@ -1031,7 +2074,7 @@ impl QueryParser {
                         } 
                         match field.field_type {
                             FieldType::Float =>  FieldValue::Value(ParamValue::Float(*i as f64)),  
-                            FieldType::Integer =>  parsed_filters.value,  
+                            FieldType::Integer | FieldType::DateTime | FieldType::Date =>  parsed_filters.value,  
                             _ => {
                                 return Err(Error::InvalidFieldType(
                                     name,
@@ -1068,7 +2111,7 @@ impl QueryParser {
                             FieldType::String => {
                                 parsed_filters.value
                             },
-                            FieldType::Base64 => {
+                            FieldType::Base64 | FieldType::File => {
                                 validate_base64(s, &name)?;
                                 if field.is_system{
                                     FieldValue::Value(ParamValue::Binary(s.clone()))
@@ -1076,6 +2119,15 @@ impl QueryParser {
                                     parsed_filters.value
                                 }
                             }
+                            FieldType::Enum(_) => {
+                                if field.field_type.enum_position(s).is_none(){
+                                    return Err(Error::InvalidEnumValue(s.clone(), name));
+                                }
+                                parsed_filters.value
+                            }
+                            FieldType::DateTime | FieldType::Date => {
+                                FieldValue::Value(ParamValue::Integer(field.field_type.parse_date(s)?))
+                            }
                             _ => {
                                 return Err(Error::InvalidFieldType(
                                     name,
@@ -1108,6 +2160,16 @@ impl QueryParser {
         entity_model: &Entity,
         parsed_order: ParsedOrderBy
     ) -> Result<OrderBy, Error> {
+        if parsed_order.json_selector.is_some() {
+            let field = Self::json_field(&parsed_order.name, entity_model)?;
+            return Ok(OrderBy { 
+                name: parsed_order.name,
+                direction: parsed_order.direction,
+                is_selected: false,
+                field: field.clone(),
+                json_selector: parsed_order.json_selector
+            });
+        }
         
    //     let mut is_aggregate = false;
         let mut is_entity_field = false;
@@ -1130,7 +2192,7 @@ impl QueryParser {
                             match e.field_type {
                                 QueryFieldType::EntityQuery(_, _) | QueryFieldType::EntityArrayQuery(_, _)=> is_entity_field = true,
                                 QueryFieldType::Aggregate(_) =>  {},// is_aggregate = true,
-                                QueryFieldType::Scalar | QueryFieldType::Binary | QueryFieldType::Json=> {},
+                                QueryFieldType::Scalar | QueryFieldType::Binary | QueryFieldType::Json | QueryFieldType::Custom(_) | QueryFieldType::Snippet(_) | QueryFieldType::FormattedDate(_) | QueryFieldType::Computed(_)=> {},
                             }
                             &e.field
                         },
@@ -1148,7 +2210,8 @@ impl QueryParser {
             direction:parsed_order.direction,
          //   is_aggregate,
             is_selected,
-            field: field.clone()
+            field: field.clone(),
+            json_selector: None
         })       
     }
 
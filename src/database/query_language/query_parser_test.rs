@@ -1,7 +1,10 @@
 #[cfg(test)]
 mod tests {
     use crate::database::{
-        query_language::{data_model_parser::DataModel, query_parser::QueryParser},
+        query_language::{
+            data_model_parser::DataModel, mutation_parser::MutationParser,
+            query_parser::QueryParser, Error, ParsingLimits,
+        },
         system_entities,
     };
 
@@ -1185,4 +1188,147 @@ mod tests {
         )
         .expect("valid query");
     }
+
+    #[test]
+    fn exists_filter() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            {
+                Person {
+                    name : String,
+                    parents: [Person],
+                    pet: Pet,
+                }
+                Pet {
+                    name : String,
+                }
+            }",
+            )
+            .unwrap();
+
+        let query = QueryParser::parse(
+            r#"
+            query aquery {
+                Person (
+                   exists(parents(name = "John", exists(pet))),
+                   not exists(pet),
+                ) {
+                    name
+                }
+            } "#,
+            &data_model,
+        )
+        .expect("valid query");
+        let params = &query.queries[0].params;
+        assert_eq!(2, params.exists_filters.len());
+        assert!(!params.exists_filters[0].negated);
+        assert!(params.exists_filters[1].negated);
+        assert_eq!(3, query.queries[0].complexity);
+
+        let _query = QueryParser::parse(
+            r#"
+            query aquery {
+                Person (
+                   exists(name)
+                ) {
+                    name
+                }
+            } "#,
+            &data_model,
+        )
+        .expect_err("name is not an entity field");
+
+        let _query = QueryParser::parse(
+            r#"
+            query aquery {
+                Person (
+                   exists(parents(pet = null))
+                ) {
+                    name
+                }
+            } "#,
+            &data_model,
+        )
+        .expect_err("entity fields must use a nested exists()");
+
+        let _query = QueryParser::parse(
+            r#"
+            query aquery {
+                Person (
+                   exists(parents(age = 12))
+                ) {
+                    name
+                }
+            } "#,
+            &data_model,
+        )
+        .expect_err("age is not a Person field");
+    }
+
+    #[test]
+    fn parsing_limits() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            {
+                Person {
+                    name : String,
+                    parents : [Person],
+                }
+            }",
+            )
+            .unwrap();
+
+        let limits = ParsingLimits {
+            max_size: 1024,
+            max_depth: 4,
+        };
+
+        let query = r#"
+            query aquery {
+                Person(name = "{{{{{{") {
+                    // comments are ignored ((((((
+                    parents {
+                        name
+                    }
+                }
+            } "#;
+        QueryParser::parse_with_limits(query, &data_model, &limits).expect("valid query");
+
+        let query = r#"
+            query aquery {
+                Person {
+                    parents {
+                        parents {
+                            parents {
+                                name
+                            }
+                        }
+                    }
+                }
+            } "#;
+        let err = QueryParser::parse_with_limits(query, &data_model, &limits).unwrap_err();
+        assert!(matches!(err, Error::InputTooDeep(4)));
+
+        let mut query = String::from("query aquery {");
+        for _ in 0..10000 {
+            query.push_str("Person { parents {");
+        }
+        QueryParser::parse(&query, &data_model).expect_err("too deep for the default limits");
+
+        let mutation = format!(
+            r#"
+            mutate {{
+                Person {{
+                    name : "{}"
+                }}
+            }} "#,
+            "a".repeat(1024)
+        );
+        let err = MutationParser::parse_with_limits(&mutation, &data_model, &limits).unwrap_err();
+        assert!(matches!(err, Error::InputTooLarge(_, 1024)));
+    }
 }
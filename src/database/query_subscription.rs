@@ -0,0 +1,281 @@
+use std::{
+    collections::HashSet,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+
+use crate::{
+    event_service::{Event, EventService},
+    Error,
+};
+
+use super::{
+    graph_database::GraphDatabaseService, query_language::parameter::Parameters, DataModification,
+};
+
+//the subscriber only needs the latest result
+const SUBSCRIPTION_BUFFER: usize = 1;
+
+///
+/// Live query created by *Discret::subscribe_query()*.
+///
+/// The first item is the current result of the query.
+/// The query is evaluated again each time an *Event::DataChanged* modifies one of the queried entities,
+/// a new item is only returned when the result differs from the previous one.
+///
+/// The subscription is also a [Stream] of results. It ends when Discret is stopped, and is cancelled by dropping it.
+///
+pub struct QuerySubscription {
+    receiver: mpsc::Receiver<Result<String, Error>>,
+}
+impl QuerySubscription {
+    pub(crate) async fn start(
+        database: &GraphDatabaseService,
+        events: &EventService,
+        query: &str,
+        parameters: Option<Parameters>,
+    ) -> Result<Self, Error> {
+        //invalid queries are rejected before subscribing
+        let entities = database.query_entities(query).await?;
+
+        //subscribe before the first evaluation to not miss any change
+        let mut event_receiver = events.subcribe().await;
+        let (sender, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER);
+
+        let database = database.clone();
+        let query = query.to_string();
+        let parameters = parameters.unwrap_or_default();
+        tokio::spawn(async move {
+            let mut last_result: Option<String> = None;
+            loop {
+                let result = database
+                    .query(&query, Some(parameters.clone()))
+                    .await
+                    .map_err(Error::from);
+
+                let send = match &result {
+                    Ok(result) => {
+                        let changed = last_result.as_ref() != Some(result);
+                        last_result = Some(result.clone());
+                        changed
+                    }
+                    Err(_) => true,
+                };
+                if send && sender.send(result).await.is_err() {
+                    break;
+                }
+
+                if !Self::wait_for_change(&entities, &mut event_receiver, &sender).await {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self { receiver })
+    }
+
+    //
+    // waits until one of the entities is modified
+    // returns false when Discret is stopped or the subscription is dropped
+    //
+    async fn wait_for_change(
+        entities: &HashSet<String>,
+        event_receiver: &mut broadcast::Receiver<Event>,
+        sender: &mpsc::Sender<Result<String, Error>>,
+    ) -> bool {
+        loop {
+            tokio::select! {
+                event = event_receiver.recv() => match event {
+                    Ok(Event::DataChanged(modification)) => {
+                        if Self::is_modified(entities, &modification) {
+                            return true;
+                        }
+                    }
+                    Ok(_) => {}
+                    //some changes might have been missed
+                    Err(RecvError::Lagged(_)) => return true,
+                    Err(RecvError::Closed) => return false,
+                },
+                _ = sender.closed() => return false,
+            }
+        }
+    }
+
+    fn is_modified(entities: &HashSet<String>, modification: &DataModification) -> bool {
+        modification
+            .rooms
+            .values()
+            .any(|room| room.keys().any(|entity| entities.contains(entity)))
+    }
+
+    ///
+    /// Wait for the next result. Returns None when Discret is stopped.
+    ///
+    pub async fn recv(&mut self) -> Option<Result<String, Error>> {
+        self.receiver.recv().await
+    }
+
+    ///
+    /// Blocking version of recv(). Cannot be used in an asynchronous context.
+    ///
+    pub fn blocking_recv(&mut self) -> Option<Result<String, Error>> {
+        self.receiver.blocking_recv()
+    }
+}
+impl Stream for QuerySubscription {
+    type Item = Result<String, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, time::Duration};
+
+    use futures::StreamExt;
+
+    use crate::{
+        configuration::Configuration,
+        database::query_language::parameter::ParametersAdd,
+        security::{base64_encode, random32},
+        ResultParser,
+    };
+
+    use super::*;
+
+    const DATA_PATH: &str = "test_data/database/query_subscription/";
+    fn init_database_path() {
+        let path: PathBuf = DATA_PATH.into();
+        fs::create_dir_all(&path).unwrap();
+        let paths = fs::read_dir(path).unwrap();
+
+        for path in paths {
+            let dir = path.unwrap().path();
+            let paths = fs::read_dir(dir).unwrap();
+            for file in paths {
+                let files = file.unwrap().path();
+                let _ = fs::remove_file(&files);
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn subscribe_query() {
+        init_database_path();
+        let data_model = "{Person{ name:String } Pet{ name:String }}";
+
+        let secret = random32();
+        let path: PathBuf = DATA_PATH.into();
+        let event_service = EventService::new();
+
+        let (app, verifying_key, _) = GraphDatabaseService::start(
+            "query subscription app",
+            data_model,
+            &secret,
+            &random32(),
+            path,
+            &Configuration::default(),
+            event_service.clone(),
+        )
+        .await
+        .unwrap();
+
+        let user_id = base64_encode(&verifying_key);
+        let mut param = Parameters::default();
+        param.add("user_id", user_id.clone()).unwrap();
+        let room = app
+            .mutate_raw(
+                r#"mutate {
+                    sys.Room{
+                        admin: [{ verif_key:$user_id }]
+                        authorisations:[{
+                            name:"admin"
+                            rights:[
+                                { entity:"Person" mutate_self:true mutate_all:true },
+                                { entity:"Pet" mutate_self:true mutate_all:true }
+                            ]
+                            users: [{ verif_key:$user_id }]
+                        }]
+                    }
+                }"#,
+                Some(param),
+            )
+            .await
+            .unwrap();
+        let room_id = base64_encode(&room.mutate_entities[0].node_to_mutate.id);
+
+        #[derive(serde::Deserialize)]
+        struct Person {
+            name: String,
+        }
+
+        let invalid =
+            QuerySubscription::start(&app, &event_service, "query { Unknown { name } }", None)
+                .await;
+        assert!(invalid.is_err());
+
+        let mut subscription = QuerySubscription::start(
+            &app,
+            &event_service,
+            "query { Person(order_by(name asc)) { name } }",
+            None,
+        )
+        .await
+        .unwrap();
+
+        let result = subscription.next().await.unwrap().unwrap();
+        let mut parser = ResultParser::new(&result).unwrap();
+        let persons: Vec<Person> = parser.take_array("Person").unwrap();
+        assert!(persons.is_empty());
+
+        let mut param = Parameters::default();
+        param.add("room_id", room_id.clone()).unwrap();
+        app.mutate_raw(
+            r#"mutate { Person { room_id:$room_id name:"Alice" } }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+
+        let result = subscription.next().await.unwrap().unwrap();
+        let mut parser = ResultParser::new(&result).unwrap();
+        let persons: Vec<Person> = parser.take_array("Person").unwrap();
+        assert_eq!(1, persons.len());
+        assert_eq!("Alice", persons[0].name);
+
+        //other entities do not trigger a new result
+        let mut param = Parameters::default();
+        param.add("room_id", room_id.clone()).unwrap();
+        app.mutate_raw(
+            r#"mutate { Pet { room_id:$room_id name:"Rex" } }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+        let next = tokio::time::timeout(Duration::from_millis(200), subscription.recv()).await;
+        assert!(next.is_err());
+
+        let mut param = Parameters::default();
+        param.add("room_id", room_id.clone()).unwrap();
+        app.mutate_raw(
+            r#"mutate { Person { room_id:$room_id name:"Bob" } }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+
+        let result = subscription.recv().await.unwrap().unwrap();
+        let mut parser = ResultParser::new(&result).unwrap();
+        let persons: Vec<Person> = parser.take_array("Person").unwrap();
+        assert_eq!(2, persons.len());
+        assert_eq!("Bob", persons[1].name);
+    }
+}
@@ -7,6 +7,7 @@ mod tests {
     use std::sync::Arc;
 
     use crate::database::mutation_query::MutationQuery;
+    use crate::database::node::Node;
     use crate::database::query_language::parameter::ParametersAdd;
     use crate::database::sqlite_database::Writeable;
     use crate::database::system_entities::SYSTEM_DATA_MODEL;
@@ -14,12 +15,15 @@ mod tests {
     use crate::database::{
         query::{PreparedQueries, Query},
         query_language::{
-            data_model_parser::DataModel, mutation_parser::MutationParser, parameter::Parameters,
+            data_model_parser::{validate_json_for_entity, DataModel},
+            mutation_parser::MutationParser,
+            parameter::Parameters,
             query_parser::QueryParser,
+            FieldValue, ParamValue,
         },
         sqlite_database::prepare_connection,
     };
-    use crate::security::{uid_encode, Ed25519SigningKey};
+    use crate::security::{base64_encode, new_uid, uid_encode, Ed25519SigningKey};
 
     #[test]
     fn simple_scalar() {
@@ -500,6 +504,165 @@ mod tests {
         assert_eq!(expected, result);
     }
 
+    #[test]
+    fn filter_in_array() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            {
+                Person {
+                    name : String ,
+                    parents : [Person] ,
+                }
+            }
+        ",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                Person {
+                    name : "John"
+                    parents:  [{name : "Hello"},{name : "World"},{name : "Again"}]
+                }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mutation = Arc::new(mutation);
+        let mut mutation_query =
+            MutationQuery::execute(&mut Parameters::new(), mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        #[derive(Deserialize)]
+        struct Person {
+            id: String,
+            name: String,
+        }
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                Person (order_by(name asc)) {
+                    id
+                    name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let mut query_result = ResultParser::new(&result).unwrap();
+        let persons: Vec<Person> = query_result.take_array("Person").unwrap();
+        assert_eq!("Again", persons[0].name);
+        assert_eq!("World", persons[3].name);
+        let ids = vec![persons[0].id.clone(), persons[3].id.clone()];
+
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                filtered: Person (id in $ids, order_by(name asc)) {
+                    name
+                }
+                Person (name = "John") {
+                    name
+                    parents (id in $ids, order_by(name asc)) {
+                        name
+                    }
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut param = Parameters::new();
+        param.add("ids", ids).unwrap();
+        let mut sql = Query {
+            parameters: param,
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"filtered\":[{\"name\":\"Again\"},{\"name\":\"World\"}],\n\"Person\":[{\"name\":\"John\",\"parents\":[{\"name\":\"Again\"},{\"name\":\"World\"}]}]\n}";
+        assert_eq!(expected, result);
+
+        QueryParser::parse(
+            r#"
+            query sample{
+                Person (parents in $ids) {
+                    name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("'in' requires a scalar field");
+
+        QueryParser::parse(
+            r#"
+            query sample{
+                Person (id in "zSRIyMbf70V999wyC0KlhQ") {
+                    name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("'in' requires a variable");
+
+        //an array bound to a scalar filter is rejected
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                Person (name = $name) {
+                    name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut param = Parameters::new();
+        param.add("name", vec!["John".to_string()]).unwrap();
+        let mut sql = Query {
+            parameters: param,
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        sql.read(&conn).expect_err("array bound to a String filter");
+
+        //array values are only allowed in 'in' filters
+        let mut query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                Person (name = "John") {
+                    name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+        query_parser.queries[0].params.filters[0].value = FieldValue::Value(ParamValue::Array(
+            vec![ParamValue::String("John".to_string())],
+        ));
+        PreparedQueries::build(&query_parser).expect_err("array value in a scalar filter");
+    }
+
     #[test]
     fn entity_namespace() {
         let mut data_model = DataModel::new();
@@ -803,6 +966,166 @@ mod tests {
         assert_eq!(expected, result);
     }
 
+    #[test]
+    fn cursor_paging() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "ns {
+            Person {
+                name : String,
+                age : Integer,
+                parents : [ns.Person],
+            }
+        }
+        ",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                ns.Person {
+                    name : $name
+                    age : $age
+                }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let mutation = Arc::new(mutation);
+
+        for (name, age) in [
+            ("John", 42),
+            ("Silvie", 46),
+            ("Kevin", 22),
+            ("Sarah", 12),
+            ("Leonore", 22),
+        ] {
+            let mut param = Parameters::new();
+            param.add("name", name.to_string()).unwrap();
+            param.add("age", age).unwrap();
+            let mut mutation_query =
+                MutationQuery::execute(&mut param, mutation.clone(), &conn).unwrap();
+            mutation_query.write(&conn).unwrap();
+        }
+
+        let query_parser = Arc::new(
+            QueryParser::parse(
+                r#"
+            query sample{
+                ns.Person (
+                        order_by(age desc, name asc, id asc),
+                        first 2,
+                        cursor($cursor)
+                    ) {
+                    name
+                }
+            }
+        "#,
+                &data_model,
+            )
+            .unwrap(),
+        );
+        let query = Arc::new(PreparedQueries::build(&query_parser).unwrap());
+
+        #[derive(Deserialize)]
+        struct Person {
+            name: String,
+        }
+
+        let mut pages = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut param = Parameters::new();
+            param.add("cursor", cursor.clone()).unwrap();
+            let mut sql = Query {
+                parameters: param,
+                parser: query_parser.clone(),
+                sql_queries: query.clone(),
+            };
+            let result = sql.read(&conn).unwrap();
+            let mut parser = ResultParser::new(&result).unwrap();
+            let persons: Vec<Person> = parser.take_array("ns.Person").unwrap();
+            pages.push(persons.into_iter().map(|p| p.name).collect::<Vec<String>>());
+            cursor = parser.take_object("ns.Person_next_cursor").unwrap();
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(
+            pages,
+            vec![
+                vec!["Silvie", "John"],
+                vec!["Kevin", "Leonore"],
+                vec!["Sarah"]
+            ]
+        );
+
+        let mut param = Parameters::new();
+        param.add("cursor", "not a cursor".to_string()).unwrap();
+        let mut sql = Query {
+            parameters: param,
+            parser: query_parser.clone(),
+            sql_queries: query.clone(),
+        };
+        sql.read(&conn).expect_err("invalid cursor");
+
+        QueryParser::parse(
+            r#"
+            query sample{
+                ns.Person (order_by(years desc), first 2, cursor($cursor)) {
+                    years: age
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("cannot order by an alias");
+
+        QueryParser::parse(
+            r#"
+            query sample{
+                ns.Person (order_by(age desc), after(22), cursor($cursor)) {
+                    age
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("after and cursor cannot be mixed");
+
+        QueryParser::parse(
+            r#"
+            query sample{
+                ns.Person (first 2, cursor($cursor)) {
+                    age
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("cursor requires an order by");
+
+        QueryParser::parse(
+            r#"
+            query sample{
+                ns.Person {
+                    name
+                    parents(order_by(name asc), cursor($cursor)) {
+                        name
+                    }
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("cursor is only allowed on top level entities");
+    }
+
     #[test]
     fn filter() {
         let mut data_model = DataModel::new();
@@ -917,18 +1240,15 @@ mod tests {
     }
 
     #[test]
-    //test variable name reuse and internalised string
-    fn positional_param() {
+    fn boolean_filter() {
         let mut data_model = DataModel::new();
         data_model
             .update(
                 "
             ns {
                 Person {
-                    name : String ,
-                    surname : String,
-                    pseudo : String,
-                    is_human : Boolean,
+                    name : String,
+                    age : Integer,
                 }
             }
         ",
@@ -938,12 +1258,10 @@ mod tests {
         let mutation = MutationParser::parse(
             r#"
             mutate {
-                ns.Person {
-                    name : "John"
-                    surname : "John"
-                    pseudo : "John"
-                    is_human : true
-                }
+                P1: ns.Person { name:"a" age:5 }
+                P2: ns.Person { name:"a" age:20 }
+                P3: ns.Person { name:"b" age:30 }
+                P4: ns.Person { name:"c" age:40 }
             } "#,
             &data_model,
         )
@@ -956,28 +1274,402 @@ mod tests {
         let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
         mutation_query.write(&conn).unwrap();
 
-        let query_parser = QueryParser::parse(
-            r#"
-            query sample{
-                ns.Person (name = $name, surname = "John", surname = $name, is_human = true){
-                    name
-                    surname
-                    pseudo
-                    is_human
-                }
-            }
-        "#,
-            &data_model,
-        )
-        .unwrap();
-
-        let query = PreparedQueries::build(&query_parser).unwrap();
-        let mut param = Parameters::new();
-        param.add("name", "John".to_string()).unwrap();
-        let mut sql = Query {
-            parameters: param,
-            parser: Arc::new(query_parser),
-            sql_queries: Arc::new(query),
+        let read = |query: &str, param: Parameters| {
+            let query_parser = QueryParser::parse(query, &data_model).unwrap();
+            let query = PreparedQueries::build(&query_parser).unwrap();
+            let mut sql = Query {
+                parameters: param,
+                parser: Arc::new(query_parser),
+                sql_queries: Arc::new(query),
+            };
+            sql.read(&conn).unwrap()
+        };
+
+        let result = read(
+            r#"query {
+                ns.Person(or(name = "a", name = "b"), age > 10, order_by(age asc)) { name age }
+            }"#,
+            Parameters::new(),
+        );
+        let expected =
+            "{\n\"ns.Person\":[{\"name\":\"a\",\"age\":20},{\"name\":\"b\",\"age\":30}]\n}";
+        assert_eq!(expected, result);
+
+        let result = read(
+            r#"query {
+                ns.Person(not(name = "a"), order_by(age asc)) { name }
+            }"#,
+            Parameters::new(),
+        );
+        let expected = "{\n\"ns.Person\":[{\"name\":\"b\"},{\"name\":\"c\"}]\n}";
+        assert_eq!(expected, result);
+
+        //groups can be nested and use variables
+        let mut param = Parameters::new();
+        param.add("name", "a".to_string()).unwrap();
+        param.add("age", 35).unwrap();
+        let result = read(
+            r#"query {
+                ns.Person(
+                    or(and(name = $name, age < 10), not(age < $age, name != "b")),
+                    order_by(age asc)
+                ) { name age }
+            }"#,
+            param,
+        );
+        let expected = "{\n\"ns.Person\":[{\"name\":\"a\",\"age\":5},{\"name\":\"b\",\"age\":30},{\"name\":\"c\",\"age\":40}]\n}";
+        assert_eq!(expected, result);
+
+        QueryParser::parse(
+            r#"query { ns.Person(or(name = "a", unknown = "b")) { name } }"#,
+            &data_model,
+        )
+        .expect_err("unknown field");
+
+        QueryParser::parse(
+            r#"query { ns.Person(or(name = "a", count > 2)) { name count: count() } }"#,
+            &data_model,
+        )
+        .expect_err("aggregates are filtered after the grouping");
+    }
+
+    #[test]
+    fn in_and_between_filter() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            ns {
+                Person {
+                    name : String,
+                    age : Integer,
+                    weight : Float,
+                    birth : DateTime,
+                    mood : Enum(\"happy\", \"sad\", \"angry\") default \"happy\",
+                }
+            }
+        ",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                P1: ns.Person { name:"a" age:5 weight:20.5 birth:"2000-01-01T00:00:00Z" }
+                P2: ns.Person { name:"b" age:20 weight:60.0 birth:"2010-01-01T00:00:00Z" mood:"sad" }
+                P3: ns.Person { name:"c" age:30 weight:70.0 birth:"2020-01-01T00:00:00Z" mood:"angry" }
+                P4: ns.Person { name:"d" age:40 weight:80.0 birth:"2024-01-01T00:00:00Z" }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let read = |query: &str, param: Parameters| {
+            let query_parser = QueryParser::parse(query, &data_model).unwrap();
+            let query = PreparedQueries::build(&query_parser).unwrap();
+            let mut sql = Query {
+                parameters: param,
+                parser: Arc::new(query_parser),
+                sql_queries: Arc::new(query),
+            };
+            let result = sql.read(&conn).unwrap();
+            let mut parser = ResultParser::new(&result).unwrap();
+            #[derive(Deserialize)]
+            struct Person {
+                name: String,
+            }
+            let persons: Vec<Person> = parser.take_array("ns.Person").unwrap();
+            persons
+                .into_iter()
+                .map(|p| p.name)
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+
+        let mut param = Parameters::new();
+        param.add("b", "b".to_string()).unwrap();
+        assert_eq!(
+            "a,b,d",
+            read(
+                r#"query { ns.Person(name in ["a", $b, "d"], order_by(name asc)) { name } }"#,
+                param
+            )
+        );
+
+        let mut param = Parameters::new();
+        param.add("low", 20).unwrap();
+        assert_eq!(
+            "b,c",
+            read(
+                r#"query { ns.Person(age between($low, 30), order_by(name asc)) { name } }"#,
+                param
+            )
+        );
+
+        //the values are converted like the other filters
+        assert_eq!(
+            "b,c",
+            read(
+                r#"query { ns.Person(birth between("2005-01-01T00:00:00Z", "2020-01-01T00:00:00Z"), weight in [60, 70.0], order_by(name asc)) { name } }"#,
+                Parameters::new()
+            )
+        );
+        assert_eq!(
+            "a,c,d",
+            read(
+                r#"query { ns.Person(mood in ["happy", "angry"], order_by(name asc)) { name } }"#,
+                Parameters::new()
+            )
+        );
+        assert_eq!(
+            "a,d",
+            read(
+                r#"query { ns.Person(not(age between(10, 30)), order_by(name asc)) { name } }"#,
+                Parameters::new()
+            )
+        );
+
+        //array parameters
+        let mut param = Parameters::new();
+        param.add("ages", vec![5i64, 40]).unwrap();
+        param.add("weights", vec![20.5f64, 80.0]).unwrap();
+        param
+            .add(
+                "births",
+                vec![
+                    "2000-01-01T00:00:00Z".to_string(),
+                    "2024-01-01T00:00:00Z".to_string(),
+                ],
+            )
+            .unwrap();
+        assert_eq!(
+            "a,d",
+            read(
+                r#"query { ns.Person(age in $ages, weight in $weights, birth in $births, order_by(name asc)) { name } }"#,
+                param
+            )
+        );
+
+        QueryParser::parse(r#"query { ns.Person(age in ["a"]) { name } }"#, &data_model)
+            .expect_err("invalid value type");
+
+        QueryParser::parse(
+            r#"query { ns.Person(name between(1)) { name } }"#,
+            &data_model,
+        )
+        .expect_err("between requires two values");
+
+        QueryParser::parse(r#"query { ns.Person(name in []) { name } }"#, &data_model)
+            .expect_err("the list cannot be empty");
+    }
+
+    #[test]
+    fn string_matching_filter() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            ns {
+                Person {
+                    name : String,
+                    nick : String default \"a_b\",
+                    age : Integer,
+                }
+            }
+        ",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                P1: ns.Person { name:"Alice" age:5 }
+                P2: ns.Person { name:"alicia" nick:"a%b" age:20 }
+                P3: ns.Person { name:"Bob_Ali" nick:"abb" age:30 }
+                P4: ns.Person { name:"100% Ali" nick:"ab" age:40 }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let read = |query: &str, param: Parameters| {
+            let query_parser = QueryParser::parse(query, &data_model).unwrap();
+            let query = PreparedQueries::build(&query_parser).unwrap();
+            let mut sql = Query {
+                parameters: param,
+                parser: Arc::new(query_parser),
+                sql_queries: Arc::new(query),
+            };
+            let result = sql.read(&conn).unwrap();
+            let mut parser = ResultParser::new(&result).unwrap();
+            #[derive(Deserialize)]
+            struct Person {
+                name: String,
+            }
+            let persons: Vec<Person> = parser.take_array("ns.Person").unwrap();
+            persons
+                .into_iter()
+                .map(|p| p.name)
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+
+        //ASCII characters are matched without case sensitivity
+        let mut param = Parameters::new();
+        param.add("prefix", "ali".to_string()).unwrap();
+        assert_eq!(
+            "Alice,alicia",
+            read(
+                r#"query { ns.Person(name starts_with $prefix, order_by(age asc)) { name } }"#,
+                param
+            )
+        );
+        assert_eq!(
+            "Alice,alicia,Bob_Ali,100% Ali",
+            read(
+                r#"query { ns.Person(name contains "ali", order_by(age asc)) { name } }"#,
+                Parameters::new()
+            )
+        );
+
+        //wildcards are escaped by starts_with and contains
+        assert_eq!(
+            "100% Ali",
+            read(
+                r#"query { ns.Person(name contains "0% ", order_by(age asc)) { name } }"#,
+                Parameters::new()
+            )
+        );
+        let mut param = Parameters::new();
+        param.add("part", "b_".to_string()).unwrap();
+        assert_eq!(
+            "Bob_Ali",
+            read(
+                r#"query { ns.Person(name contains $part, order_by(age asc)) { name } }"#,
+                param
+            )
+        );
+
+        //like uses the provided pattern
+        assert_eq!(
+            "Alice,alicia",
+            read(
+                r#"query { ns.Person(name like "_lic%", order_by(age asc)) { name } }"#,
+                Parameters::new()
+            )
+        );
+        let mut param = Parameters::new();
+        param.add("pattern", "%\\%%".to_string()).unwrap();
+        assert_eq!(
+            "100% Ali",
+            read(
+                r#"query { ns.Person(name like $pattern, order_by(age asc)) { name } }"#,
+                param
+            )
+        );
+
+        //default values are matched
+        assert_eq!(
+            "Bob_Ali,100% Ali",
+            read(
+                r#"query { ns.Person(nick starts_with "ab", order_by(age asc)) { name } }"#,
+                Parameters::new()
+            )
+        );
+        assert_eq!(
+            "Alice",
+            read(
+                r#"query { ns.Person(nick contains "_", order_by(age asc)) { name } }"#,
+                Parameters::new()
+            )
+        );
+
+        QueryParser::parse(
+            r#"query { ns.Person(age starts_with "1") { name } }"#,
+            &data_model,
+        )
+        .expect_err("string matching requires a String field");
+
+        QueryParser::parse(r#"query { ns.Person(name like 12) { name } }"#, &data_model)
+            .expect_err("string matching requires a String value");
+    }
+
+    #[test]
+    //test variable name reuse and internalised string
+    fn positional_param() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            ns {
+                Person {
+                    name : String ,
+                    surname : String,
+                    pseudo : String,
+                    is_human : Boolean,
+                }
+            }
+        ",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                ns.Person {
+                    name : "John"
+                    surname : "John"
+                    pseudo : "John"
+                    is_human : true
+                }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                ns.Person (name = $name, surname = "John", surname = $name, is_human = true){
+                    name
+                    surname
+                    pseudo
+                    is_human
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut param = Parameters::new();
+        param.add("name", "John".to_string()).unwrap();
+        let mut sql = Query {
+            parameters: param,
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
         };
 
         let result = sql.read(&conn).unwrap();
@@ -1122,89 +1814,280 @@ mod tests {
     }
 
     #[test]
-    fn search() {
+    fn order_by_json_path() {
         let mut data_model = DataModel::new();
         data_model
             .update(
-                "
-            ns {
-                Person {
+                "{
+                Task {
                     name : String,
-                    comment : String,
+                    data : Json,
                 }
-            }
-        ",
+            }",
             )
             .unwrap();
 
         let mutation = MutationParser::parse(
             r#"
             mutate {
-                P1: ns.Person { name:"John" comment:"Lorem ipsum sit doler et ames" }
-                P2: ns.Person { name:"Alice" comment:"Lorem lorem ipsum " }
-                P3: ns.Person { name:"Bob" comment:"A completely different comment" }
+                T1: Task { name:"A" data:"{\"priority\":1}" }
+                T2: Task { name:"B" data:"{\"priority\":3}" }
+                T3: Task { name:"C" data:"{\"priority\":3}" }
+                T4: Task { name:"A" data:"{\"priority\":1}" }
+                T5: Task { name:"B" data:"{\"priority\":2}" }
             } "#,
             &data_model,
         )
         .unwrap();
         let conn = Connection::open_in_memory().unwrap();
         prepare_connection(&conn).unwrap();
+        let mut mutation_query =
+            MutationQuery::execute(&mut Parameters::new(), Arc::new(mutation), &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let read = |query: &str, parameters: Parameters| {
+            let query_parser = QueryParser::parse(query, &data_model).unwrap();
+            let query = PreparedQueries::build(&query_parser).unwrap();
+            let mut sql = Query {
+                parameters,
+                parser: Arc::new(query_parser),
+                sql_queries: Arc::new(query),
+            };
+            sql.read(&conn).unwrap()
+        };
+
+        let result = read(
+            "query { Task(order_by(data->$.priority desc, name asc)) { name p: data->$.priority } }",
+            Parameters::new(),
+        );
+        let expected = "{\n\"Task\":[{\"name\":\"B\",\"p\":3},{\"name\":\"C\",\"p\":3},{\"name\":\"B\",\"p\":2},{\"name\":\"A\",\"p\":1},{\"name\":\"A\",\"p\":1}]\n}";
+        assert_eq!(expected, result);
 
         let mut param = Parameters::new();
-        let mutation = Arc::new(mutation);
-        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
-        mutation_query.write(&conn).unwrap();
+        param.add("priority", 3).unwrap();
+        let result = read(
+            r#"query { Task(order_by(data->$.priority desc, name asc), after($priority, "B")) { name } }"#,
+            param,
+        );
+        let expected =
+            "{\n\"Task\":[{\"name\":\"C\"},{\"name\":\"B\"},{\"name\":\"A\"},{\"name\":\"A\"}]\n}";
+        assert_eq!(expected, result);
 
-        let query_parser = QueryParser::parse(
-            r#"
-            query sample{
-                ns.Person(search("ames")) {
-                    name
-                    comment
-                }
+        let cursor_query = Arc::new(
+            QueryParser::parse(
+                "query { Task(order_by(data->$.priority desc, name asc, id asc), first 2, cursor($cursor)) { name } }",
+                &data_model,
+            )
+            .unwrap(),
+        );
+        let prepared = Arc::new(PreparedQueries::build(&cursor_query).unwrap());
+        #[derive(Deserialize)]
+        struct Task {
+            name: String,
+        }
+        let mut pages = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut param = Parameters::new();
+            param.add("cursor", cursor.clone()).unwrap();
+            let mut sql = Query {
+                parameters: param,
+                parser: cursor_query.clone(),
+                sql_queries: prepared.clone(),
+            };
+            let result = sql.read(&conn).unwrap();
+            let mut parser = ResultParser::new(&result).unwrap();
+            let tasks: Vec<Task> = parser.take_array("Task").unwrap();
+            pages.push(tasks.into_iter().map(|t| t.name).collect::<Vec<String>>());
+            cursor = parser.take_object("Task_next_cursor").unwrap();
+            if cursor.is_none() {
+                break;
             }
-        "#,
-            &data_model,
-        )
-        .unwrap();
+        }
+        assert_eq!(pages, vec![vec!["B", "C"], vec!["B", "A"], vec!["A"]]);
 
-        let query = PreparedQueries::build(&query_parser).unwrap();
-        let param = Parameters::new();
-        let mut sql = Query {
-            parameters: param,
-            parser: Arc::new(query_parser),
-            sql_queries: Arc::new(query),
-        };
+        //the json path is added to the grouping of aggregate queries
+        let aggregate = "query { Task(order_by(data->$.priority desc, count desc, name asc)) { name count: count() } }";
+        let result = read(aggregate, Parameters::new());
+        let expected = "{\n\"Task\":[{\"name\":\"B\",\"count\":1},{\"name\":\"C\",\"count\":1},{\"name\":\"B\",\"count\":1},{\"name\":\"A\",\"count\":2}]\n}";
+        assert_eq!(expected, result);
 
-        let result = sql.read(&conn).unwrap();
+        let result = read(
+            r#"query { Task(order_by(data->$.priority desc, count desc, name asc), after(3, 1, "B")) { name count: count() } }"#,
+            Parameters::new(),
+        );
+        let expected = "{\n\"Task\":[{\"name\":\"C\",\"count\":1},{\"name\":\"B\",\"count\":1},{\"name\":\"A\",\"count\":2}]\n}";
+        assert_eq!(expected, result);
 
-        let expected =
-            "{\n\"ns.Person\":[{\"name\":\"John\",\"comment\":\"Lorem ipsum sit doler et ames\"}]\n}";
+        let result = read(
+            r#"query { Task(order_by(data->$.priority asc, count desc), before(2, 1)) { count: count() } }"#,
+            Parameters::new(),
+        );
+        let expected = "{\n\"Task\":[{\"count\":2}]\n}";
         assert_eq!(expected, result);
+
+        QueryParser::parse(
+            "query { Task(order_by(name->$.priority desc)) { name } }",
+            &data_model,
+        )
+        .expect_err("name is not a Json field");
     }
 
     #[test]
-    fn disable_search() {
+    fn group_by_aggregate() {
         let mut data_model = DataModel::new();
         data_model
             .update(
                 "
-            ns {
-                Person (no_full_text_index){
-                    name : String,
-                    comment : String,
-                }
+        ns{
+            Person {
+                age : Integer,
+                weight : Float,
+                nat: String,
             }
+        }
         ",
             )
             .unwrap();
 
         let mutation = MutationParser::parse(
             r#"
-            mutate {
-                P1: ns.Person { name:"John" comment:"Lorem ipsum sit doler et ames" }
-                P2: ns.Person { name:"Alice" comment:"Lorem lorem ipsum " }
-                P3: ns.Person { name:"Bob" comment:"A completely different comment" }
+           mutate {
+                P1: ns.Person { age:24 weight:45 nat:"us" }
+                P2: ns.Person { age:12 weight:23 nat:"us" }
+                P3: ns.Person { age:45 weight:86 nat:"fr" }
+                P4: ns.Person { age:34 weight:43 nat:"fr" }
+                P5: ns.Person { age:54 weight:70 nat:"sa" }
+                P6: ns.Person { age:67 weight:85 nat:"sa" }
+                P7: ns.Person { age:72 weight:65 nat:"sa" }
+                P8: ns.Person { age:24 weight:95 nat:"en" }
+                P9: ns.Person { age:1 weight:52 nat:"en" }
+                P10: ns.Person { age:45 weight:65 nat:"en" }
+                P11: ns.Person { age:24 weight:75 nat:"en" }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+        let mut mutation_query =
+            MutationQuery::execute(&mut Parameters::new(), Arc::new(mutation), &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let read = |query: &str, parameters: Parameters| {
+            let query_parser = QueryParser::parse(query, &data_model).unwrap();
+            let query = PreparedQueries::build(&query_parser).unwrap();
+            let mut sql = Query {
+                parameters,
+                parser: Arc::new(query_parser),
+                sql_queries: Arc::new(query),
+            };
+            sql.read(&conn).unwrap()
+        };
+
+        //the grouping field does not have to be selected
+        let result = read(
+            "query { ns.Person(group_by(nat), order_by(count desc, sum desc)) { count: count() sum: sum(weight) } }",
+            Parameters::new(),
+        );
+        let expected = "{\n\"ns.Person\":[{\"count\":4,\"sum\":287.0},{\"count\":3,\"sum\":220.0},{\"count\":2,\"sum\":129.0},{\"count\":2,\"sum\":68.0}]\n}";
+        assert_eq!(expected, result);
+
+        let result = read(
+            "query { ns.Person(group_by(nat, age), count > 1) { nat age count: count() } }",
+            Parameters::new(),
+        );
+        let expected = "{\n\"ns.Person\":[{\"nat\":\"en\",\"age\":24,\"count\":2}]\n}";
+        assert_eq!(expected, result);
+
+        //every aggregate function can be filtered after the grouping
+        let mut param = Parameters::new();
+        param.add("sum", 200.0).unwrap();
+        let result = read(
+            "query { ns.Person(group_by(nat), sum > $sum, order_by(sum asc)) { nat sum: sum(weight) } }",
+            param,
+        );
+        let expected =
+            "{\n\"ns.Person\":[{\"nat\":\"sa\",\"sum\":220.0},{\"nat\":\"en\",\"sum\":287.0}]\n}";
+        assert_eq!(expected, result);
+
+        let result = read(
+            "query { ns.Person(group_by(nat), avg >= 70.0, max < 90, order_by(nat asc)) { nat avg: avg(weight) max: max(weight) } }",
+            Parameters::new(),
+        );
+        let expected =
+            "{\n\"ns.Person\":[{\"nat\":\"sa\",\"avg\":73.3333333333333,\"max\":85.0}]\n}";
+        assert_eq!(expected, result);
+
+        let result = read(
+            "query { ns.Person(group_by(nat), min < 30) { nat min: min(weight) } }",
+            Parameters::new(),
+        );
+        let expected = "{\n\"ns.Person\":[{\"nat\":\"us\",\"min\":23.0}]\n}";
+        assert_eq!(expected, result);
+
+        QueryParser::parse(
+            "query { ns.Person(group_by(nat)) { nat age count: count() } }",
+            &data_model,
+        )
+        .expect_err("age is not grouped");
+
+        QueryParser::parse("query { ns.Person(group_by(nat)) { nat } }", &data_model)
+            .expect_err("no aggregate function");
+
+        QueryParser::parse(
+            "query { ns.Person(group_by(unknown)) { count: count() } }",
+            &data_model,
+        )
+        .expect_err("unknown field");
+
+        QueryParser::parse(
+            "query { ns.Person(group_by(nat, nat)) { count: count() } }",
+            &data_model,
+        )
+        .expect_err("duplicated field");
+    }
+
+    #[test]
+    fn custom_function() {
+        crate::database::custom_function::register_function("manhattan", 4, |args| {
+            let x = args[0].as_f64().unwrap_or(0.0) - args[2].as_f64().unwrap_or(0.0);
+            let y = args[1].as_f64().unwrap_or(0.0) - args[3].as_f64().unwrap_or(0.0);
+            serde_json::json!(x.abs() + y.abs())
+        })
+        .unwrap();
+        crate::database::custom_function::register_function("greet", 2, |args| {
+            serde_json::json!(format!(
+                "{} {}",
+                args[0].as_str().unwrap_or(""),
+                args[1].as_str().unwrap_or("")
+            ))
+        })
+        .unwrap();
+        crate::database::custom_function::register_function("count", 0, |_| serde_json::json!(0))
+            .expect_err("reserved name");
+
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+        geo{
+            Place {
+                name : String,
+                x : Float,
+                y : Float,
+            }
+        }
+        ",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+           mutate {
+                P1: geo.Place { name:"a" x:1 y:1 }
+                P2: geo.Place { name:"b" x:5 y:-2 }
+                P3: geo.Place { name:"c" x:-1 y:0 }
             } "#,
             &data_model,
         )
@@ -1219,10 +2102,11 @@ mod tests {
 
         let query_parser = QueryParser::parse(
             r#"
-            query sample{
-                ns.Person(search("ames")) {
+            query {
+                geo.Place (dist < $max, order_by(dist desc)) {
                     name
-                    comment
+                    dist: manhattan(x, y, $x, 0)
+                    hello: greet("hello", name)
                 }
             }
         "#,
@@ -1231,41 +2115,57 @@ mod tests {
         .unwrap();
 
         let query = PreparedQueries::build(&query_parser).unwrap();
-        let param = Parameters::new();
+        let mut param = Parameters::new();
+        param.add("x", 1.0).unwrap();
+        param.add("max", 4.0).unwrap();
         let mut sql = Query {
             parameters: param,
             parser: Arc::new(query_parser),
             sql_queries: Arc::new(query),
         };
-
         let result = sql.read(&conn).unwrap();
 
-        let expected = "{\n\"ns.Person\":[]\n}";
+        let expected = "{\n\"geo.Place\":[{\"name\":\"c\",\"dist\":2.0,\"hello\":\"hello c\"},{\"name\":\"a\",\"dist\":1.0,\"hello\":\"hello a\"}]\n}";
         assert_eq!(expected, result);
+
+        QueryParser::parse("query { geo.Place { name dist: unknown(x) } }", &data_model)
+            .expect_err("unknown function");
+
+        QueryParser::parse(
+            "query { geo.Place { name dist: manhattan(x, y) } }",
+            &data_model,
+        )
+        .expect_err("invalid number of arguments");
+
+        QueryParser::parse(
+            "query { geo.Place { dist: manhattan(x, y, name, true) } }",
+            &data_model,
+        )
+        .unwrap();
     }
 
     #[test]
-    fn default_value() {
+    fn search() {
         let mut data_model = DataModel::new();
-
         data_model
             .update(
-                r#"
+                "
             ns {
                 Person {
                     name : String,
-                    def : String default "Lorem ipsum",
+                    comment : String,
                 }
             }
-        "#,
+        ",
             )
             .unwrap();
 
         let mutation = MutationParser::parse(
             r#"
             mutate {
-                P2: ns.Person { name:"Alice"  }
-                P3: ns.Person { name:"Bob" def:"A completely different" }
+                P1: ns.Person { name:"John" comment:"Lorem ipsum sit doler et ames" }
+                P2: ns.Person { name:"Alice" comment:"Lorem lorem ipsum " }
+                P3: ns.Person { name:"Bob" comment:"A completely different comment" }
             } "#,
             &data_model,
         )
@@ -1281,9 +2181,9 @@ mod tests {
         let query_parser = QueryParser::parse(
             r#"
             query sample{
-                ns.Person(order_by(name Asc)) {
+                ns.Person(search("ames")) {
                     name
-                    def
+                    comment
                 }
             }
         "#,
@@ -1298,101 +2198,153 @@ mod tests {
             parser: Arc::new(query_parser),
             sql_queries: Arc::new(query),
         };
+
         let result = sql.read(&conn).unwrap();
 
         let expected =
-            "{\n\"ns.Person\":[{\"name\":\"Alice\",\"def\":\"Lorem ipsum\"},{\"name\":\"Bob\",\"def\":\"A completely different\"}]\n}";
+            "{\n\"ns.Person\":[{\"name\":\"John\",\"comment\":\"Lorem ipsum sit doler et ames\"}]\n}";
         assert_eq!(expected, result);
+    }
 
+    #[test]
+    fn search_rank_and_snippet() {
+        let mut data_model = DataModel::new();
         data_model
             .update(
-                r#"
+                "
             ns {
                 Person {
                     name : String,
-                    def : String default "Lorem ipsum",
-                    newdef : String default "sit met",
+                    comment : String,
+                    age : Integer nullable,
                 }
             }
-        "#,
+        ",
             )
             .unwrap();
 
-        //old rows  will return the default value of the 'newdef' field
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                P1: ns.Person { name:"John" comment:"Some text before the Lorem ipsum sit doler et ames" }
+                P2: ns.Person { name:"Alice" comment:"Lorem lorem ipsum" }
+                P3: ns.Person { name:"Bob" comment:"A completely different comment" }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
         let query_parser = QueryParser::parse(
             r#"
-                query sample{
-                    ns.Person(order_by(name asc)) {
-                        name
-                        def
-                        newdef
-                    }
+            query sample{
+                ns.Person(search($term), order_by(rank, name asc)) {
+                    name
+                    excerpt: snippet(comment, 10)
                 }
-            "#,
+            }
+        "#,
             &data_model,
         )
         .unwrap();
 
         let query = PreparedQueries::build(&query_parser).unwrap();
-        let param = Parameters::new();
+        let mut param = Parameters::new();
+        param.add("term", "lorem".to_string()).unwrap();
         let mut sql = Query {
             parameters: param,
             parser: Arc::new(query_parser),
             sql_queries: Arc::new(query),
         };
+
         let result = sql.read(&conn).unwrap();
-        let expected =
-        "{\n\"ns.Person\":[{\"name\":\"Alice\",\"def\":\"Lorem ipsum\",\"newdef\":\"sit met\"},{\"name\":\"Bob\",\"def\":\"A completely different\",\"newdef\":\"sit met\"}]\n}";
+
+        //the most relevant first, with an excerpt around the searched term
+        let expected = "{\n\"ns.Person\":[{\"name\":\"Alice\",\"excerpt\":\"Lorem lore...\"},{\"name\":\"John\",\"excerpt\":\"... the Lorem ipsu...\"}]\n}";
         assert_eq!(expected, result);
+
+        //rank is the first sort criteria
+        QueryParser::parse(
+            r#"query { ns.Person(search("lorem"), order_by(name asc, rank)) { name } }"#,
+            &data_model,
+        )
+        .expect_err("rank must be first");
+
+        //sort fields require the rank
+        QueryParser::parse(
+            r#"query { ns.Person(search("lorem"), order_by(name asc)) { name } }"#,
+            &data_model,
+        )
+        .expect_err("search is sorted by rank");
+
+        QueryParser::parse(
+            r#"query { ns.Person(order_by(rank)) { name } }"#,
+            &data_model,
+        )
+        .expect_err("rank requires search");
+
+        QueryParser::parse(
+            r#"query { ns.Person { excerpt: snippet(comment, 10) } }"#,
+            &data_model,
+        )
+        .expect_err("snippet requires search");
+
+        QueryParser::parse(
+            r#"query { ns.Person(search("lorem")) { excerpt: snippet(age, 10) } }"#,
+            &data_model,
+        )
+        .expect_err("snippet requires a string field");
     }
 
     #[test]
-    fn json() {
+    fn disable_search() {
         let mut data_model = DataModel::new();
-
         data_model
             .update(
-                r#"
-            ns{
-                Person {
+                "
+            ns {
+                Person (no_full_text_index){
                     name : String,
-                    data : Json,
+                    comment : String,
                 }
-            }"#,
+            }
+        ",
             )
             .unwrap();
 
         let mutation = MutationParser::parse(
             r#"
             mutate {
-                P2: ns.Person { name:"Alice" data:"{\"val\":\"hello json\"}" }
-                P3: ns.Person { name:"Bob" data:"[1,2,3,4]" }
+                P1: ns.Person { name:"John" comment:"Lorem ipsum sit doler et ames" }
+                P2: ns.Person { name:"Alice" comment:"Lorem lorem ipsum " }
+                P3: ns.Person { name:"Bob" comment:"A completely different comment" }
             } "#,
             &data_model,
         )
         .unwrap();
-
-        let mut param = Parameters::new();
-        param
-            .add("data", String::from(r#"{"val":"hello json"}"#))
-            .unwrap();
-
         let conn = Connection::open_in_memory().unwrap();
         prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
         let mutation = Arc::new(mutation);
         let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
         mutation_query.write(&conn).unwrap();
 
         let query_parser = QueryParser::parse(
-            "
+            r#"
             query sample{
-                ns.Person (order_by(name asc)){
+                ns.Person(search("ames")) {
                     name
-                    data
-                    array: data->$.val
+                    comment
                 }
             }
-        ",
+        "#,
             &data_model,
         )
         .unwrap();
@@ -1404,21 +2356,56 @@ mod tests {
             parser: Arc::new(query_parser),
             sql_queries: Arc::new(query),
         };
+
         let result = sql.read(&conn).unwrap();
-        let expected =
-        "{\n\"ns.Person\":[{\"name\":\"Alice\",\"data\":{\"val\":\"hello json\"},\"array\":\"hello json\"},{\"name\":\"Bob\",\"data\":[1,2,3,4],\"array\":null}]\n}";
+
+        let expected = "{\n\"ns.Person\":[]\n}";
         assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn default_value() {
+        let mut data_model = DataModel::new();
+
+        data_model
+            .update(
+                r#"
+            ns {
+                Person {
+                    name : String,
+                    def : String default "Lorem ipsum",
+                }
+            }
+        "#,
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                P2: ns.Person { name:"Alice"  }
+                P3: ns.Person { name:"Bob" def:"A completely different" }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
 
         let query_parser = QueryParser::parse(
-            "
+            r#"
             query sample{
-                ns.Person(order_by(name asc)) {
+                ns.Person(order_by(name Asc)) {
                     name
-                    data
-                    array: data->1
+                    def
                 }
             }
-        ",
+        "#,
             &data_model,
         )
         .unwrap();
@@ -1433,66 +2420,1470 @@ mod tests {
         let result = sql.read(&conn).unwrap();
 
         let expected =
-        "{\n\"ns.Person\":[{\"name\":\"Alice\",\"data\":{\"val\":\"hello json\"},\"array\":null},{\"name\":\"Bob\",\"data\":[1,2,3,4],\"array\":2}]\n}";
+            "{\n\"ns.Person\":[{\"name\":\"Alice\",\"def\":\"Lorem ipsum\"},{\"name\":\"Bob\",\"def\":\"A completely different\"}]\n}";
         assert_eq!(expected, result);
 
-        let query_parser = QueryParser::parse(
-            "
-            query sample{
-                ns.Person (data->1 = 2, order_by(array asc)) {
-                    name
-                    data
-                    array: data->1
+        data_model
+            .update(
+                r#"
+            ns {
+                Person {
+                    name : String,
+                    def : String default "Lorem ipsum",
+                    newdef : String default "sit met",
+                }
+            }
+        "#,
+            )
+            .unwrap();
+
+        //old rows  will return the default value of the 'newdef' field
+        let query_parser = QueryParser::parse(
+            r#"
+                query sample{
+                    ns.Person(order_by(name asc)) {
+                        name
+                        def
+                        newdef
+                    }
+                }
+            "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let param = Parameters::new();
+        let mut sql = Query {
+            parameters: param,
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected =
+        "{\n\"ns.Person\":[{\"name\":\"Alice\",\"def\":\"Lorem ipsum\",\"newdef\":\"sit met\"},{\"name\":\"Bob\",\"def\":\"A completely different\",\"newdef\":\"sit met\"}]\n}";
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn default_from_field() {
+        let mut data_model = DataModel::new();
+
+        data_model
+            .update(
+                r#"
+            ns {
+                Person {
+                    name : String,
+                    display_name : String default name,
+                }
+            }
+        "#,
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                P1: ns.Person { name:"Alice"  }
+                P2: ns.Person { name:$name display_name:"Bobby" }
+                P3: ns.Person { name:$other }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
+        param.add("name", "Bob").unwrap();
+        param.add("other", "Carol").unwrap();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                ns.Person(order_by(name Asc)) {
+                    name
+                    display_name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+
+        let expected =
+            "{\n\"ns.Person\":[{\"name\":\"Alice\",\"display_name\":\"Alice\"},{\"name\":\"Bob\",\"display_name\":\"Bobby\"},{\"name\":\"Carol\",\"display_name\":\"Carol\"}]\n}";
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn enum_field() {
+        let mut data_model = DataModel::new();
+
+        data_model
+            .update(
+                r#"
+            ns {
+                Article {
+                    title : String,
+                    status : Enum("draft", "published", "archived") default "draft",
+                }
+            }
+        "#,
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                A1: ns.Article { title:"first" status:"archived" }
+                A2: ns.Article { title:"second" status:$status }
+                A3: ns.Article { title:"third" }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
+        param.add("status", String::from("published")).unwrap();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let result = mutation_query.result().unwrap();
+        assert!(result.contains("\"status\": \"archived\""));
+        assert!(result.contains("\"status\": \"published\""));
+
+        //values are stored using their position
+        let stored: i64 = conn
+            .query_row(
+                "SELECT _json->>'$.33' FROM _node WHERE _json->>'$.32' = 'first'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(2, stored);
+
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                ns.Article(order_by(status asc)) {
+                    title
+                    status
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"ns.Article\":[{\"title\":\"third\",\"status\":\"draft\"},{\"title\":\"second\",\"status\":\"published\"},{\"title\":\"first\",\"status\":\"archived\"}]\n}";
+        assert_eq!(expected, result);
+
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                draft: ns.Article(status = "draft") {
+                    title
+                }
+                not_draft: ns.Article(status != $status, order_by(title asc)) {
+                    title
+                }
+                after: ns.Article(order_by(status asc), after("draft")) {
+                    title
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut param = Parameters::new();
+        param.add("status", String::from("draft")).unwrap();
+        let mut sql = Query {
+            parameters: param,
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"draft\":[{\"title\":\"third\"}],\n\"not_draft\":[{\"title\":\"first\"},{\"title\":\"second\"}],\n\"after\":[{\"title\":\"second\"},{\"title\":\"first\"}]\n}";
+        assert_eq!(expected, result);
+
+        QueryParser::parse(
+            r#"
+            query sample{
+                ns.Article(status = "deleted") {
+                    title
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("'deleted' is not a valid value");
+
+        MutationParser::parse(
+            r#"
+            mutate {
+                ns.Article { title:"fourth" status:"deleted" }
+            } "#,
+            &data_model,
+        )
+        .expect_err("'deleted' is not a valid value");
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                ns.Article { title:"fourth" status:$status }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let mut param = Parameters::new();
+        param.add("status", String::from("deleted")).unwrap();
+        MutationQuery::execute(&mut param, Arc::new(mutation), &conn)
+            .expect_err("'deleted' is not a valid value");
+    }
+
+    #[test]
+    fn collation() {
+        let mut data_model = DataModel::new();
+
+        data_model
+            .update(
+                r#"
+            {
+                Contact {
+                    name : String collate nocase,
+                    code : String,
+                    label : String default "none" collate nocase,
+                }
+            }
+        "#,
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                C1: Contact { name:"bob" code:"bob" }
+                C2: Contact { name:"Alice" code:"Alice" label:"Friend" }
+                C3: Contact { name:"carol" code:"Carol" }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                by_name: Contact(order_by(name asc)) {
+                    name
+                }
+                by_code: Contact(order_by(code asc)) {
+                    code
+                }
+                equal: Contact(name = "ALICE") {
+                    name
+                }
+                equal_code: Contact(code = "ALICE") {
+                    code
+                }
+                no_label: Contact(label = "NONE", order_by(name desc)) {
+                    name
+                }
+                after: Contact(order_by(name asc), after("BOB")) {
+                    name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"by_name\":[{\"name\":\"Alice\"},{\"name\":\"bob\"},{\"name\":\"carol\"}],\n\"by_code\":[{\"code\":\"Alice\"},{\"code\":\"Carol\"},{\"code\":\"bob\"}],\n\"equal\":[{\"name\":\"Alice\"}],\n\"equal_code\":[],\n\"no_label\":[{\"name\":\"carol\"},{\"name\":\"bob\"}],\n\"after\":[{\"name\":\"carol\"}]\n}";
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn datetime_field() {
+        let mut data_model = DataModel::new();
+
+        data_model
+            .update(
+                r#"
+            ns {
+                Event {
+                    title : String,
+                    start : DateTime,
+                    end : DateTime nullable,
+                    created : DateTime default "2024-01-01T00:00:00Z",
+                }
+            }
+        "#,
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                E1: ns.Event { title:"rfc3339" start:"2024-05-15T10:00:00+02:00" }
+                E2: ns.Event { title:"millis" start:1715760000000 end:$end}
+                E3: ns.Event { title:"param" start:$start }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
+        param
+            .add("start", String::from("2024-05-16T08:00:00Z"))
+            .unwrap();
+        param.add("end", 1715760000001).unwrap();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        //dates are stored in milliseconds since unix epoch
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                ns.Event(order_by(start asc, title asc)) {
+                    title
+                    start
+                    created
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"ns.Event\":[{\"title\":\"millis\",\"start\":1715760000000,\"created\":1704067200000},{\"title\":\"rfc3339\",\"start\":1715760000000,\"created\":1704067200000},{\"title\":\"param\",\"start\":1715846400000,\"created\":1704067200000}]\n}";
+        assert_eq!(expected, result);
+
+        //range filters accepts both formats
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                ns.Event(start >= "2024-05-16T00:00:00Z", start < $end) {
+                    title
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+        let (_, end) = crate::datetime_range(1715846400000, crate::TimeUnit::Day);
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut param = Parameters::new();
+        param.add("end", end).unwrap();
+        let mut sql = Query {
+            parameters: param,
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"ns.Event\":[{\"title\":\"param\"}]\n}";
+        assert_eq!(expected, result);
+
+        MutationParser::parse(
+            r#"
+            mutate {
+                ns.Event { title:"invalid" start:"yesterday" }
+            } "#,
+            &data_model,
+        )
+        .expect_err("'yesterday' is not a valid date");
+
+        MutationParser::parse(
+            r#"
+            mutate {
+                ns.Event { title:"invalid" start:1.5 }
+            } "#,
+            &data_model,
+        )
+        .expect_err("dates cannot be float");
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                ns.Event { title:"invalid" start:$start }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let mut param = Parameters::new();
+        param
+            .add("start", String::from("2024-13-01T00:00:00Z"))
+            .unwrap();
+        MutationQuery::execute(&mut param, Arc::new(mutation), &conn).expect_err("invalid month");
+    }
+
+    #[test]
+    fn date_helpers() {
+        let mut data_model = DataModel::new();
+
+        data_model
+            .update(
+                r#"
+            ns {
+                Task {
+                    title : String,
+                    due : Date,
+                    done : DateTime nullable,
+                }
+            }
+        "#,
+            )
+            .unwrap();
+
+        let day = 24 * 60 * 60 * 1000;
+        let today = crate::truncate_datetime(crate::date_utils::now(), crate::TimeUnit::Day);
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                T1: ns.Task { title:"old" due:"2024-05-15" done:"2024-05-15T10:30:15.250+02:00" }
+                T2: ns.Task { title:"past" due:$past done:$done }
+                T3: ns.Task { title:"next" due:$next }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
+        //Date values are truncated to the start of the UTC day
+        param.add("past", today - 3 * day + 1234).unwrap();
+        param.add("done", crate::date_utils::now() - 1000).unwrap();
+        param
+            .add("next", crate::format_date(today + 2 * day).unwrap())
+            .unwrap();
+        let mut mutation_query =
+            MutationQuery::execute(&mut param, Arc::new(mutation), &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let read = |query: &str| {
+            let query_parser = QueryParser::parse(query, &data_model).unwrap();
+            let query = PreparedQueries::build(&query_parser).unwrap();
+            let mut param = Parameters::new();
+            param.add("days", 7).unwrap();
+            let mut sql = Query {
+                parameters: param,
+                parser: Arc::new(query_parser),
+                sql_queries: Arc::new(query),
+            };
+            sql.read(&conn).unwrap()
+        };
+
+        let result = read(
+            r#"
+            query sample{
+                ns.Task(title = "old") {
+                    due
+                    due_text: format_date(due)
+                    done_text: format_date(done)
+                }
+            }
+        "#,
+        );
+        let expected = "{\n\"ns.Task\":[{\"due\":1715731200000,\"due_text\":\"2024-05-15\",\"done_text\":\"2024-05-15T08:30:15.250Z\"}]\n}";
+        assert_eq!(expected, result);
+
+        let result = read(
+            r#"
+            query sample{
+                ns.Task(title = "past") {
+                    due
+                }
+            }
+        "#,
+        );
+        let expected = format!("{{\n\"ns.Task\":[{{\"due\":{}}}]\n}}", today - 3 * day);
+        assert_eq!(expected, result);
+
+        let result = read(
+            r#"
+            query sample{
+                ns.Task(within_days(due, $days), order_by(title asc)) {
+                    title
+                }
+            }
+        "#,
+        );
+        assert_eq!("{\n\"ns.Task\":[{\"title\":\"past\"}]\n}", result);
+
+        let result = read(
+            r#"
+            query sample{
+                ns.Task(within_days(due, -7)) {
+                    title
+                }
+            }
+        "#,
+        );
+        assert_eq!("{\n\"ns.Task\":[{\"title\":\"next\"}]\n}", result);
+
+        let result = read(
+            r#"
+            query sample{
+                ns.Task(within_days(done, 1)) {
+                    title
+                }
+            }
+        "#,
+        );
+        assert_eq!("{\n\"ns.Task\":[{\"title\":\"past\"}]\n}", result);
+
+        let result = read(
+            r#"
+            query sample{
+                ns.Task(within_days(cdate, 1), order_by(title asc)) {
+                    title
+                }
+            }
+        "#,
+        );
+        assert_eq!(
+            "{\n\"ns.Task\":[{\"title\":\"next\"},{\"title\":\"old\"},{\"title\":\"past\"}]\n}",
+            result
+        );
+
+        QueryParser::parse(
+            r#"
+            query sample{
+                ns.Task(within_days(title, 1)) {
+                    title
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("within_days requires a date");
+
+        QueryParser::parse(
+            r#"
+            query sample{
+                ns.Task {
+                    text: format_date(title)
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("format_date requires a date");
+
+        MutationParser::parse(
+            r#"
+            mutate {
+                ns.Task { title:"invalid" due:"2024-02-30" }
+            } "#,
+            &data_model,
+        )
+        .expect_err("invalid date");
+    }
+
+    #[test]
+    fn computed_fields() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                r#"
+            ns {
+                Item {
+                    name : String,
+                    price : Float,
+                    quantity : Integer,
+                    discount : Float default 0.5,
+                    due : Date nullable,
+                }
+            }
+        "#,
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                I1: ns.Item { name:"pen" price:1.5 quantity:4 discount:0.25 }
+                I2: ns.Item { name:"book" price:12.0 quantity:2 due:"2024-05-15" }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+        let mut mutation_query =
+            MutationQuery::execute(&mut Parameters::new(), Arc::new(mutation), &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let read = |query: &str| {
+            let query_parser = QueryParser::parse(query, &data_model).unwrap();
+            let query = PreparedQueries::build(&query_parser).unwrap();
+            let mut sql = Query {
+                parameters: Parameters::new(),
+                parser: Arc::new(query_parser),
+                sql_queries: Arc::new(query),
+            };
+            sql.read(&conn).unwrap()
+        };
+
+        let result = read(
+            r#"
+            query sample{
+                ns.Item(order_by(total desc)) {
+                    name
+                    total: price * quantity
+                    discounted: (price - discount) * quantity
+                    age_days: (now() - cdate) / 86400000
+                    neg: (quantity*-1)
+                }
+            }
+        "#,
+        );
+        let expected = "{\n\"ns.Item\":[{\"name\":\"book\",\"total\":24.0,\"discounted\":23.0,\"age_days\":0,\"neg\":-2},{\"name\":\"pen\",\"total\":6.0,\"discounted\":5.0,\"age_days\":0,\"neg\":-4}]\n}";
+        assert_eq!(expected, result);
+
+        //null values propagate
+        let result = read(
+            r#"
+            query sample{
+                ns.Item(total > 10) {
+                    name
+                    total: price * quantity
+                    due_days: (now() - due) / 86400000 + 1
+                }
+            }
+        "#,
+        );
+        assert!(
+            result.starts_with("{\n\"ns.Item\":[{\"name\":\"book\",\"total\":24.0,\"due_days\":")
+        );
+
+        let result = read(
+            r#"
+            query sample{
+                ns.Item(name = "pen") {
+                    due_days: due - 1
+                }
+            }
+        "#,
+        );
+        assert_eq!("{\n\"ns.Item\":[{\"due_days\":null}]\n}", result);
+
+        QueryParser::parse(
+            r#"
+            query sample{
+                ns.Item {
+                    invalid: name * 2
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("computed fields requires numeric fields");
+
+        QueryParser::parse(
+            r#"
+            query sample{
+                ns.Item {
+                    invalid: price * unknown
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("unknown field");
+    }
+
+    #[test]
+    fn result_shape() {
+        let mut data_model = DataModel::new();
+
+        data_model
+            .update(
+                r#"
+            ns {
+                Person {
+                    name : String,
+                    birthYear : Integer nullable,
+                    pets : [ns.Pet] nullable,
+                }
+
+                Pet {
+                    pet_name : String,
+                }
+            }
+        "#,
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                P1: ns.Person { name:"Alice" birthYear:1990 pets:[{pet_name:"Rex"}] }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query =
+            MutationQuery::execute(&mut Parameters::new(), mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample @flatten @entity_names @snake_case @ordered {
+                zPersons: ns.Person(order_by(birthYear desc), birthYear > 1900) {
+                    name
+                    birthYear
+                    pets{ petName:pet_name }
+                }
+                aPets: ns.Pet {
+                    pet_name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"a_pets\":{\"_entity\":\"ns.Pet\",\"pet_name\":\"Rex\"},\n\"z_persons\":{\"_entity\":\"ns.Person\",\"birth_year\":1990,\"name\":\"Alice\",\"pets\":{\"_entity\":\"ns.Pet\",\"pet_name\":\"Rex\"}}\n}";
+        assert_eq!(expected, result);
+
+        let query_parser = QueryParser::parse(
+            r#"
+            query @camel_case {
+                ns.Pet {
+                    pet_name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"ns.Pet\":[{\"petName\":\"Rex\"}]\n}";
+        assert_eq!(expected, result);
+
+        QueryParser::parse(
+            r#"
+            query @camel_case @snake_case {
+                ns.Pet { pet_name }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("conflicting key cases");
+
+        QueryParser::parse(
+            r#"
+            query @pretty {
+                ns.Pet { pet_name }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("unknown directive");
+    }
+
+    #[test]
+    fn json() {
+        let mut data_model = DataModel::new();
+
+        data_model
+            .update(
+                r#"
+            ns{
+                Person {
+                    name : String,
+                    data : Json,
+                }
+            }"#,
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                P2: ns.Person { name:"Alice" data:"{\"val\":\"hello json\"}" }
+                P3: ns.Person { name:"Bob" data:"[1,2,3,4]" }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let mut param = Parameters::new();
+        param
+            .add("data", String::from(r#"{"val":"hello json"}"#))
+            .unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let query_parser = QueryParser::parse(
+            "
+            query sample{
+                ns.Person (order_by(name asc)){
+                    name
+                    data
+                    array: data->$.val
+                }
+            }
+        ",
+            &data_model,
+        )
+        .unwrap();
+
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let param = Parameters::new();
+        let mut sql = Query {
+            parameters: param,
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected =
+        "{\n\"ns.Person\":[{\"name\":\"Alice\",\"data\":{\"val\":\"hello json\"},\"array\":\"hello json\"},{\"name\":\"Bob\",\"data\":[1,2,3,4],\"array\":null}]\n}";
+        assert_eq!(expected, result);
+
+        let query_parser = QueryParser::parse(
+            "
+            query sample{
+                ns.Person(order_by(name asc)) {
+                    name
+                    data
+                    array: data->1
+                }
+            }
+        ",
+            &data_model,
+        )
+        .unwrap();
+
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let param = Parameters::new();
+        let mut sql = Query {
+            parameters: param,
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+
+        let expected =
+        "{\n\"ns.Person\":[{\"name\":\"Alice\",\"data\":{\"val\":\"hello json\"},\"array\":null},{\"name\":\"Bob\",\"data\":[1,2,3,4],\"array\":2}]\n}";
+        assert_eq!(expected, result);
+
+        let query_parser = QueryParser::parse(
+            "
+            query sample{
+                ns.Person (data->1 = 2, order_by(array asc)) {
+                    name
+                    data
+                    array: data->1
+                }
+            }
+        ",
+            &data_model,
+        )
+        .unwrap();
+
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let param = Parameters::new();
+        let mut sql = Query {
+            parameters: param,
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+
+        let expected = "{\n\"ns.Person\":[{\"name\":\"Bob\",\"data\":[1,2,3,4],\"array\":2}]\n}";
+        assert_eq!(expected, result);
+
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                ns.Person (data->$.val = "hello json", order_by(array asc)) {
+                    name
+                    data
+                    array: data->$.val
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let param = Parameters::new();
+        let mut sql = Query {
+            parameters: param,
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+
+        let result = sql.read(&conn).unwrap();
+
+        let expected =
+        "{\n\"ns.Person\":[{\"name\":\"Alice\",\"data\":{\"val\":\"hello json\"},\"array\":\"hello json\"}]\n}";
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn json_schema_field() {
+        let mut data_model = DataModel::new();
+
+        data_model
+            .update(
+                r#"
+            ns{
+                Person {
+                    name : String,
+                    address : Json("{\"type\":\"object\", \"required\":[\"city\"], \"properties\":{\"city\":{\"type\":\"string\"}}}") nullable,
+                }
+            }"#,
+            )
+            .unwrap();
+
+        MutationParser::parse(
+            r#"
+            mutate {
+                ns.Person { name:"Alice" address:"{\"zip\":\"75000\"}" }
+            } "#,
+            &data_model,
+        )
+        .expect_err("city is required");
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                P1: ns.Person { name:"Alice" address:$address }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let mutation = Arc::new(mutation);
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mut param = Parameters::new();
+        param.add("address", String::from(r#"{"city":1}"#)).unwrap();
+        MutationQuery::execute(&mut param, mutation.clone(), &conn)
+            .expect_err("city must be a string");
+
+        //keys are sorted to get a canonical form
+        let mut param = Parameters::new();
+        param
+            .add(
+                "address",
+                String::from(
+                    r#"{ "zip":"75000", "city":"Paris", "geo":{"lon":2.35, "lat":48.85} }"#,
+                ),
+            )
+            .unwrap();
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let query_parser = QueryParser::parse(
+            "
+            query sample{
+                ns.Person {
+                    address
+                }
+            }
+        ",
+            &data_model,
+        )
+        .unwrap();
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"ns.Person\":[{\"address\":{\"city\":\"Paris\",\"geo\":{\"lat\":48.85,\"lon\":2.35},\"zip\":\"75000\"}}]\n}";
+        assert_eq!(expected, result);
+
+        //nodes received from other peers are validated
+        let entity = data_model.get_entity("ns.Person").unwrap();
+        let short = &entity.get_field("address").unwrap().short_name;
+        let name = &entity.get_field("name").unwrap().short_name;
+        let invalid = format!(r#"{{"{}":"Bob","{}":{{"zip":"1"}}}}"#, name, short);
+        validate_json_for_entity(entity, &Some(invalid)).expect_err("city is required");
+
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                r#"
+            ns{
+                Person {
+                    address : Json("{\"type\":\"object\"}") default "[]",
+                }
+            }"#,
+            )
+            .expect_err("default value does not match the schema");
+    }
+
+    #[test]
+    fn entity_not_null_selection() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            ns {
+                Person {
+                    name : String ,
+                    parents : [ns.Person] ,
+                    pet: ns.Pet ,
+                }
+
+                Pet {
+                    name : String
+                }
+            }
+        ",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                P1: ns.Person {
+                    name : "John"
+                    parents:  [ {name : "John Mother"} ,{ name:"John Father" pet:{ name:"Kiki" }}]
+                    pet: { name:"Truffle"}
+                }
+                P2: ns.Person {
+                    name : "Ada"
+                    parents:  [ {name : "Ada Mother" pet:{ name:"Lulu" }} ,{ name:"Ada Father" pet:{ name:"Waf" }}]
+                }
+
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let mut param = Parameters::new();
+
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                ns.Person (order_by(name asc)) {
+                    name
+                    parents (order_by(name asc)){
+                        name
+                    }
+                    pet {name}
+                    parents_pets : parents (order_by(name asc)) {
+                        name
+                        pet {name}
+                    }
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let param = Parameters::new();
+        let mut sql = Query {
+            parameters: param,
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"ns.Person\":[{\"name\":\"John\",\"parents\":[{\"name\":\"John Father\"},{\"name\":\"John Mother\"}],\"pet\":{\"name\":\"Truffle\"},\"parents_pets\":[{\"name\":\"John Father\",\"pet\":{\"name\":\"Kiki\"}}]}]\n}";
+        assert_eq!(expected, result);
+
+        let query_parser = QueryParser::parse(
+            r#"
+            query sample{
+                ns.Person (order_by(name asc)) {
+                    name
+                    parents (order_by(name asc)){
+                        name
+                    }
+
+                    parents_pets : parents (order_by(name asc)) {
+                        name
+                        pet {name}
+                    }
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let query = PreparedQueries::build(&query_parser).unwrap();
+        let param = Parameters::new();
+        let mut sql = Query {
+            parameters: param,
+            parser: Arc::new(query_parser),
+            sql_queries: Arc::new(query),
+        };
+
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada\",\"parents\":[{\"name\":\"Ada Father\"},{\"name\":\"Ada Mother\"}],\"parents_pets\":[{\"name\":\"Ada Father\",\"pet\":{\"name\":\"Waf\"}},{\"name\":\"Ada Mother\",\"pet\":{\"name\":\"Lulu\"}}]},{\"name\":\"John\",\"parents\":[{\"name\":\"John Father\"},{\"name\":\"John Mother\"}],\"parents_pets\":[{\"name\":\"John Father\",\"pet\":{\"name\":\"Kiki\"}}]}]\n}";
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn exists_filter() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            ns {
+                Person {
+                    name : String ,
+                    parents : [ns.Person] ,
+                    pet: ns.Pet ,
+                }
+
+                Pet {
+                    name : String
+                }
+            }
+        ",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                P1: ns.Person {
+                    name : "John"
+                    parents:  [ {name : "John Mother"} ,{ name:"John Father" pet:{ name:"Kiki" }}]
+                    pet: { name:"Truffle"}
+                }
+                P2: ns.Person {
+                    name : "Ada"
+                    parents:  [ {name : "Ada Mother" pet:{ name:"Lulu" }} ,{ name:"John" }]
+                }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let mut param = Parameters::new();
+
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let read = |query: &str, param: Parameters| {
+            let query_parser = QueryParser::parse(query, &data_model).unwrap();
+            let query = PreparedQueries::build(&query_parser).unwrap();
+            let mut sql = Query {
+                parameters: param,
+                parser: Arc::new(query_parser),
+                sql_queries: Arc::new(query),
+            };
+            sql.read(&conn).unwrap()
+        };
+
+        let result = read(
+            r#"
+            query sample{
+                ns.Person (order_by(name asc), exists(parents(name = "John"))) {
+                    name
+                }
+            }
+        "#,
+            Parameters::new(),
+        );
+        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada\"}]\n}";
+        assert_eq!(expected, result);
+
+        let mut param = Parameters::new();
+        param.add("pet_name", "Kiki".to_string()).unwrap();
+        let result = read(
+            r#"
+            query sample{
+                ns.Person (order_by(name asc), exists(parents(exists(pet(name = $pet_name))))) {
+                    name
+                }
+            }
+        "#,
+            param,
+        );
+        let expected = "{\n\"ns.Person\":[{\"name\":\"John\"}]\n}";
+        assert_eq!(expected, result);
+
+        let result = read(
+            r#"
+            query sample{
+                ns.Person (order_by(name asc), exists(parents), not exists(pet)) {
+                    name
+                }
+            }
+        "#,
+            Parameters::new(),
+        );
+        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada\"}]\n}";
+        assert_eq!(expected, result);
+
+        let result = read(
+            r#"
+            query sample{
+                ns.Person (order_by(name asc), not exists(parents), exists(pet)) {
+                    name
+                }
+            }
+        "#,
+            Parameters::new(),
+        );
+        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada Mother\"},{\"name\":\"John Father\"}]\n}";
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn geo_field() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+            geo {
+                Place {
+                    name : String,
+                    location : Geo nullable,
+                    index(location)
+                }
+
+                Shop {
+                    name : String,
+                    location : Geo
                 }
             }
         ",
+            )
+            .unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+        for index in data_model.get_entity("geo.Place").unwrap().indexes.values() {
+            conn.execute_batch(&index.create_query()).unwrap();
+        }
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                P1: geo.Place { name : "Paris" location: "48.8566,2.3522" }
+                P2: geo.Place { name : "London" location: "51.5074,-0.1278" }
+                P3: geo.Place { name : "Berlin" location: "52.5200,13.4050" }
+                P4: geo.Place { name : "Suva" location: "-18.1248,178.4501" }
+                P5: geo.Place { name : "Apia" location: "-13.8333,-171.7500" }
+                P6: geo.Place { name : "Nowhere" location: null }
+                S1: geo.Shop { name : "Paris" location: "48.8566,2.3522" }
+                S2: geo.Shop { name : "London" location: "51.5074,-0.1278" }
+                S3: geo.Shop { name : "Berlin" location: "52.5200,13.4050" }
+            } "#,
             &data_model,
         )
         .unwrap();
 
-        let query = PreparedQueries::build(&query_parser).unwrap();
-        let param = Parameters::new();
-        let mut sql = Query {
-            parameters: param,
-            parser: Arc::new(query_parser),
-            sql_queries: Arc::new(query),
+        let mut param = Parameters::new();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let indexed: i64 = conn
+            .query_row("SELECT count(*) FROM idx$geo$Place$location", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(5, indexed);
+
+        let read = |query: &str, param: Parameters| {
+            let query_parser = QueryParser::parse(query, &data_model).unwrap();
+            let query = PreparedQueries::build(&query_parser).unwrap();
+            let mut sql = Query {
+                parameters: param,
+                parser: Arc::new(query_parser),
+                sql_queries: Arc::new(query),
+            };
+            sql.read(&conn).unwrap()
         };
-        let result = sql.read(&conn).unwrap();
 
-        let expected = "{\n\"ns.Person\":[{\"name\":\"Bob\",\"data\":[1,2,3,4],\"array\":2}]\n}";
-        assert_eq!(expected, result);
+        for entity in ["geo.Place", "geo.Shop"] {
+            let mut param = Parameters::new();
+            param.add("lat", 48.8566).unwrap();
+            param.add("lon", 2.3522).unwrap();
+            param.add("radius", 400_000).unwrap();
+            let result = read(
+                &format!(
+                    "query {{
+                    {entity} (order_by(name asc), radius(location, $lat, $lon, $radius)) {{
+                        name
+                    }}
+                }}"
+                ),
+                param,
+            );
+            let expected =
+                format!("{{\n\"{entity}\":[{{\"name\":\"London\"}},{{\"name\":\"Paris\"}}]\n}}");
+            assert_eq!(expected, result);
+
+            let result = read(
+                &format!(
+                    "query {{
+                    {entity} (order_by(name asc), bbox(location, 48, 2, 53, 14)) {{
+                        name
+                    }}
+                }}"
+                ),
+                Parameters::new(),
+            );
+            let expected =
+                format!("{{\n\"{entity}\":[{{\"name\":\"Berlin\"}},{{\"name\":\"Paris\"}}]\n}}");
+            assert_eq!(expected, result);
+        }
 
-        let query_parser = QueryParser::parse(
-            r#"
-            query sample{
-                ns.Person (data->$.val = "hello json", order_by(array asc)) {
+        //the box crosses the antimeridian
+        let result = read(
+            "query {
+                geo.Place (order_by(name asc), bbox(location, -20, 170, -10, -170)) {
                     name
-                    data
-                    array: data->$.val
+                    location
                 }
-            }
-        "#,
+            }",
+            Parameters::new(),
+        );
+        let expected = "{\n\"geo.Place\":[{\"name\":\"Apia\",\"location\":{\"lat\":-13.8333,\"lon\":-171.75}},{\"name\":\"Suva\",\"location\":{\"lat\":-18.1248,\"lon\":178.4501}}]\n}";
+        assert_eq!(expected, result);
+
+        //the index follows the updates
+        let mut param = Parameters::new();
+        param
+            .add(
+                "id",
+                uid_encode(&mutation_query.mutate_entities[2].node_to_mutate.id),
+            )
+            .unwrap();
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                geo.Place { id: $id location: "48.8600,2.3400" }
+            } "#,
             &data_model,
         )
         .unwrap();
+        let mut mutation_query =
+            MutationQuery::execute(&mut param, Arc::new(mutation), &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
 
-        let query = PreparedQueries::build(&query_parser).unwrap();
-        let param = Parameters::new();
-        let mut sql = Query {
-            parameters: param,
-            parser: Arc::new(query_parser),
-            sql_queries: Arc::new(query),
-        };
+        let mut param = Parameters::new();
+        param.add("lat", 48.8566).unwrap();
+        param.add("lon", 2.3522).unwrap();
+        param.add("radius", 10_000).unwrap();
+        let result = read(
+            "query {
+                geo.Place (order_by(name asc), radius(location, $lat, $lon, $radius)) {
+                    name
+                }
+            }",
+            param,
+        );
+        assert_eq!(
+            "{\n\"geo.Place\":[{\"name\":\"Berlin\"},{\"name\":\"Paris\"}]\n}",
+            result
+        );
+
+        MutationParser::parse(
+            r#"mutate { geo.Place { name : "Invalid" location: "91,0" } }"#,
+            &data_model,
+        )
+        .expect_err("invalid latitude");
 
-        let result = sql.read(&conn).unwrap();
+        QueryParser::parse(
+            "query { geo.Place (location = \"0,0\") { name } }",
+            &data_model,
+        )
+        .expect_err("Geo fields are filtered with bbox() or radius()");
 
-        let expected =
-        "{\n\"ns.Person\":[{\"name\":\"Alice\",\"data\":{\"val\":\"hello json\"},\"array\":\"hello json\"}]\n}";
-        assert_eq!(expected, result);
+        QueryParser::parse(
+            "query { geo.Place (bbox(name, 0, 0, 1, 1)) { name } }",
+            &data_model,
+        )
+        .expect_err("name is not a Geo field");
     }
 
     #[test]
-    fn entity_not_null_selection() {
+    fn entity_nullable_selection() {
         let mut data_model = DataModel::new();
         data_model
             .update(
@@ -1500,15 +3891,14 @@ mod tests {
             ns {
                 Person {
                     name : String ,
-                    parents : [ns.Person] ,
+                    parents : [ns.Person] nullable,
                     pet: ns.Pet ,
                 }
 
                 Pet {
                     name : String
                 }
-            }
-        ",
+            }",
             )
             .unwrap();
 
@@ -1531,7 +3921,6 @@ mod tests {
         .unwrap();
 
         let mut param = Parameters::new();
-
         let conn = Connection::open_in_memory().unwrap();
         prepare_connection(&conn).unwrap();
 
@@ -1544,14 +3933,7 @@ mod tests {
             query sample{
                 ns.Person (order_by(name asc)) {
                     name
-                    parents (order_by(name asc)){
-                        name
-                    }
-                    pet {name}
-                    parents_pets : parents (order_by(name asc)) {
-                        name
-                        pet {name}
-                    }
+                    parents (order_by(name asc)) {name}
                 }
             }
         "#,
@@ -1567,22 +3949,15 @@ mod tests {
             sql_queries: Arc::new(query),
         };
         let result = sql.read(&conn).unwrap();
-        let expected = "{\n\"ns.Person\":[{\"name\":\"John\",\"parents\":[{\"name\":\"John Father\"},{\"name\":\"John Mother\"}],\"pet\":{\"name\":\"Truffle\"},\"parents_pets\":[{\"name\":\"John Father\",\"pet\":{\"name\":\"Kiki\"}}]}]\n}";
+        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada\",\"parents\":[{\"name\":\"Ada Father\"},{\"name\":\"Ada Mother\"}]},{\"name\":\"Ada Father\",\"parents\":[]},{\"name\":\"Ada Mother\",\"parents\":[]},{\"name\":\"John\",\"parents\":[{\"name\":\"John Father\"},{\"name\":\"John Mother\"}]},{\"name\":\"John Father\",\"parents\":[]},{\"name\":\"John Mother\",\"parents\":[]}]\n}";
         assert_eq!(expected, result);
 
         let query_parser = QueryParser::parse(
             r#"
-            query sample{
-                ns.Person (order_by(name asc)) {
+            query {
+                ns.Person (order_by(name asc), nullable(has_pet) ) {
                     name
-                    parents (order_by(name asc)){
-                        name
-                    }
-
-                    parents_pets : parents (order_by(name asc)) {
-                        name
-                        pet {name}
-                    }
+                    has_pet: pet {name}
                 }
             }
         "#,
@@ -1597,22 +3972,22 @@ mod tests {
             parser: Arc::new(query_parser),
             sql_queries: Arc::new(query),
         };
-
         let result = sql.read(&conn).unwrap();
-        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada\",\"parents\":[{\"name\":\"Ada Father\"},{\"name\":\"Ada Mother\"}],\"parents_pets\":[{\"name\":\"Ada Father\",\"pet\":{\"name\":\"Waf\"}},{\"name\":\"Ada Mother\",\"pet\":{\"name\":\"Lulu\"}}]},{\"name\":\"John\",\"parents\":[{\"name\":\"John Father\"},{\"name\":\"John Mother\"}],\"parents_pets\":[{\"name\":\"John Father\",\"pet\":{\"name\":\"Kiki\"}}]}]\n}";
+        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada\",\"has_pet\":null},{\"name\":\"Ada Father\",\"has_pet\":{\"name\":\"Waf\"}},{\"name\":\"Ada Mother\",\"has_pet\":{\"name\":\"Lulu\"}},{\"name\":\"John\",\"has_pet\":{\"name\":\"Truffle\"}},{\"name\":\"John Father\",\"has_pet\":{\"name\":\"Kiki\"}},{\"name\":\"John Mother\",\"has_pet\":null}]\n}";
         assert_eq!(expected, result);
     }
 
     #[test]
-    fn entity_nullable_selection() {
+    fn author_virtual_field() {
         let mut data_model = DataModel::new();
+        data_model.update_system(SYSTEM_DATA_MODEL).unwrap();
         data_model
             .update(
                 "
             ns {
                 Person {
                     name : String ,
-                    parents : [ns.Person] nullable,
+                    parents : [ns.Person] ,
                     pet: ns.Pet ,
                 }
 
@@ -1626,6 +4001,10 @@ mod tests {
         let mutation = MutationParser::parse(
             r#"
             mutate {
+                auth: sys.Peer {
+                    pub_key: "TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu"
+                }
+
                 P1: ns.Person {
                     name : "John"
                     parents:  [ {name : "John Mother"} ,{ name:"John Father" pet:{ name:"Kiki" }}]
@@ -1654,7 +4033,15 @@ mod tests {
             query sample{
                 ns.Person (order_by(name asc)) {
                     name
-                    parents (order_by(name asc)) {name}
+                    sys_peer{
+                        pub_key
+                    }
+                    parents (order_by(name asc)) {
+                        name
+                        sys_peer{
+                            pub_key
+                        }
+                    }
                 }
             }
         "#,
@@ -1663,6 +4050,7 @@ mod tests {
         .unwrap();
 
         let query = PreparedQueries::build(&query_parser).unwrap();
+
         let param = Parameters::new();
         let mut sql = Query {
             parameters: param,
@@ -1670,15 +4058,24 @@ mod tests {
             sql_queries: Arc::new(query),
         };
         let result = sql.read(&conn).unwrap();
-        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada\",\"parents\":[{\"name\":\"Ada Father\"},{\"name\":\"Ada Mother\"}]},{\"name\":\"Ada Father\",\"parents\":[]},{\"name\":\"Ada Mother\",\"parents\":[]},{\"name\":\"John\",\"parents\":[{\"name\":\"John Father\"},{\"name\":\"John Mother\"}]},{\"name\":\"John Father\",\"parents\":[]},{\"name\":\"John Mother\",\"parents\":[]}]\n}";
+
+        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"},\"parents\":[{\"name\":\"Ada Father\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}},{\"name\":\"Ada Mother\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}}]},{\"name\":\"John\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"},\"parents\":[{\"name\":\"John Father\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}},{\"name\":\"John Mother\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}}]}]\n}";
         assert_eq!(expected, result);
 
         let query_parser = QueryParser::parse(
             r#"
-            query {
-                ns.Person (order_by(name asc), nullable(has_pet) ) {
+            query sample{
+                ns.Person (order_by(name asc),  nullable(sys_peer)) {
                     name
-                    has_pet: pet {name}
+                    sys_peer (pub_key="cXNkcXNkcXM",){
+                        pub_key
+                    }
+                    parents (order_by(name asc)) {
+                        name
+                        sys_peer{
+                            pub_key
+                        }
+                    }
                 }
             }
         "#,
@@ -1687,6 +4084,7 @@ mod tests {
         .unwrap();
 
         let query = PreparedQueries::build(&query_parser).unwrap();
+
         let param = Parameters::new();
         let mut sql = Query {
             parameters: param,
@@ -1694,12 +4092,12 @@ mod tests {
             sql_queries: Arc::new(query),
         };
         let result = sql.read(&conn).unwrap();
-        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada\",\"has_pet\":null},{\"name\":\"Ada Father\",\"has_pet\":{\"name\":\"Waf\"}},{\"name\":\"Ada Mother\",\"has_pet\":{\"name\":\"Lulu\"}},{\"name\":\"John\",\"has_pet\":{\"name\":\"Truffle\"}},{\"name\":\"John Father\",\"has_pet\":{\"name\":\"Kiki\"}},{\"name\":\"John Mother\",\"has_pet\":null}]\n}";
+        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada\",\"sys_peer\":null,\"parents\":[{\"name\":\"Ada Father\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}},{\"name\":\"Ada Mother\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}}]},{\"name\":\"John\",\"sys_peer\":null,\"parents\":[{\"name\":\"John Father\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}},{\"name\":\"John Mother\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}}]}]\n}";
         assert_eq!(expected, result);
     }
 
     #[test]
-    fn author_virtual_field() {
+    fn room_virtual_field() {
         let mut data_model = DataModel::new();
         data_model.update_system(SYSTEM_DATA_MODEL).unwrap();
         data_model
@@ -1719,19 +4117,55 @@ mod tests {
             )
             .unwrap();
 
+        let conn: Connection = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
         let mutation = MutationParser::parse(
             r#"
             mutate {
                 auth: sys.Peer {
                     pub_key: "TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu"
                 }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let mut param = Parameters::new();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                auth: sys.Room {
+                    authorisations: [{
+                        name:"admin"
+                    }]
+                }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let mut param = Parameters::new();
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+        let q = &mutation_query.mutate_entities[0];
+        let id = q.node_to_mutate.id;
 
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
                 P1: ns.Person {
+                    room_id : $room_id
                     name : "John"
                     parents:  [ {name : "John Mother"} ,{ name:"John Father" pet:{ name:"Kiki" }}]
                     pet: { name:"Truffle"}
                 }
                 P2: ns.Person {
+                    room_id : $room_id
                     name : "Ada"
                     parents:  [ {name : "Ada Mother" pet:{ name:"Lulu" }} ,{ name:"Ada Father" pet:{ name:"Waf" }}]
                 }
@@ -1740,11 +4174,8 @@ mod tests {
             &data_model,
         )
         .unwrap();
-
         let mut param = Parameters::new();
-        let conn = Connection::open_in_memory().unwrap();
-        prepare_connection(&conn).unwrap();
-
+        param.add("room_id", uid_encode(&id)).unwrap();
         let mutation = Arc::new(mutation);
         let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
         mutation_query.write(&conn).unwrap();
@@ -1753,15 +4184,19 @@ mod tests {
             r#"
             query sample{
                 ns.Person (order_by(name asc)) {
-                    name
-                    sys_peer{
-                        pub_key
+                    sys_room {
+                        authorisations{
+                            name
+                        }
                     }
+                    name
                     parents (order_by(name asc)) {
-                        name
-                        sys_peer{
-                            pub_key
+                        sys_room {
+                            authorisations{
+                                name
+                            }
                         }
+                        name
                     }
                 }
             }
@@ -1779,23 +4214,26 @@ mod tests {
             sql_queries: Arc::new(query),
         };
         let result = sql.read(&conn).unwrap();
-
-        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"},\"parents\":[{\"name\":\"Ada Father\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}},{\"name\":\"Ada Mother\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}}]},{\"name\":\"John\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"},\"parents\":[{\"name\":\"John Father\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}},{\"name\":\"John Mother\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}}]}]\n}";
+        let expected = "{\n\"ns.Person\":[{\"sys_room\":{\"authorisations\":[{\"name\":\"admin\"}]},\"name\":\"Ada\",\"parents\":[{\"sys_room\":{\"authorisations\":[{\"name\":\"admin\"}]},\"name\":\"Ada Father\"},{\"sys_room\":{\"authorisations\":[{\"name\":\"admin\"}]},\"name\":\"Ada Mother\"}]},{\"sys_room\":{\"authorisations\":[{\"name\":\"admin\"}]},\"name\":\"John\",\"parents\":[{\"sys_room\":{\"authorisations\":[{\"name\":\"admin\"}]},\"name\":\"John Father\"},{\"sys_room\":{\"authorisations\":[{\"name\":\"admin\"}]},\"name\":\"John Mother\"}]}]\n}";
         assert_eq!(expected, result);
 
         let query_parser = QueryParser::parse(
             r#"
             query sample{
-                ns.Person (order_by(name asc),  nullable(sys_peer)) {
-                    name
-                    sys_peer (pub_key="cXNkcXNkcXM",){
-                        pub_key
+                ns.Person (order_by(name asc)) {
+                    sys_room {
+                        authorisations (name="not"){
+                            name
+                        }
                     }
+                    name
                     parents (order_by(name asc)) {
-                        name
-                        sys_peer{
-                            pub_key
+                        sys_room {
+                            authorisations{
+                                name
+                            }
                         }
+                        name
                     }
                 }
             }
@@ -1813,12 +4251,12 @@ mod tests {
             sql_queries: Arc::new(query),
         };
         let result = sql.read(&conn).unwrap();
-        let expected = "{\n\"ns.Person\":[{\"name\":\"Ada\",\"sys_peer\":null,\"parents\":[{\"name\":\"Ada Father\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}},{\"name\":\"Ada Mother\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}}]},{\"name\":\"John\",\"sys_peer\":null,\"parents\":[{\"name\":\"John Father\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}},{\"name\":\"John Mother\",\"sys_peer\":{\"pub_key\":\"TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu\"}}]}]\n}";
+        let expected = "{\n\"ns.Person\":[]\n}";
         assert_eq!(expected, result);
     }
 
     #[test]
-    fn room_virtual_field() {
+    fn all_entities_query() {
         let mut data_model = DataModel::new();
         data_model.update_system(SYSTEM_DATA_MODEL).unwrap();
         data_model
@@ -1827,8 +4265,7 @@ mod tests {
             ns {
                 Person {
                     name : String ,
-                    parents : [ns.Person] ,
-                    pet: ns.Pet ,
+                    pet: ns.Pet nullable,
                 }
 
                 Pet {
@@ -1839,141 +4276,142 @@ mod tests {
             .unwrap();
 
         let conn: Connection = Connection::open_in_memory().unwrap();
-        prepare_connection(&conn).unwrap();
-
-        let mutation = MutationParser::parse(
-            r#"
-            mutate {
-                auth: sys.Peer {
-                    pub_key: "TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu"
-                }
-            } "#,
-            &data_model,
-        )
-        .unwrap();
-
-        let mut param = Parameters::new();
-        let mutation = Arc::new(mutation);
-        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
-        mutation_query.write(&conn).unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let room_id = uid_encode(&new_uid());
+        let other_room_id = uid_encode(&new_uid());
 
         let mutation = MutationParser::parse(
             r#"
             mutate {
-                auth: sys.Room {
-                    authorisations: [{
-                        name:"admin"
-                    }]
+                P1: ns.Person {
+                    room_id : $room_id
+                    name : "John"
+                    pet: { room_id : $room_id name:"Kiki" }
+                }
+                P2: ns.Person {
+                    room_id : $other_room_id
+                    name : "Ada"
                 }
             } "#,
             &data_model,
         )
         .unwrap();
         let mut param = Parameters::new();
+        param.add("room_id", room_id.clone()).unwrap();
+        param.add("other_room_id", other_room_id).unwrap();
         let mutation = Arc::new(mutation);
         let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
         mutation_query.write(&conn).unwrap();
-        let q = &mutation_query.mutate_entities[0];
-        let id = q.node_to_mutate.id;
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
 
         let mutation = MutationParser::parse(
             r#"
             mutate {
-                P1: ns.Person {
+                ns.Pet {
                     room_id : $room_id
-                    name : "John"
-                    parents:  [ {name : "John Mother"} ,{ name:"John Father" pet:{ name:"Kiki" }}]
-                    pet: { name:"Truffle"}
-                }
-                P2: ns.Person {
-                    room_id : $room_id
-                    name : "Ada"
-                    parents:  [ {name : "Ada Mother" pet:{ name:"Lulu" }} ,{ name:"Ada Father" pet:{ name:"Waf" }}]
+                    name : "Truffle"
                 }
-
             } "#,
             &data_model,
         )
         .unwrap();
         let mut param = Parameters::new();
-        param.add("room_id", uid_encode(&id)).unwrap();
+        param.add("room_id", room_id.clone()).unwrap();
         let mutation = Arc::new(mutation);
         let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
         mutation_query.write(&conn).unwrap();
 
+        #[derive(Deserialize)]
+        struct Change {
+            _entity: String,
+            mdate: i64,
+        }
+
         let query_parser = QueryParser::parse(
             r#"
-            query sample{
-                ns.Person (order_by(name asc)) {
-                    sys_room {
-                        authorisations{
-                            name
-                        }
-                    }
-                    name
-                    parents (order_by(name asc)) {
-                        sys_room {
-                            authorisations{
-                                name
-                            }
-                        }
-                        name
-                    }
+            query {
+                _all(room_id=$room_id, after($date)) {
+                    id
+                    _entity
+                    mdate
                 }
             }
         "#,
             &data_model,
         )
         .unwrap();
+        let query_parser = Arc::new(query_parser);
+        let query = Arc::new(PreparedQueries::build(&query_parser).unwrap());
 
-        let query = PreparedQueries::build(&query_parser).unwrap();
-
-        let param = Parameters::new();
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.clone()).unwrap();
+        param.add("date", 0).unwrap();
         let mut sql = Query {
             parameters: param,
-            parser: Arc::new(query_parser),
-            sql_queries: Arc::new(query),
+            parser: query_parser.clone(),
+            sql_queries: query.clone(),
         };
         let result = sql.read(&conn).unwrap();
-        let expected = "{\n\"ns.Person\":[{\"sys_room\":{\"authorisations\":[{\"name\":\"admin\"}]},\"name\":\"Ada\",\"parents\":[{\"sys_room\":{\"authorisations\":[{\"name\":\"admin\"}]},\"name\":\"Ada Father\"},{\"sys_room\":{\"authorisations\":[{\"name\":\"admin\"}]},\"name\":\"Ada Mother\"}]},{\"sys_room\":{\"authorisations\":[{\"name\":\"admin\"}]},\"name\":\"John\",\"parents\":[{\"sys_room\":{\"authorisations\":[{\"name\":\"admin\"}]},\"name\":\"John Father\"},{\"sys_room\":{\"authorisations\":[{\"name\":\"admin\"}]},\"name\":\"John Mother\"}]}]\n}";
-        assert_eq!(expected, result);
+        let mut parser = ResultParser::new(&result).unwrap();
+        let changes: Vec<Change> = parser.take_array("_all").unwrap();
+        assert_eq!(3, changes.len());
+        let mut entities: Vec<&str> = changes[0..2].iter().map(|c| c._entity.as_str()).collect();
+        entities.sort();
+        assert_eq!(vec!["ns.Person", "ns.Pet"], entities);
+        assert_eq!("ns.Pet", changes[2]._entity);
+        assert!(changes[2].mdate > changes[0].mdate);
+
+        //only the changes made after the date
+        let mut param = Parameters::new();
+        param.add("room_id", room_id).unwrap();
+        param.add("date", changes[0].mdate).unwrap();
+        let mut sql = Query {
+            parameters: param,
+            parser: query_parser,
+            sql_queries: query,
+        };
+        let result = sql.read(&conn).unwrap();
+        let mut parser = ResultParser::new(&result).unwrap();
+        let changes: Vec<Change> = parser.take_array("_all").unwrap();
+        assert_eq!(1, changes.len());
+        assert_eq!("ns.Pet", changes[0]._entity);
 
-        let query_parser = QueryParser::parse(
+        QueryParser::parse(
             r#"
-            query sample{
-                ns.Person (order_by(name asc)) {
-                    sys_room {
-                        authorisations (name="not"){
-                            name
-                        }
-                    }
-                    name
-                    parents (order_by(name asc)) {
-                        sys_room {
-                            authorisations{
-                                name
-                            }
-                        }
-                        name
-                    }
+            query {
+                _all {
+                    id
                 }
             }
         "#,
             &data_model,
         )
-        .unwrap();
+        .expect_err("the room_id filter is required");
 
-        let query = PreparedQueries::build(&query_parser).unwrap();
+        QueryParser::parse(
+            r#"
+            query {
+                _all(room_id=$room_id) {
+                    name
+                }
+            }
+        "#,
+            &data_model,
+        )
+        .expect_err("only the system fields are available");
 
-        let param = Parameters::new();
-        let mut sql = Query {
-            parameters: param,
-            parser: Arc::new(query_parser),
-            sql_queries: Arc::new(query),
-        };
-        let result = sql.read(&conn).unwrap();
-        let expected = "{\n\"ns.Person\":[]\n}";
-        assert_eq!(expected, result);
+        MutationParser::parse(
+            r#"
+            mutate {
+                _all {
+                    room_id : $room_id
+                }
+            } "#,
+            &data_model,
+        )
+        .expect_err("_all cannot be mutated");
     }
 
     #[test]
@@ -2651,4 +5089,313 @@ mod tests {
 
         println!("{}", result);
     }
+
+    #[test]
+    fn readers_restricted_nodes() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "
+                {
+                    Note {
+                        content: String
+                    }
+                }
+            ",
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                public: Note {
+                    content : "for everyone"
+                }
+                restricted: Note {
+                    content : "for the readers"
+                    sys_readers: $readers
+                }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+
+        let reader = [1u8; 32];
+        let other = [2u8; 32];
+        let mut param = Parameters::new();
+        param
+            .add("readers", format!("[\"{}\"]", base64_encode(&reader)))
+            .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let mutation = Arc::new(mutation);
+        let mut mutation_query = MutationQuery::execute(&mut param, mutation, &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let query_parser = Arc::new(
+            QueryParser::parse(
+                "
+            query sample{
+                Note(order_by(content asc)){
+                    content
+                }
+            }
+        ",
+                &data_model,
+            )
+            .unwrap(),
+        );
+
+        let query = PreparedQueries::build_for_reader(&query_parser, Some(&reader)).unwrap();
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: query_parser.clone(),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected =
+            "{\n\"Note\":[{\"content\":\"for everyone\"},{\"content\":\"for the readers\"}]\n}";
+        assert_eq!(expected, result);
+
+        let query = PreparedQueries::build_for_reader(&query_parser, Some(&other)).unwrap();
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: query_parser.clone(),
+            sql_queries: Arc::new(query),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"Note\":[{\"content\":\"for everyone\"}]\n}";
+        assert_eq!(expected, result);
+
+        MutationParser::parse(
+            r#"
+            mutate {
+                Note {
+                    content : "invalid"
+                    sys_readers: "{\"reader\":\"a\"}"
+                }
+            } "#,
+            &data_model,
+        )
+        .expect_err("sys_readers must be an array of verifying keys");
+    }
+
+    #[test]
+    fn edit_chain() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                "ns {
+                    Message(edit_chain(original)) {
+                        content: String,
+                        original: Base64 nullable,
+                    }
+                }",
+            )
+            .unwrap();
+
+        let entity = data_model.get_entity("ns.Message").unwrap();
+        let entity_short = entity.short_name.clone();
+        let content_short = entity.get_field("content").unwrap().short_name.clone();
+        let original_short = entity.get_field("original").unwrap().short_name.clone();
+
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let room_id = new_uid();
+        let author = Ed25519SigningKey::new();
+        let other = Ed25519SigningKey::new();
+        let write =
+            |content: &str, original: Option<&[u8; 16]>, cdate: i64, key: &Ed25519SigningKey| {
+                let json = match original {
+                    Some(original) => format!(
+                        r#"{{"{}":"{}","{}":"{}"}}"#,
+                        content_short,
+                        content,
+                        original_short,
+                        uid_encode(original)
+                    ),
+                    None => format!(r#"{{"{}":"{}"}}"#, content_short, content),
+                };
+                let mut node = Node {
+                    id: new_uid(),
+                    room_id: Some(room_id),
+                    cdate,
+                    mdate: cdate,
+                    _entity: entity_short.clone(),
+                    _json: Some(json),
+                    ..Default::default()
+                };
+                node.sign(key).unwrap();
+                node.write(&conn, false, &None, &None).unwrap();
+                node.id
+            };
+
+        let first = write("hello", None, 1000, &author);
+        write("hello v1", Some(&first), 1001, &author);
+        write("hello v2", Some(&first), 1002, &author);
+        //versions created by another peer are ignored
+        write("hijacked", Some(&first), 1003, &other);
+        write("second", None, 1004, &author);
+
+        //a version received before its original is hidden until the original is received
+        let third = new_uid();
+        write("third v1", Some(&third), 1006, &author);
+
+        let query_parser = Arc::new(
+            QueryParser::parse(
+                "query {
+                    ns.Message(order_by(content asc)) {
+                        content
+                    }
+                }",
+                &data_model,
+            )
+            .unwrap(),
+        );
+        let query = Arc::new(PreparedQueries::build(&query_parser).unwrap());
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: query_parser.clone(),
+            sql_queries: query.clone(),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"ns.Message\":[{\"content\":\"hello v2\"},{\"content\":\"second\"}]\n}";
+        assert_eq!(expected, result);
+
+        let mut node = Node {
+            id: third,
+            room_id: Some(room_id),
+            cdate: 1005,
+            mdate: 1005,
+            _entity: entity_short.clone(),
+            _json: Some(format!(r#"{{"{}":"third"}}"#, content_short)),
+            ..Default::default()
+        };
+        node.sign(&author).unwrap();
+        node.write(&conn, false, &None, &None).unwrap();
+
+        let mut sql = Query {
+            parameters: Parameters::new(),
+            parser: query_parser.clone(),
+            sql_queries: query.clone(),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"ns.Message\":[{\"content\":\"hello v2\"},{\"content\":\"second\"},{\"content\":\"third v1\"}]\n}";
+        assert_eq!(expected, result);
+
+        let query_parser = Arc::new(
+            QueryParser::parse(
+                "query {
+                    ns.Message(versions($id), order_by(cdate asc)) {
+                        content
+                    }
+                }",
+                &data_model,
+            )
+            .unwrap(),
+        );
+        let mut param = Parameters::new();
+        param.add("id", uid_encode(&first)).unwrap();
+        let mut sql = Query {
+            parameters: param,
+            parser: query_parser.clone(),
+            sql_queries: Arc::new(PreparedQueries::build(&query_parser).unwrap()),
+        };
+        let result = sql.read(&conn).unwrap();
+        let expected = "{\n\"ns.Message\":[{\"content\":\"hello\"},{\"content\":\"hello v1\"},{\"content\":\"hello v2\"}]\n}";
+        assert_eq!(expected, result);
+
+        let mut invalid = DataModel::new();
+        invalid
+            .update("{ Message(edit_chain(content)) { content: String } }")
+            .expect_err("the edit chain field must be a nullable Base64");
+        invalid
+            .update("{ Message(edit_chain(missing)) { content: String } }")
+            .expect_err("the edit chain field must exist");
+
+        QueryParser::parse(
+            "query {
+                ns.Message {
+                    content
+                }
+                sys.Room(versions($id)) {
+                    id
+                }
+            }",
+            &data_model,
+        )
+        .expect_err("versions requires an edit chain");
+    }
+
+    #[test]
+    fn head_queries() {
+        let mut data_model = DataModel::new();
+        data_model
+            .update(
+                r#"
+            ns {
+                Person {
+                    name : String,
+                    age : Integer,
+                }
+            }
+        "#,
+            )
+            .unwrap();
+
+        let mutation = MutationParser::parse(
+            r#"
+            mutate {
+                P1: ns.Person { name:"John" age:32 }
+                P2: ns.Person { name:"Ada" age:36 }
+                P3: ns.Person { name:"Alan" age:41 }
+            } "#,
+            &data_model,
+        )
+        .unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+        let mut mutation_query =
+            MutationQuery::execute(&mut Parameters::new(), Arc::new(mutation), &conn).unwrap();
+        mutation_query.write(&conn).unwrap();
+
+        let head = |query: &str, exists: bool, parameters: Parameters| {
+            let query_parser = QueryParser::parse(query, &data_model).unwrap();
+            let query = PreparedQueries::build_head(&query_parser, None, exists).unwrap();
+            assert!(!query.sql_queries[0].sql_query.contains("json_object"));
+            let mut sql = Query {
+                parameters,
+                parser: Arc::new(query_parser),
+                sql_queries: Arc::new(query),
+            };
+            sql.head(&conn).unwrap()
+        };
+
+        let mut param = Parameters::new();
+        param.add("name", String::from("Ada")).unwrap();
+        let query = "query { ns.Person(name=$name) { id } }";
+        assert_eq!(1, head(query, true, param.clone()));
+        assert_eq!(1, head(query, false, param));
+
+        let mut param = Parameters::new();
+        param.add("name", String::from("Grace")).unwrap();
+        assert_eq!(0, head(query, true, param.clone()));
+        assert_eq!(0, head(query, false, param));
+
+        let query = "query { ns.Person(age > 33) { id } }";
+        assert_eq!(1, head(query, true, Parameters::new()));
+        assert_eq!(2, head(query, false, Parameters::new()));
+
+        let query = "query { ns.Person { id } }";
+        assert_eq!(3, head(query, false, Parameters::new()));
+
+        let query_parser = QueryParser::parse(
+            "query { ns.Person { id } P2: ns.Person { id } }",
+            &data_model,
+        )
+        .unwrap();
+        PreparedQueries::build_head(&query_parser, None, true).expect_err("single entity");
+    }
 }
@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::security::{base64_encode, Uid};
+
+use super::{
+    daily_log::{DailyLog, ReaderView},
+    sqlite_database::Writeable,
+};
+
+///
+/// The daily logs of a room as seen on a remote peer during the last synchronisation.
+///
+/// A day of an entity is held by the peer when its daily hash equals the local one.
+/// Used to estimate how many peers hold a copy of the local data.
+///
+pub struct PeerRoomLog {
+    pub room_id: Uid,
+    pub verifying_key: Vec<u8>,
+    pub logs: Vec<DailyLog>,
+}
+impl PeerRoomLog {
+    ///
+    /// Creates the tables used to track the peer logs
+    ///
+    /// The tables can be added to existing databases, hence the 'IF NOT EXISTS'
+    ///
+    pub fn create_tables(conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        conn.execute(
+            "
+            CREATE TABLE IF NOT EXISTS _peer_daily_log (
+                room_id BLOB NOT NULL,
+                entity TEXT NOT NULL,
+                date INTEGER NOT NULL,
+                verifying_key BLOB NOT NULL,
+                daily_hash BLOB,
+                PRIMARY KEY(room_id, entity, date, verifying_key)
+            ) WITHOUT ROWID, STRICT",
+            [],
+        )?;
+        Ok(())
+    }
+}
+impl Writeable for PeerRoomLog {
+    fn write(&mut self, conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        let mut delete_stmt = conn.prepare_cached(
+            "DELETE FROM _peer_daily_log WHERE room_id = ? AND verifying_key = ?",
+        )?;
+        delete_stmt.execute((&self.room_id, &self.verifying_key))?;
+
+        let mut insert_stmt = conn.prepare_cached(
+            "INSERT INTO _peer_daily_log (room_id, entity, date, verifying_key, daily_hash) VALUES (?, ?, ?, ?, ?)",
+        )?;
+        for log in &self.logs {
+            insert_stmt.execute((
+                &self.room_id,
+                &log.entity,
+                log.date,
+                &self.verifying_key,
+                &log.daily_hash,
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Estimated number of peers holding a full copy of the data of an entity for a given day.
+///
+/// The estimation relies on the daily logs received during the last synchronisation with each peer.
+/// Devices sharing the same key material are counted as a single peer.
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataAvailability {
+    pub entity: String,
+    pub date: i64,
+    pub entry_number: u32,
+    pub copies: usize,
+}
+impl DataAvailability {
+    ///
+    /// returns the availability of every day of the room, using the entity short name
+    ///
+    pub fn list(
+        room_id: &Uid,
+        conn: &Connection,
+    ) -> std::result::Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "
+            SELECT _daily_log.entity, _daily_log.date, _daily_log.entry_number, count(_peer_daily_log.verifying_key)
+            FROM _daily_log
+            LEFT JOIN _peer_daily_log ON
+                _peer_daily_log.room_id = _daily_log.room_id
+                AND _peer_daily_log.entity = _daily_log.entity
+                AND _peer_daily_log.date = _daily_log.date
+                AND _peer_daily_log.daily_hash = _daily_log.daily_hash
+            WHERE _daily_log.room_id = ? AND _daily_log.entry_number > 0
+            GROUP BY _daily_log.entity, _daily_log.date
+            ORDER BY _daily_log.date, _daily_log.entity
+            ",
+        )?;
+        let rows = stmt.query_map([room_id], |row| {
+            Ok(Self {
+                entity: row.get(0)?,
+                date: row.get(1)?,
+                entry_number: row.get(2)?,
+                copies: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+///
+/// An entity day whose content differs between the local device and a remote peer.
+///
+/// The comparison uses the daily logs received during the last synchronisation with the peer,
+/// only the days that hold data on the peer are listed.
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayDivergence {
+    pub entity: String,
+    pub date: i64,
+    pub local_hash: Option<Vec<u8>>,
+    pub remote_hash: Vec<u8>,
+}
+impl DayDivergence {
+    ///
+    /// returns the divergent days of the room, using the entity short name
+    ///
+    pub fn list(
+        room_id: &Uid,
+        verifying_key: &Vec<u8>,
+        conn: &Connection,
+    ) -> std::result::Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "
+            SELECT _peer_daily_log.entity, _peer_daily_log.date, _daily_log.daily_hash, _peer_daily_log.daily_hash
+            FROM _peer_daily_log
+            LEFT JOIN _daily_log ON
+                _daily_log.room_id = _peer_daily_log.room_id
+                AND _daily_log.entity = _peer_daily_log.entity
+                AND _daily_log.date = _peer_daily_log.date
+            WHERE _peer_daily_log.room_id = ?
+                AND _peer_daily_log.verifying_key = ?
+                AND _peer_daily_log.daily_hash IS NOT NULL
+                AND (_daily_log.daily_hash IS NULL OR _daily_log.daily_hash != _peer_daily_log.daily_hash)
+            ORDER BY _peer_daily_log.date, _peer_daily_log.entity
+            ",
+        )?;
+        let rows = stmt.query_map((room_id, verifying_key), |row| {
+            Ok(Self {
+                entity: row.get(0)?,
+                date: row.get(1)?,
+                local_hash: row.get(2)?,
+                remote_hash: row.get(3)?,
+            })
+        })?;
+        let divergences = rows.collect::<std::result::Result<Vec<Self>, rusqlite::Error>>()?;
+        if divergences.is_empty() {
+            return Ok(divergences);
+        }
+
+        //the peer only holds the nodes it is allowed to read
+        let view = ReaderView::build(room_id, verifying_key, conn)?;
+        let mut result = Vec::with_capacity(divergences.len());
+        for mut divergence in divergences {
+            let mut log = DailyLog {
+                room_id: *room_id,
+                entity: divergence.entity.clone(),
+                date: divergence.date,
+                daily_hash: divergence.local_hash.clone(),
+                ..Default::default()
+            };
+            view.apply(&mut log);
+            if log.daily_hash.as_ref() != Some(&divergence.remote_hash) {
+                divergence.local_hash = log.daily_hash;
+                result.push(divergence);
+            }
+        }
+        Ok(result)
+    }
+}
+
+///
+/// Synchronisation statistics of a remote peer, accumulated since the first connection and kept across restarts.
+///
+/// Devices sharing the same key material are counted as a single peer.
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PeerSyncStats {
+    ///
+    /// bytes sent to the peer, updated when a connection is closed
+    ///
+    pub bytes_sent: u64,
+
+    ///
+    /// bytes received from the peer, updated when a connection is closed
+    ///
+    pub bytes_received: u64,
+
+    ///
+    /// nodes received from the peer and inserted in the database
+    ///
+    pub nodes_accepted: u64,
+
+    ///
+    /// nodes received from the peer and rejected by the authorisation model
+    ///
+    pub nodes_rejected: u64,
+
+    ///
+    /// date of the last successful synchronisation of each room with the peer, indexed by the base64 room identifier
+    ///
+    pub last_room_sync: HashMap<String, i64>,
+}
+impl PeerSyncStats {
+    ///
+    /// Creates the tables used to store the statistics
+    ///
+    /// The tables can be added to existing databases, hence the 'IF NOT EXISTS'
+    ///
+    pub fn create_tables(conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        conn.execute(
+            "
+            CREATE TABLE IF NOT EXISTS _peer_sync_stats (
+                verifying_key BLOB NOT NULL,
+                bytes_sent INTEGER NOT NULL,
+                bytes_received INTEGER NOT NULL,
+                nodes_accepted INTEGER NOT NULL,
+                nodes_rejected INTEGER NOT NULL,
+                PRIMARY KEY(verifying_key)
+            ) WITHOUT ROWID, STRICT",
+            [],
+        )?;
+
+        conn.execute(
+            "
+            CREATE TABLE IF NOT EXISTS _peer_room_sync (
+                verifying_key BLOB NOT NULL,
+                room_id BLOB NOT NULL,
+                sync_date INTEGER NOT NULL,
+                PRIMARY KEY(verifying_key, room_id)
+            ) WITHOUT ROWID, STRICT",
+            [],
+        )?;
+        Ok(())
+    }
+
+    ///
+    /// returns the statistics of a peer, or None if the peer never connected
+    ///
+    pub fn get(
+        verifying_key: &Vec<u8>,
+        conn: &Connection,
+    ) -> std::result::Result<Option<Self>, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT bytes_sent, bytes_received, nodes_accepted, nodes_rejected
+            FROM _peer_sync_stats WHERE verifying_key = ?",
+        )?;
+        let stats = stmt
+            .query_row([verifying_key], |row| {
+                Ok(Self {
+                    bytes_sent: row.get(0)?,
+                    bytes_received: row.get(1)?,
+                    nodes_accepted: row.get(2)?,
+                    nodes_rejected: row.get(3)?,
+                    last_room_sync: HashMap::new(),
+                })
+            })
+            .optional()?;
+
+        let mut stats = match stats {
+            Some(stats) => stats,
+            None => return Ok(None),
+        };
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT room_id, sync_date FROM _peer_room_sync WHERE verifying_key = ?",
+        )?;
+        let mut rows = stmt.query([verifying_key])?;
+        while let Some(row) = rows.next()? {
+            let room_id: Uid = row.get(0)?;
+            stats
+                .last_room_sync
+                .insert(base64_encode(&room_id), row.get(1)?);
+        }
+        Ok(Some(stats))
+    }
+}
+
+///
+/// Statistics gathered during a connection or a room synchronisation, added to the stored [PeerSyncStats]
+///
+#[derive(Default)]
+pub struct PeerSyncUpdate {
+    pub verifying_key: Vec<u8>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub nodes_accepted: u64,
+    pub nodes_rejected: u64,
+    pub room_sync: Option<(Uid, i64)>,
+}
+impl PeerSyncUpdate {
+    pub fn new(verifying_key: Vec<u8>) -> Self {
+        Self {
+            verifying_key,
+            ..Default::default()
+        }
+    }
+}
+impl Writeable for PeerSyncUpdate {
+    fn write(&mut self, conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO _peer_sync_stats (verifying_key, bytes_sent, bytes_received, nodes_accepted, nodes_rejected)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(verifying_key) DO UPDATE SET
+                bytes_sent = bytes_sent + excluded.bytes_sent,
+                bytes_received = bytes_received + excluded.bytes_received,
+                nodes_accepted = nodes_accepted + excluded.nodes_accepted,
+                nodes_rejected = nodes_rejected + excluded.nodes_rejected",
+        )?;
+        stmt.execute((
+            &self.verifying_key,
+            self.bytes_sent,
+            self.bytes_received,
+            self.nodes_accepted,
+            self.nodes_rejected,
+        ))?;
+
+        if let Some((room_id, sync_date)) = &self.room_sync {
+            let mut stmt = conn.prepare_cached(
+                "INSERT INTO _peer_room_sync (verifying_key, room_id, sync_date) VALUES (?, ?, ?)
+                ON CONFLICT(verifying_key, room_id) DO UPDATE SET sync_date = excluded.sync_date",
+            )?;
+            stmt.execute((&self.verifying_key, room_id, sync_date))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{database::sqlite_database::prepare_connection, security::new_uid};
+
+    use super::*;
+
+    fn daily_log(room_id: Uid, entity: &str, date: i64, hash: u8) -> DailyLog {
+        DailyLog {
+            room_id,
+            date,
+            entity: entity.to_string(),
+            entry_number: 1,
+            daily_hash: Some(vec![hash]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn availability() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let room_id = new_uid();
+        let mut insert = conn
+            .prepare(
+                "INSERT INTO _daily_log (room_id, entity, date, entry_number, daily_hash) VALUES (?, ?, ?, ?, ?)",
+            )
+            .unwrap();
+        for log in [
+            daily_log(room_id, "0", 1000, 1),
+            daily_log(room_id, "0", 2000, 2),
+            daily_log(room_id, "1", 2000, 3),
+        ] {
+            insert
+                .execute((
+                    log.room_id,
+                    log.entity,
+                    log.date,
+                    log.entry_number,
+                    log.daily_hash,
+                ))
+                .unwrap();
+        }
+
+        let availability = DataAvailability::list(&room_id, &conn).unwrap();
+        assert_eq!(3, availability.len());
+        assert!(availability.iter().all(|a| a.copies == 0));
+
+        //the first peer holds the first day, and an outdated version of the second one
+        PeerRoomLog {
+            room_id,
+            verifying_key: vec![1],
+            logs: vec![
+                daily_log(room_id, "0", 1000, 1),
+                daily_log(room_id, "0", 2000, 9),
+            ],
+        }
+        .write(&conn)
+        .unwrap();
+
+        //the second peer holds everything
+        PeerRoomLog {
+            room_id,
+            verifying_key: vec![2],
+            logs: vec![
+                daily_log(room_id, "0", 1000, 1),
+                daily_log(room_id, "0", 2000, 2),
+                daily_log(room_id, "1", 2000, 3),
+            ],
+        }
+        .write(&conn)
+        .unwrap();
+
+        let availability = DataAvailability::list(&room_id, &conn).unwrap();
+        let copies: Vec<(String, i64, usize)> = availability
+            .into_iter()
+            .map(|a| (a.entity, a.date, a.copies))
+            .collect();
+        assert_eq!(
+            vec![
+                ("0".to_string(), 1000, 2),
+                ("0".to_string(), 2000, 1),
+                ("1".to_string(), 2000, 1)
+            ],
+            copies
+        );
+
+        //a new synchronisation replaces the previous state of the peer
+        PeerRoomLog {
+            room_id,
+            verifying_key: vec![2],
+            logs: vec![],
+        }
+        .write(&conn)
+        .unwrap();
+        let availability = DataAvailability::list(&room_id, &conn).unwrap();
+        let copies: Vec<usize> = availability.into_iter().map(|a| a.copies).collect();
+        assert_eq!(vec![1, 0, 0], copies);
+    }
+
+    #[test]
+    fn divergence() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let room_id = new_uid();
+        let mut insert = conn
+            .prepare(
+                "INSERT INTO _daily_log (room_id, entity, date, entry_number, daily_hash) VALUES (?, ?, ?, ?, ?)",
+            )
+            .unwrap();
+        for log in [
+            daily_log(room_id, "0", 1000, 1),
+            daily_log(room_id, "0", 2000, 2),
+            daily_log(room_id, "1", 2000, 3),
+        ] {
+            insert
+                .execute((
+                    log.room_id,
+                    log.entity,
+                    log.date,
+                    log.entry_number,
+                    log.daily_hash,
+                ))
+                .unwrap();
+        }
+
+        //the peer has a different version of the second day and a day that is missing locally
+        //the local only day is not a divergence
+        PeerRoomLog {
+            room_id,
+            verifying_key: vec![1],
+            logs: vec![
+                daily_log(room_id, "0", 1000, 1),
+                daily_log(room_id, "0", 2000, 9),
+                daily_log(room_id, "0", 3000, 4),
+            ],
+        }
+        .write(&conn)
+        .unwrap();
+
+        let divergence = DayDivergence::list(&room_id, &vec![1], &conn).unwrap();
+        assert_eq!(2, divergence.len());
+        assert_eq!(
+            ("0", 2000),
+            (divergence[0].entity.as_str(), divergence[0].date)
+        );
+        assert_eq!(Some(vec![2]), divergence[0].local_hash);
+        assert_eq!(vec![9], divergence[0].remote_hash);
+        assert_eq!(
+            ("0", 3000),
+            (divergence[1].entity.as_str(), divergence[1].date)
+        );
+        assert_eq!(None, divergence[1].local_hash);
+
+        let divergence = DayDivergence::list(&room_id, &vec![2], &conn).unwrap();
+        assert!(divergence.is_empty());
+    }
+
+    #[test]
+    fn sync_stats() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let verifying_key = vec![1, 2, 3];
+        assert!(PeerSyncStats::get(&verifying_key, &conn).unwrap().is_none());
+
+        let room_id = new_uid();
+        PeerSyncUpdate {
+            nodes_accepted: 10,
+            nodes_rejected: 2,
+            room_sync: Some((room_id, 1000)),
+            ..PeerSyncUpdate::new(verifying_key.clone())
+        }
+        .write(&conn)
+        .unwrap();
+
+        PeerSyncUpdate {
+            bytes_sent: 100,
+            bytes_received: 200,
+            ..PeerSyncUpdate::new(verifying_key.clone())
+        }
+        .write(&conn)
+        .unwrap();
+
+        PeerSyncUpdate {
+            nodes_accepted: 5,
+            room_sync: Some((room_id, 2000)),
+            ..PeerSyncUpdate::new(verifying_key.clone())
+        }
+        .write(&conn)
+        .unwrap();
+
+        let stats = PeerSyncStats::get(&verifying_key, &conn).unwrap().unwrap();
+        assert_eq!(100, stats.bytes_sent);
+        assert_eq!(200, stats.bytes_received);
+        assert_eq!(15, stats.nodes_accepted);
+        assert_eq!(2, stats.nodes_rejected);
+        assert_eq!(1, stats.last_room_sync.len());
+        assert_eq!(
+            2000,
+            *stats.last_room_sync.get(&base64_encode(&room_id)).unwrap()
+        );
+    }
+}
@@ -108,6 +108,15 @@ impl Room {
 
     pub fn can(&self, user: &Vec<u8>, entity: &str, date: i64, right: &RightType) -> bool {
         let user_valid = self.is_admin(user, date);
+        if entity.eq(system_entities::ROOM_SETTINGS_ENT)
+            || entity.eq(system_entities::TOMBSTONE_ENT)
+            || entity.eq(system_entities::DATA_MODEL_PROPOSAL_ENT)
+            || entity.eq(system_entities::DATA_MODEL_TEMPLATE_ENT)
+            || entity.eq(system_entities::AUTOMATION_RULE_ENT)
+        {
+            //room settings, moderation records, data model proposals, templates and automation rules can only be modified by the room admins
+            return user_valid;
+        }
         for entry in &self.authorisations {
             let auth = entry.1;
             let valid = user_valid || auth.is_user_valid_at(user, date);
@@ -316,7 +325,7 @@ impl EntityRight {
 ///
 /// Helper enum that define every rights
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RightType {
     MutateSelf,
     MutateAll,
@@ -493,8 +502,9 @@ mod tests {
         database::{
             authorisation_service::*,
             room::{Authorisation, EntityRight, RightType, Room, User},
+            system_entities,
         },
-        security::{new_uid, random32, Ed25519SigningKey},
+        security::{new_uid, random32, Ed25519SigningKey, SigningKey},
     };
     #[test]
     fn room_admins() {
@@ -700,6 +710,89 @@ mod tests {
         assert_eq!(2, room.users().len());
     }
 
+    #[test]
+    fn room_settings_right() {
+        let valid_date: i64 = 10000;
+        let admin = User {
+            verifying_key: random32().to_vec(),
+            date: valid_date,
+            enabled: true,
+        };
+        let user = User {
+            verifying_key: random32().to_vec(),
+            date: valid_date,
+            enabled: true,
+        };
+
+        let mut auth: Authorisation = Authorisation {
+            id: new_uid(),
+            ..Default::default()
+        };
+        auth.add_right(EntityRight {
+            valid_from: 0,
+            entity: "*".to_string(),
+            mutate_self: true,
+            mutate_all: true,
+        })
+        .unwrap();
+        auth.add_user(user.clone()).unwrap();
+
+        let mut room = Room::default();
+        room.add_admin_user(admin.clone()).unwrap();
+        room.add_auth(auth).unwrap();
+
+        assert!(room.can(
+            &admin.verifying_key,
+            system_entities::ROOM_SETTINGS_ENT,
+            valid_date,
+            &RightType::MutateAll
+        ));
+        assert!(!room.can(
+            &admin.verifying_key,
+            system_entities::ROOM_SETTINGS_ENT,
+            valid_date - 1,
+            &RightType::MutateSelf
+        ));
+
+        //the wildcard right does not allow to modify the settings
+        assert!(room.can(
+            &user.verifying_key,
+            "any",
+            valid_date,
+            &RightType::MutateAll
+        ));
+        assert!(!room.can(
+            &user.verifying_key,
+            system_entities::ROOM_SETTINGS_ENT,
+            valid_date,
+            &RightType::MutateSelf
+        ));
+        assert!(!room.can(
+            &user.verifying_key,
+            system_entities::TOMBSTONE_ENT,
+            valid_date,
+            &RightType::MutateSelf
+        ));
+        assert!(!room.can(
+            &user.verifying_key,
+            system_entities::AUTOMATION_RULE_ENT,
+            valid_date,
+            &RightType::MutateSelf
+        ));
+        assert!(!room.can(
+            &user.verifying_key,
+            system_entities::DATA_MODEL_PROPOSAL_ENT,
+            valid_date,
+            &RightType::MutateSelf
+        ));
+        assert!(!room.can(
+            &user.verifying_key,
+            system_entities::DATA_MODEL_TEMPLATE_ENT,
+            valid_date,
+            &RightType::MutateSelf
+        ));
+    }
+
     #[test]
     fn entity_right() {
         let user_valid_date: i64 = 1000;
@@ -810,10 +903,13 @@ mod tests {
 
         room.add_auth(auth).unwrap();
 
+        let signing_key = Ed25519SigningKey::new();
         let mut room_auth = RoomAuthorisations {
-            signing_key: Ed25519SigningKey::new(),
+            verifying_key: signing_key.export_verifying_key(),
+            signing_key: Some(signing_key),
             rooms: HashMap::new(),
             max_node_size: 256 * 1024,
+            conflicts: WriteConflicts::default(),
         };
 
         room_auth.add_room(room);
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use super::{
+    graph_database::GraphDatabaseService,
+    query_language::parameter::{Parameters, ParametersAdd},
+    ResultParser,
+};
+
+///
+/// Identifiers of a *Room* created with a [RoomBuilder].
+///
+#[derive(Debug, Clone)]
+pub struct CreatedRoom {
+    ///
+    /// the *Room* identifier
+    ///
+    pub id: String,
+
+    ///
+    /// the authorisation identifiers, indexed by authorisation name
+    ///
+    pub authorisations: HashMap<String, String>,
+}
+
+#[derive(Default, Clone, Debug)]
+struct AuthorisationBuilder {
+    name: String,
+    rights: Vec<(String, bool, bool)>,
+    users: Vec<String>,
+    user_admins: Vec<String>,
+}
+
+///
+/// Helper to build the *sys.Room* mutation.
+///
+/// *auth()* starts a new authorisation: the following *right()*, *user()* and *user_admin()* calls apply to it.
+///
+/// ```ignore
+/// let room = RoomBuilder::new()
+///     .admin(&key)
+///     .auth("writers")
+///     .right("chat.Message", true, false)
+///     .user(&key);
+/// let created = discret.create_room(&room).await?;
+/// ```
+///
+#[derive(Default, Clone, Debug)]
+pub struct RoomBuilder {
+    admins: Vec<String>,
+    authorisations: Vec<AuthorisationBuilder>,
+    orphans: usize,
+}
+impl RoomBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// adds a room admin, using its base64 verifying key
+    ///
+    pub fn admin(mut self, verifying_key: &str) -> Self {
+        self.admins.push(verifying_key.to_string());
+        self
+    }
+
+    ///
+    /// starts a new authorisation
+    ///
+    pub fn auth(mut self, name: &str) -> Self {
+        self.authorisations.push(AuthorisationBuilder {
+            name: name.to_string(),
+            ..Default::default()
+        });
+        self
+    }
+
+    ///
+    /// adds an entity right to the current authorisation
+    ///
+    pub fn right(mut self, entity: &str, mutate_self: bool, mutate_all: bool) -> Self {
+        if let Some(auth) = self.authorisations.last_mut() {
+            auth.rights
+                .push((entity.to_string(), mutate_self, mutate_all));
+        } else {
+            self.orphans += 1;
+        }
+        self
+    }
+
+    ///
+    /// adds a user to the current authorisation, using its base64 verifying key
+    ///
+    pub fn user(mut self, verifying_key: &str) -> Self {
+        if let Some(auth) = self.authorisations.last_mut() {
+            auth.users.push(verifying_key.to_string());
+        } else {
+            self.orphans += 1;
+        }
+        self
+    }
+
+    ///
+    /// adds a user admin to the current authorisation, using its base64 verifying key
+    ///
+    /// user admins are allowed to add and disable the users of the authorisation
+    ///
+    pub fn user_admin(mut self, verifying_key: &str) -> Self {
+        if let Some(auth) = self.authorisations.last_mut() {
+            auth.user_admins.push(verifying_key.to_string());
+        } else {
+            self.orphans += 1;
+        }
+        self
+    }
+
+    ///
+    /// generates the mutation and its parameters
+    ///
+    /// every value is passed as a parameter, names and keys are never inserted in the query text
+    ///
+    pub fn mutation(&self) -> Result<(String, Parameters), crate::Error> {
+        if self.orphans > 0 {
+            return Err(crate::Error::InvalidRoomBuilder(
+                "right(), user() and user_admin() must be called after auth()".to_string(),
+            ));
+        }
+        let mut params = Parameters::new();
+        let mut fields = Vec::new();
+
+        if !self.admins.is_empty() {
+            let admins = user_array("admin", &self.admins, &mut params)?;
+            fields.push(format!("admin: [{admins}]"));
+        }
+
+        let mut authorisations = Vec::new();
+        for (a, auth) in self.authorisations.iter().enumerate() {
+            let var = format!("auth_{a}");
+            params.add(&var, auth.name.clone())?;
+            let mut auth_fields = vec![format!("name: ${var}")];
+
+            if !auth.rights.is_empty() {
+                let mut rights = Vec::new();
+                for (r, (entity, mutate_self, mutate_all)) in auth.rights.iter().enumerate() {
+                    let var = format!("auth_{a}_right_{r}");
+                    params.add(&var, entity.clone())?;
+                    params.add(&format!("{var}_self"), *mutate_self)?;
+                    params.add(&format!("{var}_all"), *mutate_all)?;
+                    rights.push(format!(
+                        "{{entity: ${var} mutate_self: ${var}_self mutate_all: ${var}_all}}"
+                    ));
+                }
+                auth_fields.push(format!("rights: [{}]", rights.join(", ")));
+            }
+            if !auth.users.is_empty() {
+                let users = user_array(&format!("auth_{a}_user"), &auth.users, &mut params)?;
+                auth_fields.push(format!("users: [{users}]"));
+            }
+            if !auth.user_admins.is_empty() {
+                let user_admins = user_array(
+                    &format!("auth_{a}_user_admin"),
+                    &auth.user_admins,
+                    &mut params,
+                )?;
+                auth_fields.push(format!("user_admin: [{user_admins}]"));
+            }
+            authorisations.push(format!("{{{}}}", auth_fields.join(" ")));
+        }
+        if !authorisations.is_empty() {
+            fields.push(format!("authorisations: [{}]", authorisations.join(", ")));
+        }
+
+        let query = format!("mutate {{ sys.Room {{ {} }} }}", fields.join(" "));
+        Ok((query, params))
+    }
+
+    ///
+    /// executes the mutation and returns the identifiers of the new room and of its authorisations
+    ///
+    pub async fn execute(&self, db: &GraphDatabaseService) -> Result<CreatedRoom, crate::Error> {
+        #[derive(Deserialize)]
+        struct Auth {
+            id: String,
+            name: String,
+        }
+        #[derive(Deserialize)]
+        struct Room {
+            id: String,
+            #[serde(default)]
+            authorisations: Vec<Auth>,
+        }
+
+        let (mutation, params) = self.mutation()?;
+        let res = db.mutate(&mutation, Some(params)).await?;
+        let mut parser = ResultParser::new(&res)?;
+        let room: Room = parser.take_object("sys.Room")?;
+        Ok(CreatedRoom {
+            id: room.id,
+            authorisations: room
+                .authorisations
+                .into_iter()
+                .map(|auth| (auth.name, auth.id))
+                .collect(),
+        })
+    }
+}
+
+///
+/// builds a list of sys.UserAuth, the verifying keys are passed as parameters
+///
+fn user_array(
+    prefix: &str,
+    keys: &[String],
+    params: &mut Parameters,
+) -> Result<String, crate::Error> {
+    let mut users = Vec::with_capacity(keys.len());
+    for (i, key) in keys.iter().enumerate() {
+        let var = format!("{prefix}_{i}");
+        params.add(&var, key.clone())?;
+        users.push(format!("{{verif_key: ${var}}}"));
+    }
+    Ok(users.join(", "))
+}
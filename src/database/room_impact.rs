@@ -0,0 +1,168 @@
+use std::collections::BTreeSet;
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::security::{base64_encode, uid_encode, Uid};
+
+use super::{
+    query_language::data_model_parser::DataModel,
+    room::{RightType, Room},
+};
+
+///
+/// A right of a *Room* member
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoomRight {
+    ///
+    /// the member can modify the room definition
+    ///
+    Admin,
+    ///
+    /// the member can create and modify its own nodes of the entity
+    ///
+    MutateSelf(String),
+    ///
+    /// the member can modify the nodes of the entity created by any member
+    ///
+    MutateAll(String),
+}
+
+///
+/// A right gained or lost by a member
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberRightChange {
+    pub verifying_key: String,
+    pub right: RoomRight,
+    pub granted: bool,
+}
+
+///
+/// Nodes of an entity authored by a member that loses the right to mutate them
+/// - authored: number of nodes that the member will not be able to modify or delete anymore
+/// - rejected: number of nodes dated after the change, they would be rejected when synchronised
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AffectedData {
+    pub verifying_key: String,
+    pub entity: String,
+    pub authored: u64,
+    pub rejected: u64,
+}
+
+///
+/// Result of *Discret::preview_room_change()*
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoomChangeImpact {
+    pub room_id: String,
+    pub changes: Vec<MemberRightChange>,
+    pub affected_data: Vec<AffectedData>,
+}
+impl RoomChangeImpact {
+    ///
+    /// compares the rights of every member of the room before and after the change, at the date of the change
+    ///
+    pub fn build(
+        old_room: &Room,
+        new_room: &Room,
+        date: i64,
+        data_model: &DataModel,
+        conn: &Connection,
+    ) -> std::result::Result<Self, rusqlite::Error> {
+        let mut members: Vec<Vec<u8>> =
+            old_room.users().union(&new_room.users()).cloned().collect();
+        members.sort();
+
+        let mut entities = BTreeSet::new();
+        for room in [old_room, new_room] {
+            for auth in room.authorisations.values() {
+                entities.extend(auth.rights.keys().cloned());
+            }
+        }
+
+        let mut changes = Vec::new();
+        for member in &members {
+            let verifying_key = base64_encode(member);
+            let was_admin = old_room.is_admin(member, date);
+            let is_admin = new_room.is_admin(member, date);
+            if was_admin != is_admin {
+                changes.push(MemberRightChange {
+                    verifying_key: verifying_key.clone(),
+                    right: RoomRight::Admin,
+                    granted: is_admin,
+                });
+            }
+            for entity in &entities {
+                for right in [RightType::MutateSelf, RightType::MutateAll] {
+                    let could = old_room.can(member, entity, date, &right);
+                    let can = new_room.can(member, entity, date, &right);
+                    if could != can {
+                        let right = match right {
+                            RightType::MutateSelf => RoomRight::MutateSelf(entity.clone()),
+                            RightType::MutateAll => RoomRight::MutateAll(entity.clone()),
+                        };
+                        changes.push(MemberRightChange {
+                            verifying_key: verifying_key.clone(),
+                            right,
+                            granted: can,
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut affected_data = Vec::new();
+        for (member, entity_short, authored, rejected) in authored_nodes(&old_room.id, date, conn)?
+        {
+            let entity = match data_model.name_for(&entity_short) {
+                Some(name) => name,
+                None => continue,
+            };
+            if old_room.can(&member, &entity, date, &RightType::MutateSelf)
+                && !new_room.can(&member, &entity, date, &RightType::MutateSelf)
+            {
+                affected_data.push(AffectedData {
+                    verifying_key: base64_encode(&member),
+                    entity,
+                    authored,
+                    rejected,
+                });
+            }
+        }
+
+        Ok(Self {
+            room_id: uid_encode(&old_room.id),
+            changes,
+            affected_data,
+        })
+    }
+}
+
+//author, entity short name, number of nodes, number of nodes dated after the change
+type AuthoredNodes = (Vec<u8>, String, u64, u64);
+
+///
+/// number of nodes per author and entity short name, and the number of those nodes dated after the date
+///
+fn authored_nodes(
+    room_id: &Uid,
+    date: i64,
+    conn: &Connection,
+) -> std::result::Result<Vec<AuthoredNodes>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(
+        "
+        SELECT verifying_key, _entity, count(*), sum(mdate >= ?)
+        FROM _node
+        WHERE room_id = ?
+        GROUP BY verifying_key, _entity
+        ORDER BY verifying_key, _entity
+        ",
+    )?;
+    let rows = stmt.query_map((date, room_id), |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    })?;
+    rows.collect()
+}
@@ -126,7 +126,7 @@ impl RoomNode {
 
         let mut admin_edges = Edge::get_edges(id, ROOM_ADMIN_FIELD_SHORT, conn)?;
         //user insertion order is mandatory
-        admin_edges.sort_by(|a, b| b.cdate.cmp(&a.cdate));
+        admin_edges.sort_by_key(|e| std::cmp::Reverse(e.cdate));
 
         let mut admin_nodes = Vec::new();
         for edge in &admin_edges {
@@ -179,6 +179,25 @@ impl RoomNode {
 
         Ok(room)
     }
+
+    ///
+    /// verifying key of the peer that signed the last modification of a user of the room
+    ///
+    pub fn user_modified_by(&self, verifying_key: &Vec<u8>) -> Option<Vec<u8>> {
+        let mut user_nodes: Vec<&UserNode> = self.admin_nodes.iter().collect();
+        for auth in &self.auth_nodes {
+            user_nodes.extend(auth.user_nodes.iter());
+            user_nodes.extend(auth.user_admin_nodes.iter());
+        }
+        user_nodes
+            .into_iter()
+            .filter(|user_node| match user_node.parse() {
+                Ok(user) => user.verifying_key.eq(verifying_key),
+                Err(_) => false,
+            })
+            .max_by_key(|user_node| user_node.node.mdate)
+            .map(|user_node| user_node.node.verifying_key.clone())
+    }
 }
 
 ///
@@ -312,7 +331,7 @@ impl AuthorisationNode {
         let mut last_modified = node.mdate;
         let mut right_edges = Edge::get_edges(id, AUTH_RIGHTS_FIELD_SHORT, conn)?;
         //rights insertion must respect must be done in the right order
-        right_edges.sort_by(|a, b| b.cdate.cmp(&a.cdate));
+        right_edges.sort_by_key(|e| std::cmp::Reverse(e.cdate));
 
         let mut right_nodes = Vec::new();
         for edge in &right_edges {
@@ -325,7 +344,7 @@ impl AuthorisationNode {
 
         let mut user_edges = Edge::get_edges(id, AUTH_USER_FIELD_SHORT, conn)?;
         //user insertion order is mandatory
-        user_edges.sort_by(|a, b| b.cdate.cmp(&a.cdate));
+        user_edges.sort_by_key(|e| std::cmp::Reverse(e.cdate));
 
         let mut user_nodes = Vec::new();
         for edge in &user_edges {
@@ -338,7 +357,7 @@ impl AuthorisationNode {
 
         let mut user_admin_edges = Edge::get_edges(id, AUTH_USER_ADMIN_FIELD_SHORT, conn)?;
         //user insertion order is mandatory
-        user_admin_edges.sort_by(|a, b| b.cdate.cmp(&a.cdate));
+        user_admin_edges.sort_by_key(|e| std::cmp::Reverse(e.cdate));
 
         let mut user_admin_nodes = Vec::new();
         for edge in &user_admin_edges {
@@ -535,7 +554,7 @@ pub fn prepare_room_with_history(
             room_node.admin_edges.push(old_edge.clone());
         }
     }
-    room_node.admin_edges.sort_by(|a, b| a.cdate.cmp(&b.cdate));
+    room_node.admin_edges.sort_by_key(|a| a.cdate);
 
     for old_user in &old_room_node.admin_nodes {
         let admin_node = room_node
@@ -557,9 +576,7 @@ pub fn prepare_room_with_history(
             }
         }
     }
-    room_node
-        .admin_nodes
-        .sort_by(|a, b| a.node.mdate.cmp(&b.node.mdate));
+    room_node.admin_nodes.sort_by_key(|a| a.node.mdate);
 
     //
     // Find new admins and add them to the cloned room
@@ -729,9 +746,7 @@ fn prepare_auth_with_history(
             new_auth.user_admin_edges.push(old_edge.clone());
         }
     }
-    new_auth
-        .user_admin_edges
-        .sort_by(|a, b| a.cdate.cmp(&b.cdate));
+    new_auth.user_admin_edges.sort_by_key(|a| a.cdate);
 
     for old_user in &old_auth.user_admin_nodes {
         let user_admin_node = new_auth
@@ -753,9 +768,7 @@ fn prepare_auth_with_history(
             }
         }
     }
-    new_auth
-        .user_admin_nodes
-        .sort_by(|a, b| a.node.mdate.cmp(&b.node.mdate));
+    new_auth.user_admin_nodes.sort_by_key(|a| a.node.mdate);
 
     //
     //
@@ -793,7 +806,7 @@ fn prepare_auth_with_history(
             new_auth.user_edges.push(old_edge.clone());
         }
     }
-    new_auth.user_edges.sort_by(|a, b| a.cdate.cmp(&b.cdate));
+    new_auth.user_edges.sort_by_key(|a| a.cdate);
 
     for old_user in &old_auth.user_nodes {
         let user_node = new_auth
@@ -815,9 +828,7 @@ fn prepare_auth_with_history(
             }
         }
     }
-    new_auth
-        .user_nodes
-        .sort_by(|a, b| a.node.mdate.cmp(&b.node.mdate));
+    new_auth.user_nodes.sort_by_key(|a| a.node.mdate);
 
     //
     // verify new users
@@ -854,7 +865,7 @@ fn prepare_auth_with_history(
             new_auth.right_edges.push(old_edge.clone());
         }
     }
-    new_auth.right_edges.sort_by(|a, b| a.cdate.cmp(&b.cdate));
+    new_auth.right_edges.sort_by_key(|a| a.cdate);
 
     for old_right in &old_auth.right_nodes {
         let right_node = new_auth
@@ -877,9 +888,7 @@ fn prepare_auth_with_history(
             }
         }
     }
-    new_auth
-        .right_nodes
-        .sort_by(|a, b| a.node.mdate.cmp(&b.node.mdate));
+    new_auth.right_nodes.sort_by_key(|a| a.node.mdate);
 
     //
     // verify new right
@@ -937,7 +946,7 @@ mod tests {
             system_entities::ROOM_AUTHORISATION_FIELD,
         },
         date_utils::now,
-        event_service::EventService,
+        event_service::{Event, EventService},
         security::{base64_encode, new_uid, random32, Ed25519SigningKey},
     };
 
@@ -1757,4 +1766,121 @@ mod tests {
             .await
             .expect("no right error, protected by a previous consitency check, the edge point a node that will be verified");
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn room_membership_events() {
+        init_database_path();
+        let data_model = "{ Person{ name:String } }";
+
+        let path: PathBuf = DATA_PATH.into();
+        let (first_app, first_key, _) = GraphDatabaseService::start(
+            "authorisation app",
+            data_model,
+            &random32(),
+            &random32(),
+            path,
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+        let first_user_id = base64_encode(&first_key);
+
+        let path: PathBuf = DATA_PATH.into();
+        let events = EventService::new();
+        let mut receiver = events.subcribe().await;
+        let (second_app, second_key, _) = GraphDatabaseService::start(
+            "authorisation app",
+            data_model,
+            &random32(),
+            &random32(),
+            path,
+            &Configuration::default(),
+            events,
+        )
+        .await
+        .unwrap();
+        let second_user_id = base64_encode(&second_key);
+
+        let mut param = Parameters::default();
+        param.add("user_id", first_user_id.clone()).unwrap();
+        let room = first_app
+            .mutate_raw(
+                r#"mutate mut {
+                    sys.Room{
+                        admin: [{
+                            verif_key:$user_id
+                        }]
+                        authorisations:[{
+                            name:"members"
+                            rights:[{
+                                entity:"Person"
+                                mutate_self:true
+                                mutate_all:false
+                            }]
+                        }]
+                    }
+                }"#,
+                Some(param),
+            )
+            .await
+            .unwrap();
+        let room_insert = &room.mutate_entities[0];
+        let room_uid = room_insert.node_to_mutate.id;
+        let room_id = base64_encode(&room_uid);
+        let auth_insert = &room_insert.sub_nodes.get(ROOM_AUTHORISATION_FIELD).unwrap()[0];
+        let auth_id = base64_encode(&auth_insert.node_to_mutate.id);
+
+        let membership = |enabled: bool| {
+            let mut param = Parameters::default();
+            param.add("room_id", room_id.clone()).unwrap();
+            param.add("auth_id", auth_id.clone()).unwrap();
+            param.add("user_id", second_user_id.clone()).unwrap();
+            param.add("enabled", enabled).unwrap();
+            param
+        };
+        let update = r#"mutate mut {
+                sys.Room{
+                    id:$room_id
+                    authorisations:[{
+                        id:$auth_id
+                        users: [{
+                            verif_key:$user_id
+                            enabled:$enabled
+                        }]
+                    }]
+                }
+            }"#;
+
+        for enabled in [true, false] {
+            first_app
+                .mutate_raw(update, Some(membership(enabled)))
+                .await
+                .unwrap();
+
+            let node = first_app.get_room_node(room_uid).await.unwrap().unwrap();
+            //serialize and deserialize to get rid of the local_id
+            let ser = bincode::serialize(&node).unwrap();
+            let node: RoomNode = bincode::deserialize(&ser).unwrap();
+            second_app.add_room_node(node).await.unwrap();
+
+            loop {
+                match receiver.recv().await.unwrap() {
+                    Event::RoomJoined(room, by) => {
+                        assert!(enabled);
+                        assert_eq!(room_id, room);
+                        assert_eq!(first_key, by);
+                        break;
+                    }
+                    Event::RoomLeft(room, by) => {
+                        assert!(!enabled);
+                        assert_eq!(room_id, room);
+                        assert_eq!(first_key, by);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
 }
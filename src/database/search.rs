@@ -0,0 +1,378 @@
+use rusqlite::{functions::FunctionFlags, params_from_iter, Connection, ToSql};
+use serde::{Deserialize, Serialize};
+
+use crate::security::{base64_encode, uid_encode, Uid};
+
+use super::{
+    node::{extract_json, Node},
+    query::tombstone_exclusion,
+    system_entities::DELETED_FIELD,
+    Result,
+};
+
+//maximum number of hits returned by a search
+pub const MAX_HITS: usize = 100;
+
+//number of characters kept on each side of the matched term
+const SNIPPET_CONTEXT: usize = 40;
+
+///
+/// name of the SQL function used by the snippet() query function
+///
+pub const SNIPPET_FUNCTION: &str = "_search_snippet";
+
+const ELLIPSIS: &str = "...";
+
+///
+/// An entity matching the searched term
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SearchHit {
+    ///
+    /// name of the entity, like "chat.Message"
+    ///
+    pub entity: String,
+
+    ///
+    /// identifier of the entity
+    ///
+    pub id: String,
+
+    ///
+    /// room of the entity
+    ///
+    pub room_id: Option<String>,
+
+    ///
+    /// excerpt of the indexed text around the searched term
+    ///
+    pub snippet: String,
+
+    ///
+    /// relevance of the hit computed by the full text index, higher is better
+    ///
+    /// the relevance of the hits of a search can be compared whatever their entity
+    ///
+    pub relevance: f64,
+}
+
+///
+/// searches the full text index of the entities, the best hits first
+///
+/// entities contains the (short name, name) of the searched entities, rooms restricts the search when not empty.
+/// Like the queries, the search ignores the nodes targeted by a tombstone, the soft deleted nodes of the entities listed in soft_delete
+/// and the nodes whose sys_readers does not contain the verifying key.
+///
+pub fn search_all(
+    term: &str,
+    rooms: &[Uid],
+    entities: &[(String, String)],
+    soft_delete: &[String],
+    verifying_key: &[u8],
+    conn: &Connection,
+) -> Result<Vec<SearchHit>> {
+    if entities.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut params: Vec<Box<dyn ToSql>> = vec![Box::new(term.to_string())];
+    let mut query = String::from(
+        "SELECT _node.id, _node.room_id, _node._entity, _node._json, _node_fts.rank
+        FROM _node_fts JOIN _node ON _node_fts.rowid=_node.rowid
+        WHERE _node_fts MATCH ? AND _node._entity IN (",
+    );
+    query.push_str(&vec!["?"; entities.len()].join(","));
+    query.push(')');
+    for (short, _) in entities {
+        params.push(Box::new(short.clone()));
+    }
+    if !rooms.is_empty() {
+        query.push_str(" AND _node.room_id IN (");
+        query.push_str(&vec!["?"; rooms.len()].join(","));
+        query.push(')');
+        for room in rooms {
+            params.push(Box::new(room.to_vec()));
+        }
+    }
+    if !soft_delete.is_empty() {
+        query.push_str(&format!(
+            " AND (_node._json->>'$.{}' IS NULL OR _node._entity NOT IN (",
+            DELETED_FIELD
+        ));
+        query.push_str(&vec!["?"; soft_delete.len()].join(","));
+        query.push_str("))");
+        for short in soft_delete {
+            params.push(Box::new(short.clone()));
+        }
+    }
+    query.push_str(" AND ");
+    query.push_str(&tombstone_exclusion("_node"));
+    query.push_str(" AND ");
+    query.push_str(&Node::readable_by_condition("_node", params.len() + 1));
+    params.push(Box::new(verifying_key.to_vec()));
+    params.push(Box::new(base64_encode(verifying_key)));
+
+    query.push_str(&format!(" ORDER BY rank LIMIT {}", MAX_HITS));
+
+    let mut stmt = conn.prepare(&query)?;
+    let mut rows = stmt.query(params_from_iter(params.iter()))?;
+    let mut hits = Vec::new();
+    while let Some(row) = rows.next()? {
+        let id: Vec<u8> = row.get(0)?;
+        let room_id: Option<Vec<u8>> = row.get(1)?;
+        let short: String = row.get(2)?;
+        let json: Option<String> = row.get(3)?;
+        let rank: f64 = row.get(4)?;
+
+        let entity = entities
+            .iter()
+            .find(|(s, _)| s.eq(&short))
+            .map(|(_, name)| name.clone())
+            .unwrap_or(short);
+
+        let mut text = String::new();
+        if let Some(json) = json {
+            let value: serde_json::Value = serde_json::from_str(&json)?;
+            extract_json(&value, &mut text)?;
+        }
+
+        hits.push(SearchHit {
+            entity,
+            id: uid_encode(&to_uid(id)),
+            room_id: room_id.map(|r| uid_encode(&to_uid(r))),
+            snippet: snippet(text.trim_end(), term),
+            //the FTS5 rank is negative, the most relevant hit has the lowest rank
+            relevance: -rank,
+        });
+    }
+    Ok(hits)
+}
+
+fn to_uid(bytes: Vec<u8>) -> Uid {
+    let mut uid: Uid = Default::default();
+    let len = bytes.len().min(uid.len());
+    uid[0..len].copy_from_slice(&bytes[0..len]);
+    uid
+}
+
+///
+/// excerpt of the text around the first word of the term found in the text
+///
+/// the full text syntax of the term (quotes, operators, prefix) is ignored
+///
+pub fn snippet(text: &str, term: &str) -> String {
+    excerpt(text, term, SNIPPET_CONTEXT)
+}
+
+///
+/// excerpt of the text keeping context characters on each side of the first word of the term found in the text
+///
+/// the first 2 * context characters are returned when the term is not found
+///
+pub fn excerpt(text: &str, term: &str, context: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars.iter().map(|c| lowercase(*c)).collect();
+
+    let position = term
+        .split(|c: char| c.is_whitespace() || "\"*()^:+".contains(c))
+        .filter(|w| !w.is_empty() && !["AND", "OR", "NOT", "NEAR"].contains(w))
+        .find_map(|word| {
+            let word: Vec<char> = word.chars().map(lowercase).collect();
+            lower
+                .windows(word.len())
+                .position(|w| w.eq(word.as_slice()))
+                .map(|pos| (pos, word.len()))
+        });
+
+    let (start, end) = match position {
+        Some((pos, len)) => (
+            pos.saturating_sub(context),
+            (pos + len + context).min(chars.len()),
+        ),
+        None => (0, (2 * context).min(chars.len())),
+    };
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push_str(ELLIPSIS);
+    }
+    snippet.extend(&chars[start..end]);
+    if end < chars.len() {
+        snippet.push_str(ELLIPSIS);
+    }
+    snippet
+}
+
+///
+/// adds the function used by the snippet(field, length) query function:
+///     _search_snippet(text, term, length)
+///
+/// the full text index does not store the indexed text, the FTS5 snippet() function cannot be used
+///
+pub fn add_search_functions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        SNIPPET_FUNCTION,
+        3,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| {
+            let text = ctx.get_raw(0).as_str_or_null()?;
+            let term = ctx.get_raw(1).as_str_or_null()?.unwrap_or_default();
+            let length = ctx.get::<i64>(2)?.max(0) as usize;
+            Ok(text.map(|text| excerpt(text, term, length / 2)))
+        },
+    )
+}
+
+//lowercase that keeps the number of characters
+fn lowercase(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::{
+            sqlite_database::prepare_connection,
+            system_entities::{READERS_FIELD, TOMBSTONE_ENT_SHORT, TOMBSTONE_TARGET_SHORT},
+        },
+        security::new_uid,
+    };
+
+    use super::*;
+
+    #[test]
+    fn filters() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let local = [1u8; 32];
+        let other = [2u8; 32];
+        let room_id = new_uid();
+        let insert = |entity: &str, json: String, key: &[u8]| -> Uid {
+            let mut node = Node {
+                id: new_uid(),
+                room_id: Some(room_id),
+                _entity: entity.to_string(),
+                verifying_key: key.to_vec(),
+                _json: Some(json),
+                ..Default::default()
+            };
+            node.write(&conn, true, &None, &Some("lighthouse".to_string()))
+                .unwrap();
+            node.id
+        };
+
+        let visible = insert("msg", "{}".to_string(), &other);
+        let tombstoned = insert("msg", "{}".to_string(), &other);
+        insert(
+            TOMBSTONE_ENT_SHORT,
+            format!(
+                r#"{{"{}":"{}"}}"#,
+                TOMBSTONE_TARGET_SHORT,
+                base64_encode(&tombstoned)
+            ),
+            &other,
+        );
+        let deleted = format!(r#"{{"{}":12}}"#, DELETED_FIELD);
+        let soft_deleted = insert("note", deleted.clone(), &other);
+        //the deletion field is only meaningfull for the entities defining soft_delete
+        let not_soft_delete = insert("msg", deleted, &other);
+        let for_local = insert(
+            "msg",
+            format!(r#"{{"{}":["{}"]}}"#, READERS_FIELD, base64_encode(&local)),
+            &other,
+        );
+        let for_other = insert(
+            "msg",
+            format!(r#"{{"{}":["{}"]}}"#, READERS_FIELD, base64_encode(&other)),
+            &other,
+        );
+        let own = insert(
+            "note",
+            format!(r#"{{"{}":["{}"]}}"#, READERS_FIELD, base64_encode(&other)),
+            &local,
+        );
+
+        let entities = [
+            ("msg".to_string(), "Message".to_string()),
+            ("note".to_string(), "Note".to_string()),
+        ];
+        let soft_delete = ["note".to_string()];
+        let hits = search_all("lighthouse", &[], &entities, &soft_delete, &local, &conn).unwrap();
+        let ids: Vec<String> = hits.into_iter().map(|hit| hit.id).collect();
+        for id in [visible, not_soft_delete, for_local, own] {
+            assert!(ids.contains(&uid_encode(&id)));
+        }
+        for id in [tombstoned, soft_deleted, for_other] {
+            assert!(!ids.contains(&uid_encode(&id)));
+        }
+        assert_eq!(4, ids.len());
+
+        //the same filters apply when searching a subset of the entities in a room
+        let hits = search_all(
+            "lighthouse",
+            &[room_id],
+            &entities[1..],
+            &soft_delete,
+            &local,
+            &conn,
+        )
+        .unwrap();
+        assert_eq!(1, hits.len());
+        assert_eq!(uid_encode(&own), hits[0].id);
+
+        //without soft_delete, the deleted nodes are found
+        let hits =
+            search_all("lighthouse", &[room_id], &entities[1..], &[], &local, &conn).unwrap();
+        assert_eq!(2, hits.len());
+    }
+
+    #[test]
+    fn snippets() {
+        assert_eq!("Hello world", snippet("Hello world", "WORLD"));
+        assert_eq!("Hello world", snippet("Hello world", "\"wor\"* OR nothing"));
+
+        let text = format!("{} needle {}", "a".repeat(100), "b".repeat(100));
+        let found = snippet(&text, "needle");
+        assert!(found.starts_with(ELLIPSIS) && found.ends_with(ELLIPSIS));
+        assert!(found.contains(" needle "));
+        assert_eq!(6 + 2 * SNIPPET_CONTEXT + 2 * ELLIPSIS.len(), found.len());
+
+        //the beginning of the text when the term is not found
+        let found = snippet(&text, "missing");
+        assert_eq!(2 * SNIPPET_CONTEXT + ELLIPSIS.len(), found.len());
+        assert!(found.starts_with('a'));
+
+        assert_eq!("Été à Paris", snippet("Été à Paris", "été"));
+
+        let found = excerpt(&text, "needle", 4);
+        assert_eq!("...aaa needle bbb...", found);
+        assert_eq!("aaaa...", excerpt(&text, "missing", 2));
+    }
+
+    #[test]
+    fn snippet_function() {
+        let conn = Connection::open_in_memory().unwrap();
+        add_search_functions(&conn).unwrap();
+        let found: Option<String> = conn
+            .query_row(
+                &format!("SELECT {}(?, ?, 10)", SNIPPET_FUNCTION),
+                ("Hello wonderful world", "wonderful"),
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(Some("...ello wonderful worl...".to_string()), found);
+
+        let found: Option<String> = conn
+            .query_row(
+                &format!("SELECT {}(NULL, ?, 10)", SNIPPET_FUNCTION),
+                ["wonderful"],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(found.is_none());
+    }
+}
@@ -1,5 +1,7 @@
 #[cfg(feature = "log")]
-use log::{error, info};
+use crate::log_level::{error, info, LogSubsystem};
+#[cfg(feature = "log")]
+const LOG_SUBSYSTEM: LogSubsystem = LogSubsystem::Database;
 
 #[cfg(test)]
 use rusqlite::ToSql;
@@ -7,37 +9,60 @@ use rusqlite::ToSql;
 use rusqlite::{functions::FunctionFlags, Connection, OptionalExtension, Row};
 
 use std::{
-    path::PathBuf,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
     thread,
     time::{self, Duration},
 };
 use tokio::sync::{
     mpsc,
     oneshot::{self, Sender},
+    Notify,
 };
 
-use crate::security::{base64_decode, base64_encode, Uid};
+use crate::{
+    geo_utils::add_geo_functions,
+    security::{base64_decode, base64_encode, Uid},
+};
 
 use super::{
     authorisation_service::{
         AuthorisationMessage, RoomMutationStreamWriteQuery, RoomMutationWriteQuery,
         RoomNodeWriteQuery,
     },
+    binary_store::BinaryStore,
+    custom_function::add_custom_functions,
     daily_log::{DailyLog, DailyLogsUpdate, DailyMutations},
     deletion::DeletionQuery,
     edge::{Edge, EdgeDeletionEntry},
+    entity_counter::EntityCounter,
+    file::FileInfo,
     graph_database::DbMessage,
+    log_retention::LogPruning,
     mutation_query::MutationQuery,
-    node::{Node, NodeDeletionEntry, NodeToInsert},
-    system_entities, Error, Result,
+    node::{extract_json, Node, NodeDeletionEntry, NodeToInsert},
+    replication::{PeerRoomLog, PeerSyncStats},
+    search::add_search_functions,
+    system_entities,
+    transaction::TransactionQuery,
+    Error, Result,
 };
 
 pub type RowMappingFn<T> = fn(&Row) -> std::result::Result<Box<T>, rusqlite::Error>;
 pub type QueryFn = Box<dyn FnOnce(&Connection) + Send + 'static>;
 
+pub enum ReadMessage {
+    Query(QueryFn),
+    Relocate(Arc<Relocation>),
+}
+
 //Create a sqlcipher database connection
 //
-//path: database file path
+//path: database file path, or a SQLite URI starting with 'file:' (used for in memory databases or custom VFS)
 //
 //secret: the encryption key
 //
@@ -61,6 +86,9 @@ pub fn create_connection(
     flags.insert(rusqlite::OpenFlags::SQLITE_OPEN_CREATE);
     flags.insert(rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE);
 
+    //Allows the 'file:' URI format. Regular paths are not affected
+    flags.insert(rusqlite::OpenFlags::SQLITE_OPEN_URI);
+
     //Don't follow unix symbolic link
     // flags.insert(rusqlite::OpenFlags::SQLITE_OPEN_NOFOLLOW);
 
@@ -142,12 +170,17 @@ pub fn create_connection(
 ///
 /// Creates the necessary tables in one transaction.
 ///
-/// Add a user defined function to handle base64 encoding directly in the database
+/// Add a user defined function to handle base64 encoding directly in the database,
+/// the functions used by the Geo filters and the search snippets
+/// and the functions registered by the application
 ///
 /// This function is separated from create_connection() to be able to create unit test using in_memory databases
 ///
 pub fn prepare_connection(conn: &Connection) -> Result<()> {
     add_base64_function(conn)?;
+    add_geo_functions(conn)?;
+    add_search_functions(conn)?;
+    add_custom_functions(conn)?;
     let initialised: Option<String> = conn
         .query_row(
             "SELECT name FROM sqlite_schema WHERE type IN ('table','view') AND name = '_node'",
@@ -164,6 +197,14 @@ pub fn prepare_connection(conn: &Connection) -> Result<()> {
         system_entities::create_table(conn)?;
         conn.execute("COMMIT", [])?;
     }
+    //tables added after the initial release are created when missing
+    MutationQuery::create_tables(conn)?;
+    LogPruning::create_tables(conn)?;
+    PeerRoomLog::create_tables(conn)?;
+    PeerSyncStats::create_tables(conn)?;
+    FileInfo::create_tables(conn)?;
+    BinaryStore::create_tables(conn)?;
+    EntityCounter::create_tables(conn)?;
     Ok(())
 }
 
@@ -176,6 +217,158 @@ fn set_pragma(pragma: &str, value: &str, conn: &rusqlite::Connection) -> Result<
     Ok(())
 }
 
+///
+/// SQLITE_CORRUPT and SQLITE_NOTADB are returned when the database file is damaged
+///
+pub fn is_corruption(error: &rusqlite::Error) -> bool {
+    matches!(
+        error.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseCorrupt) | Some(rusqlite::ErrorCode::NotADatabase)
+    )
+}
+
+///
+/// path of the file that receives the rows salvaged from a corrupted database
+///
+pub fn recovered_path(path: &Path) -> PathBuf {
+    let mut file = path.to_path_buf().into_os_string();
+    file.push(".recovered");
+    PathBuf::from(file)
+}
+
+///
+/// Copies the readable rows of a corrupted database into a new database file.
+///
+/// Rows are copied one by one and the copy of a table stops at the first unreadable row.
+/// The full text index cannot be read and is rebuilt from the recovered nodes.
+///
+/// returns the number of recovered rows
+///
+pub fn recover_database(path: &PathBuf, secret: &[u8; 32], target_path: &PathBuf) -> Result<usize> {
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = target_path.clone().into_os_string();
+        file.push(suffix);
+        let file = PathBuf::from(file);
+        if file.exists() {
+            fs::remove_file(file)?;
+        }
+    }
+    let source = create_connection(path, secret, 2048, false)?;
+    set_pragma("query_only", "1", &source)?;
+    let target = create_connection(target_path, secret, 2048, false)?;
+    prepare_connection(&target)?;
+
+    let mut tables_stmt = target.prepare(
+        "SELECT name FROM sqlite_schema
+        WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE '_node_fts%'",
+    )?;
+    let tables: Vec<String> = tables_stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<std::result::Result<_, _>>()?;
+
+    let mut recovered = 0;
+    target.execute("BEGIN TRANSACTION", [])?;
+    for table in &tables {
+        let mut columns_stmt = target.prepare(&format!("PRAGMA table_info({})", table))?;
+        let columns: Vec<String> = columns_stmt
+            .query_map([], |row| row.get(1))?
+            .collect::<std::result::Result<_, _>>()?;
+        let columns = columns.join(",");
+        let placeholders = vec!["?"; columns.split(',').count()].join(",");
+
+        let select = format!("SELECT {} FROM {}", columns, table);
+        let mut select_stmt = match source.prepare(&select) {
+            Ok(stmt) => stmt,
+            Err(_e) => {
+                #[cfg(feature = "log")]
+                error!("recover_database, table {table} cannot be read: {_e}");
+                continue;
+            }
+        };
+        let mut insert_stmt = target.prepare(&format!(
+            "INSERT OR IGNORE INTO {} ({}) VALUES ({})",
+            table, columns, placeholders
+        ))?;
+        let column_count = select_stmt.column_count();
+        let mut rows = match select_stmt.query([]) {
+            Ok(rows) => rows,
+            Err(_e) => {
+                #[cfg(feature = "log")]
+                error!("recover_database, table {table} cannot be read: {_e}");
+                continue;
+            }
+        };
+        loop {
+            match rows.next() {
+                Ok(Some(row)) => {
+                    let mut values: Vec<rusqlite::types::Value> = Vec::with_capacity(column_count);
+                    for i in 0..column_count {
+                        values.push(row.get(i)?);
+                    }
+                    recovered += insert_stmt.execute(rusqlite::params_from_iter(values))?;
+                }
+                Ok(None) => break,
+                Err(_e) => {
+                    #[cfg(feature = "log")]
+                    error!("recover_database, table {table} is partially recovered: {_e}");
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut nodes_stmt =
+        target.prepare("SELECT rowid, _json FROM _node WHERE _json IS NOT NULL")?;
+    let mut fts_stmt = target.prepare("INSERT INTO _node_fts (rowid, text) VALUES (?, ?)")?;
+    let mut rows = nodes_stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let rowid: i64 = row.get(0)?;
+        let json: String = row.get(1)?;
+        if let Ok(json) = serde_json::from_str::<serde_json::Value>(&json) {
+            let mut text = String::new();
+            extract_json(&json, &mut text)?;
+            fts_stmt.execute((rowid, text))?;
+        }
+    }
+    //the triggers have counted the references of nodes copied before the store
+    BinaryStore::recount(&target)?;
+    target.execute("COMMIT", [])?;
+    Ok(recovered)
+}
+
+///
+/// Replaces the database with the recovered one, the corrupted files are kept with the '.corrupted' extension
+///
+pub fn swap_recovered_database(path: &PathBuf) -> Result<bool> {
+    let recovered = recovered_path(path);
+    if !recovered.exists() {
+        return Ok(false);
+    }
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = path.clone().into_os_string();
+        file.push(suffix);
+        let file = PathBuf::from(file);
+        if file.exists() {
+            let mut corrupted = path.clone().into_os_string();
+            corrupted.push(".corrupted");
+            corrupted.push(suffix);
+            fs::rename(&file, PathBuf::from(corrupted))?;
+        }
+    }
+    for suffix in ["-wal", "-shm"] {
+        let mut file = recovered.clone().into_os_string();
+        file.push(suffix);
+        let file = PathBuf::from(file);
+        if file.exists() {
+            let mut moved = path.clone().into_os_string();
+            moved.push(suffix);
+            fs::rename(&file, PathBuf::from(moved))?;
+        }
+    }
+    fs::rename(&recovered, path)?;
+    Ok(true)
+}
+
 ///
 /// Database main entry point
 ///
@@ -186,6 +379,7 @@ pub struct Database {
     pub writer: BufferedDatabaseWriter,
 }
 impl Database {
+    #[allow(clippy::too_many_arguments)]
     pub fn start(
         path: &PathBuf,
         secret: &[u8; 32],
@@ -194,6 +388,7 @@ impl Database {
         write_cache_size_in_kb: usize,
         write_buffer_size: usize,
         enable_memory_security: bool,
+        daily_log_sender: Option<mpsc::Sender<DbMessage>>,
     ) -> Result<Self> {
         let writer = BufferedDatabaseWriter::start(
             write_buffer_size,
@@ -201,6 +396,7 @@ impl Database {
             secret,
             write_cache_size_in_kb,
             enable_memory_security,
+            daily_log_sender,
         )?;
 
         let reader = DatabaseReader::start(
@@ -213,6 +409,194 @@ impl Database {
 
         Ok(Database { reader, writer })
     }
+
+    ///
+    /// Moves the database files to the target path.
+    ///
+    /// The writer and the readers close their connections and wait for the files to be moved,
+    /// the writes sent in the meantime are queued and processed once the database is reopened.
+    /// The database is reopened at its previous path if the files cannot be moved.
+    ///
+    /// A connection that cannot be reopened is not restored: a reader stops serving queries
+    /// and the writer switches to the read-only mode until the next startup, the error is returned.
+    ///
+    /// Blocks until the database is reopened, must not be called from an async context.
+    ///
+    pub fn relocate(&self, path: &Path, target: &PathBuf, secret: &[u8; 32]) -> Result<()> {
+        if target.exists() {
+            return Err(Error::Relocation(format!(
+                "'{}' already exists",
+                target.to_string_lossy()
+            )));
+        }
+        let relocation = Arc::new(Relocation::default());
+        let mut connections = 0;
+        let sent = (|| {
+            self.writer
+                .sender
+                .blocking_send(WriteMessage::Relocate(relocation.clone()))
+                .map_err(|e| Error::ChannelSend(e.to_string()))?;
+            connections += 1;
+            //every reader thread holds a receiver, the readers that could not be reopened by a previous relocation are gone
+            for _ in 0..self.reader.sender.receiver_count() {
+                self.reader
+                    .sender
+                    .send(ReadMessage::Relocate(relocation.clone()))
+                    .map_err(|e| Error::ChannelSend(e.to_string()))?;
+                connections += 1;
+            }
+            Ok(())
+        })();
+        if let Err(e) = sent {
+            //the connections that received the message reopen the database where it is
+            relocation.moved(path.to_path_buf());
+            return Err(e);
+        }
+
+        relocation.wait_closed(connections);
+        let result = move_database(path, target, secret);
+        let reopen_path = if result.is_ok() {
+            target.clone()
+        } else {
+            path.to_path_buf()
+        };
+        relocation.moved(reopen_path);
+        let errors = relocation.wait_reopened(connections);
+        result?;
+        if !errors.is_empty() {
+            return Err(Error::Relocation(format!(
+                "the database has been moved but could not be reopened: {}",
+                errors.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+///
+/// Synchronises the connections during a *Database::relocate()*
+///
+#[derive(Default)]
+pub struct Relocation {
+    state: Mutex<RelocationState>,
+    changed: Condvar,
+}
+#[derive(Default)]
+struct RelocationState {
+    //connections waiting for the files to be moved
+    closed: usize,
+    //connections that tried to reopen the database
+    reopened: usize,
+    //where the database must be reopened, set once the files are moved
+    path: Option<PathBuf>,
+    errors: Vec<String>,
+}
+impl Relocation {
+    fn reopen(
+        &self,
+        conn: Option<Connection>,
+        secret: &[u8; 32],
+        cache_size_in_kb: usize,
+        enable_memory_security: bool,
+        query_only: bool,
+    ) -> Result<Connection> {
+        drop(conn);
+        let path = {
+            let mut state = self.state.lock().unwrap();
+            state.closed += 1;
+            self.changed.notify_all();
+            let state = self
+                .changed
+                .wait_while(state, |state| state.path.is_none())
+                .unwrap();
+            state.path.clone().unwrap()
+        };
+
+        let result = create_connection(&path, secret, cache_size_in_kb, enable_memory_security)
+            .and_then(|conn| {
+                if query_only {
+                    set_pragma("query_only", "1", &conn)?;
+                }
+                Ok(conn)
+            });
+
+        let mut state = self.state.lock().unwrap();
+        state.reopened += 1;
+        if let Err(e) = &result {
+            state.errors.push(e.to_string());
+        }
+        self.changed.notify_all();
+        result
+    }
+
+    fn wait_closed(&self, connections: usize) {
+        let state = self.state.lock().unwrap();
+        let _state = self
+            .changed
+            .wait_while(state, |state| state.closed < connections)
+            .unwrap();
+    }
+
+    fn moved(&self, path: PathBuf) {
+        self.state.lock().unwrap().path = Some(path);
+        self.changed.notify_all();
+    }
+
+    fn wait_reopened(&self, connections: usize) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let mut state = self
+            .changed
+            .wait_while(state, |state| state.reopened < connections)
+            .unwrap();
+        std::mem::take(&mut state.errors)
+    }
+}
+
+//
+// the database, its WAL files and a recovered database waiting for the next startup are moved together
+// files are moved back if any of them cannot be moved or if the moved database cannot be opened
+//
+fn move_database(path: &Path, target: &PathBuf, secret: &[u8; 32]) -> Result<()> {
+    let mut moved = Vec::new();
+    let result = (|| {
+        for base in [path.to_path_buf(), recovered_path(path)] {
+            let target_base = if base == path {
+                target.clone()
+            } else {
+                recovered_path(target)
+            };
+            for suffix in ["", "-wal", "-shm"] {
+                let mut source = base.clone().into_os_string();
+                source.push(suffix);
+                let source = PathBuf::from(source);
+                if !source.exists() {
+                    continue;
+                }
+                let mut destination = target_base.clone().into_os_string();
+                destination.push(suffix);
+                let destination = PathBuf::from(destination);
+                move_file(&source, &destination)?;
+                moved.push((source, destination));
+            }
+        }
+        create_connection(target, secret, 1024, false)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        for (source, destination) in moved.iter().rev() {
+            let _ = move_file(destination, source);
+        }
+    }
+    result
+}
+
+//rename fails when the target is on another file system
+fn move_file(source: &Path, destination: &Path) -> std::io::Result<()> {
+    if fs::rename(source, destination).is_err() {
+        fs::copy(source, destination)?;
+        fs::remove_file(source)?;
+    }
+    Ok(())
 }
 
 // Main entry point to perform SELECT queries
@@ -224,7 +608,7 @@ impl Database {
 //
 #[derive(Clone)]
 pub struct DatabaseReader {
-    pub sender: flume::Sender<QueryFn>,
+    pub sender: flume::Sender<ReadMessage>,
 }
 impl DatabaseReader {
     pub fn start(
@@ -234,7 +618,7 @@ impl DatabaseReader {
         parallelism: usize,
         enable_memory_security: bool,
     ) -> Result<Self> {
-        let (sender, receiver) = flume::bounded::<QueryFn>(100);
+        let (sender, receiver) = flume::bounded::<ReadMessage>(100);
         for _i in 0..parallelism {
             //
             // sleep a few milliseconds to avoid some random IO errors during tests on linux
@@ -244,15 +628,32 @@ impl DatabaseReader {
             //
             let ten_millis = time::Duration::from_millis(50);
             thread::sleep(ten_millis);
-            let conn =
-                create_connection(path, secret, cache_size_in_kb, enable_memory_security).unwrap();
+            let mut conn =
+                create_connection(path, secret, cache_size_in_kb, enable_memory_security)?;
 
             set_pragma("query_only", "1", &conn)?;
 
             let local_receiver = receiver.clone();
+            let secret = *secret;
             thread::spawn(move || {
-                while let Ok(q) = local_receiver.recv() {
-                    q(&conn);
+                while let Ok(msg) = local_receiver.recv() {
+                    match msg {
+                        ReadMessage::Query(q) => q(&conn),
+                        ReadMessage::Relocate(relocation) => {
+                            //the moved database has been opened by Database::relocate()
+                            match relocation.reopen(
+                                Some(conn),
+                                &secret,
+                                cache_size_in_kb,
+                                enable_memory_security,
+                                true,
+                            ) {
+                                Ok(reopened) => conn = reopened,
+                                //the other readers keep serving the queries, the error is returned by Database::relocate()
+                                Err(_) => break,
+                            }
+                        }
+                    }
                 }
             });
         }
@@ -268,7 +669,7 @@ impl DatabaseReader {
 
     pub async fn send_async(&self, query: QueryFn) -> Result<()> {
         self.sender
-            .send_async(query)
+            .send_async(ReadMessage::Query(query))
             .await
             .map_err(|e| Error::ChannelSend(e.to_string()))?;
         Ok(())
@@ -299,7 +700,7 @@ impl DatabaseReader {
         let (send_response, receive_response) = oneshot::channel::<Result<Vec<T>>>();
 
         self.send_async(Box::new(move |conn| {
-            let result = Self::select(&query, &params, &mapping, conn).map_err(Error::from);
+            let result = Self::select(&query, &params, &mapping, conn);
             let _ = send_response.send(result);
         }))
         .await?;
@@ -338,6 +739,8 @@ pub enum WriteMessage {
     Deletion(DeletionQuery, Sender<Result<DeletionQuery>>),
     Mutation(MutationQuery, Sender<Result<MutationQuery>>),
     MutationStream(MutationQuery, mpsc::Sender<Result<MutationQuery>>),
+    MutationBatch(Vec<MutationQuery>, Sender<Result<Vec<MutationQuery>>>),
+    Transaction(Vec<TransactionQuery>, Sender<Result<Vec<TransactionQuery>>>),
     RoomMutation(RoomMutationWriteQuery, mpsc::Sender<AuthorisationMessage>),
     RoomMutationStream(
         RoomMutationStreamWriteQuery,
@@ -351,6 +754,7 @@ pub enum WriteMessage {
     Write(WriteStmt, Sender<Result<WriteStmt>>),
     ComputeDailyLog(DailyLogsUpdate, mpsc::Sender<DbMessage>),
     Optimize,
+    Relocate(Arc<Relocation>),
 }
 
 /// Main entry point to insert data in the database
@@ -373,10 +777,22 @@ pub enum WriteMessage {
 /// The only reasons to fail an insertion are a bugs or a system failure (like no more space available on disk),
 /// And in both case, it is ok to fail the last insertions batch.
 ///
+/// When a daily_log_sender is provided, batches containing local mutations or deletions compute the daily log in the same transaction
+/// and the result is sent right after the commit, allowing the connected peers to be notified without waiting for another write.
 ///
 #[derive(Clone)]
 pub struct BufferedDatabaseWriter {
     sender: mpsc::Sender<WriteMessage>,
+    //set when a corruption is detected, every following write is rejected
+    pub corrupted: Arc<AtomicBool>,
+    backlog: Arc<WriteBacklog>,
+}
+
+//number of write messages received by the writer and not yet committed
+#[derive(Default)]
+struct WriteBacklog {
+    pending: AtomicUsize,
+    drained: Notify,
 }
 impl BufferedDatabaseWriter {
     pub fn start(
@@ -385,8 +801,15 @@ impl BufferedDatabaseWriter {
         secret: &[u8; 32],
         write_cache_size: usize,
         enable_memory_security: bool,
+        daily_log_sender: Option<mpsc::Sender<DbMessage>>,
     ) -> Result<Self> {
-        let conn = create_connection(path, secret, write_cache_size, enable_memory_security)?;
+        let mut conn = Some(create_connection(
+            path,
+            secret,
+            write_cache_size,
+            enable_memory_security,
+        )?);
+        let secret = *secret;
         //only a few query can be buffered here
         //the real buffering using the buffer_size happens later
         const WRITE_QUERY_BUFFER: usize = 4;
@@ -405,6 +828,8 @@ impl BufferedDatabaseWriter {
             mpsc::Receiver<Vec<WriteMessage>>,
         ) = mpsc::channel::<Vec<WriteMessage>>(PROCESS_CHANNEL_SIZE);
 
+        let backlog = Arc::new(WriteBacklog::default());
+        let received = backlog.clone();
         tokio::spawn(async move {
             let mut query_buffer: Vec<WriteMessage> = vec![];
             let mut query_buffer_length = 0;
@@ -415,6 +840,7 @@ impl BufferedDatabaseWriter {
                     write_query = receive_write.recv() => {
                         match write_query {
                             Some(query) => {
+                                received.pending.fetch_add(1, Ordering::Relaxed);
                                 query_buffer_length += 1;
                                 query_buffer.push(query);
                             },
@@ -454,11 +880,32 @@ impl BufferedDatabaseWriter {
             }
         });
 
+        let corrupted = Arc::new(AtomicBool::new(false));
+        let read_only = corrupted.clone();
+        let processed = backlog.clone();
         thread::spawn(move || {
             while let Some(mut buffer) = receive_buffer.blocking_recv() {
-                let result = Self::process_batch_write(&mut buffer, &conn);
+                let buffer_length = buffer.len();
+                let mut relocation = None;
+                let result = match &conn {
+                    Some(conn) if !read_only.load(Ordering::Relaxed) => {
+                        Self::process_batch_write(&mut buffer, conn, daily_log_sender.is_some())
+                    }
+                    _ => Err(rusqlite::Error::SqliteFailure(
+                        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT),
+                        Some(
+                            "the database is corrupted and has been switched to read-only mode"
+                                .to_string(),
+                        ),
+                    )),
+                };
+                processed
+                    .pending
+                    .fetch_sub(buffer_length, Ordering::Relaxed);
+                processed.drained.notify_waiters();
+
                 match result {
-                    Ok(_) => {
+                    Ok(daily_log_update) => {
                         for msg in buffer {
                             match msg {
                                 WriteMessage::Deletion(q, r) => {
@@ -466,21 +913,53 @@ impl BufferedDatabaseWriter {
                                 }
 
                                 WriteMessage::Mutation(q, r) => {
-                                    let _ = r.send(Ok(q));
+                                    let _ = r.send(match q.write_error() {
+                                        Some(e) => Err(e),
+                                        None => Ok(q),
+                                    });
                                 }
 
                                 WriteMessage::MutationStream(q, r) => {
-                                    let _ = r.blocking_send(Ok(q));
+                                    let _ = r.blocking_send(match q.write_error() {
+                                        Some(e) => Err(e),
+                                        None => Ok(q),
+                                    });
+                                }
+
+                                WriteMessage::MutationBatch(q, r) => {
+                                    let _ = r.send(match q.iter().find_map(|q| q.write_error()) {
+                                        Some(e) => Err(e),
+                                        None => Ok(q),
+                                    });
+                                }
+
+                                WriteMessage::Transaction(q, r) => {
+                                    let error = q.iter().find_map(|q| match q {
+                                        TransactionQuery::Mutation(q) => q.write_error(),
+                                        TransactionQuery::Deletion(_) => None,
+                                    });
+                                    let _ = r.send(match error {
+                                        Some(e) => Err(e),
+                                        None => Ok(q),
+                                    });
                                 }
 
                                 WriteMessage::RoomMutation(q, r) => {
+                                    let res = match q.write_error() {
+                                        Some(e) => Err(e),
+                                        None => Ok(()),
+                                    };
                                     let _ = r.blocking_send(
-                                        AuthorisationMessage::RoomMutationWrite(Ok(()), q),
+                                        AuthorisationMessage::RoomMutationWrite(res, q),
                                     );
                                 }
                                 WriteMessage::RoomMutationStream(q, r) => {
+                                    let res = match q.write_error() {
+                                        Some(e) => Err(e),
+                                        None => Ok(()),
+                                    };
                                     let _ = r.blocking_send(
-                                        AuthorisationMessage::RoomMutationStreamWrite(Ok(()), q),
+                                        AuthorisationMessage::RoomMutationStreamWrite(res, q),
                                     );
                                 }
 
@@ -516,34 +995,58 @@ impl BufferedDatabaseWriter {
                                 WriteMessage::Optimize => {
                                     //do nothing
                                 }
+                                WriteMessage::Relocate(r) => relocation = Some(r),
                             }
                         }
+                        if let (Some(update), Some(sender)) = (daily_log_update, &daily_log_sender)
+                        {
+                            let _ = sender.blocking_send(DbMessage::DailyLogComputed(Ok(update)));
+                        }
                     }
                     Err(e) => {
+                        let corruption = is_corruption(&e);
+                        if corruption && !read_only.swap(true, Ordering::Relaxed) {
+                            if let Some(sender) = &daily_log_sender {
+                                let _ = sender
+                                    .blocking_send(DbMessage::DatabaseCorrupted(e.to_string()));
+                            }
+                        }
+                        let write_error = || {
+                            if corruption {
+                                Error::DatabaseCorrupted(e.to_string())
+                            } else {
+                                Error::DatabaseWrite(e.to_string())
+                            }
+                        };
                         for msg in buffer {
                             match msg {
                                 WriteMessage::Deletion(_, r) => {
-                                    let _ = r.send(Err(Error::DatabaseWrite(e.to_string())));
+                                    let _ = r.send(Err(write_error()));
                                 }
                                 WriteMessage::Mutation(_, r) => {
-                                    let _ = r.send(Err(Error::DatabaseWrite(e.to_string())));
+                                    let _ = r.send(Err(write_error()));
                                 }
 
                                 WriteMessage::MutationStream(_, r) => {
-                                    let _ =
-                                        r.blocking_send(Err(Error::DatabaseWrite(e.to_string())));
+                                    let _ = r.blocking_send(Err(write_error()));
+                                }
+                                WriteMessage::MutationBatch(_, r) => {
+                                    let _ = r.send(Err(write_error()));
+                                }
+                                WriteMessage::Transaction(_, r) => {
+                                    let _ = r.send(Err(write_error()));
                                 }
                                 WriteMessage::RoomMutation(q, r) => {
                                     let _ =
                                         r.blocking_send(AuthorisationMessage::RoomMutationWrite(
-                                            Err(Error::DatabaseWrite(e.to_string())),
+                                            Err(write_error()),
                                             q,
                                         ));
                                 }
                                 WriteMessage::RoomMutationStream(q, r) => {
                                     let _ = r.blocking_send(
                                         AuthorisationMessage::RoomMutationStreamWrite(
-                                            Err(Error::DatabaseWrite(e.to_string())),
+                                            Err(write_error()),
                                             q,
                                         ),
                                     );
@@ -551,12 +1054,12 @@ impl BufferedDatabaseWriter {
 
                                 WriteMessage::RoomNode(q, r) => {
                                     let _ = r.blocking_send(AuthorisationMessage::RoomNodeWrite(
-                                        Err(Error::DatabaseWrite(e.to_string())),
+                                        Err(write_error()),
                                         q,
                                     ));
                                 }
                                 WriteMessage::Write(_, r) => {
-                                    let _ = r.send(Err(Error::DatabaseWrite(e.to_string())));
+                                    let _ = r.send(Err(write_error()));
                                 }
                                 WriteMessage::ComputeDailyLog(_, r) => {
                                     let _ = r.blocking_send(DbMessage::DailyLogComputed(Err(
@@ -564,20 +1067,43 @@ impl BufferedDatabaseWriter {
                                     )));
                                 }
                                 WriteMessage::Nodes(_, _, r) => {
-                                    let _ = r.send(Err(Error::DatabaseWrite(e.to_string())));
+                                    let _ = r.send(Err(write_error()));
                                 }
                                 WriteMessage::Edges(_, _, r) => {
-                                    let _ = r.send(Err(Error::DatabaseWrite(e.to_string())));
+                                    let _ = r.send(Err(write_error()));
                                 }
                                 WriteMessage::DeleteEdges(_, r) => {
-                                    let _ = r.send(Err(Error::DatabaseWrite(e.to_string())));
+                                    let _ = r.send(Err(write_error()));
                                 }
                                 WriteMessage::DeleteNodes(_, r) => {
-                                    let _ = r.send(Err(Error::DatabaseWrite(e.to_string())));
+                                    let _ = r.send(Err(write_error()));
                                 }
                                 WriteMessage::Optimize => {
                                     //do nothing
                                 }
+                                WriteMessage::Relocate(r) => relocation = Some(r),
+                            }
+                        }
+                    }
+                }
+                //the writes of the batch are committed in the database before it is moved
+                if let Some(relocation) = relocation {
+                    //the moved database has been opened by Database::relocate()
+                    match relocation.reopen(
+                        conn.take(),
+                        &secret,
+                        write_cache_size,
+                        enable_memory_security,
+                        false,
+                    ) {
+                        Ok(reopened) => conn = Some(reopened),
+                        Err(e) => {
+                            //without connection, the writes are rejected until the next startup
+                            if !read_only.swap(true, Ordering::Relaxed) {
+                                if let Some(sender) = &daily_log_sender {
+                                    let _ = sender
+                                        .blocking_send(DbMessage::DatabaseCorrupted(e.to_string()));
+                                }
                             }
                         }
                     }
@@ -597,20 +1123,45 @@ impl BufferedDatabaseWriter {
             }
         });
 
-        Ok(Self { sender: send_write })
+        Ok(Self {
+            sender: send_write,
+            corrupted,
+            backlog,
+        })
+    }
+
+    //
+    // writes the queries of a batch or of a transaction inside a savepoint
+    // when a mutation is rejected by its idempotency key, every write of the batch is cancelled
+    // without failing the other messages of the writer buffer
+    //
+    fn write_all_or_nothing(
+        conn: &Connection,
+        write: impl FnOnce() -> std::result::Result<bool, rusqlite::Error>,
+    ) -> std::result::Result<bool, rusqlite::Error> {
+        conn.execute("SAVEPOINT all_or_nothing", [])?;
+        let written = write()?;
+        if !written {
+            conn.execute("ROLLBACK TO all_or_nothing", [])?;
+        }
+        conn.execute("RELEASE all_or_nothing", [])?;
+        Ok(written)
     }
 
     fn process_batch_write(
         buffer: &mut Vec<WriteMessage>,
         conn: &Connection,
-    ) -> std::result::Result<(), rusqlite::Error> {
+        compute_local_writes: bool,
+    ) -> std::result::Result<Option<DailyLogsUpdate>, rusqlite::Error> {
         let mut daily_log = DailyMutations::default();
         let mut optimize = false; //flag to run the optimize task outside a transaction
+        let mut local_write = false; //flag to compute the daily log of local writes in the same transaction
 
         conn.execute("BEGIN TRANSACTION", [])?;
         for query in buffer {
             match query {
                 WriteMessage::Deletion(query, _) => {
+                    local_write = true;
                     if let Err(e) = query.delete(conn) {
                         conn.execute("ROLLBACK", [])?;
                         return Err(e);
@@ -618,6 +1169,7 @@ impl BufferedDatabaseWriter {
                     query.update_daily_logs(&mut daily_log);
                 }
                 WriteMessage::Mutation(query, _) => {
+                    local_write = true;
                     if let Err(e) = query.write(conn) {
                         conn.execute("ROLLBACK", [])?;
                         return Err(e);
@@ -626,6 +1178,7 @@ impl BufferedDatabaseWriter {
                 }
 
                 WriteMessage::MutationStream(query, _) => {
+                    local_write = true;
                     if let Err(e) = query.write(conn) {
                         conn.execute("ROLLBACK", [])?;
                         return Err(e);
@@ -633,6 +1186,73 @@ impl BufferedDatabaseWriter {
                     query.update_daily_logs(&mut daily_log);
                 }
 
+                WriteMessage::MutationBatch(queries, _) => {
+                    local_write = true;
+                    let res = Self::write_all_or_nothing(conn, || {
+                        //replayed mutations are allready written
+                        for query in queries.iter_mut().filter(|q| q.replayed.is_none()) {
+                            query.write(conn)?;
+                            if query.key_mismatch {
+                                return Ok(false);
+                            }
+                        }
+                        Ok(true)
+                    });
+                    match res {
+                        Ok(true) => {
+                            for query in queries.iter() {
+                                query.update_daily_logs(&mut daily_log);
+                            }
+                        }
+                        Ok(false) => {}
+                        Err(e) => {
+                            conn.execute("ROLLBACK", [])?;
+                            return Err(e);
+                        }
+                    }
+                }
+
+                WriteMessage::Transaction(queries, _) => {
+                    local_write = true;
+                    let res = Self::write_all_or_nothing(conn, || {
+                        for query in queries.iter_mut() {
+                            match query {
+                                TransactionQuery::Mutation(query) => {
+                                    //replayed mutations are allready written
+                                    if query.replayed.is_some() {
+                                        continue;
+                                    }
+                                    query.write(conn)?;
+                                    if query.key_mismatch {
+                                        return Ok(false);
+                                    }
+                                }
+                                TransactionQuery::Deletion(query) => query.delete(conn)?,
+                            }
+                        }
+                        Ok(true)
+                    });
+                    match res {
+                        Ok(true) => {
+                            for query in queries.iter() {
+                                match query {
+                                    TransactionQuery::Mutation(query) => {
+                                        query.update_daily_logs(&mut daily_log)
+                                    }
+                                    TransactionQuery::Deletion(query) => {
+                                        query.update_daily_logs(&mut daily_log)
+                                    }
+                                }
+                            }
+                        }
+                        Ok(false) => {}
+                        Err(e) => {
+                            conn.execute("ROLLBACK", [])?;
+                            return Err(e);
+                        }
+                    }
+                }
+
                 WriteMessage::Nodes(node, _, _) => {
                     for nti in node {
                         if let Err(e) = nti.write(conn) {
@@ -653,6 +1273,7 @@ impl BufferedDatabaseWriter {
                 }
 
                 WriteMessage::RoomMutation(query, _) => {
+                    local_write = true;
                     if let Err(e) = query.write(conn) {
                         conn.execute("ROLLBACK", [])?;
                         return Err(e);
@@ -661,6 +1282,7 @@ impl BufferedDatabaseWriter {
                 }
 
                 WriteMessage::RoomMutationStream(query, _) => {
+                    local_write = true;
                     if let Err(e) = query.write(conn) {
                         conn.execute("ROLLBACK", [])?;
                         return Err(e);
@@ -701,10 +1323,21 @@ impl BufferedDatabaseWriter {
                     }
                 }
                 WriteMessage::Optimize => optimize = true,
+                WriteMessage::Relocate(_) => {}
             }
         }
         //at the end of the batch, update the daily log with all room dates that needs to be recomputed
         daily_log.write(conn)?;
+
+        let mut daily_log_update = None;
+        if compute_local_writes && local_write {
+            let mut update = DailyLogsUpdate::default();
+            if let Err(e) = update.compute(conn) {
+                conn.execute("ROLLBACK", [])?;
+                return Err(e);
+            }
+            daily_log_update = Some(update);
+        }
         conn.execute("COMMIT", [])?;
 
         // run the PRAGMA optimize; outside the transaction
@@ -721,7 +1354,7 @@ impl BufferedDatabaseWriter {
             }
         }
 
-        Ok(())
+        Ok(daily_log_update)
     }
 
     ///
@@ -744,6 +1377,31 @@ impl BufferedDatabaseWriter {
         Ok(())
     }
 
+    ///
+    /// number of write messages waiting to be committed
+    ///
+    pub fn backlog(&self) -> usize {
+        self.backlog.pending.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// waits until the number of write messages waiting to be committed is lower than the limit
+    /// a limit of 0 never waits
+    ///
+    pub async fn wait_for_backlog(&self, limit: usize) {
+        if limit == 0 {
+            return;
+        }
+        loop {
+            //created before the check to not miss a notification
+            let drained = self.backlog.drained.notified();
+            if self.backlog() < limit {
+                return;
+            }
+            drained.await;
+        }
+    }
+
     // ///
     // /// send a write message without waiting for the query to finish
     // ///
@@ -804,10 +1462,7 @@ pub fn add_base64_function(db: &Connection) -> rusqlite::Result<()> {
             let result = match str {
                 Some(data) => {
                     let val = base64_decode(data.as_bytes());
-                    match val {
-                        Ok(e) => Some(e),
-                        Err(_) => None,
-                    }
+                    val.ok()
                 }
                 None => None,
             };
@@ -845,7 +1500,7 @@ mod tests {
     use std::str;
 
     const STRING_MAPPING: RowMappingFn<String> = |row| Ok(Box::new(row.get(0)?));
-    const SELECT_ALL: &'static str = "
+    const SELECT_ALL: &str = "
     SELECT 	
         json_object('name', name, 'surname', surname)
     FROM person";
@@ -861,6 +1516,28 @@ mod tests {
         Ok(path)
     }
 
+    #[test]
+    fn relocation_reopen_failure() {
+        fs::create_dir_all(DATA_PATH).unwrap();
+        let relocation = Arc::new(Relocation::default());
+        let mut connections = Vec::new();
+        for _ in 0..2 {
+            let relocation = relocation.clone();
+            connections.push(thread::spawn(move || {
+                relocation
+                    .reopen(None, &[0; 32], 1024, false, true)
+                    .is_err()
+            }));
+        }
+        relocation.wait_closed(2);
+        //a folder cannot be opened as a database
+        relocation.moved(DATA_PATH.into());
+        assert_eq!(2, relocation.wait_reopened(2).len());
+        for connection in connections {
+            assert!(connection.join().unwrap());
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_sqlite_version() {
         let path: PathBuf = init_database_path("test_sqlite_version.db").unwrap();
@@ -887,6 +1564,59 @@ mod tests {
         assert_eq!(0, val);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn recover_corrupted_database() {
+        let path: PathBuf = init_database_path("recover_corrupted.db").unwrap();
+        let secret = hash(b"bytes");
+        {
+            let conn = create_connection(&path, &secret, 1024, false).unwrap();
+            prepare_connection(&conn).unwrap();
+            conn.execute(
+                "INSERT INTO _node (id, cdate, mdate, _entity, _json, verifying_key, _signature)
+                VALUES (?, 0, 0, 'Person', ?, ?, ?)",
+                (vec![1u8], r#"{"name":"Steven"}"#, vec![2u8], vec![3u8]),
+            )
+            .unwrap();
+        }
+
+        let target = recovered_path(&path);
+        let recovered = recover_database(&path, &secret, &target).unwrap();
+        assert!(recovered >= 1);
+
+        let conn = create_connection(&target, &secret, 1024, false).unwrap();
+        let found: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM _node_fts WHERE _node_fts MATCH 'Steven'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(1, found);
+        drop(conn);
+
+        assert!(swap_recovered_database(&path).unwrap());
+        assert!(!target.exists());
+        let mut corrupted = path.clone().into_os_string();
+        corrupted.push(".corrupted");
+        assert!(PathBuf::from(corrupted).exists());
+        assert!(!swap_recovered_database(&path).unwrap());
+
+        let garbage: PathBuf = init_database_path("garbage.db").unwrap();
+        fs::write(&garbage, vec![7u8; 8192]).unwrap();
+        let conn = create_connection(&garbage, &secret, 1024, false);
+        match conn {
+            Ok(conn) => {
+                let e = prepare_connection(&conn).expect_err("garbage is not a database");
+                match e {
+                    Error::Database(e) => assert!(is_corruption(&e)),
+                    e => panic!("unexpected error {e}"),
+                }
+            }
+            Err(Error::Database(e)) => assert!(is_corruption(&e)),
+            Err(e) => panic!("unexpected error {e}"),
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn async_queries() {
         let path: PathBuf = init_database_path("async_queries.db").unwrap();
@@ -902,7 +1632,7 @@ mod tests {
         )
         .unwrap();
 
-        let writer = BufferedDatabaseWriter::start(10, &path, &secret, 1024, false).unwrap();
+        let writer = BufferedDatabaseWriter::start(10, &path, &secret, 1024, false, None).unwrap();
 
         writer
             .write(Box::new(InsertPerson {
@@ -936,7 +1666,7 @@ mod tests {
         )
         .unwrap();
 
-        let writer = BufferedDatabaseWriter::start(1, &path, &secret, 1024, false).unwrap();
+        let writer = BufferedDatabaseWriter::start(1, &path, &secret, 1024, false, None).unwrap();
 
         let loop_number = 10;
         let _start = Instant::now();
@@ -966,6 +1696,50 @@ mod tests {
         assert_eq!(loop_number, res.len());
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn write_backlog() {
+        let path: PathBuf = init_database_path("write_backlog.db").unwrap();
+        let secret = hash(b"bytes");
+        let conn = create_connection(&path, &secret, 1024, false).unwrap();
+
+        conn.execute(
+            "CREATE TABLE person (
+                id              INTEGER PRIMARY KEY,
+                name            TEXT NOT NULL,
+                surname         TEXT
+            ) STRICT",
+            [],
+        )
+        .unwrap();
+
+        let writer = BufferedDatabaseWriter::start(10, &path, &secret, 1024, false, None).unwrap();
+
+        let mut reply_list = vec![];
+        for _i in 0..200 {
+            let (reply, reciev) = oneshot::channel::<Result<WriteStmt, Error>>();
+            let query = WriteMessage::Write(
+                Box::new(InsertPerson {
+                    name: "Steven".to_string(),
+                    surname: "Bob".to_string(),
+                }),
+                reply,
+            );
+            writer.send(query).await.unwrap();
+            reply_list.push(reciev);
+        }
+
+        writer.wait_for_backlog(5).await;
+        assert!(writer.backlog() < 5);
+
+        //a limit of 0 disables the wait
+        writer.wait_for_backlog(0).await;
+
+        for reply in reply_list {
+            reply.await.unwrap().unwrap();
+        }
+        assert_eq!(0, writer.backlog());
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn batch_writes_buffersize_10() {
         let path: PathBuf = init_database_path("batch_writes_buffersize_10.db").unwrap();
@@ -982,7 +1756,7 @@ mod tests {
         )
         .unwrap();
 
-        let writer = BufferedDatabaseWriter::start(10, &path, &secret, 1024, false).unwrap();
+        let writer = BufferedDatabaseWriter::start(10, &path, &secret, 1024, false, None).unwrap();
 
         let loop_number = 32;
         let _start = Instant::now();
@@ -1027,7 +1801,7 @@ mod tests {
         )
         .unwrap();
 
-        let writer = BufferedDatabaseWriter::start(10, &path, &secret, 1024, false).unwrap();
+        let writer = BufferedDatabaseWriter::start(10, &path, &secret, 1024, false, None).unwrap();
         writer
             .write(Box::new(InsertPerson {
                 name: "Steven".to_string(),
@@ -1,5 +1,8 @@
 #![allow(dead_code)]
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
 
 use rusqlite::{params_from_iter, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
@@ -8,14 +11,21 @@ use tokio::sync::{mpsc, oneshot};
 use crate::{
     base64_decode, base64_encode,
     database::VEC_OVERHEAD,
-    security::{uid_decode, uid_encode, Ed25519SigningKey, MeetingToken, Uid},
+    date_utils::now,
+    security::{new_uid, uid_decode, uid_encode, Ed25519SigningKey, MeetingToken, Uid},
     Parameters, ParametersAdd,
 };
 
 use super::{
+    deletion::DeletionQuery,
     edge::Edge,
     graph_database::GraphDatabaseService,
+    mutation_query::{InsertEntity, MutationQuery},
     node::{extract_json, Node},
+    query_language::{
+        data_model_parser::TemplateSource,
+        mutation_parser::{EntityMutation, MutationFieldValue, MutationParser},
+    },
     sqlite_database::{Database, Writeable},
     Error, ResultParser,
 };
@@ -34,6 +44,8 @@ pub fn create_table(conn: &Connection) -> Result<(), rusqlite::Error> {
 }
 
 pub const SYSTEM_NAMESPACE: &str = "sys";
+//virtual entity used to query every entity of a room
+pub const ALL_ENTITIES: &str = "_all";
 //name of the system entities
 pub const ROOM_ENT: &str = "sys.Room";
 pub const ROOM_ENT_SHORT: &str = "0.0";
@@ -56,6 +68,29 @@ pub const ALLOWED_PEER_ENT_SHORT: &str = "0.5";
 //pub const ALLOWED_HARDWARE_ENT: &str = "sys.AllowedHardware";
 pub const ALLOWED_HARDWARE_ENT_SHORT: &str = "0.6";
 
+pub const ROOM_SETTINGS_ENT: &str = "sys.RoomSettings";
+
+///
+/// *Room* setting defining the bucket size used to coarsen the daily entry counts
+/// sent to peers that are not one of your own devices. Disabled when missing or lower than 2.
+///
+pub const LOG_PRIVACY_SETTING: &str = "log_privacy";
+
+pub const TOMBSTONE_ENT: &str = "sys.Tombstone";
+pub const TOMBSTONE_ENT_SHORT: &str = "0.10";
+
+pub const DATA_MODEL_PROPOSAL_ENT: &str = "sys.DataModelProposal";
+
+pub const DATA_MODEL_TEMPLATE_ENT: &str = "sys.DataModelTemplate";
+
+pub const AUTOMATION_RULE_ENT: &str = "sys.AutomationRule";
+
+pub const SYNC_INCIDENT_ENT: &str = "sys.SyncIncident";
+pub const SYNC_INCIDENT_ENT_SHORT: &str = "0.14";
+
+//maximum number of chained automation rules triggered by a mutation
+pub const MAX_AUTOMATION_DEPTH: usize = 4;
+
 //name of the system fields
 pub const ID_FIELD: &str = "id";
 pub const ROOM_ID_FIELD: &str = "room_id";
@@ -68,6 +103,17 @@ pub const JSON_FIELD: &str = "_json";
 pub const BINARY_FIELD: &str = "_binary";
 pub const VERIFYING_KEY_FIELD: &str = "verifying_key";
 pub const SIGNATURE_FIELD: &str = "_signature";
+///
+/// optional list of base64 verifying keys allowed to read a node, on top of its author.
+/// When missing or null, every member of the room can read the node
+///
+pub const READERS_FIELD: &str = "sys_readers";
+
+///
+/// deletion date of the nodes of the entities defining the 'soft_delete' option.
+/// Soft deleted nodes are excluded from the query results, unless the query uses 'include_deleted'
+///
+pub const DELETED_FIELD: &str = "sys_deleted";
 
 //names of some authentication fields used during auth validation
 pub const ROOM_ADMIN_FIELD: &str = "admin";
@@ -92,6 +138,12 @@ pub const RIGHT_MUTATE_ALL_SHORT: &str = "34";
 
 pub const PEER_PUB_KEY_SHORT: &str = "32";
 pub const PEER_NAME_SHORT: &str = "33";
+pub const PEER_METADATA_SHORT: &str = "34";
+
+///
+/// maximum size of the metadata attached to the local sys.Peer
+///
+pub const PEER_METADATA_MAX_SIZE: usize = 1024;
 
 pub const ALLOWED_PEER_PEER_SHORT: &str = "32";
 pub const ALLOWED_PEER_TOKEN_SHORT: &str = "33";
@@ -100,6 +152,15 @@ pub const ALLOWED_PEER_STATUS_SHORT: &str = "35";
 pub const ALLOWED_HARDWARE_NAME_SHORT: &str = "32";
 pub const ALLOWED_HARDWARE_STATUS_SHORT: &str = "33";
 
+pub const TOMBSTONE_TARGET_SHORT: &str = "32";
+
+pub const SYNC_INCIDENT_ROOM_SHORT: &str = "32";
+pub const SYNC_INCIDENT_PEER_SHORT: &str = "33";
+pub const SYNC_INCIDENT_DATE_SHORT: &str = "34";
+pub const SYNC_INCIDENT_NODES_SHORT: &str = "35";
+pub const SYNC_INCIDENT_EDGES_SHORT: &str = "36";
+pub const SYNC_INCIDENT_REASON_SHORT: &str = "37";
+
 pub const SYSTEM_DATA_MODEL: &str = r#"
 sys{
     // Entities for the authorisation model
@@ -128,7 +189,9 @@ sys{
 
     Peer{
         pub_key: Base64 ,
-        name: String default "anonymous"
+        name: String default "anonymous",
+        // application provided description of the device: app version, device name, platform...
+        metadata: Json nullable,
     }
 
     AllowedPeer(no_full_text_index){
@@ -154,8 +217,63 @@ sys{
         invite_sign: Base64,
     }
 
+    // Per room key/value settings, can only be mutated by the room admins
+    RoomSettings(no_full_text_index){
+        key: String,
+        value: Json,
+    }
+
+    // Moderation records, can only be created by the room admins
+    // the target node is excluded from the query results
+    Tombstone(no_full_text_index){
+        target: Base64,
+        reason: String nullable,
+    }
+
+    // Data model updates proposed by the room admins
+    // every peer applies the update once an admin confirms it
+    DataModelProposal(no_full_text_index){
+        model: String,
+        confirmed: Boolean default false,
+    }
+
+    // Data model template published by a room admin
+    // allows generic applications to acquire the data model of the rooms they are invited in
+    DataModelTemplate(no_full_text_index){
+        template_id: String,
+        model: String,
+    }
+
+    // Automation rules defined by the room admins
+    // the peer that inserts a trigger entity in the room applies the mutation
+    AutomationRule(no_full_text_index){
+        trigger: String,
+        mutation: String,
+        enabled: Boolean default true,
+    }
+
+    // Data rejected during the synchronisation of a room, written by the engine
+    // local to the device: stored outside of any room and never synchronised
+    SyncIncident(no_full_text_index){
+        room: Base64,
+        peer: Base64,
+        date: Integer,
+        nodes_rejected: Integer,
+        edges_rejected: Integer,
+        reason: String,
+    }
+
 }"#;
 
+///
+/// A page of the peers of a room, ordered by verifying key
+///
+#[derive(Serialize, Deserialize, Default)]
+pub struct PeerPage {
+    pub peers: Vec<Node>,
+    pub next: Option<Vec<u8>>,
+}
+
 #[derive(Deserialize, Clone)]
 pub struct Peer {
     pub id: String,
@@ -197,6 +315,66 @@ impl Peer {
         Ok(())
     }
 
+    ///
+    /// set the application metadata of a peer node, the metadata must be a JSON object
+    ///
+    /// returns false when the node allready contains the same metadata
+    ///
+    pub fn set_metadata(peer: &mut Node, metadata: &Option<String>) -> Result<bool, Error> {
+        let metadata: Option<serde_json::Value> = match metadata {
+            Some(metadata) => {
+                if metadata.len() > PEER_METADATA_MAX_SIZE {
+                    return Err(Error::InvalidJsonObject(format!(
+                        "Peer metadata is larger than {} bytes",
+                        PEER_METADATA_MAX_SIZE
+                    )));
+                }
+                let value: serde_json::Value = serde_json::from_str(metadata)?;
+                if !value.is_object() {
+                    return Err(Error::InvalidJsonObject(
+                        "Peer metadata is not an object".to_string(),
+                    ));
+                }
+                Some(value)
+            }
+            None => None,
+        };
+
+        let json = peer
+            ._json
+            .as_ref()
+            .ok_or(Error::InvalidPeerNode("empty json".to_string()))?;
+        let mut json: serde_json::Value = serde_json::from_str(json)?;
+        let map = json
+            .as_object_mut()
+            .ok_or(Error::InvalidJsonObject("Peer json".to_string()))?;
+
+        if map.get(PEER_METADATA_SHORT) == metadata.as_ref() {
+            return Ok(false);
+        }
+        match metadata {
+            Some(value) => map.insert(PEER_METADATA_SHORT.to_string(), value),
+            None => map.remove(PEER_METADATA_SHORT),
+        };
+        peer._json = Some(serde_json::to_string(&json)?);
+        Ok(true)
+    }
+
+    ///
+    /// the application metadata of a peer node
+    ///
+    pub fn metadata(peer: &Node) -> Result<Option<String>, Error> {
+        let json = match &peer._json {
+            Some(json) => json,
+            None => return Ok(None),
+        };
+        let json: serde_json::Value = serde_json::from_str(json)?;
+        match json.get(PEER_METADATA_SHORT) {
+            Some(value) if !value.is_null() => Ok(Some(serde_json::to_string(value)?)),
+            _ => Ok(None),
+        }
+    }
+
     pub fn pub_key(peer: &Node) -> Result<Vec<u8>, Error> {
         if peer._json.is_none() {
             return Err(Error::InvalidPeerNode("empty json".to_string()));
@@ -379,6 +557,67 @@ impl Peer {
         Ok(())
     }
 
+    ///
+    /// Retrieve one page of peer nodes, ordered by verifying key.
+    ///
+    /// The page starts after the 'after' verifying key and is filled until it reaches the batch_size.
+    /// The returned cursor is used to request the next page and is None when the last page is reached.
+    ///
+    pub fn get_peers_page(
+        keys: HashSet<Vec<u8>>,
+        after: Option<Vec<u8>>,
+        batch_size: usize,
+        conn: &Connection,
+    ) -> Result<PeerPage, Error> {
+        let mut keys: Vec<Vec<u8>> = match &after {
+            Some(after) => keys.into_iter().filter(|k| k > after).collect(),
+            None => keys.into_iter().collect(),
+        };
+        keys.sort();
+
+        //limit the IN clause to a reasonable size, avoiding the 32766 parameter limit in sqlite
+        let row_per_query = 500;
+        let mut page = PeerPage::default();
+        let mut len = 0;
+        for ids in keys.chunks(row_per_query) {
+            let in_clause = vec!["?"; ids.len()].join(",");
+            let query = format!(
+                "SELECT id, room_id, cdate, mdate, _entity,_json, _binary, verifying_key, _signature  
+                FROM _node 
+                WHERE _entity='{}' 
+                AND verifying_key IN ({})
+                AND room_id IS NULL
+                ORDER BY verifying_key",
+                PEER_ENT_SHORT, in_clause
+            );
+            let mut stmt = conn.prepare(&query)?;
+            let mut rows = stmt.query(params_from_iter(ids.iter()))?;
+            while let Some(row) = rows.next()? {
+                let node = Node {
+                    id: row.get(0)?,
+                    room_id: row.get(1)?,
+                    cdate: row.get(2)?,
+                    mdate: row.get(3)?,
+                    _entity: row.get(4)?,
+                    _json: row.get(5)?,
+                    _binary: row.get(6)?,
+                    verifying_key: row.get(7)?,
+                    _signature: row.get(8)?,
+                    _local_id: None,
+                };
+                let size = bincode::serialized_size(&node)?;
+                let insert_len = len + size + VEC_OVERHEAD;
+                if insert_len > batch_size as u64 && !page.peers.is_empty() {
+                    page.next = page.peers.last().map(|n| n.verifying_key.clone());
+                    return Ok(page);
+                }
+                len = insert_len;
+                page.peers.push(node);
+            }
+        }
+        Ok(page)
+    }
+
     pub fn get_node(
         verifying_key: Vec<u8>,
         conn: &Connection,
@@ -597,6 +836,61 @@ impl AllowedPeer {
         })
     }
 
+    ///
+    /// enables the pending entry of a peer, does nothing if the peer is not pending
+    ///
+    pub async fn enable(
+        room_id: &str,
+        verifying_key: &str,
+        db: &GraphDatabaseService,
+    ) -> Result<(), crate::Error> {
+        #[derive(Deserialize)]
+        struct Entry {
+            id: String,
+        }
+        let peer = match db
+            .get_peer_node(base64_decode(verifying_key.as_bytes())?)
+            .await?
+        {
+            Some(peer) => peer,
+            None => return Ok(()),
+        };
+
+        let query = "query {
+            result: sys.AllowedPeer(room_id=$room_id, status=$status){
+                id
+                peer(id=$peer_id){
+                    id
+                }
+            }
+        }";
+
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.to_string())?;
+        param.add("status", Status::Pending.value().to_string())?;
+        param.add("peer_id", uid_encode(&peer.id))?;
+        let peer_str = db.query(query, Some(param)).await?;
+        let mut query_result: ResultParser = ResultParser::new(&peer_str)?;
+        let result: Vec<Entry> = query_result.take_array("result")?;
+
+        for entry in result {
+            let mut param = Parameters::new();
+            param.add("id", entry.id)?;
+            param.add("status", Status::Enabled.value().to_string())?;
+            db.mutate(
+                "mutate {
+                    sys.AllowedPeer{
+                        id: $id
+                        status: $status
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
     pub async fn get(
         room_id: String,
         status: Status,
@@ -633,6 +927,7 @@ impl Writeable for AllowedPeerWriter {
         Ok(())
     }
 }
+#[allow(clippy::too_many_arguments)]
 pub async fn init_allowed_peers(
     database: &Database,
     peer_uid: Uid,
@@ -640,31 +935,45 @@ pub async fn init_allowed_peers(
     allowed_uid: Uid,
     private_room_id: Uid,
     token: MeetingToken,
+    metadata: &Option<String>,
     signing_key: &Ed25519SigningKey,
 ) -> Result<(), Error> {
     //init peer entity
-    let (reply, receive) = oneshot::channel::<Result<bool, Error>>();
+    let (reply, receive) = oneshot::channel::<Result<Option<Box<Node>>, Error>>();
     database
         .reader
         .send_async(Box::new(move |conn| {
-            let room_node = Node::exist(&peer_uid, PEER_ENT_SHORT, conn);
-            let _ = reply.send(room_node);
+            let peer_node =
+                Node::get_with_entity(&peer_uid, PEER_ENT_SHORT, conn).map_err(Error::from);
+            let _ = reply.send(peer_node);
         }))
         .await?;
-    let exists = receive.await??;
-    if !exists {
-        let mut peer_node = Peer::create(peer_uid, base64_encode(public_key));
-        peer_node.sign(signing_key)?;
-
-        let mut index = String::new();
-        let val = serde_json::from_str(&peer_node._json.clone().unwrap())?;
-        extract_json(&val, &mut index)?;
-        let peer_writer = PeerWriter {
-            node: peer_node,
-            index,
-        };
+    let peer_node = receive.await??;
+    match peer_node {
+        None => {
+            let mut peer_node = Peer::create(peer_uid, base64_encode(public_key));
+            Peer::set_metadata(&mut peer_node, metadata)?;
+            peer_node.sign(signing_key)?;
+
+            let mut index = String::new();
+            let val = serde_json::from_str(&peer_node._json.clone().unwrap())?;
+            extract_json(&val, &mut index)?;
+            let peer_writer = PeerWriter {
+                node: peer_node,
+                index,
+            };
 
-        database.writer.write(Box::new(peer_writer)).await?;
+            database.writer.write(Box::new(peer_writer)).await?;
+        }
+        Some(mut peer_node) => {
+            //the metadata is updated and signed again to be sent to the other peers during the next connections
+            if Peer::set_metadata(&mut peer_node, metadata)? {
+                peer_node.mdate = now();
+                peer_node.sign(signing_key)?;
+                let update = PeerUpdate::new(*peer_node)?;
+                database.writer.write(Box::new(update)).await?;
+            }
+        }
     }
 
     //init allowed_peer entity
@@ -696,6 +1005,54 @@ pub async fn init_allowed_peers(
     Ok(())
 }
 
+///
+/// Replaces a sys.Peer with a more recent version signed by the same peer.
+/// Used to store the peer metadata received during the connection handshake
+///
+pub struct PeerUpdate {
+    pub node: Node,
+    pub index: String,
+}
+impl PeerUpdate {
+    pub fn new(node: Node) -> Result<Self, Error> {
+        let mut index = String::new();
+        if let Some(json) = &node._json {
+            let val = serde_json::from_str(json)?;
+            extract_json(&val, &mut index)?;
+        }
+        Ok(Self { node, index })
+    }
+}
+impl Writeable for PeerUpdate {
+    fn write(&mut self, conn: &Connection) -> std::result::Result<(), rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT rowid, mdate, _json, verifying_key FROM _node WHERE id = ? AND _entity = ? AND room_id IS NULL",
+        )?;
+        let previous: Option<(i64, i64, Option<String>, Vec<u8>)> = stmt
+            .query_row((&self.node.id, PEER_ENT_SHORT), |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .optional()?;
+
+        if let Some((rowid, mdate, json, verifying_key)) = previous {
+            if !verifying_key.eq(&self.node.verifying_key) || mdate >= self.node.mdate {
+                return Ok(());
+            }
+            let mut old_index = None;
+            if let Some(Ok(val)) = json.map(|j| serde_json::from_str::<serde_json::Value>(&j)) {
+                let mut index = String::new();
+                if extract_json(&val, &mut index).is_ok() {
+                    old_index = Some(index);
+                }
+            }
+            self.node._local_id = Some(rowid);
+            self.node
+                .write(conn, true, &old_index, &Some(self.index.clone()))?;
+        }
+        Ok(())
+    }
+}
+
 //Initialised Peers needs to be inserted with the ftse index, otherwise it is not possible to update their name without getting ans horrible:'database disk image is malformed' error
 pub struct PeerWriter {
     pub node: Node,
@@ -840,6 +1197,41 @@ impl OwnedInvite {
         Ok(())
     }
 
+    ///
+    /// delete the owned invites created before the date and returns the number of deleted invites
+    ///
+    pub async fn delete_older_than(
+        room_id: String,
+        date: i64,
+        db: &GraphDatabaseService,
+    ) -> Result<usize, crate::Error> {
+        let mut param = Parameters::new();
+        param.add("room_id", room_id)?;
+        param.add("date", date)?;
+
+        let result = db
+            .query(
+                "query{
+            sys.OwnedInvite(room_id=$room_id, mdate < $date){
+                id
+            }
+        }",
+                Some(param),
+            )
+            .await?;
+
+        #[derive(Deserialize)]
+        struct InvId {
+            id: String,
+        }
+        let mut q = ResultParser::new(&result)?;
+        let ids: Vec<InvId> = q.take_array("sys.OwnedInvite")?;
+        for id in &ids {
+            Self::delete(uid_decode(&id.id)?, db).await?;
+        }
+        Ok(ids.len())
+    }
+
     pub async fn list_valid(
         room_id: String,
         db: &GraphDatabaseService,
@@ -944,7 +1336,7 @@ impl Invite {
         let invite_id = id.id;
         let invite_id = uid_decode(&invite_id)?;
         let hash_val = Self::hash_val(invite_id, &application);
-        let (_key, invite_sign) = db.sign(hash_val).await;
+        let (_key, invite_sign) = db.sign(hash_val).await?;
 
         let invite = Self {
             invite_id,
@@ -1103,6 +1495,51 @@ impl Invite {
         Ok(list)
     }
 
+    ///
+    /// delete the received invites inserted before the date and returns the number of deleted invites
+    ///
+    pub async fn delete_older_than(
+        room_id: String,
+        date: i64,
+        db: &GraphDatabaseService,
+    ) -> Result<usize, crate::Error> {
+        let mut param = Parameters::new();
+        param.add("room_id", room_id)?;
+        param.add("date", date)?;
+
+        let result = db
+            .query(
+                "query{
+            sys.Invite(room_id=$room_id, mdate < $date){
+                id
+            }
+        }",
+                Some(param),
+            )
+            .await?;
+
+        #[derive(Deserialize)]
+        struct InvId {
+            id: String,
+        }
+        let mut q = ResultParser::new(&result)?;
+        let ids: Vec<InvId> = q.take_array("sys.Invite")?;
+        for id in &ids {
+            let mut param = Parameters::new();
+            param.add("id", id.id.clone())?;
+            db.delete(
+                "delete {
+            sys.Invite{
+                $id
+            }
+        }",
+                Some(param),
+            )
+            .await?;
+        }
+        Ok(ids.len())
+    }
+
     pub fn hash(&self) -> Vec<u8> {
         Self::hash_val(self.invite_id, &self.application)
     }
@@ -1128,21 +1565,932 @@ pub struct DefaultRoom {
     pub authorisation: String,
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::security::{Ed25519SigningKey, HardwareFingerprint};
-    use crate::Configuration;
-    use crate::{event_service::EventService, security::random32};
+///
+/// Result of *Discret::invite_room_members()*
+/// - invited: verifying keys of the members added to the authorisation
+/// - unknown_peers: invited members whose *sys.Peer* is not known yet, no meeting token could be created for them.
+///   They will be allowed to connect once their peer definition is received during the synchronisation of a shared room.
+///
+#[derive(Default, Clone, Debug)]
+pub struct GroupInvitation {
+    pub room: String,
+    pub authorisation: String,
+    pub invited: Vec<String>,
+    pub unknown_peers: Vec<String>,
+}
 
-    use crate::database::sqlite_database::prepare_connection;
+///
+/// Application level key/value settings of a *Room*.
+///
+/// Settings are stored in the *sys.RoomSettings* entity, are synchronized with the room
+/// and can only be modified by the room admins.
+/// It allows applications to toggle per-room features (read-only mode, slow mode, ...) without changing the data model.
+///
+/// The *log_privacy* setting is used by the library: see [LOG_PRIVACY_SETTING].
+///
+#[derive(Default, Clone, Debug)]
+pub struct RoomSettings {
+    pub room_id: String,
+    settings: HashMap<String, serde_json::Value>,
+}
+impl RoomSettings {
+    pub async fn load(room_id: &str, db: &GraphDatabaseService) -> Result<Self, crate::Error> {
+        #[derive(Deserialize)]
+        struct Entry {
+            key: String,
+            value: serde_json::Value,
+        }
 
-    use super::*;
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.to_string())?;
 
-    use std::{fs, path::PathBuf};
-    const DATA_PATH: &str = "test_data/database/system_entities/";
-    fn init_database_path() {
-        let path: PathBuf = DATA_PATH.into();
-        fs::create_dir_all(&path).unwrap();
+        let res = db
+            .query(
+                "query {
+                result: sys.RoomSettings(room_id=$room_id, order_by(mdate asc, id asc)){
+                        key
+                        value
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        let mut query_result: ResultParser = ResultParser::new(&res)?;
+        let entries: Vec<Entry> = query_result.take_array("result")?;
+
+        //the most recent definition of a key wins
+        let mut settings = HashMap::new();
+        for entry in entries {
+            settings.insert(entry.key, entry.value);
+        }
+        Ok(Self {
+            room_id: room_id.to_string(),
+            settings,
+        })
+    }
+
+    pub async fn set(
+        room_id: &str,
+        key: &str,
+        value: serde_json::Value,
+        db: &GraphDatabaseService,
+    ) -> Result<(), crate::Error> {
+        #[derive(Deserialize)]
+        struct Id {
+            id: String,
+        }
+
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.to_string())?;
+        param.add("key", key.to_string())?;
+
+        let res = db
+            .query(
+                "query {
+                result: sys.RoomSettings(room_id=$room_id, key=$key, order_by(mdate desc, id desc), first 1){
+                        id
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        let mut query_result: ResultParser = ResultParser::new(&res)?;
+        let mut existing: Vec<Id> = query_result.take_array("result")?;
+
+        let mut param = Parameters::new();
+        param.add("value", value.to_string())?;
+        match existing.pop() {
+            Some(entry) => {
+                param.add("id", entry.id)?;
+                db.mutate(
+                    "mutate {
+                    sys.RoomSettings{
+                        id: $id
+                        value: $value
+                    }
+                }",
+                    Some(param),
+                )
+                .await?;
+            }
+            None => {
+                param.add("room_id", room_id.to_string())?;
+                param.add("key", key.to_string())?;
+                db.mutate(
+                    "mutate {
+                    sys.RoomSettings{
+                        room_id: $room_id
+                        key: $key
+                        value: $value
+                    }
+                }",
+                    Some(param),
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&serde_json::Value> {
+        self.settings.get(key)
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key).and_then(|v| v.as_bool())
+    }
+
+    pub fn get_i64(&self, key: &str) -> Option<i64> {
+        self.get(key).and_then(|v| v.as_i64())
+    }
+
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        self.get(key).and_then(|v| v.as_f64())
+    }
+
+    ///
+    /// bucket size used to coarsen the daily entry counts sent to low trust peers
+    ///
+    pub fn log_privacy(&self) -> Option<u32> {
+        self.get_i64(LOG_PRIVACY_SETTING)
+            .filter(|bucket| *bucket > 1)
+            .map(|bucket| bucket.min(u32::MAX as i64) as u32)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|v| v.as_str())
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.settings.keys()
+    }
+}
+
+///
+/// Moderation record created by a *Room* admin to hide a node authored by another member.
+///
+/// The target node is kept in the database but is excluded from the query results of every peer.
+/// Deleting the tombstone restores the node.
+///
+pub struct Tombstone {}
+impl Tombstone {
+    pub async fn create(
+        room_id: &str,
+        target: &str,
+        reason: Option<&str>,
+        db: &GraphDatabaseService,
+    ) -> Result<String, crate::Error> {
+        #[derive(Deserialize)]
+        struct Id {
+            id: String,
+        }
+
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.to_string())?;
+        param.add("target", target.to_string())?;
+        match reason {
+            Some(reason) => param.add("reason", reason.to_string())?,
+            None => param.add_null("reason")?,
+        };
+
+        let res = db
+            .mutate(
+                "mutate {
+                    sys.Tombstone{
+                        room_id: $room_id
+                        target: $target
+                        reason: $reason
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        let mut parser = ResultParser::new(&res)?;
+        let id: Id = parser.take_object(TOMBSTONE_ENT)?;
+        Ok(id.id)
+    }
+}
+
+///
+/// A data model update published in a *Room* by one of its admins.
+///
+/// Proposals are synchronized like any other room data, allowing every peer to validate the update beforehand.
+/// Once an admin confirms the proposal, every peer of the room applies the update after the next synchronisation.
+/// It avoids the situation where some peers reject the data created with a data model they don't know yet.
+///
+#[derive(Deserialize, Clone, Debug)]
+pub struct DataModelProposal {
+    pub id: String,
+    pub room_id: String,
+    pub mdate: i64,
+    pub verifying_key: String,
+    pub model: String,
+    pub confirmed: bool,
+}
+impl DataModelProposal {
+    pub async fn create(
+        room_id: &str,
+        model: &str,
+        db: &GraphDatabaseService,
+    ) -> Result<String, crate::Error> {
+        #[derive(Deserialize)]
+        struct Id {
+            id: String,
+        }
+
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.to_string())?;
+        param.add("model", model.to_string())?;
+
+        let res = db
+            .mutate(
+                "mutate {
+                    sys.DataModelProposal{
+                        room_id: $room_id
+                        model: $model
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        let mut parser = ResultParser::new(&res)?;
+        let id: Id = parser.take_object(DATA_MODEL_PROPOSAL_ENT)?;
+        Ok(id.id)
+    }
+
+    pub async fn get(id: &str, db: &GraphDatabaseService) -> Result<Option<Self>, crate::Error> {
+        let mut param = Parameters::new();
+        param.add("id", id.to_string())?;
+
+        let res = db
+            .query(
+                "query {
+                result: sys.DataModelProposal(id=$id){
+                        id
+                        room_id
+                        mdate
+                        verifying_key
+                        model
+                        confirmed
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        let mut parser = ResultParser::new(&res)?;
+        let mut list: Vec<Self> = parser.take_array("result")?;
+        Ok(list.pop())
+    }
+
+    pub async fn list(room_id: &str, db: &GraphDatabaseService) -> Result<Vec<Self>, crate::Error> {
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.to_string())?;
+
+        let res = db
+            .query(
+                "query {
+                result: sys.DataModelProposal(room_id=$room_id, order_by(mdate desc, id desc)){
+                        id
+                        room_id
+                        mdate
+                        verifying_key
+                        model
+                        confirmed
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        let mut parser = ResultParser::new(&res)?;
+        parser.take_array("result")
+    }
+
+    pub async fn confirm(id: &str, db: &GraphDatabaseService) -> Result<(), crate::Error> {
+        let mut param = Parameters::new();
+        param.add("id", id.to_string())?;
+        db.mutate(
+            "mutate {
+                sys.DataModelProposal{
+                    id: $id
+                    confirmed: true
+                }
+            }",
+            Some(param),
+        )
+        .await?;
+        Ok(())
+    }
+
+    ///
+    /// applies the last confirmed proposal of the room if it is more recent than the last applied one
+    /// returns true if the data model has been updated
+    ///
+    pub async fn activate(room_id: &str, db: &GraphDatabaseService) -> Result<bool, crate::Error> {
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.to_string())?;
+
+        let res = db
+            .query(
+                "query {
+                result: sys.DataModelProposal(room_id=$room_id, confirmed=true, order_by(mdate desc, id desc), first 1){
+                        mdate
+                        model
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+
+        #[derive(Deserialize)]
+        struct Confirmed {
+            mdate: i64,
+            model: String,
+        }
+        let mut parser = ResultParser::new(&res)?;
+        let mut confirmed: Vec<Confirmed> = parser.take_array("result")?;
+        match confirmed.pop() {
+            Some(proposal) => Ok(db
+                .apply_data_model_proposal(&proposal.model, proposal.mdate)
+                .await?),
+            None => Ok(false),
+        }
+    }
+}
+
+///
+/// A complete data model published in a *Room* by one of its admins.
+///
+/// Peers enabling *Configuration.accept_data_model_templates* apply the template after each synchronisation of the room.
+/// The first applied template pins the template identifier and its signer: a template with another identifier
+/// is rejected with *InvalidUpdateTemplate*, and an update signed by another peer is rejected with *InvalidSigner*.
+///
+#[derive(Deserialize, Clone, Debug)]
+pub struct DataModelTemplate {
+    pub id: String,
+    pub room_id: String,
+    pub mdate: i64,
+    pub verifying_key: String,
+    pub template_id: String,
+    pub model: String,
+}
+impl DataModelTemplate {
+    ///
+    /// publishes the template in the room, updating the previous version if it exists
+    ///
+    pub async fn publish(
+        room_id: &str,
+        template_id: &str,
+        model: &str,
+        db: &GraphDatabaseService,
+    ) -> Result<String, crate::Error> {
+        #[derive(Deserialize)]
+        struct Id {
+            id: String,
+        }
+
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.to_string())?;
+        param.add("template_id", template_id.to_string())?;
+        param.add("model", model.to_string())?;
+
+        let res = match Self::get(room_id, db).await? {
+            Some(previous) => {
+                if !previous.template_id.eq(template_id) {
+                    return Err(crate::Error::InvalidUpdateTemplate());
+                }
+                param.add("id", previous.id)?;
+                db.mutate(
+                    "mutate {
+                        sys.DataModelTemplate{
+                            id: $id
+                            room_id: $room_id
+                            template_id: $template_id
+                            model: $model
+                        }
+                    }",
+                    Some(param),
+                )
+                .await?
+            }
+            None => {
+                db.mutate(
+                    "mutate {
+                        sys.DataModelTemplate{
+                            room_id: $room_id
+                            template_id: $template_id
+                            model: $model
+                        }
+                    }",
+                    Some(param),
+                )
+                .await?
+            }
+        };
+        let mut parser = ResultParser::new(&res)?;
+        let id: Id = parser.take_object(DATA_MODEL_TEMPLATE_ENT)?;
+        Ok(id.id)
+    }
+
+    ///
+    /// the most recent template published in the room
+    ///
+    pub async fn get(
+        room_id: &str,
+        db: &GraphDatabaseService,
+    ) -> Result<Option<Self>, crate::Error> {
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.to_string())?;
+
+        let res = db
+            .query(
+                "query {
+                result: sys.DataModelTemplate(room_id=$room_id, order_by(mdate desc, id desc), first 1){
+                        id
+                        room_id
+                        mdate
+                        verifying_key
+                        template_id
+                        model
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        let mut parser = ResultParser::new(&res)?;
+        let mut list: Vec<Self> = parser.take_array("result")?;
+        Ok(list.pop())
+    }
+
+    ///
+    /// applies the template of the room if it is more recent than the applied one
+    /// returns true if the data model has been updated
+    ///
+    pub async fn activate(room_id: &str, db: &GraphDatabaseService) -> Result<bool, crate::Error> {
+        let template = match Self::get(room_id, db).await? {
+            Some(template) => template,
+            None => return Ok(false),
+        };
+
+        if let Some(current) = db.data_model_template_source().await? {
+            if !current.template_id.eq(&template.template_id) {
+                return Err(crate::Error::InvalidUpdateTemplate());
+            }
+            if !current.signer.eq(&template.verifying_key) {
+                return Err(crate::Error::InvalidSigner());
+            }
+        }
+
+        let source = TemplateSource {
+            template_id: template.template_id,
+            signer: template.verifying_key,
+            date: template.mdate,
+        };
+        Ok(db
+            .apply_data_model_template(&template.model, source)
+            .await?)
+    }
+}
+
+///
+/// Automation rule defined by a *Room* admin.
+///
+/// When an entity of the *trigger* type (like "chat.Message") is inserted in the room, the peer that inserted it applies the rule *mutation*.
+/// The *$id* and *$room_id* parameters of the mutation are set to the identifiers of the inserted entity and of its room.
+/// Rules are synchronized with the room and their results are synchronized like any other data,
+/// allowing small workflows to behave the same way on every peer without application code.
+///
+/// Rules are applied after the mutations performed with *mutate()*, system entities never trigger a rule.
+/// A rule can only mutate the room of the inserted entity and cannot mutate system entities, see [AutomationRule::validate].
+/// A rule is never triggered by its own consequences and chains are limited to [MAX_AUTOMATION_DEPTH] rules.
+///
+#[derive(Deserialize, Clone, Debug)]
+pub struct AutomationRule {
+    pub id: String,
+    pub room_id: String,
+    pub trigger: String,
+    pub mutation: String,
+    pub enabled: bool,
+}
+impl AutomationRule {
+    ///
+    /// creates an enabled rule and returns its identifier
+    /// the mutation is rejected if it does not respect [AutomationRule::validate]
+    ///
+    pub async fn create(
+        room_id: &str,
+        trigger: &str,
+        mutation: &str,
+        db: &GraphDatabaseService,
+    ) -> Result<String, crate::Error> {
+        #[derive(Deserialize)]
+        struct Id {
+            id: String,
+        }
+
+        db.validate_automation_rule(mutation).await?;
+
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.to_string())?;
+        param.add("trigger", trigger.to_string())?;
+        param.add("mutation", mutation.to_string())?;
+
+        let res = db
+            .mutate(
+                "mutate {
+                    sys.AutomationRule{
+                        room_id: $room_id
+                        trigger: $trigger
+                        mutation: $mutation
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        let mut parser = ResultParser::new(&res)?;
+        let id: Id = parser.take_object(AUTOMATION_RULE_ENT)?;
+        Ok(id.id)
+    }
+
+    pub async fn set_enabled(
+        id: &str,
+        enabled: bool,
+        db: &GraphDatabaseService,
+    ) -> Result<(), crate::Error> {
+        let mut param = Parameters::new();
+        param.add("id", id.to_string())?;
+        param.add("enabled", enabled)?;
+        db.mutate(
+            "mutate {
+                sys.AutomationRule{
+                    id: $id
+                    enabled: $enabled
+                }
+            }",
+            Some(param),
+        )
+        .await?;
+        Ok(())
+    }
+
+    ///
+    /// every rule of the room, including the disabled ones
+    ///
+    pub async fn load(room_id: &str, db: &GraphDatabaseService) -> Result<Vec<Self>, crate::Error> {
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.to_string())?;
+
+        let res = db
+            .query(
+                "query {
+                result: sys.AutomationRule(room_id=$room_id, order_by(mdate asc, id asc)){
+                        id
+                        room_id
+                        trigger
+                        mutation
+                        enabled
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        let mut parser = ResultParser::new(&res)?;
+        parser.take_array("result")
+    }
+
+    ///
+    /// the enabled rules of the room
+    ///
+    pub async fn enabled(
+        room_id: &str,
+        db: &GraphDatabaseService,
+    ) -> Result<Vec<Self>, crate::Error> {
+        let mut param = Parameters::new();
+        param.add("room_id", room_id.to_string())?;
+
+        let res = db
+            .query(
+                "query {
+                result: sys.AutomationRule(room_id=$room_id, enabled=true, order_by(mdate asc, id asc)){
+                        id
+                        room_id
+                        trigger
+                        mutation
+                        enabled
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        let mut parser = ResultParser::new(&res)?;
+        parser.take_array("result")
+    }
+
+    ///
+    /// a rule only mutates the room that triggered it:
+    /// every entity of the mutation is in the *$room_id* room, the only existing entity that can be updated is *$id*
+    /// and system entities cannot be mutated
+    ///
+    pub fn validate(mutation: &MutationParser) -> Result<(), Error> {
+        for entity in &mutation.mutations {
+            Self::validate_entity(entity)?;
+        }
+        Ok(())
+    }
+
+    fn validate_entity(entity: &EntityMutation) -> Result<(), Error> {
+        let system = entity
+            .name
+            .split('.')
+            .next()
+            .is_some_and(|namespace| namespace.eq(SYSTEM_NAMESPACE));
+        if system {
+            return Err(Error::InvalidAutomationRule(format!(
+                "system entity '{}' cannot be mutated",
+                entity.name
+            )));
+        }
+
+        let is_variable = |field: &str| {
+            entity.fields.get(field).map(|f| match &f.field_value {
+                MutationFieldValue::Variable(var) => var.eq(field),
+                _ => false,
+            })
+        };
+        if is_variable(ROOM_ID_FIELD) != Some(true) {
+            return Err(Error::InvalidAutomationRule(format!(
+                "entity '{}' must be mutated in the $room_id room",
+                entity.name
+            )));
+        }
+        if is_variable(ID_FIELD) == Some(false) {
+            return Err(Error::InvalidAutomationRule(format!(
+                "entity '{}' can only update the $id entity",
+                entity.name
+            )));
+        }
+
+        for field in entity.fields.values() {
+            match &field.field_value {
+                MutationFieldValue::Array(inners) => {
+                    for inner in inners {
+                        Self::validate_entity(inner)?;
+                    }
+                }
+                MutationFieldValue::Entity(inner) => Self::validate_entity(inner)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+///
+/// The enabled automation rules of each room, loaded the first time an entity is inserted in the room.
+///
+/// Rooms without rules are cached too, inserting an entity does not query the rules again.
+/// The cache is cleared each time a rule is written, locally or by a synchronisation.
+///
+#[derive(Clone, Default)]
+pub struct AutomationRuleCache {
+    rooms: Arc<Mutex<RoomRules>>,
+}
+#[derive(Default)]
+struct RoomRules {
+    //incremented on each clear, rules loaded before a clear are not cached
+    generation: u64,
+    rules: HashMap<Uid, Arc<Vec<AutomationRule>>>,
+}
+impl AutomationRuleCache {
+    ///
+    /// the enabled rules of the room triggered by the entity
+    ///
+    pub async fn triggered_by(
+        &self,
+        room_id: &Uid,
+        trigger: &str,
+        db: &GraphDatabaseService,
+    ) -> Result<Vec<AutomationRule>, crate::Error> {
+        let (cached, generation) = {
+            let rooms = self.rooms.lock().unwrap();
+            (rooms.rules.get(room_id).cloned(), rooms.generation)
+        };
+        let rules = match cached {
+            Some(rules) => rules,
+            None => {
+                let rules = Arc::new(AutomationRule::enabled(&uid_encode(room_id), db).await?);
+                let mut rooms = self.rooms.lock().unwrap();
+                if rooms.generation == generation {
+                    rooms.rules.insert(*room_id, rules.clone());
+                }
+                rules
+            }
+        };
+        Ok(rules
+            .iter()
+            .filter(|rule| rule.trigger.eq(trigger))
+            .cloned()
+            .collect())
+    }
+
+    pub fn clear(&self) {
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.generation += 1;
+        rooms.rules.clear();
+    }
+
+    ///
+    /// clears the cache when the mutation writes a rule
+    ///
+    pub fn mutated(&self, mutation: &MutationQuery) {
+        if mutation.mutate_entities.iter().any(Self::writes_rule) {
+            self.clear();
+        }
+    }
+
+    fn writes_rule(insert: &InsertEntity) -> bool {
+        insert.node_to_mutate.entity.eq(AUTOMATION_RULE_ENT)
+            || insert
+                .sub_nodes
+                .values()
+                .any(|sub_nodes| sub_nodes.iter().any(Self::writes_rule))
+    }
+
+    ///
+    /// clears the cache when the deletion removes a rule
+    ///
+    pub fn deleted(&self, deletion: &DeletionQuery) {
+        if deletion
+            .nodes
+            .iter()
+            .chain(deletion.soft_deleted.iter())
+            .any(|node| node.name.eq(AUTOMATION_RULE_ENT))
+        {
+            self.clear();
+        }
+    }
+}
+
+///
+/// An entity inserted in a room by a mutation, that can trigger automation rules
+///
+pub struct AutomationTrigger {
+    pub id: Uid,
+    pub room_id: Uid,
+    pub entity: String,
+    //identifiers of the rules that led to the insertion
+    pub chain: Vec<String>,
+}
+impl AutomationTrigger {
+    pub fn collect(mutation: &MutationQuery, chain: &[String]) -> Vec<Self> {
+        let mut triggers = Vec::new();
+        if mutation.replayed.is_none() {
+            for insert in &mutation.mutate_entities {
+                Self::collect_entity(insert, chain, &mut triggers);
+            }
+        }
+        triggers
+    }
+
+    fn collect_entity(insert: &InsertEntity, chain: &[String], triggers: &mut Vec<Self>) {
+        let node = &insert.node_to_mutate;
+        let system = node
+            .entity
+            .split('.')
+            .next()
+            .is_some_and(|namespace| namespace.eq(SYSTEM_NAMESPACE));
+        if let Some(room_id) = node.room_id {
+            if node.node.is_some() && node.old_node.is_none() && !system {
+                triggers.push(Self {
+                    id: node.id,
+                    room_id,
+                    entity: node.entity.clone(),
+                    chain: chain.to_vec(),
+                });
+            }
+        }
+        for sub_nodes in insert.sub_nodes.values() {
+            for sub in sub_nodes {
+                Self::collect_entity(sub, chain, triggers);
+            }
+        }
+    }
+}
+
+///
+/// Data of a *Room* sent by a peer and rejected during the synchronisation.
+///
+/// Rejections usually come from a peer using outdated authorisations or an unknown data model.
+/// One incident is recorded per synchronised day, *date* being the day of the rejected data.
+/// Incidents are local to the device: they are stored outside of any *Room* and are never synchronised.
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SyncIncident {
+    pub id: String,
+    pub mdate: i64,
+    pub room: String,
+    pub peer: String,
+    pub date: i64,
+    pub nodes_rejected: i64,
+    pub edges_rejected: i64,
+    pub reason: String,
+}
+impl SyncIncident {
+    pub async fn record(
+        room_id: Uid,
+        peer: &[u8],
+        date: i64,
+        nodes_rejected: u64,
+        edges_rejected: u64,
+        reason: &str,
+        db: &GraphDatabaseService,
+    ) -> Result<(), Error> {
+        let json = serde_json::json!({
+            SYNC_INCIDENT_ROOM_SHORT: uid_encode(&room_id),
+            SYNC_INCIDENT_PEER_SHORT: base64_encode(peer),
+            SYNC_INCIDENT_DATE_SHORT: date,
+            SYNC_INCIDENT_NODES_SHORT: nodes_rejected,
+            SYNC_INCIDENT_EDGES_SHORT: edges_rejected,
+            SYNC_INCIDENT_REASON_SHORT: reason,
+        });
+        let now = now();
+        let node = Node {
+            id: new_uid(),
+            room_id: None,
+            cdate: now,
+            mdate: now,
+            _entity: SYNC_INCIDENT_ENT_SHORT.to_string(),
+            _json: Some(json.to_string()),
+            ..Default::default()
+        };
+        db.db.writer.write(Box::new(node)).await?;
+        Ok(())
+    }
+
+    ///
+    /// lists the incidents of a room, most recent first
+    ///
+    pub async fn list(room_id: &str, db: &GraphDatabaseService) -> Result<Vec<Self>, crate::Error> {
+        let mut param = Parameters::new();
+        param.add("room", room_id.to_string())?;
+
+        let res = db
+            .query(
+                "query {
+                result: sys.SyncIncident(room=$room, order_by(mdate desc, id desc)){
+                        id
+                        mdate
+                        room
+                        peer
+                        date
+                        nodes_rejected
+                        edges_rejected
+                        reason
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        let mut parser = ResultParser::new(&res)?;
+        parser.take_array("result")
+    }
+
+    ///
+    /// deletes the incidents of a room and returns the number of deleted incidents
+    ///
+    pub async fn delete(room_id: &str, db: &GraphDatabaseService) -> Result<usize, crate::Error> {
+        let incidents = Self::list(room_id, db).await?;
+        for incident in &incidents {
+            let mut param = Parameters::new();
+            param.add("id", incident.id.clone())?;
+            db.delete(
+                "delete {
+                    sys.SyncIncident{
+                        $id
+                    }
+                }",
+                Some(param),
+            )
+            .await?;
+        }
+        Ok(incidents.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::security::{new_uid, Ed25519SigningKey, HardwareFingerprint};
+    use crate::Configuration;
+    use crate::{event_service::EventService, security::random32};
+
+    use crate::database::sqlite_database::prepare_connection;
+
+    use super::*;
+
+    use std::{fs, path::PathBuf};
+    const DATA_PATH: &str = "test_data/database/system_entities/";
+    fn init_database_path() {
+        let path: PathBuf = DATA_PATH.into();
+        fs::create_dir_all(&path).unwrap();
     }
     #[test]
     fn peer() {
@@ -1189,6 +2537,111 @@ mod tests {
         assert_eq!(missing.len(), 0);
     }
 
+    #[test]
+    fn peer_page() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let num_peer = 10;
+        let mut peers = HashSet::with_capacity(num_peer);
+        let mut node_size = 0;
+        for _ in 0..num_peer {
+            let keypair = Ed25519SigningKey::new();
+            let mut node = Node {
+                _entity: PEER_ENT_SHORT.to_string(),
+                ..Default::default()
+            };
+            node.sign(&keypair).unwrap();
+            node_size = bincode::serialized_size(&node).unwrap() as usize + VEC_OVERHEAD as usize;
+            peers.insert(node.verifying_key.clone());
+            node.write(&conn, false, &None, &None).unwrap();
+        }
+        //unknown peers are ignored
+        peers.insert(random32().to_vec());
+
+        let page = Peer::get_peers_page(peers.clone(), None, 400 * 1024, &conn).unwrap();
+        assert_eq!(page.peers.len(), num_peer);
+        assert!(page.next.is_none());
+
+        //three peers per page
+        let mut after = None;
+        let mut keys = Vec::new();
+        let mut pages = 0;
+        loop {
+            let page = Peer::get_peers_page(peers.clone(), after, node_size * 3, &conn).unwrap();
+            assert!(page.peers.len() <= 3);
+            keys.extend(page.peers.into_iter().map(|n| n.verifying_key));
+            pages += 1;
+            after = page.next;
+            if after.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages, 4);
+        assert_eq!(keys.len(), num_peer);
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+    }
+
+    #[test]
+    fn peer_metadata() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let keypair = Ed25519SigningKey::new();
+        let mut node = Peer::create(new_uid(), base64_encode(&random32()));
+        assert!(Peer::metadata(&node).unwrap().is_none());
+        node.sign(&keypair).unwrap();
+        let mut writer = PeerWriter {
+            index: PeerUpdate::new(node.clone()).unwrap().index,
+            node: node.clone(),
+        };
+        writer.write(&conn).unwrap();
+
+        Peer::set_metadata(&mut node, &Some("[1, 2]".to_string())).expect_err("not an object");
+        let large = format!(r#"{{"name":"{}"}}"#, "a".repeat(PEER_METADATA_MAX_SIZE));
+        Peer::set_metadata(&mut node, &Some(large)).expect_err("too large");
+
+        let metadata = Some(r#"{"device":"laptop","platform":"linux"}"#.to_string());
+        assert!(Peer::set_metadata(&mut node, &metadata).unwrap());
+        assert!(!Peer::set_metadata(&mut node, &metadata).unwrap());
+        node.mdate = now();
+        node.sign(&keypair).unwrap();
+        assert!(Peer::validate(&node).is_ok());
+
+        //updates signed by another key are ignored
+        let mut forged = node.clone();
+        forged.sign(&Ed25519SigningKey::new()).unwrap();
+        PeerUpdate::new(forged).unwrap().write(&conn).unwrap();
+        let stored = Peer::get_node(node.verifying_key.clone(), &conn)
+            .unwrap()
+            .unwrap();
+        assert!(Peer::metadata(&stored).unwrap().is_none());
+
+        PeerUpdate::new(node.clone()).unwrap().write(&conn).unwrap();
+        let stored = Peer::get_node(node.verifying_key.clone(), &conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            r#"{"device":"laptop","platform":"linux"}"#,
+            Peer::metadata(&stored).unwrap().unwrap()
+        );
+
+        //older versions are ignored
+        let mut old = *Node::get_with_entity(&node.id, PEER_ENT_SHORT, &conn)
+            .unwrap()
+            .unwrap();
+        Peer::set_metadata(&mut old, &None).unwrap();
+        old.mdate = node.mdate - 1;
+        old.sign(&keypair).unwrap();
+        PeerUpdate::new(old).unwrap().write(&conn).unwrap();
+        let stored = Peer::get_node(node.verifying_key.clone(), &conn)
+            .unwrap()
+            .unwrap();
+        assert!(Peer::metadata(&stored).unwrap().is_some());
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn init_allowed_peer() {
         init_database_path();
@@ -1202,7 +2655,7 @@ mod tests {
                 "authorisation app",
                 "",
                 &secret,
-                &pub_key,
+                pub_key,
                 path.clone(),
                 &Configuration::default(),
                 EventService::new(),
@@ -1239,7 +2692,7 @@ mod tests {
                 "authorisation app",
                 "",
                 &secret,
-                &pub_key,
+                pub_key,
                 path,
                 &Configuration::default(),
                 EventService::new(),
@@ -1275,7 +2728,7 @@ mod tests {
             "authorisation app",
             "",
             &secret,
-            &pub_key,
+            pub_key,
             path,
             &Configuration::default(),
             EventService::new(),
@@ -1318,7 +2771,7 @@ mod tests {
             "authorisation app",
             "",
             &secret,
-            &pub_key,
+            pub_key,
             path.clone(),
             &Configuration::default(),
             EventService::new(),
@@ -1331,6 +2784,59 @@ mod tests {
         assert_eq!(1, list.len());
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn enable_allowed_peer() {
+        init_database_path();
+
+        let path: PathBuf = DATA_PATH.into();
+        let (app, _verifying_key, private_room) = GraphDatabaseService::start(
+            "authorisation app",
+            "",
+            &random32(),
+            &random32(),
+            path.clone(),
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        let (other, other_key, _) = GraphDatabaseService::start(
+            "authorisation app",
+            "",
+            &random32(),
+            &random32(),
+            path.clone(),
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+        let peer = other
+            .get_peer_node(other_key.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        app.add_peer_nodes(vec![peer]).await.unwrap();
+
+        let room_id = uid_encode(&private_room);
+        let verifying_key = base64_encode(&other_key);
+        let token = base64_encode(&random32());
+        AllowedPeer::add(&room_id, &verifying_key, &token, Status::Pending, &app)
+            .await
+            .unwrap();
+        assert_eq!(1, app.get_allowed_peers(private_room).await.unwrap().len());
+
+        AllowedPeer::enable(&room_id, &verifying_key, &app)
+            .await
+            .unwrap();
+        assert_eq!(2, app.get_allowed_peers(private_room).await.unwrap().len());
+        let pending = AllowedPeer::get(room_id, Status::Pending, &app)
+            .await
+            .unwrap();
+        assert!(pending.is_empty());
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn hardware() {
         init_database_path();
@@ -1343,7 +2849,7 @@ mod tests {
             "authorisation app",
             "",
             &secret,
-            &pub_key,
+            pub_key,
             path.clone(),
             &Configuration::default(),
             EventService::new(),
@@ -1400,7 +2906,7 @@ mod tests {
             "authorisation app",
             "",
             &secret,
-            &pub_key,
+            pub_key,
             path.clone(),
             &Configuration::default(),
             EventService::new(),
@@ -1451,4 +2957,171 @@ mod tests {
 
         drop(db);
     }
+
+    #[test]
+    fn system_short_names() {
+        let mut data_model = crate::database::query_language::data_model_parser::DataModel::new();
+        data_model.update_system(SYSTEM_DATA_MODEL).unwrap();
+
+        let short = |entity: &str| data_model.get_entity(entity).unwrap().short_name.clone();
+        let field_short = |entity: &str, field: &str| {
+            data_model
+                .get_entity(entity)
+                .unwrap()
+                .get_field(field)
+                .unwrap()
+                .short_name
+                .clone()
+        };
+
+        assert_eq!(ROOM_ENT_SHORT, short(ROOM_ENT));
+        assert_eq!(AUTHORISATION_ENT_SHORT, short(AUTHORISATION_ENT));
+        assert_eq!(USER_AUTH_ENT_SHORT, short(USER_AUTH_ENT));
+        assert_eq!(ENTITY_RIGHT_ENT_SHORT, short(ENTITY_RIGHT_ENT));
+        assert_eq!(PEER_ENT_SHORT, short(PEER_ENT));
+        assert_eq!(ALLOWED_PEER_ENT_SHORT, short("sys.AllowedPeer"));
+        assert_eq!(ALLOWED_HARDWARE_ENT_SHORT, short("sys.AllowedHardware"));
+
+        //the tombstone short names are hardcoded in the query filters
+        assert_eq!(TOMBSTONE_ENT_SHORT, short(TOMBSTONE_ENT));
+        assert_eq!(TOMBSTONE_TARGET_SHORT, field_short(TOMBSTONE_ENT, "target"));
+
+        assert_eq!(
+            ROOM_ADMIN_FIELD_SHORT,
+            field_short(ROOM_ENT, ROOM_ADMIN_FIELD)
+        );
+        assert_eq!(
+            ROOM_AUTHORISATION_FIELD_SHORT,
+            field_short(ROOM_ENT, ROOM_AUTHORISATION_FIELD)
+        );
+        assert_eq!(
+            AUTH_RIGHTS_FIELD_SHORT,
+            field_short(AUTHORISATION_ENT, AUTH_RIGHTS_FIELD)
+        );
+        assert_eq!(
+            AUTH_USER_FIELD_SHORT,
+            field_short(AUTHORISATION_ENT, AUTH_USER_FIELD)
+        );
+        assert_eq!(
+            AUTH_USER_ADMIN_FIELD_SHORT,
+            field_short(AUTHORISATION_ENT, AUTH_USER_ADMIN_FIELD)
+        );
+        assert_eq!(
+            USER_VERIFYING_KEY_SHORT,
+            field_short(USER_AUTH_ENT, "verif_key")
+        );
+        assert_eq!(USER_ENABLED_SHORT, field_short(USER_AUTH_ENT, "enabled"));
+        assert_eq!(RIGHT_ENTITY_SHORT, field_short(ENTITY_RIGHT_ENT, "entity"));
+        assert_eq!(
+            RIGHT_MUTATE_SELF_SHORT,
+            field_short(ENTITY_RIGHT_ENT, "mutate_self")
+        );
+        assert_eq!(
+            RIGHT_MUTATE_ALL_SHORT,
+            field_short(ENTITY_RIGHT_ENT, "mutate_all")
+        );
+        assert_eq!(PEER_PUB_KEY_SHORT, field_short(PEER_ENT, "pub_key"));
+        assert_eq!(PEER_NAME_SHORT, field_short(PEER_ENT, "name"));
+        assert_eq!(PEER_METADATA_SHORT, field_short(PEER_ENT, "metadata"));
+        assert_eq!(
+            ALLOWED_PEER_PEER_SHORT,
+            field_short("sys.AllowedPeer", "peer")
+        );
+        assert_eq!(
+            ALLOWED_PEER_TOKEN_SHORT,
+            field_short("sys.AllowedPeer", "meeting_token")
+        );
+        assert_eq!(
+            ALLOWED_PEER_STATUS_SHORT,
+            field_short("sys.AllowedPeer", "status")
+        );
+        assert_eq!(
+            ALLOWED_HARDWARE_NAME_SHORT,
+            field_short("sys.AllowedHardware", "name")
+        );
+        assert_eq!(
+            ALLOWED_HARDWARE_STATUS_SHORT,
+            field_short("sys.AllowedHardware", "status")
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sync_incident() {
+        init_database_path();
+
+        let mut data_model = crate::database::query_language::data_model_parser::DataModel::new();
+        data_model.update_system(SYSTEM_DATA_MODEL).unwrap();
+        let entity = data_model.get_entity(SYNC_INCIDENT_ENT).unwrap();
+        assert_eq!(SYNC_INCIDENT_ENT_SHORT, entity.short_name);
+        assert_eq!(
+            SYNC_INCIDENT_REASON_SHORT,
+            entity.get_field("reason").unwrap().short_name
+        );
+
+        let path: PathBuf = DATA_PATH.into();
+        let (app, _verifying_key, private_room) = GraphDatabaseService::start(
+            "sync incident app",
+            "",
+            &random32(),
+            &random32(),
+            path.clone(),
+            &Configuration::default(),
+            EventService::new(),
+        )
+        .await
+        .unwrap();
+
+        let room_id = new_uid();
+        let peer = random32();
+        SyncIncident::record(room_id, &peer, 1000, 2, 1, "rejected", &app)
+            .await
+            .unwrap();
+        SyncIncident::record(new_uid(), &peer, 1000, 1, 0, "rejected", &app)
+            .await
+            .unwrap();
+
+        let incidents = SyncIncident::list(&uid_encode(&room_id), &app)
+            .await
+            .unwrap();
+        assert_eq!(1, incidents.len());
+        let incident = &incidents[0];
+        assert_eq!(uid_encode(&room_id), incident.room);
+        assert_eq!(base64_encode(&peer), incident.peer);
+        assert_eq!(1000, incident.date);
+        assert_eq!(2, incident.nodes_rejected);
+        assert_eq!(1, incident.edges_rejected);
+        assert_eq!("rejected", incident.reason);
+
+        //incidents cannot be inserted in a room
+        let mut param = Parameters::new();
+        param.add("room_id", uid_encode(&private_room)).unwrap();
+        param.add("room", uid_encode(&room_id)).unwrap();
+        let err = app
+            .mutate(
+                r#"mutate {
+                sys.SyncIncident{
+                    room_id: $room_id
+                    room: $room
+                    peer: $room
+                    date: 0
+                    nodes_rejected: 0
+                    edges_rejected: 0
+                    reason: ""
+                }
+            }"#,
+                Some(param),
+            )
+            .await
+            .expect_err("sys.SyncIncident cannot have a room_id");
+        assert!(matches!(err, Error::ForbiddenRoomId(_)));
+
+        let deleted = SyncIncident::delete(&uid_encode(&room_id), &app)
+            .await
+            .unwrap();
+        assert_eq!(1, deleted);
+        assert!(SyncIncident::list(&uid_encode(&room_id), &app)
+            .await
+            .unwrap()
+            .is_empty());
+    }
 }
@@ -0,0 +1,183 @@
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::date_utils::{date, now};
+
+use super::Result;
+
+//upper bounds of the database size buckets, in bytes
+const SIZE_BUCKETS: [(u64, &str); 4] = [
+    (1 << 20, "<1MB"),
+    (10 << 20, "1MB-10MB"),
+    (100 << 20, "10MB-100MB"),
+    (1 << 30, "100MB-1GB"),
+];
+const LARGEST_SIZE_BUCKET: &str = ">1GB";
+
+///
+/// Number of operations per duration range
+///
+#[derive(Default, Clone, Debug, Serialize, PartialEq)]
+pub struct DurationBuckets {
+    pub count: u64,
+    pub under_10ms: u64,
+    pub under_100ms: u64,
+    pub under_1s: u64,
+    pub under_10s: u64,
+    pub over_10s: u64,
+}
+impl DurationBuckets {
+    pub fn add(&mut self, duration_in_ms: u64) {
+        self.count += 1;
+        match duration_in_ms {
+            0..10 => self.under_10ms += 1,
+            10..100 => self.under_100ms += 1,
+            100..1000 => self.under_1s += 1,
+            1000..10000 => self.under_10s += 1,
+            _ => self.over_10s += 1,
+        }
+    }
+}
+
+///
+/// Anonymous performance metrics returned by *Discret::telemetry()*
+///
+/// The report only contains aggregated numbers: no query, room, peer or key is ever included.
+/// Submitting it to the library maintainers is left to the application.
+///
+/// - version: version of the discret library
+/// - since: day of the start of the aggregation, the time of the day is removed
+/// - queries: duration of the local queries
+/// - synchronisations: duration of the room synchronisations with remote peers
+/// - database_size: size range of the database, like "10MB-100MB"
+///
+#[derive(Clone, Debug, Serialize)]
+pub struct TelemetryReport {
+    pub version: String,
+    pub since: i64,
+    pub queries: DurationBuckets,
+    pub synchronisations: DurationBuckets,
+    pub database_size: String,
+}
+
+///
+/// Aggregates the metrics when the *enable_telemetry* configuration is set
+///
+pub struct Telemetry {
+    enabled: bool,
+    since: i64,
+    queries: DurationBuckets,
+    synchronisations: DurationBuckets,
+}
+impl Telemetry {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            since: date(now()),
+            queries: DurationBuckets::default(),
+            synchronisations: DurationBuckets::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn add_query(&mut self, duration_in_ms: u64) {
+        if self.enabled {
+            self.queries.add(duration_in_ms);
+        }
+    }
+
+    pub fn add_synchronisation(&mut self, duration_in_ms: u64) {
+        if self.enabled {
+            self.synchronisations.add(duration_in_ms);
+        }
+    }
+
+    ///
+    /// reset starts a new aggregation, typically after the report has been submitted
+    ///
+    pub fn report(&mut self, database_size: u64, reset: bool) -> TelemetryReport {
+        let report = TelemetryReport {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            since: self.since,
+            queries: self.queries.clone(),
+            synchronisations: self.synchronisations.clone(),
+            database_size: size_bucket(database_size).to_string(),
+        };
+        if reset {
+            *self = Self::new(self.enabled);
+        }
+        report
+    }
+}
+
+pub fn database_size(conn: &Connection) -> Result<u64> {
+    let size: i64 = conn.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(size.max(0) as u64)
+}
+
+fn size_bucket(size: u64) -> &'static str {
+    SIZE_BUCKETS
+        .iter()
+        .find(|(limit, _)| size < *limit)
+        .map(|(_, name)| *name)
+        .unwrap_or(LARGEST_SIZE_BUCKET)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telemetry() {
+        let mut disabled = Telemetry::new(false);
+        disabled.add_query(5);
+        disabled.add_synchronisation(5);
+        let report = disabled.report(0, false);
+        assert_eq!(0, report.queries.count);
+        assert_eq!(0, report.synchronisations.count);
+
+        let mut telemetry = Telemetry::new(true);
+        for duration in [0, 9, 10, 150, 999, 5000, 20000] {
+            telemetry.add_query(duration);
+        }
+        telemetry.add_synchronisation(1200);
+
+        let report = telemetry.report(5 << 20, true);
+        assert_eq!(env!("CARGO_PKG_VERSION"), report.version);
+        assert_eq!(date(now()), report.since);
+        assert_eq!(
+            DurationBuckets {
+                count: 7,
+                under_10ms: 2,
+                under_100ms: 1,
+                under_1s: 2,
+                under_10s: 1,
+                over_10s: 1,
+            },
+            report.queries
+        );
+        assert_eq!(1, report.synchronisations.under_10s);
+        assert_eq!("1MB-10MB", report.database_size);
+
+        //the report has been reset
+        let report = telemetry.report(2 << 30, false);
+        assert_eq!(0, report.queries.count);
+        assert_eq!(0, report.synchronisations.count);
+        assert_eq!(">1GB", report.database_size);
+        assert_eq!("<1MB", size_bucket(0));
+    }
+
+    #[test]
+    fn sqlite_database_size() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE t(v TEXT)", []).unwrap();
+        assert!(database_size(&conn).unwrap() > 0);
+    }
+}
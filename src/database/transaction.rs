@@ -0,0 +1,95 @@
+use super::{
+    deletion::DeletionQuery, graph_database::GraphDatabaseService, mutation_query::MutationQuery,
+    query_language::parameter::Parameters,
+};
+
+///
+/// a mutation or a deletion of a [TransactionBuilder], before it is parsed
+///
+#[derive(Clone, Debug)]
+pub enum TransactionOperation {
+    Mutation(String, Parameters),
+    Deletion(String, Parameters),
+}
+
+///
+/// a mutation or a deletion ready to be validated and written in the transaction
+///
+pub enum TransactionQuery {
+    Mutation(MutationQuery),
+    Deletion(DeletionQuery),
+}
+
+///
+/// Groups several mutations and deletions in a single database transaction.
+///
+/// Every operation is validated by the authorisation model before anything is written:
+/// if one of them is rejected, none of them is written.
+///
+/// The operations are built from the state of the database before the transaction,
+/// a deletion cannot reference an entity inserted by a previous mutation of the same transaction.
+/// *sys.Room* mutations are not allowed in a transaction.
+///
+/// ```ignore
+/// let transaction = TransactionBuilder::new()
+///     .mutate("mutate { Task { id: $id done: true } }", Some(params))
+///     .delete("delete { Draft { $draft_id } }", Some(draft_params));
+/// let results = discret.transaction(&transaction).await?;
+/// ```
+///
+#[derive(Default, Clone, Debug)]
+pub struct TransactionBuilder {
+    operations: Vec<TransactionOperation>,
+}
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// adds a mutation to the transaction
+    ///
+    pub fn mutate(mut self, mutation: &str, parameters: Option<Parameters>) -> Self {
+        self.operations.push(TransactionOperation::Mutation(
+            mutation.to_string(),
+            parameters.unwrap_or_default(),
+        ));
+        self
+    }
+
+    ///
+    /// adds a deletion to the transaction
+    ///
+    pub fn delete(mut self, deletion: &str, parameters: Option<Parameters>) -> Self {
+        self.operations.push(TransactionOperation::Deletion(
+            deletion.to_string(),
+            parameters.unwrap_or_default(),
+        ));
+        self
+    }
+
+    ///
+    /// number of operations in the transaction
+    ///
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    ///
+    /// writes the transaction and returns the JSON result of each mutation, in the order of the mutations
+    ///
+    pub async fn execute(&self, db: &GraphDatabaseService) -> Result<Vec<String>, crate::Error> {
+        let queries = db.transaction(self.operations.clone()).await?;
+        let mut results = Vec::new();
+        for query in queries {
+            if let TransactionQuery::Mutation(mutation) = query {
+                results.push(mutation.result()?);
+            }
+        }
+        Ok(results)
+    }
+}
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use chrono::{DateTime, Datelike, Days, Duration, Months, NaiveDate, NaiveDateTime, Utc};
 
 ///
 /// current time in milliseconds since unix epoch
@@ -22,3 +22,170 @@ pub fn date_next_day(date_time: i64) -> i64 {
     let ds: NaiveDateTime = date.date_naive().and_hms_opt(0, 0, 0).unwrap();
     ds.and_utc().timestamp_millis()
 }
+
+///
+/// Parse a RFC3339 date (ex: "2024-05-01T10:30:00+02:00") into milliseconds since unix epoch
+///
+pub fn parse_datetime(value: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|d| d.timestamp_millis())
+}
+
+///
+/// Format milliseconds since unix epoch as a UTC RFC3339 date
+///
+pub fn format_datetime(date_time: i64) -> Option<String> {
+    DateTime::from_timestamp_millis(date_time)
+        .map(|d| d.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+}
+
+///
+/// Parse a date (ex: "2024-05-01") or a RFC3339 date into milliseconds since unix epoch at the start of the UTC day
+///
+pub fn parse_date(value: &str) -> Option<i64> {
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(day) => day
+            .and_hms_opt(0, 0, 0)
+            .map(|d| d.and_utc().timestamp_millis()),
+        Err(_) => parse_datetime(value).map(|date| truncate_datetime(date, TimeUnit::Day)),
+    }
+}
+
+///
+/// Format milliseconds since unix epoch as a UTC date (ex: "2024-05-01")
+///
+pub fn format_date(date: i64) -> Option<String> {
+    DateTime::from_timestamp_millis(date).map(|d| d.format("%Y-%m-%d").to_string())
+}
+
+///
+/// Time units used to truncate *DateTime* values
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeUnit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+///
+/// Truncates milliseconds since unix epoch to the start of the UTC time unit
+///
+/// weeks starts on monday
+///
+pub fn truncate_datetime(date_time: i64, unit: TimeUnit) -> i64 {
+    let Some(date) = DateTime::from_timestamp_millis(date_time) else {
+        return date_time;
+    };
+    let day = date.date_naive();
+    let start = match unit {
+        TimeUnit::Second => return date_time - date_time.rem_euclid(1000),
+        TimeUnit::Minute => return date_time - date_time.rem_euclid(60 * 1000),
+        TimeUnit::Hour => return date_time - date_time.rem_euclid(60 * 60 * 1000),
+        TimeUnit::Day => Some(day),
+        TimeUnit::Week => {
+            day.checked_sub_days(Days::new(day.weekday().num_days_from_monday().into()))
+        }
+        TimeUnit::Month => day.with_day(1),
+        TimeUnit::Year => NaiveDate::from_ymd_opt(day.year(), 1, 1),
+    };
+    match start.and_then(|d| d.and_hms_opt(0, 0, 0)) {
+        Some(d) => d.and_utc().timestamp_millis(),
+        None => date_time,
+    }
+}
+
+///
+/// Returns the [start, end) range of the UTC time unit containing the date.
+///
+/// Usefull to build range filters: date >= $start and date < $end
+///
+pub fn datetime_range(date_time: i64, unit: TimeUnit) -> (i64, i64) {
+    let start = truncate_datetime(date_time, unit);
+    let end = match unit {
+        TimeUnit::Second => Some(start + 1000),
+        TimeUnit::Minute => Some(start + 60 * 1000),
+        TimeUnit::Hour => Some(start + 60 * 60 * 1000),
+        TimeUnit::Day => Some(start + 24 * 60 * 60 * 1000),
+        TimeUnit::Week => Some(start + 7 * 24 * 60 * 60 * 1000),
+        TimeUnit::Month => DateTime::from_timestamp_millis(start)
+            .and_then(|d| d.checked_add_months(Months::new(1)))
+            .map(|d| d.timestamp_millis()),
+        TimeUnit::Year => DateTime::from_timestamp_millis(start)
+            .and_then(|d| d.checked_add_months(Months::new(12)))
+            .map(|d| d.timestamp_millis()),
+    };
+    (start, end.unwrap_or(i64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datetime_helpers() {
+        let date = parse_datetime("2024-05-15T10:30:15.250+02:00").unwrap();
+        assert_eq!(
+            format_datetime(date).unwrap(),
+            "2024-05-15T08:30:15.250Z".to_string()
+        );
+        assert!(parse_datetime("2024-05-15").is_none());
+        assert!(parse_datetime("1715761815250").is_none());
+
+        let expect = |value: &str| parse_datetime(value).unwrap();
+        assert_eq!(
+            truncate_datetime(date, TimeUnit::Second),
+            expect("2024-05-15T08:30:15Z")
+        );
+        assert_eq!(
+            truncate_datetime(date, TimeUnit::Hour),
+            expect("2024-05-15T08:00:00Z")
+        );
+        //2024-05-15 is a wednesday
+        assert_eq!(
+            truncate_datetime(date, TimeUnit::Week),
+            expect("2024-05-13T00:00:00Z")
+        );
+        assert_eq!(
+            truncate_datetime(date, TimeUnit::Year),
+            expect("2024-01-01T00:00:00Z")
+        );
+
+        assert_eq!(
+            datetime_range(date, TimeUnit::Day),
+            (
+                expect("2024-05-15T00:00:00Z"),
+                expect("2024-05-16T00:00:00Z")
+            )
+        );
+        assert_eq!(
+            datetime_range(expect("2024-12-31T23:59:59Z"), TimeUnit::Month),
+            (
+                expect("2024-12-01T00:00:00Z"),
+                expect("2025-01-01T00:00:00Z")
+            )
+        );
+
+        assert_eq!(
+            parse_date("2024-05-15"),
+            Some(expect("2024-05-15T00:00:00Z"))
+        );
+        assert_eq!(
+            parse_date("2024-05-15T23:30:00-02:00"),
+            Some(expect("2024-05-16T00:00:00Z"))
+        );
+        assert!(parse_date("2024-02-30").is_none());
+        assert_eq!(format_date(date).unwrap(), "2024-05-15".to_string());
+
+        //dates before the unix epoch
+        assert_eq!(
+            truncate_datetime(-1, TimeUnit::Minute),
+            expect("1969-12-31T23:59:00Z")
+        );
+    }
+}
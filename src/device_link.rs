@@ -0,0 +1,278 @@
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use serde::{Deserialize, Serialize};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::{
+    configuration::Configuration,
+    date_utils::now,
+    security::{random32, Error},
+};
+
+//the approval of a request must happen shortly after its creation
+const REQUEST_VALIDITY_MS: i64 = 10 * 60 * 1000;
+
+#[derive(Serialize, Deserialize)]
+struct LinkRequest {
+    public_key: [u8; 32],
+    date: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LinkResponse {
+    public_key: [u8; 32],
+    nonce: [u8; NONCE_LEN],
+    ciphertext: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LinkPayload {
+    key_material: [u8; 32],
+    configuration: Configuration,
+}
+
+///
+/// The account transferred by an existing device.
+///
+pub struct LinkedDevice {
+    ///
+    /// the key material to provide to *Discret::new()*
+    ///
+    pub key_material: [u8; 32],
+
+    ///
+    /// the configuration of the existing device, that can be adapted before starting Discret
+    ///
+    pub configuration: Configuration,
+}
+
+///
+/// Links a new device to an existing account without typing the pass phrase.
+///
+/// The protocol is
+///     the new device creates a request and displays its short code
+///     the request bytes are transferred to the existing device, for example with a QR code
+///     the user enters the short code on the existing device, which approves the request with *Discret::approve_device_link()*
+///     the existing device returns the key material and its configuration, encrypted for this request only
+///     the response bytes are transferred back to the new device that decrypts it with *complete()*
+///
+/// The short code authenticates the request: a request replaced during the transfer is rejected by the existing device.
+/// It has 15 digits, forging a request that shares the code of another one is out of reach during the validity of the request.
+/// The response can only be decrypted by the new device, using a Diffie Hellman key exchange.
+/// Requests expire after 10 minutes.
+///
+pub struct DeviceLinkRequest {
+    secret: StaticSecret,
+    request: LinkRequest,
+}
+impl Default for DeviceLinkRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl DeviceLinkRequest {
+    pub fn new() -> Self {
+        let secret = StaticSecret::from(random32());
+        let public_key = PublicKey::from(&secret).to_bytes();
+        Self {
+            secret,
+            request: LinkRequest {
+                public_key,
+                date: now(),
+            },
+        }
+    }
+
+    ///
+    /// the request to transfer to the existing device
+    ///
+    pub fn request(&self) -> Vec<u8> {
+        bincode::serialize(&self.request).unwrap()
+    }
+
+    ///
+    /// the short code to display on the new device, formatted like '123-456-789-012-345'
+    ///
+    pub fn code(&self) -> String {
+        link_code(&self.request.public_key)
+    }
+
+    ///
+    /// decrypts the response of the existing device
+    ///
+    pub fn complete(&self, response: &[u8]) -> Result<LinkedDevice, crate::Error> {
+        let response: LinkResponse = bincode::deserialize(response)?;
+        let key = link_key(
+            &self.secret,
+            &PublicKey::from(response.public_key),
+            &self.request.public_key,
+            &response.public_key,
+        );
+        let mut in_out = response.ciphertext;
+        let plain = open_key(&key)?
+            .open_in_place(
+                Nonce::assume_unique_for_key(response.nonce),
+                Aad::empty(),
+                &mut in_out,
+            )
+            .map_err(|_| Error::DeviceLink("invalid device link response".to_string()))?;
+        let payload: LinkPayload = serde_json::from_slice(plain)?;
+        Ok(LinkedDevice {
+            key_material: payload.key_material,
+            configuration: payload.configuration,
+        })
+    }
+}
+
+///
+/// encrypts the key material and the configuration for the device that created the request
+///
+/// the code must be the one displayed by the new device
+///
+pub fn approve_device_link(
+    request: &[u8],
+    code: &str,
+    key_material: &[u8; 32],
+    configuration: &Configuration,
+) -> Result<Vec<u8>, crate::Error> {
+    let request: LinkRequest = bincode::deserialize(request)?;
+    if !digits(code).eq(&digits(&link_code(&request.public_key))) {
+        return Err(Error::DeviceLink("invalid device link code".to_string()).into());
+    }
+    if (now() - request.date).abs() > REQUEST_VALIDITY_MS {
+        return Err(Error::DeviceLink("expired device link request".to_string()).into());
+    }
+
+    let secret = StaticSecret::from(random32());
+    let public_key = PublicKey::from(&secret).to_bytes();
+    let key = link_key(
+        &secret,
+        &PublicKey::from(request.public_key),
+        &request.public_key,
+        &public_key,
+    );
+
+    let mut in_out = serde_json::to_vec(&LinkPayload {
+        key_material: *key_material,
+        configuration: configuration.clone(),
+    })?;
+    let mut nonce = [0; NONCE_LEN];
+    nonce.copy_from_slice(&random32()[0..NONCE_LEN]);
+    open_key(&key)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| Error::DeviceLink("device link encryption failed".to_string()))?;
+
+    Ok(bincode::serialize(&LinkResponse {
+        public_key,
+        nonce,
+        ciphertext: in_out,
+    })?)
+}
+
+const LINK_CODE_DIGITS: usize = 15;
+fn link_code(public_key: &[u8; 32]) -> String {
+    let hash = blake3::derive_key("DEVICE_LINK_CODE", public_key);
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&hash[0..8]);
+    let code = format!(
+        "{:015}",
+        u64::from_le_bytes(bytes) % 10_u64.pow(LINK_CODE_DIGITS as u32)
+    );
+    let groups: Vec<&str> = (0..LINK_CODE_DIGITS)
+        .step_by(3)
+        .map(|i| &code[i..i + 3])
+        .collect();
+    groups.join("-")
+}
+
+fn digits(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+//the key is bound to both public keys
+fn link_key(
+    secret: &StaticSecret,
+    their_public: &PublicKey,
+    request_public: &[u8; 32],
+    response_public: &[u8; 32],
+) -> [u8; 32] {
+    let shared = secret.diffie_hellman(their_public);
+    let mut material = Vec::with_capacity(96);
+    material.extend_from_slice(shared.as_bytes());
+    material.extend_from_slice(request_public);
+    material.extend_from_slice(response_public);
+    blake3::derive_key("DEVICE_LINK_KEY", &material)
+}
+
+fn open_key(key: &[u8; 32]) -> Result<LessSafeKey, Error> {
+    let key = UnboundKey::new(&CHACHA20_POLY1305, key)
+        .map_err(|_| Error::DeviceLink("invalid device link key".to_string()))?;
+    Ok(LessSafeKey::new(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link() {
+        let key_material = random32();
+        let configuration = Configuration {
+            parallelism: 7,
+            ..Default::default()
+        };
+
+        let new_device = DeviceLinkRequest::new();
+        let code = new_device.code();
+        assert_eq!(19, code.len());
+        assert_eq!(LINK_CODE_DIGITS, digits(&code).len());
+
+        let response = approve_device_link(
+            &new_device.request(),
+            &code.replace('-', " "),
+            &key_material,
+            &configuration,
+        )
+        .unwrap();
+
+        let linked = new_device.complete(&response).unwrap();
+        assert_eq!(key_material, linked.key_material);
+        assert_eq!(7, linked.configuration.parallelism);
+
+        //only the device that created the request can read the response
+        assert!(DeviceLinkRequest::new().complete(&response).is_err());
+
+        let mut tampered = response.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(new_device.complete(&tampered).is_err());
+    }
+
+    #[test]
+    fn rejected_request() {
+        let key_material = random32();
+        let configuration = Configuration::default();
+        let new_device = DeviceLinkRequest::new();
+
+        approve_device_link(
+            &new_device.request(),
+            "000-000-000-000-000",
+            &key_material,
+            &configuration,
+        )
+        .expect_err("invalid code");
+
+        let mut expired = DeviceLinkRequest::new();
+        expired.request.date -= REQUEST_VALIDITY_MS + 1000;
+        approve_device_link(
+            &expired.request(),
+            &expired.code(),
+            &key_material,
+            &configuration,
+        )
+        .expect_err("expired request");
+    }
+}
@@ -170,27 +170,66 @@
 //! - iOS: not tested
 //!
 
+#[cfg(feature = "log")]
+use log::error;
+
+use std::future::Future;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use tokio::sync::{mpsc, oneshot};
-use tokio::{runtime::Runtime, sync::broadcast};
+use tokio::{
+    runtime::{Handle, Runtime},
+    sync::broadcast,
+    task::JoinHandle,
+};
 type Result<T> = std::result::Result<T, Error>;
 
 use crate::{
-    configuration::Configuration,
+    configuration::{Configuration, Storage},
     database::{
-        graph_database::{GraphDatabaseService, MutateReceiver},
-        query_language::parameter::Parameters,
-        system_entities::DefaultRoom,
+        authorisation_service::EntityContention,
+        backup::{BackupInfo, BackupStatus},
+        deletion::DeletedEntity,
+        entity_counter::EntityCounter,
+        graph_database::{GraphDatabaseService, MutateReceiver, OrderedMutateReceiver},
+        mutation_batch::MutationBatch,
+        query::SlowQuery,
+        query_language::{
+            parameter::{Parameters, ParametersAdd},
+            validate_file_id,
+        },
+        query_subscription::QuerySubscription,
+        replication::{DataAvailability, DayDivergence, PeerSyncStats},
+        room::RightType,
+        room_impact::RoomChangeImpact,
+        room_builder::{CreatedRoom, RoomBuilder},
+        search::SearchHit,
+        system_entities::{
+            AutomationRule, DataModelProposal, DataModelTemplate, DefaultRoom, GroupInvitation,
+            Peer, RoomSettings, SyncIncident, Tombstone,
+        },
+        telemetry::TelemetryReport,
+        transaction::TransactionBuilder,
+        Encoding,
     },
-    event_service::Event,
+    date_utils::now,
+    device_link::approve_device_link,
+    event_service::{Event, EventReceiver},
     event_service::EventService,
+    log_level::{self, LogLevel, LogSubsystem},
+    network::{HandshakeStats, MeetingTokenInfo},
     peer_connection_service::{PeerConnectionMessage, PeerConnectionService},
     security::{
-        base64_encode, default_uid, derive_key, uid_encode, HardwareFingerprint, MeetingSecret, Uid,
+        base64_decode, base64_encode, decrypt_with_pin, default_uid, derive_key, derive_uid,
+        encrypt_with_pin, is_pin_encrypted, uid_decode, uid_encode, HardwareFingerprint,
+        MeetingSecret, Uid,
     },
     signature_verification_service::SignatureVerificationService,
+    synchronisation::{
+        archive::{self, ArchiveImport},
+        peer_outbound_service::PeerQueryStats,
+    },
     Error,
 };
 
@@ -213,6 +252,9 @@ pub fn database_exists(
     GraphDatabaseService::database_exists(app_key, key_material, data_folder)
 }
 
+//stored in the data folder, shared by every database of the folder
+const HARDWARE_FINGERPRINT_FILE: &str = "hardware_fingerprint.bin";
+
 ///
 /// All the parameters available after Discret initialisation
 ///
@@ -249,7 +291,7 @@ impl Discret {
     ///- datamodel: define the data types that can be used by discret,
     ///- app_key: a unique identifier for the application that **cannot not** change once the application is in produciton
     ///- key_material: a master secret that will be used wit the app_key to derive all the secret required by discret
-    ///- data_folder: where data is stored
+    ///- data_folder: where data is stored, unused when the configuration storage is Storage::Memory
     ///- configuration: the configuration stucture
     pub async fn new(
         datamodel: &str,
@@ -258,9 +300,17 @@ impl Discret {
         data_folder: PathBuf,
         configuration: Configuration,
     ) -> std::result::Result<Self, Error> {
-        let mut hardware_file = data_folder.clone();
-        hardware_file.push("hardware_fingerprint.bin");
-        let hardware_fingerprint = HardwareFingerprint::get(&hardware_file).unwrap();
+        if configuration.error_privacy_mode {
+            crate::set_error_privacy_mode(true);
+        }
+        let hardware_fingerprint = match configuration.storage {
+            Storage::Memory => HardwareFingerprint::ephemeral(),
+            _ => {
+                let mut hardware_file = data_folder.clone();
+                hardware_file.push(HARDWARE_FINGERPRINT_FILE);
+                HardwareFingerprint::get(&hardware_file).unwrap()
+            }
+        };
         let meeting_secret_key =
             derive_key(&format!("{}{}", "MEETING_SECRET", app_key,), key_material);
         let meeting_secret = MeetingSecret::new(meeting_secret_key);
@@ -268,7 +318,8 @@ impl Discret {
         let pub_key = meeting_secret.public_key();
         let public_key = pub_key.as_bytes();
 
-        let event_service: EventService = EventService::new();
+        let event_service: EventService =
+            EventService::with_buffer_size(configuration.event_buffer_size);
         let (database_service, verifying_key, private_room_id) = GraphDatabaseService::start(
             app_key,
             datamodel,
@@ -306,11 +357,86 @@ impl Discret {
     }
 
     ///
-    /// Performs a Deletion query
+    /// Starts the Discret engine on the provided runtime instead of the ambient one, with the same parameters as *new()* and:
+    ///- runtime: the runtime that owns the Discret tasks,
+    ///- shutdown: a future that triggers *shutdown()* once completed, for example the receiving end of a oneshot channel.
+    ///
+    /// Useful when Discret is embedded in a server that manages the lifecycle of its services.
+    ///
+    pub async fn new_with_runtime<F>(
+        datamodel: &str,
+        app_key: &str,
+        key_material: &[u8; 32],
+        data_folder: PathBuf,
+        configuration: Configuration,
+        runtime: Handle,
+        shutdown: F,
+    ) -> std::result::Result<Self, Error>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let datamodel = datamodel.to_string();
+        let app_key = app_key.to_string();
+        let key_material = *key_material;
+        //every task spawned during the startup belongs to the runtime
+        let discret = runtime
+            .spawn(async move {
+                Self::new(
+                    &datamodel,
+                    &app_key,
+                    &key_material,
+                    data_folder,
+                    configuration,
+                )
+                .await
+            })
+            .await??;
+
+        let app = discret.clone();
+        runtime.spawn(async move {
+            shutdown.await;
+            if let Err(_e) = app.shutdown().await {
+                #[cfg(feature = "log")]
+                error!("Discret shutdown, error: {_e}");
+            }
+        });
+        Ok(discret)
+    }
+
+    ///
+    /// Stops the Discret engine: the peer connections are closed, the pending writes are flushed to the database
+    /// and the services stop processing messages.
+    ///
+    /// Every following call on this instance, or any of its clones, returns an error.
+    ///
+    pub async fn shutdown(&self) -> std::result::Result<(), Error> {
+        let (reply, receive) = oneshot::channel::<()>();
+        if self
+            .peers
+            .sender
+            .send(PeerConnectionMessage::Shutdown(reply))
+            .await
+            .is_ok()
+        {
+            let _ = receive.await;
+        }
+        Ok(self.services.database.shutdown().await?)
+    }
+
+    ///
+    /// Performs a Deletion query and returns the entities that were removed
+    ///
+    /// Entities that do not exist are ignored and are not returned.
+    /// With `delete @cascade {...}`, the entities that are only referenced by the deleted entities or by the removed references
+    /// are also deleted and returned.
     ///
-    pub async fn delete(&self, d: &str, p: Option<Parameters>) -> std::result::Result<(), Error> {
+    pub async fn delete(
+        &self,
+        d: &str,
+        p: Option<Parameters>,
+    ) -> std::result::Result<Vec<DeletedEntity>, Error> {
         match self.services.database.delete(d, p).await {
-            Ok(_) => Ok(()),
+            Ok(deletion) => Ok(deletion.deleted()),
             Err(e) => Err(e.into()),
         }
     }
@@ -318,6 +444,11 @@ impl Discret {
     ///
     /// Performs a mutation query and returns the inserted tuple in a JSON String
     ///
+    /// An optional idempotency key can be provided with the following syntax: `mutate @id("client-uuid") {...}` or `mutate @id($key) {...}`.
+    /// Retrying a mutation with an allready used key does not modify the data and returns the result of the first mutation.
+    /// Using the key with a different mutation or different parameters returns an error.
+    /// Keys are forgotten after [Configuration::idempotency_key_retention_in_hours](crate::Configuration).
+    ///
     pub async fn mutate(
         &self,
         m: &str,
@@ -326,6 +457,41 @@ impl Discret {
         Ok(self.services.database.mutate(m, p).await?)
     }
 
+    ///
+    /// Performs the same mutation query for each parameter set and returns the inserted tuples in JSON Strings, in the order of the parameters.
+    ///
+    /// The mutation is parsed once and every mutation is written in a single transaction, which is much faster than calling *mutate()* for each row when importing data.
+    /// The batch is atomic: if one mutation is invalid, nothing is written.
+    /// Room definitions cannot be modified by a batch.
+    ///
+    pub async fn mutate_batch(
+        &self,
+        m: &str,
+        p: Vec<Parameters>,
+    ) -> std::result::Result<Vec<String>, Error> {
+        let mutations = self.services.database.mutate_batch(m, p).await?;
+        let mut results = Vec::with_capacity(mutations.len());
+        for mutation in mutations {
+            results.push(mutation.result()?);
+        }
+        Ok(results)
+    }
+
+    ///
+    /// Performs a mutation query only once per database, usefull to insert the initial reference data of an application.
+    ///
+    /// The seed name is recorded with the mutation, calling this method again with the same name does not modify the data.
+    /// Returns true if the mutation was performed, false if the seed was allready applied.
+    ///
+    pub async fn seed(
+        &self,
+        name: &str,
+        m: &str,
+        p: Option<Parameters>,
+    ) -> std::result::Result<bool, Error> {
+        Ok(self.services.database.seed(name, m, p).await?)
+    }
+
     ///
     /// Allow to send a stream of mutation.
     ///
@@ -338,10 +504,43 @@ impl Discret {
         self.services.database.mutation_stream()
     }
 
+    ///
+    /// Allow to send a stream of mutation, each mutation being tagged with a sequence id chosen by the caller.
+    ///
+    /// The results are returned with the sequence id of their mutation, in the order of the submissions,
+    /// which allows importers to map the failures back to their input.
+    /// Mutations are still processed concurrently, like with *mutation_stream()*.
+    ///
+    pub fn ordered_mutation_stream(
+        &self,
+    ) -> (
+        mpsc::Sender<(u64, String, Option<Parameters>)>,
+        OrderedMutateReceiver,
+    ) {
+        self.services.database.ordered_mutation_stream()
+    }
+
+    ///
+    /// Creates a [MutationBatch] to import a large number of mutations.
+    ///
+    /// Mutations are written in batches like with *mutation_stream()*, with at most *max_in_flight* mutations being processed at the same time.
+    /// The batch provides explicit *flush()* and *close()* operations and a summary of the written and failed mutations,
+    /// allowing importers to checkpoint their progress.
+    ///
+    pub fn mutation_batch(&self, max_in_flight: usize) -> MutationBatch {
+        MutationBatch::new(&self.services.database, max_in_flight)
+    }
+
     ///
     /// Perform a query to retrieve results from the database.
     /// returns the result in a JSON object
     ///
+    /// Entities using the `cursor($c)` parameter are paginated without tracking the Order By values:
+    /// the result contains an opaque `<name>_next_cursor` field to pass as *$c* to get the next page, null for the first page and after the last one.
+    ///
+    /// Entities defined with the `edit_chain(field)` option only return the latest version of each node,
+    /// `versions($id)` returns the original node and all its versions.
+    ///
     pub async fn query(
         &self,
         q: &str,
@@ -350,6 +549,84 @@ impl Discret {
         Ok(self.services.database.query(q, p).await?)
     }
 
+    ///
+    /// Checks that at least one entity matches the filters, without reading the entities or building a JSON result.
+    /// - entity: the entity name, e.g. "chat.Message"
+    /// - filters: the entity parameters of a query, e.g. "room_id=$room_id, username=$name"
+    ///
+    /// Compiled to a `SELECT EXISTS(...)` that stops at the first matching row.
+    ///
+    pub async fn exists(
+        &self,
+        entity: &str,
+        filters: &str,
+        p: Option<Parameters>,
+    ) -> std::result::Result<bool, Error> {
+        Ok(self.services.database.exists(entity, filters, p).await?)
+    }
+
+    ///
+    /// Number of entities matching the filters, without reading the entities, see *exists()*
+    ///
+    pub async fn count(
+        &self,
+        entity: &str,
+        filters: &str,
+        p: Option<Parameters>,
+    ) -> std::result::Result<u64, Error> {
+        Ok(self.services.database.count(entity, filters, p).await?)
+    }
+
+    ///
+    /// Number of *entity* in a *Room* and the modification date of the most recent one.
+    ///
+    /// The counters are maintained by the database each time an entity is inserted, modified or deleted:
+    /// reading them does not depend on the size of the room, unlike *count()*. Useful for badges and unread indicators.
+    ///
+    pub async fn entity_counter(
+        &self,
+        room_id: &str,
+        entity: &str,
+    ) -> std::result::Result<EntityCounter, Error> {
+        let room_id = uid_decode(room_id)?;
+        Ok(self
+            .services
+            .database
+            .entity_counter(room_id, entity)
+            .await?)
+    }
+
+    ///
+    /// Subscribe to the results of a query.
+    ///
+    /// The returned [QuerySubscription] provides the current result of the query, and a new result each time
+    /// a modification of one of the queried entities changes it. UIs don't have to run the query again on every *Event::DataChanged*.
+    ///
+    /// Invalid queries are rejected here, the errors that occur during the later evaluations are returned by the subscription.
+    ///
+    pub async fn subscribe_query(
+        &self,
+        q: &str,
+        p: Option<Parameters>,
+    ) -> std::result::Result<QuerySubscription, Error> {
+        QuerySubscription::start(&self.services.database, &self.services.events, q, p).await
+    }
+
+    ///
+    /// Perform a query to retrieve results from the database.
+    /// returns the result with the requested encoding, that can be parsed with *ResultParser::from_bytes()*
+    ///
+    /// The Cbor encoding is more compact and faster to parse than JSON for large result sets.
+    ///
+    pub async fn query_bytes(
+        &self,
+        q: &str,
+        p: Option<Parameters>,
+        encoding: Encoding,
+    ) -> std::result::Result<Vec<u8>, Error> {
+        Ok(self.services.database.query_bytes(q, p, encoding).await?)
+    }
+
     ///
     /// Create an invitation
     /// - default_room: once the inviation is accepted, the new Peer will be granted access to this room.
@@ -371,6 +648,11 @@ impl Discret {
     /// Once an invitation is accepted, the two peers will be able to discover themselves and start exchanging data
     ///   
     pub async fn accept_invite(&self, invitation: Vec<u8>) -> std::result::Result<(), Error> {
+        if is_pin_encrypted(&invitation) {
+            return Err(Error::InvalidInvite(
+                "the invitation is protected by a PIN, use accept_invite_with_pin".to_string(),
+            ));
+        }
         let _ = self
             .peers
             .sender
@@ -381,189 +663,1781 @@ impl Discret {
     }
 
     ///
-    /// This is is your Public identity.
-    ///
-    /// It is derived from the provided key_material and app_key.
+    /// Create an invitation encrypted with a PIN, see *Discret::invite()*
     ///
-    /// Every data you create will be signed using the associated signing_key, and  
-    /// other peers will use this verifying key to ensure the integrity of the data
+    /// The PIN has to be exchanged through another channel than the invitation, for example read over the phone.
+    /// A leaked invitation cannot be accepted without the PIN, but it can be attacked offline:
+    /// an attacker holding the invitation can try every PIN, each guess costing an argon2 key derivation.
+    /// The PINs weaker than *MIN_PIN_ENTROPY* are rejected, *new_pin()* generates a PIN that is accepted.
     ///
-    pub fn verifying_key(&self) -> String {
-        base64_encode(&self.params.verifying_key)
+    pub async fn invite_with_pin(
+        &self,
+        default_room: Option<DefaultRoom>,
+        pin: &str,
+    ) -> Result<Vec<u8>> {
+        let invitation = self.invite(default_room).await?;
+        Ok(encrypt_with_pin(&invitation, pin)?)
     }
 
     ///
-    /// This special room is used internally to store system data.
-    /// you are allowed to used it to store any kind of private data that will only be synchronized with your devices.
+    /// Accept an invitation created by *Discret::invite_with_pin()*
     ///
-    pub fn private_room(&self) -> String {
-        base64_encode(&self.params.private_room_id)
+    /// Fails when the PIN is invalid
+    ///
+    pub async fn accept_invite_with_pin(
+        &self,
+        invitation: Vec<u8>,
+        pin: &str,
+    ) -> std::result::Result<(), Error> {
+        let invitation = decrypt_with_pin(&invitation, pin)?;
+        self.accept_invite(invitation).await
     }
 
     ///
-    /// Subscribe for the event queue
+    /// Accepts or rejects the connections of a peer when the *connection_policy* configuration is not *ConnectionPolicy::AutoAccept*
+    /// - verifying_key: the verifying key of the peer, provided by *Event::ConnectionRequest*
     ///
-    pub async fn subscribe_for_events(&self) -> broadcast::Receiver<Event> {
-        self.services.events.subcribe().await
+    /// The connections waiting for the answer are started or closed. The answer applies to the following connections of the peer until Discret is stopped,
+    /// a peer can be accepted before it tries to connect.
+    ///
+    pub async fn answer_connection_request(
+        &self,
+        verifying_key: Vec<u8>,
+        accept: bool,
+    ) -> std::result::Result<(), Error> {
+        self.peers
+            .answer_connection_request(verifying_key, accept)
+            .await;
+        Ok(())
     }
 
     ///
-    /// Update the existing data model definition with a new one.  
-    ///
-    /// returns the JSON representation of the updated datamodel.
+    /// Invites every current member of a *Room* into an authorisation of another *Room* in one operation,
+    /// for example to create a breakout room from an existing group.
+    /// - from_room: the room whose members are invited
+    /// - to_room: the room the members are invited into
+    /// - authorisation: an authorisation of to_room that the members are added to
     ///
-    /// Can be usefull to create a data model editor.
+    /// Members already enabled in the authorisation are skipped. The caller must be allowed to modify to_room.
+    /// A meeting token is created for each member with a known *sys.Peer*, allowing them to connect even if
+    /// *auto_allow_new_peers* is disabled. The members are notified when the new room definition is synchronised.
     ///
-    pub async fn update_data_model(&self, datamodel: &str) -> std::result::Result<String, Error> {
-        Ok(self.services.database.update_data_model(datamodel).await?)
+    pub async fn invite_room_members(
+        &self,
+        from_room: &str,
+        to_room: &str,
+        authorisation: &str,
+    ) -> std::result::Result<GroupInvitation, Error> {
+        let from_id = uid_decode(from_room)?;
+        let to_id = uid_decode(to_room)?;
+        let auth_id = uid_decode(authorisation)?;
+        let database = &self.services.database;
+
+        let from = database
+            .get_room(from_id)
+            .await?
+            .ok_or(crate::database::Error::UnknownRoom(from_room.to_string()))?;
+        let to = database
+            .get_room(to_id)
+            .await?
+            .ok_or(crate::database::Error::UnknownRoom(to_room.to_string()))?;
+        let auth =
+            to.authorisations
+                .get(&auth_id)
+                .ok_or(crate::database::Error::UnknownAuthorisation(
+                    authorisation.to_string(),
+                    to_room.to_string(),
+                ))?;
+
+        let date = now();
+        let mut members: Vec<Vec<u8>> = from
+            .users()
+            .into_iter()
+            .filter(|member| {
+                !member.eq(&self.params.verifying_key)
+                    && from.is_user_valid_at(member, date)
+                    && !auth.is_user_valid_at(member, date)
+            })
+            .collect();
+        members.sort();
+
+        let mut invitation = GroupInvitation {
+            room: to_room.to_string(),
+            authorisation: authorisation.to_string(),
+            ..Default::default()
+        };
+        if members.is_empty() {
+            return Ok(invitation);
+        }
+
+        let mut users = String::new();
+        let mut param = Parameters::new();
+        param.add("id", to_room.to_string())?;
+        param.add("auth", authorisation.to_string())?;
+        for (i, member) in members.iter().enumerate() {
+            users.push_str(&format!("{{verif_key:$key{} enabled:true}}", i));
+            param.add(&format!("key{}", i), base64_encode(member))?;
+        }
+        let mutation = format!(
+            "mutate {{ sys.Room{{ id:$id authorisations:[{{ id:$auth users:[{}] }}] }} }}",
+            users
+        );
+        database.mutate(&mutation, Some(param)).await?;
+
+        let mut peers = Vec::new();
+        for member in members {
+            match database.get_peer_node(member.clone()).await? {
+                Some(peer) => peers.push(peer),
+                None => invitation.unknown_peers.push(base64_encode(&member)),
+            }
+            invitation.invited.push(base64_encode(&member));
+        }
+
+        if !peers.is_empty() {
+            let (reply, receive) = oneshot::channel::<Result<()>>();
+            let _ = self
+                .peers
+                .sender
+                .send(PeerConnectionMessage::AllowPeers(peers, reply))
+                .await;
+            receive.await??;
+        }
+        Ok(invitation)
     }
 
     ///
-    /// Provide a JSON representation of the datamodel  
+    /// Approves the link request of a new device, see *DeviceLinkRequest*.
+    /// - request: the request created by the new device
+    /// - code: the short code displayed by the new device
+    /// - key_material: the key material of this account, Discret does not keep it and the application has to ask for it again
     ///
-    /// The JSON contains the model plain text along with the internal datamodel representation.
+    /// Fails with *Error::InvalidAccount* when the key material belongs to another account.
     ///
-    /// Can be usefull to create a data model editor.
+    /// Returns the key material and the configuration of this device, encrypted for the new device.
+    /// The returned byte array have to be sent manually to the new device.
     ///
-    pub async fn data_model(&self) -> std::result::Result<String, Error> {
-        Ok(self.services.database.datamodel().await?)
-    }
-}
-
-struct BlockingRuntime {
-    rt: Option<Runtime>,
-}
-impl BlockingRuntime {
-    pub fn new() -> Self {
-        Self { rt: None }
-    }
-    pub fn rt(&mut self) -> std::result::Result<&Runtime, Error> {
-        if self.rt.is_none() {
-            self.rt = Some(
-                tokio::runtime::Builder::new_multi_thread()
-                    .enable_all()
-                    .build()?,
-            );
+    pub fn approve_device_link(
+        &self,
+        request: &[u8],
+        code: &str,
+        key_material: &[u8; 32],
+    ) -> std::result::Result<Vec<u8>, Error> {
+        let verifying_key =
+            GraphDatabaseService::derive_verifying_key(&self.params.app_key, key_material);
+        if verifying_key != self.params.verifying_key {
+            return Err(Error::InvalidAccount);
         }
-        Ok(self.rt.as_ref().unwrap())
+        approve_device_link(request, code, key_material, &self.params.configuration)
     }
-}
-
-lazy_static::lazy_static! {
-    static ref TOKIO_BLOCKING: Arc<Mutex<BlockingRuntime>> =
-    Arc::new(Mutex::new(BlockingRuntime::new()));
-}
-///
-/// The main entry point for the Discret Library, with a blocking API
-/// Provides a blocking API
-///
-#[derive(Clone)]
-pub struct DiscretBlocking {
-    discret: Discret,
-}
-impl DiscretBlocking {
-    /// Starts the Discret engine with the following parameters:
-    ///- datamodel: define the data types that can be used by discret,
-    ///- app_key: a unique identifier for the application that **cannot not** change once the application is in produciton
-    ///- key_material: a master secret that will be used wit the app_key to derive all the secret required by discret
-    ///- data_folder: where data is stored
-    ///- configuration: the configuration stucture
-    pub fn new(
-        datamodel: &str,
-        app_key: &str,
-        key_material: &[u8; 32],
-        data_folder: PathBuf,
-        configuration: Configuration,
-    ) -> std::result::Result<Self, Error> {
-        let discret = TOKIO_BLOCKING.lock().unwrap().rt()?.block_on(Discret::new(
-            datamodel,
-            app_key,
-            key_material,
-            data_folder,
-            configuration,
-        ))?;
 
-        Ok(Self { discret })
+    ///
+    /// Changes the verbosity of the internal logs of a subsystem at runtime,
+    /// allowing to capture detailed synchronisation traces without rebuilding the application.
+    ///
+    /// The errors and the informations of every subsystem are logged by default.
+    /// The logs are only available with the "log" feature and are written to the logger installed by the application.
+    /// Raising a level above the *log::max_level()* of the application raises it, the logger can still filter the records.
+    ///
+    /// The levels are shared by every Discret instance of the process.
+    ///
+    pub fn set_log_level(&self, subsystem: LogSubsystem, level: LogLevel) {
+        log_level::set_log_level(subsystem, level);
     }
 
     ///
-    /// Performs a Deletion query
+    /// The current log verbosity of a subsystem, see *set_log_level()*
     ///
-    pub fn delete(&self, d: &str, p: Option<Parameters>) -> std::result::Result<(), Error> {
-        TOKIO_BLOCKING
-            .lock()
-            .unwrap()
-            .rt()?
-            .block_on(self.discret.delete(d, p))
+    pub fn log_level(&self, subsystem: LogSubsystem) -> LogLevel {
+        log_level::log_level(subsystem)
     }
 
     ///
-    /// Performs a mutation query and returns the inserted tuple in a JSON String
+    /// Write contention statistics per entity, gathered during synchronisation since startup.
     ///
-    pub fn mutate(&self, m: &str, p: Option<Parameters>) -> std::result::Result<String, Error> {
-        TOKIO_BLOCKING
-            .lock()
-            .unwrap()
-            .rt()?
-            .block_on(self.discret.mutate(m, p))
+    /// A write conflict occurs when a synchronisation overwrites a locally authored node with a version authored by another peer.
+    /// Entities with a high number of conflicts are frequently modified concurrently and might need a different design.
+    ///
+    pub async fn contention_stats(&self) -> std::result::Result<Vec<EntityContention>, Error> {
+        Ok(self.services.database.contention_stats().await?)
     }
 
     ///
-    /// Allow to send a stream of mutation.
+    /// The most recent queries that took longer than the *slow_query_threshold_in_ms* configuration, the most recent first.
     ///
-    /// Usefull for batch insertion as you do have to wait for the mutation to finished before sending another.
+    /// At most *slow_query_log_size* queries are kept in memory. Each slow query also triggers an *Event::SlowQuery*.
     ///
-    /// The receiver retrieve an internal representation of the mutation query to avoid the performance cost of creating the JSON result, wich is probably unecessary when doing batch insert.
-    /// To get the JSON, call the  MutationQuery.result() method
+    pub async fn slow_queries(&self) -> std::result::Result<Vec<SlowQuery>, Error> {
+        Ok(self.services.database.slow_queries().await?)
+    }
+
     ///
-    pub fn mutation_stream(&self) -> (mpsc::Sender<(String, Option<Parameters>)>, MutateReceiver) {
-        self.discret.mutation_stream()
+    /// Anonymous performance metrics aggregated since the start of Discret or the last reset,
+    /// returns None when the *enable_telemetry* configuration is not set.
+    ///
+    /// The report can be submitted by the application to help prioritizing the performance work on the library.
+    /// Use *reset* to start a new aggregation once the report has been submitted.
+    ///
+    pub async fn telemetry(
+        &self,
+        reset: bool,
+    ) -> std::result::Result<Option<TelemetryReport>, Error> {
+        Ok(self.services.database.telemetry(reset).await?)
     }
 
     ///
-    /// Perform a query to retrieve results from the database.
-    /// returns the result in a JSON object
+    /// Salvages the readable data of a corrupted database, after an *Event::DatabaseCorrupted*.
     ///
-    pub fn query(&self, q: &str, p: Option<Parameters>) -> std::result::Result<String, Error> {
-        TOKIO_BLOCKING
-            .lock()
-            .unwrap()
-            .rt()?
-            .block_on(self.discret.query(q, p))
+    /// The readable rows are copied in a new database file that replaces the corrupted one the next time *Discret* is started.
+    /// The corrupted file is kept in the data folder with the '.corrupted' extension.
+    ///
+    /// returns the number of recovered rows. Only available for the *Storage::File* storage.
+    ///
+    pub async fn attempt_recovery(&self) -> std::result::Result<usize, Error> {
+        if self.params.configuration.storage != Storage::File {
+            return Err(Error::Unsupported(
+                "database recovery is only available for Storage::File".to_string(),
+            ));
+        }
+        Ok(self.services.database.attempt_recovery().await?)
     }
 
     ///
-    /// Create an invitation
-    /// - default_room: once the inviation is accepted, the new Peer will be granted access to this room.
+    /// Writes a backup of the database in the *backup_directory* now, without waiting for the next scheduled backup.
     ///
-    /// The returned byte array have to be sent manually to another peer.
+    /// The backup is encrypted with the database secret. The rotation defined by the *backup_keep_daily* and *backup_keep_weekly* configuration is applied.
     ///
-    pub async fn invite(&self, default_room: Option<DefaultRoom>) -> Result<Vec<u8>> {
-        TOKIO_BLOCKING
-            .lock()
-            .unwrap()
-            .rt()?
-            .block_on(self.discret.invite(default_room))
+    pub async fn backup_now(&self) -> std::result::Result<BackupInfo, Error> {
+        Ok(self.services.database.backup().await?)
     }
 
     ///
-    /// Accept an invitation
-    /// Once an invitation is accepted, the two peers will be able to discover themselves and start exchanging data
-    ///   
-    pub async fn accept_invite(&self, invitation: Vec<u8>) -> std::result::Result<(), Error> {
-        TOKIO_BLOCKING
-            .lock()
-            .unwrap()
-            .rt()?
-            .block_on(self.discret.accept_invite(invitation))
+    /// Returns the state of the backup scheduler: the next backup date, the error of the last attempt and the kept backups.
+    ///
+    pub async fn backup_status(&self) -> std::result::Result<BackupStatus, Error> {
+        Ok(self.services.database.backup_status().await?)
     }
 
     ///
-    /// This is is your Public identity.
+    /// Writes the content of *Rooms* in an encrypted archive file, to move data between peers that cannot connect, for example with an USB stick.
     ///
-    /// It is derived from the provided key_material and app_key.
+    /// The archive contains the room definitions, the nodes, the edges and the deletion logs of the rooms.
+    /// It is signed by the local peer and encrypted with the *pass_phrase*, which must be provided to *import_archive()*.
     ///
-    /// Every data you create will be signed using the associated signing_key, and  
+    pub async fn export_rooms(
+        &self,
+        room_ids: Vec<String>,
+        path: PathBuf,
+        pass_phrase: &str,
+    ) -> std::result::Result<(), Error> {
+        let mut ids = Vec::with_capacity(room_ids.len());
+        for room_id in &room_ids {
+            ids.push(uid_decode(room_id)?);
+        }
+        archive::export_rooms(ids, &path, pass_phrase, &self.services).await
+    }
+
+    ///
+    /// Imports an archive created by *export_rooms()*.
+    ///
+    /// The archive content is verified and inserted like the data received during a synchronisation:
+    /// the authorisations of the rooms apply and the local data that is more recent than the archive is kept.
+    /// Fails when the pass phrase is invalid or the archive has been modified.
+    ///
+    /// The rooms that are unknown to the local peer are skipped and listed in *ArchiveImport::rooms_skipped*,
+    /// set *accept_new_rooms* to add them to the local peer.
+    ///
+    pub async fn import_archive(
+        &self,
+        path: PathBuf,
+        pass_phrase: &str,
+        accept_new_rooms: bool,
+    ) -> std::result::Result<ArchiveImport, Error> {
+        archive::import_archive(&path, pass_phrase, accept_new_rooms, &self.services).await
+    }
+
+    ///
+    /// Moves the database files to a new data folder, for example to store the application data on an SD card or another drive.
+    ///
+    /// The writes are paused while the files are moved: they are queued and processed once the database is reopened in the new folder.
+    /// Files are copied when they cannot be renamed, which allows the new folder to be on another file system.
+    /// On failure, the database keeps using the previous folder.
+    /// If the moved database cannot be reopened, the error is returned and the database is read-only until the next startup.
+    ///
+    /// The *data_folder* provided to *new()* must be updated by the application for the next startups.
+    /// Only available for the *Storage::File* storage.
+    ///
+    pub async fn relocate_data_folder(
+        &self,
+        new_data_folder: PathBuf,
+    ) -> std::result::Result<(), Error> {
+        if self.params.configuration.storage != Storage::File {
+            return Err(Error::Unsupported(
+                "data folder relocation is only available for Storage::File".to_string(),
+            ));
+        }
+        let previous_folder = self
+            .services
+            .database
+            .relocate(new_data_folder.clone())
+            .await?;
+
+        //the fingerprint is shared by every database of the folder, it is copied instead of moved
+        let fingerprint = previous_folder.join(HARDWARE_FINGERPRINT_FILE);
+        let target = new_data_folder.join(HARDWARE_FINGERPRINT_FILE);
+        if fingerprint.exists() && !target.exists() {
+            std::fs::copy(fingerprint, target)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Checks whether a peer is currently allowed to mutate or delete the nodes of an entity in a *Room*,
+    /// using the same rules as the ones applied when the data is written.
+    /// - MutateSelf: the peer can modify the nodes it created
+    /// - MutateAll: the peer can modify the nodes created by any peer
+    ///
+    /// It allows applications to disable actions that would be rejected instead of relying on failed mutations.
+    /// Returns false if the room is unknown.
+    ///
+    pub async fn can(
+        &self,
+        verifying_key: &str,
+        room_id: &str,
+        entity: &str,
+        right: RightType,
+    ) -> std::result::Result<bool, Error> {
+        let verifying_key = base64_decode(verifying_key.as_bytes())?;
+        let room_id = uid_decode(room_id)?;
+        Ok(self
+            .services
+            .database
+            .can(verifying_key, room_id, entity.to_string(), right)
+            .await?)
+    }
+
+    ///
+    /// Validates a mutation modifying a *Room* without applying it, and returns its impact on the room members:
+    /// - changes: the admin and entity rights gained or lost by each member
+    /// - affected_data: per member and entity, the number of nodes the member will not be able to modify anymore,
+    ///   and the number of those nodes dated after the change that would be rejected during synchronisation
+    ///
+    /// It allows admins to detect accidental lockouts before applying a rights change.
+    /// The mutation is validated with the same rules as *mutate()* and fails the same way.
+    ///
+    pub async fn preview_room_change(
+        &self,
+        room_id: &str,
+        mutation: &str,
+        param_opt: Option<Parameters>,
+    ) -> std::result::Result<RoomChangeImpact, Error> {
+        let room_id = uid_decode(room_id)?;
+        Ok(self
+            .services
+            .database
+            .preview_room_change(room_id, mutation, param_opt)
+            .await?)
+    }
+
+    ///
+    /// Load the application settings of a *Room*.
+    ///
+    /// Settings are key/value pairs stored in the *sys.RoomSettings* entity and synchronized with the room.
+    ///
+    pub async fn room_settings(&self, room_id: &str) -> std::result::Result<RoomSettings, Error> {
+        RoomSettings::load(room_id, &self.services.database).await
+    }
+
+    ///
+    /// Insert or update a *Room* setting.
+    ///
+    /// Only the room admins are allowed to modify the settings.
+    ///
+    pub async fn set_room_setting(
+        &self,
+        room_id: &str,
+        key: &str,
+        value: impl Into<serde_json::Value>,
+    ) -> std::result::Result<(), Error> {
+        RoomSettings::set(room_id, key, value.into(), &self.services.database).await
+    }
+
+    ///
+    /// Hides a node of a *Room* by creating a *sys.Tombstone* moderation record.
+    ///
+    /// Only the room admins are allowed to create tombstones. The tombstone is signed and synchronized like any other room data.
+    /// The node is kept in the database but is excluded from the query results of every peer.
+    /// Deleting the *sys.Tombstone* restores the node.
+    ///
+    /// Returns the identifier of the tombstone.
+    ///
+    pub async fn tombstone(
+        &self,
+        room_id: &str,
+        node_id: &str,
+        reason: Option<&str>,
+    ) -> std::result::Result<String, Error> {
+        Tombstone::create(room_id, node_id, reason, &self.services.database).await
+    }
+
+    ///
+    /// Creates an automation rule in a *Room*, stored in the *sys.AutomationRule* entity and synchronized with the room.
+    ///
+    /// When a *trigger* entity (like "chat.Message") is inserted in the room, the peer that inserted it applies the *mutation*,
+    /// with the *$id* and *$room_id* parameters set to the identifiers of the inserted entity and of its room.
+    /// A rule is never triggered by its own consequences, which prevents infinite loops.
+    ///
+    /// Every entity of the mutation must define *room_id:$room_id* and only the *$id* entity can be updated:
+    /// a rule cannot mutate another room or a system entity.
+    ///
+    /// Only the room admins are allowed to create rules. Returns the identifier of the rule.
+    ///
+    pub async fn create_automation_rule(
+        &self,
+        room_id: &str,
+        trigger: &str,
+        mutation: &str,
+    ) -> std::result::Result<String, Error> {
+        AutomationRule::create(room_id, trigger, mutation, &self.services.database).await
+    }
+
+    ///
+    /// Enables or disables an automation rule.
+    ///
+    pub async fn enable_automation_rule(
+        &self,
+        rule_id: &str,
+        enabled: bool,
+    ) -> std::result::Result<(), Error> {
+        AutomationRule::set_enabled(rule_id, enabled, &self.services.database).await
+    }
+
+    ///
+    /// Returns the automation rules of a *Room*, including the disabled ones.
+    ///
+    pub async fn automation_rules(
+        &self,
+        room_id: &str,
+    ) -> std::result::Result<Vec<AutomationRule>, Error> {
+        AutomationRule::load(room_id, &self.services.database).await
+    }
+
+    ///
+    /// Creates a *Room* from a [RoomBuilder], which generates the nested *sys.Room* mutation.
+    ///
+    /// Returns the identifiers of the room and of its authorisations, indexed by authorisation name.
+    ///
+    pub async fn create_room(&self, room: &RoomBuilder) -> std::result::Result<CreatedRoom, Error> {
+        room.execute(&self.services.database).await
+    }
+
+    ///
+    /// Writes the mutations and deletions of a [TransactionBuilder] in a single database transaction.
+    ///
+    /// Every operation is checked by the authorisation model before anything is written: if one of them is rejected, nothing is written.
+    /// returns the JSON result of each mutation, in the order of the mutations.
+    ///
+    pub async fn transaction(
+        &self,
+        transaction: &TransactionBuilder,
+    ) -> std::result::Result<Vec<String>, Error> {
+        transaction.execute(&self.services.database).await
+    }
+
+    ///
+    /// Returns the clock offset of a peer in milliseconds, estimated during the last connection handshake.
+    ///
+    /// The offset is positive when the peer clock is ahead of the local clock.
+    /// Returns None if the peer has not connected since startup.
+    ///
+    pub async fn peer_clock_offset(&self, verifying_key: Vec<u8>) -> Result<Option<i64>> {
+        let (reply, receive) = oneshot::channel::<Option<i64>>();
+        let _ = self
+            .peers
+            .sender
+            .send(PeerConnectionMessage::GetClockOffset(verifying_key, reply))
+            .await;
+        Ok(receive.await?)
+    }
+
+    ///
+    /// Returns the metadata that a peer defined with the *peer_metadata* configuration, as a JSON object.
+    ///
+    /// The metadata is stored in the sys.Peer of the peer and is updated each time the peer connects.
+    /// Returns None if the peer is unknown or did not define any metadata.
+    ///
+    pub async fn peer_metadata(&self, verifying_key: Vec<u8>) -> Result<Option<String>> {
+        let peer = self.services.database.get_peer_node(verifying_key).await?;
+        match peer {
+            Some(peer) => Ok(Peer::metadata(&peer)?),
+            None => Ok(None),
+        }
+    }
+
+    ///
+    /// Returns the synchronisation statistics of a peer: bytes exchanged, nodes accepted and rejected,
+    /// and the date of the last successful synchronisation of each room.
+    ///
+    /// The statistics are stored in the database and kept across restarts.
+    /// Returns None if the peer never connected.
+    ///
+    pub async fn peer_sync_stats(&self, verifying_key: Vec<u8>) -> Result<Option<PeerSyncStats>> {
+        Ok(self
+            .services
+            .database
+            .get_peer_sync_stats(verifying_key)
+            .await?)
+    }
+
+    ///
+    /// Lists the synchronisation incidents of a *Room*, most recent first.
+    ///
+    /// An incident is recorded when data sent by a peer is rejected during the synchronisation of a day,
+    /// usually because the peer uses outdated authorisations or an unknown data model.
+    /// Incidents are local *sys.SyncIncident* entities that are never synchronised, they can also be queried directly.
+    ///
+    pub async fn sync_incidents(
+        &self,
+        room_id: &str,
+    ) -> std::result::Result<Vec<SyncIncident>, Error> {
+        SyncIncident::list(room_id, &self.services.database).await
+    }
+
+    ///
+    /// Deletes the synchronisation incidents of a *Room* and returns the number of deleted incidents.
+    ///
+    pub async fn clear_sync_incidents(&self, room_id: &str) -> std::result::Result<usize, Error> {
+        SyncIncident::delete(room_id, &self.services.database).await
+    }
+
+    ///
+    /// Returns the handshake latency of the last outbound connection to a peer,
+    /// and whether a session ticket allowed it to use 0-RTT.
+    ///
+    /// Session tickets are only kept in memory: the first connection to a peer after a restart of either peer always performs a full handshake.
+    ///
+    /// Returns None if no outbound connection to this peer has been made since startup.
+    ///
+    pub async fn peer_handshake_stats(
+        &self,
+        verifying_key: Vec<u8>,
+    ) -> Result<Option<HandshakeStats>> {
+        let (reply, receive) = oneshot::channel::<Option<HandshakeStats>>();
+        let _ = self
+            .peers
+            .sender
+            .send(PeerConnectionMessage::GetHandshakeStats(
+                verifying_key,
+                reply,
+            ))
+            .await;
+        Ok(receive.await?)
+    }
+
+    ///
+    /// Returns the number of queries received from a peer since startup,
+    /// and how many of them have been delayed by the *remote_query_rate* and *remote_query_concurrency* limits.
+    ///
+    /// Returns None if the peer did not send any query since startup.
+    ///
+    pub async fn peer_query_stats(&self, verifying_key: Vec<u8>) -> Result<Option<PeerQueryStats>> {
+        let (reply, receive) = oneshot::channel::<Option<PeerQueryStats>>();
+        let _ = self
+            .peers
+            .sender
+            .send(PeerConnectionMessage::GetQueryStats(verifying_key, reply))
+            .await;
+        Ok(receive.await?)
+    }
+
+    ///
+    /// Notifies Discret that the network of the device has changed, for example when switching from Wi-Fi to a mobile network.
+    ///
+    /// The connections initiated by this device migrate to the new network without restarting their synchronisation.
+    /// The connections initiated by the peers cannot follow the device and are re-established when they time out.
+    ///
+    pub async fn network_changed(&self) -> Result<()> {
+        let (reply, receive) = oneshot::channel::<Result<()>>();
+        let _ = self
+            .peers
+            .sender
+            .send(PeerConnectionMessage::NetworkChanged(reply))
+            .await;
+        receive.await?
+    }
+
+    ///
+    /// Subscribes to some entities of a *Room*, for example "chat.Message" without the bulky "chat.Attachment".
+    ///
+    /// The connected peers only notify this device when one of the subscribed entities is modified in the room,
+    /// reducing the wake-ups and the synchronisation queries. The other entities are still synchronised every time the room is.
+    /// An empty list restores the notifications for every modification of the room.
+    ///
+    /// The subscriptions are kept in memory and must be defined again after a restart.
+    ///
+    pub async fn subscribe_entities(&self, room_id: &str, entities: Vec<String>) -> Result<()> {
+        let room_id = uid_decode(room_id)?;
+        let (reply, receive) = oneshot::channel::<()>();
+        let _ = self
+            .peers
+            .sender
+            .send(PeerConnectionMessage::SubscribeEntities(
+                room_id, entities, reply,
+            ))
+            .await;
+        Ok(receive.await?)
+    }
+
+    ///
+    /// Lists the meeting tokens announced on the local network and to the beacons,
+    /// with the allowed peer or the default room associated with each token.
+    ///
+    pub async fn meeting_tokens(&self) -> Result<Vec<MeetingTokenInfo>> {
+        let (reply, receive) = oneshot::channel::<Vec<MeetingTokenInfo>>();
+        let _ = self
+            .peers
+            .sender
+            .send(PeerConnectionMessage::MeetingTokens(reply))
+            .await;
+        Ok(receive.await?)
+    }
+
+    ///
+    /// Removes the stale meeting tokens from the announce list:
+    /// - allowed peers that are no longer enabled,
+    /// - invites created or received before the 'older_than' date (in milliseconds since the Unix epoch), that were never used.
+    ///
+    /// Returns the number of removed tokens.
+    ///
+    pub async fn prune_tokens(&self, older_than: i64) -> Result<usize> {
+        let (reply, receive) = oneshot::channel::<Result<usize>>();
+        let _ = self
+            .peers
+            .sender
+            .send(PeerConnectionMessage::PruneTokens(older_than, reply))
+            .await;
+        receive.await?
+    }
+
+    ///
+    /// This is is your Public identity.
+    ///
+    /// It is derived from the provided key_material and app_key.
+    ///
+    /// Every data you create will be signed using the associated signing_key, and  
+    /// other peers will use this verifying key to ensure the integrity of the data
+    ///
+    pub fn verifying_key(&self) -> String {
+        base64_encode(&self.params.verifying_key)
+    }
+
+    ///
+    /// This special room is used internally to store system data.
+    /// you are allowed to used it to store any kind of private data that will only be synchronized with your devices.
+    ///
+    pub fn private_room(&self) -> String {
+        base64_encode(&self.params.private_room_id)
+    }
+
+    ///
+    /// Returns an additional private room identified by a label, creating it if needed.
+    ///
+    /// Like the *private_room*, it is only synchronized with your devices. The room identifier is derived from the key_material,
+    /// allowing your devices to store data in separately synchronized rooms (settings, drafts, ...).
+    ///
+    /// The room is not stored in the database: every device must call this method with the same label at each start to synchronize the room.
+    ///
+    pub async fn private_room_for(&self, label: &str) -> std::result::Result<String, Error> {
+        let room_id = derive_uid(
+            &format!("{}{}", "PRIVATE_ROOM", label),
+            &self.params.private_room_id,
+        );
+        self.services.database.add_private_room(room_id).await?;
+        Ok(base64_encode(&room_id))
+    }
+
+    ///
+    /// Subscribe for the event queue
+    ///
+    pub async fn subscribe_for_events(&self) -> broadcast::Receiver<Event> {
+        self.services.events.subcribe().await
+    }
+
+    ///
+    /// Subscribe for the event queue.
+    ///
+    /// Unlike *subscribe_for_events*, the receiver returns an *Event::Lagged* when events have been missed,
+    /// and provides a pull based iterator over the buffered events.
+    ///
+    pub async fn event_receiver(&self) -> EventReceiver {
+        self.services.events.receiver().await
+    }
+
+    ///
+    /// Spawns a task that calls the handler for every event.
+    ///
+    /// The loop stops when the handler returns false or when Discret is stopped.
+    /// Missed events are notified with *Event::Lagged*.
+    ///
+    pub async fn spawn_event_loop<F, Fut>(&self, mut handler: F) -> JoinHandle<()>
+    where
+        F: FnMut(Event) -> Fut + Send + 'static,
+        Fut: Future<Output = bool> + Send,
+    {
+        let mut events = self.event_receiver().await;
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                if !handler(event).await {
+                    break;
+                }
+            }
+        })
+    }
+
+    ///
+    /// Update the existing data model definition with a new one.  
+    ///
+    /// returns the JSON representation of the updated datamodel.
+    ///
+    /// Can be usefull to create a data model editor.
+    ///
+    pub async fn update_data_model(&self, datamodel: &str) -> std::result::Result<String, Error> {
+        Ok(self.services.database.update_data_model(datamodel).await?)
+    }
+
+    ///
+    /// Provide a JSON representation of the datamodel  
+    ///
+    /// The JSON contains the model plain text along with the internal datamodel representation.
+    ///
+    /// Can be usefull to create a data model editor.
+    ///
+    pub async fn data_model(&self) -> std::result::Result<String, Error> {
+        Ok(self.services.database.datamodel().await?)
+    }
+
+    ///
+    /// Checks that a data model update can be applied to the current data model, without applying it.
+    ///
+    /// returns the JSON representation of the resulting datamodel.
+    ///
+    pub async fn preflight_data_model(
+        &self,
+        datamodel: &str,
+    ) -> std::result::Result<String, Error> {
+        Ok(self
+            .services
+            .database
+            .preflight_data_model(datamodel)
+            .await?)
+    }
+
+    ///
+    /// Publishes a data model update in a *Room* as a pending *sys.DataModelProposal*.
+    ///
+    /// Only the room admins are allowed to propose an update. The proposal is checked locally before being published,
+    /// and is synchronized with the room so other peers can check it with *preflight_data_model* before it is confirmed.
+    ///
+    /// Returns the identifier of the proposal.
+    ///
+    pub async fn propose_data_model(
+        &self,
+        room_id: &str,
+        datamodel: &str,
+    ) -> std::result::Result<String, Error> {
+        self.services
+            .database
+            .preflight_data_model(datamodel)
+            .await?;
+        DataModelProposal::create(room_id, datamodel, &self.services.database).await
+    }
+
+    ///
+    /// Lists the data model proposals of a *Room*, most recent first.
+    ///
+    pub async fn data_model_proposals(
+        &self,
+        room_id: &str,
+    ) -> std::result::Result<Vec<DataModelProposal>, Error> {
+        DataModelProposal::list(room_id, &self.services.database).await
+    }
+
+    ///
+    /// Confirms a data model proposal and applies it locally.
+    ///
+    /// Only the room admins are allowed to confirm a proposal.
+    /// The other peers of the room apply the update once the confirmation is synchronized.
+    ///
+    /// returns the JSON representation of the updated datamodel.
+    ///
+    pub async fn confirm_data_model(
+        &self,
+        proposal_id: &str,
+    ) -> std::result::Result<String, Error> {
+        let database = &self.services.database;
+        let proposal = DataModelProposal::get(proposal_id, database)
+            .await?
+            .ok_or(Error::InvalidDataModelProposal(proposal_id.to_string()))?;
+        database.preflight_data_model(&proposal.model).await?;
+        DataModelProposal::confirm(proposal_id, database).await?;
+        DataModelProposal::activate(&proposal.room_id, database).await?;
+        Ok(database.datamodel().await?)
+    }
+
+    ///
+    /// Publishes a complete data model in a *Room* as a *sys.DataModelTemplate*, replacing the previous version of the template.
+    ///
+    /// Only the room admins are allowed to publish a template, and a room provides a single template identifier.
+    /// Peers enabling *Configuration.accept_data_model_templates* apply the template when the room is synchronized,
+    /// allowing generic applications to acquire the data model of the rooms they are invited in.
+    ///
+    /// Returns the identifier of the template node.
+    ///
+    pub async fn publish_data_model_template(
+        &self,
+        room_id: &str,
+        template_id: &str,
+        datamodel: &str,
+    ) -> std::result::Result<String, Error> {
+        self.services
+            .database
+            .preflight_data_model(datamodel)
+            .await?;
+        DataModelTemplate::publish(room_id, template_id, datamodel, &self.services.database).await
+    }
+
+    ///
+    /// The data model template published in a *Room*, if any.
+    ///
+    pub async fn data_model_template(
+        &self,
+        room_id: &str,
+    ) -> std::result::Result<Option<DataModelTemplate>, Error> {
+        DataModelTemplate::get(room_id, &self.services.database).await
+    }
+
+    ///
+    /// Applies the data model template published in a *Room*.
+    ///
+    /// The first applied template defines the template identifier and the signer allowed to update it.
+    /// Fails with *InvalidUpdateTemplate* or *InvalidSigner* if the room template does not match.
+    ///
+    /// returns the JSON representation of the updated datamodel.
+    ///
+    pub async fn accept_data_model_template(
+        &self,
+        room_id: &str,
+    ) -> std::result::Result<String, Error> {
+        let database = &self.services.database;
+        DataModelTemplate::activate(room_id, database).await?;
+        Ok(database.datamodel().await?)
+    }
+
+    ///
+    /// Searches a term in every entity indexed for full text search, to power global search bars.
+    ///
+    /// The term uses the same syntax as the *search()* filter of the queries. The search can be restricted to a list of rooms,
+    /// an empty list searches every room.
+    /// Returns at most 100 hits, the most relevant first, with an excerpt of the text around the searched term.
+    ///
+    /// Like the queries, the search ignores the moderated and soft deleted entities, and the entities whose *sys_readers* excludes this peer.
+    ///
+    pub async fn search_all(
+        &self,
+        term: &str,
+        rooms: Vec<String>,
+    ) -> std::result::Result<Vec<SearchHit>, Error> {
+        self.search_entities(term, rooms, Vec::new()).await
+    }
+
+    ///
+    /// Searches a term in a subset of the entities indexed for full text search, like every entity of a "search in this room" bar.
+    ///
+    /// The hits of the different entities are ranked together, the most relevant first, see *SearchHit::relevance*.
+    /// Empty *rooms* or *entities* lists search every room or every indexed entity, like *search_all()*.
+    /// The moderated, soft deleted and *sys_readers* restricted entities are ignored, like in *search_all()*.
+    /// Fails when an entity does not exists or is not indexed for full text search.
+    ///
+    pub async fn search_entities(
+        &self,
+        term: &str,
+        rooms: Vec<String>,
+        entities: Vec<String>,
+    ) -> std::result::Result<Vec<SearchHit>, Error> {
+        let mut room_ids = Vec::with_capacity(rooms.len());
+        for room in &rooms {
+            room_ids.push(uid_decode(room)?);
+        }
+        Ok(self
+            .services
+            .database
+            .search_all(term, room_ids, entities)
+            .await?)
+    }
+
+    ///
+    /// Stores a file in a *Room* and returns its identifier, to be used as the value of the *File* fields.
+    ///
+    /// Files are not limited by the size of the nodes: they are split in chunks that are synchronised on a dedicated stream
+    /// with the peers of the room, once a node referencing the file has been synchronised.
+    /// Interrupted transfers are resumed during the next synchronisation.
+    ///
+    pub async fn put_file(
+        &self,
+        room_id: &str,
+        content: Vec<u8>,
+    ) -> std::result::Result<String, Error> {
+        let room_id = uid_decode(room_id)?;
+        let id = self.services.database.put_file(room_id, content).await?;
+        Ok(base64_encode(&id))
+    }
+
+    ///
+    /// Returns the content of a file stored in a *File* field,
+    /// None if the file has not been fully received yet.
+    ///
+    pub async fn get_file(&self, id: &str) -> std::result::Result<Option<Vec<u8>>, Error> {
+        validate_file_id(id, "id")?;
+        let id = base64_decode(id.as_bytes())?;
+        //the length has been validated
+        let id = id.try_into().unwrap();
+        Ok(self.services.database.get_file(id).await?)
+    }
+
+    ///
+    /// Estimates, for each entity and day of a *Room*, how many peers hold a full copy of the local data.
+    ///
+    /// The estimation uses the state of each peer during its last synchronisation: it can be outdated if the peer lost its data since.
+    /// Entries with zero *copies* only exist on this device.
+    ///
+    pub async fn availability(
+        &self,
+        room_id: &str,
+    ) -> std::result::Result<Vec<DataAvailability>, Error> {
+        let room_id = uid_decode(room_id)?;
+        Ok(self.services.database.availability(room_id).await?)
+    }
+
+    ///
+    /// Lists the entity days of a *Room* whose content differs from the content of a peer.
+    ///
+    /// The comparison uses the state of the peer during its last synchronisation.
+    /// Used to investigate an *Event::RoomDivergence*.
+    ///
+    pub async fn room_divergence(
+        &self,
+        room_id: &str,
+        verifying_key: Vec<u8>,
+    ) -> std::result::Result<Vec<DayDivergence>, Error> {
+        let room_id = uid_decode(room_id)?;
+        Ok(self
+            .services
+            .database
+            .room_divergence(room_id, verifying_key)
+            .await?)
+    }
+}
+
+struct BlockingRuntime {
+    rt: Option<Runtime>,
+}
+impl BlockingRuntime {
+    pub fn new() -> Self {
+        Self { rt: None }
+    }
+    pub fn rt(&mut self) -> std::result::Result<&Runtime, Error> {
+        if self.rt.is_none() {
+            self.rt = Some(
+                tokio::runtime::Builder::new_multi_thread()
+                    .enable_all()
+                    .build()?,
+            );
+        }
+        Ok(self.rt.as_ref().unwrap())
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref TOKIO_BLOCKING: Arc<Mutex<BlockingRuntime>> =
+    Arc::new(Mutex::new(BlockingRuntime::new()));
+}
+///
+/// The main entry point for the Discret Library, with a blocking API
+/// Provides a blocking API
+///
+#[derive(Clone)]
+pub struct DiscretBlocking {
+    discret: Discret,
+}
+impl DiscretBlocking {
+    /// Starts the Discret engine with the following parameters:
+    ///- datamodel: define the data types that can be used by discret,
+    ///- app_key: a unique identifier for the application that **cannot not** change once the application is in produciton
+    ///- key_material: a master secret that will be used wit the app_key to derive all the secret required by discret
+    ///- data_folder: where data is stored, unused when the configuration storage is Storage::Memory
+    ///- configuration: the configuration stucture
+    pub fn new(
+        datamodel: &str,
+        app_key: &str,
+        key_material: &[u8; 32],
+        data_folder: PathBuf,
+        configuration: Configuration,
+    ) -> std::result::Result<Self, Error> {
+        let discret = TOKIO_BLOCKING.lock().unwrap().rt()?.block_on(Discret::new(
+            datamodel,
+            app_key,
+            key_material,
+            data_folder,
+            configuration,
+        ))?;
+
+        Ok(Self { discret })
+    }
+
+    ///
+    /// Performs a Deletion query and returns the entities that were removed, see *Discret::delete()*
+    ///
+    pub fn delete(
+        &self,
+        d: &str,
+        p: Option<Parameters>,
+    ) -> std::result::Result<Vec<DeletedEntity>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.delete(d, p))
+    }
+
+    ///
+    /// Performs a mutation query and returns the inserted tuple in a JSON String
+    ///
+    pub fn mutate(&self, m: &str, p: Option<Parameters>) -> std::result::Result<String, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.mutate(m, p))
+    }
+
+    ///
+    /// Performs the same mutation query for each parameter set in a single transaction, see *Discret::mutate_batch()*
+    ///
+    pub fn mutate_batch(
+        &self,
+        m: &str,
+        p: Vec<Parameters>,
+    ) -> std::result::Result<Vec<String>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.mutate_batch(m, p))
+    }
+
+    ///
+    /// Performs a mutation query only once per database, see *Discret::seed()*
+    ///
+    pub fn seed(
+        &self,
+        name: &str,
+        m: &str,
+        p: Option<Parameters>,
+    ) -> std::result::Result<bool, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.seed(name, m, p))
+    }
+
+    ///
+    /// Allow to send a stream of mutation.
+    ///
+    /// Usefull for batch insertion as you do have to wait for the mutation to finished before sending another.
+    ///
+    /// The receiver retrieve an internal representation of the mutation query to avoid the performance cost of creating the JSON result, wich is probably unecessary when doing batch insert.
+    /// To get the JSON, call the  MutationQuery.result() method
+    ///
+    pub fn mutation_stream(&self) -> (mpsc::Sender<(String, Option<Parameters>)>, MutateReceiver) {
+        self.discret.mutation_stream()
+    }
+
+    ///
+    /// see *Discret::ordered_mutation_stream()*
+    ///
+    pub fn ordered_mutation_stream(
+        &self,
+    ) -> (
+        mpsc::Sender<(u64, String, Option<Parameters>)>,
+        OrderedMutateReceiver,
+    ) {
+        self.discret.ordered_mutation_stream()
+    }
+
+    ///
+    /// Perform a query to retrieve results from the database.
+    /// returns the result in a JSON object
+    ///
+    pub fn query(&self, q: &str, p: Option<Parameters>) -> std::result::Result<String, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.query(q, p))
+    }
+
+    ///
+    /// Checks that at least one entity matches the filters, see *Discret::exists()*
+    ///
+    pub fn exists(
+        &self,
+        entity: &str,
+        filters: &str,
+        p: Option<Parameters>,
+    ) -> std::result::Result<bool, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.exists(entity, filters, p))
+    }
+
+    ///
+    /// Number of entities matching the filters, see *Discret::count()*
+    ///
+    pub fn count(
+        &self,
+        entity: &str,
+        filters: &str,
+        p: Option<Parameters>,
+    ) -> std::result::Result<u64, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.count(entity, filters, p))
+    }
+
+    ///
+    /// Number of *entity* in a *Room* and the modification date of the most recent one, see *Discret::entity_counter()*
+    ///
+    pub fn entity_counter(
+        &self,
+        room_id: &str,
+        entity: &str,
+    ) -> std::result::Result<EntityCounter, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.entity_counter(room_id, entity))
+    }
+
+    ///
+    /// Subscribe to the results of a query, see *Discret::subscribe_query()*
+    ///
+    /// Use *QuerySubscription::blocking_recv()* to wait for the results.
+    ///
+    pub fn subscribe_query(
+        &self,
+        q: &str,
+        p: Option<Parameters>,
+    ) -> std::result::Result<QuerySubscription, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.subscribe_query(q, p))
+    }
+
+    ///
+    /// Perform a query to retrieve results from the database, see *Discret::query_bytes()*
+    ///
+    pub fn query_bytes(
+        &self,
+        q: &str,
+        p: Option<Parameters>,
+        encoding: Encoding,
+    ) -> std::result::Result<Vec<u8>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.query_bytes(q, p, encoding))
+    }
+
+    ///
+    /// Create an invitation
+    /// - default_room: once the inviation is accepted, the new Peer will be granted access to this room.
+    ///
+    /// The returned byte array have to be sent manually to another peer.
+    ///
+    pub async fn invite(&self, default_room: Option<DefaultRoom>) -> Result<Vec<u8>> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.invite(default_room))
+    }
+
+    ///
+    /// Accept an invitation
+    /// Once an invitation is accepted, the two peers will be able to discover themselves and start exchanging data
+    ///   
+    pub async fn accept_invite(&self, invitation: Vec<u8>) -> std::result::Result<(), Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.accept_invite(invitation))
+    }
+
+    ///
+    /// Create an invitation encrypted with a PIN, see *Discret::invite_with_pin()*
+    ///
+    pub fn invite_with_pin(&self, default_room: Option<DefaultRoom>, pin: &str) -> Result<Vec<u8>> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.invite_with_pin(default_room, pin))
+    }
+
+    ///
+    /// Accept an invitation created by *Discret::invite_with_pin()*
+    ///
+    pub fn accept_invite_with_pin(
+        &self,
+        invitation: Vec<u8>,
+        pin: &str,
+    ) -> std::result::Result<(), Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.accept_invite_with_pin(invitation, pin))
+    }
+
+    ///
+    /// Accepts or rejects the connections of a peer, see *Discret::answer_connection_request()*
+    ///
+    pub fn answer_connection_request(
+        &self,
+        verifying_key: Vec<u8>,
+        accept: bool,
+    ) -> std::result::Result<(), Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.answer_connection_request(verifying_key, accept))
+    }
+
+    ///
+    /// see *Discret::invite_room_members()*
+    ///
+    pub fn invite_room_members(
+        &self,
+        from_room: &str,
+        to_room: &str,
+        authorisation: &str,
+    ) -> std::result::Result<GroupInvitation, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(
+                self.discret
+                    .invite_room_members(from_room, to_room, authorisation),
+            )
+    }
+
+    ///
+    /// Approves the link request of a new device, see *DeviceLinkRequest*.
+    /// - request: the request created by the new device
+    /// - code: the short code displayed by the new device
+    /// - key_material: the key material of this device
+    ///
+    /// Returns the key material and the configuration of this device, encrypted for the new device.
+    /// The returned byte array have to be sent manually to the new device.
+    ///
+    pub fn approve_device_link(
+        &self,
+        request: &[u8],
+        code: &str,
+        key_material: &[u8; 32],
+    ) -> std::result::Result<Vec<u8>, Error> {
+        self.discret
+            .approve_device_link(request, code, key_material)
+    }
+
+    ///
+    /// see *Discret::set_log_level()*
+    ///
+    pub fn set_log_level(&self, subsystem: LogSubsystem, level: LogLevel) {
+        self.discret.set_log_level(subsystem, level)
+    }
+
+    ///
+    /// see *Discret::log_level()*
+    ///
+    pub fn log_level(&self, subsystem: LogSubsystem) -> LogLevel {
+        self.discret.log_level(subsystem)
+    }
+
+    ///
+    /// Write contention statistics per entity, gathered during synchronisation since startup.
+    ///
+    /// A write conflict occurs when a synchronisation overwrites a locally authored node with a version authored by another peer.
+    /// Entities with a high number of conflicts are frequently modified concurrently and might need a different design.
+    ///
+    pub fn contention_stats(&self) -> std::result::Result<Vec<EntityContention>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.contention_stats())
+    }
+
+    ///
+    /// The most recent queries that took longer than the *slow_query_threshold_in_ms* configuration, the most recent first.
+    ///
+    /// At most *slow_query_log_size* queries are kept in memory. Each slow query also triggers an *Event::SlowQuery*.
+    ///
+    pub fn slow_queries(&self) -> std::result::Result<Vec<SlowQuery>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.slow_queries())
+    }
+
+    ///
+    /// Anonymous performance metrics aggregated since the start of Discret or the last reset,
+    /// returns None when the *enable_telemetry* configuration is not set.
+    ///
+    /// The report can be submitted by the application to help prioritizing the performance work on the library.
+    /// Use *reset* to start a new aggregation once the report has been submitted.
+    ///
+    pub fn telemetry(&self, reset: bool) -> std::result::Result<Option<TelemetryReport>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.telemetry(reset))
+    }
+
+    ///
+    /// Salvages the readable data of a corrupted database, after an *Event::DatabaseCorrupted*.
+    ///
+    /// The readable rows are copied in a new database file that replaces the corrupted one the next time *Discret* is started.
+    /// The corrupted file is kept in the data folder with the '.corrupted' extension.
+    ///
+    /// returns the number of recovered rows. Only available for the *Storage::File* storage.
+    ///
+    pub fn attempt_recovery(&self) -> std::result::Result<usize, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.attempt_recovery())
+    }
+
+    ///
+    /// Writes a backup of the database in the *backup_directory* now, without waiting for the next scheduled backup.
+    ///
+    /// The backup is encrypted with the database secret. The rotation defined by the *backup_keep_daily* and *backup_keep_weekly* configuration is applied.
+    ///
+    pub fn backup_now(&self) -> std::result::Result<BackupInfo, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.backup_now())
+    }
+
+    ///
+    /// Returns the state of the backup scheduler: the next backup date, the error of the last attempt and the kept backups.
+    ///
+    pub fn backup_status(&self) -> std::result::Result<BackupStatus, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.backup_status())
+    }
+
+    ///
+    /// see *Discret::export_rooms()*
+    ///
+    pub fn export_rooms(
+        &self,
+        room_ids: Vec<String>,
+        path: PathBuf,
+        pass_phrase: &str,
+    ) -> std::result::Result<(), Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.export_rooms(room_ids, path, pass_phrase))
+    }
+
+    ///
+    /// see *Discret::import_archive()*
+    ///
+    pub fn import_archive(
+        &self,
+        path: PathBuf,
+        pass_phrase: &str,
+        accept_new_rooms: bool,
+    ) -> std::result::Result<ArchiveImport, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(
+                self.discret
+                    .import_archive(path, pass_phrase, accept_new_rooms),
+            )
+    }
+
+    ///
+    /// see *Discret::relocate_data_folder()*
+    ///
+    pub fn relocate_data_folder(&self, new_data_folder: PathBuf) -> std::result::Result<(), Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.relocate_data_folder(new_data_folder))
+    }
+
+    ///
+    /// Checks whether a peer is currently allowed to mutate or delete the nodes of an entity in a *Room*,
+    /// using the same rules as the ones applied when the data is written.
+    /// - MutateSelf: the peer can modify the nodes it created
+    /// - MutateAll: the peer can modify the nodes created by any peer
+    ///
+    /// It allows applications to disable actions that would be rejected instead of relying on failed mutations.
+    /// Returns false if the room is unknown.
+    ///
+    pub fn can(
+        &self,
+        verifying_key: &str,
+        room_id: &str,
+        entity: &str,
+        right: RightType,
+    ) -> std::result::Result<bool, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.can(verifying_key, room_id, entity, right))
+    }
+
+    ///
+    /// see *Discret::preview_room_change()*
+    ///
+    pub fn preview_room_change(
+        &self,
+        room_id: &str,
+        mutation: &str,
+        param_opt: Option<Parameters>,
+    ) -> std::result::Result<RoomChangeImpact, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.preview_room_change(room_id, mutation, param_opt))
+    }
+
+    ///
+    /// Load the application settings of a *Room*.
+    ///
+    /// Settings are key/value pairs stored in the *sys.RoomSettings* entity and synchronized with the room.
+    ///
+    pub fn room_settings(&self, room_id: &str) -> std::result::Result<RoomSettings, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.room_settings(room_id))
+    }
+
+    ///
+    /// Insert or update a *Room* setting.
+    ///
+    /// Only the room admins are allowed to modify the settings.
+    ///
+    pub fn set_room_setting(
+        &self,
+        room_id: &str,
+        key: &str,
+        value: impl Into<serde_json::Value>,
+    ) -> std::result::Result<(), Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.set_room_setting(room_id, key, value))
+    }
+
+    ///
+    /// Hides a node of a *Room* by creating a *sys.Tombstone* moderation record.
+    ///
+    /// Only the room admins are allowed to create tombstones. The tombstone is signed and synchronized like any other room data.
+    /// The node is kept in the database but is excluded from the query results of every peer.
+    /// Deleting the *sys.Tombstone* restores the node.
+    ///
+    /// Returns the identifier of the tombstone.
+    ///
+    pub fn tombstone(
+        &self,
+        room_id: &str,
+        node_id: &str,
+        reason: Option<&str>,
+    ) -> std::result::Result<String, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.tombstone(room_id, node_id, reason))
+    }
+
+    ///
+    /// Creates an automation rule in a *Room*, stored in the *sys.AutomationRule* entity and synchronized with the room.
+    ///
+    /// When a *trigger* entity (like "chat.Message") is inserted in the room, the peer that inserted it applies the *mutation*,
+    /// with the *$id* and *$room_id* parameters set to the identifiers of the inserted entity and of its room.
+    /// A rule is never triggered by its own consequences, which prevents infinite loops.
+    ///
+    /// Every entity of the mutation must define *room_id:$room_id* and only the *$id* entity can be updated:
+    /// a rule cannot mutate another room or a system entity.
+    ///
+    /// Only the room admins are allowed to create rules. Returns the identifier of the rule.
+    ///
+    pub fn create_automation_rule(
+        &self,
+        room_id: &str,
+        trigger: &str,
+        mutation: &str,
+    ) -> std::result::Result<String, Error> {
+        TOKIO_BLOCKING.lock().unwrap().rt()?.block_on(
+            self.discret
+                .create_automation_rule(room_id, trigger, mutation),
+        )
+    }
+
+    ///
+    /// Enables or disables an automation rule.
+    ///
+    pub fn enable_automation_rule(
+        &self,
+        rule_id: &str,
+        enabled: bool,
+    ) -> std::result::Result<(), Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.enable_automation_rule(rule_id, enabled))
+    }
+
+    ///
+    /// Returns the automation rules of a *Room*, including the disabled ones.
+    ///
+    pub fn automation_rules(
+        &self,
+        room_id: &str,
+    ) -> std::result::Result<Vec<AutomationRule>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.automation_rules(room_id))
+    }
+
+    ///
+    /// Creates a *Room* from a [RoomBuilder], which generates the nested *sys.Room* mutation.
+    ///
+    /// Returns the identifiers of the room and of its authorisations, indexed by authorisation name.
+    ///
+    pub fn create_room(&self, room: &RoomBuilder) -> std::result::Result<CreatedRoom, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.create_room(room))
+    }
+
+    ///
+    /// Writes the mutations and deletions of a [TransactionBuilder] in a single database transaction, see *Discret::transaction()*
+    ///
+    pub fn transaction(
+        &self,
+        transaction: &TransactionBuilder,
+    ) -> std::result::Result<Vec<String>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.transaction(transaction))
+    }
+
+    ///
+    /// Returns the clock offset of a peer in milliseconds, estimated during the last connection handshake.
+    ///
+    /// The offset is positive when the peer clock is ahead of the local clock.
+    /// Returns None if the peer has not connected since startup.
+    ///
+    pub fn peer_clock_offset(&self, verifying_key: Vec<u8>) -> Result<Option<i64>> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.peer_clock_offset(verifying_key))
+    }
+
+    ///
+    /// Returns the metadata that a peer defined with the *peer_metadata* configuration, as a JSON object.
+    ///
+    /// The metadata is stored in the sys.Peer of the peer and is updated each time the peer connects.
+    /// Returns None if the peer is unknown or did not define any metadata.
+    ///
+    pub fn peer_metadata(&self, verifying_key: Vec<u8>) -> Result<Option<String>> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.peer_metadata(verifying_key))
+    }
+
+    ///
+    /// Returns the synchronisation statistics of a peer: bytes exchanged, nodes accepted and rejected,
+    /// and the date of the last successful synchronisation of each room.
+    ///
+    /// The statistics are stored in the database and kept across restarts.
+    /// Returns None if the peer never connected.
+    ///
+    pub fn peer_sync_stats(&self, verifying_key: Vec<u8>) -> Result<Option<PeerSyncStats>> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.peer_sync_stats(verifying_key))
+    }
+
+    ///
+    /// Lists the synchronisation incidents of a *Room*, most recent first, see *Discret::sync_incidents()*
+    ///
+    pub fn sync_incidents(&self, room_id: &str) -> std::result::Result<Vec<SyncIncident>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.sync_incidents(room_id))
+    }
+
+    ///
+    /// Deletes the synchronisation incidents of a *Room* and returns the number of deleted incidents.
+    ///
+    pub fn clear_sync_incidents(&self, room_id: &str) -> std::result::Result<usize, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.clear_sync_incidents(room_id))
+    }
+
+    ///
+    /// Returns the handshake latency of the last outbound connection to a peer,
+    /// and whether a session ticket allowed it to use 0-RTT.
+    ///
+    /// Session tickets are only kept in memory: the first connection to a peer after a restart of either peer always performs a full handshake.
+    ///
+    /// Returns None if no outbound connection to this peer has been made since startup.
+    ///
+    pub fn peer_handshake_stats(&self, verifying_key: Vec<u8>) -> Result<Option<HandshakeStats>> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.peer_handshake_stats(verifying_key))
+    }
+
+    ///
+    /// Returns the number of queries received from a peer since startup,
+    /// and how many of them have been delayed by the *remote_query_rate* and *remote_query_concurrency* limits.
+    ///
+    /// Returns None if the peer did not send any query since startup.
+    ///
+    pub fn peer_query_stats(&self, verifying_key: Vec<u8>) -> Result<Option<PeerQueryStats>> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.peer_query_stats(verifying_key))
+    }
+
+    ///
+    /// Notifies Discret that the network of the device has changed, for example when switching from Wi-Fi to a mobile network.
+    ///
+    /// The connections initiated by this device migrate to the new network without restarting their synchronisation.
+    /// The connections initiated by the peers cannot follow the device and are re-established when they time out.
+    ///
+    pub fn network_changed(&self) -> Result<()> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.network_changed())
+    }
+
+    ///
+    /// Subscribes to some entities of a *Room*, see *Discret::subscribe_entities()*
+    ///
+    pub fn subscribe_entities(&self, room_id: &str, entities: Vec<String>) -> Result<()> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.subscribe_entities(room_id, entities))
+    }
+
+    ///
+    /// Lists the meeting tokens announced on the local network and to the beacons,
+    /// with the allowed peer or the default room associated with each token.
+    ///
+    pub fn meeting_tokens(&self) -> Result<Vec<MeetingTokenInfo>> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.meeting_tokens())
+    }
+
+    ///
+    /// Removes the stale meeting tokens from the announce list:
+    /// - allowed peers that are no longer enabled,
+    /// - invites created or received before the 'older_than' date (in milliseconds since the Unix epoch), that were never used.
+    ///
+    /// Returns the number of removed tokens.
+    ///
+    pub fn prune_tokens(&self, older_than: i64) -> Result<usize> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.prune_tokens(older_than))
+    }
+
+    ///
+    /// This is is your Public identity.
+    ///
+    /// It is derived from the provided key_material and app_key.
+    ///
+    /// Every data you create will be signed using the associated signing_key, and  
     /// other peers will use this verifying key to ensure the integrity of the data
     ///
     pub fn verifying_key(&self) -> String {
@@ -577,6 +2451,22 @@ impl DiscretBlocking {
         self.discret.private_room()
     }
 
+    ///
+    /// Returns an additional private room identified by a label, creating it if needed.
+    ///
+    /// Like the *private_room*, it is only synchronized with your devices. The room identifier is derived from the key_material,
+    /// allowing your devices to store data in separately synchronized rooms (settings, drafts, ...).
+    ///
+    /// The room is not stored in the database: every device must call this method with the same label at each start to synchronize the room.
+    ///
+    pub fn private_room_for(&self, label: &str) -> std::result::Result<String, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.private_room_for(label))
+    }
+
     ///
     /// Subscribe for the event queue
     ///
@@ -589,6 +2479,21 @@ impl DiscretBlocking {
             .block_on(self.discret.subscribe_for_events())
     }
 
+    ///
+    /// Subscribe for the event queue.
+    ///
+    /// Unlike *subscribe_for_events*, the receiver returns an *Event::Lagged* when events have been missed,
+    /// and provides a pull based iterator over the buffered events.
+    ///
+    pub fn event_receiver(&self) -> EventReceiver {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()
+            .unwrap()
+            .block_on(self.discret.event_receiver())
+    }
+
     ///
     /// Update the existing data model definition with a new one.  
     ///
@@ -618,4 +2523,221 @@ impl DiscretBlocking {
             .rt()?
             .block_on(self.discret.data_model())
     }
+
+    ///
+    /// Checks that a data model update can be applied to the current data model, without applying it.
+    ///
+    /// returns the JSON representation of the resulting datamodel.
+    ///
+    pub fn preflight_data_model(&self, datamodel: &str) -> std::result::Result<String, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.preflight_data_model(datamodel))
+    }
+
+    ///
+    /// Publishes a data model update in a *Room* as a pending *sys.DataModelProposal*.
+    ///
+    /// Only the room admins are allowed to propose an update. The proposal is checked locally before being published,
+    /// and is synchronized with the room so other peers can check it with *preflight_data_model* before it is confirmed.
+    ///
+    /// Returns the identifier of the proposal.
+    ///
+    pub fn propose_data_model(
+        &self,
+        room_id: &str,
+        datamodel: &str,
+    ) -> std::result::Result<String, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.propose_data_model(room_id, datamodel))
+    }
+
+    ///
+    /// Lists the data model proposals of a *Room*, most recent first.
+    ///
+    pub fn data_model_proposals(
+        &self,
+        room_id: &str,
+    ) -> std::result::Result<Vec<DataModelProposal>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.data_model_proposals(room_id))
+    }
+
+    ///
+    /// Confirms a data model proposal and applies it locally.
+    ///
+    /// Only the room admins are allowed to confirm a proposal.
+    /// The other peers of the room apply the update once the confirmation is synchronized.
+    ///
+    /// returns the JSON representation of the updated datamodel.
+    ///
+    pub fn confirm_data_model(&self, proposal_id: &str) -> std::result::Result<String, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.confirm_data_model(proposal_id))
+    }
+
+    ///
+    /// Publishes a complete data model in a *Room* as a *sys.DataModelTemplate*, replacing the previous version of the template.
+    ///
+    /// Only the room admins are allowed to publish a template, and a room provides a single template identifier.
+    /// Peers enabling *Configuration.accept_data_model_templates* apply the template when the room is synchronized,
+    /// allowing generic applications to acquire the data model of the rooms they are invited in.
+    ///
+    /// Returns the identifier of the template node.
+    ///
+    pub fn publish_data_model_template(
+        &self,
+        room_id: &str,
+        template_id: &str,
+        datamodel: &str,
+    ) -> std::result::Result<String, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(
+                self.discret
+                    .publish_data_model_template(room_id, template_id, datamodel),
+            )
+    }
+
+    ///
+    /// The data model template published in a *Room*, if any.
+    ///
+    pub fn data_model_template(
+        &self,
+        room_id: &str,
+    ) -> std::result::Result<Option<DataModelTemplate>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.data_model_template(room_id))
+    }
+
+    ///
+    /// Applies the data model template published in a *Room*.
+    ///
+    /// The first applied template defines the template identifier and the signer allowed to update it.
+    /// Fails with *InvalidUpdateTemplate* or *InvalidSigner* if the room template does not match.
+    ///
+    /// returns the JSON representation of the updated datamodel.
+    ///
+    pub fn accept_data_model_template(&self, room_id: &str) -> std::result::Result<String, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.accept_data_model_template(room_id))
+    }
+
+    ///
+    /// Searches a term in every entity indexed for full text search, see *Discret::search_all()*
+    ///
+    pub fn search_all(
+        &self,
+        term: &str,
+        rooms: Vec<String>,
+    ) -> std::result::Result<Vec<SearchHit>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.search_all(term, rooms))
+    }
+
+    ///
+    /// Searches a term in a subset of the entities indexed for full text search, see *Discret::search_entities()*
+    ///
+    pub fn search_entities(
+        &self,
+        term: &str,
+        rooms: Vec<String>,
+        entities: Vec<String>,
+    ) -> std::result::Result<Vec<SearchHit>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.search_entities(term, rooms, entities))
+    }
+
+    ///
+    /// Stores a file in a *Room* and returns its identifier, see *Discret::put_file()*
+    ///
+    pub fn put_file(&self, room_id: &str, content: Vec<u8>) -> std::result::Result<String, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.put_file(room_id, content))
+    }
+
+    ///
+    /// Returns the content of a file stored in a *File* field, see *Discret::get_file()*
+    ///
+    pub fn get_file(&self, id: &str) -> std::result::Result<Option<Vec<u8>>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.get_file(id))
+    }
+
+    ///
+    /// Estimates, for each entity and day of a *Room*, how many peers hold a full copy of the local data.
+    ///
+    /// The estimation uses the state of each peer during its last synchronisation: it can be outdated if the peer lost its data since.
+    /// Entries with zero *copies* only exist on this device.
+    ///
+    pub fn availability(&self, room_id: &str) -> std::result::Result<Vec<DataAvailability>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.availability(room_id))
+    }
+
+    ///
+    /// Lists the entity days of a *Room* whose content differs from the content of a peer.
+    ///
+    /// The comparison uses the state of the peer during its last synchronisation.
+    /// Used to investigate an *Event::RoomDivergence*.
+    ///
+    pub fn room_divergence(
+        &self,
+        room_id: &str,
+        verifying_key: Vec<u8>,
+    ) -> std::result::Result<Vec<DayDivergence>, Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.room_divergence(room_id, verifying_key))
+    }
+
+    ///
+    /// Stops the Discret engine: the peer connections are closed, the pending writes are flushed to the database
+    /// and the services stop processing messages.
+    ///
+    /// Every following call on this instance, or any of its clones, returns an error.
+    ///
+    pub fn shutdown(&self) -> std::result::Result<(), Error> {
+        TOKIO_BLOCKING
+            .lock()
+            .unwrap()
+            .rt()?
+            .block_on(self.discret.shutdown())
+    }
 }
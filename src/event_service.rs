@@ -1,10 +1,13 @@
 use std::sync::Arc;
 
-use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::sync::{
+    broadcast::{self, error::RecvError, error::TryRecvError},
+    mpsc, oneshot,
+};
 
 use crate::{
     base64_encode,
-    database::{room::Room, DataModification},
+    database::{query::SlowQuery, room::Room, DataModification},
     security::Uid,
 };
 
@@ -14,9 +17,20 @@ pub enum EventServiceMessage {
     RoomModified(Room),
     PeerConnected(Vec<u8>, i64, Uid),
     PeerDisconnected(Vec<u8>, i64, Uid),
+    ClockSkew(Vec<u8>, i64),
+    WriteContention(String, u64),
+    SlowQuery(SlowQuery),
+    DatabaseCorrupted(String),
     RoomSynchronized(Uid),
+    LocalOnlyData(Uid, usize),
+    RoomDivergence(Uid, Vec<u8>, usize),
+    PeerThrottled(Vec<u8>, u64),
+    FutureDatedData(Uid, Vec<u8>, usize),
+    RoomJoined(Uid, Vec<u8>),
+    RoomLeft(Uid, Vec<u8>),
     PendingPeer(),
     PendingHardware(),
+    ConnectionRequest(Vec<u8>),
 }
 
 ///
@@ -48,15 +62,154 @@ pub enum Event {
     /// - **connection_id**: the unique identifier of the connection
     PeerDisconnected(Vec<u8>, i64, String),
 
+    /// This event is triggered when the clock of a connected peer differs from the local clock by more than the *clock_skew_warning_in_ms* configuration.
+    /// - **verifying_key**: the peer verifying key,
+    /// - **offset**: the estimated offset in milliseconds, positive when the peer clock is ahead of the local clock
+    ClockSkew(Vec<u8>, i64),
+
+    /// This event is triggered when the number of write conflicts of an entity reaches a multiple of the *write_conflict_warning_threshold* configuration.
+    /// - **entity**: the entity name,
+    /// - **conflicts**: the number of conflicts since startup
+    ///
+    /// A write conflict occurs when a synchronisation overwrites a locally authored node with a version authored by another peer.
+    WriteContention(String, u64),
+
+    /// This event is triggered when a query takes longer than the *slow_query_threshold_in_ms* configuration.
+    /// - **query_hash**: base64 encoded hash of the query text,
+    /// - **duration**: the execution time in milliseconds,
+    /// - **rows**: the number of rows returned
+    ///
+    /// The most recent slow queries are also available with *Discret::slow_queries()*.
+    SlowQuery {
+        query_hash: String,
+        duration: u64,
+        rows: usize,
+    },
+
+    /// This event is triggered when a corruption of the database file is detected.
+    /// - **error**: the SQLite error message
+    ///
+    /// The database is switched to read-only mode: queries still work for the readable data but every write is rejected.
+    /// *Discret::attempt_recovery()* salvages the readable data in a new database.
+    DatabaseCorrupted(String),
+
     /// This event is triggered when a *Room* has been synchronized.
     /// - **room_id**: the *Room* identifier
     RoomSynchronized(String),
 
+    /// This event is triggered after a *Room* synchronisation when some of the local data is not held by any known peer.
+    /// - **room_id**: the *Room* identifier
+    /// - **days**: the number of entity days that only exists on this device
+    ///
+    /// *Discret::availability()* provides the details.
+    LocalOnlyData(String, usize),
+
+    /// This event is triggered after a *Room* synchronisation when the room content still differs from the content of the remote peer.
+    /// - **room_id**: the *Room* identifier
+    /// - **verifying_key**: the peer verifying key
+    /// - **days**: the number of entity days that differ
+    ///
+    /// Divergences caused by concurrent modifications disappear once the peer has synchronised with this device.
+    /// A divergence that persists reveals a synchronisation bug or a tampered database.
+    /// *Discret::room_divergence()* lists the entity days that differ.
+    RoomDivergence(String, Vec<u8>, usize),
+
+    /// This event is triggered when a remote peer starts exceeding the *remote_query_rate* or *remote_query_concurrency* configuration.
+    /// - **verifying_key**: the peer verifying key
+    /// - **throttled**: the number of throttled queries of this peer since startup
+    ///
+    /// The queries of the peer are delayed until it slows down. *Discret::peer_query_stats()* provides the counters.
+    PeerThrottled(Vec<u8>, u64),
+
+    /// This event is triggered when a synchronisation receives data dated too far in the future.
+    /// - **room_id**: the *Room* identifier
+    /// - **verifying_key**: the verifying key of the peer that signed the data
+    /// - **rejected**: the number of rejected nodes and edges
+    ///
+    /// Such data would win every conflict against the legitimate modifications, it is rejected until the local clock reaches its date.
+    /// The tolerance is defined by the *future_date_tolerance_in_ms* configuration.
+    FutureDatedData(String, Vec<u8>, usize),
+
+    /// This event is triggered when a synchronised *Room* definition adds this device to the *Room*.
+    /// - **room_id**: the *Room* identifier
+    /// - **by**: the verifying key of the peer that added this device
+    ///
+    /// **by** is your own verifying key when the change was made on another of your devices.
+    RoomJoined(String, Vec<u8>),
+
+    /// This event is triggered when a synchronised *Room* definition removes or disables this device in the *Room*.
+    /// - **room_id**: the *Room* identifier
+    /// - **by**: the verifying key of the peer that removed this device
+    RoomLeft(String, Vec<u8>),
+
     /// This event is triggered when a new peer is found when synchronising a **Room**.
     PendingPeer(),
 
     /// This event is triggered when a new device is detected.
     PendingHardware(),
+
+    /// This event is triggered when a peer tries to connect and the *connection_policy* configuration is *ConnectionPolicy::Prompt*.
+    /// - **verifying_key**: the peer verifying key
+    ///
+    /// The connection waits for *Discret::answer_connection_request()*.
+    ConnectionRequest(Vec<u8>),
+
+    /// This event is only sent by the *EventReceiver* when the subscriber was too slow to consume the events.
+    /// - **missed**: the number of events that have been dropped
+    ///
+    /// The subscriber should resynchronise its state, for example by querying the database.
+    Lagged(u64),
+}
+
+///
+/// Event subscription that reports missed events.
+///
+/// Events are buffered up to the *event_buffer_size* configuration.
+/// When the subscriber is too slow, the oldest events are dropped and an *Event::Lagged* is returned instead.
+///
+pub struct EventReceiver {
+    receiver: broadcast::Receiver<Event>,
+}
+impl EventReceiver {
+    ///
+    /// Wait for the next event. Returns None when Discret is stopped.
+    ///
+    pub async fn recv(&mut self) -> Option<Event> {
+        match self.receiver.recv().await {
+            Ok(event) => Some(event),
+            Err(RecvError::Lagged(missed)) => Some(Event::Lagged(missed)),
+            Err(RecvError::Closed) => None,
+        }
+    }
+
+    ///
+    /// Blocking version of recv(). Cannot be used in an asynchronous context.
+    ///
+    pub fn blocking_recv(&mut self) -> Option<Event> {
+        match self.receiver.blocking_recv() {
+            Ok(event) => Some(event),
+            Err(RecvError::Lagged(missed)) => Some(Event::Lagged(missed)),
+            Err(RecvError::Closed) => None,
+        }
+    }
+
+    ///
+    /// Returns the next buffered event without waiting.
+    ///
+    pub fn try_recv(&mut self) -> Option<Event> {
+        match self.receiver.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Lagged(missed)) => Some(Event::Lagged(missed)),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => None,
+        }
+    }
+
+    ///
+    /// Pull based iterator over the buffered events. The iteration stops when there is no more buffered events.
+    ///
+    pub fn try_iter(&mut self) -> impl Iterator<Item = Event> + '_ {
+        std::iter::from_fn(|| self.try_recv())
+    }
 }
 
 #[derive(Clone)]
@@ -64,10 +217,16 @@ pub struct EventService {
     pub sender: mpsc::Sender<EventServiceMessage>,
 }
 impl EventService {
+    #[cfg(test)]
     pub fn new() -> Self {
+        Self::with_buffer_size(16)
+    }
+
+    pub fn with_buffer_size(buffer_size: usize) -> Self {
         let (sender, mut receiver) = mpsc::channel(100);
 
-        let (broadcast, _) = broadcast::channel(16);
+        //a broadcast channel cannot be empty
+        let (broadcast, _) = broadcast::channel(buffer_size.max(1));
 
         tokio::spawn(async move {
             while let Some(msg) = receiver.recv().await {
@@ -95,15 +254,60 @@ impl EventService {
                             base64_encode(&connection_id),
                         ));
                     }
+                    EventServiceMessage::ClockSkew(verifying_key, offset) => {
+                        let _ = broadcast.send(Event::ClockSkew(verifying_key, offset));
+                    }
+                    EventServiceMessage::WriteContention(entity, conflicts) => {
+                        let _ = broadcast.send(Event::WriteContention(entity, conflicts));
+                    }
+                    EventServiceMessage::SlowQuery(query) => {
+                        let _ = broadcast.send(Event::SlowQuery {
+                            query_hash: query.query_hash,
+                            duration: query.duration,
+                            rows: query.rows,
+                        });
+                    }
+                    EventServiceMessage::DatabaseCorrupted(error) => {
+                        let _ = broadcast.send(Event::DatabaseCorrupted(error));
+                    }
                     EventServiceMessage::RoomSynchronized(room) => {
                         let _ = broadcast.send(Event::RoomSynchronized(base64_encode(&room)));
                     }
+                    EventServiceMessage::LocalOnlyData(room, days) => {
+                        let _ = broadcast.send(Event::LocalOnlyData(base64_encode(&room), days));
+                    }
+                    EventServiceMessage::RoomDivergence(room, verifying_key, days) => {
+                        let _ = broadcast.send(Event::RoomDivergence(
+                            base64_encode(&room),
+                            verifying_key,
+                            days,
+                        ));
+                    }
+                    EventServiceMessage::PeerThrottled(verifying_key, throttled) => {
+                        let _ = broadcast.send(Event::PeerThrottled(verifying_key, throttled));
+                    }
+                    EventServiceMessage::FutureDatedData(room, verifying_key, rejected) => {
+                        let _ = broadcast.send(Event::FutureDatedData(
+                            base64_encode(&room),
+                            verifying_key,
+                            rejected,
+                        ));
+                    }
+                    EventServiceMessage::RoomJoined(room, by) => {
+                        let _ = broadcast.send(Event::RoomJoined(base64_encode(&room), by));
+                    }
+                    EventServiceMessage::RoomLeft(room, by) => {
+                        let _ = broadcast.send(Event::RoomLeft(base64_encode(&room), by));
+                    }
                     EventServiceMessage::PendingPeer() => {
                         let _ = broadcast.send(Event::PendingPeer());
                     }
                     EventServiceMessage::PendingHardware() => {
                         let _ = broadcast.send(Event::PendingHardware());
                     }
+                    EventServiceMessage::ConnectionRequest(verifying_key) => {
+                        let _ = broadcast.send(Event::ConnectionRequest(verifying_key));
+                    }
                 };
             }
         });
@@ -121,7 +325,58 @@ impl EventService {
         receiver.await.unwrap()
     }
 
+    pub async fn receiver(&self) -> EventReceiver {
+        EventReceiver {
+            receiver: self.subcribe().await,
+        }
+    }
+
     pub async fn notify(&self, msg: EventServiceMessage) {
         let _ = self.sender.send(msg).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn lagged_receiver() {
+        let event_service = EventService::with_buffer_size(2);
+        let mut receiver = event_service.receiver().await;
+
+        for _ in 0..5 {
+            event_service
+                .notify(EventServiceMessage::PendingPeer())
+                .await;
+        }
+        //messages are processed in order, every events are sent when the subscription is returned
+        let _ = event_service.subcribe().await;
+
+        let events: Vec<Event> = receiver.try_iter().collect();
+        assert_eq!(3, events.len());
+        assert!(matches!(events[0], Event::Lagged(3)));
+        assert!(matches!(events[1], Event::PendingPeer()));
+        assert!(matches!(events[2], Event::PendingPeer()));
+
+        assert!(receiver.try_recv().is_none());
+
+        event_service
+            .notify(EventServiceMessage::PendingHardware())
+            .await;
+        let event = receiver.recv().await.unwrap();
+        assert!(matches!(event, Event::PendingHardware()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn empty_buffer_size() {
+        let event_service = EventService::with_buffer_size(0);
+        let mut receiver = event_service.subcribe().await;
+
+        event_service
+            .notify(EventServiceMessage::PendingPeer())
+            .await;
+        let event = receiver.recv().await.unwrap();
+        assert!(matches!(event, Event::PendingPeer()));
+    }
+}
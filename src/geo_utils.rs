@@ -0,0 +1,133 @@
+use rusqlite::{functions::FunctionFlags, Connection};
+
+//mean earth radius in meters
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+///
+/// Parse a location formatted as "latitude,longitude" (ex: "48.8566,2.3522")
+///
+/// the latitude must be in the [-90, 90] range and the longitude in the [-180, 180] range
+///
+pub fn parse_location(value: &str) -> Option<(f64, f64)> {
+    let (lat, lon) = value.split_once(',')?;
+    let lat: f64 = lat.trim().parse().ok()?;
+    let lon: f64 = lon.trim().parse().ok()?;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return None;
+    }
+    Some((lat, lon))
+}
+
+///
+/// Great circle distance in meters between two locations, using the haversine formula
+///
+pub fn geo_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let d_lat = (lat2 - lat1).to_radians();
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
+
+///
+/// Bounding box that contains every location within the radius in meters
+///
+/// returns (min_lat, min_lon, max_lat, max_lon)
+///
+/// the box covers every longitude when it crosses a pole or the antimeridian
+///
+pub fn geo_bounding_box(lat: f64, lon: f64, radius: f64) -> (f64, f64, f64, f64) {
+    let angle = radius / EARTH_RADIUS;
+    let d_lat = angle.to_degrees();
+    let min_lat = lat - d_lat;
+    let max_lat = lat + d_lat;
+    let sin_lon = angle.sin() / lat.to_radians().cos();
+    if min_lat <= -90.0 || max_lat >= 90.0 || sin_lon >= 1.0 {
+        return (min_lat.max(-90.0), -180.0, max_lat.min(90.0), 180.0);
+    }
+    let d_lon = sin_lon.asin().to_degrees();
+    let min_lon = lon - d_lon;
+    let max_lon = lon + d_lon;
+    if min_lon < -180.0 || max_lon > 180.0 {
+        return (min_lat, -180.0, max_lat, 180.0);
+    }
+    (min_lat, min_lon, max_lat, max_lon)
+}
+
+///
+/// Add the functions used by the Geo filters
+///
+/// geo_distance(lat1, lon1, lat2, lon2): distance in meters
+/// geo_box(lat, lon, radius, corner): corner of the bounding box, 0:min_lat 1:min_lon 2:max_lat 3:max_lon
+///
+pub fn add_geo_functions(db: &Connection) -> rusqlite::Result<()> {
+    db.create_scalar_function(
+        "geo_distance",
+        4,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| {
+            let lat1: Option<f64> = ctx.get(0)?;
+            let lon1: Option<f64> = ctx.get(1)?;
+            let lat2: Option<f64> = ctx.get(2)?;
+            let lon2: Option<f64> = ctx.get(3)?;
+            Ok(match (lat1, lon1, lat2, lon2) {
+                (Some(lat1), Some(lon1), Some(lat2), Some(lon2)) => {
+                    Some(geo_distance(lat1, lon1, lat2, lon2))
+                }
+                _ => None,
+            })
+        },
+    )?;
+
+    db.create_scalar_function(
+        "geo_box",
+        4,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| {
+            let lat: f64 = ctx.get(0)?;
+            let lon: f64 = ctx.get(1)?;
+            let radius: f64 = ctx.get(2)?;
+            let corner: i64 = ctx.get(3)?;
+            let bbox = geo_bounding_box(lat, lon, radius);
+            Ok(match corner {
+                0 => bbox.0,
+                1 => bbox.1,
+                2 => bbox.2,
+                _ => bbox.3,
+            })
+        },
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn location() {
+        assert_eq!(Some((48.8566, 2.3522)), parse_location("48.8566, 2.3522"));
+        assert_eq!(Some((-90.0, 180.0)), parse_location("-90,180"));
+        assert_eq!(None, parse_location("91,0"));
+        assert_eq!(None, parse_location("0,-181"));
+        assert_eq!(None, parse_location("48.8566"));
+        assert_eq!(None, parse_location("a,b"));
+    }
+
+    #[test]
+    fn distance() {
+        //Paris - London
+        let d = geo_distance(48.8566, 2.3522, 51.5074, -0.1278);
+        assert!((d - 343_500.0).abs() < 1000.0);
+        assert_eq!(0.0, geo_distance(10.0, 10.0, 10.0, 10.0));
+
+        let (min_lat, min_lon, max_lat, max_lon) = geo_bounding_box(48.8566, 2.3522, 10_000.0);
+        assert!(min_lat < 48.8566 && max_lat > 48.8566);
+        assert!(min_lon < 2.3522 && max_lon > 2.3522);
+        assert!(geo_distance(48.8566, 2.3522, max_lat, 2.3522) >= 9_999.0);
+        assert!(geo_distance(48.8566, 2.3522, 48.8566, max_lon) >= 9_999.0);
+
+        let (_, min_lon, _, max_lon) = geo_bounding_box(0.0, 179.99, 10_000.0);
+        assert_eq!((-180.0, 180.0), (min_lon, max_lon));
+    }
+}
@@ -152,7 +152,7 @@
 //! As data lives on your devices, Discret should only be used for applications with data generated by "real person", with hundreds of peers at most.
 //! It is not suited for large scale applications and communities with thousands of peoples.
 //!
-//! It currently only supports text data but supports for file synchronization is planned.
+//! Binary content is stored in *File* fields with *Discret::put_file()*, files are synchronised by chunks and can be much larger than the nodes.
 //!
 //! Connection over the internet is not 100% guaranteed to work, because certain types of enterprise firewalls will block the connection attempts.
 //!
@@ -175,33 +175,73 @@
 mod configuration;
 mod database;
 mod date_utils;
+mod device_link;
 mod discret;
 mod event_service;
+mod geo_utils;
+mod log_level;
 mod network;
+mod observer;
 mod peer_connection_service;
 mod security;
 mod signature_verification_service;
 mod synchronisation;
 
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use thiserror::Error;
 
 type Result<T> = std::result::Result<T, Error>;
 
 pub use crate::{
-    configuration::{BeaconConfig, Configuration},
+    configuration::{BeaconConfig, Configuration, ConnectionPolicy, Storage},
     database::{
+        authorisation_service::EntityContention,
+        backup::{BackupInfo, BackupStatus},
+        custom_function::register_function,
+        deletion::DeletedEntity,
+        entity_counter::EntityCounter,
+        json_stream::ArrayStream,
+        mutation_batch::{MutationBatch, MutationBatchSummary},
+        query::SlowQuery,
+        query_subscription::QuerySubscription,
         query_language::parameter::{Parameters, ParametersAdd},
-        room::Room,
-        system_entities::DefaultRoom,
-        DataModification, ResultParser,
+        replication::{DataAvailability, DayDivergence, PeerSyncStats},
+        room::{RightType, Room},
+        room_builder::{CreatedRoom, RoomBuilder},
+        room_impact::{AffectedData, MemberRightChange, RoomChangeImpact, RoomRight},
+        search::SearchHit,
+        system_entities::{
+            AutomationRule, DataModelProposal, DataModelTemplate, DefaultRoom, GroupInvitation,
+            RoomSettings, SyncIncident, LOG_PRIVACY_SETTING,
+        },
+        telemetry::{DurationBuckets, TelemetryReport},
+        transaction::TransactionBuilder,
+        DataModification, Encoding, ResultParser,
+    },
+    date_utils::{
+        datetime_range, format_date, format_datetime, parse_date, parse_datetime, truncate_datetime,
+        TimeUnit,
     },
+    device_link::{DeviceLinkRequest, LinkedDevice},
     discret::{database_exists, zero_uid, Discret, DiscretBlocking},
-    event_service::Event,
-    network::beacon::Beacon,
+    event_service::{Event, EventReceiver},
+    geo_utils::{geo_bounding_box, geo_distance, parse_location},
+    log_level::{LogLevel, LogSubsystem},
+    network::{
+        beacon::{
+            application_id, ApplicationLimits, ApplicationMetrics, Beacon, BeaconEvent,
+            BeaconHandle, BeaconHook, BeaconMetrics, BeaconServerConfig,
+        },
+        HandshakeStats, MeetingTokenInfo, MeetingTokenKind,
+    },
+    observer::DiscretObserver,
     security::{
-        base64_decode, base64_encode, derive_pass_phrase, generate_x509_certificate, hash,
-        random_domain_name,
+        base64_decode, base64_encode, derive_pass_phrase, derive_pass_phrase_with,
+        generate_x509_certificate, hash, new_pin, pin_entropy, random_domain_name,
+        PassPhraseParams, MIN_PIN_ENTROPY,
     },
+    synchronisation::{archive::ArchiveImport, peer_outbound_service::PeerQueryStats},
 };
 
 ///
@@ -292,6 +332,53 @@ pub enum Error {
 
     #[error("{0}")]
     Unsupported(String),
+
+    #[error("Unknown data model proposal {0}")]
+    InvalidDataModelProposal(String),
+
+    #[error("Invalid room builder: {0}")]
+    InvalidRoomBuilder(String),
+
+    #[error("{0}")]
+    InvalidFunction(String),
+
+    #[error("Invalid archive: {0}")]
+    InvalidArchive(String),
+}
+
+static ERROR_PRIVACY_MODE: AtomicBool = AtomicBool::new(false);
+
+///
+/// Enables the redaction of the identifiers in the public error messages, see *Configuration::error_privacy_mode*.
+///
+/// The mode is shared by every Discret instance of the process.
+///
+fn set_error_privacy_mode(enabled: bool) {
+    ERROR_PRIVACY_MODE.store(enabled, Ordering::Relaxed);
+}
+
+impl Error {
+    ///
+    /// The error message that can be displayed to users or written in logs.
+    ///
+    /// When the privacy mode is enabled, the base64 identifiers (room ids, node ids, keys, hashes,...) are replaced with '<redacted>'.
+    /// Otherwise, it is the same as *to_string()*.
+    ///
+    pub fn public_message(&self) -> String {
+        let message = self.to_string();
+        if ERROR_PRIVACY_MODE.load(Ordering::Relaxed) {
+            security::redact_identifiers(&message)
+        } else {
+            message
+        }
+    }
+
+    ///
+    /// The base64 identifiers embedded in the error message, available regardless of the privacy mode.
+    ///
+    pub fn identifiers(&self) -> Vec<String> {
+        security::find_identifiers(&self.to_string())
+    }
 }
 
 #[cfg(test)]
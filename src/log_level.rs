@@ -0,0 +1,159 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+///
+/// Internal subsystems whose log verbosity can be changed with *Discret::set_log_level()*
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogSubsystem {
+    /// peer discovery, connections and beacons
+    Network,
+    /// database reads, writes and maintenance
+    Database,
+    /// room synchronisation with the remote peers
+    Sync,
+    /// validation of the room rights
+    Auth,
+}
+
+///
+/// Log verbosity, from the least to the most verbose
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+impl LogLevel {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => LogLevel::Off,
+            1 => LogLevel::Error,
+            2 => LogLevel::Warn,
+            3 => LogLevel::Info,
+            4 => LogLevel::Debug,
+            _ => LogLevel::Trace,
+        }
+    }
+}
+
+//
+// the errors and the informations are logged by default, like before the levels existed
+//
+static LEVELS: [AtomicU8; 4] = [
+    AtomicU8::new(LogLevel::Info as u8),
+    AtomicU8::new(LogLevel::Info as u8),
+    AtomicU8::new(LogLevel::Info as u8),
+    AtomicU8::new(LogLevel::Info as u8),
+];
+
+///
+/// Changes the verbosity of a subsystem, see *Discret::set_log_level()*
+///
+/// The levels are shared by every Discret instance of the process.
+///
+pub fn set_log_level(subsystem: LogSubsystem, level: LogLevel) {
+    LEVELS[subsystem as usize].store(level as u8, Ordering::Relaxed);
+
+    //the log crate discards the records above its global maximum before they reach the logger
+    #[cfg(feature = "log")]
+    {
+        let filter = match level {
+            LogLevel::Off => log::LevelFilter::Off,
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        };
+        if filter > log::max_level() {
+            log::set_max_level(filter);
+        }
+    }
+}
+
+///
+/// current verbosity of a subsystem
+///
+pub fn log_level(subsystem: LogSubsystem) -> LogLevel {
+    LogLevel::from_u8(LEVELS[subsystem as usize].load(Ordering::Relaxed))
+}
+
+///
+/// true if a record of this level is logged for the subsystem
+///
+#[cfg(feature = "log")]
+pub fn enabled(subsystem: LogSubsystem, level: log::Level) -> bool {
+    level as u8 <= LEVELS[subsystem as usize].load(Ordering::Relaxed)
+}
+
+//
+// Replacements of the log crate macros filtered by the level of the subsystem.
+// The subsystem is defined by the LOG_SUBSYSTEM constant of the calling module.
+//
+#[cfg(feature = "log")]
+macro_rules! error {
+    ($($arg:tt)+) => {
+        if $crate::log_level::enabled(LOG_SUBSYSTEM, ::log::Level::Error) {
+            ::log::error!($($arg)+)
+        }
+    };
+}
+#[cfg(feature = "log")]
+pub(crate) use error;
+
+//named warning! since 'warn' is a builtin attribute
+#[cfg(feature = "log")]
+macro_rules! warning {
+    ($($arg:tt)+) => {
+        if $crate::log_level::enabled(LOG_SUBSYSTEM, ::log::Level::Warn) {
+            ::log::warn!($($arg)+)
+        }
+    };
+}
+#[cfg(feature = "log")]
+pub(crate) use warning;
+
+#[cfg(feature = "log")]
+macro_rules! info {
+    ($($arg:tt)+) => {
+        if $crate::log_level::enabled(LOG_SUBSYSTEM, ::log::Level::Info) {
+            ::log::info!($($arg)+)
+        }
+    };
+}
+#[cfg(feature = "log")]
+pub(crate) use info;
+
+#[cfg(feature = "log")]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        if $crate::log_level::enabled(LOG_SUBSYSTEM, ::log::Level::Debug) {
+            ::log::debug!($($arg)+)
+        }
+    };
+}
+#[cfg(feature = "log")]
+pub(crate) use debug;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subsystem_levels() {
+        assert_eq!(LogLevel::Info, log_level(LogSubsystem::Auth));
+        set_log_level(LogSubsystem::Auth, LogLevel::Trace);
+        assert_eq!(LogLevel::Trace, log_level(LogSubsystem::Auth));
+        assert!(enabled(LogSubsystem::Auth, log::Level::Debug));
+        assert!(log::max_level() >= log::LevelFilter::Trace);
+        assert!(!enabled(LogSubsystem::Network, log::Level::Debug));
+
+        set_log_level(LogSubsystem::Auth, LogLevel::Off);
+        assert!(!enabled(LogSubsystem::Auth, log::Level::Error));
+        set_log_level(LogSubsystem::Auth, LogLevel::Info);
+    }
+}
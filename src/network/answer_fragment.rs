@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{security::hash, synchronisation::Answer};
+
+use super::Error;
+
+///
+/// bytes reserved for the frame envelope when computing the fragment payload size
+///
+static FRAME_OVERHEAD: usize = 128;
+
+///
+/// an oversized answer cannot be split in more fragments
+///
+static MAX_FRAGMENTS: u32 = 4096;
+
+///
+/// serialisation overhead of an answer in a batch: id, flags and payload length
+///
+static ANSWER_OVERHEAD: usize = 32;
+
+///
+/// Messages exchanged on the answer stream
+///
+/// Answers that fits in the stream buffer are sent in batches.
+/// Larger answers are split in fragments that are reassembled by the receiver
+///
+#[derive(Serialize, Deserialize)]
+pub enum AnswerFrame {
+    Batch(Vec<Answer>),
+    Fragment(AnswerFragment),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AnswerFragment {
+    pub id: u64,
+    pub success: bool,
+    pub complete: bool,
+    pub index: u32,
+    pub count: u32,
+    pub checksum: [u8; 32],
+    pub data: Vec<u8>,
+}
+
+///
+/// converts a batch of answers into frames that fits in max_buffer_size
+///
+/// answers order is preserved
+///
+pub fn split_answers(answers: Vec<Answer>, max_buffer_size: usize) -> Vec<AnswerFrame> {
+    let fragment_size = max_buffer_size.saturating_sub(FRAME_OVERHEAD).max(1);
+    let mut frames = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_size = 0;
+    for answer in answers {
+        let size = answer.serialized.len() + ANSWER_OVERHEAD;
+        if size <= fragment_size && batch_size + size <= fragment_size {
+            batch_size += size;
+            batch.push(answer);
+            continue;
+        }
+        if !batch.is_empty() {
+            frames.push(AnswerFrame::Batch(std::mem::take(&mut batch)));
+            batch_size = 0;
+        }
+        if size <= fragment_size {
+            batch_size = size;
+            batch.push(answer);
+            continue;
+        }
+        let count = answer.serialized.len().div_ceil(fragment_size) as u32;
+        for (index, data) in answer.serialized.chunks(fragment_size).enumerate() {
+            frames.push(AnswerFrame::Fragment(AnswerFragment {
+                id: answer.id,
+                success: answer.success,
+                complete: answer.complete,
+                index: index as u32,
+                count,
+                checksum: hash(data),
+                data: data.to_vec(),
+            }));
+        }
+    }
+    if !batch.is_empty() {
+        frames.push(AnswerFrame::Batch(batch));
+    }
+    frames
+}
+
+///
+/// rebuilds the answers from the received frames
+///
+/// fragments of an answer are sent contiguously on the ordered answer stream,
+/// any gap, corruption or interleaving is a protocol violation
+///
+#[derive(Default)]
+pub struct AnswerReassembler {
+    pending: Option<Answer>,
+    next_index: u32,
+    count: u32,
+}
+impl AnswerReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// returns the completed answers, an empty vector when the answer is still partial
+    ///
+    pub fn push(&mut self, frame: AnswerFrame) -> Result<Vec<Answer>, Error> {
+        match frame {
+            AnswerFrame::Batch(answers) => {
+                if self.pending.is_some() {
+                    return Err(Error::InvalidFragment(
+                        "batch received before the end of a fragmented answer".to_string(),
+                    ));
+                }
+                Ok(answers)
+            }
+            AnswerFrame::Fragment(fragment) => {
+                if hash(&fragment.data) != fragment.checksum {
+                    return Err(Error::InvalidFragment(format!(
+                        "checksum mismatch for answer {} fragment {}",
+                        fragment.id, fragment.index
+                    )));
+                }
+                if fragment.count == 0 || fragment.count > MAX_FRAGMENTS {
+                    return Err(Error::InvalidFragment(format!(
+                        "invalid fragment count {}",
+                        fragment.count
+                    )));
+                }
+                match &mut self.pending {
+                    None => {
+                        if fragment.index != 0 {
+                            return Err(Error::InvalidFragment(format!(
+                                "answer {} starts at fragment {}",
+                                fragment.id, fragment.index
+                            )));
+                        }
+                        self.count = fragment.count;
+                        self.pending = Some(Answer {
+                            id: fragment.id,
+                            success: fragment.success,
+                            complete: fragment.complete,
+                            serialized: fragment.data,
+                        });
+                    }
+                    Some(answer) => {
+                        if answer.id != fragment.id
+                            || fragment.index != self.next_index
+                            || fragment.count != self.count
+                        {
+                            return Err(Error::InvalidFragment(format!(
+                                "unexpected fragment {} for answer {}",
+                                fragment.index, fragment.id
+                            )));
+                        }
+                        answer.serialized.extend_from_slice(&fragment.data);
+                    }
+                }
+                self.next_index = fragment.index + 1;
+                if self.next_index == self.count {
+                    self.next_index = 0;
+                    self.count = 0;
+                    return Ok(vec![self.pending.take().unwrap()]);
+                }
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(id: u64, size: usize) -> Answer {
+        Answer {
+            id,
+            success: true,
+            complete: id > 2,
+            serialized: (0..size).map(|i| (i % 251) as u8).collect(),
+        }
+    }
+
+    #[test]
+    fn fragment_and_reassemble() {
+        let max_buffer_size = 1024;
+        let answers = vec![
+            answer(1, 10),
+            answer(2, 5000),
+            answer(3, 20),
+            answer(4, 900),
+        ];
+        let frames = split_answers(answers, max_buffer_size);
+
+        let mut reassembler = AnswerReassembler::new();
+        let mut received = Vec::new();
+        for frame in frames {
+            let serialized = bincode::serialize(&frame).unwrap();
+            assert!(serialized.len() <= max_buffer_size);
+            let frame: AnswerFrame = bincode::deserialize(&serialized).unwrap();
+            received.extend(reassembler.push(frame).unwrap());
+        }
+        assert_eq!(4, received.len());
+        for (i, size) in [10, 5000, 20, 900].iter().enumerate() {
+            let expected = answer(i as u64 + 1, *size);
+            assert_eq!(expected.id, received[i].id);
+            assert_eq!(expected.complete, received[i].complete);
+            assert_eq!(expected.serialized, received[i].serialized);
+        }
+    }
+
+    #[test]
+    fn corrupted_fragment() {
+        let mut frames = split_answers(vec![answer(1, 5000)], 1024);
+        if let AnswerFrame::Fragment(fragment) = &mut frames[1] {
+            fragment.data[0] ^= 1;
+        }
+        let mut reassembler = AnswerReassembler::new();
+        let mut frames = frames.into_iter();
+        assert!(reassembler.push(frames.next().unwrap()).unwrap().is_empty());
+        assert!(reassembler.push(frames.next().unwrap()).is_err());
+
+        let mut frames = split_answers(vec![answer(1, 5000)], 1024);
+        frames.remove(1);
+        let mut reassembler = AnswerReassembler::new();
+        let mut frames = frames.into_iter();
+        assert!(reassembler.push(frames.next().unwrap()).unwrap().is_empty());
+        assert!(reassembler.push(frames.next().unwrap()).is_err());
+    }
+}
@@ -1,9 +1,13 @@
 #[cfg(feature = "log")]
-use log::error;
+use crate::log_level::{error, LogSubsystem};
+#[cfg(feature = "log")]
+const LOG_SUBSYSTEM: LogSubsystem = LogSubsystem::Network;
 use std::{
     collections::{HashMap, HashSet},
     net::SocketAddr,
+    ops::Deref,
     sync::Arc,
+    time::Instant,
 };
 
 use quinn::{crypto::rustls::QuicServerConfig, Connection, Endpoint, Incoming, SendStream, VarInt};
@@ -14,11 +18,11 @@ use tokio::{
     sync::Mutex,
 };
 
-use crate::security::MeetingToken;
+use crate::security::{base64_encode, hash, MeetingToken};
 
 use super::{
     peer_manager::MAX_MESSAGE_SIZE, shared_buffers::SharedBuffers, Announce, AnnounceHeader,
-    ALPN_QUIC_HTTP,
+    ALPN_QUIC_HTTP, PROTOCOL_VERSION,
 };
 
 #[derive(Serialize, Deserialize)]
@@ -26,10 +30,258 @@ pub enum BeaconMessage {
     InitiateConnection(AnnounceHeader, SocketAddr, MeetingToken),
 }
 
+///
+/// Identifies an application on the Beacon.
+///
+/// Peers sends it right after opening the connection, meeting tokens are only matched with peers of the same application.
+///
+pub type ApplicationId = [u8; 32];
+
+///
+/// Computes the identifier sent to the Beacons by the applications using this app_key
+///
+pub fn application_id(app_key: &str) -> ApplicationId {
+    hash(format!("BEACON_APPLICATION{}", app_key).as_bytes())
+}
+
+///
+/// Limits applied to each application served by the Beacon
+///
+#[derive(Debug, Clone)]
+pub struct ApplicationLimits {
+    ///
+    /// maximum number of simultaneous peer connections
+    ///
+    /// default: 4096
+    ///
+    pub max_connections: usize,
+
+    ///
+    /// maximum number of meeting tokens a peer can announce, extra tokens are ignored
+    ///
+    /// default: 512
+    ///
+    pub max_tokens_per_connection: usize,
+}
+impl Default for ApplicationLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: 4096,
+            max_tokens_per_connection: 512,
+        }
+    }
+}
+
+///
+/// Usage metrics of an application served by the Beacon
+///
+#[derive(Debug, Clone, Default)]
+pub struct ApplicationMetrics {
+    ///
+    /// currently connected peers
+    ///
+    pub connections: usize,
+
+    ///
+    /// total number of accepted connections since the Beacon started
+    ///
+    pub total_connections: u64,
+
+    ///
+    /// number of connections rejected because of the max_connections limit
+    ///
+    pub rejected_connections: u64,
+
+    ///
+    /// number of distinct meeting tokens currently announced
+    ///
+    pub tokens: usize,
+
+    ///
+    /// number of peer pairs that have been asked to connect to each other
+    ///
+    pub initiated_connections: u64,
+
+    ///
+    /// number of announces ignored because their tokens were not registered by an approved owner
+    ///
+    pub unregistered_announces: u64,
+}
+
+///
+/// Parameters of a Beacon started with *Beacon::start_with()*
+///
+#[derive(Clone)]
+pub struct BeaconServerConfig {
+    ///
+    /// UDP port of the service, 0 lets the system choose a free port, see *BeaconHandle::local_addr()*
+    ///
+    pub ipv4_port: u16,
+
+    ///
+    /// DER encoded certificate, its hash is provided to the peers in their BeaconConfig
+    ///
+    pub der: Vec<u8>,
+
+    ///
+    /// DER encoded private key of the certificate
+    ///
+    pub pks_der: Vec<u8>,
+
+    ///
+    /// match the peers sharing the same IP address, like the devices of a local network behind a NAT.
+    ///
+    /// default: false
+    ///
+    pub allow_same_ip: bool,
+
+    ///
+    /// limits applied to the applications that do not have specific limits
+    ///
+    pub default_limits: ApplicationLimits,
+
+    ///
+    /// called for each BeaconEvent, allowing the host application to log the activity of the Beacon with its own logger.
+    /// The hook is called from the network tasks and must return quickly.
+    ///
+    pub hook: Option<BeaconHook>,
+}
+impl BeaconServerConfig {
+    pub fn new(ipv4_port: u16, der: Vec<u8>, pks_der: Vec<u8>) -> Self {
+        Self {
+            ipv4_port,
+            der,
+            pks_der,
+            allow_same_ip: false,
+            default_limits: ApplicationLimits::default(),
+            hook: None,
+        }
+    }
+}
+
+///
+/// Activity of the Beacon, provided to the BeaconServerConfig hook
+///
+/// application is the base64 encoded application id, see application_id()
+///
+#[derive(Debug, Clone)]
+pub enum BeaconEvent {
+    ConnectionAccepted {
+        application: String,
+        address: SocketAddr,
+    },
+    ConnectionRejected {
+        application: String,
+        address: SocketAddr,
+        reason: String,
+    },
+    ConnectionClosed {
+        application: String,
+        address: SocketAddr,
+    },
+    Error(String),
+}
+
+pub type BeaconHook = Arc<dyn Fn(&BeaconEvent) + Send + Sync>;
+
+fn notify(hook: &Option<BeaconHook>, event: BeaconEvent) {
+    if let Some(hook) = hook {
+        hook(&event);
+    }
+}
+
+///
+/// Totals of every application served by the Beacon
+///
+#[derive(Debug, Clone, Default)]
+pub struct BeaconMetrics {
+    ///
+    /// seconds since the Beacon started
+    ///
+    pub uptime_secs: u64,
+
+    ///
+    /// number of applications known by the Beacon: the configured ones and those with connected peers
+    ///
+    pub applications: usize,
+
+    ///
+    /// currently connected peers
+    ///
+    pub connections: usize,
+
+    ///
+    /// total number of accepted connections since the Beacon started
+    ///
+    pub total_connections: u64,
+
+    ///
+    /// number of connections rejected because of the max_connections limit
+    ///
+    pub rejected_connections: u64,
+
+    ///
+    /// number of peer pairs that have been asked to connect to each other
+    ///
+    pub initiated_connections: u64,
+}
+
+struct Tenant {
+    limits: ApplicationLimits,
+    approved_owners: Option<Arc<HashSet<Vec<u8>>>>,
+    //defined by the host application, never evicted
+    configured: bool,
+    metrics: ApplicationMetrics,
+    meeting_point: Arc<Mutex<MeetingPoint>>,
+}
+impl Tenant {
+    fn new(limits: ApplicationLimits) -> Self {
+        Self {
+            limits,
+            approved_owners: None,
+            configured: false,
+            metrics: ApplicationMetrics::default(),
+            meeting_point: Arc::new(Mutex::new(MeetingPoint {
+                meeting: HashMap::new(),
+                buffer: Vec::new(),
+                initiated_connections: 0,
+            })),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Tenants {
+    default_limits: ApplicationLimits,
+    applications: HashMap<ApplicationId, Tenant>,
+}
+impl Tenants {
+    ///
+    /// Tenants are created for any application id sent by a client.
+    /// Those that were not configured by the host application are removed once they have no connection left,
+    /// preventing clients from growing the map by sending random application ids.
+    ///
+    async fn evict_unused(&mut self, app_id: &ApplicationId) {
+        if let Some(tenant) = self.applications.get(app_id) {
+            if tenant.configured || tenant.metrics.connections > 0 {
+                return;
+            }
+            if !tenant.meeting_point.lock().await.meeting.is_empty() {
+                return;
+            }
+            self.applications.remove(app_id);
+        }
+    }
+}
+
 ///
 /// Provides a Beacon service that allow peers to discover each others on the Internet
 ///
-pub struct Beacon {}
+/// A single Beacon can serve several applications: each application has its own meeting token namespace, limits and metrics.
+///
+pub struct Beacon {
+    tenants: Arc<Mutex<Tenants>>,
+}
 impl Beacon {
     ///
     /// starts the service
@@ -40,18 +292,134 @@ impl Beacon {
         pks_der: Vec<u8>,
         allow_same_ip: bool,
     ) -> Result<Self, super::Error> {
-        let shared_buffers = Arc::new(SharedBuffers::new());
+        let config = BeaconServerConfig {
+            allow_same_ip,
+            ..BeaconServerConfig::new(ipv4_port, der, pks_der)
+        };
+        Ok(Self::start_with(config)?.beacon)
+    }
 
-        let ipv4_addr: SocketAddr = format!("0.0.0.0:{}", ipv4_port).parse()?;
-        let ipv4_endpoint = Self::enpoint(ipv4_addr, der.clone(), pks_der.clone())?;
+    ///
+    /// starts the service embedded in a host application
+    ///
+    /// The returned handle provides the Beacon API, the runtime metrics and the shutdown of the service.
+    ///
+    pub fn start_with(config: BeaconServerConfig) -> Result<BeaconHandle, super::Error> {
+        let shared_buffers = Arc::new(SharedBuffers::new());
+        let tenants = Arc::new(Mutex::new(Tenants {
+            default_limits: config.default_limits,
+            applications: HashMap::new(),
+        }));
+
+        let ipv4_addr: SocketAddr = format!("0.0.0.0:{}", config.ipv4_port).parse()?;
+        let ipv4_endpoint = Self::enpoint(ipv4_addr, config.der, config.pks_der)?;
+        let local_addr = ipv4_endpoint.local_addr()?;
         Self::start_endpoint(
-            ipv4_endpoint,
+            ipv4_endpoint.clone(),
             shared_buffers.clone(),
             MAX_MESSAGE_SIZE,
-            allow_same_ip,
+            config.allow_same_ip,
+            tenants.clone(),
+            config.hook,
         );
 
-        Ok(Self {})
+        Ok(BeaconHandle {
+            beacon: Self { tenants },
+            endpoint: ipv4_endpoint,
+            local_addr,
+            started: Instant::now(),
+        })
+    }
+
+    ///
+    /// Defines the limits applied to the applications that do not have specific limits
+    ///
+    pub async fn set_default_limits(&self, limits: ApplicationLimits) {
+        let mut tenants = self.tenants.lock().await;
+        tenants.default_limits = limits;
+    }
+
+    ///
+    /// Defines the limits of the application using the provided app_key
+    ///
+    pub async fn set_application_limits(&self, app_key: &str, limits: ApplicationLimits) {
+        let app_id = application_id(app_key);
+        let mut tenants = self.tenants.lock().await;
+        let tenant = tenants
+            .applications
+            .entry(app_id)
+            .or_insert_with(|| Tenant::new(limits.clone()));
+        tenant.limits = limits;
+        tenant.configured = true;
+    }
+
+    ///
+    /// Only matches the meeting tokens registered by an approved owner, for the application using the provided app_key.
+    ///
+    /// Peers register their tokens by enabling *register_tokens* in their BeaconConfig: their announces are signed with their identity.
+    /// The tokens of the peers without a valid registration from an approved owner are ignored,
+    /// preventing unknown peers from guessing tokens or scraping the rendezvous of the application.
+    ///
+    /// owners contains the verifying keys of the approved owners, see *Discret::verifying_key()*.
+    /// An empty list disables the gate. The change applies to the new connections.
+    ///
+    pub async fn set_approved_owners(&self, app_key: &str, owners: Vec<Vec<u8>>) {
+        let app_id = application_id(app_key);
+        let approved_owners = match owners.is_empty() {
+            true => None,
+            false => Some(Arc::new(HashSet::from_iter(owners))),
+        };
+        let mut tenants = self.tenants.lock().await;
+        let default_limits = tenants.default_limits.clone();
+        let tenant = tenants
+            .applications
+            .entry(app_id)
+            .or_insert_with(|| Tenant::new(default_limits));
+        tenant.approved_owners = approved_owners;
+        tenant.configured = true;
+    }
+
+    ///
+    /// Returns the metrics of every application served by the Beacon.
+    ///
+    /// The map is keyed by the base64 encoded application id, see application_id()
+    ///
+    /// The applications that were not configured with *set_application_limits()* or *set_approved_owners()*
+    /// are forgotten when their last peer disconnects, and their metrics are reset.
+    ///
+    pub async fn metrics(&self) -> HashMap<String, ApplicationMetrics> {
+        //the tenants lock is released before waiting for the meeting points
+        let applications: Vec<_> = {
+            let tenants = self.tenants.lock().await;
+            tenants
+                .applications
+                .iter()
+                .map(|(app_id, tenant)| {
+                    (
+                        base64_encode(app_id),
+                        tenant.metrics.clone(),
+                        tenant.meeting_point.clone(),
+                    )
+                })
+                .collect()
+        };
+        let mut result = HashMap::new();
+        for (app_id, mut metrics, meeting_point) in applications {
+            let meeting = meeting_point.lock().await;
+            metrics.tokens = meeting.meeting.len();
+            metrics.initiated_connections = meeting.initiated_connections;
+            result.insert(app_id, metrics);
+        }
+        result
+    }
+
+    ///
+    /// Returns the metrics of the application using the provided app_key
+    ///
+    pub async fn application_metrics(&self, app_key: &str) -> Option<ApplicationMetrics> {
+        self.metrics()
+            .await
+            .remove(&base64_encode(&application_id(app_key)))
     }
 
     fn enpoint(addr: SocketAddr, der: Vec<u8>, pks_der: Vec<u8>) -> Result<Endpoint, super::Error> {
@@ -76,28 +444,28 @@ impl Beacon {
         shared_buffers: Arc<SharedBuffers>,
         max_buffer_size: usize,
         allow_same_ip: bool,
+        tenants: Arc<Mutex<Tenants>>,
+        hook: Option<BeaconHook>,
     ) {
         tokio::spawn(async move {
-            let meeting_point: Arc<Mutex<MeetingPoint>> = Arc::new(Mutex::new(MeetingPoint {
-                meeting: HashMap::new(),
-                buffer: Vec::new(),
-            }));
-
             while let Some(incoming) = endpoint.accept().await {
                 let shared_buff = shared_buffers.clone();
-                let meeting_point = meeting_point.clone();
+                let tenants = tenants.clone();
+                let hook = hook.clone();
                 tokio::spawn(async move {
                     let new_conn = Self::start_accepted(
                         incoming,
                         shared_buff,
                         max_buffer_size,
-                        meeting_point,
+                        tenants,
                         allow_same_ip,
+                        hook.clone(),
                     )
                     .await;
-                    if let Err(_e) = new_conn {
+                    if let Err(e) = new_conn {
                         #[cfg(feature = "log")]
-                        error!("Beacon - start_accepted, Error: {_e}");
+                        error!("Beacon - start_accepted, Error: {e}");
+                        notify(&hook, BeaconEvent::Error(e.to_string()));
                     }
                 });
             }
@@ -108,13 +476,66 @@ impl Beacon {
         incoming: Incoming,
         shared_buffers: Arc<SharedBuffers>,
         max_buffer_size: usize,
-        meeting_point: Arc<Mutex<MeetingPoint>>,
+        tenants: Arc<Mutex<Tenants>>,
         allow_same_ip: bool,
+        hook: Option<BeaconHook>,
     ) -> Result<(), super::Error> {
         let new_conn = incoming.await?;
         let (send, mut recv) = new_conn.accept_bi().await?;
 
-        recv.read_u8().await?;
+        let version = recv.read_u8().await?;
+        if version != PROTOCOL_VERSION {
+            new_conn.close(
+                VarInt::from_u32(1),
+                "unsupported protocol version".as_bytes(),
+            );
+            return Err(super::Error::UnsupportedProtocolVersion(
+                version,
+                PROTOCOL_VERSION,
+            ));
+        }
+        let mut app_id: ApplicationId = [0; 32];
+        recv.read_exact(&mut app_id).await?;
+        let address = new_conn.remote_address();
+
+        let (meeting_point, max_tokens, approved_owners) = {
+            let mut tenants = tenants.lock().await;
+            let default_limits = tenants.default_limits.clone();
+            let tenant = tenants
+                .applications
+                .entry(app_id)
+                .or_insert_with(|| Tenant::new(default_limits));
+
+            if tenant.metrics.connections >= tenant.limits.max_connections {
+                tenant.metrics.rejected_connections += 1;
+                tenants.evict_unused(&app_id).await;
+                new_conn.close(VarInt::from_u32(1), "too many connections".as_bytes());
+                notify(
+                    &hook,
+                    BeaconEvent::ConnectionRejected {
+                        application: base64_encode(&app_id),
+                        address,
+                        reason: "too many connections".to_string(),
+                    },
+                );
+                return Ok(());
+            }
+            tenant.metrics.connections += 1;
+            tenant.metrics.total_connections += 1;
+            (
+                tenant.meeting_point.clone(),
+                tenant.limits.max_tokens_per_connection,
+                tenant.approved_owners.clone(),
+            )
+        };
+
+        notify(
+            &hook,
+            BeaconEvent::ConnectionAccepted {
+                application: base64_encode(&app_id),
+                address,
+            },
+        );
 
         let sbuff = shared_buffers.clone();
         tokio::spawn(async move {
@@ -155,7 +576,16 @@ impl Beacon {
                     break;
                 }
 
-                let announce = announce.unwrap();
+                let mut announce = announce.unwrap();
+                if let Some(owners) = &approved_owners {
+                    if !announce.is_registered_by(owners) {
+                        announce.tokens.clear();
+                        let mut tenants = tenants.lock().await;
+                        if let Some(tenant) = tenants.applications.get_mut(&app_id) {
+                            tenant.metrics.unregistered_announces += 1;
+                        }
+                    }
+                }
                 if !header_initialised {
                     let header = announce.header;
 
@@ -167,7 +597,7 @@ impl Beacon {
                 }
 
                 let new_tokens: HashSet<MeetingToken> =
-                    HashSet::from_iter(announce.tokens.into_iter());
+                    HashSet::from_iter(announce.tokens.into_iter().take(max_tokens));
 
                 let to_remove: HashSet<&MeetingToken> =
                     last_tokens.difference(&new_tokens).collect();
@@ -189,15 +619,85 @@ impl Beacon {
             }
             let mut meeting = meeting_point.lock().await;
             meeting.remove_tokens(id, &to_remove).await;
+            drop(meeting);
+
+            let mut tenants = tenants.lock().await;
+            if let Some(tenant) = tenants.applications.get_mut(&app_id) {
+                tenant.metrics.connections -= 1;
+            }
+            tenants.evict_unused(&app_id).await;
+            drop(tenants);
+            notify(
+                &hook,
+                BeaconEvent::ConnectionClosed {
+                    application: base64_encode(&app_id),
+                    address,
+                },
+            );
         });
 
         Ok(())
     }
 }
 
+///
+/// A Beacon embedded in a host application, see *Beacon::start_with()*
+///
+/// The handle dereferences to the Beacon to configure the applications limits and approved owners.
+///
+pub struct BeaconHandle {
+    beacon: Beacon,
+    endpoint: Endpoint,
+    local_addr: SocketAddr,
+    started: Instant,
+}
+impl BeaconHandle {
+    ///
+    /// address of the service, provides the port chosen by the system when started with the port 0
+    ///
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    ///
+    /// Totals of every application served by the Beacon
+    ///
+    pub async fn runtime_metrics(&self) -> BeaconMetrics {
+        let mut result = BeaconMetrics {
+            uptime_secs: self.started.elapsed().as_secs(),
+            ..Default::default()
+        };
+        for metrics in self.beacon.metrics().await.values() {
+            result.applications += 1;
+            result.connections += metrics.connections;
+            result.total_connections += metrics.total_connections;
+            result.rejected_connections += metrics.rejected_connections;
+            result.initiated_connections += metrics.initiated_connections;
+        }
+        result
+    }
+
+    ///
+    /// closes every connection, stops accepting new ones and waits for the connections to be closed
+    ///
+    pub async fn shutdown(&self) {
+        self.endpoint
+            .close(VarInt::from_u32(0), "shutdown".as_bytes());
+        self.endpoint.wait_idle().await;
+    }
+}
+impl Deref for BeaconHandle {
+    type Target = Beacon;
+
+    fn deref(&self) -> &Self::Target {
+        &self.beacon
+    }
+}
+
 struct MeetingPoint {
     meeting: HashMap<MeetingToken, Vec<Arc<Mutex<ConnectionInfo>>>>,
     buffer: Vec<u8>,
+    initiated_connections: u64,
 }
 impl MeetingPoint {
     pub async fn add_tokens(
@@ -268,6 +768,7 @@ impl MeetingPoint {
                         if other_peer.sender.write_all(&self.buffer).await.is_err() {
                             other_peer.conn.close(VarInt::from_u32(1), "".as_bytes());
                         }
+                        self.initiated_connections += 1;
                     }
                 }
             }
@@ -291,6 +792,9 @@ impl MeetingPoint {
                 if index >= 0 {
                     entry.remove(index as usize);
                 }
+                if entry.is_empty() {
+                    self.meeting.remove(*token);
+                }
             }
         }
     }
@@ -301,3 +805,196 @@ struct ConnectionInfo {
     sender: SendStream,
     header: Option<AnnounceHeader>,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use quinn::RecvStream;
+
+    use super::*;
+    use crate::{
+        network::endpoint::{build_endpoint, ServerCertVerifier},
+        security::{generate_x509_certificate, new_uid},
+    };
+
+    async fn connect_client(
+        beacon: &BeaconHandle,
+        cert_verifier: &Arc<ServerCertVerifier>,
+        name: &str,
+        app_id: &ApplicationId,
+        token: MeetingToken,
+    ) -> (Connection, SendStream, RecvStream) {
+        let addr = "0.0.0.0:0".parse().unwrap();
+        let endpoint = build_endpoint(
+            addr,
+            generate_x509_certificate("client.me"),
+            cert_verifier.clone(),
+        )
+        .unwrap();
+        let beacon_addr = format!("127.0.0.1:{}", beacon.local_addr().port())
+            .parse()
+            .unwrap();
+        let conn = endpoint.connect(beacon_addr, name).unwrap().await.unwrap();
+        let (mut send, recv) = conn.open_bi().await.unwrap();
+        send.write_u8(PROTOCOL_VERSION).await.unwrap();
+        send.write_all(app_id).await.unwrap();
+
+        let announce = Announce {
+            header: AnnounceHeader {
+                endpoint_id: new_uid(),
+                certificate_hash: [0; 32],
+                signature: vec![],
+            },
+            tokens: vec![token],
+            registration: None,
+        };
+        let bin = bincode::serialize(&announce).unwrap();
+        send.write_u32(bin.len() as u32).await.unwrap();
+        send.write_all(&bin).await.unwrap();
+        (conn, send, recv)
+    }
+
+    async fn receive_message(recv: &mut RecvStream) -> Option<BeaconMessage> {
+        let len = tokio::time::timeout(Duration::from_millis(500), recv.read_u32())
+            .await
+            .ok()?
+            .unwrap();
+        let mut buffer = vec![0; len as usize];
+        recv.read_exact(&mut buffer).await.unwrap();
+        Some(bincode::deserialize(&buffer).unwrap())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn application_isolation() {
+        let certificate = generate_x509_certificate("beacon.me");
+        let cert_verifier = ServerCertVerifier::new();
+        let name = cert_verifier.add_valid_certificate(hash(certificate.cert.der().deref()));
+        let der: Vec<u8> = certificate.cert.der().deref().to_vec();
+        let pks_der: Vec<u8> = certificate.key_pair.serialize_der();
+
+        let beacon = Beacon::start_with(BeaconServerConfig {
+            allow_same_ip: true,
+            ..BeaconServerConfig::new(0, der, pks_der)
+        })
+        .unwrap();
+
+        let app_one = application_id("app one");
+        let app_two = application_id("app two");
+        let token: MeetingToken = [7; 7];
+
+        let (conn_one, _send_one, mut recv_one) =
+            connect_client(&beacon, &cert_verifier, &name, &app_one, token).await;
+        let (conn_two, _send_two, mut recv_two) =
+            connect_client(&beacon, &cert_verifier, &name, &app_two, token).await;
+
+        //same token, different applications: no match
+        assert!(receive_message(&mut recv_two).await.is_none());
+        assert!(receive_message(&mut recv_one).await.is_none());
+        let metrics = beacon.runtime_metrics().await;
+        assert_eq!(2, metrics.applications);
+        assert_eq!(0, metrics.initiated_connections);
+
+        let (conn_three, _send_three, mut recv_three) =
+            connect_client(&beacon, &cert_verifier, &name, &app_one, token).await;
+
+        let message = receive_message(&mut recv_three).await.unwrap();
+        let BeaconMessage::InitiateConnection(_, _, received) = message;
+        assert_eq!(token, received);
+        let message = receive_message(&mut recv_one).await.unwrap();
+        let BeaconMessage::InitiateConnection(_, _, received) = message;
+        assert_eq!(token, received);
+        assert!(receive_message(&mut recv_two).await.is_none());
+
+        conn_one.close(VarInt::from_u32(0), "".as_bytes());
+        conn_two.close(VarInt::from_u32(0), "".as_bytes());
+        conn_three.close(VarInt::from_u32(0), "".as_bytes());
+
+        //unconfigured applications are forgotten once their last peer is disconnected
+        let mut applications = 2;
+        for _ in 0..40 {
+            applications = beacon.runtime_metrics().await.applications;
+            if applications == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(0, applications);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unsupported_protocol_version() {
+        let certificate = generate_x509_certificate("beacon.me");
+        let cert_verifier = ServerCertVerifier::new();
+        let name = cert_verifier.add_valid_certificate(hash(certificate.cert.der().deref()));
+        let der: Vec<u8> = certificate.cert.der().deref().to_vec();
+        let pks_der: Vec<u8> = certificate.key_pair.serialize_der();
+        let beacon = Beacon::start_with(BeaconServerConfig::new(0, der, pks_der)).unwrap();
+
+        let endpoint = build_endpoint(
+            "0.0.0.0:0".parse().unwrap(),
+            generate_x509_certificate("client.me"),
+            cert_verifier.clone(),
+        )
+        .unwrap();
+        let beacon_addr = format!("127.0.0.1:{}", beacon.local_addr().port())
+            .parse()
+            .unwrap();
+        let conn = endpoint.connect(beacon_addr, &name).unwrap().await.unwrap();
+        let (mut send, _recv) = conn.open_bi().await.unwrap();
+        send.write_u8(PROTOCOL_VERSION + 1).await.unwrap();
+        send.write_all(&application_id("app one")).await.unwrap();
+
+        let error = tokio::time::timeout(Duration::from_secs(2), conn.closed())
+            .await
+            .unwrap();
+        match error {
+            quinn::ConnectionError::ApplicationClosed(close) => {
+                assert_eq!(&b"unsupported protocol version"[..], &close.reason[..])
+            }
+            e => panic!("unexpected close {e}"),
+        }
+        assert_eq!(0, beacon.runtime_metrics().await.applications);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn configured_application_kept() {
+        let certificate = generate_x509_certificate("beacon.me");
+        let cert_verifier = ServerCertVerifier::new();
+        let name = cert_verifier.add_valid_certificate(hash(certificate.cert.der().deref()));
+        let der: Vec<u8> = certificate.cert.der().deref().to_vec();
+        let pks_der: Vec<u8> = certificate.key_pair.serialize_der();
+
+        let beacon = Beacon::start_with(BeaconServerConfig::new(0, der, pks_der)).unwrap();
+        beacon
+            .set_application_limits("app one", ApplicationLimits::default())
+            .await;
+
+        let app_one = application_id("app one");
+        let (conn_one, _send_one, _recv_one) =
+            connect_client(&beacon, &cert_verifier, &name, &app_one, [1; 7]).await;
+
+        let mut connections = 0;
+        for _ in 0..40 {
+            connections = beacon.runtime_metrics().await.connections;
+            if connections == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(1, connections);
+
+        conn_one.close(VarInt::from_u32(0), "".as_bytes());
+        for _ in 0..40 {
+            connections = beacon.runtime_metrics().await.connections;
+            if connections == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(0, connections);
+
+        let metrics = beacon.application_metrics("app one").await.unwrap();
+        assert_eq!(1, metrics.total_connections);
+    }
+}
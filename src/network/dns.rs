@@ -0,0 +1,382 @@
+//!
+//! Minimal DNS client used to discover the beacon servers of a domain.
+//!
+//! The beacons of a domain are published with:
+//! - SRV records named `_discret-beacon._udp.<domain>`, giving the beacon hostname and port,
+//! - TXT records with the same name, containing `<hostname>=<certificate hash>` for each beacon.
+//!
+//! Beacons without a certificate hash are ignored.
+//!
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
+use tokio::{net::UdpSocket, time::timeout};
+
+use super::Error;
+use crate::configuration::BeaconConfig;
+
+pub const BEACON_SERVICE: &str = "_discret-beacon._udp";
+
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+const DNS_PORT: u16 = 53;
+const MAX_MESSAGE_SIZE: usize = 4096;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, PartialEq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+///
+/// retrieve the beacons published by a domain, ordered by priority
+///
+/// when the dns server is not provided, the first nameserver of /etc/resolv.conf is used
+///
+pub async fn discover_beacons(
+    domain: &str,
+    dns_server: Option<&str>,
+) -> Result<Vec<BeaconConfig>, Error> {
+    let server = match dns_server {
+        Some(server) => parse_server(server)?,
+        None => system_server()?,
+    };
+    let name = format!("{}.{}", BEACON_SERVICE, domain.trim_end_matches('.'));
+
+    let srv = query(server, &name, TYPE_SRV).await?;
+    let srv = parse_srv(&srv)?;
+
+    let txt = query(server, &name, TYPE_TXT).await?;
+    let hashes = cert_hashes(&parse_txt(&txt)?);
+
+    Ok(beacons(srv, &hashes))
+}
+
+fn parse_server(server: &str) -> Result<SocketAddr, Error> {
+    match server.parse::<SocketAddr>() {
+        Ok(address) => Ok(address),
+        Err(_) => Ok(SocketAddr::new(server.parse()?, DNS_PORT)),
+    }
+}
+
+fn system_server() -> Result<SocketAddr, Error> {
+    let conf = std::fs::read_to_string("/etc/resolv.conf")
+        .map_err(|e| Error::Dns(format!("cannot read /etc/resolv.conf: {e}")))?;
+    for line in conf.lines() {
+        let mut parts = line.split_whitespace();
+        if parts.next() == Some("nameserver") {
+            if let Some(ip) = parts.next() {
+                if let Ok(ip) = ip.parse() {
+                    return Ok(SocketAddr::new(ip, DNS_PORT));
+                }
+            }
+        }
+    }
+    Err(Error::Dns(
+        "no nameserver found in /etc/resolv.conf".to_string(),
+    ))
+}
+
+async fn query(server: SocketAddr, name: &str, record_type: u16) -> Result<Vec<u8>, Error> {
+    let local: SocketAddr = if server.is_ipv4() {
+        "0.0.0.0:0".parse()?
+    } else {
+        "[::]:0".parse()?
+    };
+    let socket = UdpSocket::bind(local).await?;
+    let id: u16 = rand::random();
+    let request = build_query(id, name, record_type)?;
+    socket.send_to(&request, server).await?;
+
+    let mut buffer = vec![0; MAX_MESSAGE_SIZE];
+    loop {
+        let (len, from) = timeout(QUERY_TIMEOUT, socket.recv_from(&mut buffer))
+            .await
+            .map_err(|_| Error::Dns(format!("timeout while querying {server} for {name}")))??;
+        if from == server && len >= 2 && u16::from_be_bytes([buffer[0], buffer[1]]) == id {
+            buffer.truncate(len);
+            return Ok(buffer);
+        }
+    }
+}
+
+fn build_query(id: u16, name: &str, record_type: u16) -> Result<Vec<u8>, Error> {
+    let mut message = Vec::with_capacity(name.len() + 18);
+    message.extend_from_slice(&id.to_be_bytes());
+    //standard query with recursion desired
+    message.extend_from_slice(&0x0100u16.to_be_bytes());
+    //one question, no answer, authority or additional records
+    message.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(Error::Dns(format!("invalid domain name: {name}")));
+        }
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+    message.extend_from_slice(&record_type.to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(message)
+}
+
+fn read_u16(message: &[u8], pos: usize) -> Result<u16, Error> {
+    match message.get(pos..pos + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(Error::Dns("truncated DNS message".to_string())),
+    }
+}
+
+//
+// returns the name and the position following it, following compression pointers
+//
+fn read_name(message: &[u8], mut pos: usize) -> Result<(String, usize), Error> {
+    let mut labels = Vec::new();
+    let mut next = None;
+    let mut jumps = 0;
+    loop {
+        let len = *message
+            .get(pos)
+            .ok_or_else(|| Error::Dns("truncated DNS name".to_string()))?
+            as usize;
+        if len == 0 {
+            pos += 1;
+            break;
+        }
+        if len & 0xC0 == 0xC0 {
+            jumps += 1;
+            if jumps > 16 {
+                return Err(Error::Dns("too many DNS name pointers".to_string()));
+            }
+            let pointer = (read_u16(message, pos)? & 0x3FFF) as usize;
+            if next.is_none() {
+                next = Some(pos + 2);
+            }
+            pos = pointer;
+            continue;
+        }
+        let label = message
+            .get(pos + 1..pos + 1 + len)
+            .ok_or_else(|| Error::Dns("truncated DNS name".to_string()))?;
+        labels.push(String::from_utf8_lossy(label).to_string());
+        pos += 1 + len;
+    }
+    Ok((labels.join("."), next.unwrap_or(pos)))
+}
+
+//
+// returns the record data of the answers of the requested type, along with the start of the data in the message
+//
+fn answers(message: &[u8], record_type: u16) -> Result<Vec<(usize, usize)>, Error> {
+    let flags = read_u16(message, 2)?;
+    let rcode = flags & 0x000F;
+    //NXDOMAIN: the domain does not publish beacons
+    if rcode == 3 {
+        return Ok(Vec::new());
+    }
+    if rcode != 0 {
+        return Err(Error::Dns(format!("DNS query failed with code {rcode}")));
+    }
+    let questions = read_u16(message, 4)?;
+    let answers = read_u16(message, 6)?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(message, pos)?.1 + 4;
+    }
+    let mut result = Vec::new();
+    for _ in 0..answers {
+        pos = read_name(message, pos)?.1;
+        let rtype = read_u16(message, pos)?;
+        let len = read_u16(message, pos + 8)? as usize;
+        let start = pos + 10;
+        if start + len > message.len() {
+            return Err(Error::Dns("truncated DNS record".to_string()));
+        }
+        if rtype == record_type {
+            result.push((start, len));
+        }
+        pos = start + len;
+    }
+    Ok(result)
+}
+
+fn parse_srv(message: &[u8]) -> Result<Vec<SrvRecord>, Error> {
+    let mut records = Vec::new();
+    for (start, len) in answers(message, TYPE_SRV)? {
+        if len < 7 {
+            return Err(Error::Dns("invalid SRV record".to_string()));
+        }
+        let (target, _) = read_name(message, start + 6)?;
+        records.push(SrvRecord {
+            priority: read_u16(message, start)?,
+            weight: read_u16(message, start + 2)?,
+            port: read_u16(message, start + 4)?,
+            target,
+        });
+    }
+    Ok(records)
+}
+
+fn parse_txt(message: &[u8]) -> Result<Vec<String>, Error> {
+    let mut records = Vec::new();
+    for (start, len) in answers(message, TYPE_TXT)? {
+        //a TXT record is a list of character strings, that are concatenated
+        let mut text = Vec::new();
+        let mut pos = start;
+        while pos < start + len {
+            let size = message[pos] as usize;
+            let part = message
+                .get(pos + 1..pos + 1 + size)
+                .ok_or_else(|| Error::Dns("invalid TXT record".to_string()))?;
+            text.extend_from_slice(part);
+            pos += 1 + size;
+        }
+        records.push(String::from_utf8_lossy(&text).to_string());
+    }
+    Ok(records)
+}
+
+fn cert_hashes(txt: &[String]) -> HashMap<String, String> {
+    let mut hashes = HashMap::new();
+    for record in txt {
+        if let Some((hostname, hash)) = record.split_once('=') {
+            hashes.insert(
+                hostname.trim().trim_end_matches('.').to_lowercase(),
+                hash.trim().to_string(),
+            );
+        }
+    }
+    hashes
+}
+
+fn beacons(mut srv: Vec<SrvRecord>, hashes: &HashMap<String, String>) -> Vec<BeaconConfig> {
+    srv.sort_by(|a, b| a.priority.cmp(&b.priority).then(b.weight.cmp(&a.weight)));
+    srv.into_iter()
+        .filter_map(|record| {
+            let target = record.target.trim_end_matches('.').to_lowercase();
+            hashes.get(&target).map(|hash| BeaconConfig {
+                hostname: format!("{}:{}", target, record.port),
+                cert_hash: hash.clone(),
+                register_tokens: false,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //
+    // builds a response to a query using a compression pointer to the question name
+    //
+    fn response(id: u16, name: &str, record_type: u16, records: &[Vec<u8>]) -> Vec<u8> {
+        let mut message = build_query(id, name, record_type).unwrap();
+        message[2] = 0x81;
+        message[3] = 0x80;
+        message[6..8].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        for data in records {
+            message.extend_from_slice(&[0xC0, 12]);
+            message.extend_from_slice(&record_type.to_be_bytes());
+            message.extend_from_slice(&CLASS_IN.to_be_bytes());
+            message.extend_from_slice(&300u32.to_be_bytes());
+            message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            message.extend_from_slice(data);
+        }
+        message
+    }
+
+    fn srv_data(priority: u16, weight: u16, port: u16, target: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&priority.to_be_bytes());
+        data.extend_from_slice(&weight.to_be_bytes());
+        data.extend_from_slice(&port.to_be_bytes());
+        for label in target.split('.') {
+            data.push(label.len() as u8);
+            data.extend_from_slice(label.as_bytes());
+        }
+        data.push(0);
+        data
+    }
+
+    fn txt_data(parts: &[&str]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for part in parts {
+            data.push(part.len() as u8);
+            data.extend_from_slice(part.as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn beacon_records() {
+        let name = format!("{}.example.com", BEACON_SERVICE);
+
+        let message = response(
+            1,
+            &name,
+            TYPE_SRV,
+            &[
+                srv_data(20, 0, 4264, "backup.example.com"),
+                srv_data(10, 5, 4264, "Beacon.example.com"),
+                srv_data(10, 1, 4266, "unknown.example.com"),
+            ],
+        );
+        let srv = parse_srv(&message).unwrap();
+        assert_eq!(3, srv.len());
+        assert_eq!(
+            SrvRecord {
+                priority: 20,
+                weight: 0,
+                port: 4264,
+                target: "backup.example.com".to_string()
+            },
+            srv[0]
+        );
+
+        let message = response(
+            2,
+            &name,
+            TYPE_TXT,
+            &[
+                txt_data(&["beacon.example.com=", "hash1"]),
+                txt_data(&["backup.example.com.=hash2"]),
+                txt_data(&["unrelated"]),
+            ],
+        );
+        let txt = parse_txt(&message).unwrap();
+        assert_eq!("beacon.example.com=hash1", txt[0]);
+
+        let beacons = beacons(srv, &cert_hashes(&txt));
+        assert_eq!(2, beacons.len());
+        assert_eq!("beacon.example.com:4264", beacons[0].hostname);
+        assert_eq!("hash1", beacons[0].cert_hash);
+        assert_eq!("backup.example.com:4264", beacons[1].hostname);
+        assert_eq!("hash2", beacons[1].cert_hash);
+    }
+
+    #[test]
+    fn dns_errors() {
+        let name = format!("{}.example.com", BEACON_SERVICE);
+        let mut message = response(1, &name, TYPE_SRV, &[]);
+        message[3] = 0x83;
+        assert!(parse_srv(&message).unwrap().is_empty());
+
+        message[3] = 0x82;
+        parse_srv(&message).expect_err("server failure");
+
+        let mut message = response(1, &name, TYPE_SRV, &[srv_data(1, 1, 1, "a.b")]);
+        message.truncate(message.len() - 2);
+        parse_srv(&message).expect_err("truncated message");
+
+        build_query(1, "a..b", TYPE_SRV).expect_err("empty label");
+        assert_eq!(
+            "10.0.0.1:53".parse::<SocketAddr>().unwrap(),
+            parse_server("10.0.0.1").unwrap()
+        );
+    }
+}
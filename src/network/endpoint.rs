@@ -1,10 +1,12 @@
 #[cfg(feature = "log")]
-use log::{error, info};
+use crate::log_level::{error, info, LogSubsystem};
+#[cfg(feature = "log")]
+const LOG_SUBSYSTEM: LogSubsystem = LogSubsystem::Network;
 
 use quinn::{
     crypto::rustls::{QuicClientConfig, QuicServerConfig},
-    ClientConfig, Connection, Endpoint, IdleTimeout, Incoming, RecvStream, SendStream,
-    TransportConfig, VarInt,
+    ClientConfig, Connecting, Connection, Endpoint, IdleTimeout, Incoming, RecvStream, SendStream,
+    TransportConfig, VarInt, ZeroRttAccepted,
 };
 use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
 use std::{
@@ -12,7 +14,7 @@ use std::{
     net::SocketAddr,
     ops::Deref,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
@@ -22,12 +24,17 @@ use tokio::{
 use crate::{
     peer_connection_service::{PeerConnectionMessage, PeerConnectionService},
     security::{self, hash, new_uid, random_domain_name, MeetingToken, Uid},
-    synchronisation::{Answer, QueryProtocol, RemoteEvent},
+    synchronisation::{
+        file_transfer::{FileRequest, FILE_STREAM},
+        Answer, QueryProtocol, RemoteEvent,
+    },
 };
 
 use super::{
-    beacon::BeaconMessage, shared_buffers::SharedBuffers, Announce, ConnectionInfo, Error,
-    ALPN_QUIC_HTTP,
+    answer_fragment::{split_answers, AnswerFrame, AnswerReassembler},
+    beacon::{ApplicationId, BeaconMessage},
+    shared_buffers::SharedBuffers,
+    Announce, ConnectionInfo, Error, HandshakeStats, ALPN_QUIC_HTTP, PROTOCOL_VERSION,
 };
 
 static MAX_CONNECTION_RETRY: usize = 4;
@@ -41,6 +48,15 @@ static ANSWER_STREAM: u8 = 1;
 static QUERY_STREAM: u8 = 2;
 static EVENT_STREAM: u8 = 3;
 
+type ConnectionStreams = (
+    SendStream,
+    RecvStream,
+    SendStream,
+    RecvStream,
+    SendStream,
+    RecvStream,
+);
+
 pub enum EndpointMessage {
     InitiateConnection(SocketAddr, [u8; 32], Uid, MeetingToken, Vec<u8>),
     InitiateBeaconConnection(SocketAddr, [u8; 32]),
@@ -51,12 +67,14 @@ pub struct DiscretEndpoint {
     pub sender: mpsc::Sender<EndpointMessage>,
     pub ipv4_port: u16,
     pub ipv4_cert_hash: [u8; 32],
+    endpoint: Endpoint,
 }
 impl DiscretEndpoint {
     pub async fn start(
         peer_service: PeerConnectionService,
         max_buffer_size: usize,
         local_verifying_key: &[u8],
+        application_id: ApplicationId,
     ) -> Result<Self, Error> {
         let cert_verifier = ServerCertVerifier::new();
         let endpoint_id = new_uid();
@@ -70,6 +88,7 @@ impl DiscretEndpoint {
         let ipv4_port = ipv4_endpoint.local_addr()?.port();
 
         let ipv4 = ipv4_endpoint.clone();
+        let endpoint = ipv4_endpoint.clone();
         let peer_s = peer_service.clone();
         let data_buffer = Arc::new(SharedBuffers::new());
         let shared_buffers = data_buffer.clone();
@@ -104,6 +123,7 @@ impl DiscretEndpoint {
                         Self::initiate_beacon_connection(
                             address,
                             cert_hash,
+                            application_id,
                             cert_verifier.clone(),
                             &peer_s,
                             &ipv4,
@@ -141,8 +161,31 @@ impl DiscretEndpoint {
             sender,
             ipv4_port,
             ipv4_cert_hash,
+            endpoint,
         })
     }
+
+    ///
+    /// moves the endpoint to a new UDP socket after a network change
+    ///
+    /// the connections initiated by this endpoint migrate to the new socket without being restarted,
+    /// the remote peers see the new address once the path is validated.
+    /// the port changes and must be announced again.
+    ///
+    pub fn rebind(&mut self) -> Result<(), Error> {
+        let socket = std::net::UdpSocket::bind("0.0.0.0:0")?;
+        self.endpoint.rebind(socket)?;
+        self.ipv4_port = self.endpoint.local_addr()?.port();
+        Ok(())
+    }
+
+    ///
+    /// closes every connection and stops accepting new ones
+    ///
+    pub async fn close(&self, error_code: u16) {
+        self.endpoint.close(VarInt::from(error_code), "".as_bytes());
+        self.endpoint.wait_idle().await;
+    }
     #[allow(clippy::too_many_arguments)]
     fn initiate_connection(
         cert_verifier: Arc<ServerCertVerifier>,
@@ -174,13 +217,14 @@ impl DiscretEndpoint {
 
         tokio::spawn(async move {
             for i in 0..MAX_CONNECTION_RETRY {
+                let connection_start = Instant::now();
                 let conn_result: Result<quinn::Connecting, quinn::ConnectError> =
                     endpoint.connect(address, &name);
 
                 match conn_result {
                     Ok(connecting) => {
-                        match connecting.await {
-                            Ok(conn) => {
+                        match Self::handshake(connecting).await {
+                            Ok((conn, zero_rtt)) => {
                                 let connnection_id = new_uid();
                                 let info = ConnectionInfo {
                                     endpoint_id,
@@ -192,6 +236,8 @@ impl DiscretEndpoint {
 
                                 if let Err(_e) = Self::start_connection(
                                     conn,
+                                    zero_rtt,
+                                    connection_start,
                                     &peer_service,
                                     &local_verifying_key,
                                     info,
@@ -263,31 +309,66 @@ impl DiscretEndpoint {
             }
         });
     }
+    ///
+    /// Uses 0-RTT when a session ticket is available for the remote peer, and performs a full handshake otherwise
+    ///
+    async fn handshake(
+        connecting: Connecting,
+    ) -> Result<(Connection, Option<ZeroRttAccepted>), quinn::ConnectionError> {
+        match connecting.into_0rtt() {
+            Ok((conn, accepted)) => Ok((conn, Some(accepted))),
+            Err(connecting) => Ok((connecting.await?, None)),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn start_connection(
         conn: Connection,
+        zero_rtt: Option<ZeroRttAccepted>,
+        connection_start: Instant,
         peer_service: &PeerConnectionService,
         local_verifying_key: &[u8],
         info: ConnectionInfo,
         shared_buffers: Arc<SharedBuffers>,
         max_buffer_size: usize,
     ) -> Result<(), Error> {
-        let (mut answer_send, answer_receiv) = conn.open_bi().await?;
-        answer_send.write_u8(ANSWER_STREAM).await?;
-
-        let (mut query_send, query_receiv) = conn.open_bi().await?;
-        query_send.write_u8(QUERY_STREAM).await?;
-
-        let (mut event_send, event_receiv) = conn.open_bi().await?;
-        event_send.write_u8(EVENT_STREAM).await?;
-
         let mut remote_con_msg = info.clone();
         remote_con_msg.peer_verifying_key = local_verifying_key.to_owned();
-
         let conn_info = bincode::serialize(&remote_con_msg)?;
-        event_send
-            .write_u32(conn_info.len().try_into().unwrap())
-            .await?;
-        event_send.write_all(&conn_info).await?;
+
+        //
+        // With 0-RTT, the streams are opened before the end of the handshake.
+        // Early data can be replayed, but it is safe here: the remote peer only reads the streams once the handshake is complete.
+        // If the remote peer rejects the early data, the streams have to be opened again.
+        //
+        let mut streams = Self::open_streams(&conn, &conn_info).await;
+        let zero_rtt = match zero_rtt {
+            Some(accepted) => {
+                let accepted = accepted.await;
+                if !accepted {
+                    streams = Self::open_streams(&conn, &conn_info).await;
+                }
+                accepted
+            }
+            None => false,
+        };
+        let (answer_send, answer_receiv, query_send, query_receiv, event_send, event_receiv) =
+            streams?;
+
+        let latency_ms = connection_start
+            .elapsed()
+            .as_millis()
+            .try_into()
+            .unwrap_or(u64::MAX);
+        peer_service
+            .handshake_stats(
+                info.peer_verifying_key.clone(),
+                HandshakeStats {
+                    latency_ms,
+                    zero_rtt,
+                },
+            )
+            .await;
 
         Self::start_channels(
             conn,
@@ -307,6 +388,32 @@ impl DiscretEndpoint {
         Ok(())
     }
 
+    async fn open_streams(conn: &Connection, conn_info: &[u8]) -> Result<ConnectionStreams, Error> {
+        let (mut answer_send, answer_receiv) = conn.open_bi().await?;
+        answer_send.write_u8(ANSWER_STREAM).await?;
+
+        let (mut query_send, query_receiv) = conn.open_bi().await?;
+        query_send.write_u8(QUERY_STREAM).await?;
+
+        let (mut event_send, event_receiv) = conn.open_bi().await?;
+        event_send.write_u8(EVENT_STREAM).await?;
+        event_send.write_u8(PROTOCOL_VERSION).await?;
+
+        event_send
+            .write_u32(conn_info.len().try_into().unwrap())
+            .await?;
+        event_send.write_all(conn_info).await?;
+
+        Ok((
+            answer_send,
+            answer_receiv,
+            query_send,
+            query_receiv,
+            event_send,
+            event_receiv,
+        ))
+    }
+
     async fn start_accepted(
         peer_service: &PeerConnectionService,
         incoming: Incoming,
@@ -350,6 +457,15 @@ impl DiscretEndpoint {
         let event_send = event_send.unwrap();
         let mut event_receiv = event_receiv.unwrap();
 
+        let version = event_receiv.read_u8().await?;
+        if version != PROTOCOL_VERSION {
+            new_conn.close(
+                VarInt::from_u32(1),
+                "unsupported protocol version".as_bytes(),
+            );
+            return Err(Error::UnsupportedProtocolVersion(version, PROTOCOL_VERSION));
+        }
+
         let len = event_receiv.read_u32().await?;
         let len: usize = len.try_into().unwrap();
         let mut buf = vec![0; len];
@@ -394,6 +510,7 @@ impl DiscretEndpoint {
         let (in_answer_sd, in_answer_rcv) = mpsc::channel::<Answer>(CHANNEL_SIZE);
         let shared_b = shared_buffers.clone();
         tokio::spawn(async move {
+            let mut reassembler = AnswerReassembler::new();
             loop {
                 let len = answer_receiv.read_u32().await;
                 if len.is_err() {
@@ -416,45 +533,58 @@ impl DiscretEndpoint {
                     break;
                 }
 
-                let answer: Result<Answer, Box<bincode::ErrorKind>> =
+                let frame: Result<AnswerFrame, Box<bincode::ErrorKind>> =
                     bincode::deserialize(&buffer[0..len]);
 
-                if answer.is_err() {
+                if frame.is_err() {
                     shared_b.release(buffer);
                     break;
                 }
                 shared_b.release(buffer);
 
-                let answer = answer.unwrap();
-                let _ = in_answer_sd.send(answer).await;
+                let answers = match reassembler.push(frame.unwrap()) {
+                    Ok(answers) => answers,
+                    Err(_e) => {
+                        #[cfg(feature = "log")]
+                        error!("Answer stream, Error: {_e}");
+                        break;
+                    }
+                };
+                for answer in answers {
+                    let _ = in_answer_sd.send(answer).await;
+                }
             }
         });
 
-        let (out_answer_sd, mut out_answer_rcv) = mpsc::channel::<Answer>(CHANNEL_SIZE);
+        //answers are sent in batches to reduce the per message overhead
+        let (out_answer_sd, mut out_answer_rcv) = mpsc::channel::<Vec<Answer>>(CHANNEL_SIZE);
         let shared_b = shared_buffers.clone();
         tokio::spawn(async move {
-            while let Some(answer) = out_answer_rcv.recv().await {
-                let mut buffer = shared_b.take();
-                buffer.clear();
-
-                let serialised =
-                    bincode::serialize_into::<&mut Vec<u8>, Answer>(&mut buffer, &answer);
-                if serialised.is_err() {
-                    shared_b.release(buffer);
-                    break;
-                }
+            'stream: while let Some(answers) = out_answer_rcv.recv().await {
+                //oversized answers are fragmented to fit in the remote peer buffer
+                for frame in split_answers(answers, max_buffer_size) {
+                    let mut buffer = shared_b.take();
+                    buffer.clear();
+
+                    let serialised =
+                        bincode::serialize_into::<&mut Vec<u8>, AnswerFrame>(&mut buffer, &frame);
+                    if serialised.is_err() {
+                        shared_b.release(buffer);
+                        break 'stream;
+                    }
 
-                let sent = answer_send.write_u32(buffer.len() as u32).await;
-                if sent.is_err() {
-                    shared_b.release(buffer);
-                    break;
-                }
-                let sent = answer_send.write_all(&buffer).await;
-                if sent.is_err() {
+                    let sent = answer_send.write_u32(buffer.len() as u32).await;
+                    if sent.is_err() {
+                        shared_b.release(buffer);
+                        break 'stream;
+                    }
+                    let sent = answer_send.write_all(&buffer).await;
+                    if sent.is_err() {
+                        shared_b.release(buffer);
+                        break 'stream;
+                    }
                     shared_b.release(buffer);
-                    break;
                 }
-                shared_b.release(buffer);
             }
         });
 
@@ -595,6 +725,31 @@ impl DiscretEndpoint {
             }
         });
 
+        //files are transfered on dedicated streams, opened by the remote peer for each request
+        let (file_request_sd, file_request_rcv) = mpsc::channel::<FileRequest>(CHANNEL_SIZE);
+        let file_conn = conn.clone();
+        tokio::spawn(async move {
+            while let Ok((send, mut recv)) = file_conn.accept_bi().await {
+                let file_request_sd = file_request_sd.clone();
+                tokio::spawn(async move {
+                    match recv.read_u8().await {
+                        Ok(flag) if flag.eq(&FILE_STREAM) => {
+                            match FileRequest::read(send, recv).await {
+                                Ok(request) => {
+                                    let _ = file_request_sd.send(request).await;
+                                }
+                                Err(_e) => {
+                                    #[cfg(feature = "log")]
+                                    error!("FileRequest::read, Error: {_e}");
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                });
+            }
+        });
+
         let _ = peer_service
             .sender
             .send(PeerConnectionMessage::NewConnection(
@@ -606,6 +761,7 @@ impl DiscretEndpoint {
                 in_query_rcv,
                 out_event_sd,
                 in_event_rcv,
+                file_request_rcv,
             ))
             .await;
     }
@@ -613,6 +769,7 @@ impl DiscretEndpoint {
     pub async fn initiate_beacon_connection(
         address: SocketAddr,
         cert_hash: [u8; 32],
+        application_id: ApplicationId,
         cert_verifier: Arc<ServerCertVerifier>,
         peer_service: &PeerConnectionService,
         ipv4_endpoint: &Endpoint,
@@ -634,7 +791,9 @@ impl DiscretEndpoint {
             match conn_result {
                 Ok(connecting) => match connecting.await {
                     Ok(conn) => {
-                        if let Err(e) = Self::start_beacon_client(conn, &peer_service).await {
+                        if let Err(e) =
+                            Self::start_beacon_client(conn, application_id, &peer_service).await
+                        {
                             let _ = &peer_service
                                 .sender
                                 .send(PeerConnectionMessage::BeaconConnectionFailed(
@@ -669,10 +828,12 @@ impl DiscretEndpoint {
 
     pub async fn start_beacon_client(
         conn: Connection,
+        application_id: ApplicationId,
         peer_service: &PeerConnectionService,
     ) -> Result<(), Error> {
         let (mut beacon_send_stream, mut beacon_recv_stream) = conn.open_bi().await?;
-        beacon_send_stream.write_u8(ANSWER_STREAM).await?;
+        beacon_send_stream.write_u8(PROTOCOL_VERSION).await?;
+        beacon_send_stream.write_all(&application_id).await?;
 
         let (beacon_send, mut beacon_recv) = mpsc::channel::<Announce>(1);
 
@@ -773,11 +934,15 @@ pub fn build_endpoint(
         .with_single_cert(vec![cert_der], priv_key.into())?;
 
     server_crypto.alpn_protocols = ALPN_QUIC_HTTP.iter().map(|&x| x.into()).collect();
+    //quinn requires this exact value to enable 0-RTT
+    server_crypto.max_early_data_size = u32::MAX;
 
     let mut server_config =
         quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(server_crypto)?));
     let transport_config = Arc::get_mut(&mut server_config.transport).unwrap();
     transport_config.max_concurrent_uni_streams(0_u8.into());
+    //accepted connections follow the client when its address changes (roaming, NAT rebinding)
+    server_config.migration(true);
 
     let mut endpoint = Endpoint::server(server_config, bind_addr)?;
     endpoint.set_default_client_config(client_tls_config(cert_verifier)?);
@@ -791,6 +956,9 @@ fn client_tls_config(cert_verifier: Arc<ServerCertVerifier>) -> Result<ClientCon
         .with_no_client_auth();
 
     tls_config.alpn_protocols = ALPN_QUIC_HTTP.iter().map(|&x| x.into()).collect();
+    //session tickets are kept in memory by the default resumption store and are not persisted:
+    //the remote peers generate a new certificate and new ticket keys at each startup, a persisted ticket would never be accepted
+    tls_config.enable_early_data = true;
 
     let quick_client_config = Arc::new(QuicClientConfig::try_from(tls_config)?);
 
@@ -814,6 +982,7 @@ lazy_static::lazy_static! {
 pub struct ServerCertVerifier {
     provider: rustls::crypto::CryptoProvider,
     valid_certificates: std::sync::Mutex<HashMap<String, [u8; 32]>>,
+    certificate_names: std::sync::Mutex<HashMap<[u8; 32], String>>,
 }
 
 impl ServerCertVerifier {
@@ -821,10 +990,20 @@ impl ServerCertVerifier {
         Arc::new(ServerCertVerifier {
             provider: rustls::crypto::ring::default_provider(),
             valid_certificates: std::sync::Mutex::new(HashMap::new()),
+            certificate_names: std::sync::Mutex::new(HashMap::new()),
         })
     }
 
+    ///
+    /// returns the server name to use when connecting to the endpoint owning the certificate
+    ///
+    /// A certificate always gets the same random name: TLS session resumption (and 0-RTT) requires the server name to be unchanged between connections
+    ///
     pub fn add_valid_certificate(&self, certificate: [u8; 32]) -> String {
+        let mut names = self.certificate_names.lock().unwrap();
+        if let Some(name) = names.get(&certificate) {
+            return name.clone();
+        }
         let mut v = self.valid_certificates.lock().unwrap();
         let mut name = random_domain_name();
         while v.contains_key(&name) {
@@ -832,6 +1011,7 @@ impl ServerCertVerifier {
         }
 
         v.insert(name.clone(), certificate);
+        names.insert(certificate, name.clone());
         name
     }
 
@@ -1002,6 +1182,50 @@ mod tests {
         println!("{}: end", now());
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn connection_migration() {
+        let addr = "0.0.0.0:0".parse().unwrap();
+        let cert = security::generate_x509_certificate("server_migration.me");
+        let hasshe = hash(cert.cert.der().deref());
+        let cert_verifier = ServerCertVerifier::new();
+        let name = cert_verifier.add_valid_certificate(hasshe);
+
+        let server = build_endpoint(addr, cert, cert_verifier.clone()).unwrap();
+        let server_port = server.local_addr().unwrap().port();
+        let (sender, mut receiver) = mpsc::channel::<(i32, SocketAddr)>(2);
+        tokio::spawn(async move {
+            let conn = server.accept().await.unwrap().await.unwrap();
+            while let Ok((_, mut recv)) = conn.accept_bi().await {
+                let number = recv.read_i32().await.unwrap();
+                sender.send((number, conn.remote_address())).await.unwrap();
+            }
+        });
+
+        let cert = security::generate_x509_certificate("client_migration.me");
+        let client = build_endpoint(addr, cert, cert_verifier).unwrap();
+        let conn = client
+            .connect(format!("127.0.0.1:{}", server_port).parse().unwrap(), &name)
+            .unwrap()
+            .await
+            .unwrap();
+
+        let (mut send, _) = conn.open_bi().await.unwrap();
+        send.write_i32(1).await.unwrap();
+        let (number, address) = receiver.recv().await.unwrap();
+        assert_eq!(1, number);
+
+        //the connection continues on the new socket
+        client
+            .rebind(std::net::UdpSocket::bind("0.0.0.0:0").unwrap())
+            .unwrap();
+        let (mut send, _) = conn.open_bi().await.unwrap();
+        send.write_i32(2).await.unwrap();
+        let (number, migrated) = receiver.recv().await.unwrap();
+        assert_eq!(2, number);
+        assert_ne!(address.port(), migrated.port());
+        assert!(conn.close_reason().is_none());
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_connection_ipv6() {
         let addr = "[::]:0".parse().unwrap();
@@ -1036,6 +1260,56 @@ mod tests {
         endpoint.wait_idle().await;
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn zero_rtt_resumption() {
+        let addr = "0.0.0.0:0".parse().unwrap();
+
+        let cert = security::generate_x509_certificate("server.me");
+        let server_hash = hash(cert.cert.der().deref());
+        let cert_verifier = ServerCertVerifier::new();
+
+        let server = build_endpoint(addr, cert, cert_verifier.clone()).unwrap();
+        let server_address = format!("127.0.0.1:{}", server.local_addr().unwrap().port())
+            .parse()
+            .unwrap();
+        tokio::spawn(async move {
+            while let Some(incoming) = server.accept().await {
+                tokio::spawn(async move {
+                    let new_conn = incoming.await.unwrap();
+                    let (mut send, mut receiv) = new_conn.accept_bi().await.unwrap();
+                    let number = receiv.read_i32().await.unwrap();
+                    send.write_i32(number).await.unwrap();
+                    let _ = new_conn.closed().await;
+                });
+            }
+        });
+
+        let cert = security::generate_x509_certificate("client.me");
+        let client = build_endpoint(addr, cert, cert_verifier.clone()).unwrap();
+
+        //first connection: no session ticket yet, a full handshake is required
+        let name = cert_verifier.add_valid_certificate(server_hash);
+        let connecting = client.connect(server_address, &name).unwrap();
+        let (conn, zero_rtt) = DiscretEndpoint::handshake(connecting).await.unwrap();
+        assert!(zero_rtt.is_none());
+
+        let (mut send, mut receiv) = conn.open_bi().await.unwrap();
+        send.write_i32(1).await.unwrap();
+        assert_eq!(1, receiv.read_i32().await.unwrap());
+        conn.close(VarInt::from(0_u8), "".as_bytes());
+
+        let name = cert_verifier.add_valid_certificate(server_hash);
+        let connecting = client.connect(server_address, &name).unwrap();
+        let (conn, zero_rtt) = DiscretEndpoint::handshake(connecting).await.unwrap();
+        let zero_rtt = zero_rtt.expect("a session ticket should be available");
+
+        let (mut send, mut receiv) = conn.open_bi().await.unwrap();
+        send.write_i32(2).await.unwrap();
+        assert!(zero_rtt.await);
+        assert_eq!(2, receiv.read_i32().await.unwrap());
+        conn.close(VarInt::from(0_u8), "".as_bytes());
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_invalid_server_name() {
         let addr = "[::]:0".parse().unwrap();
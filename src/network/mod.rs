@@ -1,18 +1,24 @@
+pub mod answer_fragment;
 pub mod beacon;
+pub mod dns;
 pub mod endpoint;
 pub mod multicast;
 pub mod peer_manager;
 pub mod shared_buffers;
 use serde::{Deserialize, Serialize};
 
-use std::io;
+use std::{collections::HashSet, io};
 use thiserror::Error;
 
-use crate::security::{MeetingToken, Uid};
+use crate::security::{import_verifying_key, MeetingToken, Uid};
 
 //Application-Layer Protocol Negotiation (ALPN). Use the tag used for HTTP/3 over QUIC v1
 pub const ALPN_QUIC_HTTP: &[&[u8]] = &[b"h3"];
 
+//version of the frames exchanged with the peers and the beacons, sent first by the side that opens the connection
+//must be increased by any change of the wire format, like the batched and the fragmented answers
+pub const PROTOCOL_VERSION: u8 = 2;
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ConnectionInfo {
     pub endpoint_id: Uid,
@@ -22,6 +28,71 @@ pub struct ConnectionInfo {
     pub peer_verifying_key: Vec<u8>,
 }
 
+///
+/// Handshake measurement of the last outbound connection to a peer
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HandshakeStats {
+    ///
+    /// time in milliseconds between the connection attempt and the completion of the TLS handshake
+    ///
+    pub latency_ms: u64,
+
+    ///
+    /// true when a session ticket received during a previous connection allowed the connection to send data in 0-RTT
+    ///
+    pub zero_rtt: bool,
+}
+
+///
+/// Origin of a meeting token announced on the local network and to the beacons
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum MeetingTokenKind {
+    ///
+    /// token shared with an allowed peer
+    ///
+    AllowedPeer,
+
+    ///
+    /// token of an invite created by this peer and not yet accepted
+    ///
+    OwnedInvite,
+
+    ///
+    /// token of an invite received from another peer and not yet used to connect
+    ///
+    Invite,
+}
+
+///
+/// A meeting token currently announced by this peer
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MeetingTokenInfo {
+    ///
+    /// base64 encoded token
+    ///
+    pub token: String,
+
+    pub kind: MeetingTokenKind,
+
+    ///
+    /// base64 encoded verifying key of the allowed peer
+    ///
+    pub peer: Option<String>,
+
+    ///
+    /// default room granted to the peer accepting an owned invite
+    ///
+    pub room: Option<String>,
+
+    ///
+    /// true when at least one connection currently uses this token
+    ///
+    pub connected: bool,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct AnnounceHeader {
     endpoint_id: Uid,
@@ -41,6 +112,51 @@ impl AnnounceHeader {
 pub struct Announce {
     pub header: AnnounceHeader,
     pub tokens: Vec<MeetingToken>,
+    pub registration: Option<TokenRegistration>,
+}
+impl Announce {
+    ///
+    /// hash signed by the token registration
+    ///
+    pub fn registration_hash(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"TOKEN_REGISTRATION");
+        hasher.update(&self.header.hash());
+        for token in &self.tokens {
+            hasher.update(token);
+        }
+        *hasher.finalize().as_bytes()
+    }
+
+    ///
+    /// true if the tokens are registered by one of the owners
+    ///
+    pub fn is_registered_by(&self, owners: &HashSet<Vec<u8>>) -> bool {
+        let registration = match &self.registration {
+            Some(registration) => registration,
+            None => return false,
+        };
+        if registration.verifying_key.is_empty() || !owners.contains(&registration.verifying_key) {
+            return false;
+        }
+        match import_verifying_key(&registration.verifying_key) {
+            Ok(key) => key
+                .verify(&self.registration_hash(), &registration.signature)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+///
+/// Registration of the announced meeting tokens, signed by the owner of the tokens
+///
+/// Beacons can be configured to only match the tokens registered by approved owners
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TokenRegistration {
+    pub verifying_key: Vec<u8>,
+    pub signature: Vec<u8>,
 }
 
 #[derive(Error, Debug)]
@@ -84,6 +200,9 @@ pub enum Error {
     #[error("Message size {0} is to long and is ignored. Maximum allowed: {1}")]
     MsgDeserialisationToLong(usize, usize),
 
+    #[error("Invalid answer fragment: {0}")]
+    InvalidFragment(String),
+
     #[error("IPV6 is not supported on this device")]
     IPV6NotSuported(),
 
@@ -93,12 +212,18 @@ pub enum Error {
     #[error("Invalid Stream flag: {0}")]
     InvalidStream(u8),
 
+    #[error("Unsupported protocol version: {0}, expected: {1}")]
+    UnsupportedProtocolVersion(u8, u8),
+
     #[error("One or several Streams are missing")]
     MissingStream(),
 
     #[error("{0}")]
     UnacceptableBehavior(String),
 
+    #[error("DNS discovery failed: {0}")]
+    Dns(String),
+
     #[error("{0}")]
     Unknown(String),
 }
@@ -1,5 +1,7 @@
 #[cfg(feature = "log")]
-use log::error;
+use crate::log_level::{error, LogSubsystem};
+#[cfg(feature = "log")]
+const LOG_SUBSYSTEM: LogSubsystem = LogSubsystem::Network;
 
 use super::{Announce, AnnounceHeader, Error};
 use crate::peer_connection_service::{PeerConnectionMessage, PeerConnectionService};
@@ -61,10 +63,15 @@ pub async fn start_multicast_discovery(
 
             match rec {
                 Ok((msg, adress)) => {
-                    let _ = peer_service
+                    if peer_service
                         .sender
                         .send(PeerConnectionMessage::MulticastMessage(msg, adress))
-                        .await;
+                        .await
+                        .is_err()
+                    {
+                        //the peer connection service is stopped
+                        break;
+                    }
                 }
                 Err(_e) => {
                     #[cfg(feature = "log")]
@@ -1,5 +1,7 @@
 #[cfg(feature = "log")]
-use log::{error, info};
+use crate::log_level::{error, info, LogSubsystem};
+#[cfg(feature = "log")]
+const LOG_SUBSYSTEM: LogSubsystem = LogSubsystem::Network;
 
 use quinn::{Connection, VarInt};
 use std::{
@@ -18,10 +20,14 @@ use crate::{
     discret::{DiscretParams, DiscretServices},
     network::endpoint::EndpointMessage,
     security::{uid_encode, HardwareFingerprint, MeetingSecret, MeetingToken, Uid},
+    synchronisation::peer_outbound_service::QueryLimiter,
     DefaultRoom, Error, Parameters, ParametersAdd,
 };
 
-use super::{endpoint::DiscretEndpoint, multicast::MulticastMessage, Announce, AnnounceHeader};
+use super::{
+    endpoint::DiscretEndpoint, multicast::MulticastMessage, Announce, AnnounceHeader,
+    HandshakeStats, MeetingTokenInfo, MeetingTokenKind, TokenRegistration,
+};
 
 #[derive(Clone)]
 pub enum TokenType {
@@ -35,6 +41,9 @@ const REASON_CONN_ELECTION: u16 = 1;
 //the remote peer does not hav to knwow why the connection is closed
 pub const REASON_UNKNOWN: u16 = 2;
 
+//the local peer is stopping
+const REASON_SHUTDOWN: u16 = 3;
+
 pub const MAX_MESSAGE_SIZE: usize = 4096;
 //with a 7 byte token, fits into a 4096 message
 pub const MAX_ANNOUNCE_TOKENS: usize = 512;
@@ -44,6 +53,7 @@ const DERIVE_STRING: &str = "P";
 pub struct BeaconInfo {
     pub cert_hash: [u8; 32],
     pub header: AnnounceHeader,
+    pub register_tokens: bool,
     pub retry: u8,
 }
 
@@ -73,8 +83,13 @@ pub struct PeerManager {
     connected: HashMap<[u8; 32], (Connection, Uid, MeetingToken)>,
     connected_tokens: HashMap<MeetingToken, HashSet<[u8; 32]>>,
     local_circuit: HashSet<[u8; 32]>,
+    remote_addresses: HashMap<[u8; 32], SocketAddr>,
     beacons: HashMap<SocketAddr, BeaconInfo>,
     connected_beacons: HashMap<SocketAddr, mpsc::Sender<Announce>>,
+    clock_offsets: HashMap<Vec<u8>, i64>,
+    handshake_stats: HashMap<Vec<u8>, HandshakeStats>,
+    query_limiter: QueryLimiter,
+    entity_subscriptions: HashMap<Uid, Vec<String>>,
     services: DiscretServices,
 }
 impl PeerManager {
@@ -89,29 +104,11 @@ impl PeerManager {
             .database
             .get_allowed_peers(params.private_room_id)
             .await?;
-        let mut allowed_token: HashMap<MeetingToken, Vec<TokenType>> = HashMap::new();
-
-        for peer in &allowed_peers {
-            let token = MeetingSecret::decode_token(&peer.meeting_token)?;
-            let entry = allowed_token.entry(token).or_default();
-            entry.push(TokenType::AllowedPeer(peer.clone()));
-        }
-
         let owned_invites =
             OwnedInvite::list_valid(uid_encode(&params.private_room_id), &services.database)
                 .await?;
-        for owned in &owned_invites {
-            let token = MeetingSecret::derive_token(DERIVE_STRING, &owned.id);
-            let entry = allowed_token.entry(token).or_default();
-            entry.push(TokenType::OwnedInvite(owned.clone()));
-        }
         let invites = Invite::list(uid_encode(&params.private_room_id), &services.database).await?;
-        for invite in &invites {
-            let uid = &invite.invite_id;
-            let token = MeetingSecret::derive_token(DERIVE_STRING, uid);
-            let entry = allowed_token.entry(token).or_default();
-            entry.push(TokenType::Invite(invite.clone()));
-        }
+        let allowed_token = Self::index_tokens(&allowed_peers, &owned_invites, &invites)?;
 
         let multicast = if let Some(multicast_discovery) = multicast_discovery {
             // let probe_value = random32();
@@ -121,7 +118,7 @@ impl PeerManager {
                 certificate_hash: endpoint.ipv4_cert_hash,
                 signature: Vec::new(),
             };
-            let (_verifying, signature) = services.database.sign(header.hash().to_vec()).await;
+            let (_verifying, signature) = services.database.sign(header.hash().to_vec()).await?;
             header.signature = signature;
 
             Some(MulticastInfo {
@@ -148,18 +145,55 @@ impl PeerManager {
             connected_tokens: HashMap::new(),
             connection_progress: HashMap::new(),
             local_circuit: HashSet::new(),
+            remote_addresses: HashMap::new(),
             beacons: HashMap::new(),
             connected_beacons: HashMap::new(),
+            clock_offsets: HashMap::new(),
+            handshake_stats: HashMap::new(),
+            query_limiter: QueryLimiter::new(&params.configuration, services.events.clone()),
+            entity_subscriptions: HashMap::new(),
             services: services.clone(),
         })
     }
 
+    fn index_tokens(
+        allowed_peers: &[AllowedPeer],
+        owned_invites: &[OwnedInvite],
+        invites: &[Invite],
+    ) -> Result<HashMap<MeetingToken, Vec<TokenType>>, crate::Error> {
+        let mut allowed_token: HashMap<MeetingToken, Vec<TokenType>> = HashMap::new();
+
+        for peer in allowed_peers {
+            let token = MeetingSecret::decode_token(&peer.meeting_token)?;
+            let entry = allowed_token.entry(token).or_default();
+            entry.push(TokenType::AllowedPeer(peer.clone()));
+        }
+
+        for owned in owned_invites {
+            let token = MeetingSecret::derive_token(DERIVE_STRING, &owned.id);
+            let entry = allowed_token.entry(token).or_default();
+            entry.push(TokenType::OwnedInvite(owned.clone()));
+        }
+
+        for invite in invites {
+            let token = MeetingSecret::derive_token(DERIVE_STRING, &invite.invite_id);
+            let entry = allowed_token.entry(token).or_default();
+            entry.push(TokenType::Invite(invite.clone()));
+        }
+        Ok(allowed_token)
+    }
+
     pub async fn add_beacon(
         &mut self,
         hostname: &str,
         cert_hash: &str,
+        register_tokens: bool,
     ) -> Result<(), crate::Error> {
         for address in tokio::net::lookup_host(&hostname).await? {
+            if self.beacons.contains_key(&address) {
+                //allready known, for example when a discovered beacon is also configured
+                continue;
+            }
             let local_cert_has = if address.is_ipv4() {
                 self.endpoint.ipv4_cert_hash
             } else {
@@ -172,7 +206,8 @@ impl PeerManager {
                 certificate_hash: local_cert_has,
                 signature: Vec::new(),
             };
-            let (_verifying, signature) = self.services.database.sign(header.hash().to_vec()).await;
+            let (_verifying, signature) =
+                self.services.database.sign(header.hash().to_vec()).await?;
             header.signature = signature;
 
             let deserialized = base64_decode(cert_hash.as_bytes())?;
@@ -186,6 +221,7 @@ impl PeerManager {
                 BeaconInfo {
                     cert_hash,
                     header,
+                    register_tokens,
                     retry: 0,
                 },
             );
@@ -229,6 +265,7 @@ impl PeerManager {
             let ipv4_announce = Announce {
                 header: multicast.header.clone(),
                 tokens: tokens.clone(),
+                registration: None,
             };
             multicast
                 .sender
@@ -242,10 +279,7 @@ impl PeerManager {
 
         for (address, sender) in &self.connected_beacons {
             if let Some(info) = self.beacons.get(address) {
-                let announce = Announce {
-                    header: info.header.clone(),
-                    tokens: tokens.clone(),
-                };
+                let announce = self.beacon_announce(info, tokens.clone()).await;
                 let _ = sender.send(announce).await;
             }
         }
@@ -253,6 +287,31 @@ impl PeerManager {
         Ok(())
     }
 
+    ///
+    /// the tokens are registered with the local identity when required by the beacon configuration
+    ///
+    async fn beacon_announce(&self, info: &BeaconInfo, tokens: Vec<MeetingToken>) -> Announce {
+        let mut announce = Announce {
+            header: info.header.clone(),
+            tokens,
+            registration: None,
+        };
+        if info.register_tokens {
+            if let Ok((verifying_key, signature)) = self
+                .services
+                .database
+                .sign(announce.registration_hash().to_vec())
+                .await
+            {
+                announce.registration = Some(TokenRegistration {
+                    verifying_key,
+                    signature,
+                });
+            }
+        }
+        announce
+    }
+
     pub async fn multicast_announce(
         &mut self,
         a: Announce,
@@ -402,6 +461,19 @@ impl PeerManager {
         Ok(())
     }
 
+    ///
+    /// closes every connection before stopping
+    ///
+    pub async fn shutdown(&mut self) {
+        for (conn, _, _) in self.connected.values() {
+            conn.close(VarInt::from(REASON_SHUTDOWN), "".as_bytes());
+        }
+        self.connected.clear();
+        self.connected_tokens.clear();
+        self.connected_beacons.clear();
+        self.endpoint.close(REASON_SHUTDOWN).await;
+    }
+
     pub fn is_local_circuit(&self, circuit_id: &[u8; 32]) -> bool {
         self.local_circuit.contains(circuit_id)
     }
@@ -415,6 +487,8 @@ impl PeerManager {
         token: MeetingToken,
     ) {
         self.connection_progress.remove(&circuit_id);
+        self.remote_addresses
+            .insert(circuit_id, conn.remote_address());
 
         if let Some((old_conn, old_conn_id, token)) = self.connected.remove(&circuit_id) {
             if old_conn_id > conn_id {
@@ -436,6 +510,29 @@ impl PeerManager {
         }
     }
 
+    ///
+    /// closes a connection refused by the connection policy, a new connection can be attempted later
+    ///
+    pub fn reject_connection(&mut self, circuit_id: [u8; 32], conn: Option<Connection>) {
+        self.connection_progress.remove(&circuit_id);
+        if let Some(conn) = conn {
+            conn.close(VarInt::from(REASON_UNKNOWN), "".as_bytes());
+        }
+    }
+
+    ///
+    /// bytes sent and received on a connection
+    ///
+    pub fn connection_bytes(&self, circuit_id: &[u8; 32], conn_id: &Uid) -> Option<(u64, u64)> {
+        match self.connected.get(circuit_id) {
+            Some((conn, uid, _)) if uid.eq(conn_id) => {
+                let stats = conn.stats();
+                Some((stats.udp_tx.bytes, stats.udp_rx.bytes))
+            }
+            _ => None,
+        }
+    }
+
     pub fn disconnect(
         &mut self,
         circuit_id: [u8; 32],
@@ -468,10 +565,46 @@ impl PeerManager {
         }
         if !self.connected.contains_key(&circuit_id) {
             self.local_circuit.remove(&circuit_id);
+            self.remote_addresses.remove(&circuit_id);
         }
         disconnected
     }
 
+    ///
+    /// moves the endpoint to a new socket after a network change and announces the new port
+    ///
+    /// the connections initiated by this device migrate without restarting their synchronisation
+    ///
+    pub async fn network_changed(&mut self) -> Result<(), crate::Error> {
+        self.endpoint.rebind()?;
+        self.send_annouces().await
+    }
+
+    ///
+    /// updates the address of the connections that migrated to a new path
+    ///
+    /// a migrated connection keeps its circuit, its room locks and its synchronisation state.
+    /// it is no longer considered to be on the local network, as the new path is unknown.
+    ///
+    /// returns the number of migrated connections
+    ///
+    pub fn reconcile_addresses(&mut self) -> usize {
+        let mut migrated = 0;
+        for (circuit_id, (conn, _, _)) in &self.connected {
+            let address = conn.remote_address();
+            if let Some(previous) = self.remote_addresses.get_mut(circuit_id) {
+                if *previous != address {
+                    #[cfg(feature = "log")]
+                    info!("connection migrated from {} to {}", previous, address);
+                    *previous = address;
+                    self.local_circuit.remove(circuit_id);
+                    migrated += 1;
+                }
+            }
+        }
+        migrated
+    }
+
     pub fn clean_progress(&mut self, endpoint_id: Uid, remote_id: Uid) {
         let circuit_id = Self::circuit_id(endpoint_id, remote_id);
         self.connection_progress.remove(&circuit_id);
@@ -765,6 +898,48 @@ impl PeerManager {
         Ok(pending)
     }
 
+    ///
+    /// allows the peers regardless of the auto_allow_new_peers configuration, pending peers are enabled
+    ///
+    pub async fn allow_peers(&mut self, peers: Vec<Node>) -> Result<(), crate::Error> {
+        let room_id = uid_encode(&self.private_room_id);
+        let mut send_announce = false;
+        for peer in peers {
+            let pub_key = Peer::pub_key(&peer)?;
+            let peer_public: PublicKey = bincode::deserialize(&pub_key)?;
+            let token = self.meeting_secret.token(&peer_public);
+
+            let allowed = self.allowed_token.get(&token).is_some_and(|tokens| {
+                tokens
+                    .iter()
+                    .any(|tt| matches!(tt, TokenType::AllowedPeer(_)))
+            });
+            if allowed {
+                continue;
+            }
+
+            let verifying_key = base64_encode(&peer.verifying_key);
+            AllowedPeer::enable(&room_id, &verifying_key, &self.services.database).await?;
+            let allowed = AllowedPeer::add(
+                &room_id,
+                &verifying_key,
+                &base64_encode(&token),
+                Status::Enabled,
+                &self.services.database,
+            )
+            .await?;
+
+            let entry = self.allowed_token.entry(token).or_default();
+            entry.push(TokenType::AllowedPeer(allowed.clone()));
+            self.allowed_peers.push(allowed);
+            send_announce = true;
+        }
+        if send_announce {
+            self.send_annouces().await?;
+        }
+        Ok(())
+    }
+
     pub async fn beacon_connection_failed(&mut self, address: SocketAddr, _error: String) {
         if let Some(beacon) = self.beacons.get_mut(&address) {
             beacon.retry += 1;
@@ -807,10 +982,7 @@ impl PeerManager {
                 tokens.push(meeting_token);
             }
 
-            let announce = Announce {
-                header: info.header.clone(),
-                tokens: tokens.clone(),
-            };
+            let announce = self.beacon_announce(info, tokens).await;
             let _ = sender.send(announce).await;
             self.connected_beacons.insert(address, sender);
             #[cfg(feature = "log")]
@@ -838,6 +1010,125 @@ impl PeerManager {
         }
         self.connected_beacons.remove(&address);
     }
+
+    ///
+    /// store the last clock offset estimated for a peer during the connection handshake
+    ///
+    pub fn set_clock_offset(&mut self, verifying_key: Vec<u8>, offset: i64) {
+        self.clock_offsets.insert(verifying_key, offset);
+    }
+
+    pub fn clock_offset(&self, verifying_key: &Vec<u8>) -> Option<i64> {
+        self.clock_offsets.get(verifying_key).copied()
+    }
+
+    ///
+    /// store the handshake measurement of the last outbound connection to a peer
+    ///
+    pub fn set_handshake_stats(&mut self, verifying_key: Vec<u8>, stats: HandshakeStats) {
+        self.handshake_stats.insert(verifying_key, stats);
+    }
+
+    pub fn handshake_stats(&self, verifying_key: &Vec<u8>) -> Option<HandshakeStats> {
+        self.handshake_stats.get(verifying_key).cloned()
+    }
+
+    ///
+    /// the inbound query limiter, shared by every connection
+    ///
+    pub fn query_limiter(&self) -> QueryLimiter {
+        self.query_limiter.clone()
+    }
+
+    ///
+    /// the entities of a room the peers have to notify, an empty list removes the subscription
+    ///
+    pub fn subscribe_entities(&mut self, room_id: Uid, entities: Vec<String>) {
+        if entities.is_empty() {
+            self.entity_subscriptions.remove(&room_id);
+        } else {
+            self.entity_subscriptions.insert(room_id, entities);
+        }
+    }
+
+    ///
+    /// the entity subscriptions, sent to the new connections
+    ///
+    pub fn entity_subscriptions(&self) -> HashMap<Uid, Vec<String>> {
+        self.entity_subscriptions.clone()
+    }
+
+    ///
+    /// list the meeting tokens that are currently announced
+    ///
+    pub fn meeting_tokens(&self) -> Vec<MeetingTokenInfo> {
+        let mut list = Vec::new();
+        for (token, token_types) in &self.allowed_token {
+            let connected = self
+                .connected_tokens
+                .get(token)
+                .is_some_and(|circuits| !circuits.is_empty());
+            for token_type in token_types {
+                let (kind, peer, room) = match token_type {
+                    TokenType::AllowedPeer(allowed) => (
+                        MeetingTokenKind::AllowedPeer,
+                        Some(allowed.peer.verifying_key.clone()),
+                        None,
+                    ),
+                    TokenType::OwnedInvite(owned) => (
+                        MeetingTokenKind::OwnedInvite,
+                        None,
+                        owned.room.as_ref().map(uid_encode),
+                    ),
+                    TokenType::Invite(_) => (MeetingTokenKind::Invite, None, None),
+                };
+                list.push(MeetingTokenInfo {
+                    token: base64_encode(token),
+                    kind,
+                    peer,
+                    room,
+                    connected,
+                });
+            }
+        }
+        list
+    }
+
+    ///
+    /// Removes the stale meeting tokens:
+    /// - allowed peers that are no longer enabled, and duplicated allowed peer entries
+    /// - owned and received invites that where created before the 'older_than' date
+    ///
+    /// returns the number of removed tokens
+    ///
+    pub async fn prune_tokens(&mut self, older_than: i64) -> Result<usize, crate::Error> {
+        let room_id = uid_encode(&self.private_room_id);
+        let database = &self.services.database;
+
+        OwnedInvite::delete_older_than(room_id.clone(), older_than, database).await?;
+        Invite::delete_older_than(room_id.clone(), older_than, database).await?;
+
+        let mut allowed_peers = database.get_allowed_peers(self.private_room_id).await?;
+        let mut unique = HashSet::new();
+        allowed_peers.retain(|peer| unique.insert(peer.meeting_token.clone()));
+
+        let owned_invites = OwnedInvite::list_valid(room_id.clone(), database).await?;
+        let invites = Invite::list(room_id, database).await?;
+
+        let before = self.allowed_peers.len() + self.owned_invites.len() + self.invites.len();
+        let after = allowed_peers.len() + owned_invites.len() + invites.len();
+
+        self.allowed_token = Self::index_tokens(&allowed_peers, &owned_invites, &invites)?;
+        self.allowed_peers = allowed_peers;
+        self.owned_invites = owned_invites;
+        self.invites = invites;
+
+        let removed = before.saturating_sub(after);
+        if removed > 0 {
+            self.send_annouces().await?;
+        }
+        Ok(removed)
+    }
     pub async fn beacon_initiate_connection(
         &mut self,
         address: SocketAddr,
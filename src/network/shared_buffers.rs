@@ -12,10 +12,7 @@ impl SharedBuffers {
 
     pub fn take(&self) -> Vec<u8> {
         let mut buff = self.buffers.lock().unwrap();
-        match buff.pop() {
-            Some(v) => v,
-            None => Vec::new(),
-        }
+        buff.pop().unwrap_or_default()
 
         //todo
     }
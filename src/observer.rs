@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+
+use tokio::sync::broadcast;
+
+use crate::{
+    configuration::Configuration,
+    database::{
+        graph_database::GraphDatabaseService, query_language::parameter::Parameters,
+        replication::DataAvailability, search::SearchHit, Encoding,
+    },
+    discret::DiscretServices,
+    event_service::{Event, EventReceiver, EventService},
+    security::{base64_decode, base64_encode, import_verifying_key, uid_decode},
+    signature_verification_service::SignatureVerificationService,
+    synchronisation::archive::{self, ArchiveImport},
+    Error,
+};
+
+///
+/// Read-only access to Discret, for dashboards, kiosk displays or auditors.
+///
+/// The observer is identified by a verifying key and never holds a signing key:
+/// the database rejects every local mutation with an error, whatever the API used.
+///
+/// An administrator of the observed rooms adds the verifying key as a user of an authorisation that does not define any right.
+///
+/// Without a signing key, the observer cannot prove its identity to other peers and does not connect to them.
+/// It is synchronised with the archives exported by a peer of the rooms, see *Discret::export_rooms()*.
+/// The archive content is verified: only the data signed by peers allowed to write in the rooms is inserted.
+///
+/// The database of the observer is encrypted with a storage secret provided by the application.
+/// This secret only protects the data at rest: unlike the key material of *Discret*, it does not derive any signing key.
+///
+#[derive(Clone)]
+pub struct DiscretObserver {
+    verifying_key: Vec<u8>,
+    services: DiscretServices,
+}
+impl DiscretObserver {
+    ///
+    /// Starts the observer with the following parameters:
+    ///- datamodel: the data model of the observed application,
+    ///- app_key: the unique identifier of the observed application,
+    ///- verifying_key: the identity of the observer, as returned by *Discret::verifying_key()*,
+    ///- storage_secret: a 32 bytes secret used to encrypt the database, it must be kept by the application like a key material,
+    ///- data_folder: where data is stored, unused when the configuration storage is Storage::Memory
+    ///- configuration: the configuration stucture
+    ///
+    pub async fn new(
+        datamodel: &str,
+        app_key: &str,
+        verifying_key: &str,
+        storage_secret: &[u8; 32],
+        data_folder: PathBuf,
+        configuration: Configuration,
+    ) -> Result<Self, Error> {
+        if configuration.error_privacy_mode {
+            crate::set_error_privacy_mode(true);
+        }
+        let verifying_key = base64_decode(verifying_key.as_bytes())?;
+        import_verifying_key(&verifying_key)?;
+
+        let event_service: EventService =
+            EventService::with_buffer_size(configuration.event_buffer_size);
+        let database_service = GraphDatabaseService::start_observer(
+            app_key,
+            datamodel,
+            &verifying_key,
+            storage_secret,
+            data_folder,
+            &configuration,
+            event_service.clone(),
+        )
+        .await?;
+        let verify_service = SignatureVerificationService::start(configuration.parallelism);
+
+        Ok(Self {
+            verifying_key,
+            services: DiscretServices {
+                events: event_service,
+                database: database_service,
+                signature_verification: verify_service,
+            },
+        })
+    }
+
+    ///
+    /// Perform a query to retrieve results from the database.
+    /// returns the result in a JSON object
+    ///
+    pub async fn query(&self, q: &str, p: Option<Parameters>) -> Result<String, Error> {
+        Ok(self.services.database.query(q, p).await?)
+    }
+
+    ///
+    /// Perform a query and returns the result with the requested encoding, see *Discret::query_bytes()*
+    ///
+    pub async fn query_bytes(
+        &self,
+        q: &str,
+        p: Option<Parameters>,
+        encoding: Encoding,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(self.services.database.query_bytes(q, p, encoding).await?)
+    }
+
+    ///
+    /// Searches a term in every entity indexed for full text search, see *Discret::search_all()*
+    ///
+    pub async fn search_all(
+        &self,
+        term: &str,
+        rooms: Vec<String>,
+    ) -> Result<Vec<SearchHit>, Error> {
+        let mut room_ids = Vec::with_capacity(rooms.len());
+        for room in &rooms {
+            room_ids.push(uid_decode(room)?);
+        }
+        Ok(self
+            .services
+            .database
+            .search_all(term, room_ids, Vec::new())
+            .await?)
+    }
+
+    ///
+    /// Imports an archive created by *Discret::export_rooms()*, see *Discret::import_archive()*
+    ///
+    /// The rooms of the archive are unknown to a new observer: *accept_new_rooms* must be set to import them.
+    ///
+    pub async fn import_archive(
+        &self,
+        path: PathBuf,
+        pass_phrase: &str,
+        accept_new_rooms: bool,
+    ) -> Result<ArchiveImport, Error> {
+        archive::import_archive(&path, pass_phrase, accept_new_rooms, &self.services).await
+    }
+
+    ///
+    /// The identity of the observer, to be added to the authorisations of the observed rooms
+    ///
+    pub fn verifying_key(&self) -> String {
+        base64_encode(&self.verifying_key)
+    }
+
+    ///
+    /// Subscribe for the event queue
+    ///
+    pub async fn subscribe_for_events(&self) -> broadcast::Receiver<Event> {
+        self.services.events.subcribe().await
+    }
+
+    ///
+    /// Subscribe for the event queue, see *Discret::event_receiver()*
+    ///
+    pub async fn event_receiver(&self) -> EventReceiver {
+        self.services.events.receiver().await
+    }
+
+    ///
+    /// Provide a JSON representation of the datamodel
+    ///
+    pub async fn data_model(&self) -> Result<String, Error> {
+        Ok(self.services.database.datamodel().await?)
+    }
+
+    ///
+    /// Estimates how many known peers hold each entity day of a *Room*, see *Discret::availability()*
+    ///
+    pub async fn availability(&self, room_id: &str) -> Result<Vec<DataAvailability>, Error> {
+        let room_id = uid_decode(room_id)?;
+        Ok(self.services.database.availability(room_id).await?)
+    }
+
+    ///
+    /// Stops the observer, the pending writes are flushed to the database
+    ///
+    pub async fn shutdown(&self) -> Result<(), Error> {
+        Ok(self.services.database.shutdown().await?)
+    }
+}
@@ -1,8 +1,10 @@
 #[cfg(feature = "log")]
-use log::error;
+use crate::log_level::{debug, error, LogSubsystem};
+#[cfg(feature = "log")]
+const LOG_SUBSYSTEM: LogSubsystem = LogSubsystem::Network;
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     net::{Ipv4Addr, SocketAddr},
     sync::{atomic::AtomicBool, Arc},
     time::Duration,
@@ -11,20 +13,23 @@ use std::{
 use quinn::Connection;
 
 use crate::{
-    database::node::Node,
+    configuration::{BeaconConfig, ConnectionPolicy},
+    database::{node::Node, replication::PeerSyncUpdate},
     date_utils::now,
     discret::{DiscretParams, DiscretServices},
     event_service::{Event, EventServiceMessage},
     network::{
+        beacon, dns,
         endpoint::DiscretEndpoint,
         multicast::{self, MulticastMessage},
         peer_manager::{self, PeerManager, TokenType},
-        Announce, AnnounceHeader, ConnectionInfo,
+        Announce, AnnounceHeader, ConnectionInfo, HandshakeStats, MeetingTokenInfo,
     },
-    security::{uid_decode, HardwareFingerprint, MeetingSecret, MeetingToken, Uid},
+    security::{new_uid, uid_decode, HardwareFingerprint, MeetingSecret, MeetingToken, Uid},
     synchronisation::{
-        peer_inbound_service::{LocalPeerService, QueryService},
-        peer_outbound_service::{InboundQueryService, RemotePeerHandle},
+        file_transfer::FileRequest,
+        peer_inbound_service::{LocalPeerService, QueryService, SyncTuning},
+        peer_outbound_service::{InboundQueryService, PeerQueryStats, RemotePeerHandle},
         room_locking_service::RoomLockService,
         Answer, LocalEvent, QueryProtocol, RemoteEvent,
     },
@@ -39,20 +44,34 @@ pub enum PeerConnectionMessage {
     NewConnection(
         Option<Connection>,
         ConnectionInfo,
-        mpsc::Sender<Answer>,
+        mpsc::Sender<Vec<Answer>>,
         mpsc::Receiver<Answer>,
         mpsc::Sender<QueryProtocol>,
         mpsc::Receiver<QueryProtocol>,
         mpsc::Sender<RemoteEvent>,
         mpsc::Receiver<RemoteEvent>,
+        mpsc::Receiver<FileRequest>,
     ),
     PeerConnectionFailed(Uid, Uid),
     PeerConnected(Vec<u8>, Uid),
+    ClockOffset(Vec<u8>, i64),
+    PeerIdentity(Node),
+    GetClockOffset(Vec<u8>, oneshot::Sender<Option<i64>>),
+    HandshakeStats(Vec<u8>, HandshakeStats),
+    GetHandshakeStats(Vec<u8>, oneshot::Sender<Option<HandshakeStats>>),
+    GetQueryStats(Vec<u8>, oneshot::Sender<Option<PeerQueryStats>>),
+    MeetingTokens(oneshot::Sender<Vec<MeetingTokenInfo>>),
+    PruneTokens(i64, oneshot::Sender<Result<usize>>),
     PeerDisconnected(Vec<u8>, [u8; 32], Uid),
     ValidateHardware([u8; 32], HardwareFingerprint, oneshot::Sender<Result<bool>>),
     InviteAccepted(TokenType, Node),
     NewPeer(Vec<Node>),
+    AllowPeers(Vec<Node>, oneshot::Sender<Result<()>>),
+    AnswerConnectionRequest(Vec<u8>, bool, oneshot::Sender<()>),
+    ConnectionRequestExpired(Vec<u8>, Uid),
     SendAnnounce(),
+    NetworkChanged(oneshot::Sender<Result<()>>),
+    SubscribeEntities(Uid, Vec<String>, oneshot::Sender<()>),
     MulticastMessage(MulticastMessage, SocketAddr),
     CreateInvite(Option<DefaultRoom>, oneshot::Sender<Result<Vec<u8>>>),
     AcceptInvite(Vec<u8>),
@@ -60,10 +79,40 @@ pub enum PeerConnectionMessage {
     BeaconConnected(SocketAddr, mpsc::Sender<Announce>),
     BeaconDisconnected(SocketAddr),
     BeaconInitiateConnection(SocketAddr, AnnounceHeader, MeetingToken),
+    AddBeacons(Vec<BeaconConfig>),
+    Shutdown(oneshot::Sender<()>),
 }
 
 static PEER_CHANNEL_SIZE: usize = 32;
 
+//time given to the application to answer a connection request
+const CONNECTION_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+//a new connection and its channels
+struct IncomingConnection {
+    connection: Option<Connection>,
+    connection_info: ConnectionInfo,
+    answer_sender: mpsc::Sender<Vec<Answer>>,
+    answer_receiver: mpsc::Receiver<Answer>,
+    query_sender: mpsc::Sender<QueryProtocol>,
+    query_receiver: mpsc::Receiver<QueryProtocol>,
+    event_sender: mpsc::Sender<RemoteEvent>,
+    event_receiver: mpsc::Receiver<RemoteEvent>,
+    file_request_receiver: mpsc::Receiver<FileRequest>,
+    token_type: TokenType,
+}
+
+//
+// decisions of the application for the connection_policy configuration
+//
+#[derive(Default)]
+struct ConnectionRequests {
+    accepted: HashSet<Vec<u8>>,
+    rejected: HashSet<Vec<u8>>,
+    //connections waiting for an answer, identified by a request id to ignore outdated timeouts
+    pending: HashMap<Vec<u8>, (Uid, Vec<IncomingConnection>)>,
+}
+
 ///
 /// Handle the creation and removal of peers
 ///
@@ -90,6 +139,7 @@ impl PeerConnectionService {
             peer_service.clone(),
             max_buffer_size as usize,
             &params.verifying_key,
+            beacon::application_id(&params.app_key),
         )
         .await?;
 
@@ -124,9 +174,30 @@ impl PeerConnectionService {
         if params.configuration.enable_beacons {
             for beacon in &params.configuration.beacons {
                 peer_manager
-                    .add_beacon(&beacon.hostname, &beacon.cert_hash)
+                    .add_beacon(&beacon.hostname, &beacon.cert_hash, beacon.register_tokens)
                     .await?;
             }
+
+            if let Some(domain) = &params.configuration.beacon_domain {
+                //DNS discovery can be slow and should not delay the startup
+                let domain = domain.clone();
+                let dns_server = params.configuration.dns_server.clone();
+                let service = peer_service.clone();
+                tokio::spawn(async move {
+                    match dns::discover_beacons(&domain, dns_server.as_deref()).await {
+                        Ok(beacons) => {
+                            let _ = service
+                                .sender
+                                .send(PeerConnectionMessage::AddBeacons(beacons))
+                                .await;
+                        }
+                        Err(_e) => {
+                            #[cfg(feature = "log")]
+                            error!("Beacon discovery for domain {domain} failed: {_e}");
+                        }
+                    }
+                });
+            }
         }
 
         let service = peer_service.clone();
@@ -137,10 +208,14 @@ impl PeerConnectionService {
 
             loop {
                 interval.tick().await;
-                let _ = service
+                if service
                     .sender
                     .send(PeerConnectionMessage::SendAnnounce())
-                    .await;
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
             }
         });
 
@@ -148,14 +223,26 @@ impl PeerConnectionService {
         let discret_service = services.clone();
         tokio::spawn(async move {
             let mut event_receiver = discret_service.events.subcribe().await;
+            let mut connection_requests = ConnectionRequests::default();
             loop {
                 tokio::select! {
                     msg = connection_receiver.recv() =>{
                         match msg{
+                            Some(PeerConnectionMessage::Shutdown(reply)) =>{
+                                peer_manager.shutdown().await;
+                                let _ = reply.send(());
+                                break;
+                            },
+                            Some(PeerConnectionMessage::SubscribeEntities(room_id, entities, reply)) =>{
+                                peer_manager.subscribe_entities(room_id, entities.clone());
+                                let _ = local_event_broadcast.send(LocalEvent::EntitySubscription(room_id, entities));
+                                let _ = reply.send(());
+                            },
                             Some(msg) =>{
                                 let err = Self::process_peer_message(
                                     msg,
                                     &mut peer_manager,
+                                    &mut connection_requests,
                                     &discret_params,
                                     &discret_service,
                                     &peer_service,
@@ -214,6 +301,46 @@ impl PeerConnectionService {
             .await;
     }
 
+    pub async fn clock_offset(&self, verifying_key: Vec<u8>, offset: i64) {
+        let _ = self
+            .sender
+            .send(PeerConnectionMessage::ClockOffset(verifying_key, offset))
+            .await;
+    }
+
+    ///
+    /// the sys.Peer received in the identity proof, it carries the peer metadata
+    ///
+    pub async fn peer_identity(&self, peer: Node) {
+        let _ = self
+            .sender
+            .send(PeerConnectionMessage::PeerIdentity(peer))
+            .await;
+    }
+
+    pub async fn handshake_stats(&self, verifying_key: Vec<u8>, stats: HandshakeStats) {
+        let _ = self
+            .sender
+            .send(PeerConnectionMessage::HandshakeStats(verifying_key, stats))
+            .await;
+    }
+
+    ///
+    /// accepts or rejects the connections of a peer, see *ConnectionPolicy*
+    ///
+    pub async fn answer_connection_request(&self, verifying_key: Vec<u8>, accept: bool) {
+        let (reply, receive) = oneshot::channel::<()>();
+        let _ = self
+            .sender
+            .send(PeerConnectionMessage::AnswerConnectionRequest(
+                verifying_key,
+                accept,
+                reply,
+            ))
+            .await;
+        let _ = receive.await;
+    }
+
     pub async fn invite_accepted(&self, token: TokenType, peer: Node) {
         let _ = self
             .sender
@@ -221,9 +348,11 @@ impl PeerConnectionService {
             .await;
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn process_peer_message(
         msg: PeerConnectionMessage,
         peer_manager: &mut PeerManager,
+        connection_requests: &mut ConnectionRequests,
         discret_params: &DiscretParams,
         discret_services: &DiscretServices,
         peer_service: &PeerConnectionService,
@@ -240,64 +369,135 @@ impl PeerConnectionService {
                 query_receiver,
                 event_sender,
                 event_receiver,
+                file_request_receiver,
             ) => {
-                let circuit_id =
-                    PeerManager::circuit_id(connection_info.endpoint_id, connection_info.remote_id);
-
                 let token_type = peer_manager.get_token_type(
                     &connection_info.meeting_token,
                     &connection_info.peer_verifying_key,
                 )?;
-
-                if let Some(conn) = connection {
-                    peer_manager.add_connection(
-                        circuit_id,
-                        conn,
-                        connection_info.conn_id,
-                        connection_info.meeting_token,
-                    )
+                let incoming = IncomingConnection {
+                    connection,
+                    connection_info,
+                    answer_sender,
+                    answer_receiver,
+                    query_sender,
+                    query_receiver,
+                    event_sender,
+                    event_receiver,
+                    file_request_receiver,
+                    token_type,
                 };
 
-                let remote_verifying_key: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
-                let conn_ready = Arc::new(AtomicBool::new(true));
+                //invites are an explicit consent, and the own devices are validated with their hardware fingerprint
+                let verifying_key = incoming.connection_info.peer_verifying_key.clone();
+                let governed = matches!(incoming.token_type, TokenType::AllowedPeer(_))
+                    && !verifying_key.eq(&discret_params.verifying_key);
 
-                let inbound_query_service = InboundQueryService::start(
-                    discret_params.hardware_fingerprint.clone(),
-                    circuit_id,
-                    connection_info.conn_id,
-                    RemotePeerHandle {
-                        db: discret_services.database.clone(),
-                        allowed_room: HashSet::new(),
-                        verifying_key: discret_params.verifying_key.clone(),
-                        reply: answer_sender,
-                    },
-                    query_receiver,
-                    peer_service.clone(),
-                    remote_verifying_key.clone(),
-                    conn_ready.clone(),
-                );
+                let policy = &discret_params.configuration.connection_policy;
+                if !governed
+                    || policy.eq(&ConnectionPolicy::AutoAccept)
+                    || connection_requests.accepted.contains(&verifying_key)
+                {
+                    Self::start_connection(
+                        incoming,
+                        peer_manager,
+                        discret_params,
+                        discret_services,
+                        peer_service,
+                        lock_service,
+                        local_event_broadcast,
+                    );
+                } else if policy.eq(&ConnectionPolicy::Deny)
+                    || connection_requests.rejected.contains(&verifying_key)
+                {
+                    #[cfg(feature = "log")]
+                    debug!(
+                        "connection of peer {} rejected",
+                        crate::security::base64_encode(&verifying_key)
+                    );
+                    Self::reject_connection(incoming, peer_manager);
+                } else if let Some((_, pending)) =
+                    connection_requests.pending.get_mut(&verifying_key)
+                {
+                    pending.push(incoming);
+                } else {
+                    let request_id = new_uid();
+                    connection_requests
+                        .pending
+                        .insert(verifying_key.clone(), (request_id, vec![incoming]));
 
-                let query_service = QueryService::start(query_sender, answer_receiver);
+                    let _ = discret_services
+                        .events
+                        .sender
+                        .send(EventServiceMessage::ConnectionRequest(
+                            verifying_key.clone(),
+                        ))
+                        .await;
 
-                LocalPeerService::start(
-                    event_receiver,
-                    local_event_broadcast,
-                    circuit_id,
-                    connection_info.clone(),
-                    discret_params.verifying_key.clone(),
-                    token_type,
-                    remote_verifying_key.clone(),
-                    conn_ready,
-                    lock_service.clone(),
-                    query_service,
-                    event_sender.clone(),
-                    peer_service.clone(),
-                    inbound_query_service,
-                    &discret_services,
-                );
+                    let service = peer_service.clone();
+                    tokio::spawn(async move {
+                        time::sleep(CONNECTION_REQUEST_TIMEOUT).await;
+                        let _ = service
+                            .sender
+                            .send(PeerConnectionMessage::ConnectionRequestExpired(
+                                verifying_key,
+                                request_id,
+                            ))
+                            .await;
+                    });
+                }
+            }
+
+            PeerConnectionMessage::AnswerConnectionRequest(verifying_key, accept, reply) => {
+                let pending = connection_requests.pending.remove(&verifying_key);
+                if accept {
+                    connection_requests.rejected.remove(&verifying_key);
+                    connection_requests.accepted.insert(verifying_key);
+                } else {
+                    connection_requests.accepted.remove(&verifying_key);
+                    connection_requests.rejected.insert(verifying_key);
+                }
+                if let Some((_, pending)) = pending {
+                    for incoming in pending {
+                        if accept {
+                            Self::start_connection(
+                                incoming,
+                                peer_manager,
+                                discret_params,
+                                discret_services,
+                                peer_service,
+                                lock_service,
+                                local_event_broadcast.resubscribe(),
+                            );
+                        } else {
+                            Self::reject_connection(incoming, peer_manager);
+                        }
+                    }
+                }
+                let _ = reply.send(());
+            }
+
+            PeerConnectionMessage::ConnectionRequestExpired(verifying_key, request_id) => {
+                let expired = connection_requests
+                    .pending
+                    .get(&verifying_key)
+                    .is_some_and(|(id, _)| id.eq(&request_id));
+                if expired {
+                    if let Some((_, pending)) = connection_requests.pending.remove(&verifying_key)
+                    {
+                        for incoming in pending {
+                            Self::reject_connection(incoming, peer_manager);
+                        }
+                    }
+                }
             }
 
             PeerConnectionMessage::PeerConnected(verifying_key, connection_id) => {
+                #[cfg(feature = "log")]
+                debug!(
+                    "peer {} connected",
+                    crate::security::base64_encode(&verifying_key)
+                );
                 let _ = discret_services
                     .events
                     .sender
@@ -309,7 +509,62 @@ impl PeerConnectionService {
                     .await;
             }
 
+            PeerConnectionMessage::ClockOffset(verifying_key, offset) => {
+                peer_manager.set_clock_offset(verifying_key.clone(), offset);
+                if offset.abs() > discret_params.configuration.clock_skew_warning_in_ms {
+                    let _ = discret_services
+                        .events
+                        .sender
+                        .send(EventServiceMessage::ClockSkew(verifying_key, offset))
+                        .await;
+                }
+            }
+
+            PeerConnectionMessage::PeerIdentity(peer) => {
+                if let Err(_e) = discret_services.database.update_peer_node(peer).await {
+                    #[cfg(feature = "log")]
+                    error!("PeerConnectionMessage::PeerIdentity error: {_e}");
+                }
+            }
+
+            PeerConnectionMessage::GetClockOffset(verifying_key, reply) => {
+                let _ = reply.send(peer_manager.clock_offset(&verifying_key));
+            }
+
+            PeerConnectionMessage::HandshakeStats(verifying_key, stats) => {
+                peer_manager.set_handshake_stats(verifying_key, stats);
+            }
+
+            PeerConnectionMessage::GetHandshakeStats(verifying_key, reply) => {
+                let _ = reply.send(peer_manager.handshake_stats(&verifying_key));
+            }
+
+            PeerConnectionMessage::GetQueryStats(verifying_key, reply) => {
+                let _ = reply.send(peer_manager.query_limiter().stats(&verifying_key));
+            }
+
+            PeerConnectionMessage::MeetingTokens(reply) => {
+                let _ = reply.send(peer_manager.meeting_tokens());
+            }
+
+            PeerConnectionMessage::PruneTokens(older_than, reply) => {
+                let _ = reply.send(peer_manager.prune_tokens(older_than).await);
+            }
+
             PeerConnectionMessage::PeerDisconnected(verifying_key, circuit_id, connection_id) => {
+                if let Some((bytes_sent, bytes_received)) =
+                    peer_manager.connection_bytes(&circuit_id, &connection_id)
+                {
+                    let update = PeerSyncUpdate {
+                        bytes_sent,
+                        bytes_received,
+                        ..PeerSyncUpdate::new(verifying_key.clone())
+                    };
+                    if let Err(_e) = discret_services.database.add_peer_sync_stats(update).await {
+                        #[cfg(feature = "log")]
+                        error!("PeerConnectionMessage::PeerDisconnected error: {_e}");
+                    }
+                }
                 if peer_manager.disconnect(
                     circuit_id,
                     connection_id,
@@ -348,7 +603,16 @@ impl PeerConnectionService {
                 }
             }
 
+            PeerConnectionMessage::AllowPeers(peers, reply) => {
+                let _ = reply.send(peer_manager.allow_peers(peers).await);
+            }
+
+            PeerConnectionMessage::NetworkChanged(reply) => {
+                let _ = reply.send(peer_manager.network_changed().await);
+            }
+
             PeerConnectionMessage::SendAnnounce() => {
+                peer_manager.reconcile_addresses();
                 if let Err(_e) = peer_manager.send_annouces().await {
                     #[cfg(feature = "log")]
                     error!("PeerConnectionMessage::SendAnnounce, error: {_e} ");
@@ -405,21 +669,112 @@ impl PeerConnectionService {
             PeerConnectionMessage::BeaconDisconnected(address) => {
                 peer_manager.beacon_disconnected(address).await;
             }
+            PeerConnectionMessage::AddBeacons(beacons) => {
+                for beacon in beacons {
+                    if let Err(_e) = peer_manager
+                        .add_beacon(&beacon.hostname, &beacon.cert_hash, beacon.register_tokens)
+                        .await
+                    {
+                        #[cfg(feature = "log")]
+                        error!(
+                            "PeerConnectionMessage::AddBeacons {}, error: {_e}",
+                            beacon.hostname
+                        );
+                    }
+                }
+            }
             PeerConnectionMessage::BeaconInitiateConnection(address, header, token) => {
                 peer_manager
                     .beacon_initiate_connection(address, header, token)
                     .await?;
             }
+            PeerConnectionMessage::Shutdown(_) | PeerConnectionMessage::SubscribeEntities(..) => {
+                unreachable!("handled by the service loop")
+            }
         }
         Ok(())
     }
 
+    fn start_connection(
+        incoming: IncomingConnection,
+        peer_manager: &mut PeerManager,
+        discret_params: &DiscretParams,
+        discret_services: &DiscretServices,
+        peer_service: &PeerConnectionService,
+        lock_service: &RoomLockService,
+        local_event_broadcast: broadcast::Receiver<LocalEvent>,
+    ) {
+        let connection_info = incoming.connection_info;
+        let circuit_id =
+            PeerManager::circuit_id(connection_info.endpoint_id, connection_info.remote_id);
+
+        let file_connection = incoming.connection.clone();
+        if let Some(conn) = incoming.connection {
+            peer_manager.add_connection(
+                circuit_id,
+                conn,
+                connection_info.conn_id,
+                connection_info.meeting_token,
+            )
+        };
+
+        let remote_verifying_key: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+        let conn_ready = Arc::new(AtomicBool::new(true));
+
+        let inbound_query_service = InboundQueryService::start(
+            discret_params.hardware_fingerprint.clone(),
+            circuit_id,
+            connection_info.conn_id,
+            RemotePeerHandle::new(
+                discret_services.database.clone(),
+                discret_params.verifying_key.clone(),
+                incoming.answer_sender,
+            ),
+            incoming.query_receiver,
+            incoming.file_request_receiver,
+            peer_service.clone(),
+            remote_verifying_key.clone(),
+            conn_ready.clone(),
+            peer_manager.query_limiter(),
+        );
+
+        let query_service = QueryService::start(incoming.query_sender, incoming.answer_receiver);
+
+        LocalPeerService::start(
+            incoming.event_receiver,
+            local_event_broadcast,
+            circuit_id,
+            connection_info.clone(),
+            discret_params.verifying_key.clone(),
+            incoming.token_type,
+            remote_verifying_key.clone(),
+            conn_ready,
+            lock_service.clone(),
+            query_service,
+            incoming.event_sender.clone(),
+            peer_service.clone(),
+            inbound_query_service,
+            peer_manager.entity_subscriptions(),
+            SyncTuning::new(&discret_params.configuration),
+            file_connection,
+            discret_services,
+        );
+    }
+
+    //the channels are dropped with the connection
+    fn reject_connection(incoming: IncomingConnection, peer_manager: &mut PeerManager) {
+        let info = &incoming.connection_info;
+        let circuit_id = PeerManager::circuit_id(info.endpoint_id, info.remote_id);
+        peer_manager.reject_connection(circuit_id, incoming.connection);
+    }
+
     async fn process_event(event: Event, local_event_broadcast: &broadcast::Sender<LocalEvent>) {
         match event {
             Event::DataChanged(data_modif) => {
                 let mut rooms = Vec::new();
                 for room in &data_modif.rooms {
-                    rooms.push(uid_decode(room.0).unwrap());
+                    let entities = room.1.keys().cloned().collect();
+                    rooms.push((uid_decode(room.0).unwrap(), entities));
                 }
                 let _ = local_event_broadcast.send(LocalEvent::RoomDataChanged(rooms));
             }
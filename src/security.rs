@@ -2,14 +2,16 @@ use std::{
     fs::File,
     io::{Read, Write},
     path::PathBuf,
+    sync::Mutex,
 };
 
 use crate::date_utils::now;
 use argon2::{self, Config, Variant, Version};
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD as enc64, Engine as _};
 use ed25519_dalek::{SignatureError, Signer, Verifier};
-use rand::{rngs::OsRng, RngCore};
+use rand::{rngs::OsRng, Rng, RngCore};
 use rcgen::{CertificateParams, KeyPair, SanType};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
 use serde::{Deserialize, Serialize};
 use sysinfo::System;
 use thiserror::Error;
@@ -40,6 +42,18 @@ pub enum Error {
 
     #[error("Invalid Base64 encoded MeetingToken")]
     MeetingToken(),
+
+    #[error("{0}")]
+    InvalidPassPhraseParams(String),
+
+    #[error("{0}")]
+    DeviceLink(String),
+
+    #[error("{0}")]
+    PinEncryption(String),
+
+    #[error("{0}")]
+    PassPhraseEncryption(String),
 }
 
 ///
@@ -177,9 +191,9 @@ impl SigningKey for Ed25519SigningKey {
 /// Defines the necessary function to verify data  
 ///
 pub trait VerifyingKey {
-    ///
-    /// Export the verifying key, adding a flag to detect the encryption scheme
-    ///
+    //
+    // Export the verifying key, adding a flag to detect the encryption scheme
+    //
     //  fn export(&self) -> Vec<u8>;
 
     ///
@@ -356,30 +370,343 @@ impl MeetingSecret {
 }
 
 ///
-/// Derive a password using argon2id
-///  using parameters slighly greater than the minimum recommended by OSWAP <https://cheatsheetseries.owasp.org/cheatsheets/Password_Storage_Cheat_Sheet.html>
+/// Argon2id cost parameters used by *derive_pass_phrase_with*
+///
+/// The default values are slighly greater than the minimum recommended by OSWAP <https://cheatsheetseries.owasp.org/cheatsheets/Password_Storage_Cheat_Sheet.html>
 /// - 20480 KB of memory
 /// - an iteration count of 2
 /// - parallelism count of 2
+///
+/// Changing the parameters changes the derived key material.
+/// Applications that want to raise the costs over time should store the *encode()* value of the parameters used to create an account,
+/// and use the decoded parameters to derive the key material of this account.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassPhraseParams {
+    /// memory cost in KB
+    pub mem_cost: u32,
+    /// number of iterations
+    pub time_cost: u32,
+    /// degree of parallelism
+    pub lanes: u32,
+}
+impl Default for PassPhraseParams {
+    fn default() -> Self {
+        Self {
+            mem_cost: 20480,
+            time_cost: 2,
+            lanes: 2,
+        }
+    }
+}
+impl PassPhraseParams {
+    /// Minimum memory cost in KB, as recommended by OSWAP
+    pub const MIN_MEM_COST: u32 = 19456;
+    /// Minimum number of iterations, as recommended by OSWAP
+    pub const MIN_TIME_COST: u32 = 2;
+
+    const ENCODING_VERSION: &'static str = "v1";
+
+    ///
+    /// Encode the parameters in a versioned string: "v1:m=20480,t=2,p=2"
+    ///
+    pub fn encode(&self) -> String {
+        format!(
+            "{}:m={},t={},p={}",
+            Self::ENCODING_VERSION,
+            self.mem_cost,
+            self.time_cost,
+            self.lanes
+        )
+    }
+
+    ///
+    /// Decode parameters encoded with *encode()*
+    ///
+    pub fn decode(encoded: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidPassPhraseParams(format!("'{}'", encoded));
+
+        let (version, values) = encoded.split_once(':').ok_or_else(invalid)?;
+        if version != Self::ENCODING_VERSION {
+            return Err(Error::InvalidPassPhraseParams(format!(
+                "unsupported version '{}'",
+                version
+            )));
+        }
+
+        let mut mem_cost = None;
+        let mut time_cost = None;
+        let mut lanes = None;
+        for value in values.split(',') {
+            let (name, value) = value.split_once('=').ok_or_else(invalid)?;
+            let value: u32 = value.parse().map_err(|_| invalid())?;
+            let param = match name {
+                "m" => &mut mem_cost,
+                "t" => &mut time_cost,
+                "p" => &mut lanes,
+                _ => return Err(invalid()),
+            };
+            if param.replace(value).is_some() {
+                return Err(invalid());
+            }
+        }
+
+        let params = Self {
+            mem_cost: mem_cost.ok_or_else(invalid)?,
+            time_cost: time_cost.ok_or_else(invalid)?,
+            lanes: lanes.ok_or_else(invalid)?,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+
+    ///
+    /// Ensure that the parameters are not weaker than the OSWAP recommendations
+    ///
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.mem_cost < Self::MIN_MEM_COST {
+            return Err(Error::InvalidPassPhraseParams(format!(
+                "memory cost must be at least {} KB",
+                Self::MIN_MEM_COST
+            )));
+        }
+        if self.time_cost < Self::MIN_TIME_COST {
+            return Err(Error::InvalidPassPhraseParams(format!(
+                "iteration count must be at least {}",
+                Self::MIN_TIME_COST
+            )));
+        }
+        if self.lanes == 0 {
+            return Err(Error::InvalidPassPhraseParams(
+                "parallelism must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+///
+/// Derive a password using argon2id and the default *PassPhraseParams*
 /// - the login is used as a salt
 ///
 pub fn derive_pass_phrase(login: &str, pass_phrase: &str) -> [u8; 32] {
+    derive_pass_phrase_with(login, pass_phrase, &PassPhraseParams::default()).unwrap()
+}
+
+///
+/// Derive a password using argon2id and the provided cost parameters
+/// - the login is used as a salt
+///
+pub fn derive_pass_phrase_with(
+    login: &str,
+    pass_phrase: &str,
+    params: &PassPhraseParams,
+) -> Result<[u8; 32], Error> {
+    params.validate()?;
     let password = pass_phrase.as_bytes();
     let salt = hash(login.as_bytes());
 
     let config = Config::<'_> {
-        mem_cost: 20480,
-        time_cost: 2,
+        mem_cost: params.mem_cost,
+        time_cost: params.time_cost,
         variant: Variant::Argon2id,
-        lanes: 2,
+        lanes: params.lanes,
         version: Version::Version13,
         ..Default::default()
     };
 
-    let hashed = argon2::hash_encoded(password, &salt, &config).unwrap();
+    let hashed = argon2::hash_encoded(password, &salt, &config)
+        .map_err(|e| Error::InvalidPassPhraseParams(e.to_string()))?;
     let matches = argon2::verify_encoded(&hashed, password).unwrap();
     assert!(matches);
-    hash(hashed.as_bytes())
+    Ok(hash(hashed.as_bytes()))
+}
+
+///
+/// prefix of the data encrypted with a PIN, followed by the salt and the nonce
+///
+const PIN_MARKER: &[u8; 4] = b"dPIN";
+///
+/// prefix of the data encrypted with a pass phrase, followed by the salt and the nonce
+///
+const PASS_PHRASE_MARKER: &[u8; 4] = b"dPWD";
+const SECRET_SALT_LEN: usize = 16;
+
+///
+/// The minimum estimated entropy of a PIN, in bits, see *pin_entropy()*
+///
+/// For example 11 digits, 8 lower case letters or 6 mixed case letters and digits.
+///
+pub const MIN_PIN_ENTROPY: f64 = 35.0;
+const PIN_LEN: usize = 12;
+
+///
+/// Estimates the entropy of a PIN, in bits
+///
+/// Every character is worth the size of the character classes used by the PIN: digits, lower case, upper case and others.
+/// A character that repeats the previous one is only worth one bit.
+///
+pub fn pin_entropy(pin: &str) -> f64 {
+    let mut alphabet = 0;
+    if pin.chars().any(|c| c.is_ascii_digit()) {
+        alphabet += 10;
+    }
+    if pin.chars().any(|c| c.is_ascii_lowercase()) {
+        alphabet += 26;
+    }
+    if pin.chars().any(|c| c.is_ascii_uppercase()) {
+        alphabet += 26;
+    }
+    if pin.chars().any(|c| !c.is_ascii_alphanumeric()) {
+        alphabet += 33;
+    }
+    if alphabet == 0 {
+        return 0.0;
+    }
+    let bits = (alphabet as f64).log2();
+
+    let mut previous = None;
+    let mut entropy = 0.0;
+    for c in pin.chars() {
+        if previous == Some(c) {
+            entropy += 1.0;
+        } else {
+            entropy += bits;
+        }
+        previous = Some(c);
+    }
+    entropy
+}
+
+///
+/// Generates a random PIN of 12 digits that is accepted by *encrypt_with_pin()*
+///
+pub fn new_pin() -> String {
+    loop {
+        let pin: String = (0..PIN_LEN)
+            .map(|_| char::from(b'0' + OsRng.gen_range(0..10u8)))
+            .collect();
+        if pin_entropy(&pin) >= MIN_PIN_ENTROPY {
+            return pin;
+        }
+    }
+}
+
+///
+/// Encrypt data with a short secret exchanged out of band, like a PIN read over the phone
+///
+/// The key is derived from the PIN and a random salt using argon2id, the data is encrypted with ChaCha20-Poly1305.
+///
+/// The encrypted data can be attacked offline: anyone that obtained it can try every PIN without interacting with its author,
+/// and the PIN only slows down the attack as every guess costs an argon2 derivation.
+/// The PINs below *MIN_PIN_ENTROPY* are rejected: finding a PIN at the minimum entropy still takes about 2^34 derivations on average.
+/// Use *new_pin()* to generate a PIN, and a pass phrase when the encrypted data could be kept by an attacker for a long time.
+///
+pub fn encrypt_with_pin(data: &[u8], pin: &str) -> Result<Vec<u8>, Error> {
+    if pin.is_empty() {
+        return Err(Error::PinEncryption("empty PIN".to_string()));
+    }
+    if pin_entropy(pin) < MIN_PIN_ENTROPY {
+        return Err(Error::PinEncryption(format!(
+            "the PIN is too weak, at least {} bits of entropy are required",
+            MIN_PIN_ENTROPY
+        )));
+    }
+    seal_with_secret(PIN_MARKER, data, pin).map_err(Error::PinEncryption)
+}
+
+///
+/// Decrypt data encrypted by *encrypt_with_pin*, fails when the PIN is invalid or the data was modified
+///
+pub fn decrypt_with_pin(data: &[u8], pin: &str) -> Result<Vec<u8>, Error> {
+    if !is_pin_encrypted(data) {
+        return Err(Error::PinEncryption(
+            "data is not encrypted with a PIN".to_string(),
+        ));
+    }
+    open_with_secret(PIN_MARKER, data, pin)
+        .map_err(|_| Error::PinEncryption("invalid PIN".to_string()))
+}
+
+///
+/// true when the data has been encrypted by *encrypt_with_pin*
+///
+pub fn is_pin_encrypted(data: &[u8]) -> bool {
+    is_sealed_with(PIN_MARKER, data)
+}
+
+///
+/// Encrypt data with a pass phrase, used for the data that leaves the database like the room archives
+///
+/// Same scheme as *encrypt_with_pin* with a distinct prefix: data encrypted with a PIN cannot be opened as a pass phrase encrypted data.
+///
+pub fn encrypt_with_pass_phrase(data: &[u8], pass_phrase: &str) -> Result<Vec<u8>, Error> {
+    if pass_phrase.is_empty() {
+        return Err(Error::PassPhraseEncryption("empty pass phrase".to_string()));
+    }
+    seal_with_secret(PASS_PHRASE_MARKER, data, pass_phrase).map_err(Error::PassPhraseEncryption)
+}
+
+///
+/// Decrypt data encrypted by *encrypt_with_pass_phrase*, fails when the pass phrase is invalid or the data was modified
+///
+pub fn decrypt_with_pass_phrase(data: &[u8], pass_phrase: &str) -> Result<Vec<u8>, Error> {
+    if !is_sealed_with(PASS_PHRASE_MARKER, data) {
+        return Err(Error::PassPhraseEncryption(
+            "data is not encrypted with a pass phrase".to_string(),
+        ));
+    }
+    open_with_secret(PASS_PHRASE_MARKER, data, pass_phrase)
+        .map_err(|_| Error::PassPhraseEncryption("invalid pass phrase".to_string()))
+}
+
+fn is_sealed_with(marker: &[u8; 4], data: &[u8]) -> bool {
+    data.len() > marker.len() + SECRET_SALT_LEN + NONCE_LEN && data.starts_with(marker)
+}
+
+fn seal_with_secret(marker: &[u8; 4], data: &[u8], secret: &str) -> Result<Vec<u8>, String> {
+    let mut salt = [0u8; SECRET_SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut encrypted = Vec::with_capacity(marker.len() + SECRET_SALT_LEN + NONCE_LEN + data.len());
+    encrypted.extend_from_slice(marker);
+    encrypted.extend_from_slice(&salt);
+
+    let key = secret_key(&salt, secret)?;
+    let mut in_out = data.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(&encrypted),
+        &mut in_out,
+    )
+    .map_err(|_| "encryption failed".to_string())?;
+
+    encrypted.extend_from_slice(&nonce);
+    encrypted.append(&mut in_out);
+    Ok(encrypted)
+}
+
+fn open_with_secret(marker: &[u8; 4], data: &[u8], secret: &str) -> Result<Vec<u8>, String> {
+    let header_len = marker.len() + SECRET_SALT_LEN;
+    let (header, rest) = data.split_at(header_len);
+    let (nonce, encrypted) = rest.split_at(NONCE_LEN);
+
+    let key = secret_key(&header[marker.len()..], secret)?;
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "invalid nonce".to_string())?;
+    let mut in_out = encrypted.to_vec();
+    let plain = key
+        .open_in_place(nonce, Aad::from(header), &mut in_out)
+        .map_err(|_| "invalid secret".to_string())?;
+    Ok(plain.to_vec())
+}
+
+fn secret_key(salt: &[u8], secret: &str) -> Result<LessSafeKey, String> {
+    let key = derive_pass_phrase_with(&base64_encode(salt), secret, &PassPhraseParams::default())
+        .map_err(|e| e.to_string())?;
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key).map_err(|_| "invalid key".to_string())?;
+    Ok(LessSafeKey::new(key))
 }
 
 ///
@@ -411,6 +738,55 @@ pub fn base64_decode(data: &[u8]) -> Result<Vec<u8>, Error> {
     enc64.decode(data).map_err(Error::from)
 }
 
+///
+/// Encoded length of the identifiers: uids, hashes, verifying keys and signatures
+///
+const IDENTIFIER_LENGTHS: [usize; 4] = [22, 43, 44, 86];
+
+fn is_base64_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+fn is_identifier(token: &str) -> bool {
+    IDENTIFIER_LENGTHS.contains(&token.len()) && base64_decode(token.as_bytes()).is_ok()
+}
+
+///
+/// returns the base 64 identifiers embedded in a message
+///
+pub fn find_identifiers(message: &str) -> Vec<String> {
+    message
+        .split(|c: char| !is_base64_char(c))
+        .filter(|token| is_identifier(token))
+        .map(|token| token.to_string())
+        .collect()
+}
+
+///
+/// replaces the base 64 identifiers embedded in a message with '<redacted>'
+///
+pub fn redact_identifiers(message: &str) -> String {
+    let mut redacted = String::with_capacity(message.len());
+    let mut start = 0;
+    for (end, c) in message
+        .char_indices()
+        .filter(|(_, c)| !is_base64_char(*c))
+        .chain(std::iter::once((message.len(), ' ')))
+    {
+        let token = &message[start..end];
+        if is_identifier(token) {
+            redacted.push_str("<redacted>");
+        } else {
+            redacted.push_str(token);
+        }
+        if end < message.len() {
+            redacted.push(c);
+        }
+        start = end + c.len_utf8();
+    }
+    redacted
+}
+
 pub const UID_SIZE: usize = 16;
 pub type Uid = [u8; UID_SIZE];
 const DEFAULT_UID: Uid = [0; UID_SIZE];
@@ -432,6 +808,47 @@ pub fn new_uid() -> Uid {
     uid
 }
 
+//last timestamp and counter used by new_time_ordered_uid()
+static TIME_ORDERED_STATE: Mutex<(i64, u16)> = Mutex::new((0, 0));
+
+///
+/// generate a 16 byte uid following the UUIDv7 layout: a 48 bits timestamp in milliseconds, a 12 bits counter and random bits.
+/// The uids generated by this process are strictly increasing, new rows are always appended at the end of the indexes
+///
+pub fn new_time_ordered_uid() -> Uid {
+    const MAX_COUNTER: u16 = 0x0FFF;
+    let (time, counter) = {
+        let mut state = TIME_ORDERED_STATE.lock().unwrap();
+        let time = now();
+        if time > state.0 {
+            *state = (time, 0);
+        } else if state.1 < MAX_COUNTER {
+            state.1 += 1;
+        } else {
+            //the counter is exhausted, borrow the next millisecond
+            *state = (state.0 + 1, 0);
+        }
+        *state
+    };
+
+    let mut uid = DEFAULT_UID;
+    OsRng.fill_bytes(&mut uid[8..]);
+    uid[0..6].copy_from_slice(&time.to_be_bytes()[2..]);
+    uid[6] = 0x70 | ((counter >> 8) as u8 & 0x0F);
+    uid[7] = counter as u8;
+    uid[8] = 0x80 | (uid[8] & 0x3F);
+    uid
+}
+
+///
+/// generate a fully random 16 byte uid that does not disclose its creation date
+///
+pub fn new_random_uid() -> Uid {
+    let mut uid = DEFAULT_UID;
+    OsRng.fill_bytes(&mut uid);
+    uid
+}
+
 /// derive a ket from a string context and a secret
 /// provided by the Blake3 hash function  
 ///
@@ -480,7 +897,7 @@ impl HardwareFingerprint {
         let id = if file_path.is_file() {
             let mut file = File::open(file_path)?;
             let mut uid = default_uid();
-            file.read(&mut uid)?;
+            file.read_exact(&mut uid)?;
             uid
         } else {
             let uid = new_uid();
@@ -488,7 +905,17 @@ impl HardwareFingerprint {
             file.write_all(&uid)?;
             uid
         };
+        Ok(Self::with_id(id))
+    }
+
+    ///
+    /// creates a fingerprint that is not stored on disk, used for in memory databases
+    ///
+    pub fn ephemeral() -> Self {
+        Self::with_id(new_uid())
+    }
 
+    fn with_id(id: Uid) -> Self {
         let mut name = "Unknown Device".to_string();
         if sysinfo::IS_SUPPORTED_SYSTEM {
             let host = System::host_name().unwrap_or_default();
@@ -498,7 +925,7 @@ impl HardwareFingerprint {
             name = format!("{osname}, {host}");
         };
 
-        Ok(Self { id, name })
+        Self { id, name }
     }
 }
 
@@ -507,6 +934,78 @@ mod tests {
     use std::fs;
 
     use super::*;
+    #[test]
+    fn pin_encryption() {
+        let data = b"invitation".to_vec();
+        let pin = "482193750614";
+        let encrypted = encrypt_with_pin(&data, pin).unwrap();
+        assert!(is_pin_encrypted(&encrypted));
+        assert!(!is_pin_encrypted(&data));
+        assert_eq!(data, decrypt_with_pin(&encrypted, pin).unwrap());
+        assert!(decrypt_with_pin(&encrypted, "482193750615").is_err());
+
+        let mut modified = encrypted.clone();
+        let last = modified.len() - 1;
+        modified[last] ^= 1;
+        assert!(decrypt_with_pin(&modified, pin).is_err());
+
+        //the same data and PIN never produce the same encrypted data
+        assert_ne!(encrypted, encrypt_with_pin(&data, pin).unwrap());
+        assert!(encrypt_with_pin(&data, "").is_err());
+
+        //weak PINs are rejected
+        assert!(encrypt_with_pin(&data, "4821").is_err());
+        assert!(encrypt_with_pin(&data, "111111111111").is_err());
+        assert!(pin_entropy("aB3x9Q") >= MIN_PIN_ENTROPY);
+        let pin = new_pin();
+        assert_eq!(PIN_LEN, pin.len());
+        assert!(pin.chars().all(|c| c.is_ascii_digit()));
+        assert!(encrypt_with_pin(&data, &pin).is_ok());
+
+        let encrypted = encrypt_with_pass_phrase(&data, "a long pass phrase").unwrap();
+        assert_eq!(
+            data,
+            decrypt_with_pass_phrase(&encrypted, "a long pass phrase").unwrap()
+        );
+        assert!(decrypt_with_pass_phrase(&encrypted, "another pass phrase").is_err());
+        //the two formats are not interchangeable
+        assert!(!is_pin_encrypted(&encrypted));
+        assert!(decrypt_with_pin(&encrypted, "a long pass phrase").is_err());
+        assert!(encrypt_with_pass_phrase(&data, "").is_err());
+    }
+
+    #[test]
+    fn redact() {
+        let room = uid_encode(&new_uid());
+        let key = base64_encode(&[1; 33]);
+        let message = format!("room: {room}, peer: '{key}' rejected synchronisation_of_room");
+
+        assert_eq!(vec![room, key], find_identifiers(&message));
+        assert_eq!(
+            "room: <redacted>, peer: '<redacted>' rejected synchronisation_of_room",
+            redact_identifiers(&message)
+        );
+        assert_eq!("", redact_identifiers(""));
+    }
+
+    #[test]
+    fn time_ordered_uid() {
+        let mut previous = new_time_ordered_uid();
+        for _ in 0..10000 {
+            let uid = new_time_ordered_uid();
+            assert!(uid > previous);
+            assert_eq!(0x70, uid[6] & 0xF0);
+            assert_eq!(0x80, uid[8] & 0xC0);
+            previous = uid;
+        }
+
+        let mut time = [0; 8];
+        time[2..].copy_from_slice(&previous[0..6]);
+        assert!(i64::from_be_bytes(time) >= now() - 1000);
+
+        assert_ne!(new_random_uid(), new_random_uid());
+    }
+
     #[test]
     fn control_derive_pass_phrase() {
         let login = "test";
@@ -520,6 +1019,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pass_phrase_params() {
+        let login = "test";
+        let pass_phrase = "testphrase";
+
+        let default = PassPhraseParams::default();
+        assert_eq!("v1:m=20480,t=2,p=2", default.encode());
+        assert_eq!(
+            default,
+            PassPhraseParams::decode(&default.encode()).unwrap()
+        );
+        assert_eq!(
+            derive_pass_phrase(login, pass_phrase),
+            derive_pass_phrase_with(login, pass_phrase, &default).unwrap()
+        );
+
+        let stronger = PassPhraseParams {
+            mem_cost: 32768,
+            time_cost: 3,
+            lanes: 1,
+        };
+        let decoded = PassPhraseParams::decode(&stronger.encode()).unwrap();
+        assert_eq!(stronger, decoded);
+        assert_ne!(
+            derive_pass_phrase(login, pass_phrase),
+            derive_pass_phrase_with(login, pass_phrase, &decoded).unwrap()
+        );
+
+        let weak = PassPhraseParams {
+            mem_cost: 1024,
+            ..Default::default()
+        };
+        derive_pass_phrase_with(login, pass_phrase, &weak).expect_err("memory cost too low");
+        PassPhraseParams::decode(&weak.encode()).expect_err("memory cost too low");
+
+        PassPhraseParams::decode("v2:m=20480,t=2,p=2").expect_err("unknown version");
+        PassPhraseParams::decode("v1:m=20480,t=2").expect_err("missing parallelism");
+        PassPhraseParams::decode("v1:m=20480,t=2,p=2,p=3").expect_err("duplicated parallelism");
+        PassPhraseParams::decode("v1:m=20480,t=two,p=2").expect_err("invalid number");
+    }
+
     #[test]
     fn control_hash() {
         assert_eq!(
@@ -546,7 +1086,7 @@ mod tests {
         );
 
         let msg = b"message to sign";
-        let signature = signing_key.sign(&msg.to_vec());
+        let signature = signing_key.sign(msg.as_ref());
 
         let keypair = import_signing_key(&exp_kp).unwrap();
 
@@ -0,0 +1,489 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::{
+    database::{
+        binary_store::binary_column,
+        edge::{Edge, EdgeDeletionEntry},
+        node::{Node, NodeDeletionEntry, NodeIdentifier},
+        room_node::RoomNode,
+        system_entities::{
+            AUTHORISATION_ENT_SHORT, ENTITY_RIGHT_ENT_SHORT, READERS_FIELD, ROOM_ENT_SHORT,
+            USER_AUTH_ENT_SHORT,
+        },
+    },
+    date_utils::now,
+    discret::DiscretServices,
+    security::{
+        base64_encode, decrypt_with_pass_phrase, encrypt_with_pass_phrase, hash,
+        import_verifying_key, uid_encode, Uid,
+    },
+    Error, Result,
+};
+
+///
+/// number of nodes sent to the database in one write
+///
+const ARCHIVE_BATCH_SIZE: usize = 512;
+
+///
+/// The summary of an archive import
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ArchiveImport {
+    ///
+    /// verifying key of the peer that exported the archive
+    ///
+    pub author: String,
+
+    ///
+    /// export date of the archive
+    ///
+    pub date: i64,
+
+    ///
+    /// the imported *Room* ids
+    ///
+    pub rooms: Vec<String>,
+
+    ///
+    /// the *Room* ids that are unknown to the local peer and were not imported
+    ///
+    pub rooms_skipped: Vec<String>,
+
+    ///
+    /// nodes that were missing or older in the local database
+    ///
+    pub nodes_imported: u64,
+
+    ///
+    /// nodes rejected by the authorisation rules of their *Room*
+    ///
+    pub nodes_rejected: u64,
+
+    pub edges_imported: u64,
+    pub edges_rejected: u64,
+}
+
+///
+/// The content of a *Room* as stored in the database, every element keeps the signature of its author
+///
+/// private rooms are derived from the key material and have no room definition.
+/// An archive can be read by anyone knowing the pass phrase: the nodes restricted to some readers with *sys_readers* are not exported.
+///
+#[derive(Serialize, Deserialize)]
+struct RoomArchive {
+    room_id: Uid,
+    room: Option<RoomNode>,
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    node_deletions: Vec<NodeDeletionEntry>,
+    edge_deletions: Vec<EdgeDeletionEntry>,
+}
+impl RoomArchive {
+    fn read(room_id: &Uid, conn: &Connection) -> std::result::Result<Self, rusqlite::Error> {
+        let room = RoomNode::read(conn, room_id)?;
+
+        //the room definition is carried by the RoomNode
+        //unsigned nodes, like the allowed peers of the private room, are local to the device
+        let nodes_query = format!(
+            "
+            SELECT id , room_id, cdate, mdate, _entity,_json, {}, verifying_key, _signature, rowid
+            FROM _node
+            WHERE
+                room_id = ? AND
+                length(verifying_key) > 0 AND
+                _entity NOT IN (?, ?, ?, ?) AND
+                {}",
+            binary_column!(),
+            unrestricted_condition("_node")
+        );
+        let mut stmt = conn.prepare(&nodes_query)?;
+        let rows = stmt.query_map(
+            (
+                room_id,
+                ROOM_ENT_SHORT,
+                AUTHORISATION_ENT_SHORT,
+                USER_AUTH_ENT_SHORT,
+                ENTITY_RIGHT_ENT_SHORT,
+            ),
+            Node::NODE_MAPPING,
+        )?;
+        let mut nodes = Vec::new();
+        for node in rows {
+            let mut node = *node?;
+            node._local_id = None;
+            nodes.push(node);
+        }
+
+        let edges_query = format!(
+            "SELECT _edge.src, _edge.src_entity, _edge.label, _edge.dest, _edge.cdate, _edge.verifying_key, _edge.signature
+            FROM _edge JOIN _node ON  _edge.src = _node.id
+            WHERE
+                _node.room_id = ? AND
+                length(_edge.verifying_key) > 0 AND
+                _node._entity NOT IN (?, ?, ?, ?) AND
+                {}",
+            unrestricted_condition("_node")
+        );
+        let mut stmt = conn.prepare(&edges_query)?;
+        let rows = stmt.query_map(
+            (
+                room_id,
+                ROOM_ENT_SHORT,
+                AUTHORISATION_ENT_SHORT,
+                USER_AUTH_ENT_SHORT,
+                ENTITY_RIGHT_ENT_SHORT,
+            ),
+            Edge::EDGE_MAPPING,
+        )?;
+        let mut edges = Vec::new();
+        for edge in rows {
+            edges.push(*edge?);
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT room_id, id, entity, mdate, deletion_date, verifying_key, signature
+            FROM _node_deletion_log
+            WHERE room_id = ?",
+        )?;
+        let rows = stmt.query_map([room_id], |row| {
+            Ok(NodeDeletionEntry {
+                room_id: row.get(0)?,
+                id: row.get(1)?,
+                entity: row.get(2)?,
+                mdate: row.get(3)?,
+                deletion_date: row.get(4)?,
+                verifying_key: row.get(5)?,
+                signature: row.get(6)?,
+                entity_name: None,
+            })
+        })?;
+        let mut node_deletions = Vec::new();
+        for entry in rows {
+            node_deletions.push(entry?);
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT room_id, src, src_entity, dest, label, cdate, deletion_date, verifying_key, signature
+            FROM _edge_deletion_log
+            WHERE room_id = ?",
+        )?;
+        let rows = stmt.query_map([room_id], |row| {
+            Ok(EdgeDeletionEntry {
+                room_id: row.get(0)?,
+                src: row.get(1)?,
+                src_entity: row.get(2)?,
+                dest: row.get(3)?,
+                label: row.get(4)?,
+                cdate: row.get(5)?,
+                deletion_date: row.get(6)?,
+                verifying_key: row.get(7)?,
+                signature: row.get(8)?,
+                entity_name: None,
+            })
+        })?;
+        let mut edge_deletions = Vec::new();
+        for entry in rows {
+            edge_deletions.push(entry?);
+        }
+
+        Ok(Self {
+            room_id: *room_id,
+            room,
+            nodes,
+            edges,
+            node_deletions,
+            edge_deletions,
+        })
+    }
+}
+
+//the nodes that do not restrict their readers
+fn unrestricted_condition(table: &str) -> String {
+    format!(
+        "({table}._json IS NULL OR (json_valid({table}._json) AND IFNULL(json_type({table}._json, '$.{READERS_FIELD}'), 'null') = 'null'))"
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    date: i64,
+    rooms: Vec<RoomArchive>,
+}
+
+///
+/// the serialized *Archive* signed by the exporting peer, encrypted with the pass phrase before being written
+///
+#[derive(Serialize, Deserialize)]
+struct SignedArchive {
+    archive: Vec<u8>,
+    verifying_key: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+///
+/// Writes the content of the rooms in an encrypted archive file
+///
+pub async fn export_rooms(
+    room_ids: Vec<Uid>,
+    path: &Path,
+    pass_phrase: &str,
+    services: &DiscretServices,
+) -> Result<()> {
+    let mut rooms = Vec::with_capacity(room_ids.len());
+    for room_id in room_ids {
+        if services.database.get_room(room_id).await?.is_none() {
+            return Err(Error::from(crate::database::Error::UnknownRoom(
+                uid_encode(&room_id),
+            )));
+        }
+        let (reply, receive) = oneshot::channel::<Result<RoomArchive>>();
+        services
+            .database
+            .db
+            .reader
+            .send_async(Box::new(move |conn| {
+                let archive = RoomArchive::read(&room_id, conn)
+                    .map_err(|e| Error::from(crate::database::Error::from(e)));
+                let _ = reply.send(archive);
+            }))
+            .await?;
+        rooms.push(receive.await??);
+    }
+    let archive = bincode::serialize(&Archive { date: now(), rooms })?;
+    let (verifying_key, signature) = services.database.sign(hash(&archive).to_vec()).await?;
+    let signed = bincode::serialize(&SignedArchive {
+        archive,
+        verifying_key,
+        signature,
+    })?;
+    let encrypted = encrypt_with_pass_phrase(&signed, pass_phrase)?;
+    tokio::fs::write(path, encrypted).await?;
+    Ok(())
+}
+
+///
+/// Reads an archive file created by *export_rooms* and inserts its content
+///
+/// Every element is verified and inserted like the data received during a synchronisation:
+/// the rules of the *Room* apply, and the local data that is more recent than the archive is kept.
+/// The nodes whose *sys_readers* excludes the local peer are rejected.
+/// The rooms that are unknown to the local peer are skipped, unless *accept_new_rooms* is set.
+/// The private rooms of another key material are always skipped.
+///
+pub async fn import_archive(
+    path: &Path,
+    pass_phrase: &str,
+    accept_new_rooms: bool,
+    services: &DiscretServices,
+) -> Result<ArchiveImport> {
+    let encrypted = tokio::fs::read(path).await?;
+    let signed = decrypt_with_pass_phrase(&encrypted, pass_phrase)?;
+    let signed: SignedArchive = bincode::deserialize(&signed)
+        .map_err(|_| Error::InvalidArchive("invalid archive format".to_string()))?;
+    import_verifying_key(&signed.verifying_key)?
+        .verify(&hash(&signed.archive), &signed.signature)
+        .map_err(|_| Error::InvalidArchive("invalid archive signature".to_string()))?;
+    let archive: Archive = bincode::deserialize(&signed.archive)
+        .map_err(|_| Error::InvalidArchive("invalid archive format".to_string()))?;
+
+    let mut summary = ArchiveImport {
+        author: base64_encode(&signed.verifying_key),
+        date: archive.date,
+        ..Default::default()
+    };
+    for room_archive in archive.rooms {
+        let room_id = room_archive.room_id;
+        if import_room(room_archive, accept_new_rooms, services, &mut summary).await? {
+            summary.rooms.push(uid_encode(&room_id));
+        } else {
+            summary.rooms_skipped.push(uid_encode(&room_id));
+        }
+    }
+    services.database.compute_daily_log().await;
+    Ok(summary)
+}
+
+async fn import_room(
+    room_archive: RoomArchive,
+    accept_new_rooms: bool,
+    services: &DiscretServices,
+    summary: &mut ArchiveImport,
+) -> Result<bool> {
+    let database = &services.database;
+    let verification = &services.signature_verification;
+
+    let room_id = room_archive.room_id;
+    let known_room = database.get_room(room_id).await?.is_some();
+    match room_archive.room {
+        Some(room) => {
+            if !room.node.id.eq(&room_id) {
+                return Err(Error::InvalidArchive("invalid room definition".to_string()));
+            }
+            if !known_room && !accept_new_rooms {
+                return Ok(false);
+            }
+            let room = verification.verify_room_node(room).await?;
+            database.add_room_node(room).await?;
+        }
+        None => {
+            if !known_room {
+                return Ok(false);
+            }
+        }
+    }
+
+    if !room_archive.edge_deletions.is_empty() {
+        let edge_deletions = verification
+            .verify_edge_log(room_archive.edge_deletions)
+            .await?;
+        database.delete_edges(edge_deletions).await?;
+    }
+    if !room_archive.node_deletions.is_empty() {
+        let node_deletions = verification
+            .verify_node_log(room_archive.node_deletions)
+            .await?;
+        database.delete_nodes(node_deletions).await?;
+    }
+
+    //an archive written by another implementation could contain nodes this peer is not allowed to read
+    let (nodes, unreadable): (Vec<Node>, Vec<Node>) = room_archive
+        .nodes
+        .into_iter()
+        .partition(|node| node.is_readable_by(&database.verifying_key));
+    summary.nodes_rejected += unreadable.len() as u64;
+
+    let identifiers: HashSet<NodeIdentifier> = nodes
+        .iter()
+        .map(|node| NodeIdentifier {
+            id: node.id,
+            mdate: node.mdate,
+            signature: node._signature.clone(),
+        })
+        .collect();
+    let filtered = database.filter_existing_node(identifiers).await?;
+    let mut node_map = HashMap::with_capacity(filtered.len());
+    for node_to_insert in filtered {
+        node_map.insert(node_to_insert.id, node_to_insert);
+    }
+    //only the edges of the inserted nodes, created after the local version of the node
+    let edge_dates: HashMap<Uid, i64> = node_map
+        .values()
+        .map(|node_to_insert| (node_to_insert.id, node_to_insert.old_mdate))
+        .collect();
+
+    let nodes: Vec<Node> = nodes
+        .into_iter()
+        .filter(|node| node_map.contains_key(&node.id))
+        .collect();
+    let mut nodes = nodes.into_iter().peekable();
+    while nodes.peek().is_some() {
+        let batch: Vec<Node> = nodes.by_ref().take(ARCHIVE_BATCH_SIZE).collect();
+        let batch = verification.verify_nodes(batch).await?;
+        let mut nodes_to_insert = Vec::with_capacity(batch.len());
+        for mut node in batch {
+            if let Some(mut nti) = node_map.remove(&node.id) {
+                node._local_id = nti.old_local_id;
+                nti.node = Some(node);
+                nodes_to_insert.push(nti);
+            }
+        }
+        database.wait_for_write_backlog().await;
+        let received = nodes_to_insert.len();
+        let rejected = database.add_nodes(room_id, nodes_to_insert).await?;
+        summary.nodes_imported += (received - rejected.len()) as u64;
+        summary.nodes_rejected += rejected.len() as u64;
+    }
+
+    let edges: Vec<Edge> = room_archive
+        .edges
+        .into_iter()
+        .filter(|edge| match edge_dates.get(&edge.src) {
+            Some(old_mdate) => edge.cdate >= *old_mdate,
+            None => false,
+        })
+        .collect();
+    let mut edges = edges.into_iter().peekable();
+    while edges.peek().is_some() {
+        let batch: Vec<Edge> = edges.by_ref().take(ARCHIVE_BATCH_SIZE).collect();
+        let batch = verification.verify_edges(batch).await?;
+        let received = batch.len();
+        let rejected = database.add_edges(room_id, batch).await?;
+        summary.edges_imported += (received - rejected.len()) as u64;
+        summary.edges_rejected += rejected.len() as u64;
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use crate::{
+        database::sqlite_database::prepare_connection,
+        security::{base64_encode, new_uid, Ed25519SigningKey, SigningKey},
+    };
+
+    use super::*;
+
+    #[test]
+    fn restricted_nodes_are_not_exported() {
+        let conn = Connection::open_in_memory().unwrap();
+        prepare_connection(&conn).unwrap();
+
+        let author = Ed25519SigningKey::new();
+        let reader = Ed25519SigningKey::new();
+        let room_id = new_uid();
+        let mdate = now();
+
+        let mut shared = Node {
+            id: new_uid(),
+            room_id: Some(room_id),
+            cdate: mdate,
+            mdate,
+            _entity: "1.0".to_string(),
+            ..Default::default()
+        };
+        shared.sign(&author).unwrap();
+
+        let mut restricted = Node {
+            _json: Some(format!(
+                r#"{{"{}":["{}"]}}"#,
+                READERS_FIELD,
+                base64_encode(&reader.export_verifying_key())
+            )),
+            id: new_uid(),
+            ..shared.clone()
+        };
+        restricted.sign(&author).unwrap();
+
+        shared.write(&conn, false, &None, &None).unwrap();
+        restricted.write(&conn, false, &None, &None).unwrap();
+
+        for node in [&shared, &restricted] {
+            let mut edge = Edge {
+                src: node.id,
+                src_entity: node._entity.clone(),
+                label: "0".to_string(),
+                dest: new_uid(),
+                cdate: mdate,
+                ..Default::default()
+            };
+            edge.sign(&author).unwrap();
+            edge.write(&conn).unwrap();
+        }
+
+        let archive = RoomArchive::read(&room_id, &conn).unwrap();
+        assert_eq!(1, archive.nodes.len());
+        assert_eq!(shared.id, archive.nodes[0].id);
+        assert_eq!(1, archive.edges.len());
+        assert_eq!(shared.id, archive.edges[0].src);
+    }
+}
@@ -0,0 +1,229 @@
+#[cfg(feature = "log")]
+use crate::log_level::{error, LogSubsystem};
+#[cfg(feature = "log")]
+const LOG_SUBSYSTEM: LogSubsystem = LogSubsystem::Sync;
+
+use std::time::Duration;
+
+use quinn::{Connection, RecvStream, SendStream};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::timeout,
+};
+
+use crate::{
+    database::{
+        file::{FileChunk, FileId, FileInfo},
+        graph_database::GraphDatabaseService,
+    },
+    security::{base64_encode, Uid},
+};
+
+use super::NETWORK_TIMEOUT_SEC;
+
+///
+/// flag of the streams dedicated to the file transfers, the other streams are defined in the endpoint
+///
+pub static FILE_STREAM: u8 = 4;
+
+//requests and file descriptions grow with the number of chunks, this allows files of several GB
+static MAX_DESCRIPTION_SIZE: usize = 4 * 1024 * 1024;
+
+//number of chunks read from the database at once
+static CHUNK_BATCH: usize = 16;
+
+///
+/// Requests sent on a file stream
+///
+/// Each request uses its own stream, which is closed after the answer.
+/// A transfer is resumed by requesting the chunks that are still missing.
+///
+#[derive(Serialize, Deserialize, Debug)]
+pub enum FileQuery {
+    Info(Uid, FileId),
+    Chunks(Uid, FileId, Vec<u32>),
+}
+impl FileQuery {
+    pub fn room_id(&self) -> &Uid {
+        match self {
+            FileQuery::Info(room_id, _) => room_id,
+            FileQuery::Chunks(room_id, _, _) => room_id,
+        }
+    }
+}
+
+///
+/// a request received from a remote peer and the stream used to answer it
+///
+pub struct FileRequest {
+    pub query: FileQuery,
+    pub send: SendStream,
+}
+impl FileRequest {
+    ///
+    /// reads the request of a stream whose flag has already been read
+    ///
+    pub async fn read(send: SendStream, mut recv: RecvStream) -> Result<Self, crate::Error> {
+        let query = read_message(&mut recv, MAX_DESCRIPTION_SIZE).await?;
+        Ok(Self { query, send })
+    }
+
+    ///
+    /// the room authorisation must have been verified
+    ///
+    /// a file that is only referenced by nodes that the remote peer cannot read is answered as unknown
+    ///
+    pub async fn answer(
+        mut self,
+        db: &GraphDatabaseService,
+        verifying_key: Vec<u8>,
+    ) -> Result<(), crate::Error> {
+        match self.query {
+            FileQuery::Info(room_id, id) => {
+                let info = if db.is_file_readable(room_id, id, verifying_key).await? {
+                    db.get_file_info(room_id, id).await?
+                } else {
+                    None
+                };
+                write_message(&mut self.send, &info).await?;
+            }
+            FileQuery::Chunks(room_id, id, positions) => {
+                let positions = if db.is_file_readable(room_id, id, verifying_key).await? {
+                    positions
+                } else {
+                    Vec::new()
+                };
+                for positions in positions.chunks(CHUNK_BATCH) {
+                    let chunks = db.get_file_chunks(room_id, id, positions.to_vec()).await?;
+                    for chunk in chunks {
+                        write_message(&mut self.send, &Some(chunk)).await?;
+                    }
+                }
+                //end of the answer
+                write_message::<Option<FileChunk>>(&mut self.send, &None).await?;
+            }
+        }
+        let _ = self.send.finish();
+        Ok(())
+    }
+}
+
+///
+/// downloads the files referenced by the room that are missing or incomplete
+///
+/// chunks are verified and stored as they arrive: an interrupted transfer restarts from the missing chunks
+///
+pub async fn synchronise_files(
+    conn: &Connection,
+    room_id: Uid,
+    db: &GraphDatabaseService,
+) -> Result<(), crate::Error> {
+    for id in db.missing_files(room_id).await? {
+        if let Err(_e) = synchronise_file(conn, room_id, id, db).await {
+            #[cfg(feature = "log")]
+            error!("synchronise_file {}, Error: {_e}", base64_encode(&id));
+        }
+    }
+    Ok(())
+}
+
+async fn synchronise_file(
+    conn: &Connection,
+    room_id: Uid,
+    id: FileId,
+    db: &GraphDatabaseService,
+) -> Result<(), crate::Error> {
+    let info = match db.get_file_info(room_id, id).await? {
+        Some(info) => info,
+        None => {
+            let (mut send, mut recv) = open_file_stream(conn).await?;
+            write_message(&mut send, &FileQuery::Info(room_id, id)).await?;
+            let info: Option<FileInfo> = read_message(&mut recv, MAX_DESCRIPTION_SIZE).await?;
+            let info = match info {
+                Some(info) => info,
+                //the peer does not have the file
+                None => return Ok(()),
+            };
+            if info.id() != id {
+                return Err(crate::Error::SecurityViolation(format!(
+                    "invalid description for file {}",
+                    base64_encode(&id)
+                )));
+            }
+            db.add_file_chunks(room_id, info.clone(), Vec::new())
+                .await?;
+            info
+        }
+    };
+
+    let missing = match db.get_missing_chunks(room_id, id).await? {
+        Some(missing) => missing,
+        None => return Ok(()),
+    };
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let (mut send, mut recv) = open_file_stream(conn).await?;
+    write_message(&mut send, &FileQuery::Chunks(room_id, id, missing)).await?;
+    let max_chunk_size = bincode::serialized_size(&Some(FileChunk {
+        position: 0,
+        data: vec![0; crate::database::file::FILE_CHUNK_SIZE],
+    }))? as usize;
+
+    let mut verified = Vec::with_capacity(CHUNK_BATCH);
+    while let Some(chunk) = read_message::<Option<FileChunk>>(&mut recv, max_chunk_size).await? {
+        if !info.is_valid_chunk(&chunk) {
+            return Err(crate::Error::SecurityViolation(format!(
+                "invalid chunk {} for file {}",
+                chunk.position,
+                base64_encode(&id)
+            )));
+        }
+        verified.push(chunk);
+        if verified.len() >= CHUNK_BATCH {
+            let chunks = std::mem::take(&mut verified);
+            db.add_file_chunks(room_id, info.clone(), chunks).await?;
+        }
+    }
+    if !verified.is_empty() {
+        db.add_file_chunks(room_id, info, verified).await?;
+    }
+    Ok(())
+}
+
+async fn open_file_stream(conn: &Connection) -> Result<(SendStream, RecvStream), crate::Error> {
+    let (mut send, recv) = conn.open_bi().await.map_err(crate::network::Error::from)?;
+    send.write_u8(FILE_STREAM).await?;
+    Ok((send, recv))
+}
+
+async fn write_message<T: Serialize>(send: &mut SendStream, msg: &T) -> Result<(), crate::Error> {
+    let serialized = bincode::serialize(msg)?;
+    send.write_u32(serialized.len() as u32).await?;
+    send.write_all(&serialized)
+        .await
+        .map_err(crate::network::Error::from)?;
+    Ok(())
+}
+
+async fn read_message<T: DeserializeOwned>(
+    recv: &mut RecvStream,
+    max_size: usize,
+) -> Result<T, crate::Error> {
+    let read = async {
+        let len = recv.read_u32().await? as usize;
+        if len > max_size {
+            return Err(crate::Error::from(
+                crate::network::Error::MsgDeserialisationToLong(len, max_size),
+            ));
+        }
+        let mut buffer = vec![0; len];
+        recv.read_exact(&mut buffer)
+            .await
+            .map_err(crate::network::Error::from)?;
+        Ok(bincode::deserialize(&buffer)?)
+    };
+    timeout(Duration::from_secs(NETWORK_TIMEOUT_SEC), read).await?
+}
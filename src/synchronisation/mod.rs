@@ -7,6 +7,8 @@ use crate::{
     security::{self, Uid},
 };
 use thiserror::Error;
+pub mod archive;
+pub mod file_transfer;
 pub mod peer_inbound_service;
 pub mod peer_outbound_service;
 pub mod room_locking_service;
@@ -44,9 +46,15 @@ pub enum Query {
     EdgeDeletionLog(Uid, String, i64),
     NodeDeletionLog(Uid, String, i64),
     RoomDailyNodes(Uid, String, i64),
+    //the room content older than the log horizon, for the peers that missed the pruned deletion logs
+    SnapshotNodes(Uid, i64),
+    SnapshotEdges(Uid, i64),
     Nodes(Uid, Vec<Uid>),
+    //the binaries referenced by the hashes received with the nodes
+    Binaries(Uid, Vec<Vec<u8>>),
     Edges(Uid, Vec<(Uid, i64)>),
-    PeersForRoom(Uid),
+    //paginated by verifying key, the second parameter is the cursor returned by the previous page
+    PeersForRoom(Uid, Option<Vec<u8>>),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -66,7 +74,9 @@ pub struct Answer {
 #[derive(Clone)]
 pub enum LocalEvent {
     RoomDefinitionChanged(Arc<Room>),
-    RoomDataChanged(Vec<Uid>),
+    //the modified rooms with the name of the modified entities
+    RoomDataChanged(Vec<(Uid, Vec<String>)>),
+    EntitySubscription(Uid, Vec<String>),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -74,18 +84,60 @@ pub enum RemoteEvent {
     Ready,            //indicate that this end of the connection is ready to synchronize
     ReadyFingerprint, //indicate that this end of the connection is ready to perform a hardware fingerprint check
     RoomDefinitionChanged(Uid),
-    RoomDataChanged(Uid),
+    RoomDataChanged(Vec<Uid>), //all the modified rooms shared with the peer are notified in one message
+    EntitySubscription(Uid, Vec<String>), //the peer is only notified of the modifications of these entities of the room, an empty list removes the subscription
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct IdentityAnswer {
     pub peer: Node,
     pub chall_signature: Vec<u8>,
+    pub date: i64,
 }
 impl IdentityAnswer {
+    ///
+    /// the answer date is signed with the challenge to prevent the peer from lying about its clock
+    ///
+    pub fn signed_bytes(challenge: &[u8], date: i64) -> Vec<u8> {
+        let mut bytes = challenge.to_vec();
+        bytes.extend_from_slice(&date.to_le_bytes());
+        bytes
+    }
+
     pub fn verify(&self, challenge: &[u8]) -> Result<(), security::Error> {
         let pub_key = security::import_verifying_key(&self.peer.verifying_key)?;
-        pub_key.verify(challenge, &self.chall_signature)?;
+        pub_key.verify(
+            &Self::signed_bytes(challenge, self.date),
+            &self.chall_signature,
+        )?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::{random32, Ed25519SigningKey, SigningKey};
+
+    #[test]
+    fn identity_answer_date_is_signed() {
+        let signing_key = Ed25519SigningKey::new();
+        let challenge = random32().to_vec();
+        let date = 1_000_000;
+
+        let mut answer = IdentityAnswer {
+            peer: Node {
+                verifying_key: signing_key.export_verifying_key(),
+                ..Default::default()
+            },
+            chall_signature: signing_key.sign(&IdentityAnswer::signed_bytes(&challenge, date)),
+            date,
+        };
+        answer.verify(&challenge).expect("valid answer");
+
+        answer.date = date + 3600 * 1000;
+        answer
+            .verify(&challenge)
+            .expect_err("the date was modified");
+    }
+}
@@ -1,5 +1,7 @@
 #[cfg(feature = "log")]
-use log::error;
+use crate::log_level::{debug, error, LogSubsystem};
+#[cfg(feature = "log")]
+const LOG_SUBSYSTEM: LogSubsystem = LogSubsystem::Sync;
 
 use std::{
     collections::{HashMap, HashSet, VecDeque},
@@ -8,10 +10,11 @@ use std::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use futures::Future;
+use futures::{stream, Future, StreamExt};
+use quinn::Connection;
 use serde::de::DeserializeOwned;
 use tokio::{
     sync::{
@@ -24,13 +27,19 @@ use tokio::{
 
 use crate::{
     base64_decode,
+    configuration::Configuration,
     database::{
+        binary_store::StoredBinary,
         daily_log::{DailyLog, RoomDefinitionLog},
         edge::{Edge, EdgeDeletionEntry},
-        node::{Node, NodeDeletionEntry, NodeIdentifier},
+        log_retention::{EdgeKey, LogPruning, RoomSnapshot},
+        graph_database::GraphDatabaseService,
+        node::{Node, NodeBatch, NodeDeletionEntry, NodeIdentifier},
+        replication::PeerSyncUpdate,
         room_node::RoomNode,
-        system_entities::Peer,
+        system_entities::{DataModelProposal, DataModelTemplate, Peer, PeerPage, SyncIncident},
     },
+    date_utils::now,
     discret::DiscretServices,
     event_service::EventServiceMessage,
     network::{peer_manager::TokenType, ConnectionInfo},
@@ -39,8 +48,9 @@ use crate::{
 };
 
 use super::{
-    peer_outbound_service::InboundQueryService, room_locking_service::RoomLockService, Answer,
-    Error, IdentityAnswer, LocalEvent, Query, QueryProtocol, RemoteEvent, NETWORK_TIMEOUT_SEC,
+    file_transfer, peer_outbound_service::InboundQueryService,
+    room_locking_service::RoomLockService, Answer, Error, IdentityAnswer, LocalEvent, Query,
+    QueryProtocol, RemoteEvent, NETWORK_TIMEOUT_SEC,
 };
 
 static QUERY_SEND_BUFFER: usize = 10;
@@ -140,6 +150,47 @@ impl QueryService {
     }
 }
 
+//maximum multiplier applied to the synchronisation fan-out on slow links
+const MAX_SLOW_LINK_FACTOR: u64 = 4;
+
+///
+/// defines how much data is requested at the same time during a room synchronisation
+///
+/// slow links are detected with the round trip time of the first query of the synchronisation,
+/// the number of days and the batch size are increased to keep more data in flight.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SyncTuning {
+    pub days_per_round: usize,
+    pub batch_size: usize,
+    slow_link_rtt: Duration,
+}
+impl SyncTuning {
+    pub fn new(configuration: &Configuration) -> Self {
+        Self {
+            days_per_round: configuration.sync_days_per_round.max(1),
+            batch_size: configuration.sync_batch_size.max(1),
+            slow_link_rtt: Duration::from_millis(configuration.sync_slow_link_rtt_in_ms),
+        }
+    }
+
+    ///
+    /// tuning adapted to the measured round trip time
+    ///
+    pub fn adapt(&self, rtt: Duration) -> Self {
+        if self.slow_link_rtt.is_zero() {
+            return *self;
+        }
+        let factor = (rtt.as_millis() / self.slow_link_rtt.as_millis()) as u64 + 1;
+        let factor = factor.min(MAX_SLOW_LINK_FACTOR) as usize;
+        Self {
+            days_per_round: self.days_per_round * factor,
+            batch_size: self.batch_size * factor,
+            slow_link_rtt: self.slow_link_rtt,
+        }
+    }
+}
+
 pub struct LocalPeerService {}
 impl LocalPeerService {
     #[allow(clippy::too_many_arguments)]
@@ -155,13 +206,22 @@ impl LocalPeerService {
     ) -> Result<bool, crate::Error> {
         let challenge = random32().to_vec();
 
+        let query_date = now();
         let proof = Self::query(query_service, Query::ProveIdentity(challenge.clone())).await;
+        let answer_date = now();
         if proof.is_err() {
             return Ok(false); //silently return to try to avoid poluting the logs with the error caused by the deletion of one of the two connection established during P2P initiaiton
         }
         let proof: IdentityAnswer = proof.unwrap();
         proof.verify(&challenge)?;
         Peer::validate(&proof.peer)?;
+        peer_service.peer_identity(proof.peer.clone()).await;
+
+        //the remote date is assumed to be taken halfway through the round trip
+        let clock_offset = proof.date - (query_date + (answer_date - query_date) / 2);
+        peer_service
+            .clock_offset(proof.peer.verifying_key.clone(), clock_offset)
+            .await;
         let mut ready = true;
         match &token_type {
             TokenType::AllowedPeer(peer) => {
@@ -241,6 +301,9 @@ impl LocalPeerService {
         event_sender: Sender<RemoteEvent>,
         peer_service: PeerConnectionService,
         inbound_query_service: InboundQueryService,
+        entity_subscriptions: HashMap<Uid, Vec<String>>,
+        sync_tuning: SyncTuning,
+        connection: Option<Connection>,
         discret_services: &DiscretServices,
     ) {
         let (lock_reply, mut lock_receiver) = mpsc::unbounded_channel::<Uid>();
@@ -283,6 +346,9 @@ impl LocalPeerService {
             }
 
             let mut remote_rooms: HashSet<Uid> = HashSet::new();
+            //the entities that this device needs to be notified of, and the ones the remote peer needs
+            let mut local_subscriptions = entity_subscriptions;
+            let mut remote_subscriptions: HashMap<Uid, HashSet<String>> = HashMap::new();
             let acquired_lock = Arc::new(Mutex::new(HashSet::<Uid>::new()));
             loop {
                 tokio::select! {
@@ -299,6 +365,8 @@ impl LocalPeerService {
                                     &lock_service,
                                     &query_service,
                                     &mut remote_rooms,
+                                    &local_subscriptions,
+                                    &mut remote_subscriptions,
                                     circuit_id,
                                     &conn_ready,
                                     &event_sender,
@@ -318,7 +386,7 @@ impl LocalPeerService {
 
                     msg = local_event.recv() =>{
                         if let Ok(msg) = msg{
-                            if let Err(_e) = Self::process_local_event(msg, &remote_verifying_key, &event_sender, &remote_rooms, &inbound_query_service).await{
+                            if let Err(_e) = Self::process_local_event(msg, &remote_verifying_key, &event_sender, &remote_rooms, &mut local_subscriptions, &remote_subscriptions, &inbound_query_service).await{
                                 #[cfg(feature = "log")]
                                 error!("LocalPeerService Local Event, Error: {_e}");
                                 break;
@@ -329,12 +397,17 @@ impl LocalPeerService {
                     msg = lock_receiver.recv() =>{
                         match msg{
                             Some(room) => {
+                                let remote_key = remote_verifying_key.lock().await.clone();
                                 if let Err(_e) =Self::process_acquired_room(
                                     room,
+                                    local_verifying_key.clone(),
+                                    remote_key,
                                     acquired_lock.clone(),
                                     query_service.clone(),
                                     lock_service.clone(),
                                     peer_service.clone(),
+                                    sync_tuning,
+                                    connection.clone(),
                                     &discret_services,
                                 )
                                     .await {
@@ -368,6 +441,8 @@ impl LocalPeerService {
         lock_service: &RoomLockService,
         query_service: &QueryService,
         remote_rooms: &mut HashSet<Uid>,
+        local_subscriptions: &HashMap<Uid, Vec<String>>,
+        remote_subscriptions: &mut HashMap<Uid, HashSet<String>>,
         circuit_id: [u8; 32],
         conn_ready: &Arc<AtomicBool>,
         event_sender: &Sender<RemoteEvent>,
@@ -383,6 +458,9 @@ impl LocalPeerService {
                     let rooms = rooms?;
                     for room in &rooms {
                         remote_rooms.insert(*room);
+                        if let Some(entities) = local_subscriptions.get(room) {
+                            Self::send_subscription(event_sender, *room, entities.clone()).await?;
+                        }
                     }
                     lock_service
                         .request_locks(circuit_id, rooms, lock_reply.clone())
@@ -422,19 +500,33 @@ impl LocalPeerService {
             }
 
             RemoteEvent::RoomDefinitionChanged(room) => {
-                remote_rooms.insert(room);
+                if remote_rooms.insert(room) {
+                    if let Some(entities) = local_subscriptions.get(&room) {
+                        Self::send_subscription(event_sender, room, entities.clone()).await?;
+                    }
+                }
                 let mut q = VecDeque::new();
                 q.push_back(room);
                 lock_service.request_locks(circuit_id, q, lock_reply).await;
             }
 
-            RemoteEvent::RoomDataChanged(room) => {
-                if remote_rooms.contains(&room) {
-                    let mut q = VecDeque::new();
-                    q.push_back(room);
+            RemoteEvent::RoomDataChanged(rooms) => {
+                let q: VecDeque<Uid> = rooms
+                    .into_iter()
+                    .filter(|room| remote_rooms.contains(room))
+                    .collect();
+                if !q.is_empty() {
                     lock_service.request_locks(circuit_id, q, lock_reply).await;
                 }
             }
+
+            RemoteEvent::EntitySubscription(room, entities) => {
+                if entities.is_empty() {
+                    remote_subscriptions.remove(&room);
+                } else {
+                    remote_subscriptions.insert(room, entities.into_iter().collect());
+                }
+            }
         }
         Ok(())
     }
@@ -444,6 +536,8 @@ impl LocalPeerService {
         remote_key: &Arc<Mutex<Vec<u8>>>,
         event_sender: &Sender<RemoteEvent>,
         remote_rooms: &HashSet<Uid>,
+        local_subscriptions: &mut HashMap<Uid, Vec<String>>,
+        remote_subscriptions: &HashMap<Uid, HashSet<String>>,
         inbound_query_service: &InboundQueryService,
     ) -> Result<(), crate::Error> {
         match msg {
@@ -457,26 +551,58 @@ impl LocalPeerService {
                 }
             }
             LocalEvent::RoomDataChanged(rooms) => {
-                for room in rooms {
-                    if remote_rooms.contains(&room) {
-                        Self::send_event(event_sender, RemoteEvent::RoomDataChanged(room))
-                            .await
-                            .map_err(|_| {
-                                crate::Error::TimeOut("RoomDefinitionChanged".to_string())
-                            })?;
-                    }
+                //a peer that subscribed to some entities of a room is only notified of their modifications
+                let rooms: Vec<Uid> = rooms
+                    .into_iter()
+                    .filter(|(room, entities)| {
+                        remote_rooms.contains(room)
+                            && match remote_subscriptions.get(room) {
+                                Some(subscribed) => entities.iter().any(|e| subscribed.contains(e)),
+                                None => true,
+                            }
+                    })
+                    .map(|(room, _)| room)
+                    .collect();
+                if !rooms.is_empty() {
+                    Self::send_event(event_sender, RemoteEvent::RoomDataChanged(rooms))
+                        .await
+                        .map_err(|_| crate::Error::TimeOut("RoomDataChanged".to_string()))?;
+                }
+            }
+            LocalEvent::EntitySubscription(room, entities) => {
+                if entities.is_empty() {
+                    local_subscriptions.remove(&room);
+                } else {
+                    local_subscriptions.insert(room, entities.clone());
+                }
+                if remote_rooms.contains(&room) {
+                    Self::send_subscription(event_sender, room, entities).await?;
                 }
             }
         }
         Ok(())
     }
+
+    async fn send_subscription(
+        event_sender: &Sender<RemoteEvent>,
+        room: Uid,
+        entities: Vec<String>,
+    ) -> Result<(), crate::Error> {
+        Self::send_event(event_sender, RemoteEvent::EntitySubscription(room, entities))
+            .await
+            .map_err(|_| crate::Error::TimeOut("EntitySubscription".to_string()))
+    }
     #[allow(clippy::too_many_arguments)]
     async fn process_acquired_room(
         room: Uid,
+        local_verifying_key: Vec<u8>,
+        remote_verifying_key: Vec<u8>,
         acquired_lock: Arc<Mutex<HashSet<Uid>>>,
         query_service: QueryService,
         lock_service: RoomLockService,
         peer_service: PeerConnectionService,
+        sync_tuning: SyncTuning,
+        connection: Option<Connection>,
         discret_services: &DiscretServices,
     ) -> Result<(), crate::Error> {
         let discret_services = discret_services.clone();
@@ -484,14 +610,70 @@ impl LocalPeerService {
             {
                 acquired_lock.lock().await.insert(room);
             }
-            match Self::synchronise_room(room, &query_service, peer_service, &discret_services)
-                .await
+            let sync_start = Instant::now();
+            match Self::synchronise_room(
+                room,
+                &local_verifying_key,
+                &remote_verifying_key,
+                &query_service,
+                peer_service,
+                sync_tuning,
+                &discret_services,
+            )
+            .await
             {
-                Ok(_) => {
+                Ok(remote_room) => {
+                    discret_services
+                        .database
+                        .add_synchronisation_duration(sync_start.elapsed().as_millis() as u64)
+                        .await;
+
+                    //the files referenced by the synchronised nodes
+                    if let Some(connection) = &connection {
+                        if let Err(_e) = file_transfer::synchronise_files(
+                            connection,
+                            room,
+                            &discret_services.database,
+                        )
+                        .await
+                        {
+                            #[cfg(feature = "log")]
+                            error!("synchronise_files, Error: {_e}");
+                        }
+                    }
                     discret_services
                         .events
                         .notify(EventServiceMessage::RoomSynchronized(room))
                         .await;
+
+                    //warn when some data is not held by any known peer
+                    match discret_services.database.availability(room).await {
+                        Ok(availability) => {
+                            let local_only = availability.iter().filter(|a| a.copies == 0).count();
+                            if local_only > 0 {
+                                discret_services
+                                    .events
+                                    .notify(EventServiceMessage::LocalOnlyData(room, local_only))
+                                    .await;
+                            }
+                        }
+                        Err(_e) => {
+                            #[cfg(feature = "log")]
+                            error!("availability, Error: {_e}");
+                        }
+                    }
+
+                    //compare the room content with the remote peer
+                    if let Err(_e) = Self::check_divergence(
+                        &remote_room,
+                        &remote_verifying_key,
+                        &discret_services,
+                    )
+                    .await
+                    {
+                        #[cfg(feature = "log")]
+                        error!("check_divergence, Error: {_e}");
+                    }
                 }
                 Err(_e) => {
                     #[cfg(feature = "log")]
@@ -508,18 +690,31 @@ impl LocalPeerService {
 
     async fn synchronise_room(
         room_id: Uid,
+        local_verifying_key: &[u8],
+        remote_verifying_key: &[u8],
         query_service: &QueryService,
         peer_service: PeerConnectionService,
+        sync_tuning: SyncTuning,
         discret_services: &DiscretServices,
-    ) -> Result<(), crate::Error> {
+    ) -> Result<RoomDefinitionLog, crate::Error> {
+        let sync_date = now();
+        #[cfg(feature = "log")]
+        debug!(
+            "synchronising room {} with peer {}",
+            base64_encode(&room_id),
+            base64_encode(remote_verifying_key)
+        );
         //
         // update room definition
         //
+        let query_start = Instant::now();
         let remote_room_def: Option<RoomDefinitionLog> =
             Self::query(query_service, Query::RoomDefinition(room_id)).await?;
+        let sync_tuning = sync_tuning.adapt(query_start.elapsed());
+        //the local log is compared as seen by the remote peer, see ReaderView
         let local_room_def = discret_services
             .database
-            .get_room_definition(room_id)
+            .get_room_definition(room_id, Some(remote_verifying_key.to_vec()))
             .await?;
 
         if remote_room_def.is_none() {
@@ -550,30 +745,29 @@ impl LocalPeerService {
             }
         }
 
-        let mut peers_receiv: Receiver<Result<Vec<Node>, Error>> =
-            Self::query_multiple(query_service, Query::PeersForRoom(room_id)).await;
-
         let mut peer_nodes: Vec<Node> = Vec::new();
-        while let Some(node) = peers_receiv.recv().await {
-            match node {
-                Ok(nodes) => {
-                    for node in nodes {
-                        let local = local_peers.get(&node.id);
-                        match local {
-                            Some(local_node) => {
-                                if local_node.mdate < node.mdate {
-                                    Peer::validate(&node)?;
-                                    peer_nodes.push(node);
-                                }
-                            }
-                            None => {
-                                Peer::validate(&node)?;
-                                peer_nodes.push(node);
-                            }
+        let mut after: Option<Vec<u8>> = None;
+        loop {
+            let page: PeerPage =
+                Self::query(query_service, Query::PeersForRoom(room_id, after)).await?;
+            for node in page.peers {
+                let local = local_peers.get(&node.id);
+                match local {
+                    Some(local_node) => {
+                        if local_node.mdate < node.mdate {
+                            Peer::validate(&node)?;
+                            peer_nodes.push(node);
                         }
                     }
+                    None => {
+                        Peer::validate(&node)?;
+                        peer_nodes.push(node);
+                    }
                 }
-                Err(e) => return Err(crate::Error::from(e)),
+            }
+            after = page.next;
+            if after.is_none() {
+                break;
             }
         }
 
@@ -591,19 +785,161 @@ impl LocalPeerService {
             .send(PeerConnectionMessage::NewPeer(peer_nodes))
             .await;
 
+        //
+        // the remote peer has pruned the deletion logs that were created after the last synchronisation of the room
+        // the remote horizon is not trusted beyond the local retention
+        //
+        let horizon = LogPruning::remote_horizon(
+            remote_room.log_horizon,
+            discret_services.database.log_retention_in_days,
+        );
+        if local_room_def.is_some() && horizon > 0 {
+            let last_sync = discret_services
+                .database
+                .get_room_sync_date(room_id)
+                .await?;
+            if last_sync.is_none_or(|date| date < horizon) {
+                Self::synchronise_snapshot(
+                    room_id,
+                    horizon,
+                    local_verifying_key,
+                    remote_verifying_key,
+                    query_service,
+                    discret_services,
+                )
+                .await?;
+                discret_services.database.compute_daily_log().await;
+            }
+        }
+
+        let mut stats = PeerSyncUpdate::new(remote_verifying_key.to_vec());
         if Self::synchronise_room_data(
             &remote_room,
             &local_room_def,
+            &mut stats,
+            sync_tuning,
             query_service,
             discret_services,
         )
         .await?
         {
             discret_services.database.compute_daily_log().await;
+
+            //a data model update might have been confirmed by a room admin
+            if let Err(_e) =
+                DataModelProposal::activate(&base64_encode(&room_id), &discret_services.database)
+                    .await
+            {
+                #[cfg(feature = "log")]
+                error!("DataModelProposal::activate, Error: {_e}");
+            }
+
+            //the room might provide a data model template
+            if discret_services.database.accept_data_model_templates {
+                if let Err(_e) = DataModelTemplate::activate(
+                    &base64_encode(&room_id),
+                    &discret_services.database,
+                )
+                .await
+                {
+                    #[cfg(feature = "log")]
+                    error!("DataModelTemplate::activate, Error: {_e}");
+                }
+            }
+        }
+        discret_services
+            .database
+            .set_room_sync_date(room_id, sync_date)
+            .await?;
+
+        #[cfg(feature = "log")]
+        debug!(
+            "room {} synchronised, nodes accepted: {}, nodes rejected: {}",
+            base64_encode(&room_id),
+            stats.nodes_accepted,
+            stats.nodes_rejected
+        );
+        stats.room_sync = Some((room_id, sync_date));
+        discret_services.database.add_peer_sync_stats(stats).await?;
+        Ok(remote_room)
+    }
+
+    ///
+    /// compares the history hash of the room, which covers every daily log, with the one of the remote peer
+    /// and notifies the entity days that still differ after the synchronisation
+    ///
+    async fn check_divergence(
+        remote_room: &RoomDefinitionLog,
+        remote_verifying_key: &[u8],
+        discret_services: &DiscretServices,
+    ) -> Result<(), crate::Error> {
+        let local_room = discret_services
+            .database
+            .get_room_definition(remote_room.room_id, Some(remote_verifying_key.to_vec()))
+            .await?;
+        if let Some(local_room) = local_room {
+            if local_room.history_hash.eq(&remote_room.history_hash)
+                && local_room.last_data_date.eq(&remote_room.last_data_date)
+            {
+                return Ok(());
+            }
+        }
+        let divergence = discret_services
+            .database
+            .room_divergence(remote_room.room_id, remote_verifying_key.to_vec())
+            .await?;
+        if !divergence.is_empty() {
+            discret_services
+                .events
+                .notify(EventServiceMessage::RoomDivergence(
+                    remote_room.room_id,
+                    remote_verifying_key.to_vec(),
+                    divergence.len(),
+                ))
+                .await;
         }
         Ok(())
     }
 
+    ///
+    /// removes the local nodes and edges older than the horizon that are missing from the remote room
+    ///
+    async fn synchronise_snapshot(
+        room_id: Uid,
+        horizon: i64,
+        local_verifying_key: &[u8],
+        remote_verifying_key: &[u8],
+        query_service: &QueryService,
+        discret_services: &DiscretServices,
+    ) -> Result<(), crate::Error> {
+        let mut nodes = HashSet::new();
+        let mut nodes_recv: Receiver<Result<Vec<Uid>, Error>> =
+            Self::query_multiple(query_service, Query::SnapshotNodes(room_id, horizon)).await;
+        while let Some(ids) = nodes_recv.recv().await {
+            nodes.extend(ids?);
+        }
+
+        let mut edges = HashSet::new();
+        let mut edges_recv: Receiver<Result<Vec<EdgeKey>, Error>> =
+            Self::query_multiple(query_service, Query::SnapshotEdges(room_id, horizon)).await;
+        while let Some(keys) = edges_recv.recv().await {
+            edges.extend(keys?);
+        }
+
+        discret_services
+            .database
+            .apply_snapshot(RoomSnapshot {
+                room_id,
+                horizon,
+                local_key: local_verifying_key.to_vec(),
+                remote_key: remote_verifying_key.to_vec(),
+                nodes,
+                edges,
+            })
+            .await?;
+        Ok(())
+    }
+
     async fn synchronise_room_definition(
         remote_room: &RoomDefinitionLog,
         local_room_def: &Option<RoomDefinitionLog>,
@@ -640,6 +976,8 @@ impl LocalPeerService {
     async fn synchronise_room_data(
         remote_room: &RoomDefinitionLog,
         local_room_def: &Option<RoomDefinitionLog>,
+        stats: &mut PeerSyncUpdate,
+        sync_tuning: SyncTuning,
         query_service: &QueryService,
         discret_services: &DiscretServices,
     ) -> Result<bool, crate::Error> {
@@ -652,15 +990,31 @@ impl LocalPeerService {
             None => true,
         };
         if sync_history {
-            Self::synchronise_history(remote_room.room_id, query_service, discret_services).await
+            Self::synchronise_history(
+                remote_room.room_id,
+                stats,
+                sync_tuning,
+                query_service,
+                discret_services,
+            )
+            .await
         } else {
-            Self::synchronise_last_day(remote_room, local_room_def, query_service, discret_services)
-                .await
+            Self::synchronise_last_day(
+                remote_room,
+                local_room_def,
+                stats,
+                sync_tuning,
+                query_service,
+                discret_services,
+            )
+            .await
         }
     }
 
     async fn synchronise_history(
         room_id: Uid,
+        stats: &mut PeerSyncUpdate,
+        sync_tuning: SyncTuning,
         query_service: &QueryService,
         discret_services: &DiscretServices,
     ) -> Result<bool, crate::Error> {
@@ -674,14 +1028,8 @@ impl LocalPeerService {
             }
         }
 
-        let mut local_log_receiver = discret_services.database.get_room_log(room_id).await;
-        let mut local_log: Vec<DailyLog> = Vec::new();
-        while let Some(log) = local_log_receiver.recv().await {
-            match log {
-                Ok(mut log) => local_log.append(&mut log),
-                Err(e) => return Err(crate::Error::from(e)),
-            }
-        }
+        let local_log =
+            Self::local_room_log(room_id, &stats.verifying_key, discret_services).await?;
         let mut local_map: HashMap<i64, HashMap<String, DailyLog>> =
             HashMap::with_capacity(local_log.len());
 
@@ -690,64 +1038,63 @@ impl LocalPeerService {
 
             room_entry.insert(log.entity.clone(), log);
         }
-        let mut modified = false;
+        let mut days = Vec::new();
         for remote in &remote_log {
-            let local_room_date = local_map.get(&remote.date);
-            match local_room_date {
-                Some(local_room_date) => {
-                    let local_entity_log = local_room_date.get(&remote.entity);
-
-                    match local_entity_log {
-                        Some(local_log) => {
-                            if !local_log.daily_hash.eq(&remote.daily_hash)
-                                && Self::synchronise_day(
-                                    room_id,
-                                    remote.entity.clone(),
-                                    remote.date,
-                                    query_service,
-                                    discret_services,
-                                )
-                                .await?
-                            {
-                                modified = true;
-                            }
-                        }
-                        None => {
-                            if Self::synchronise_day(
-                                room_id,
-                                remote.entity.clone(),
-                                remote.date,
-                                query_service,
-                                discret_services,
-                            )
-                            .await?
-                            {
-                                modified = true;
-                            }
-                        }
-                    }
-                }
-                None => {
-                    if Self::synchronise_day(
-                        room_id,
-                        remote.entity.clone(),
-                        remote.date,
-                        query_service,
-                        discret_services,
-                    )
-                    .await?
-                    {
-                        modified = true;
-                    }
-                }
+            let local_entity_log = local_map
+                .get(&remote.date)
+                .and_then(|local_room_date| local_room_date.get(&remote.entity));
+            let modified_day = match local_entity_log {
+                Some(local_log) => !local_log.daily_hash.eq(&remote.daily_hash),
+                None => true,
+            };
+            if modified_day {
+                days.push((remote.entity.clone(), remote.date));
             }
         }
+        let modified = Self::synchronise_days(
+            room_id,
+            days,
+            stats,
+            sync_tuning,
+            query_service,
+            discret_services,
+        )
+        .await?;
+
+        discret_services
+            .database
+            .set_peer_room_log(room_id, stats.verifying_key.clone(), remote_log)
+            .await?;
         Ok(modified)
     }
 
+    ///
+    /// the local log as seen by the remote peer
+    ///
+    async fn local_room_log(
+        room_id: Uid,
+        remote_verifying_key: &[u8],
+        discret_services: &DiscretServices,
+    ) -> Result<Vec<DailyLog>, crate::Error> {
+        let mut local_log_receiver = discret_services
+            .database
+            .get_room_log(room_id, Some(remote_verifying_key.to_vec()))
+            .await;
+        let mut local_log: Vec<DailyLog> = Vec::new();
+        while let Some(log) = local_log_receiver.recv().await {
+            match log {
+                Ok(mut log) => local_log.append(&mut log),
+                Err(e) => return Err(crate::Error::from(e)),
+            }
+        }
+        Ok(local_log)
+    }
+
     async fn synchronise_last_day(
         remote_room: &RoomDefinitionLog,
         local_room_def: &Option<RoomDefinitionLog>,
+        stats: &mut PeerSyncUpdate,
+        sync_tuning: SyncTuning,
         query_service: &QueryService,
         discret_services: &DiscretServices,
     ) -> Result<bool, crate::Error> {
@@ -760,37 +1107,97 @@ impl LocalPeerService {
             None => true,
         };
 
-        if sync_day {
+        //the history is shared, only the last day might differ
+        let mut peer_log =
+            Self::local_room_log(remote_room.room_id, &stats.verifying_key, discret_services)
+                .await?;
+
+        let modified = if sync_day {
+            let last_data_date = remote_room.last_data_date.unwrap(); //checked by sync_day
             let remote_log: Vec<DailyLog> = Self::query(
                 query_service,
-                Query::RoomLogAt(remote_room.room_id, remote_room.last_data_date.unwrap()),
+                Query::RoomLogAt(remote_room.room_id, last_data_date),
             )
             .await?;
 
-            for log in remote_log {
-                Self::synchronise_day(
-                    remote_room.room_id,
-                    log.entity,
-                    remote_room.last_data_date.unwrap(), //checked by sync_day
-                    query_service,
-                    discret_services,
-                )
-                .await?;
-            }
-            Ok(true)
+            peer_log.retain(|log| log.date != last_data_date);
+            let days = remote_log
+                .iter()
+                .map(|log| (log.entity.clone(), last_data_date))
+                .collect();
+            Self::synchronise_days(
+                remote_room.room_id,
+                days,
+                stats,
+                sync_tuning,
+                query_service,
+                discret_services,
+            )
+            .await?;
+            peer_log.extend(remote_log);
+            true
         } else {
-            Ok(false)
+            false
+        };
+
+        discret_services
+            .database
+            .set_peer_room_log(remote_room.room_id, stats.verifying_key.clone(), peer_log)
+            .await?;
+        Ok(modified)
+    }
+
+    ///
+    /// synchronises the (entity, date) days, up to sync_tuning.days_per_round at the same time
+    ///
+    async fn synchronise_days(
+        room_id: Uid,
+        days: Vec<(String, i64)>,
+        stats: &mut PeerSyncUpdate,
+        sync_tuning: SyncTuning,
+        query_service: &QueryService,
+        discret_services: &DiscretServices,
+    ) -> Result<bool, crate::Error> {
+        let verifying_key = stats.verifying_key.clone();
+        let verifying_key = &verifying_key;
+        let mut rounds = stream::iter(days.into_iter().map(|(entity, date)| async move {
+            let mut day_stats = PeerSyncUpdate::new(verifying_key.clone());
+            let modified = Self::synchronise_day(
+                room_id,
+                entity,
+                date,
+                &mut day_stats,
+                sync_tuning.batch_size,
+                query_service,
+                discret_services,
+            )
+            .await?;
+            Ok::<_, crate::Error>((modified, day_stats))
+        }))
+        .buffer_unordered(sync_tuning.days_per_round);
+
+        let mut modified = false;
+        while let Some(day) = rounds.next().await {
+            let (modified_day, day_stats) = day?;
+            modified |= modified_day;
+            stats.nodes_accepted += day_stats.nodes_accepted;
+            stats.nodes_rejected += day_stats.nodes_rejected;
         }
+        Ok(modified)
     }
 
     async fn synchronise_day(
         room_id: Uid,
         entity: String,
         date: i64,
+        stats: &mut PeerSyncUpdate,
+        batch_size: usize,
         query_service: &QueryService,
         discret_services: &DiscretServices,
     ) -> Result<bool, crate::Error> {
         let mut has_changes = false;
+        let nodes_rejected_before = stats.nodes_rejected;
+        let mut edges_rejected = 0;
 
         //edge deletion
         let mut edge_deletion_recv: Receiver<Result<Vec<EdgeDeletionEntry>, Error>> =
@@ -864,7 +1271,6 @@ impl LocalPeerService {
             return Ok(has_changes);
         }
 
-        let batch_size = 2048;
         let mut node_list = Vec::with_capacity(batch_size);
         let mut edge_list = Vec::with_capacity(batch_size);
         let mut node_map = HashMap::with_capacity(batch_size);
@@ -874,14 +1280,16 @@ impl LocalPeerService {
             edge_list.push((node_to_insert.id, node_to_insert.old_mdate));
             node_map.insert(node_to_insert.id, node_to_insert);
             if node_list.len() == batch_size {
-                let mut result_recv: Receiver<Result<Vec<Node>, Error>> =
-                    LocalPeerService::query_multiple(
-                        query_service,
-                        Query::Nodes(room_id, node_list.clone()),
-                    )
-                    .await;
-                while let Some(nodes) = result_recv.recv().await {
-                    let nodes = nodes?;
+                //do not download more nodes than the database can write
+                discret_services.database.wait_for_write_backlog().await;
+                let batches = LocalPeerService::fetch_nodes(
+                    query_service,
+                    &discret_services.database,
+                    room_id,
+                    node_list.clone(),
+                )
+                .await?;
+                for nodes in batches {
                     let nodes = discret_services
                         .signature_verification
                         .verify_nodes(nodes)
@@ -894,10 +1302,13 @@ impl LocalPeerService {
                             nodes_to_insert.push(nti);
                         }
                     }
+                    let received = nodes_to_insert.len();
                     let res = discret_services
                         .database
                         .add_nodes(room_id, nodes_to_insert)
                         .await?;
+                    stats.nodes_accepted += (received - res.len()) as u64;
+                    stats.nodes_rejected += res.len() as u64;
                     if !res.is_empty() {
                         #[cfg(feature = "log")]
                         error!(
@@ -924,6 +1335,7 @@ impl LocalPeerService {
                         .verify_edges(edges)
                         .await?;
                     let res = discret_services.database.add_edges(room_id, edges).await?;
+                    edges_rejected += res.len() as u64;
                     if !res.is_empty() {
                         #[cfg(feature = "log")]
                         error!(
@@ -942,11 +1354,15 @@ impl LocalPeerService {
         }
 
         if !node_list.is_empty() {
-            let mut result_recv: Receiver<Result<Vec<Node>, Error>> =
-                LocalPeerService::query_multiple(query_service, Query::Nodes(room_id, node_list))
-                    .await;
-            while let Some(nodes) = result_recv.recv().await {
-                let nodes = nodes?;
+            discret_services.database.wait_for_write_backlog().await;
+            let batches = LocalPeerService::fetch_nodes(
+                query_service,
+                &discret_services.database,
+                room_id,
+                node_list,
+            )
+            .await?;
+            for nodes in batches {
                 let nodes = discret_services
                     .signature_verification
                     .verify_nodes(nodes)
@@ -959,10 +1375,13 @@ impl LocalPeerService {
                         nodes_to_insert.push(nti);
                     }
                 }
+                let received = nodes_to_insert.len();
                 let res = discret_services
                     .database
                     .add_nodes(room_id, nodes_to_insert)
                     .await?;
+                stats.nodes_accepted += (received - res.len()) as u64;
+                stats.nodes_rejected += res.len() as u64;
                 if !res.is_empty() {
                     #[cfg(feature = "log")]
                     error!(
@@ -986,6 +1405,7 @@ impl LocalPeerService {
                     .verify_edges(edges)
                     .await?;
                 let res = discret_services.database.add_edges(room_id, edges).await?;
+                edges_rejected += res.len() as u64;
                 if !res.is_empty() {
                     #[cfg(feature = "log")]
                     error!(
@@ -996,9 +1416,70 @@ impl LocalPeerService {
             }
         }
 
+        let nodes_rejected = stats.nodes_rejected - nodes_rejected_before;
+        if nodes_rejected > 0 || edges_rejected > 0 {
+            Self::record_incident(
+                room_id,
+                &stats.verifying_key,
+                date,
+                nodes_rejected,
+                edges_rejected,
+                discret_services,
+            )
+            .await;
+        }
+
         Ok(has_changes)
     }
 
+    ///
+    /// stores the rejected data of a day in a local sys.SyncIncident, allowing applications to display synchronisation issues
+    ///
+    async fn record_incident(
+        room_id: Uid,
+        verifying_key: &[u8],
+        date: i64,
+        nodes_rejected: u64,
+        edges_rejected: u64,
+        discret_services: &DiscretServices,
+    ) {
+        let mut reasons = Vec::new();
+        if nodes_rejected > 0 {
+            reasons.push(
+                crate::Error::NodeRejected(
+                    nodes_rejected as usize,
+                    security::uid_encode(&room_id),
+                    date,
+                )
+                .to_string(),
+            );
+        }
+        if edges_rejected > 0 {
+            reasons.push(
+                crate::Error::EdgeRejected(
+                    edges_rejected as usize,
+                    security::uid_encode(&room_id),
+                    date,
+                )
+                .to_string(),
+            );
+        }
+        if let Err(_e) = SyncIncident::record(
+            room_id,
+            verifying_key,
+            date,
+            nodes_rejected,
+            edges_rejected,
+            &reasons.join(", "),
+            &discret_services.database,
+        )
+        .await
+        {
+            #[cfg(feature = "log")]
+            error!("SyncIncident::record, Error: {_e}");
+        }
+    }
+
     pub async fn send_event(
         event_sender: &Sender<RemoteEvent>,
         event: RemoteEvent,
@@ -1041,6 +1522,69 @@ impl LocalPeerService {
         }
     }
 
+    ///
+    /// downloads the nodes and the binaries that are not already stored locally
+    ///
+    /// every answer of the node query is received before querying the binaries, the answers being processed in order.
+    /// nodes whose binary cannot be retrieved are dropped and will be synchronised later
+    ///
+    async fn fetch_nodes(
+        query_service: &QueryService,
+        database: &GraphDatabaseService,
+        room_id: Uid,
+        node_list: Vec<Uid>,
+    ) -> Result<Vec<Vec<Node>>, crate::Error> {
+        let mut result_recv: Receiver<Result<NodeBatch, Error>> =
+            Self::query_multiple(query_service, Query::Nodes(room_id, node_list)).await;
+        let mut batches = Vec::new();
+        let mut hashes = HashSet::new();
+        while let Some(batch) = result_recv.recv().await {
+            let batch = batch?;
+            hashes.extend(batch.binary_hashes.values().cloned());
+            batches.push(batch);
+        }
+
+        let mut binaries = HashMap::new();
+        if !hashes.is_empty() {
+            binaries = database.get_binaries(hashes.into_iter().collect()).await?;
+            let missing: Vec<Vec<u8>> = batches
+                .iter()
+                .flat_map(|batch| batch.binary_hashes.values())
+                .filter(|binary_hash| !binaries.contains_key(*binary_hash))
+                .cloned()
+                .collect::<HashSet<Vec<u8>>>()
+                .into_iter()
+                .collect();
+            if !missing.is_empty() {
+                let mut result_recv: Receiver<Result<Vec<StoredBinary>, Error>> =
+                    Self::query_multiple(query_service, Query::Binaries(room_id, missing)).await;
+                while let Some(received) = result_recv.recv().await {
+                    for (binary_hash, data) in received? {
+                        if security::hash(&data).as_slice() == binary_hash.as_slice() {
+                            binaries.insert(binary_hash, data);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut result = Vec::with_capacity(batches.len());
+        for batch in batches {
+            let mut nodes = Vec::with_capacity(batch.nodes.len());
+            for mut node in batch.nodes {
+                if let Some(binary_hash) = batch.binary_hashes.get(&node.id) {
+                    match binaries.get(binary_hash) {
+                        Some(data) => node._binary = Some(data.clone()),
+                        None => continue,
+                    }
+                }
+                nodes.push(node);
+            }
+            result.push(nodes);
+        }
+        Ok(result)
+    }
+
     async fn query_multiple<T: DeserializeOwned + Send + 'static>(
         query_service: &QueryService,
         query: Query,
@@ -1082,3 +1626,41 @@ impl LocalPeerService {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sync_tuning() {
+        let configuration = Configuration {
+            sync_days_per_round: 2,
+            sync_batch_size: 100,
+            sync_slow_link_rtt_in_ms: 50,
+            ..Default::default()
+        };
+        let tuning = SyncTuning::new(&configuration);
+
+        let fast = tuning.adapt(Duration::from_millis(10));
+        assert_eq!(2, fast.days_per_round);
+        assert_eq!(100, fast.batch_size);
+
+        let slow = tuning.adapt(Duration::from_millis(120));
+        assert_eq!(6, slow.days_per_round);
+        assert_eq!(300, slow.batch_size);
+
+        //the fan-out is bounded on very slow links
+        let very_slow = tuning.adapt(Duration::from_secs(10));
+        assert_eq!(8, very_slow.days_per_round);
+        assert_eq!(400, very_slow.batch_size);
+
+        let configuration = Configuration {
+            sync_days_per_round: 0,
+            sync_slow_link_rtt_in_ms: 0,
+            ..Default::default()
+        };
+        let disabled = SyncTuning::new(&configuration).adapt(Duration::from_secs(10));
+        assert_eq!(1, disabled.days_per_round);
+        assert_eq!(2048, disabled.batch_size);
+    }
+}
@@ -1,28 +1,34 @@
 #[cfg(feature = "log")]
-use log::error;
+use crate::log_level::{error, LogSubsystem};
+#[cfg(feature = "log")]
+const LOG_SUBSYSTEM: LogSubsystem = LogSubsystem::Sync;
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tokio::sync::{
     mpsc::{self, UnboundedSender},
-    Mutex,
+    Mutex, OwnedSemaphorePermit, Semaphore,
 };
 
 use crate::{
     base64_encode,
-    database::graph_database::GraphDatabaseService,
+    configuration::Configuration,
+    database::{graph_database::GraphDatabaseService, system_entities::RoomSettings},
+    date_utils::now,
+    event_service::{EventService, EventServiceMessage},
     peer_connection_service::PeerConnectionService,
-    security::{HardwareFingerprint, Uid},
+    security::{uid_encode, HardwareFingerprint, Uid},
 };
 
-use super::{Answer, Error, IdentityAnswer, Query, QueryProtocol};
+use super::{file_transfer::FileRequest, Answer, Error, IdentityAnswer, Query, QueryProtocol};
 
 ///
 /// handle all inbound queries
@@ -39,9 +45,11 @@ impl InboundQueryService {
         conn_id: Uid,
         mut peer: RemotePeerHandle,
         mut receiver: mpsc::Receiver<QueryProtocol>,
+        mut file_receiver: mpsc::Receiver<FileRequest>,
         peer_service: PeerConnectionService,
         verifying_key: Arc<Mutex<Vec<u8>>>,
         conn_ready: Arc<AtomicBool>,
+        limiter: QueryLimiter,
     ) -> Self {
         let (room_sender, mut room_receiver) = mpsc::unbounded_channel::<Uid>();
 
@@ -51,10 +59,19 @@ impl InboundQueryService {
                     msg = receiver.recv() =>{
                         match msg{
                             Some(msg) => {
+                                let key = verifying_key.lock().await.clone();
+                                let _permit = limiter.acquire(&key).await;
                                 if let Err(_e)  = Self::process_inbound(msg, &mut peer, &verifying_key, &conn_ready,  &fingerprint).await{
                                     #[cfg(feature = "log")]
                                     error!("RemoteQueryService Channel Send, Error: {_e}");
                                 }
+                                //small answers are kept while other queries are waiting to be processed
+                                if receiver.is_empty() {
+                                    if let Err(_e)  = peer.flush().await{
+                                        #[cfg(feature = "log")]
+                                        error!("RemoteQueryService Channel Send, Error: {_e}");
+                                    }
+                                }
                             },
                             None => break,
                         }
@@ -66,6 +83,11 @@ impl InboundQueryService {
                             None => break,
                         }
                     }
+                    Some(request) = file_receiver.recv() =>{
+                        //a closed file channel only disables this branch
+                        let key = verifying_key.lock().await.clone();
+                        Self::process_file_request(request, key, &peer);
+                    }
                 }
             }
 
@@ -86,7 +108,11 @@ impl InboundQueryService {
     ) -> Result<(), crate::Error> {
         match msg.query {
             Query::ProveIdentity(challenge) => {
-                let res = peer.db.sign(challenge).await;
+                let date = now();
+                let res = peer
+                    .db
+                    .sign(IdentityAnswer::signed_bytes(&challenge, date))
+                    .await?;
                 let self_peer = peer
                     .db
                     .get_peer_node(peer.verifying_key.clone())
@@ -99,6 +125,7 @@ impl InboundQueryService {
                     IdentityAnswer {
                         peer: self_peer,
                         chall_signature: res.1,
+                        date,
                     },
                 )
                 .await
@@ -156,9 +183,17 @@ impl InboundQueryService {
 
             Query::RoomDefinition(room_id) => {
                 if peer.allowed_room.contains(&room_id) {
-                    let res = peer.db.get_room_definition(room_id).await;
+                    let key = verifying_key.lock().await.clone();
+                    let res = peer.db.get_room_definition(room_id, Some(key)).await;
                     match res {
-                        Ok(definition) => peer.send(msg.id, true, true, definition).await?,
+                        Ok(mut definition) => {
+                            if let Some(bucket) = peer.log_privacy(&room_id, verifying_key).await {
+                                if let Some(definition) = definition.as_mut() {
+                                    peer.db.blur_definition(definition, bucket);
+                                }
+                            }
+                            peer.send(msg.id, true, true, definition).await?
+                        }
                         Err(_e) => {
                             #[cfg(feature = "log")]
                             error!("Query::RoomDefinition, Error: {_e}");
@@ -215,10 +250,17 @@ impl InboundQueryService {
 
             Query::RoomLog(room_id) => {
                 if peer.allowed_room.contains(&room_id) {
-                    let mut res_reply = peer.db.get_room_log(room_id).await;
+                    let privacy = peer.log_privacy(&room_id, verifying_key).await;
+                    let key = verifying_key.lock().await.clone();
+                    let mut res_reply = peer.db.get_room_log(room_id, Some(key)).await;
                     while let Some(res) = res_reply.recv().await {
                         match res {
-                            Ok(log) => peer.send(msg.id, true, false, log).await?,
+                            Ok(mut log) => {
+                                if let Some(bucket) = privacy {
+                                    peer.db.blur_logs(&mut log, bucket);
+                                }
+                                peer.send(msg.id, true, false, log).await?
+                            }
                             Err(_e) => {
                                 #[cfg(feature = "log")]
                                 error!("Query::RoomLog, Error: {_e}");
@@ -248,9 +290,15 @@ impl InboundQueryService {
 
             Query::RoomLogAt(room_id, date) => {
                 if peer.allowed_room.contains(&room_id) {
-                    let res = peer.db.get_room_log_at(room_id, date).await;
+                    let key = verifying_key.lock().await.clone();
+                    let res = peer.db.get_room_log_at(room_id, date, Some(key)).await;
                     match res {
-                        Ok(log) => peer.send(msg.id, true, true, log).await?,
+                        Ok(mut log) => {
+                            if let Some(bucket) = peer.log_privacy(&room_id, verifying_key).await {
+                                peer.db.blur_logs(&mut log, bucket);
+                            }
+                            peer.send(msg.id, true, true, log).await?
+                        }
                         Err(_e) => {
                             #[cfg(feature = "log")]
                             error!("Query::RoomLog, Error: {_e}");
@@ -278,7 +326,11 @@ impl InboundQueryService {
 
             Query::RoomDailyNodes(room_id, entity, date) => {
                 if peer.allowed_room.contains(&room_id) {
-                    let mut res_reply = peer.db.get_room_daily_nodes(room_id, entity, date).await;
+                    let key = verifying_key.lock().await.clone();
+                    let mut res_reply = peer
+                        .db
+                        .get_room_daily_nodes(room_id, entity, date, key)
+                        .await;
                     while let Some(res) = res_reply.recv().await {
                         match res {
                             Ok(log) => peer.send(msg.id, true, false, log).await?,
@@ -308,12 +360,86 @@ impl InboundQueryService {
                 Ok(())
             }
 
+            Query::SnapshotNodes(room_id, horizon) => {
+                if peer.allowed_room.contains(&room_id) {
+                    let key = verifying_key.lock().await.clone();
+                    let mut res_reply = peer.db.get_snapshot_nodes(room_id, horizon, key).await;
+                    while let Some(res) = res_reply.recv().await {
+                        match res {
+                            Ok(ids) => peer.send(msg.id, true, false, ids).await?,
+                            Err(_e) => {
+                                #[cfg(feature = "log")]
+                                error!("Query::SnapshotNodes, Error: {_e}");
+                                peer.send(
+                                    msg.id,
+                                    false,
+                                    true,
+                                    Error::RemoteTechnical("Query::SnapshotNodes".to_string()),
+                                )
+                                .await?
+                            }
+                        }
+                    }
+                    peer.send(msg.id, true, true, "").await?;
+                } else {
+                    peer.send(
+                        msg.id,
+                        false,
+                        true,
+                        Error::Authorisation("Query::SnapshotNodes".to_string()),
+                    )
+                    .await?
+                }
+                Ok(())
+            }
+
+            Query::SnapshotEdges(room_id, horizon) => {
+                if peer.allowed_room.contains(&room_id) {
+                    let key = verifying_key.lock().await.clone();
+                    let mut res_reply = peer.db.get_snapshot_edges(room_id, horizon, key).await;
+                    while let Some(res) = res_reply.recv().await {
+                        match res {
+                            Ok(edges) => peer.send(msg.id, true, false, edges).await?,
+                            Err(_e) => {
+                                #[cfg(feature = "log")]
+                                error!("Query::SnapshotEdges, Error: {_e}");
+                                peer.send(
+                                    msg.id,
+                                    false,
+                                    true,
+                                    Error::RemoteTechnical("Query::SnapshotEdges".to_string()),
+                                )
+                                .await?
+                            }
+                        }
+                    }
+                    peer.send(msg.id, true, true, "").await?;
+                } else {
+                    peer.send(
+                        msg.id,
+                        false,
+                        true,
+                        Error::Authorisation("Query::SnapshotEdges".to_string()),
+                    )
+                    .await?
+                }
+                Ok(())
+            }
+
             Query::Nodes(room_id, node_ids) => {
                 if peer.allowed_room.contains(&room_id) {
+                    let key = verifying_key.lock().await.clone();
                     let mut res_reply = peer.db.get_nodes(room_id, node_ids).await;
                     while let Some(res) = res_reply.recv().await {
                         match res {
-                            Ok(log) => peer.send(msg.id, true, false, log).await?,
+                            Ok(mut batch) => {
+                                //nodes restricted to other readers are never sent
+                                batch.nodes.retain(|node| node.is_readable_by(&key));
+                                let ids: HashSet<Uid> =
+                                    batch.nodes.iter().map(|node| node.id).collect();
+                                batch.binary_hashes.retain(|id, _| ids.contains(id));
+                                peer.send(msg.id, true, false, batch).await?
+                            }
                             Err(_e) => {
                                 #[cfg(feature = "log")]
                                 error!("Query::Nodes, Error: {_e}");
@@ -340,9 +466,43 @@ impl InboundQueryService {
                 Ok(())
             }
 
+            Query::Binaries(room_id, hashes) => {
+                if peer.allowed_room.contains(&room_id) {
+                    let key = verifying_key.lock().await.clone();
+                    let mut res_reply = peer.db.get_room_binaries(room_id, hashes, key).await;
+                    while let Some(res) = res_reply.recv().await {
+                        match res {
+                            Ok(binaries) => peer.send(msg.id, true, false, binaries).await?,
+                            Err(_e) => {
+                                #[cfg(feature = "log")]
+                                error!("Query::Binaries, Error: {_e}");
+                                peer.send(
+                                    msg.id,
+                                    false,
+                                    true,
+                                    Error::RemoteTechnical("Query::Binaries".to_string()),
+                                )
+                                .await?
+                            }
+                        }
+                    }
+                    peer.send(msg.id, true, true, "").await?;
+                } else {
+                    peer.send(
+                        msg.id,
+                        false,
+                        true,
+                        Error::Authorisation("Query::Binaries".to_string()),
+                    )
+                    .await?
+                }
+                Ok(())
+            }
+
             Query::Edges(room_id, nodes) => {
                 if peer.allowed_room.contains(&room_id) {
-                    let mut res_reply = peer.db.get_edges(room_id, nodes).await;
+                    let key = verifying_key.lock().await.clone();
+                    let mut res_reply = peer.db.get_edges(room_id, nodes, key).await;
                     while let Some(res) = res_reply.recv().await {
                         match res {
                             Ok(log) => peer.send(msg.id, true, false, log).await?,
@@ -442,27 +602,23 @@ impl InboundQueryService {
                 Ok(())
             }
 
-            Query::PeersForRoom(room_id) => {
+            Query::PeersForRoom(room_id, after) => {
                 if peer.allowed_room.contains(&room_id) {
-                    let mut res_reply = peer.db.peers_for_room(room_id).await;
-
-                    while let Some(res) = res_reply.recv().await {
-                        match res {
-                            Ok(log) => peer.send(msg.id, true, false, log).await?,
-                            Err(_e) => {
-                                #[cfg(feature = "log")]
-                                error!("Query::PeerNodes, Error: {_e}");
-                                peer.send(
-                                    msg.id,
-                                    false,
-                                    true,
-                                    Error::RemoteTechnical("Query::PeerNodes".to_string()),
-                                )
-                                .await?
-                            }
+                    let res = peer.db.peers_for_room_page(room_id, after).await;
+                    match res {
+                        Ok(page) => peer.send(msg.id, true, true, page).await?,
+                        Err(_e) => {
+                            #[cfg(feature = "log")]
+                            error!("Query::PeerNodes, Error: {_e}");
+                            peer.send(
+                                msg.id,
+                                false,
+                                true,
+                                Error::RemoteTechnical("Query::PeerNodes".to_string()),
+                            )
+                            .await?
                         }
                     }
-                    peer.send(msg.id, true, true, "").await?;
                 } else {
                     peer.send(
                         msg.id,
@@ -476,39 +632,315 @@ impl InboundQueryService {
             }
         }
     }
+    ///
+    /// file transfers are served in their own task to not delay the other queries
+    ///
+    fn process_file_request(request: FileRequest, verifying_key: Vec<u8>, peer: &RemotePeerHandle) {
+        if !peer.allowed_room.contains(request.query.room_id()) {
+            #[cfg(feature = "log")]
+            error!("FileRequest, Error: unauthorised room");
+            //dropping the request resets the stream
+            return;
+        }
+        let db = peer.db.clone();
+        tokio::spawn(async move {
+            if let Err(_e) = request.answer(&db, verifying_key).await {
+                #[cfg(feature = "log")]
+                error!("FileRequest, Error: {_e}");
+            }
+        });
+    }
+
     pub fn add_allowed_room(&self, room: Uid) {
         let _ = self.room_sender.send(room);
     }
 }
 
+///
+/// Inbound query counters of a remote peer since startup
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PeerQueryStats {
+    ///
+    /// number of queries received from the peer
+    ///
+    pub queries: u64,
+
+    ///
+    /// number of queries that have been delayed because the peer exceeded the rate or the concurrency limit
+    ///
+    pub throttled: u64,
+}
+
+struct PeerLimit {
+    tokens: f64,
+    last: Instant,
+    permits: Option<Arc<Semaphore>>,
+    throttling: bool,
+    stats: PeerQueryStats,
+}
+
+///
+/// limits the inbound queries of each remote peer
+///
+/// the rate is enforced with a token bucket: queries exceeding it are delayed instead of rejected, which slows down the peer.
+/// the concurrency limit is shared by every connection of the peer.
+/// queries received before the peer has proven its identity are not limited.
+///
+#[derive(Clone)]
+pub struct QueryLimiter {
+    rate: f64,
+    burst: f64,
+    concurrency: usize,
+    peers: Arc<std::sync::Mutex<HashMap<Vec<u8>, PeerLimit>>>,
+    events: EventService,
+}
+impl QueryLimiter {
+    pub fn new(configuration: &Configuration, events: EventService) -> Self {
+        Self {
+            rate: configuration.remote_query_rate as f64,
+            burst: configuration.remote_query_burst.max(1) as f64,
+            concurrency: configuration.remote_query_concurrency,
+            peers: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            events,
+        }
+    }
+
+    ///
+    /// waits until the peer is allowed to send a new query
+    ///
+    /// the returned permit must be kept during the query processing
+    ///
+    pub async fn acquire(&self, verifying_key: &[u8]) -> Option<OwnedSemaphorePermit> {
+        if verifying_key.is_empty() {
+            return None;
+        }
+        let (delay, permits, notify) = {
+            let mut peers = self.peers.lock().unwrap();
+            let limit = peers
+                .entry(verifying_key.to_vec())
+                .or_insert_with(|| PeerLimit {
+                    tokens: self.burst,
+                    last: Instant::now(),
+                    permits: (self.concurrency > 0)
+                        .then(|| Arc::new(Semaphore::new(self.concurrency))),
+                    throttling: false,
+                    stats: PeerQueryStats::default(),
+                });
+            limit.stats.queries += 1;
+
+            let mut delay = None;
+            if self.rate > 0.0 {
+                let now = Instant::now();
+                let elapsed = now.duration_since(limit.last).as_secs_f64();
+                limit.tokens = (limit.tokens + elapsed * self.rate).min(self.burst) - 1.0;
+                limit.last = now;
+                if limit.tokens < 0.0 {
+                    delay = Some(Duration::from_secs_f64(-limit.tokens / self.rate));
+                }
+            }
+            let busy = limit
+                .permits
+                .as_ref()
+                .is_some_and(|permits| permits.available_permits() == 0);
+
+            let throttled = delay.is_some() || busy;
+            let mut notify = None;
+            if throttled {
+                limit.stats.throttled += 1;
+                if !limit.throttling {
+                    notify = Some(limit.stats.throttled);
+                }
+            }
+            limit.throttling = throttled;
+            (delay, limit.permits.clone(), notify)
+        };
+
+        if let Some(throttled) = notify {
+            self.events
+                .notify(EventServiceMessage::PeerThrottled(
+                    verifying_key.to_vec(),
+                    throttled,
+                ))
+                .await;
+        }
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+        match permits {
+            Some(permits) => permits.acquire_owned().await.ok(),
+            None => None,
+        }
+    }
+
+    pub fn stats(&self, verifying_key: &Vec<u8>) -> Option<PeerQueryStats> {
+        self.peers
+            .lock()
+            .unwrap()
+            .get(verifying_key)
+            .map(|limit| limit.stats.clone())
+    }
+}
+
+///
+/// answers smaller than this size are batched with other answers
+///
+static SMALL_ANSWER_SIZE: usize = 4 * 1024;
+
+///
+/// a batch of answers is sent as soon as it reaches this size
+///
+static ANSWER_BATCH_SIZE: usize = 32 * 1024;
+
 pub struct RemotePeerHandle {
     pub allowed_room: HashSet<Uid>,
     pub db: GraphDatabaseService,
     pub verifying_key: Vec<u8>,
-    pub reply: mpsc::Sender<Answer>,
+    reply: mpsc::Sender<Vec<Answer>>,
+    batch: Vec<Answer>,
+    batch_size: usize,
 }
 impl RemotePeerHandle {
+    pub fn new(
+        db: GraphDatabaseService,
+        verifying_key: Vec<u8>,
+        reply: mpsc::Sender<Vec<Answer>>,
+    ) -> Self {
+        Self {
+            allowed_room: HashSet::new(),
+            db,
+            verifying_key,
+            reply,
+            batch: Vec::new(),
+            batch_size: 0,
+        }
+    }
+
     fn add_allowed_room(&mut self, room: Uid) {
         self.allowed_room.insert(room);
     }
 
+    ///
+    /// your own devices share the same verifying key and receive the exact room logs,
+    /// other peers receive coarsened counts when the room defines the log_privacy setting
+    ///
+    async fn log_privacy(&self, room_id: &Uid, remote_key: &Arc<Mutex<Vec<u8>>>) -> Option<u32> {
+        if remote_key.lock().await.eq(&self.verifying_key) {
+            return None;
+        }
+        match RoomSettings::load(&uid_encode(room_id), &self.db).await {
+            Ok(settings) => settings.log_privacy(),
+            Err(_e) => {
+                #[cfg(feature = "log")]
+                error!("RoomSettings::load, Error: {_e}");
+                None
+            }
+        }
+    }
+
     async fn send<T: Serialize>(
-        &self,
+        &mut self,
         id: u64,
         success: bool,
         complete: bool,
         msg: T,
     ) -> Result<(), crate::Error> {
         let serialized = bincode::serialize(&msg)?;
+        let size = serialized.len();
         let answer = Answer {
             id,
             success,
             complete,
             serialized,
         };
+        if size >= SMALL_ANSWER_SIZE {
+            self.flush().await?;
+            return self
+                .reply
+                .send(vec![answer])
+                .await
+                .map_err(|e| crate::Error::SendError(e.to_string()));
+        }
+
+        self.batch.push(answer);
+        self.batch_size += size;
+        if self.batch_size >= ANSWER_BATCH_SIZE {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// send the pending small answers in one message
+    ///
+    async fn flush(&mut self) -> Result<(), crate::Error> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = std::mem::take(&mut self.batch);
+        self.batch_size = 0;
         self.reply
-            .send(answer)
+            .send(batch)
             .await
             .map_err(|e| crate::Error::SendError(e.to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_service::Event;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn query_limiter() {
+        let configuration = Configuration {
+            remote_query_rate: 100,
+            remote_query_burst: 5,
+            remote_query_concurrency: 1,
+            ..Default::default()
+        };
+        let events = EventService::new();
+        let mut receiver = events.receiver().await;
+        let limiter = QueryLimiter::new(&configuration, events);
+        let key = vec![1, 2, 3];
+
+        //not limited before the identity is proven
+        assert!(limiter.acquire(&[]).await.is_none());
+
+        let start = Instant::now();
+        for _ in 0..5 {
+            let _permit = limiter.acquire(&key).await;
+        }
+        let stats = limiter.stats(&key).unwrap();
+        assert_eq!(5, stats.queries);
+        assert_eq!(0, stats.throttled);
+
+        for _ in 0..10 {
+            let _permit = limiter.acquire(&key).await;
+        }
+        //10 queries over the burst at 100 queries per second
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        let stats = limiter.stats(&key).unwrap();
+        assert_eq!(15, stats.queries);
+        assert!(stats.throttled >= 9);
+
+        match receiver.recv().await {
+            Some(Event::PeerThrottled(verifying_key, throttled)) => {
+                assert_eq!(key, verifying_key);
+                assert_eq!(1, throttled);
+            }
+            _ => panic!("PeerThrottled expected"),
+        }
+
+        //the concurrency is limited
+        let permit = limiter.acquire(&[4]).await;
+        let other = limiter.clone();
+        let waiting = tokio::spawn(async move { other.acquire(&[4]).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        drop(permit);
+        waiting.await.unwrap();
+        assert_eq!(1, limiter.stats(&vec![4]).unwrap().throttled);
+        assert!(limiter.stats(&vec![5]).is_none());
+    }
+}
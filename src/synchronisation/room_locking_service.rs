@@ -141,14 +141,14 @@ mod tests {
         let (sender, mut receiver) = mpsc::unbounded_channel::<Uid>();
 
         lock_service
-            .request_locks(peer_id.clone(), rooms.clone(), sender.clone())
+            .request_locks(peer_id, rooms.clone(), sender.clone())
             .await;
         let room = receiver.recv().await.unwrap();
 
         lock_service.unlock(room).await;
 
         lock_service
-            .request_locks(peer_id.clone(), rooms, sender.clone())
+            .request_locks(peer_id, rooms, sender.clone())
             .await;
 
         let room = receiver.recv().await.unwrap();
@@ -175,7 +175,7 @@ mod tests {
                 let (sender, mut receiver) = mpsc::unbounded_channel::<Uid>();
                 service
                     .clone()
-                    .request_locks(peer.clone(), local_rooms, sender.clone())
+                    .request_locks(peer, local_rooms, sender.clone())
                     .await;
                 for _ in 0..num_entries {
                     let room = receiver.recv().await.unwrap();
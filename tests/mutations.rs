@@ -1,6 +1,9 @@
 use std::path::PathBuf;
 
-use discret::{Configuration, Discret, Parameters, ParametersAdd, ResultParser};
+use discret::{
+    base64_encode, AffectedData, Configuration, Discret, MemberRightChange, Parameters,
+    ParametersAdd, ResultParser, RightType, RoomBuilder, RoomRight, TransactionBuilder,
+};
 use rand::{rngs::OsRng, RngCore};
 
 use serde::Deserialize;
@@ -72,7 +75,24 @@ async fn minimal() {
     assert_eq!(
         result,
         "{\n\"Greetings\":[{\"message\":\"Hello World\"}]\n}"
-    )
+    );
+
+    let mut params = Parameters::new();
+    params.add("id", id.clone()).unwrap();
+    assert!(app
+        .exists("Greetings", "id=$id", Some(params.clone()))
+        .await
+        .unwrap());
+    assert_eq!(
+        1,
+        app.count("Greetings", "id=$id", Some(params))
+            .await
+            .unwrap()
+    );
+    assert!(!app
+        .exists("Greetings", "message=\"Goodbye\"", None)
+        .await
+        .unwrap());
 }
 
 #[tokio::test(flavor = "multi_thread")]
@@ -147,3 +167,1023 @@ async fn batch_insert() {
     assert_eq!(msg.len(), num_message);
     assert_eq!(&msg[0].message, "hello world 0");
 }
+
+#[test]
+fn shutdown() {
+    let datamodel = "{
+            Greetings{
+                message:String
+            }
+        }";
+    let key_material = random32();
+    let embedded = tokio::runtime::Runtime::new().unwrap();
+    let caller = tokio::runtime::Runtime::new().unwrap();
+
+    caller.block_on(async {
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let app = Discret::new_with_runtime(
+            datamodel,
+            "shutdown",
+            &key_material,
+            DATA_PATH.into(),
+            Configuration::default(),
+            embedded.handle().clone(),
+            async {
+                let _ = stopped.await;
+            },
+        )
+        .await
+        .unwrap();
+
+        app.mutate(
+            r#"mutate {
+                Greetings{
+                    message: "Hello World"
+                }
+            }"#,
+            None,
+        )
+        .await
+        .unwrap();
+
+        //the shutdown token stops the services
+        stop.send(()).unwrap();
+        let handle = tokio::spawn(async move {
+            while app
+                .query("query { Greetings{ message } }", None)
+                .await
+                .is_ok()
+            {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            app
+        });
+        let app = tokio::time::timeout(std::time::Duration::from_secs(5), handle)
+            .await
+            .unwrap()
+            .unwrap();
+        app.mutate(r#"mutate { Greetings{ message: "too late" } }"#, None)
+            .await
+            .expect_err("stopped");
+        app.shutdown().await.unwrap();
+
+        //the flushed data is available after a restart
+        let app = Discret::new(
+            datamodel,
+            "shutdown",
+            &key_material,
+            DATA_PATH.into(),
+            Configuration::default(),
+        )
+        .await
+        .unwrap();
+        let result = app
+            .query("query { Greetings{ message } }", None)
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            "{\n\"Greetings\":[{\"message\":\"Hello World\"}]\n}"
+        );
+        app.shutdown().await.unwrap();
+        app.query("query { Greetings{ message } }", None)
+            .await
+            .expect_err("stopped");
+    });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mutation_batch() {
+    let datamodel = "{
+            Greetings{
+                message:String
+            }
+        }";
+    let key_material = random32();
+    let app = Discret::new(
+        datamodel,
+        "mutation_batch",
+        &key_material,
+        DATA_PATH.into(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    let query = r#"mutate {
+                result: Greetings{
+                    message: $message
+                }
+            }"#;
+
+    let mut batch = app.mutation_batch(8);
+    for i in 0..50 {
+        let mut param = Parameters::new();
+        param.add("message", format!("hello world {}", i)).unwrap();
+        assert_eq!(i, batch.send(query, Some(param)).await.unwrap());
+    }
+    //the batch never holds more than max_in_flight mutations
+    assert!(batch.summary().pending() <= 8);
+
+    //checkpoint: every sent mutation is written
+    let summary = batch.flush().await.unwrap();
+    assert_eq!(50, summary.sent);
+    assert_eq!(50, summary.written);
+    assert_eq!(0, summary.pending());
+    assert!(summary.failures.is_empty());
+
+    //the missing parameter makes the mutation fail
+    batch.send(query, None).await.unwrap();
+    let mut param = Parameters::new();
+    param.add("message", "last".to_string()).unwrap();
+    batch.send(query, Some(param)).await.unwrap();
+
+    let summary = batch.close().await.unwrap();
+    assert_eq!(52, summary.sent);
+    assert_eq!(51, summary.written);
+    assert_eq!(1, summary.failures.len());
+    assert_eq!(50, summary.failures[0].0);
+
+    let result = app
+        .query("query { Greetings { message } }", None)
+        .await
+        .unwrap();
+    let mut parser = ResultParser::new(&result).unwrap();
+    #[derive(Deserialize)]
+    struct Messages {
+        pub message: String,
+    }
+    let msg: Vec<Messages> = parser.take_array("Greetings").unwrap();
+    assert_eq!(51, msg.len());
+    assert!(msg.iter().any(|m| m.message.eq("last")));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mutate_batch() {
+    let datamodel = "{
+            Greetings{
+                message:String
+            }
+        }";
+    let key_material = random32();
+    let app = Discret::new(
+        datamodel,
+        "mutate_batch",
+        &key_material,
+        DATA_PATH.into(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    let query = r#"mutate {
+                result: Greetings{
+                    message: $message
+                }
+            }"#;
+
+    let mut params = Vec::new();
+    for i in 0..100 {
+        let mut param = Parameters::new();
+        param.add("message", format!("hello world {}", i)).unwrap();
+        params.push(param);
+    }
+    let results = app.mutate_batch(query, params).await.unwrap();
+    assert_eq!(100, results.len());
+    assert!(results[99].contains("hello world 99"));
+    assert_eq!(100, app.count("Greetings", "", None).await.unwrap());
+
+    //the batch is atomic: the missing parameter rejects every mutation
+    let mut param = Parameters::new();
+    param.add("message", "valid".to_string()).unwrap();
+    app.mutate_batch(query, vec![param, Parameters::new()])
+        .await
+        .expect_err("missing parameter");
+    assert_eq!(100, app.count("Greetings", "", None).await.unwrap());
+
+    let mut param = Parameters::new();
+    param.add("key", app.verifying_key()).unwrap();
+    let err = app
+        .mutate_batch(
+            r#"mutate { sys.Room{ admin: [{ verif_key:$key }] } }"#,
+            vec![param],
+        )
+        .await
+        .expect_err("rooms cannot be batched");
+    assert!(err.to_string().contains("batch"));
+
+    assert!(app
+        .mutate_batch(query, Vec::new())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn transaction() {
+    let datamodel = "{
+            Greetings{
+                message:String
+            }
+        }";
+    let app = Discret::new(
+        datamodel,
+        "transaction",
+        &random32(),
+        DATA_PATH.into(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    #[derive(Deserialize)]
+    struct Id {
+        id: String,
+    }
+    let insert = r#"mutate { result: Greetings{ message: $message } }"#;
+    let mut param = Parameters::new();
+    param.add("message", "first".to_string()).unwrap();
+    let res = app.mutate(insert, Some(param)).await.unwrap();
+    let first: Id = ResultParser::new(&res)
+        .unwrap()
+        .take_object("result")
+        .unwrap();
+
+    let mut delete_param = Parameters::new();
+    delete_param.add("id", first.id.clone()).unwrap();
+    let mut param = Parameters::new();
+    param.add("message", "second".to_string()).unwrap();
+    let transaction = TransactionBuilder::new()
+        .delete("delete { Greetings { $id } }", Some(delete_param))
+        .mutate(insert, Some(param));
+    let results = app.transaction(&transaction).await.unwrap();
+    assert_eq!(1, results.len());
+    assert!(results[0].contains("second"));
+    assert_eq!(1, app.count("Greetings", "", None).await.unwrap());
+    let second: Id = ResultParser::new(&results[0])
+        .unwrap()
+        .take_object("result")
+        .unwrap();
+
+    //the mutation in a room without any right is rejected: nothing is written
+    let room = RoomBuilder::new().admin(&app.verifying_key());
+    let room = app.create_room(&room).await.unwrap();
+    let mut delete_param = Parameters::new();
+    delete_param.add("id", second.id.clone()).unwrap();
+    let mut param = Parameters::new();
+    param.add("message", "third".to_string()).unwrap();
+    let mut room_param = Parameters::new();
+    room_param.add("room_id", room.id.clone()).unwrap();
+    let transaction = TransactionBuilder::new()
+        .mutate(insert, Some(param))
+        .delete("delete { Greetings { $id } }", Some(delete_param))
+        .mutate(
+            r#"mutate { Greetings{ room_id: $room_id message: "forbidden" } }"#,
+            Some(room_param),
+        );
+    assert_eq!(3, transaction.len());
+    let err = app
+        .transaction(&transaction)
+        .await
+        .expect_err("no right in the room");
+    assert!(err.to_string().contains("not enough right"));
+    assert_eq!(1, app.count("Greetings", "", None).await.unwrap());
+    assert!(app
+        .exists("Greetings", "message=\"second\"", None)
+        .await
+        .unwrap());
+
+    let mut param = Parameters::new();
+    param.add("key", app.verifying_key()).unwrap();
+    let transaction = TransactionBuilder::new().mutate(
+        r#"mutate { sys.Room{ admin: [{ verif_key:$key }] } }"#,
+        Some(param),
+    );
+    let err = app
+        .transaction(&transaction)
+        .await
+        .expect_err("rooms cannot be modified in a transaction");
+    assert!(err.to_string().contains("transaction"));
+
+    assert!(app
+        .transaction(&TransactionBuilder::new())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn delete_cascade() {
+    let datamodel = "{
+            Person{
+                name:String,
+                pets:[Pet]
+            }
+            Pet{
+                name:String,
+                toys:[Toy]
+            }
+            Toy{
+                name:String
+            }
+        }";
+    let app = Discret::new(
+        datamodel,
+        "delete_cascade",
+        &random32(),
+        DATA_PATH.into(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    #[derive(Deserialize)]
+    struct Id {
+        id: String,
+    }
+    #[derive(Deserialize)]
+    struct Pet {
+        id: String,
+        #[serde(default)]
+        toys: Vec<Id>,
+    }
+    #[derive(Deserialize)]
+    struct Person {
+        id: String,
+        pets: Vec<Pet>,
+    }
+    let res = app
+        .mutate(
+            r#"mutate {
+                P1: Person{
+                    name: "John"
+                    pets: [{ name: "Rex" toys: [{ name: "Ball" }] }, { name: "Shared" }]
+                }
+            }"#,
+            None,
+        )
+        .await
+        .unwrap();
+    let john: Person = ResultParser::new(&res).unwrap().take_object("P1").unwrap();
+    let shared = john.pets[1].id.clone();
+
+    let mut param = Parameters::new();
+    param.add("shared", shared.clone()).unwrap();
+    let res = app
+        .mutate(
+            r#"mutate { P2: Person{ name: "Alice" pets: [{ id: $shared }] } }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+    let alice: Id = ResultParser::new(&res).unwrap().take_object("P2").unwrap();
+
+    //the shared pet is still referenced by Alice
+    let mut param = Parameters::new();
+    param.add("id", john.id.clone()).unwrap();
+    let deleted = app
+        .delete("delete @cascade { Person { $id } }", Some(param))
+        .await
+        .unwrap();
+    assert_eq!(3, deleted.len());
+    assert_eq!(john.id, deleted[0].id);
+    assert_eq!("Person", deleted[0].entity);
+    assert!(deleted
+        .iter()
+        .any(|d| d.id.eq(&john.pets[0].id) && d.entity.eq("Pet")));
+    assert!(deleted
+        .iter()
+        .any(|d| d.id.eq(&john.pets[0].toys[0].id) && d.entity.eq("Toy")));
+    assert_eq!(1, app.count("Pet", "", None).await.unwrap());
+    assert_eq!(0, app.count("Toy", "", None).await.unwrap());
+
+    //removing the last reference deletes the pet
+    let mut param = Parameters::new();
+    param.add("id", alice.id.clone()).unwrap();
+    param.add("shared", shared.clone()).unwrap();
+    let deleted = app
+        .delete(
+            "delete @cascade { Person { $id }.pets{ $shared } }",
+            Some(param),
+        )
+        .await
+        .unwrap();
+    assert_eq!(1, deleted.len());
+    assert_eq!(shared, deleted[0].id);
+    assert_eq!(0, app.count("Pet", "", None).await.unwrap());
+    assert_eq!(1, app.count("Person", "", None).await.unwrap());
+
+    //without cascade only the existing entities are returned
+    let mut param = Parameters::new();
+    param.add("id", alice.id.clone()).unwrap();
+    param.add("missing", shared).unwrap();
+    let deleted = app
+        .delete("delete { Person { $id } Pet { $missing } }", Some(param))
+        .await
+        .unwrap();
+    assert_eq!(1, deleted.len());
+    assert_eq!(alice.id, deleted[0].id);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn soft_delete() {
+    let datamodel = "{
+            Note(soft_delete){
+                title:String,
+                tags:[Tag]
+            }
+            Tag{
+                name:String
+            }
+            Folder{
+                notes:[Note]
+            }
+        }";
+    let app = Discret::new(
+        datamodel,
+        "soft_delete",
+        &random32(),
+        DATA_PATH.into(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    #[derive(Deserialize)]
+    struct Id {
+        id: String,
+    }
+    #[derive(Deserialize)]
+    struct Folder {
+        notes: Vec<Id>,
+    }
+    let res = app
+        .mutate(
+            r#"mutate {
+                F: Folder{
+                    notes: [{ title: "first" tags: [{ name: "todo" }] }, { title: "second" }]
+                }
+            }"#,
+            None,
+        )
+        .await
+        .unwrap();
+    let folder: Folder = ResultParser::new(&res).unwrap().take_object("F").unwrap();
+    let first = folder.notes[0].id.clone();
+
+    let mut param = Parameters::new();
+    param.add("id", first.clone()).unwrap();
+    let deleted = app
+        .delete("delete { Note { $id } }", Some(param.clone()))
+        .await
+        .unwrap();
+    assert_eq!(1, deleted.len());
+    assert_eq!(first, deleted[0].id);
+
+    //soft deleted notes are excluded from the results, sub entities included
+    assert_eq!(1, app.count("Note", "", None).await.unwrap());
+    assert_eq!(2, app.count("Note", "include_deleted", None).await.unwrap());
+    let res = app
+        .query("query { Folder { notes { id } } }", None)
+        .await
+        .unwrap();
+    let folders: Vec<Folder> = ResultParser::new(&res)
+        .unwrap()
+        .take_array("Folder")
+        .unwrap();
+    assert_eq!(1, folders[0].notes.len());
+
+    //the trash folder, the references of the deleted note are kept
+    #[derive(Deserialize)]
+    struct Deleted {
+        id: String,
+        sys_deleted: i64,
+        tags: Vec<Id>,
+    }
+    let res = app
+        .query(
+            "query { Note(include_deleted, sys_deleted > 0) { id sys_deleted tags { id } } }",
+            None,
+        )
+        .await
+        .unwrap();
+    let trash: Vec<Deleted> = ResultParser::new(&res).unwrap().take_array("Note").unwrap();
+    assert_eq!(1, trash.len());
+    assert_eq!(first, trash[0].id);
+    assert!(trash[0].sys_deleted > 0);
+    assert_eq!(1, trash[0].tags.len());
+
+    //undo
+    app.mutate(
+        "mutate { Note { id: $id sys_deleted: null } }",
+        Some(param.clone()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(2, app.count("Note", "", None).await.unwrap());
+
+    //deleting a soft deleted note removes it
+    app.delete("delete { Note { $id } }", Some(param.clone()))
+        .await
+        .unwrap();
+    app.delete("delete { Note { $id } }", Some(param))
+        .await
+        .unwrap();
+    assert_eq!(1, app.count("Note", "include_deleted", None).await.unwrap());
+
+    app.query("query { Tag(include_deleted) { id } }", None)
+        .await
+        .expect_err("Tag does not define soft_delete");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn room_builder() {
+    let datamodel = "chat { 
+            Message{
+                content:String
+            }
+        }";
+    let key_material = random32();
+    let app = Discret::new(
+        datamodel,
+        "room_builder",
+        &key_material,
+        DATA_PATH.into(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+    let key = app.verifying_key();
+    let other_key = Discret::new(
+        datamodel,
+        "room_builder",
+        &random32(),
+        DATA_PATH.into(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap()
+    .verifying_key();
+
+    let room = RoomBuilder::new()
+        .admin(&key)
+        .auth("writers")
+        .right("chat.Message", true, false)
+        .user(&key)
+        .user_admin(&key)
+        .auth("readers")
+        .user(&other_key);
+    let created = app.create_room(&room).await.unwrap();
+    assert_eq!(2, created.authorisations.len());
+    assert!(created.authorisations.contains_key("writers"));
+    assert!(created.authorisations.contains_key("readers"));
+
+    assert!(app
+        .can(&key, &created.id, "chat.Message", RightType::MutateSelf)
+        .await
+        .unwrap());
+    assert!(!app
+        .can(&key, &created.id, "chat.Message", RightType::MutateAll)
+        .await
+        .unwrap());
+    assert!(!app
+        .can(
+            &other_key,
+            &created.id,
+            "chat.Message",
+            RightType::MutateSelf
+        )
+        .await
+        .unwrap());
+
+    let mut param = Parameters::new();
+    param.add("room_id", created.id.clone()).unwrap();
+    app.mutate(
+        r#"mutate {
+            chat.Message{
+                room_id: $room_id
+                content: "hello"
+            }
+        }"#,
+        Some(param),
+    )
+    .await
+    .unwrap();
+
+    let counter = app
+        .entity_counter(&created.id, "chat.Message")
+        .await
+        .unwrap();
+    assert_eq!(1, counter.count);
+    assert!(counter.mdate > 0);
+    assert!(app
+        .entity_counter(&created.id, "chat.Unknown")
+        .await
+        .is_err());
+
+    //rights must belong to an authorisation
+    let invalid = RoomBuilder::new()
+        .admin(&key)
+        .right("chat.Message", true, false);
+    assert!(app.create_room(&invalid).await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ordered_mutation_stream() {
+    let datamodel = "{
+            Greetings{
+                message:String
+            }
+        }";
+    let key_material = random32();
+    let app = Discret::new(
+        datamodel,
+        "ordered_mutation_stream",
+        &key_material,
+        DATA_PATH.into(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    let query = r#"mutate {
+                result: Greetings{
+                    message: $message
+                }
+            }"#;
+
+    let (sender, mut receiver) = app.ordered_mutation_stream();
+    let result_task = tokio::spawn(async move {
+        let mut results = Vec::new();
+        while let Some((sequence, result)) = receiver.recv().await {
+            results.push((sequence, result.is_ok()));
+        }
+        results
+    });
+
+    for i in 0..100u64 {
+        //every tenth mutation fails because of the missing parameter
+        let param = if i % 10 == 3 {
+            None
+        } else {
+            let mut param = Parameters::new();
+            param.add("message", format!("hello world {}", i)).unwrap();
+            Some(param)
+        };
+        sender.send((i, query.to_string(), param)).await.unwrap();
+    }
+    drop(sender);
+
+    let results = result_task.await.unwrap();
+    assert_eq!(100, results.len());
+    for (i, (sequence, success)) in results.into_iter().enumerate() {
+        assert_eq!(i as u64, sequence);
+        assert_eq!(i % 10 != 3, success);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn relocate_data_folder() {
+    let datamodel = "{
+            Greetings{
+                message:String
+            }
+        }";
+    let key_material = random32();
+    let folder = format!("{}relocation/{}", DATA_PATH, OsRng.next_u64());
+    let first: PathBuf = format!("{}/first", folder).into();
+    let second: PathBuf = format!("{}/second", folder).into();
+    std::fs::create_dir_all(&first).unwrap();
+
+    let app = Discret::new(
+        datamodel,
+        "relocation",
+        &key_material,
+        first.clone(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    let query = r#"mutate {
+                result: Greetings{
+                    message: $message
+                }
+            }"#;
+    let mut param = Parameters::new();
+    param.add("message", "before".to_string()).unwrap();
+    app.mutate(query, Some(param)).await.unwrap();
+
+    //the writes sent during the relocation are queued
+    let writer = app.clone();
+    let writes = tokio::spawn(async move {
+        for i in 0..20 {
+            let mut param = Parameters::new();
+            param.add("message", format!("during {}", i)).unwrap();
+            writer.mutate(query, Some(param)).await.unwrap();
+        }
+    });
+    app.relocate_data_folder(second.clone()).await.unwrap();
+    writes.await.unwrap();
+
+    //the database is the only file of its subfolder
+    let remaining: Vec<_> = std::fs::read_dir(&first)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(
+        vec![std::ffi::OsString::from("hardware_fingerprint.bin")],
+        remaining
+    );
+    assert!(second.join("hardware_fingerprint.bin").exists());
+
+    app.relocate_data_folder(second.clone())
+        .await
+        .expect_err("the database is already in the folder");
+
+    let mut param = Parameters::new();
+    param.add("message", "after".to_string()).unwrap();
+    app.mutate(query, Some(param)).await.unwrap();
+    app.shutdown().await.unwrap();
+
+    let app = Discret::new(
+        datamodel,
+        "relocation",
+        &key_material,
+        second,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+    let result = app
+        .query("query { Greetings{ message } }", None)
+        .await
+        .unwrap();
+    let mut parser = ResultParser::new(&result).unwrap();
+    #[derive(Deserialize)]
+    struct Messages {
+        pub message: String,
+    }
+    let msg: Vec<Messages> = parser.take_array("Greetings").unwrap();
+    assert_eq!(22, msg.len());
+    assert!(msg.iter().any(|m| m.message.eq("before")));
+    assert!(msg.iter().any(|m| m.message.eq("after")));
+    app.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn preview_room_change() {
+    let datamodel = "chat {
+            Message{
+                content:String
+            }
+        }";
+    let app = Discret::new(
+        datamodel,
+        "preview_room_change",
+        &random32(),
+        DATA_PATH.into(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+    let key = app.verifying_key();
+    let writer_key = Discret::new(
+        datamodel,
+        "preview_room_change",
+        &random32(),
+        DATA_PATH.into(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap()
+    .verifying_key();
+
+    let room = RoomBuilder::new()
+        .admin(&key)
+        .auth("writers")
+        .right("chat.Message", true, false)
+        .user(&writer_key);
+    let created = app.create_room(&room).await.unwrap();
+    let auth_id = created.authorisations.get("writers").unwrap().clone();
+
+    for i in 0..3 {
+        let mut param = Parameters::new();
+        param.add("room_id", created.id.clone()).unwrap();
+        param.add("content", format!("message {i}")).unwrap();
+        app.mutate(
+            r#"mutate { chat.Message{ room_id: $room_id content: $content } }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+    }
+
+    let change = r#"mutate {
+            sys.Room{
+                id: $room_id
+                authorisations:[{
+                    id: $auth_id
+                    rights:[{entity:"chat.Message" mutate_self:false mutate_all:false}]
+                }]
+            }
+        }"#;
+    let mut param = Parameters::new();
+    param.add("room_id", created.id.clone()).unwrap();
+    param.add("auth_id", auth_id.clone()).unwrap();
+    let impact = app
+        .preview_room_change(&created.id, change, Some(param))
+        .await
+        .unwrap();
+
+    assert_eq!(created.id, impact.room_id);
+    assert_eq!(2, impact.changes.len());
+    for member in [&key, &writer_key] {
+        assert!(impact.changes.contains(&MemberRightChange {
+            verifying_key: member.clone(),
+            right: RoomRight::MutateSelf("chat.Message".to_string()),
+            granted: false,
+        }));
+    }
+    assert_eq!(
+        vec![AffectedData {
+            verifying_key: key.clone(),
+            entity: "chat.Message".to_string(),
+            authored: 3,
+            rejected: 0,
+        }],
+        impact.affected_data
+    );
+
+    //the preview does not modify the room
+    assert!(app
+        .can(&key, &created.id, "chat.Message", RightType::MutateSelf)
+        .await
+        .unwrap());
+
+    //adding a user grants the rights of the authorisation
+    let new_key = Discret::new(
+        datamodel,
+        "preview_room_change",
+        &random32(),
+        DATA_PATH.into(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap()
+    .verifying_key();
+    let mut param = Parameters::new();
+    param.add("room_id", created.id.clone()).unwrap();
+    param.add("auth_id", auth_id.clone()).unwrap();
+    param.add("new_key", new_key.clone()).unwrap();
+    let impact = app
+        .preview_room_change(
+            &created.id,
+            r#"mutate {
+                sys.Room{
+                    id: $room_id
+                    authorisations:[{
+                        id: $auth_id
+                        users:[{verif_key:$new_key}]
+                    }]
+                }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![MemberRightChange {
+            verifying_key: new_key,
+            right: RoomRight::MutateSelf("chat.Message".to_string()),
+            granted: true,
+        }],
+        impact.changes
+    );
+    assert!(impact.affected_data.is_empty());
+
+    //invalid mutations are rejected
+    let mut param = Parameters::new();
+    param.add("room_id", created.id.clone()).unwrap();
+    param.add("auth_id", auth_id).unwrap();
+    app.preview_room_change(&created.id, change, None)
+        .await
+        .expect_err("missing parameters");
+    app.preview_room_change(&base64_encode(&random32()[0..16]), change, Some(param))
+        .await
+        .expect_err("unknown room");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invite_room_members() {
+    let datamodel = "chat {
+            Message{
+                content:String
+            }
+        }";
+    let new_key = || async {
+        Discret::new(
+            datamodel,
+            "invite_room_members",
+            &random32(),
+            DATA_PATH.into(),
+            Configuration::default(),
+        )
+        .await
+        .unwrap()
+        .verifying_key()
+    };
+    let app = Discret::new(
+        datamodel,
+        "invite_room_members",
+        &random32(),
+        DATA_PATH.into(),
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+    let key = app.verifying_key();
+    let first_key = new_key().await;
+    let second_key = new_key().await;
+    let disabled_key = new_key().await;
+
+    let group = RoomBuilder::new()
+        .admin(&key)
+        .auth("members")
+        .right("chat.Message", true, false)
+        .user(&first_key)
+        .user(&second_key)
+        .user(&disabled_key);
+    let group = app.create_room(&group).await.unwrap();
+    let mut param = Parameters::new();
+    param.add("room_id", group.id.clone()).unwrap();
+    param
+        .add(
+            "auth_id",
+            group.authorisations.get("members").unwrap().clone(),
+        )
+        .unwrap();
+    param.add("key", disabled_key.clone()).unwrap();
+    app.mutate(
+        r#"mutate {
+            sys.Room{
+                id: $room_id
+                authorisations:[{
+                    id: $auth_id
+                    users:[{verif_key:$key enabled:false}]
+                }]
+            }
+        }"#,
+        Some(param),
+    )
+    .await
+    .unwrap();
+
+    let breakout = RoomBuilder::new()
+        .admin(&key)
+        .auth("breakout")
+        .right("chat.Message", true, false)
+        .user(&second_key);
+    let breakout = app.create_room(&breakout).await.unwrap();
+    let auth_id = breakout.authorisations.get("breakout").unwrap().clone();
+
+    let invitation = app
+        .invite_room_members(&group.id, &breakout.id, &auth_id)
+        .await
+        .unwrap();
+    //the local peer, the disabled member and the existing member are not invited
+    assert_eq!(vec![first_key.clone()], invitation.invited);
+    //the peer definitions are only known once a room is synchronised
+    assert_eq!(vec![first_key.clone()], invitation.unknown_peers);
+    assert!(app
+        .can(
+            &first_key,
+            &breakout.id,
+            "chat.Message",
+            RightType::MutateSelf
+        )
+        .await
+        .unwrap());
+    assert!(!app
+        .can(
+            &disabled_key,
+            &breakout.id,
+            "chat.Message",
+            RightType::MutateSelf
+        )
+        .await
+        .unwrap());
+
+    let invitation = app
+        .invite_room_members(&group.id, &breakout.id, &auth_id)
+        .await
+        .unwrap();
+    assert!(invitation.invited.is_empty());
+
+    app.invite_room_members(&group.id, &breakout.id, &group.id)
+        .await
+        .expect_err("unknown authorisation");
+    app.invite_room_members(&base64_encode(&random32()[0..16]), &breakout.id, &auth_id)
+        .await
+        .expect_err("unknown room");
+}
@@ -1,8 +1,10 @@
 use std::{ops::Deref, path::PathBuf, time::Duration};
 
 use discret::{
-    base64_encode, generate_x509_certificate, hash, Beacon, BeaconConfig, Configuration,
-    DefaultRoom, Discret, Event, Parameters, ParametersAdd, ResultParser,
+    base64_decode, base64_encode, generate_x509_certificate, hash, Beacon, BeaconConfig,
+    BeaconEvent, BeaconServerConfig, Configuration, ConnectionPolicy, DefaultRoom,
+    DeviceLinkRequest, Discret, DiscretObserver, Event, MeetingTokenInfo, MeetingTokenKind,
+    Parameters, ParametersAdd, ResultParser, RightType, RoomBuilder, Storage,
 };
 use rand::{rngs::OsRng, RngCore};
 
@@ -46,13 +48,12 @@ async fn multicast_connect() {
         loop {
             let event = events.recv().await;
             match event {
-                Ok(e) => match e {
-                    Event::RoomSynchronized(room_id) => {
+                Ok(e) => {
+                    if let Event::RoomSynchronized(room_id) = e {
                         assert_eq!(room_id, private_room_id);
                         break;
                     }
-                    _ => {}
-                },
+                }
                 Err(e) => println!("Error {}", e),
             }
         }
@@ -63,6 +64,345 @@ async fn multicast_connect() {
     assert!(s.is_ok());
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn clock_offset() {
+    let path: PathBuf = DATA_PATH.into();
+    let model = "{Person{name:String,}}";
+    let key_material = random32();
+    let discret1: Discret = Discret::new(
+        model,
+        "clock_offset",
+        &key_material,
+        path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    let second_path: PathBuf = format!("{}/second", DATA_PATH).into();
+    let discret2: Discret = Discret::new(
+        model,
+        "clock_offset",
+        &key_material,
+        second_path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+    let mut events = discret2.subscribe_for_events().await;
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Ok(Event::PeerConnected(verifying_key, _, _)) = events.recv().await {
+                break verifying_key;
+            }
+        }
+    });
+
+    let verifying_key = tokio::time::timeout(Duration::from_secs(2), handle)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let offset = discret2
+        .peer_clock_offset(verifying_key.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    //both instances share the same clock
+    assert!(offset.abs() < 1000);
+
+    let offset = discret1
+        .peer_clock_offset(random32().to_vec())
+        .await
+        .unwrap();
+    assert!(offset.is_none());
+
+    //only the instance that initiated the connection measures the handshake
+    let stats1 = discret1
+        .peer_handshake_stats(verifying_key.clone())
+        .await
+        .unwrap();
+    let stats2 = discret2
+        .peer_handshake_stats(verifying_key.clone())
+        .await
+        .unwrap();
+    assert!(stats1.is_some() || stats2.is_some());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn data_model_proposal() {
+    let path: PathBuf = DATA_PATH.into();
+    let model = "{Person{name:String,}}";
+    let app_name = "data_model_proposal";
+    let key_material = random32();
+    let discret1: Discret = Discret::new(
+        model,
+        app_name,
+        &key_material,
+        path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    let second_path: PathBuf = format!("{}/second", DATA_PATH).into();
+    let discret2: Discret = Discret::new(
+        model,
+        app_name,
+        &key_material,
+        second_path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    let mut param = Parameters::new();
+    param.add("user_id", discret1.verifying_key()).unwrap();
+    let result = discret1
+        .mutate(
+            r#"mutate {
+                sys.Room{
+                    admin: [{
+                        verif_key:$user_id
+                    }]
+                }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+    #[derive(Deserialize)]
+    struct Id {
+        id: String,
+    }
+    let mut parser = ResultParser::new(&result).unwrap();
+    let room_id = parser.take_object::<Id>("sys.Room").unwrap().id;
+
+    discret1
+        .propose_data_model(&room_id, "{Person{name:Strin,}}")
+        .await
+        .expect_err("invalid data model");
+
+    let new_model = "{Person{name:String,} Pet{name:String,}}";
+    let proposal_id = discret1
+        .propose_data_model(&room_id, new_model)
+        .await
+        .unwrap();
+
+    let proposals = discret1.data_model_proposals(&room_id).await.unwrap();
+    assert_eq!(1, proposals.len());
+    assert!(!proposals[0].confirmed);
+    assert!(discret1
+        .preflight_data_model(&proposals[0].model)
+        .await
+        .unwrap()
+        .contains("Pet"));
+    assert!(!discret1.data_model().await.unwrap().contains("Pet"));
+
+    let data_model = discret1.confirm_data_model(&proposal_id).await.unwrap();
+    assert!(data_model.contains("Pet"));
+
+    //the second device applies the update once the confirmation is synchronized
+    let handle = tokio::spawn(async move {
+        loop {
+            if discret2.data_model().await.unwrap().contains("Pet") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    });
+    tokio::time::timeout(Duration::from_secs(3), handle)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn data_model_template() {
+    let path: PathBuf = DATA_PATH.into();
+    let model = "{Person{name:String,}}";
+    let app_name = "data_model_template";
+    let key_material = random32();
+    let discret1: Discret = Discret::new(
+        model,
+        app_name,
+        &key_material,
+        path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    //a generic application starts without any data model
+    let second_path: PathBuf = format!("{}/second", DATA_PATH).into();
+    let discret2: Discret = Discret::new(
+        "",
+        app_name,
+        &key_material,
+        second_path,
+        Configuration {
+            accept_data_model_templates: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let mut param = Parameters::new();
+    param.add("user_id", discret1.verifying_key()).unwrap();
+    let result = discret1
+        .mutate(
+            r#"mutate {
+                sys.Room{
+                    admin: [{
+                        verif_key:$user_id
+                    }]
+                }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+    #[derive(Deserialize)]
+    struct Id {
+        id: String,
+    }
+    let mut parser = ResultParser::new(&result).unwrap();
+    let room_id = parser.take_object::<Id>("sys.Room").unwrap().id;
+
+    discret1
+        .publish_data_model_template(&room_id, "people", "{Person{name:Strin,}}")
+        .await
+        .expect_err("invalid data model");
+
+    let template_id = discret1
+        .publish_data_model_template(&room_id, "people", model)
+        .await
+        .unwrap();
+
+    discret1
+        .publish_data_model_template(&room_id, "pets", model)
+        .await
+        .expect_err("a room provides a single template");
+
+    let template = discret1
+        .data_model_template(&room_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(template_id, template.id);
+    assert_eq!("people", template.template_id);
+
+    let handle = tokio::spawn(async move {
+        loop {
+            if discret2.data_model().await.unwrap().contains("Person") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        discret2
+    });
+    let discret2 = tokio::time::timeout(Duration::from_secs(3), handle)
+        .await
+        .unwrap()
+        .unwrap();
+
+    //updates of the template are applied
+    discret1
+        .publish_data_model_template(
+            &room_id,
+            "people",
+            "{Person{name:String,} Pet{name:String,}}",
+        )
+        .await
+        .unwrap();
+
+    let handle = tokio::spawn(async move {
+        loop {
+            if discret2.data_model().await.unwrap().contains("Pet") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        discret2
+    });
+    let discret2 = tokio::time::timeout(Duration::from_secs(3), handle)
+        .await
+        .unwrap()
+        .unwrap();
+
+    //the acquired template cannot be replaced by another one
+    let mut param = Parameters::new();
+    param.add("user_id", discret2.verifying_key()).unwrap();
+    let result = discret2
+        .mutate(
+            r#"mutate {
+                sys.Room{
+                    admin: [{
+                        verif_key:$user_id
+                    }]
+                }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+    let mut parser = ResultParser::new(&result).unwrap();
+    let other_room_id = parser.take_object::<Id>("sys.Room").unwrap().id;
+    discret2
+        .publish_data_model_template(
+            &other_room_id,
+            "pets",
+            "{Person{name:String,} Pet{name:String,}}",
+        )
+        .await
+        .unwrap();
+    match discret2.accept_data_model_template(&other_room_id).await {
+        Err(discret::Error::InvalidUpdateTemplate()) => {}
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn meeting_tokens() {
+    let path: PathBuf = DATA_PATH.into();
+    let model = "{Person{name:String,}}";
+    let key_material = random32();
+    let discret: Discret = Discret::new(
+        model,
+        "meeting_tokens",
+        &key_material,
+        path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    discret.invite(None).await.unwrap();
+    discret.invite(None).await.unwrap();
+
+    let invites = |tokens: Vec<MeetingTokenInfo>| {
+        tokens
+            .into_iter()
+            .filter(|t| t.kind == MeetingTokenKind::OwnedInvite)
+            .count()
+    };
+
+    let tokens = discret.meeting_tokens().await.unwrap();
+    assert_eq!(2, invites(tokens));
+
+    //nothing is older than the epoch
+    let removed = discret.prune_tokens(0).await.unwrap();
+    assert_eq!(0, removed);
+    let tokens = discret.meeting_tokens().await.unwrap();
+    assert_eq!(2, invites(tokens));
+
+    let removed = discret.prune_tokens(i64::MAX).await.unwrap();
+    assert_eq!(2, removed);
+    let tokens = discret.meeting_tokens().await.unwrap();
+    assert_eq!(0, invites(tokens));
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn invites() {
     let path: PathBuf = DATA_PATH.into();
@@ -160,13 +500,12 @@ async fn invites() {
         loop {
             let event = events.recv().await;
             match event {
-                Ok(e) => match e {
-                    Event::RoomSynchronized(room_id) => {
+                Ok(e) => {
+                    if let Event::RoomSynchronized(room_id) = e {
                         assert_eq!(room_id, new_room);
                         break;
                     }
-                    _ => {}
-                },
+                }
                 Err(e) => println!("Error {}", e),
             }
         }
@@ -207,7 +546,7 @@ async fn invites() {
     let mut parser = ResultParser::new(&res1).unwrap();
     let ids: Vec<Id> = parser.take_array("sys.AllowedPeer").unwrap();
     assert_eq!(ids.len(), 2);
-    assert!(ids[0].id.len() > 0);
+    assert!(!ids[0].id.is_empty());
 
     let res2 = discret2.query(query, None).await.unwrap();
     let mut parser = ResultParser::new(&res2).unwrap();
@@ -248,43 +587,255 @@ async fn invites() {
 }
 
 #[tokio::test(flavor = "multi_thread")]
-async fn invites_beacon() {
+async fn connection_policy() {
     let path: PathBuf = DATA_PATH.into();
-    let app_name = "hello";
+    let app_name = "connection_policy";
     let model = "{Person{name:String,}}";
-    let key_material = random32();
-    let certificate = generate_x509_certificate("sample.org");
-    let cert_hash = hash(certificate.cert.der().deref());
-    let cert_hash = base64_encode(&cert_hash);
-    let der: Vec<u8> = certificate.cert.der().deref().to_vec();
-    let pks_der: Vec<u8> = certificate.key_pair.serialize_der();
-
-    let port = 4262;
-    let hostname = format!("127.0.0.1:{}", port); //::1
-    let beacon_conf = BeaconConfig {
-        hostname,
-        cert_hash,
-    };
-    let beacons_def = vec![beacon_conf];
-
     let config = Configuration {
-        enable_multicast: false,
-        beacons: beacons_def,
+        multicast_ipv4_group: "224.0.0.224:22406".to_string(),
         ..Default::default()
     };
-    let _ = Beacon::start(port, der, pks_der, true).unwrap();
+    let prompt_config = Configuration {
+        connection_policy: ConnectionPolicy::Prompt,
+        ..config.clone()
+    };
 
-    let discret1: Discret =
+    let discret1: Discret = Discret::new(
+        model,
+        app_name,
+        &random32(),
+        path.clone(),
+        prompt_config.clone(),
+    )
+    .await
+    .unwrap();
+
+    let key_material = random32();
+    let discret2: Discret =
         Discret::new(model, app_name, &key_material, path.clone(), config.clone())
             .await
             .unwrap();
 
-    let mut param = Parameters::new();
-    param.add("key", discret1.verifying_key()).unwrap();
-    let result = discret1
-        .mutate(
-            r#"mutate mut {
-                sys.Room{
+    //invites are an explicit consent and are not prompted
+    let invite = discret1.invite(None).await.unwrap();
+    let mut events = discret2.subscribe_for_events().await;
+    discret2.accept_invite(invite).await.unwrap();
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Ok(Event::PeerConnected(_, _, _)) = events.recv().await {
+                break;
+            }
+        }
+    });
+    tokio::time::timeout(Duration::from_secs(3), handle)
+        .await
+        .unwrap()
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    discret2.shutdown().await.unwrap();
+
+    //the allowed peer reconnects and has to be accepted
+    let mut events = discret1.subscribe_for_events().await;
+    let discret2: Discret = Discret::new(model, app_name, &key_material, path, config)
+        .await
+        .unwrap();
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Ok(Event::ConnectionRequest(verifying_key)) = events.recv().await {
+                break verifying_key;
+            }
+        }
+    });
+    let verifying_key = tokio::time::timeout(Duration::from_secs(3), handle)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(discret2.verifying_key(), base64_encode(&verifying_key));
+
+    let mut events = discret2.subscribe_for_events().await;
+    discret1
+        .answer_connection_request(verifying_key, true)
+        .await
+        .unwrap();
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Ok(Event::PeerConnected(_, _, _)) = events.recv().await {
+                break;
+            }
+        }
+    });
+    tokio::time::timeout(Duration::from_secs(3), handle)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn device_link_approval() {
+    let path: PathBuf = DATA_PATH.into();
+    let key_material = random32();
+    let discret: Discret = Discret::new(
+        "{Person{name:String,}}",
+        "device link",
+        &key_material,
+        path,
+        Configuration {
+            storage: Storage::Memory,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let new_device = DeviceLinkRequest::new();
+    discret
+        .approve_device_link(&new_device.request(), &new_device.code(), &random32())
+        .expect_err("the key material of another account");
+
+    let response = discret
+        .approve_device_link(&new_device.request(), &new_device.code(), &key_material)
+        .unwrap();
+    let linked = new_device.complete(&response).unwrap();
+    assert_eq!(key_material, linked.key_material);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invites_with_pin() {
+    let path: PathBuf = DATA_PATH.into();
+    let app_name = "hello";
+    let model = "{Person{name:String,}}";
+    let config = Configuration {
+        multicast_ipv4_group: "224.0.0.224:22407".to_string(),
+        ..Default::default()
+    };
+
+    let discret1: Discret =
+        Discret::new(model, app_name, &random32(), path.clone(), config.clone())
+            .await
+            .unwrap();
+
+    let mut param = Parameters::new();
+    param.add("key", discret1.verifying_key()).unwrap();
+    let result = discret1
+        .mutate(
+            r#"mutate mut {
+                sys.Room{
+                    admin: [{
+                        verif_key:$key
+                    }]
+                    authorisations:[{
+                        name:"admin"
+                        rights:[{
+                            entity:"Person"
+                            mutate_self:true
+                            mutate_all:true
+                        }]
+                    }]
+                }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+
+    #[derive(Deserialize)]
+    struct Ids {
+        id: String,
+        authorisations: Vec<Auth>,
+    }
+    #[derive(Deserialize)]
+    struct Auth {
+        id: String,
+    }
+    let mut parser = ResultParser::new(&result).unwrap();
+    let mut ids: Ids = parser.take_object("sys.Room").unwrap();
+    let room_id = ids.id;
+    let auth_id = ids.authorisations.pop().unwrap().id;
+
+    let invite = discret1
+        .invite_with_pin(
+            Some(DefaultRoom {
+                room: room_id.clone(),
+                authorisation: auth_id,
+            }),
+            "739104582261",
+        )
+        .await
+        .unwrap();
+
+    let discret2: Discret = Discret::new(model, app_name, &random32(), path, config.clone())
+        .await
+        .unwrap();
+
+    discret2
+        .accept_invite(invite.clone())
+        .await
+        .expect_err("the PIN is required");
+    discret2
+        .accept_invite_with_pin(invite.clone(), "739104582262")
+        .await
+        .expect_err("invalid PIN");
+
+    let mut events = discret2.subscribe_for_events().await;
+    discret2
+        .accept_invite_with_pin(invite, "739104582261")
+        .await
+        .unwrap();
+
+    let new_room = room_id;
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Ok(Event::RoomSynchronized(room_id)) = events.recv().await {
+                assert_eq!(room_id, new_room);
+                break;
+            }
+        }
+    });
+    tokio::time::timeout(Duration::from_millis(3000), handle)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invites_beacon() {
+    let path: PathBuf = DATA_PATH.into();
+    let app_name = "hello";
+    let model = "{Person{name:String,}}";
+    let key_material = random32();
+    let certificate = generate_x509_certificate("sample.org");
+    let cert_hash = hash(certificate.cert.der().deref());
+    let cert_hash = base64_encode(&cert_hash);
+    let der: Vec<u8> = certificate.cert.der().deref().to_vec();
+    let pks_der: Vec<u8> = certificate.key_pair.serialize_der();
+
+    let port = 4262;
+    let hostname = format!("127.0.0.1:{}", port); //::1
+    let beacon_conf = BeaconConfig {
+        hostname,
+        cert_hash,
+        register_tokens: false,
+    };
+    let beacons_def = vec![beacon_conf];
+
+    let config = Configuration {
+        enable_multicast: false,
+        beacons: beacons_def,
+        ..Default::default()
+    };
+    let beacon = Beacon::start(port, der, pks_der, true).unwrap();
+
+    let discret1: Discret =
+        Discret::new(model, app_name, &key_material, path.clone(), config.clone())
+            .await
+            .unwrap();
+
+    let mut param = Parameters::new();
+    param.add("key", discret1.verifying_key()).unwrap();
+    let result = discret1
+        .mutate(
+            r#"mutate mut {
+                sys.Room{
                     admin: [{
                         verif_key:$key
                     }]
@@ -357,13 +908,12 @@ async fn invites_beacon() {
         loop {
             let event = events.recv().await;
             match event {
-                Ok(e) => match e {
-                    Event::RoomSynchronized(room_id) => {
+                Ok(e) => {
+                    if let Event::RoomSynchronized(room_id) = e {
                         assert_eq!(room_id, new_room);
                         break;
                     }
-                    _ => {}
-                },
+                }
                 Err(e) => println!("Error {}", e),
             }
         }
@@ -404,7 +954,7 @@ async fn invites_beacon() {
     let mut parser = ResultParser::new(&res1).unwrap();
     let ids: Vec<Id> = parser.take_array("sys.AllowedPeer").unwrap();
     assert_eq!(ids.len(), 2);
-    assert!(ids[0].id.len() > 0);
+    assert!(!ids[0].id.is_empty());
 
     let res2 = discret2.query(query, None).await.unwrap();
     let mut parser = ResultParser::new(&res2).unwrap();
@@ -442,6 +992,11 @@ async fn invites_beacon() {
     let mut parser = ResultParser::new(&res2).unwrap();
     let ids: Vec<Id> = parser.take_array("sys.Invite").unwrap();
     assert_eq!(ids.len(), 0);
+
+    let metrics = beacon.application_metrics(app_name).await.unwrap();
+    assert_eq!(metrics.connections, 2);
+    assert!(metrics.initiated_connections > 0);
+    assert!(beacon.application_metrics("another app").await.is_none());
 }
 
 #[tokio::test(flavor = "multi_thread")]
@@ -538,13 +1093,12 @@ async fn new_peers_from_room() {
         loop {
             let event = events.recv().await;
             match event {
-                Ok(e) => match e {
-                    Event::RoomSynchronized(room_id) => {
+                Ok(e) => {
+                    if let Event::RoomSynchronized(room_id) = e {
                         assert_eq!(room_id, new_r);
                         break;
                     }
-                    _ => {}
-                },
+                }
                 Err(e) => println!("Error {}", e),
             }
         }
@@ -568,13 +1122,12 @@ async fn new_peers_from_room() {
         loop {
             let event = events.recv().await;
             match event {
-                Ok(e) => match e {
-                    Event::RoomSynchronized(room_id) => {
+                Ok(e) => {
+                    if let Event::RoomSynchronized(room_id) = e {
                         assert_eq!(room_id, new_room);
                         break;
                     }
-                    _ => {}
-                },
+                }
                 Err(e) => println!("Error {}", e),
             }
         }
@@ -619,13 +1172,13 @@ async fn new_peers_from_room() {
     let mut parser = ResultParser::new(&res2).unwrap();
     let ids: Vec<Id> = parser.take_array("sys.AllowedPeer").unwrap();
     assert_eq!(ids.len(), 1);
-    assert!(ids[0].id.len() > 0);
+    assert!(!ids[0].id.is_empty());
 
     let res3 = discret3.query(query, None).await.unwrap();
     let mut parser = ResultParser::new(&res3).unwrap();
     let ids: Vec<Id> = parser.take_array("sys.AllowedPeer").unwrap();
     assert_eq!(ids.len(), 1);
-    assert!(ids[0].id.len() > 0);
+    assert!(!ids[0].id.is_empty());
 }
 
 #[tokio::test(flavor = "multi_thread")]
@@ -715,13 +1268,12 @@ async fn multiple_entities() {
         loop {
             let event = events.recv().await;
             match event {
-                Ok(e) => match e {
-                    Event::RoomSynchronized(room_id) => {
+                Ok(e) => {
+                    if let Event::RoomSynchronized(room_id) = e {
                         assert_eq!(room_id, private_room_id);
                         break;
                     }
-                    _ => {}
-                },
+                }
                 Err(e) => println!("Error {}", e),
             }
         }
@@ -735,3 +1287,806 @@ async fn multiple_entities() {
     let res2 = discret2.query(query, None).await.unwrap();
     assert_eq!(res1, res2);
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn labelled_private_room() {
+    let path: PathBuf = DATA_PATH.into();
+    let model = "{Person{name:String,}}";
+    let key_material = random32();
+    let discret1: Discret = Discret::new(
+        model,
+        "hello",
+        &key_material,
+        path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    let settings_room = discret1.private_room_for("settings").await.unwrap();
+    assert_ne!(settings_room, discret1.private_room());
+    assert_eq!(
+        settings_room,
+        discret1.private_room_for("settings").await.unwrap()
+    );
+    assert_ne!(
+        settings_room,
+        discret1.private_room_for("drafts").await.unwrap()
+    );
+
+    let mutation = "mutate {
+        Person {
+            room_id:$room_id
+            name:$name
+        }
+    }";
+    let mut param = Parameters::new();
+    param.add("room_id", settings_room.clone()).unwrap();
+    param.add("name", "John".to_string()).unwrap();
+    discret1.mutate(mutation, Some(param)).await.unwrap();
+
+    let second_path: PathBuf = format!("{}/second", DATA_PATH).into();
+    let discret2: Discret = Discret::new(
+        model,
+        "hello",
+        &key_material,
+        second_path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+    let mut events = discret2.subscribe_for_events().await;
+    assert_eq!(
+        settings_room,
+        discret2.private_room_for("settings").await.unwrap()
+    );
+
+    let mut param = Parameters::new();
+    param.add("room_id", settings_room.clone()).unwrap();
+    param.add("name", "Ada".to_string()).unwrap();
+    discret1.mutate(mutation, Some(param)).await.unwrap();
+
+    #[derive(Deserialize)]
+    struct Person {
+        name: String,
+    }
+    let room_id = settings_room.clone();
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Ok(Event::RoomSynchronized(synchronized)) = events.recv().await {
+                if synchronized != room_id {
+                    continue;
+                }
+                let mut param = Parameters::new();
+                param.add("room_id", room_id.clone()).unwrap();
+                let result = discret2
+                    .query(
+                        "query {
+                            Person(room_id=$room_id, order_by(name asc)){
+                                name
+                            }
+                        }",
+                        Some(param),
+                    )
+                    .await
+                    .unwrap();
+                let mut parser = ResultParser::new(&result).unwrap();
+                let persons: Vec<Person> = parser.take_array("Person").unwrap();
+                if persons.len() == 2 {
+                    assert_eq!(persons[0].name, "Ada");
+                    assert_eq!(persons[1].name, "John");
+                    break;
+                }
+            }
+        }
+    });
+
+    tokio::time::timeout(Duration::from_secs(4), handle)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn availability() {
+    let path: PathBuf = DATA_PATH.into();
+    let model = "{Person{name:String,}}";
+    let key_material = random32();
+    let discret1: Discret = Discret::new(
+        model,
+        "availability",
+        &key_material,
+        path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+    let private_room_id = discret1.private_room();
+
+    let mut param = Parameters::new();
+    param.add("room_id", private_room_id.clone()).unwrap();
+    discret1
+        .mutate(
+            r#"mutate {
+                P1: Person { room_id:$room_id name: "John" }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+
+    //no peer has synchronised yet
+    let availability = discret1.availability(&private_room_id).await.unwrap();
+    let person: Vec<_> = availability
+        .iter()
+        .filter(|a| a.entity.eq("Person"))
+        .collect();
+    assert_eq!(1, person.len());
+    assert_eq!(1, person[0].entry_number);
+    assert_eq!(0, person[0].copies);
+
+    let second_path: PathBuf = format!("{}/second", DATA_PATH).into();
+    let discret2: Discret = Discret::new(
+        model,
+        "availability",
+        &key_material,
+        second_path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+    let mut events = discret2.subscribe_for_events().await;
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Ok(Event::RoomSynchronized(room_id)) = events.recv().await {
+                break room_id;
+            }
+        }
+    });
+    let room_id = tokio::time::timeout(Duration::from_secs(4), handle)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(private_room_id, room_id);
+
+    //the synchronised data is held by the first peer
+    let availability = discret2.availability(&private_room_id).await.unwrap();
+    let person: Vec<_> = availability
+        .iter()
+        .filter(|a| a.entity.eq("Person"))
+        .collect();
+    assert_eq!(1, person.len());
+    assert_eq!(1, person[0].copies);
+
+    //both devices share the same key material, hence the same verifying key
+    let verifying_key = base64_decode(discret1.verifying_key().as_bytes()).unwrap();
+    let stats = discret2
+        .peer_sync_stats(verifying_key.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(stats.nodes_accepted > 0);
+    assert!(stats.last_room_sync.contains_key(&private_room_id));
+
+    //the synchronised room holds the same content on both devices
+    let divergence = discret2
+        .room_divergence(&private_room_id, verifying_key)
+        .await
+        .unwrap();
+    assert!(divergence.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn file_synchronisation() {
+    let path: PathBuf = DATA_PATH.into();
+    let model = "{Document{name:String, content:File,}}";
+    let key_material = random32();
+    let discret1: Discret = Discret::new(
+        model,
+        "file_synchronisation",
+        &key_material,
+        path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+    let private_room_id = discret1.private_room();
+
+    //several chunks, larger than the maximum node size
+    let content: Vec<u8> = (0..300 * 1024).map(|i| (i % 251) as u8).collect();
+    let file_id = discret1
+        .put_file(&private_room_id, content.clone())
+        .await
+        .unwrap();
+    assert_eq!(content, discret1.get_file(&file_id).await.unwrap().unwrap());
+    assert!(discret1.get_file("invalid").await.is_err());
+
+    let mut param = Parameters::new();
+    param.add("room_id", private_room_id.clone()).unwrap();
+    param.add("file_id", file_id.clone()).unwrap();
+    discret1
+        .mutate(
+            r#"mutate {
+                Document { room_id:$room_id name: "report" content:$file_id }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+
+    let second_path: PathBuf = format!("{}/second", DATA_PATH).into();
+    let discret2: Discret = Discret::new(
+        model,
+        "file_synchronisation",
+        &key_material,
+        second_path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+    let mut events = discret2.subscribe_for_events().await;
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Ok(Event::RoomSynchronized(room_id)) = events.recv().await {
+                break room_id;
+            }
+        }
+    });
+    let room_id = tokio::time::timeout(Duration::from_secs(4), handle)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(private_room_id, room_id);
+
+    //the files are downloaded before the room is notified as synchronised
+    assert_eq!(content, discret2.get_file(&file_id).await.unwrap().unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn observer() {
+    let app_name = "observer";
+    let model = "{Person{name:String,}}";
+    let folder = format!("{}observer/{}", DATA_PATH, OsRng.next_u64());
+    std::fs::create_dir_all(&folder).unwrap();
+    let config = Configuration {
+        enable_multicast: false,
+        ..Default::default()
+    };
+
+    let discret: Discret = Discret::new(
+        model,
+        app_name,
+        &random32(),
+        folder.clone().into(),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+
+    //only the verifying key of the auditor is provided to the observer
+    let auditor: Discret = Discret::new(
+        model,
+        app_name,
+        &random32(),
+        folder.clone().into(),
+        Configuration {
+            storage: Storage::Memory,
+            ..config.clone()
+        },
+    )
+    .await
+    .unwrap();
+    let auditor_key = auditor.verifying_key();
+    auditor.shutdown().await.unwrap();
+
+    //the readers authorisation does not define any right
+    let room = RoomBuilder::new()
+        .admin(&discret.verifying_key())
+        .auth("writers")
+        .right("Person", true, true)
+        .auth("readers")
+        .user(&auditor_key);
+    let created = discret.create_room(&room).await.unwrap();
+
+    let mut param = Parameters::new();
+    param.add("room_id", created.id.clone()).unwrap();
+    discret
+        .mutate(
+            r#"mutate {
+                Person{
+                    room_id:$room_id
+                    name: "John Doe"
+                }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+
+    let observer = DiscretObserver::new(
+        model,
+        app_name,
+        &auditor_key,
+        &random32(),
+        folder.clone().into(),
+        config,
+    )
+    .await
+    .unwrap();
+    assert_eq!(auditor_key, observer.verifying_key());
+
+    let archive: PathBuf = format!("{}/rooms.archive", folder).into();
+    discret
+        .export_rooms(vec![created.id.clone()], archive.clone(), "kiosk")
+        .await
+        .unwrap();
+    let summary = observer
+        .import_archive(archive, "kiosk", true)
+        .await
+        .unwrap();
+    assert_eq!(vec![created.id.clone()], summary.rooms);
+    assert_eq!(1, summary.nodes_imported);
+
+    let query = "query{
+        Person{
+            name
+        }
+    }";
+    let res = observer.query(query, None).await.unwrap();
+    assert_eq!(res, discret.query(query, None).await.unwrap());
+    assert!(res.contains("John Doe"));
+
+    //the observer cannot write in the room
+    assert!(!discret
+        .can(
+            &observer.verifying_key(),
+            &created.id,
+            "Person",
+            RightType::MutateSelf
+        )
+        .await
+        .unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn entity_subscription() {
+    let path: PathBuf = DATA_PATH.into();
+    let model = "{
+        Message {
+            content: String
+        }
+
+        Attachment {
+            data: String
+        }
+    }";
+    let key_material = random32();
+    let discret1: Discret = Discret::new(
+        model,
+        "hello",
+        &key_material,
+        path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+
+    let second_path: PathBuf = format!("{}/second", DATA_PATH).into();
+    let discret2: Discret = Discret::new(
+        model,
+        "hello",
+        &key_material,
+        second_path,
+        Configuration::default(),
+    )
+    .await
+    .unwrap();
+    let private_room_id = discret2.private_room();
+
+    let mut events = discret2.subscribe_for_events().await;
+    let room_id = private_room_id.clone();
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Ok(Event::RoomSynchronized(id)) = events.recv().await {
+                assert_eq!(id, room_id);
+                break;
+            }
+        }
+    });
+    tokio::time::timeout(Duration::from_secs(4), handle)
+        .await
+        .unwrap()
+        .unwrap();
+
+    discret2
+        .subscribe_entities(&private_room_id, vec!["Message".to_string()])
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    //the attachment does not notify the second peer
+    let mut param = Parameters::new();
+    param.add("room_id", discret1.private_room()).unwrap();
+    let mut events = discret2.subscribe_for_events().await;
+    discret1
+        .mutate(
+            "mutate { Attachment { room_id:$room_id data:\"bulky\" } }",
+            Some(param),
+        )
+        .await
+        .unwrap();
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Ok(Event::RoomSynchronized(_)) = events.recv().await {
+                break;
+            }
+        }
+    });
+    assert!(tokio::time::timeout(Duration::from_secs(1), handle)
+        .await
+        .is_err());
+
+    //the message does, and the room is fully synchronised
+    let mut param = Parameters::new();
+    param.add("room_id", discret1.private_room()).unwrap();
+    let mut events = discret2.subscribe_for_events().await;
+    discret1
+        .mutate(
+            "mutate { Message { room_id:$room_id content:\"hello\" } }",
+            Some(param),
+        )
+        .await
+        .unwrap();
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Ok(Event::RoomSynchronized(_)) = events.recv().await {
+                break;
+            }
+        }
+    });
+    tokio::time::timeout(Duration::from_secs(4), handle)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let query = "query {
+        Message { content }
+        Attachment { data }
+    }";
+    let res1 = discret1.query(query, None).await.unwrap();
+    let res2 = discret2.query(query, None).await.unwrap();
+    assert_eq!(res1, res2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn beacon_token_registration() {
+    let app_name = "registered app";
+    let model = "{Person{name:String,}}";
+    let certificate = generate_x509_certificate("sample.org");
+    let cert_hash = base64_encode(&hash(certificate.cert.der().deref()));
+    let der: Vec<u8> = certificate.cert.der().deref().to_vec();
+    let pks_der: Vec<u8> = certificate.key_pair.serialize_der();
+
+    let port = 4263;
+    let config = Configuration {
+        enable_multicast: false,
+        beacons: vec![BeaconConfig {
+            hostname: format!("127.0.0.1:{}", port),
+            cert_hash,
+            register_tokens: true,
+        }],
+        ..Default::default()
+    };
+    let beacon = Beacon::start(port, der, pks_der, true).unwrap();
+
+    //the identities only depend on the key material and the application, they are approved before connecting
+    let path: PathBuf = format!("{}/registration", DATA_PATH).into();
+    std::fs::create_dir_all(&path).unwrap();
+    let key_materials = [random32(), random32()];
+    let mut owners = Vec::new();
+    for key_material in &key_materials {
+        let owner = Discret::new(
+            model,
+            app_name,
+            key_material,
+            path.clone(),
+            Configuration {
+                enable_multicast: false,
+                enable_beacons: false,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        owners.push(base64_decode(owner.verifying_key().as_bytes()).unwrap());
+        owner.shutdown().await.unwrap();
+    }
+    beacon.set_approved_owners(app_name, owners).await;
+
+    let discret1: Discret = Discret::new(
+        model,
+        app_name,
+        &key_materials[0],
+        path.clone(),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+    let discret2: Discret = Discret::new(
+        model,
+        app_name,
+        &key_materials[1],
+        path.clone(),
+        config.clone(),
+    )
+    .await
+    .unwrap();
+
+    let mut events = discret2.subscribe_for_events().await;
+    let invite = discret1.invite(None).await.unwrap();
+    discret2.accept_invite(invite).await.unwrap();
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Ok(Event::PeerConnected(_, _, _)) = events.recv().await {
+                break;
+            }
+        }
+    });
+    tokio::time::timeout(Duration::from_secs(4), handle)
+        .await
+        .unwrap()
+        .unwrap();
+    let metrics = beacon.application_metrics(app_name).await.unwrap();
+    assert_eq!(0, metrics.unregistered_announces);
+
+    //the tokens of an identity that is not approved are ignored
+    let _other: Discret = Discret::new(model, app_name, &random32(), path, config)
+        .await
+        .unwrap();
+    let mut unregistered = 0;
+    for _ in 0..40 {
+        let metrics = beacon.application_metrics(app_name).await.unwrap();
+        unregistered = metrics.unregistered_announces;
+        if unregistered > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(unregistered > 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn embedded_beacon() {
+    let model = "{Person{name:String,}}";
+    let certificate = generate_x509_certificate("sample.org");
+    let cert_hash = base64_encode(&hash(certificate.cert.der().deref()));
+    let der: Vec<u8> = certificate.cert.der().deref().to_vec();
+    let pks_der: Vec<u8> = certificate.key_pair.serialize_der();
+
+    let (event_sender, mut event_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let beacon = Beacon::start_with(BeaconServerConfig {
+        allow_same_ip: true,
+        hook: Some(std::sync::Arc::new(move |event: &BeaconEvent| {
+            let _ = event_sender.send(event.clone());
+        })),
+        ..BeaconServerConfig::new(0, der, pks_der)
+    })
+    .unwrap();
+    let port = beacon.local_addr().port();
+    assert_ne!(0, port);
+
+    let path: PathBuf = format!("{}/embedded_beacon", DATA_PATH).into();
+    std::fs::create_dir_all(&path).unwrap();
+    let discret: Discret = Discret::new(
+        model,
+        "embedded beacon",
+        &random32(),
+        path,
+        Configuration {
+            enable_multicast: false,
+            beacons: vec![BeaconConfig {
+                hostname: format!("127.0.0.1:{}", port),
+                cert_hash,
+                register_tokens: false,
+            }],
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let event = tokio::time::timeout(Duration::from_secs(4), event_receiver.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(event, BeaconEvent::ConnectionAccepted { .. }));
+
+    let metrics = beacon.runtime_metrics().await;
+    assert_eq!(1, metrics.applications);
+    assert_eq!(1, metrics.connections);
+    assert_eq!(1, metrics.total_connections);
+
+    beacon.shutdown().await;
+    let event = tokio::time::timeout(Duration::from_secs(4), event_receiver.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(event, BeaconEvent::ConnectionClosed { .. }));
+    assert_eq!(0, beacon.runtime_metrics().await.connections);
+    drop(discret);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn room_archive() {
+    let model = "{
+        Person{
+            name:String,
+            pet : Pet
+        }
+
+        Pet {
+            name: String
+        }
+    }";
+    let key_material = random32();
+    let folder = format!("{}archive/{}", DATA_PATH, OsRng.next_u64());
+    std::fs::create_dir_all(format!("{}/first", folder)).unwrap();
+    std::fs::create_dir_all(format!("{}/second", folder)).unwrap();
+    //the two instances must not synchronise through the network
+    let configuration = Configuration {
+        enable_multicast: false,
+        ..Default::default()
+    };
+    let discret1: Discret = Discret::new(
+        model,
+        "room_archive",
+        &key_material,
+        format!("{}/first", folder).into(),
+        configuration.clone(),
+    )
+    .await
+    .unwrap();
+
+    let room = RoomBuilder::new()
+        .admin(&discret1.verifying_key())
+        .auth("writers")
+        .right("Person", true, true)
+        .right("Pet", true, true)
+        .user(&discret1.verifying_key());
+    let room_id = discret1.create_room(&room).await.unwrap().id;
+
+    let mut param = Parameters::new();
+    param.add("room_id", room_id.clone()).unwrap();
+    let res = discret1
+        .mutate(
+            r#"mutate {
+                P1: Person {
+                    room_id:$room_id
+                    name : "John"
+                    pet: { room_id:$room_id name:"Truffle"}
+                }
+                P2: Person {
+                    room_id:$room_id
+                    name : "Ada"
+                }
+            }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+    #[derive(Deserialize)]
+    struct Id {
+        id: String,
+    }
+    let mut parser = ResultParser::new(&res).unwrap();
+    let _: Id = parser.take_object("P1").unwrap();
+    let ada: Id = parser.take_object("P2").unwrap();
+
+    let mut param = Parameters::new();
+    param.add("room_id", discret1.private_room()).unwrap();
+    discret1
+        .mutate(
+            r#"mutate { Pet { room_id:$room_id name:"Private" } }"#,
+            Some(param),
+        )
+        .await
+        .unwrap();
+
+    let query = "query {
+        Person (order_by(name desc)) {
+            name
+            pet{
+                name
+            }
+        }
+        Pet (order_by(name desc)) {
+            name
+        }
+    }";
+    let res1 = discret1.query(query, None).await.unwrap();
+
+    let archive: PathBuf = format!("{}/rooms.archive", folder).into();
+    discret1
+        .export_rooms(
+            vec![room_id.clone(), discret1.private_room()],
+            archive.clone(),
+            "usb stick",
+        )
+        .await
+        .unwrap();
+    discret1
+        .export_rooms(
+            vec![base64_encode(&random32()[0..16])],
+            archive.clone(),
+            "usb stick",
+        )
+        .await
+        .expect_err("unknown room");
+
+    let discret2: Discret = Discret::new(
+        model,
+        "room_archive",
+        &key_material,
+        format!("{}/second", folder).into(),
+        configuration,
+    )
+    .await
+    .unwrap();
+    assert_ne!(res1, discret2.query(query, None).await.unwrap());
+
+    discret2
+        .import_archive(archive.clone(), "wrong pass phrase", false)
+        .await
+        .expect_err("invalid pass phrase");
+
+    //the room is unknown to the second peer and is not imported without its consent
+    let summary = discret2
+        .import_archive(archive.clone(), "usb stick", false)
+        .await
+        .unwrap();
+    assert_eq!(vec![discret1.private_room()], summary.rooms);
+    assert_eq!(vec![room_id.clone()], summary.rooms_skipped);
+    assert_eq!(1, summary.nodes_imported);
+    assert_eq!(0, discret2.count("Person", "", None).await.unwrap());
+
+    let summary = discret2
+        .import_archive(archive.clone(), "usb stick", true)
+        .await
+        .unwrap();
+    assert_eq!(discret1.verifying_key(), summary.author);
+    assert_eq!(2, summary.rooms.len());
+    assert!(summary.rooms_skipped.is_empty());
+    assert_eq!(3, summary.nodes_imported);
+    assert_eq!(0, summary.nodes_rejected);
+    assert_eq!(1, summary.edges_imported);
+    assert_eq!(res1, discret2.query(query, None).await.unwrap());
+
+    //importing the same archive again does not change anything
+    let summary = discret2
+        .import_archive(archive.clone(), "usb stick", false)
+        .await
+        .unwrap();
+    assert_eq!(0, summary.nodes_imported);
+    assert_eq!(0, summary.edges_imported);
+
+    //deletions are carried by the archive
+    let mut param = Parameters::new();
+    param.add("id", ada.id).unwrap();
+    discret1
+        .delete("delete { Person { $id } }", Some(param))
+        .await
+        .unwrap();
+    discret1
+        .export_rooms(vec![room_id], archive.clone(), "usb stick")
+        .await
+        .unwrap();
+    discret2
+        .import_archive(archive, "usb stick", false)
+        .await
+        .unwrap();
+    assert_eq!(
+        discret1.query(query, None).await.unwrap(),
+        discret2.query(query, None).await.unwrap()
+    );
+    assert_eq!(1, discret2.count("Person", "", None).await.unwrap());
+}